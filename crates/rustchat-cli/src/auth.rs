@@ -0,0 +1,288 @@
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// 访问令牌到期前提前刷新的时间窗口
+const REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// 服务器返回的令牌对
+#[derive(Debug, Clone, Deserialize)]
+pub struct TokenPair {
+    pub access_token: String,
+    pub refresh_token: String,
+    /// 访问令牌有效期（秒）
+    pub expires_in: i64,
+}
+
+/// 服务器返回的账户信息
+#[derive(Debug, Deserialize)]
+struct AccountInfo {
+    email: String,
+    tokens: Option<TokenPair>,
+}
+
+/// 认证API的统一响应格式
+#[derive(Debug, Deserialize)]
+struct AuthApiResponse {
+    success: bool,
+    #[serde(default)]
+    message: String,
+    account: Option<AccountInfo>,
+    tokens: Option<TokenPair>,
+}
+
+#[derive(Serialize)]
+struct LoginRequest<'a> {
+    email: &'a str,
+    password: &'a str,
+}
+
+#[derive(Serialize)]
+struct RegisterRequest<'a> {
+    email: &'a str,
+    password: &'a str,
+    display_name: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct RefreshRequest<'a> {
+    refresh_token: &'a str,
+}
+
+/// 登录会话
+#[derive(Debug, Clone)]
+pub struct AuthSession {
+    pub email: String,
+    pub access_token: String,
+    pub refresh_token: String,
+    expires_at: Instant,
+}
+
+impl AuthSession {
+    pub fn new(email: String, tokens: TokenPair) -> Self {
+        Self {
+            email,
+            expires_at: expires_at(tokens.expires_in),
+            access_token: tokens.access_token,
+            refresh_token: tokens.refresh_token,
+        }
+    }
+
+    /// 从保存的刷新令牌恢复会话，访问令牌需要刷新后才能使用
+    pub fn from_refresh_token(email: String, refresh_token: String) -> Self {
+        Self {
+            email,
+            access_token: String::new(),
+            refresh_token,
+            expires_at: Instant::now(),
+        }
+    }
+
+    /// 仍然有效的访问令牌
+    pub fn valid_access_token(&self) -> Option<&str> {
+        (Instant::now() < self.expires_at).then_some(self.access_token.as_str())
+    }
+
+    /// 访问令牌是否即将过期
+    pub fn needs_refresh(&self) -> bool {
+        Instant::now() + REFRESH_MARGIN >= self.expires_at
+    }
+
+    /// 使用刷新得到的令牌更新会话
+    pub fn apply_refresh(&mut self, tokens: TokenPair) {
+        self.expires_at = expires_at(tokens.expires_in);
+        self.access_token = tokens.access_token;
+        self.refresh_token = tokens.refresh_token;
+    }
+}
+
+fn expires_at(expires_in: i64) -> Instant {
+    Instant::now() + Duration::from_secs(expires_in.max(0) as u64)
+}
+
+/// 认证 API 客户端
+pub struct AuthApiClient {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl AuthApiClient {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: base_url.to_string(),
+        }
+    }
+
+    /// 登录并获取令牌
+    pub async fn login(&self, email: &str, password: &str) -> Result<AuthSession> {
+        let response = self
+            .post("/api/auth/login", &LoginRequest { email, password })
+            .await
+            .context("登录请求失败")?;
+
+        let account = response
+            .account
+            .ok_or_else(|| anyhow!("登录响应缺少账户信息"))?;
+        let tokens = account
+            .tokens
+            .ok_or_else(|| anyhow!("登录响应缺少令牌"))?;
+
+        Ok(AuthSession::new(account.email, tokens))
+    }
+
+    /// 注册新账户，返回服务器提示信息
+    pub async fn register(
+        &self,
+        email: &str,
+        password: &str,
+        display_name: Option<&str>,
+    ) -> Result<String> {
+        let response = self
+            .post(
+                "/api/auth/register",
+                &RegisterRequest {
+                    email,
+                    password,
+                    display_name,
+                },
+            )
+            .await
+            .context("注册请求失败")?;
+
+        Ok(response.message)
+    }
+
+    /// 使用刷新令牌获取新的访问令牌
+    pub async fn refresh(&self, refresh_token: &str) -> Result<TokenPair> {
+        let response = self
+            .post("/api/auth/refresh", &RefreshRequest { refresh_token })
+            .await
+            .context("刷新令牌请求失败")?;
+
+        response
+            .tokens
+            .ok_or_else(|| anyhow!("刷新响应缺少令牌"))
+    }
+
+    async fn post<T: Serialize>(&self, path: &str, body: &T) -> Result<AuthApiResponse> {
+        let url = format!("{}{}", self.base_url, path);
+        let response: AuthApiResponse = self
+            .client
+            .post(&url)
+            .json(body)
+            .send()
+            .await?
+            .json()
+            .await
+            .context("解析认证响应失败")?;
+
+        if !response.success {
+            bail!("{}", response.message);
+        }
+
+        Ok(response)
+    }
+}
+
+/// 是否为网络错误（服务器不可达等），此类错误不代表凭据失效
+pub fn is_network_error(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<reqwest::Error>().is_some()
+}
+
+/// 将WebSocket地址转换为HTTP API根地址（ws→http，wss→https）
+pub fn http_base_url(ws_url: &str) -> Result<String> {
+    let parsed = url::Url::parse(ws_url).with_context(|| format!("无效的服务器地址: {}", ws_url))?;
+    let scheme = match parsed.scheme() {
+        "wss" => "https",
+        _ => "http",
+    };
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| anyhow!("服务器地址缺少主机名: {}", ws_url))?;
+
+    Ok(match parsed.port() {
+        Some(port) => format!("{}://{}:{}", scheme, host, port),
+        None => format!("{}://{}", scheme, host),
+    })
+}
+
+/// 在WebSocket地址上附加访问令牌参数
+pub fn ws_url_with_token(ws_url: &str, access_token: Option<&str>) -> Result<String> {
+    let Some(token) = access_token else {
+        return Ok(ws_url.to_string());
+    };
+
+    let mut parsed = url::Url::parse(ws_url).with_context(|| format!("无效的服务器地址: {}", ws_url))?;
+    parsed.query_pairs_mut().append_pair("token", token);
+    Ok(parsed.to_string())
+}
+
+/// 在WebSocket地址上附加断线恢复令牌参数（仅匿名连接使用）
+pub fn ws_url_with_resume_token(ws_url: &str, resume_token: Option<&str>) -> Result<String> {
+    let Some(token) = resume_token else {
+        return Ok(ws_url.to_string());
+    };
+
+    let mut parsed = url::Url::parse(ws_url).with_context(|| format!("无效的服务器地址: {}", ws_url))?;
+    parsed.query_pairs_mut().append_pair("resume", token);
+    Ok(parsed.to_string())
+}
+
+/// 在WebSocket地址上声明本客户端的协议版本，服务器据此决定是否发送批量事件等新格式
+pub fn ws_url_with_protocol(ws_url: &str) -> Result<String> {
+    let mut parsed = url::Url::parse(ws_url).with_context(|| format!("无效的服务器地址: {}", ws_url))?;
+    parsed.query_pairs_mut().append_pair("protocol", rustchat_types::PROTOCOL_VERSION);
+    Ok(parsed.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_http_base_url() {
+        assert_eq!(http_base_url("ws://127.0.0.1:8080/ws").unwrap(), "http://127.0.0.1:8080");
+        assert_eq!(http_base_url("wss://chat.example.com/ws").unwrap(), "https://chat.example.com");
+    }
+
+    #[test]
+    fn test_ws_url_with_token() {
+        assert_eq!(
+            ws_url_with_token("ws://127.0.0.1:8080/ws", None).unwrap(),
+            "ws://127.0.0.1:8080/ws"
+        );
+        assert_eq!(
+            ws_url_with_token("ws://127.0.0.1:8080/ws", Some("abc.def")).unwrap(),
+            "ws://127.0.0.1:8080/ws?token=abc.def"
+        );
+        assert_eq!(
+            ws_url_with_resume_token("ws://127.0.0.1:8080/ws", Some("f00d")).unwrap(),
+            "ws://127.0.0.1:8080/ws?resume=f00d"
+        );
+        assert_eq!(
+            ws_url_with_protocol("ws://127.0.0.1:8080/ws?resume=f00d").unwrap(),
+            format!("ws://127.0.0.1:8080/ws?resume=f00d&protocol={}", rustchat_types::PROTOCOL_VERSION)
+        );
+    }
+
+    #[test]
+    fn test_session_needs_refresh() {
+        let tokens = |expires_in| TokenPair {
+            access_token: "access".to_string(),
+            refresh_token: "refresh".to_string(),
+            expires_in,
+        };
+
+        let mut session = AuthSession::new("a@example.com".to_string(), tokens(30));
+        assert!(session.needs_refresh());
+        assert_eq!(session.valid_access_token(), Some("access"));
+
+        session.apply_refresh(tokens(900));
+        assert!(!session.needs_refresh());
+
+        let restored = AuthSession::from_refresh_token("a@example.com".to_string(), "refresh".to_string());
+        assert!(restored.needs_refresh());
+        assert_eq!(restored.valid_access_token(), None);
+    }
+}
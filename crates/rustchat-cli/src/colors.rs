@@ -1,168 +1,383 @@
+use chrono::{DateTime, Local, TimeZone, Utc};
 use crossterm::{
-    style::{Color, ResetColor, SetForegroundColor, Stylize},
+    style::{Attribute, Color, ResetColor, SetAttribute, SetForegroundColor, Stylize},
     ExecutableCommand,
 };
 use rustchat_types::{Message, MessageType};
-use std::io::{self, Write};
+use std::io::{self, IsTerminal, Write};
 
 /// 颜色主题配置
 #[derive(Clone)]
 pub struct ColorTheme {
+    /// 主题名称
+    pub name: &'static str,
+    /// 是否输出颜色控制序列（mono主题关闭，适合管道和日志）
+    pub colored: bool,
     pub timestamp_color: Color,
     pub username_color: Color,
+    /// 用户名配色板，非空时按用户名哈希分配颜色
+    pub username_colors: Vec<Color>,
     pub bot_color: Color,
     pub system_color: Color,
+    /// 房间消息和房间事件的前缀颜色
+    pub room_color: Color,
     pub text_color: Color,
     pub error_color: Color,
     pub success_color: Color,
     pub info_color: Color,
+    pub banner_color: Color,
+    pub prompt_color: Color,
 }
 
 impl ColorTheme {
+    /// 可用的主题名称
+    pub const NAMES: &'static [&'static str] = &["default", "rainbow", "mono"];
+
     /// 默认颜色主题
     pub fn default() -> Self {
         Self {
+            name: "default",
+            colored: true,
             timestamp_color: Color::DarkGrey,
             username_color: Color::Cyan,
+            username_colors: vec![
+                Color::Cyan,
+                Color::Magenta,
+                Color::Blue,
+                Color::Green,
+                Color::Yellow,
+                Color::Red,
+                Color::DarkCyan,
+                Color::DarkMagenta,
+            ],
             bot_color: Color::Green,
             system_color: Color::Yellow,
+            room_color: Color::DarkYellow,
             text_color: Color::White,
             error_color: Color::Red,
             success_color: Color::Green,
             info_color: Color::Blue,
+            banner_color: Color::Cyan,
+            prompt_color: Color::DarkGreen,
         }
     }
 
     /// 彩虹颜色主题（为用户名分配不同颜色）
     pub fn rainbow() -> Self {
         Self {
+            name: "rainbow",
+            colored: true,
             timestamp_color: Color::DarkGrey,
-            username_color: Color::Magenta, // 这个会被动态替换
+            username_color: Color::Magenta,
+            username_colors: vec![
+                Color::Red,
+                Color::DarkYellow,
+                Color::Yellow,
+                Color::Green,
+                Color::Cyan,
+                Color::Blue,
+                Color::Magenta,
+                Color::DarkRed,
+                Color::DarkGreen,
+                Color::DarkCyan,
+                Color::DarkBlue,
+                Color::DarkMagenta,
+            ],
             bot_color: Color::Green,
             system_color: Color::Yellow,
+            room_color: Color::DarkCyan,
             text_color: Color::White,
             error_color: Color::Red,
             success_color: Color::Green,
             info_color: Color::Cyan,
+            banner_color: Color::Magenta,
+            prompt_color: Color::Magenta,
         }
     }
+
+    /// 无颜色主题（输出纯文本，适合重定向到文件或管道）
+    pub fn mono() -> Self {
+        Self {
+            name: "mono",
+            colored: false,
+            timestamp_color: Color::Reset,
+            username_color: Color::Reset,
+            username_colors: Vec::new(),
+            bot_color: Color::Reset,
+            system_color: Color::Reset,
+            room_color: Color::Reset,
+            text_color: Color::Reset,
+            error_color: Color::Reset,
+            success_color: Color::Reset,
+            info_color: Color::Reset,
+            banner_color: Color::Reset,
+            prompt_color: Color::Reset,
+        }
+    }
+
+    /// 根据名称获取主题
+    pub fn by_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "default" => Some(Self::default()),
+            "rainbow" => Some(Self::rainbow()),
+            "mono" | "none" | "plain" => Some(Self::mono()),
+            _ => None,
+        }
+    }
+
+    /// 选择启动时使用的主题：输出不是终端时强制使用mono，否则使用保存的主题
+    pub fn select(saved: Option<&str>) -> Self {
+        if !io::stdout().is_terminal() {
+            return Self::mono();
+        }
+        saved.and_then(Self::by_name).unwrap_or_else(Self::default)
+    }
+}
+
+/// 是否允许颜色输出：--no-color 参数或非空的 NO_COLOR 环境变量会禁用颜色
+pub fn colors_allowed(no_color_flag: bool) -> bool {
+    let no_color_env = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    !no_color_flag && !no_color_env
+}
+
+/// 消息时间戳的显示格式，时间戳按本地时区显示
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimestampFormat {
+    /// 使用12小时制（带 AM/PM）
+    pub hour12: bool,
+    /// 不是今天的消息在时间前显示日期
+    pub show_date: bool,
+}
+
+impl Default for TimestampFormat {
+    fn default() -> Self {
+        Self { hour12: false, show_date: true }
+    }
+}
+
+impl TimestampFormat {
+    /// 格式说明，例如 `24小时制，显示日期`
+    pub fn describe(&self) -> String {
+        format!(
+            "{}，{}",
+            if self.hour12 { "12小时制" } else { "24小时制" },
+            if self.show_date { "非当天消息显示日期" } else { "不显示日期" }
+        )
+    }
 }
 
 /// 颜色显示工具
 #[derive(Clone)]
 pub struct ColorDisplay {
     theme: ColorTheme,
-    username_colors: Vec<Color>,
+    /// 是否启用颜色输出（--no-color 或 NO_COLOR 时关闭）
+    enabled: bool,
+    timestamp_format: TimestampFormat,
 }
 
 impl ColorDisplay {
     pub fn new() -> Self {
+        Self::with_theme(ColorTheme::default())
+    }
+
+    /// 使用指定主题创建
+    pub fn with_theme(theme: ColorTheme) -> Self {
         Self {
-            theme: ColorTheme::default(),
-            username_colors: vec![
-                Color::Cyan,
-                Color::Magenta,
-                Color::Blue,
-                Color::Green,
-                Color::Yellow,
-                Color::Red,
-                Color::DarkCyan,
-                Color::DarkMagenta,
-            ],
+            theme,
+            enabled: true,
+            timestamp_format: TimestampFormat::default(),
+        }
+    }
+
+    /// 设置时间戳格式
+    pub fn with_timestamp_format(mut self, timestamp_format: TimestampFormat) -> Self {
+        self.timestamp_format = timestamp_format;
+        self
+    }
+
+    /// 当前时间戳格式
+    pub fn timestamp_format(&self) -> TimestampFormat {
+        self.timestamp_format
+    }
+
+    /// 设置是否启用颜色输出
+    pub fn with_enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
+
+    /// 切换主题，保留颜色开关
+    pub fn set_theme(&mut self, theme: ColorTheme) {
+        self.theme = theme;
+    }
+
+    /// 是否输出颜色控制序列
+    fn colored(&self) -> bool {
+        self.enabled && self.theme.colored
+    }
+
+    /// 按本地时区格式化消息时间戳（存储和传输始终使用UTC）
+    pub fn format_timestamp(&self, timestamp: &DateTime<Utc>) -> String {
+        self.format_timestamp_in(timestamp, &Local, Utc::now())
+    }
+
+    /// 按指定时区格式化时间戳，`now` 用于判断消息是否是今天的
+    fn format_timestamp_in<Tz: TimeZone>(&self, timestamp: &DateTime<Utc>, tz: &Tz, now: DateTime<Utc>) -> String
+    where
+        Tz::Offset: std::fmt::Display,
+    {
+        let local = timestamp.with_timezone(tz);
+        let time = if self.timestamp_format.hour12 {
+            local.format("%I:%M:%S %p")
+        } else {
+            local.format("%H:%M:%S")
+        };
+
+        if self.timestamp_format.show_date && local.date_naive() != now.with_timezone(tz).date_naive() {
+            format!("{} {}", local.format("%Y-%m-%d"), time)
+        } else {
+            time.to_string()
+        }
+    }
+
+    /// 当前主题名称
+    pub fn theme_name(&self) -> &'static str {
+        self.theme.name
+    }
+
+    /// 设置前景色（禁用颜色或无颜色主题下不输出控制序列）
+    pub fn set_color(&self, stdout: &mut io::Stdout, color: Color) {
+        if self.colored() {
+            stdout.execute(SetForegroundColor(color)).unwrap();
+        }
+    }
+
+    /// 重置颜色（禁用颜色或无颜色主题下不输出控制序列）
+    pub fn reset_color(&self, stdout: &mut io::Stdout) {
+        if self.colored() {
+            stdout.execute(ResetColor).unwrap();
         }
     }
 
     /// 获取用户名颜色（基于用户名哈希分配）
     fn get_username_color(&self, username: &str) -> Color {
+        let palette = &self.theme.username_colors;
+        if palette.is_empty() {
+            return self.theme.username_color;
+        }
         let hash = username.chars().map(|c| c as usize).sum::<usize>();
-        let index = hash % self.username_colors.len();
-        self.username_colors[index]
+        palette[hash % palette.len()]
     }
 
-    /// 格式化并显示消息
-    pub fn display_message(&self, msg: &Message) {
+    /// 发送者昵称的颜色：机器人消息使用机器人颜色，其他按昵称分配
+    fn sender_color(&self, msg: &Message, sender: &str) -> Color {
+        if msg.is_bot {
+            self.theme.bot_color
+        } else {
+            self.get_username_color(sender)
+        }
+    }
+
+    /// 终端响铃提醒（输出不是终端时忽略）
+    pub fn ring_bell(&self) {
         let mut stdout = io::stdout();
+        if stdout.is_terminal() {
+            print!("\x07");
+            stdout.flush().unwrap();
+        }
+    }
+
+    /// 格式化并显示消息，`highlight` 为true时整行反色显示（用于提及当前用户的消息）
+    pub fn display_message(&self, msg: &Message, highlight: bool) {
+        self.print_message(msg, highlight, None);
+    }
+
+    /// 显示房间消息，时间戳后带 `[#房间]` 前缀
+    pub fn display_room_message(&self, room_label: &str, msg: &Message, highlight: bool) {
+        self.print_message(msg, highlight, Some(room_label));
+    }
+
+    fn print_message(&self, msg: &Message, highlight: bool, room_label: Option<&str>) {
+        let mut stdout = io::stdout();
+        let highlight = highlight && self.colored();
+        if highlight {
+            stdout.execute(SetAttribute(Attribute::Reverse)).unwrap();
+        }
         
         // 显示时间戳
-        let time = msg.timestamp.format("%H:%M:%S");
-        stdout
-            .execute(SetForegroundColor(self.theme.timestamp_color))
-            .unwrap();
+        let time = self.format_timestamp(&msg.timestamp);
+        self.set_color(&mut stdout, self.theme.timestamp_color);
         print!("[{}] ", time);
+
+        if let Some(room_label) = room_label {
+            self.set_color(&mut stdout, self.theme.room_color);
+            print!("[#{}] ", room_label);
+        }
         
         match &msg.content {
-            MessageType::Text(text) => {
-                let sender = msg.from_nick.as_deref().unwrap_or("匿名用户");
+            MessageType::Text(_) | MessageType::Markdown(_) => {
+                let sender = msg.from_nick.clone().unwrap_or_else(|| format!("匿名用户 {}", msg.from.short()));
                 
-                // 检查是否是机器人消息
-                if sender.contains("Bot") || sender.contains("机器人") {
-                    stdout
-                        .execute(SetForegroundColor(self.theme.bot_color))
-                        .unwrap();
-                    print!("{}: ", sender);
-                } else {
-                    let username_color = self.get_username_color(sender);
-                    stdout
-                        .execute(SetForegroundColor(username_color))
-                        .unwrap();
-                    print!("{}: ", sender);
-                }
+                self.set_color(&mut stdout, self.sender_color(msg, &sender));
+                print!("{}: ", sender);
                 
-                // 显示消息内容
-                stdout
-                    .execute(SetForegroundColor(self.theme.text_color))
-                    .unwrap();
-                println!("{}", text);
+                // 显示消息内容（Markdown降级为纯文本）
+                self.set_color(&mut stdout, self.theme.text_color);
+                println!("{}", msg.render_plain());
             }
             MessageType::System(text) => {
-                stdout
-                    .execute(SetForegroundColor(self.theme.system_color))
-                    .unwrap();
+                self.set_color(&mut stdout, self.theme.system_color);
                 println!("[系统]: {}", text);
             }
             MessageType::NickChange { old_nick, new_nick } => {
-                stdout
-                    .execute(SetForegroundColor(self.theme.system_color))
-                    .unwrap();
+                self.set_color(&mut stdout, self.theme.system_color);
                 println!("[系统]: {} 将昵称改为 {}", old_nick, new_nick);
             }
+            MessageType::Action(text) => {
+                let sender = msg.from_nick.clone().unwrap_or_else(|| format!("匿名用户 {}", msg.from.short()));
+                self.set_color(&mut stdout, self.get_username_color(&sender));
+                if self.colored() {
+                    stdout.execute(SetAttribute(Attribute::Italic)).unwrap();
+                }
+                println!("* {} {}", sender, text);
+                if self.colored() {
+                    stdout.execute(SetAttribute(Attribute::Reset)).unwrap();
+                }
+            }
         }
         
         // 重置颜色
-        stdout.execute(ResetColor).unwrap();
+        if highlight {
+            stdout.execute(SetAttribute(Attribute::Reset)).unwrap();
+        }
+        self.reset_color(&mut stdout);
         stdout.flush().unwrap();
     }
 
     /// 显示成功消息
     pub fn display_success(&self, message: &str) {
         let mut stdout = io::stdout();
-        stdout
-            .execute(SetForegroundColor(self.theme.success_color))
-            .unwrap();
+        self.set_color(&mut stdout, self.theme.success_color);
         println!("✅ {}", message);
-        stdout.execute(ResetColor).unwrap();
+        self.reset_color(&mut stdout);
         stdout.flush().unwrap();
     }
 
     /// 显示错误消息
     pub fn display_error(&self, message: &str) {
         let mut stdout = io::stdout();
-        stdout
-            .execute(SetForegroundColor(self.theme.error_color))
-            .unwrap();
+        self.set_color(&mut stdout, self.theme.error_color);
         println!("❌ {}", message);
-        stdout.execute(ResetColor).unwrap();
+        self.reset_color(&mut stdout);
         stdout.flush().unwrap();
     }
 
     /// 显示信息消息
     pub fn display_info(&self, message: &str) {
         let mut stdout = io::stdout();
-        stdout
-            .execute(SetForegroundColor(self.theme.info_color))
-            .unwrap();
+        self.set_color(&mut stdout, self.theme.info_color);
         println!("💡 {}", message);
-        stdout.execute(ResetColor).unwrap();
+        self.reset_color(&mut stdout);
         stdout.flush().unwrap();
     }
 
@@ -170,17 +385,13 @@ impl ColorDisplay {
     pub fn display_connection_status(&self, connected: bool) {
         let mut stdout = io::stdout();
         if connected {
-            stdout
-                .execute(SetForegroundColor(self.theme.success_color))
-                .unwrap();
+            self.set_color(&mut stdout, self.theme.success_color);
             println!("🔗 已连接到服务器");
         } else {
-            stdout
-                .execute(SetForegroundColor(self.theme.error_color))
-                .unwrap();
+            self.set_color(&mut stdout, self.theme.error_color);
             println!("🔌 与服务器断开连接");
         }
-        stdout.execute(ResetColor).unwrap();
+        self.reset_color(&mut stdout);
         stdout.flush().unwrap();
     }
 
@@ -191,68 +402,248 @@ impl ColorDisplay {
         let mut stdout = io::stdout();
         
         // 显示Banner
-        stdout
-            .execute(SetForegroundColor(Color::Cyan))
-            .unwrap();
+        self.set_color(&mut stdout, self.theme.banner_color);
         println!("╔══════════════════════════════════════════════════════════╗");
         println!("║                    🚀 RustChat CLI v0.1.0                ║");
         println!("║              现代化Rust聊天应用 - 终端客户端              ║");
         println!("╚══════════════════════════════════════════════════════════╝");
         
-        stdout
-            .execute(SetForegroundColor(self.theme.info_color))
-            .unwrap();
+        self.set_color(&mut stdout, self.theme.info_color);
         println!("💬 开始你的聊天之旅！输入 /help 查看可用命令");
         println!("🎨 支持彩色显示：昵称会显示不同颜色，机器人消息为绿色");
         
-        stdout.execute(ResetColor).unwrap();
+        self.reset_color(&mut stdout);
         stdout.flush().unwrap();
     }
 
     /// 清空屏幕
     pub fn clear_screen(&self) {
+        // 输出被重定向时不写入清屏控制序列
+        if !io::stdout().is_terminal() {
+            return;
+        }
         print!("\x1B[2J\x1B[1;1H");
         io::stdout().flush().unwrap();
     }
 
-    /// 显示输入提示符
-    pub fn display_prompt(&self) {
-        let mut stdout = io::stdout();
-        stdout
-            .execute(SetForegroundColor(Color::DarkGreen))
-            .unwrap();
-        print!("> ");
-        stdout.execute(ResetColor).unwrap();
-        stdout.flush().unwrap();
+    /// 生成带颜色的输入提示符
+    pub fn styled_prompt(&self, prompt: &str) -> String {
+        if self.colored() {
+            prompt.with(self.theme.prompt_color).to_string()
+        } else {
+            prompt.to_string()
+        }
     }
 
     /// 显示历史消息分隔符
     pub fn display_history_separator(&self, count: usize) {
         let mut stdout = io::stdout();
-        stdout
-            .execute(SetForegroundColor(self.theme.info_color))
-            .unwrap();
-        println!("┌─────────────────────────────────────────────────────────┐");
-        println!("│              📚 最近 {} 条消息历史              │", count);
-        println!("└─────────────────────────────────────────────────────────┘");
-        stdout.execute(ResetColor).unwrap();
+        self.set_color(&mut stdout, self.theme.info_color);
+        println!("┌{}┐", "─".repeat(INFO_BOX_WIDTH));
+        println!("│{}│", center_to_width(&format!("📚 最近 {} 条消息历史", count), INFO_BOX_WIDTH));
+        println!("└{}┘", "─".repeat(INFO_BOX_WIDTH));
+        self.reset_color(&mut stdout);
+        stdout.flush().unwrap();
+    }
+
+    /// 显示未读消息分隔线
+    pub fn display_unread_separator(&self, unread_count: usize) {
+        let mut stdout = io::stdout();
+        self.set_color(&mut stdout, self.theme.error_color);
+        println!("──────────────── 以下为 {} 条新消息 ────────────────", unread_count);
+        self.reset_color(&mut stdout);
+        stdout.flush().unwrap();
+    }
+
+    /// 在消息下方显示表情回应汇总，例如 `↳ 你好: 👍x3 🎉x1`
+    pub fn display_reactions(&self, message_preview: &str, reactions: &[(String, usize)]) {
+        let mut stdout = io::stdout();
+        self.set_color(&mut stdout, self.theme.timestamp_color);
+        if reactions.is_empty() {
+            println!("    ↳ {}: (无回应)", message_preview);
+        } else {
+            println!("    ↳ {}: {}", message_preview, format_reactions(reactions));
+        }
+        self.reset_color(&mut stdout);
+        stdout.flush().unwrap();
+    }
+
+    /// 显示房间事件，例如成员加入或离开
+    pub fn display_room_event(&self, room_label: &str, event: &str) {
+        let mut stdout = io::stdout();
+        self.set_color(&mut stdout, self.theme.room_color);
+        println!("[#{}] {}", room_label, event);
+        self.reset_color(&mut stdout);
+        stdout.flush().unwrap();
+    }
+
+    /// 以帮助界面的边框样式显示一组“名称: 值”
+    pub fn display_info_box(&self, title: &str, rows: &[(&str, String)]) {
+        let mut stdout = io::stdout();
+
+        self.set_color(&mut stdout, Color::DarkGrey);
+        println!("┌{}┐", "─".repeat(INFO_BOX_WIDTH));
+        self.set_color(&mut stdout, Color::Yellow);
+        println!("│{}│", pad_to_width(&format!(" {}", title), INFO_BOX_WIDTH));
+        self.set_color(&mut stdout, Color::DarkGrey);
+        println!("├{}┤", "─".repeat(INFO_BOX_WIDTH));
+
+        for (label, value) in rows {
+            self.set_color(&mut stdout, Color::Green);
+            println!("│{}│", pad_to_width(&format!(" {}: {}", label, value), INFO_BOX_WIDTH));
+        }
+
+        self.set_color(&mut stdout, Color::DarkGrey);
+        println!("└{}┘", "─".repeat(INFO_BOX_WIDTH));
+        self.reset_color(&mut stdout);
         stdout.flush().unwrap();
     }
 
     /// 显示分隔线
     pub fn display_separator(&self) {
         let mut stdout = io::stdout();
-        stdout
-            .execute(SetForegroundColor(Color::DarkGrey))
-            .unwrap();
+        self.set_color(&mut stdout, self.theme.timestamp_color);
         println!("─────────────────────────────────────────────────────────────");
-        stdout.execute(ResetColor).unwrap();
+        self.reset_color(&mut stdout);
         stdout.flush().unwrap();
     }
 }
 
+/// 信息框内部宽度（终端列数），与帮助界面一致
+const INFO_BOX_WIDTH: usize = 57;
+
+/// 文本在终端中占用的列数（中日韩文字和表情符号按两列计算）
+pub fn display_width(text: &str) -> usize {
+    text.chars()
+        .map(|c| match c as u32 {
+            0x1100..=0x115F | 0x2E80..=0xA4CF | 0xAC00..=0xD7A3 | 0xF900..=0xFAFF | 0xFE30..=0xFE4F
+            | 0xFF00..=0xFF60 | 0xFFE0..=0xFFE6 | 0x1F300..=0x1FAFF | 0x20000..=0x3FFFD => 2,
+            _ => 1,
+        })
+        .sum()
+}
+
+/// 在文本右侧补空格到指定列数，超出时原样返回
+fn pad_to_width(text: &str, width: usize) -> String {
+    let padding = width.saturating_sub(display_width(text));
+    format!("{}{}", text, " ".repeat(padding))
+}
+
+/// 将文本居中并用空格补齐到指定列数
+fn center_to_width(text: &str, width: usize) -> String {
+    let padding = width.saturating_sub(display_width(text));
+    format!("{}{}{}", " ".repeat(padding / 2), text, " ".repeat(padding - padding / 2))
+}
+
+/// 房间的显示名称，名称未知时使用房间ID的前8位
+pub fn room_label(room_id: &str, room_name: Option<&str>) -> String {
+    match room_name {
+        Some(name) if !name.is_empty() => name.to_string(),
+        _ => room_id.chars().take(8).collect(),
+    }
+}
+
+/// 格式化表情回应汇总，例如 `👍x3 🎉x1`
+pub fn format_reactions(reactions: &[(String, usize)]) -> String {
+    reactions
+        .iter()
+        .map(|(emoji, count)| format!("{}x{}", emoji, count))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 impl Default for ColorDisplay {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_theme_by_name() {
+        for name in ColorTheme::NAMES {
+            assert_eq!(ColorTheme::by_name(name).unwrap().name, *name);
+        }
+        assert_eq!(ColorTheme::by_name("RAINBOW").unwrap().name, "rainbow");
+        assert_eq!(ColorTheme::by_name("none").unwrap().name, "mono");
+        assert!(ColorTheme::by_name("neon").is_none());
+    }
+
+    #[test]
+    fn test_mono_prompt_is_plain() {
+        let display = ColorDisplay::with_theme(ColorTheme::mono());
+        assert_eq!(display.styled_prompt("> "), "> ");
+
+        let display = ColorDisplay::with_theme(ColorTheme::default());
+        assert_ne!(display.styled_prompt("> "), "> ");
+    }
+
+    #[test]
+    fn test_disabled_display_keeps_theme_but_drops_color() {
+        let mut display = ColorDisplay::with_theme(ColorTheme::rainbow()).with_enabled(false);
+        assert_eq!(display.styled_prompt("> "), "> ");
+
+        // 切换主题不会重新启用颜色
+        display.set_theme(ColorTheme::default());
+        assert_eq!(display.theme_name(), "default");
+        assert_eq!(display.styled_prompt("> "), "> ");
+    }
+
+    #[test]
+    fn test_format_timestamp_in_fixed_timezone() {
+        let tz = chrono::FixedOffset::east_opt(8 * 3600).unwrap();
+        // UTC 2024-03-01 15:04:05 是东八区 2024-03-01 23:04:05
+        let timestamp = Utc.with_ymd_and_hms(2024, 3, 1, 15, 4, 5).unwrap();
+        let same_day = Utc.with_ymd_and_hms(2024, 3, 1, 15, 30, 0).unwrap();
+        // 东八区已经是 3月2日
+        let next_day = Utc.with_ymd_and_hms(2024, 3, 1, 17, 0, 0).unwrap();
+
+        let display = ColorDisplay::new();
+        assert_eq!(display.format_timestamp_in(&timestamp, &tz, same_day), "23:04:05");
+        assert_eq!(display.format_timestamp_in(&timestamp, &tz, next_day), "2024-03-01 23:04:05");
+
+        let display = ColorDisplay::new().with_timestamp_format(TimestampFormat { hour12: true, show_date: false });
+        assert_eq!(display.format_timestamp_in(&timestamp, &tz, next_day), "11:04:05 PM");
+    }
+
+    #[test]
+    fn test_format_reactions() {
+        let reactions = vec![("👍".to_string(), 3), ("🎉".to_string(), 1)];
+        assert_eq!(format_reactions(&reactions), "👍x3 🎉x1");
+        assert_eq!(format_reactions(&[]), "");
+    }
+
+    #[test]
+    fn test_display_width_and_padding() {
+        assert_eq!(display_width("abc"), 3);
+        assert_eq!(display_width("消息总数: 12"), 12);
+        assert_eq!(display_width("👍"), 2);
+
+        assert_eq!(pad_to_width("昵称", 6), "昵称  ");
+        assert_eq!(pad_to_width("too long", 3), "too long");
+        assert_eq!(center_to_width("历史", 7), " 历史  ");
+    }
+
+    #[test]
+    fn test_room_label() {
+        let room_id = "3f2a9c1e-0000-4000-8000-000000000000";
+        assert_eq!(room_label(room_id, Some("rust")), "rust");
+        assert_eq!(room_label(room_id, Some("")), "3f2a9c1e");
+        assert_eq!(room_label(room_id, None), "3f2a9c1e");
+    }
+
+    #[test]
+    fn test_bot_color_follows_message_tag() {
+        let display = ColorDisplay::with_theme(ColorTheme::default());
+        let bot = Message::new_bot_text("hi".to_string(), "Echo Bot");
+        let bottle = Message::new_text(rustchat_types::UserId::new(), "hi".to_string(), Some("Bottle".to_string()));
+
+        assert!(bot.is_bot);
+        assert!(!bottle.is_bot);
+        assert_eq!(display.sender_color(&bot, "Echo Bot"), display.theme.bot_color);
+        assert_eq!(display.sender_color(&bottle, "Bottle"), display.get_username_color("Bottle"));
+    }
+}
@@ -0,0 +1,85 @@
+use rustchat_types::PROTOCOL_VERSION;
+
+/// 服务器协议兼容性
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Compatibility {
+    /// 协议版本完全一致
+    Compatible,
+    /// 主版本一致、次版本不同，可以继续使用但部分功能可能不可用
+    MinorMismatch,
+    /// 主版本不同或版本号无法解析，无法正常通信
+    Incompatible,
+}
+
+/// 根据服务器报告的协议版本判断是否与本客户端兼容
+pub fn check_protocol_version(server_protocol: &str) -> Compatibility {
+    check_against(server_protocol, PROTOCOL_VERSION)
+}
+
+fn check_against(server_protocol: &str, client_protocol: &str) -> Compatibility {
+    let (Some(server), Some(client)) = (parse_version(server_protocol), parse_version(client_protocol)) else {
+        return Compatibility::Incompatible;
+    };
+
+    if server.0 != client.0 {
+        Compatibility::Incompatible
+    } else if server.1 != client.1 {
+        Compatibility::MinorMismatch
+    } else {
+        Compatibility::Compatible
+    }
+}
+
+/// 服务器是否会确认收到的消息（协议1.1起支持MessageAck）
+pub fn supports_message_ack(server_protocol: &str) -> bool {
+    parse_version(server_protocol).is_some_and(|(major, minor)| major == 1 && minor >= 1)
+}
+
+/// 服务器是否会确认房间的加入和离开（协议1.5起支持RoomJoined/RoomLeft）
+pub fn supports_room_events(server_protocol: &str) -> bool {
+    parse_version(server_protocol).is_some_and(|(major, minor)| major == 1 && minor >= 5)
+}
+
+/// 解析"主版本.次版本"格式的版本号，缺少次版本时视为0
+fn parse_version(version: &str) -> Option<(u64, u64)> {
+    let mut parts = version.trim().split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = match parts.next() {
+        Some(minor) => minor.parse().ok()?,
+        None => 0,
+    };
+    Some((major, minor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_protocol_compatibility() {
+        assert_eq!(check_against("1.0", "1.0"), Compatibility::Compatible);
+        assert_eq!(check_against("1", "1.0"), Compatibility::Compatible);
+        assert_eq!(check_against("1.3", "1.0"), Compatibility::MinorMismatch);
+        assert_eq!(check_against("2.0", "1.0"), Compatibility::Incompatible);
+        assert_eq!(check_against("0.9", "1.0"), Compatibility::Incompatible);
+        assert_eq!(check_against("", "1.0"), Compatibility::Incompatible);
+        assert_eq!(check_against("v1.x", "1.0"), Compatibility::Incompatible);
+
+        // 与当前内置版本完全一致的服务器总是兼容
+        assert_eq!(check_protocol_version(PROTOCOL_VERSION), Compatibility::Compatible);
+    }
+
+    #[test]
+    fn test_message_ack_support() {
+        assert!(supports_message_ack(PROTOCOL_VERSION));
+        assert!(supports_message_ack("1.2"));
+        assert!(!supports_message_ack("1.0"));
+        assert!(!supports_message_ack("1"));
+        assert!(!supports_message_ack("2.0"));
+        assert!(!supports_message_ack("garbage"));
+
+        assert!(supports_room_events(PROTOCOL_VERSION));
+        assert!(!supports_room_events("1.4"));
+        assert!(!supports_room_events("2.0"));
+    }
+}
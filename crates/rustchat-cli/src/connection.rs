@@ -0,0 +1,254 @@
+use anyhow::{bail, Context, Result};
+use rand::Rng;
+use serde::Deserialize;
+use std::path::Path;
+use std::time::Duration;
+use tracing::info;
+
+/// 连接配置文件名（位于 ~/.rustchat/ 下）
+const CONNECTION_FILE_NAME: &str = "connection.toml";
+
+/// 启动时默认加载的历史消息条数
+pub const DEFAULT_HISTORY_LIMIT: usize = 100;
+
+/// 连接断开后重连前的基础等待时间
+pub const DISCONNECT_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// 连接配置
+#[derive(Clone, Debug)]
+pub struct ConnectionConfig {
+    pub url: String,
+    pub max_reconnect_attempts: u32,
+    pub initial_retry_delay: Duration,
+    pub max_retry_delay: Duration,
+    pub retry_backoff_factor: f64,
+    /// 重连间隔的随机抖动比例（0~1），避免服务器重启后所有客户端同时重连
+    pub jitter_factor: f64,
+    /// 启动时从本地数据库加载的历史消息条数，0表示不加载
+    pub history_limit: usize,
+}
+
+impl Default for ConnectionConfig {
+    fn default() -> Self {
+        Self {
+            url: "ws://127.0.0.1:8080/ws".to_string(),
+            max_reconnect_attempts: 10,
+            initial_retry_delay: Duration::from_secs(1),
+            max_retry_delay: Duration::from_secs(30),
+            retry_backoff_factor: 2.0,
+            jitter_factor: 0.2,
+            history_limit: DEFAULT_HISTORY_LIMIT,
+        }
+    }
+}
+
+/// connection.toml 的内容，所有字段均可省略
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConnectionFile {
+    url: Option<String>,
+    max_reconnect_attempts: Option<u32>,
+    /// 初始重连间隔（秒）
+    initial_retry_delay: Option<f64>,
+    /// 最大重连间隔（秒）
+    max_retry_delay: Option<f64>,
+    retry_backoff_factor: Option<f64>,
+    /// 重连间隔的随机抖动比例，0表示不抖动
+    jitter_factor: Option<f64>,
+    history_limit: Option<usize>,
+}
+
+impl ConnectionConfig {
+    /// 加载连接配置：默认值 < connection.toml < 命令行 --server
+    pub async fn load(config_dir: &Path, server_override: Option<&str>) -> Result<Self> {
+        let file_path = config_dir.join(CONNECTION_FILE_NAME);
+        let mut config = Self::default();
+
+        if file_path.exists() {
+            let content = tokio::fs::read_to_string(&file_path)
+                .await
+                .with_context(|| format!("无法读取连接配置文件: {:?}", file_path))?;
+            config = config
+                .merge_toml(&content)
+                .with_context(|| format!("连接配置文件格式错误: {:?}", file_path))?;
+            info!("已加载连接配置: {:?}", file_path);
+        }
+
+        if let Some(url) = server_override {
+            config.url = url.to_string();
+        }
+
+        validate_server_url(&config.url)?;
+        Ok(config)
+    }
+
+    /// 用TOML内容覆盖对应字段
+    fn merge_toml(mut self, content: &str) -> Result<Self> {
+        let file: ConnectionFile = toml::from_str(content)?;
+
+        if let Some(url) = file.url {
+            self.url = url;
+        }
+        if let Some(attempts) = file.max_reconnect_attempts {
+            self.max_reconnect_attempts = attempts;
+        }
+        if let Some(delay) = file.initial_retry_delay {
+            if !delay.is_finite() || delay < 0.0 {
+                bail!("initial_retry_delay 必须是非负数");
+            }
+            self.initial_retry_delay = Duration::from_secs_f64(delay);
+        }
+        if let Some(delay) = file.max_retry_delay {
+            if !delay.is_finite() || delay < 0.0 {
+                bail!("max_retry_delay 必须是非负数");
+            }
+            self.max_retry_delay = Duration::from_secs_f64(delay);
+        }
+        if self.max_retry_delay < self.initial_retry_delay {
+            bail!("max_retry_delay 不能小于 initial_retry_delay");
+        }
+        if let Some(factor) = file.retry_backoff_factor {
+            if !factor.is_finite() || factor < 1.0 {
+                bail!("retry_backoff_factor 必须大于等于 1.0");
+            }
+            self.retry_backoff_factor = factor;
+        }
+        if let Some(jitter) = file.jitter_factor {
+            if !(0.0..=1.0).contains(&jitter) {
+                bail!("jitter_factor 必须在 0 到 1 之间");
+            }
+            self.jitter_factor = jitter;
+        }
+        if let Some(limit) = file.history_limit {
+            self.history_limit = limit;
+        }
+
+        Ok(self)
+    }
+
+    /// 按指数退避计算下一次的重连间隔，不超过 `max_retry_delay`
+    pub fn next_retry_delay(&self, current: Duration) -> Duration {
+        Duration::from_millis(((current.as_millis() as f64) * self.retry_backoff_factor) as u64).min(self.max_retry_delay)
+    }
+
+    /// 连接断开后第一次重连前的等待时间，同样加上随机抖动，避免服务器重启时所有客户端同时重连
+    pub fn disconnect_retry_delay(&self, rng: &mut impl Rng) -> Duration {
+        self.jittered_delay(DISCONNECT_RETRY_DELAY, rng)
+    }
+
+    /// 对重连间隔加上 ±`jitter_factor` 的随机抖动，结果不超过 `max_retry_delay`
+    pub fn jittered_delay(&self, delay: Duration, rng: &mut impl Rng) -> Duration {
+        if self.jitter_factor <= 0.0 {
+            return delay;
+        }
+        let scale = 1.0 + rng.gen_range(-self.jitter_factor..=self.jitter_factor);
+        delay.mul_f64(scale).min(self.max_retry_delay)
+    }
+}
+
+/// 校验服务器地址必须是 ws:// 或 wss://
+pub fn validate_server_url(url: &str) -> Result<()> {
+    let parsed = url::Url::parse(url)
+        .with_context(|| format!("无效的服务器地址: {}", url))?;
+
+    match parsed.scheme() {
+        "ws" | "wss" => Ok(()),
+        scheme => bail!(
+            "服务器地址必须使用 ws:// 或 wss:// 协议，当前为 {}://: {}",
+            scheme,
+            url
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_toml_overrides_fields() {
+        let content = r#"
+url = "wss://chat.example.com/ws"
+max_reconnect_attempts = 3
+initial_retry_delay = 0.5
+"#;
+        let config = ConnectionConfig::default().merge_toml(content).unwrap();
+
+        assert_eq!(config.url, "wss://chat.example.com/ws");
+        assert_eq!(config.max_reconnect_attempts, 3);
+        assert_eq!(config.initial_retry_delay, Duration::from_millis(500));
+        // 未配置的字段保持默认值
+        assert_eq!(config.retry_backoff_factor, 2.0);
+        assert_eq!(config.max_retry_delay, Duration::from_secs(30));
+        assert_eq!(config.history_limit, DEFAULT_HISTORY_LIMIT);
+
+        let config = ConnectionConfig::default().merge_toml("history_limit = 0").unwrap();
+        assert_eq!(config.history_limit, 0);
+        assert!(ConnectionConfig::default().merge_toml("history_limit = -5").is_err());
+    }
+
+    #[test]
+    fn test_max_retry_delay() {
+        let config = ConnectionConfig::default().merge_toml("max_retry_delay = 5").unwrap();
+        assert_eq!(config.max_retry_delay, Duration::from_secs(5));
+
+        let result = ConnectionConfig::default().merge_toml("initial_retry_delay = 10\nmax_retry_delay = 5");
+        assert!(result.is_err());
+        assert!(ConnectionConfig::default().merge_toml("max_retry_delay = -1").is_err());
+    }
+
+    #[test]
+    fn test_retry_delay_jitter_bounds() {
+        use rand::SeedableRng;
+
+        let config = ConnectionConfig::default().merge_toml("jitter_factor = 0.2").unwrap();
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        let mut delay = config.initial_retry_delay;
+        for _ in 0..10 {
+            for _ in 0..100 {
+                let jittered = config.jittered_delay(delay, &mut rng);
+                assert!(jittered >= delay.mul_f64(0.8) && jittered <= delay.mul_f64(1.2));
+                assert!(jittered <= config.max_retry_delay);
+            }
+            delay = config.next_retry_delay(delay);
+        }
+        // 退避到上限后保持不变，抖动也不会超过上限
+        assert_eq!(delay, config.max_retry_delay);
+
+        // 断线后的等待同样加上抖动，不会所有客户端都恰好等待相同的时间
+        let delays: Vec<Duration> = (0..100).map(|_| config.disconnect_retry_delay(&mut rng)).collect();
+        assert!(delays.iter().all(|d| *d >= DISCONNECT_RETRY_DELAY.mul_f64(0.8) && *d <= DISCONNECT_RETRY_DELAY.mul_f64(1.2)));
+        assert!(delays.iter().any(|d| *d != delays[0]));
+
+        let config = ConnectionConfig::default().merge_toml("jitter_factor = 0").unwrap();
+        assert_eq!(config.jittered_delay(Duration::from_secs(4), &mut rng), Duration::from_secs(4));
+        assert_eq!(config.disconnect_retry_delay(&mut rng), DISCONNECT_RETRY_DELAY);
+        assert!(ConnectionConfig::default().merge_toml("jitter_factor = 1.5").is_err());
+        assert!(ConnectionConfig::default().merge_toml("jitter_factor = -0.1").is_err());
+    }
+
+    #[test]
+    fn test_validate_server_url() {
+        assert!(validate_server_url("ws://127.0.0.1:8080/ws").is_ok());
+        assert!(validate_server_url("wss://chat.example.com/ws").is_ok());
+        assert!(validate_server_url("http://127.0.0.1:8080/ws").is_err());
+        assert!(validate_server_url("not a url").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_server_override_wins_over_file() {
+        let dir = std::env::temp_dir().join(format!("rustchat-conn-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(CONNECTION_FILE_NAME), "url = \"ws://file.example.com/ws\"\n").unwrap();
+
+        let from_file = ConnectionConfig::load(&dir, None).await.unwrap();
+        assert_eq!(from_file.url, "ws://file.example.com/ws");
+
+        let overridden = ConnectionConfig::load(&dir, Some("ws://cli.example.com/ws")).await.unwrap();
+        assert_eq!(overridden.url, "ws://cli.example.com/ws");
+
+        assert!(ConnectionConfig::load(&dir, Some("https://cli.example.com")).await.is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
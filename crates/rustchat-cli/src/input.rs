@@ -0,0 +1,165 @@
+use crate::{AppState, CommandParser};
+use rustchat_types::Message;
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+use std::borrow::Cow;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// 输入辅助器，提供命令与昵称的Tab补全以及彩色提示符
+pub struct InputHelper {
+    state: Arc<Mutex<AppState>>,
+}
+
+impl InputHelper {
+    pub fn new(state: Arc<Mutex<AppState>>) -> Self {
+        Self { state }
+    }
+}
+
+/// 从消息列表中收集出现过的昵称（去重，保持首次出现顺序）
+pub fn collect_nicknames(messages: &[Message]) -> Vec<String> {
+    let mut nicknames: Vec<String> = Vec::new();
+    for msg in messages {
+        if msg.is_system() {
+            continue;
+        }
+        if let Some(nick) = &msg.from_nick {
+            if !nick.is_empty() && !nicknames.contains(nick) {
+                nicknames.push(nick.clone());
+            }
+        }
+    }
+    nicknames
+}
+
+/// 计算补全候选项，返回替换起始位置与候选列表
+pub fn complete_input(line: &str, pos: usize, nicknames: &[String]) -> (usize, Vec<String>) {
+    let before_cursor = &line[..pos];
+    let word_start = before_cursor
+        .rfind(char::is_whitespace)
+        .map(|idx| idx + 1)
+        .unwrap_or(0);
+    let word = &before_cursor[word_start..];
+
+    // 行首的 / 开头单词补全为命令
+    if word_start == 0 && word.starts_with('/') {
+        let word_lower = word.to_lowercase();
+        let candidates = CommandParser::COMMANDS
+            .iter()
+            .filter(|cmd| cmd.starts_with(&word_lower))
+            .map(|cmd| cmd.to_string())
+            .collect();
+        return (word_start, candidates);
+    }
+
+    if word.is_empty() {
+        return (word_start, Vec::new());
+    }
+
+    // 其余单词补全为昵称，支持 @ 前缀
+    let (prefix, partial) = match word.strip_prefix('@') {
+        Some(rest) => ("@", rest),
+        None => ("", word),
+    };
+    let partial_lower = partial.to_lowercase();
+    let candidates = nicknames
+        .iter()
+        .filter(|nick| nick.to_lowercase().starts_with(&partial_lower))
+        .map(|nick| format!("{}{}", prefix, nick))
+        .collect();
+
+    (word_start, candidates)
+}
+
+impl Completer for InputHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let nicknames = {
+            let app_state = self.state.blocking_lock();
+            collect_nicknames(&app_state.messages)
+        };
+
+        let (start, candidates) = complete_input(line, pos, &nicknames);
+        let pairs = candidates
+            .into_iter()
+            .map(|candidate| Pair {
+                display: candidate.clone(),
+                replacement: candidate,
+            })
+            .collect();
+
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for InputHelper {
+    type Hint = String;
+}
+
+impl Highlighter for InputHelper {
+    fn highlight_prompt<'b, 's: 'b, 'p: 'b>(
+        &'s self,
+        prompt: &'p str,
+        _default: bool,
+    ) -> Cow<'b, str> {
+        // 使用当前主题，/theme 切换后立即生效
+        let app_state = self.state.blocking_lock();
+        Cow::Owned(app_state.color_display.styled_prompt(prompt))
+    }
+}
+
+impl Validator for InputHelper {}
+
+impl Helper for InputHelper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustchat_types::UserId;
+
+    #[test]
+    fn test_complete_command() {
+        let (start, candidates) = complete_input("/hi", 3, &[]);
+        assert_eq!(start, 0);
+        assert_eq!(candidates, vec!["/history".to_string()]);
+
+        let (_, candidates) = complete_input("/", 1, &[]);
+        assert_eq!(candidates.len(), CommandParser::COMMANDS.len());
+    }
+
+    #[test]
+    fn test_complete_nickname() {
+        let nicknames = vec!["Alice".to_string(), "alfred".to_string(), "Bob".to_string()];
+
+        let (start, candidates) = complete_input("hello al", 8, &nicknames);
+        assert_eq!(start, 6);
+        assert_eq!(candidates, vec!["Alice".to_string(), "alfred".to_string()]);
+
+        let (start, candidates) = complete_input("hi @b", 5, &nicknames);
+        assert_eq!(start, 3);
+        assert_eq!(candidates, vec!["@Bob".to_string()]);
+    }
+
+    #[test]
+    fn test_collect_nicknames() {
+        let user_id = UserId::new();
+        let messages = vec![
+            Message::new_text(user_id.clone(), "a".to_string(), Some("Alice".to_string())),
+            Message::new_text(user_id.clone(), "b".to_string(), Some("Alice".to_string())),
+            Message::new_text(user_id, "c".to_string(), None),
+            Message::new_system("started".to_string()),
+        ];
+
+        assert_eq!(collect_nicknames(&messages), vec!["Alice".to_string()]);
+    }
+}
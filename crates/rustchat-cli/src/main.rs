@@ -1,19 +1,39 @@
+mod auth;
 mod colors;
+mod compat;
+mod connection;
+mod input;
 
 use anyhow::{Context, Result};
-use colors::ColorDisplay;
-use crossterm::ExecutableCommand;
+use auth::{AuthApiClient, AuthSession};
+use clap::Parser;
+use colors::{ColorDisplay, ColorTheme, TimestampFormat};
+use connection::ConnectionConfig;
+use input::InputHelper;
 use futures_util::{SinkExt, StreamExt};
 use rustchat_core::{UserConfigManager, MessageDatabase};
-use rustchat_types::{Message, UserId};
+use rustchat_types::{validate_nickname, ErrorCode, Message, MessageId, UserId};
 use serde::{Deserialize, Serialize};
-use std::io::{self, Write};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
 use tokio::time;
-use tokio_tungstenite::{connect_async, tungstenite::protocol::Message as WsMessage};
-use tracing::{error, info};
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::Editor;
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::error::{Error as WsError, ProtocolError},
+    tungstenite::protocol::{frame::coding::CloseCode, CloseFrame, Message as WsMessage},
+};
+use tracing::{debug, error, info, warn};
+
+/// 等待服务器确认消息的最长时间
+const MESSAGE_ACK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 退出时等待服务器回应关闭帧的最长时间
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(3);
 
 // 房间相关的 API 客户端和数据结构
 
@@ -24,8 +44,8 @@ struct CreateRoomRequest {
     max_members: Option<usize>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct RoomResponse {
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RoomResponse {
     id: String,
     name: String,
     owner: String,
@@ -50,11 +70,27 @@ struct RoomApiClient {
     base_url: String,
 }
 
+/// 创建HTTP客户端，已登录时自动携带访问令牌
+fn authorized_http_client(app_state: &AppState) -> reqwest::Client {
+    let mut headers = reqwest::header::HeaderMap::new();
+    if let Some(session) = &app_state.auth {
+        if let Ok(value) = reqwest::header::HeaderValue::from_str(&format!("Bearer {}", session.access_token)) {
+            headers.insert(reqwest::header::AUTHORIZATION, value);
+        }
+    }
+
+    reqwest::Client::builder()
+        .default_headers(headers)
+        .build()
+        .unwrap_or_default()
+}
+
 impl RoomApiClient {
-    fn new() -> Self {
+    /// 根据应用状态创建客户端，已登录时自动携带访问令牌
+    fn from_state(app_state: &AppState) -> Self {
         Self {
-            client: reqwest::Client::new(),
-            base_url: "http://127.0.0.1:8080".to_string(),
+            client: authorized_http_client(app_state),
+            base_url: app_state.api_base_url.clone(),
         }
     }
     
@@ -88,95 +124,351 @@ impl RoomApiClient {
         }
     }
     
-    async fn join_room(&self, user_id: &str, room_id: String) -> Result<RoomResponse> {
-        let url = format!("{}/api/rooms/{}/join?user_id={}", self.base_url, room_id, user_id);
+    async fn get_room(&self, room_id: &str) -> Result<RoomResponse> {
+        let url = format!("{}/api/rooms/{}", self.base_url, room_id);
         let response = self.client
-            .post(&url)
+            .get(&url)
             .send()
             .await
-            .context("加入房间请求失败")?;
-        
+            .context("获取房间信息请求失败")?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(anyhow::anyhow!("房间 {} 不存在", room_id));
+        }
+
         let api_response: ApiResponse<RoomResponse> = response
             .json()
             .await
-            .context("解析加入房间响应失败")?;
-        
+            .context("解析房间信息响应失败")?;
+
         if api_response.success {
             api_response.data.ok_or_else(|| anyhow::anyhow!("响应数据为空"))
         } else {
             Err(anyhow::anyhow!(
-                "加入房间失败: {}",
+                "获取房间信息失败: {}",
                 api_response.error.unwrap_or_else(|| "未知错误".to_string())
             ))
         }
     }
     
-    async fn leave_room(&self, user_id: &str, room_id: String) -> Result<RoomResponse> {
-        let url = format!("{}/api/rooms/{}/leave?user_id={}", self.base_url, room_id, user_id);
+    async fn list_rooms(&self) -> Result<Vec<RoomResponse>> {
+        let url = format!("{}/api/rooms", self.base_url);
         let response = self.client
-            .post(&url)
+            .get(&url)
             .send()
             .await
-            .context("离开房间请求失败")?;
+            .context("获取房间列表请求失败")?;
         
-        let api_response: ApiResponse<RoomResponse> = response
+        let api_response: ApiResponse<Vec<RoomResponse>> = response
             .json()
             .await
-            .context("解析离开房间响应失败")?;
+            .context("解析房间列表响应失败")?;
         
         if api_response.success {
-            api_response.data.ok_or_else(|| anyhow::anyhow!("响应数据为空"))
+            Ok(api_response.data.unwrap_or_default())
         } else {
             Err(anyhow::anyhow!(
-                "离开房间失败: {}",
+                "获取房间列表失败: {}",
                 api_response.error.unwrap_or_else(|| "未知错误".to_string())
             ))
         }
     }
-    
-    async fn list_user_rooms(&self, user_id: &str) -> Result<Vec<RoomResponse>> {
-        let url = format!("{}/api/user/rooms?user_id={}", self.base_url, user_id);
+}
+
+/// 好友列表中的一项
+#[derive(Serialize, Deserialize, Debug)]
+struct FriendEntry {
+    user_id: UserId,
+    alias: Option<String>,
+}
+
+impl FriendEntry {
+    /// 显示名称，优先使用备注名
+    fn display_name(&self) -> String {
+        match &self.alias {
+            Some(alias) if !alias.is_empty() => alias.clone(),
+            _ => self.user_id.to_string(),
+        }
+    }
+}
+
+/// 好友 API 客户端
+struct FriendApiClient {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl FriendApiClient {
+    /// 根据应用状态创建客户端，已登录时自动携带访问令牌
+    fn from_state(app_state: &AppState) -> Self {
+        Self {
+            client: authorized_http_client(app_state),
+            base_url: app_state.api_base_url.clone(),
+        }
+    }
+
+    async fn list_friends(&self) -> Result<Vec<FriendEntry>> {
+        let url = format!("{}/api/friends/list", self.base_url);
         let response = self.client
             .get(&url)
             .send()
             .await
-            .context("获取房间列表请求失败")?;
-        
-        let api_response: ApiResponse<Vec<RoomResponse>> = response
+            .context("获取好友列表请求失败")?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!("获取好友列表失败 ({}): {}", status, body));
+        }
+
+        response
             .json()
             .await
-            .context("解析房间列表响应失败")?;
-        
-        if api_response.success {
-            Ok(api_response.data.unwrap_or_default())
-        } else {
-            Err(anyhow::anyhow!(
-                "获取房间列表失败: {}",
-                api_response.error.unwrap_or_else(|| "未知错误".to_string())
-            ))
+            .context("解析好友列表响应失败")
+    }
+}
+
+/// 服务器健康检查接口中的运行信息
+#[derive(Deserialize, Debug)]
+struct ServerRuntime {
+    version: String,
+    uptime: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct HealthResponse {
+    server: ServerRuntime,
+}
+
+/// 查询服务器版本和运行时长的超时时间
+const SERVER_RUNTIME_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// 通过健康检查接口查询服务器版本和运行时长
+async fn fetch_server_runtime(api_base_url: &str) -> Result<ServerRuntime> {
+    let response = reqwest::Client::new()
+        .get(format!("{}/health", api_base_url))
+        .timeout(SERVER_RUNTIME_TIMEOUT)
+        .send()
+        .await
+        .context("查询服务器状态请求失败")?;
+    let health: HealthResponse = response.json().await.context("解析服务器状态响应失败")?;
+    Ok(health.server)
+}
+
+/// 用户的公开资料（`GET /api/users/{id}`）
+#[derive(Deserialize, Debug)]
+struct UserProfile {
+    user_id: UserId,
+    nickname: Option<String>,
+    display_name: Option<String>,
+    is_online: bool,
+    /// 旧版服务器不提供
+    #[serde(default)]
+    first_seen_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// 查询用户的公开资料，用户不存在时返回None
+async fn fetch_user_profile(client: &reqwest::Client, api_base_url: &str, user_id: &UserId) -> Result<Option<UserProfile>> {
+    let response = client
+        .get(format!("{}/api/users/{}", api_base_url, user_id))
+        .timeout(SERVER_RUNTIME_TIMEOUT)
+        .send()
+        .await
+        .context("查询用户资料请求失败")?;
+
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!("查询用户资料失败 ({})", response.status()));
+    }
+
+    let api_response: ApiResponse<UserProfile> = response
+        .json()
+        .await
+        .context("解析用户资料响应失败")?;
+    match api_response.data {
+        Some(profile) if api_response.success => Ok(Some(profile)),
+        _ => Err(anyhow::anyhow!(
+            "查询用户资料失败: {}",
+            api_response.error.unwrap_or_else(|| "未知错误".to_string())
+        )),
+    }
+}
+
+/// /whois 显示的用户信息，`online` 为None表示无法从服务器获取
+struct WhoisInfo {
+    user_id: UserId,
+    nickname: Option<String>,
+    online: Option<bool>,
+    first_seen_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl WhoisInfo {
+    fn rows(&self, color_display: &ColorDisplay) -> Vec<(&'static str, String)> {
+        let status = match self.online {
+            Some(true) => "在线",
+            Some(false) => "离线",
+            None => "未知",
+        };
+        vec![
+            ("用户ID", self.user_id.short()),
+            ("昵称", self.nickname.clone().unwrap_or_else(|| "未设置".to_string())),
+            ("状态", status.to_string()),
+            (
+                "首次出现",
+                self.first_seen_at
+                    .map(|at| color_display.format_timestamp(&at))
+                    .unwrap_or_else(|| "未知".to_string()),
+            ),
+        ]
+    }
+}
+
+/// 补齐断线消息时每次请求的条数（服务器单次最多返回100条）
+const GAP_RECOVERY_PAGE_SIZE: usize = 100;
+/// 重连后最多补齐的消息条数
+const MAX_GAP_RECOVERY_MESSAGES: usize = 1000;
+
+/// 获取服务器在 `after` 之后接收的全局消息，按接收时间正序排列
+async fn fetch_messages_after(
+    client: &reqwest::Client,
+    api_base_url: &str,
+    after: chrono::DateTime<chrono::Utc>,
+) -> Result<Vec<Message>> {
+    let response = client
+        .get(format!("{}/api/messages", api_base_url))
+        .query(&[
+            ("after", after.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true)),
+            ("limit", GAP_RECOVERY_PAGE_SIZE.to_string()),
+        ])
+        .send()
+        .await
+        .context("获取断线期间的消息请求失败")?;
+
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!("获取断线期间的消息失败 ({})", response.status()));
+    }
+
+    let api_response: ApiResponse<Vec<Message>> = response
+        .json()
+        .await
+        .context("解析断线期间的消息响应失败")?;
+
+    if api_response.success {
+        Ok(api_response.data.unwrap_or_default())
+    } else {
+        Err(anyhow::anyhow!(
+            "获取断线期间的消息失败: {}",
+            api_response.error.unwrap_or_else(|| "未知错误".to_string())
+        ))
+    }
+}
+
+/// 重连后补齐断线期间错过的全局消息，已显示过的消息不会重复显示
+async fn recover_message_gap(state: &Arc<Mutex<AppState>>, message_db: &MessageDatabase) {
+    let (client, api_base_url, mut after, color_display) = {
+        let app_state = state.lock().await;
+        let Some(after) = app_state.latest_global_message_time() else {
+            return;
+        };
+        (
+            authorized_http_client(&app_state),
+            app_state.api_base_url.clone(),
+            after,
+            app_state.color_display.clone(),
+        )
+    };
+
+    let mut missed = Vec::new();
+    while missed.len() < MAX_GAP_RECOVERY_MESSAGES {
+        let page = match fetch_messages_after(&client, &api_base_url, after).await {
+            Ok(page) => page,
+            Err(err) => {
+                warn!("补齐断线期间的消息失败: {:#}", err);
+                color_display.display_error("无法获取断线期间的消息，部分消息可能缺失");
+                return;
+            }
+        };
+        let full_page = page.len() == GAP_RECOVERY_PAGE_SIZE;
+        if let Some(last) = page.last() {
+            after = last.received_at.unwrap_or(last.timestamp);
+        }
+        missed.extend(page);
+        if !full_page {
+            break;
         }
     }
+
+    let unseen: Vec<Message> = {
+        let app_state = state.lock().await;
+        missed.into_iter().filter(|msg| !app_state.seen_messages.contains(&msg.id)).collect()
+    };
+    if unseen.is_empty() {
+        return;
+    }
+
+    color_display.display_info(&format!("断线期间有 {} 条新消息:", unseen.len()));
+    for msg in unseen {
+        receive_message(msg, state, message_db, &color_display).await;
+    }
 }
 
 /// WebSocket事件类型（与服务器端保持一致）
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "event", content = "data")]
 pub enum WsEvent {
-    Connected { user_id: UserId },
+    Connected {
+        user_id: UserId,
+        #[serde(default)]
+        resume_token: Option<String>,
+    },
+    ServerInfo { version: String, protocol_version: String },
     Message(Message),
+    MessageBatch(Vec<Message>),
     UserJoined { user_id: UserId, nickname: Option<String> },
     UserLeft { user_id: UserId },
+    RoomMessage { room_id: String, message: Message },
+    RoomMessageBatch { room_id: String, messages: Vec<Message> },
+    UserJoinedRoom { room_id: String, user_id: UserId },
+    UserLeftRoom { room_id: String, user_id: UserId },
+    RoomJoined { room: RoomResponse },
+    RoomLeft { room_id: String },
+    RoomList { rooms: Vec<RoomResponse> },
+    MessagesPurged { user_id: UserId },
+    NicknameChanged { user_id: UserId, old_nick: Option<String>, new_nick: String },
+    MessagesExpired { message_ids: Vec<MessageId> },
+    Mentioned { message: Message },
+    ReactionUpdated { message_id: MessageId, emoji: String, count: usize, users: Vec<UserId> },
+    MessageAck { client_msg_id: String, message_id: MessageId },
     Ping,
     Pong,
-    Error { message: String },
+    /// 协议1.3之前的服务器不提供错误代码
+    Error {
+        #[serde(default)]
+        code: ErrorCode,
+        message: String,
+    },
 }
 
 /// 客户端消息类型
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", content = "data")]
 pub enum ClientMessage {
-    SendMessage { content: String, nickname: Option<String> },
+    SendMessage {
+        content: String,
+        nickname: Option<String>,
+        client_timestamp: Option<chrono::DateTime<chrono::Utc>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        client_msg_id: Option<String>,
+    },
+    SendAction { content: String, nickname: Option<String> },
+    SendRoomMessage { room_id: String, content: String },
+    JoinRoom { room_id: String },
+    LeaveRoom { room_id: String },
     SetNickname { nickname: String },
+    React { message_id: MessageId, emoji: String },
+    Unreact { message_id: MessageId, emoji: String },
     Pong,
 }
 
@@ -185,10 +477,47 @@ pub struct AppState {
     pub user_id: Option<UserId>,
     pub nickname: Option<String>,
     pub messages: Vec<Message>,
+    /// 已缓存消息的ID，重连后服务器重发的消息不再重复显示
+    pub seen_messages: HashSet<MessageId>,
+    /// 每条消息的表情回应数量（按表情首次出现的顺序）
+    pub reactions: HashMap<MessageId, Vec<(String, usize)>>,
     pub connected: bool,
+    /// 当前连接建立的时间，未连接时为None
+    pub connected_since: Option<Instant>,
     pub color_display: ColorDisplay,
+    /// 当前房间，设置后直接输入的消息发送到该房间
     pub current_room_id: Option<String>,
     pub current_room_name: Option<String>,
+    /// 已知的房间名称（房间ID -> 名称），用于显示房间消息
+    pub room_names: HashMap<String, String>,
+    /// 已知的用户昵称（用户ID -> 昵称），随收到的消息和昵称变更事件更新
+    pub nicknames: HashMap<UserId, String>,
+    /// HTTP API根地址（由WebSocket地址推导）
+    pub api_base_url: String,
+    /// 登录会话，匿名使用时为None
+    pub auth: Option<AuthSession>,
+    /// 是否需要立即重连（如登录后使用令牌重新连接）
+    pub reconnect_requested: bool,
+    /// 服务器协议版本不兼容，停止自动重连
+    pub incompatible_server: bool,
+    /// 当前服务器是否会确认收到的消息
+    pub message_ack_supported: bool,
+    /// 当前服务器是否会向加入/离开房间的连接发送确认事件
+    pub room_events_supported: bool,
+    /// 已发送、尚未收到服务器确认的消息（client_msg_id -> 内容预览）
+    pub pending_messages: HashMap<String, String>,
+    /// 服务器签发的断线恢复令牌，匿名重连时携带以沿用同一用户ID
+    pub resume_token: Option<String>,
+    /// 正在重连时为 (已尝试次数, 最大次数)，连接成功后清空
+    pub reconnect_attempt: Option<(u32, u32)>,
+    /// 执行 /clear-history 后等待用户输入 yes 确认
+    pub clear_history_pending: bool,
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl AppState {
@@ -196,17 +525,213 @@ impl AppState {
             user_id: None,
             nickname: None,
             messages: Vec::new(),
+            seen_messages: HashSet::new(),
+            reactions: HashMap::new(),
             connected: false,
+            connected_since: None,
             color_display: ColorDisplay::new(),
             current_room_id: None,
             current_room_name: None,
+            room_names: HashMap::new(),
+            nicknames: HashMap::new(),
+            api_base_url: "http://127.0.0.1:8080".to_string(),
+            auth: None,
+            reconnect_requested: false,
+            incompatible_server: false,
+            message_ack_supported: false,
+            room_events_supported: false,
+            pending_messages: HashMap::new(),
+            resume_token: None,
+            reconnect_attempt: None,
+            clear_history_pending: false,
+        }
+    }
+
+    /// 当前连接状态的描述，用于 /status
+    pub fn connection_status(&self) -> String {
+        if let Some((attempt, max_attempts)) = self.reconnect_attempt {
+            return format!("正在重连（第 {}/{} 次）", attempt, max_attempts);
+        }
+        match self.connected_since {
+            Some(since) if self.connected => format!("已连接（{}）", format_uptime(since.elapsed())),
+            _ => "未连接".to_string(),
+        }
+    }
+}
+
+/// 消息内容的简短预览（用于回应汇总行）
+fn message_preview(message: &Message) -> String {
+    text_preview(&message.render_plain())
+}
+
+/// 针对错误代码给出的操作提示
+fn error_hint(code: ErrorCode) -> Option<&'static str> {
+    match code {
+        ErrorCode::RateLimited => Some("消息未发送，请稍后再试"),
+        ErrorCode::RoomNotMember => Some("使用 /join <房间ID> 加入房间后再发送，服务器重启后需要重新加入"),
+        ErrorCode::NickCooldown => Some("昵称未修改，冷却结束后再使用 /nick"),
+        ErrorCode::SlowConsumer => Some("部分消息未显示，重新连接后可收到最新消息"),
+        ErrorCode::SessionTerminated => Some("连接已被服务器结束，如需继续使用请重新登录"),
+        ErrorCode::IdleWarning => Some("发送任意消息即可保持连接"),
+        _ => None,
+    }
+}
+
+/// 格式化连接时长，例如 `1小时2分3秒`
+fn format_uptime(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, minutes, seconds) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if hours > 0 {
+        format!("{}小时{}分{}秒", hours, minutes, seconds)
+    } else if minutes > 0 {
+        format!("{}分{}秒", minutes, seconds)
+    } else {
+        format!("{}秒", seconds)
+    }
+}
+
+/// 文本的简短预览，过长时截断
+fn text_preview(text: &str) -> String {
+    const PREVIEW_CHARS: usize = 20;
+    let body = text.replace('\n', " ");
+    if body.chars().count() > PREVIEW_CHARS {
+        format!("{}…", body.chars().take(PREVIEW_CHARS).collect::<String>())
+    } else {
+        body
+    }
+}
+
+/// 把昵称解析为用户ID（不区分大小写）
+///
+/// 先查已知的在线昵称，找不到时按最近消息的发送者昵称从新到旧查找
+fn resolve_nickname(nickname: &str, nicknames: &HashMap<UserId, String>, recent: &[Message]) -> Option<UserId> {
+    nicknames
+        .iter()
+        .find(|(_, known)| known.eq_ignore_ascii_case(nickname))
+        .map(|(user_id, _)| user_id.clone())
+        .or_else(|| {
+            recent
+                .iter()
+                .rev()
+                .find(|msg| msg.from_nick.as_deref().is_some_and(|nick| nick.eq_ignore_ascii_case(nickname)))
+                .map(|msg| msg.from.clone())
+        })
+}
+
+/// 显示消息（使用彩色显示），提及本地用户的消息会高亮
+fn display_message(msg: &Message, color_display: &ColorDisplay, local_user: Option<&UserId>) {
+    let mentioned = local_user.is_some_and(|user_id| msg.mentions_user(user_id));
+    color_display.display_message(msg, mentioned);
+}
+
+impl AppState {
+    /// 房间的显示名称
+    fn room_label(&self, room_id: &str) -> String {
+        colors::room_label(room_id, self.room_names.get(room_id).map(String::as_str))
+    }
+
+    /// 设置当前房间并记录房间名称
+    fn enter_room(&mut self, room_id: String, room_name: String) {
+        self.room_names.insert(room_id.clone(), room_name.clone());
+        self.current_room_id = Some(room_id);
+        self.current_room_name = Some(room_name);
+    }
+
+    /// 离开房间，离开的是当前房间时返回true，之后的消息发送到公共聊天
+    fn leave_room(&mut self, room_id: &str) -> bool {
+        if self.current_room_id.as_deref() != Some(room_id) {
+            return false;
+        }
+        self.current_room_id = None;
+        self.current_room_name = None;
+        true
+    }
+
+    /// 缓存消息，已缓存过相同ID的消息时返回false
+    fn add_message(&mut self, msg: Message) -> bool {
+        if !self.seen_messages.insert(msg.id.clone()) {
+            return false;
+        }
+        self.messages.push(msg);
+        true
+    }
+
+    /// 已缓存的全局消息中最新一条被服务器接收的时间，用于重连后补齐断线期间的消息
+    fn latest_global_message_time(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.messages
+            .iter()
+            .filter(|msg| msg.room_id.is_none())
+            .map(|msg| msg.received_at.unwrap_or(msg.timestamp))
+            .max()
+    }
+
+    /// 记录用户的昵称
+    fn remember_nickname(&mut self, user_id: &UserId, nickname: &str) {
+        self.nicknames.insert(user_id.clone(), nickname.to_string());
+    }
+
+    /// 用户的显示名称：已知昵称，否则为简短的用户ID
+    fn user_label(&self, user_id: &UserId) -> String {
+        match self.nicknames.get(user_id) {
+            Some(nickname) => nickname.clone(),
+            None => format!("用户 {}", user_id.short()),
         }
     }
 }
 
-/// 显示消息（使用彩色显示）
-fn display_message(msg: &Message, color_display: &ColorDisplay) {
-    color_display.display_message(msg);
+/// 处理收到的新消息：缓存、保存到本地数据库并显示，房间消息带房间前缀
+///
+/// 已经显示过的消息（如重连后重复收到）直接忽略
+async fn receive_message(
+    msg: Message,
+    state: &Arc<Mutex<AppState>>,
+    message_db: &MessageDatabase,
+    color_display: &ColorDisplay,
+) {
+    let mut app_state = state.lock().await;
+    if !app_state.add_message(msg.clone()) {
+        debug!("忽略重复消息: {}", msg.id);
+        return;
+    }
+    if let Some(nickname) = &msg.from_nick {
+        app_state.remember_nickname(&msg.from, nickname);
+    }
+    let local_user = app_state.user_id.clone();
+    let room_label = msg.room_id.as_deref().map(|room_id| app_state.room_label(room_id));
+    drop(app_state);
+
+    // 保存消息到数据库
+    if let Err(err) = message_db.save_message(&msg).await {
+        error!("保存消息到数据库失败: {}", err);
+    }
+
+    match room_label {
+        Some(room_label) => {
+            let mentioned = local_user.as_ref().is_some_and(|user_id| msg.mentions_user(user_id));
+            color_display.display_room_message(&room_label, &msg, mentioned);
+        }
+        None => display_message(&msg, color_display, local_user.as_ref()),
+    }
+}
+
+/// 显示房间成员变化
+///
+/// 服务器向所有连接广播房间事件，这里只显示自己的和当前房间内的
+async fn display_room_membership(
+    state: &Arc<Mutex<AppState>>,
+    room_id: &str,
+    user_id: &UserId,
+    action: &str,
+    color_display: &ColorDisplay,
+) {
+    let app_state = state.lock().await;
+    let is_self = app_state.user_id.as_ref() == Some(user_id);
+    if !is_self && app_state.current_room_id.as_deref() != Some(room_id) {
+        return;
+    }
+
+    let who = if is_self { "您".to_string() } else { app_state.user_label(user_id) };
+    color_display.display_room_event(&app_state.room_label(room_id), &format!("{} {}", who, action));
 }
 
 /// 处理WebSocket事件（通过通道发送）
@@ -219,13 +744,16 @@ async fn handle_ws_event_with_sender(
     color_display: &ColorDisplay,
 ) -> Result<()> {
     match event {
-        WsEvent::Connected { user_id } => {
+        WsEvent::Connected { user_id, resume_token } => {
             info!("已连接到服务器，服务器分配的用户ID: {}", user_id);
             
             let mut app_state = state.lock().await;
+            app_state.resume_token = resume_token;
             
-            // 检查服务器返回的ID是否与本地存储的ID一致
-            if let Some(local_user_id) = &app_state.user_id {
+            // 登录用户的ID即账户ID，不覆盖本地保存的匿名ID
+            if app_state.auth.is_some() {
+                app_state.user_id = Some(user_id.clone());
+            } else if let Some(local_user_id) = &app_state.user_id {
                 if local_user_id != &user_id {
                     // 如果不一致，使用服务器分配的新ID并更新本地配置
                     info!("服务器分配了新的用户ID，更新本地配置");
@@ -243,6 +771,7 @@ async fn handle_ws_event_with_sender(
                 config_manager.save_config(&config).await?;
             }
               app_state.connected = true;
+            app_state.connected_since = Some(Instant::now());
             
             color_display.display_success("已连接到RustChat服务器");
             color_display.display_info(&format!("您的用户ID: {}", app_state.user_id.as_ref().unwrap()));
@@ -252,29 +781,173 @@ async fn handle_ws_event_with_sender(
                 color_display.display_info("使用 /nick <昵称> 来设置您的昵称");
             }
             color_display.display_info("输入消息开始聊天，输入 /help 查看命令帮助");
+
+            // 重连后服务器不再保留房间订阅，重新进入当前房间
+            if let Some(room_id) = app_state.current_room_id.clone() {
+                send_client_message(ws_sender, &ClientMessage::JoinRoom { room_id: room_id.clone() })?;
+                color_display.display_info(&format!("已重新进入房间 '{}'", app_state.room_label(&room_id)));
+            }
             color_display.display_separator();
         }
+        WsEvent::ServerInfo { version, protocol_version } => {
+            info!("服务器版本: {}，协议版本: {}", version, protocol_version);
+            {
+                let mut app_state = state.lock().await;
+                app_state.message_ack_supported = compat::supports_message_ack(&protocol_version);
+                app_state.room_events_supported = compat::supports_room_events(&protocol_version);
+            }
+
+            match compat::check_protocol_version(&protocol_version) {
+                compat::Compatibility::Compatible => {}
+                compat::Compatibility::MinorMismatch => {
+                    color_display.display_info(&format!(
+                        "服务器协议版本 {} 与客户端 {} 不同，部分功能可能不可用",
+                        protocol_version,
+                        rustchat_types::PROTOCOL_VERSION
+                    ));
+                }
+                compat::Compatibility::Incompatible => {
+                    state.lock().await.incompatible_server = true;
+                    color_display.display_error(&format!(
+                        "服务器 (版本 {}，协议 {}) 与当前客户端 (协议 {}) 不兼容，请升级客户端",
+                        version,
+                        protocol_version,
+                        rustchat_types::PROTOCOL_VERSION
+                    ));
+                }
+            }
+        }
         WsEvent::Message(msg) => {
-            let mut app_state = state.lock().await;
-            app_state.messages.push(msg.clone());
-            drop(app_state);
-            
-            // 保存消息到数据库
-            if let Err(err) = message_db.save_message(&msg).await {
-                error!("保存消息到数据库失败: {}", err);
+            receive_message(msg, &state, &message_db, color_display).await;
+        }
+        WsEvent::MessageBatch(messages) => {
+            for msg in messages {
+                receive_message(msg, &state, &message_db, color_display).await;
             }
-            
-            display_message(&msg, color_display);
-        }        WsEvent::UserJoined { user_id: _, nickname } => {
-            let nick = nickname.unwrap_or_else(|| "匿名用户".to_string());
+        }
+        WsEvent::RoomMessage { room_id, mut message } => {
+            message.room_id.get_or_insert(room_id);
+            receive_message(message, &state, &message_db, color_display).await;
+        }
+        WsEvent::RoomMessageBatch { room_id, messages } => {
+            for mut message in messages {
+                message.room_id.get_or_insert_with(|| room_id.clone());
+                receive_message(message, &state, &message_db, color_display).await;
+            }
+        }
+        WsEvent::UserJoined { user_id, nickname } => {
+            if let Some(nickname) = &nickname {
+                state.lock().await.remember_nickname(&user_id, nickname);
+            }
+            let nick = nickname.unwrap_or_else(|| format!("匿名用户 {}", user_id.short()));
             color_display.display_success(&format!("{} 加入了聊天室", nick));
         }
-        WsEvent::UserLeft { user_id: _ } => {
-            color_display.display_info("用户离开了聊天室");
+        WsEvent::UserLeft { user_id } => {
+            let user_label = state.lock().await.user_label(&user_id);
+            color_display.display_info(&format!("{} 离开了聊天室", user_label));
+        }
+        WsEvent::NicknameChanged { user_id, old_nick, new_nick } => {
+            // 变更提示已随昵称变更消息显示，这里只更新缓存
+            info!("用户 {} 昵称变更: {:?} -> {}", user_id, old_nick, new_nick);
+            let mut app_state = state.lock().await;
+            app_state.remember_nickname(&user_id, &new_nick);
+            // 同一账户在其他设备上修改了昵称
+            if app_state.user_id.as_ref() == Some(&user_id) {
+                app_state.nickname = Some(new_nick);
+            }
+        }
+        WsEvent::UserJoinedRoom { room_id, user_id } => {
+            display_room_membership(&state, &room_id, &user_id, "加入了房间", color_display).await;
+        }
+        WsEvent::UserLeftRoom { room_id, user_id } => {
+            display_room_membership(&state, &room_id, &user_id, "离开了房间", color_display).await;
+        }
+        WsEvent::RoomJoined { room } => {
+            let mut app_state = state.lock().await;
+            // 重连后重新进入当前房间时服务器同样会确认，此时只更新房间信息
+            let rejoined = app_state.current_room_id.as_deref() == Some(room.id.as_str());
+            app_state.enter_room(room.id.clone(), room.name.clone());
+            if !rejoined {
+                color_display.display_success(&format!(
+                    "✅ 当前房间: '{}' (ID: {}，{} 名成员)",
+                    room.name, room.id, room.member_count
+                ));
+                color_display.display_info("直接输入的消息将发送到该房间，使用 /leave 离开");
+            }
+        }
+        WsEvent::RoomLeft { room_id } => {
+            let mut app_state = state.lock().await;
+            if app_state.leave_room(&room_id) {
+                color_display.display_info("之后的消息将发送到公共聊天");
+            }
+            color_display.display_success(&format!("✅ 已离开房间 '{}'", app_state.room_label(&room_id)));
+        }
+        WsEvent::RoomList { rooms } => {
+            // 加入或离开房间后服务器推送的最新列表，只用于更新房间名称缓存
+            let mut app_state = state.lock().await;
+            for room in rooms {
+                app_state.room_names.insert(room.id, room.name);
+            }
         }
-        WsEvent::Error { message } => {
-            error!("服务器错误: {}", message);
+        WsEvent::MessagesPurged { user_id } => {
+            let is_self = {
+                let mut app_state = state.lock().await;
+                app_state.messages.retain(|msg| msg.from != user_id);
+                app_state.user_id.as_ref() == Some(&user_id)
+            };
+
+            // 同步删除本地缓存的消息
+            if let Err(err) = message_db.purge_user_messages(&user_id).await {
+                error!("删除本地缓存消息失败: {}", err);
+            }
+
+            if is_self {
+                color_display.display_success("您的个人数据已清除");
+            } else {
+                color_display.display_info("一位用户的消息已被清除");
+            }
+        }
+        WsEvent::MessagesExpired { message_ids } => {
+            // 隐藏已过期的阅后即焚消息，本地缓存读取时会自动过滤
+            let mut app_state = state.lock().await;
+            app_state.messages.retain(|msg| !message_ids.contains(&msg.id));
+            for message_id in &message_ids {
+                app_state.reactions.remove(message_id);
+            }
+        }
+        WsEvent::ReactionUpdated { message_id, emoji, count, users: _ } => {
+            let mut app_state = state.lock().await;
+            let reactions = app_state.reactions.entry(message_id.clone()).or_default();
+            match reactions.iter_mut().find(|(existing, _)| *existing == emoji) {
+                Some(reaction) => reaction.1 = count,
+                None => reactions.push((emoji, count)),
+            }
+            reactions.retain(|(_, count)| *count > 0);
+            let summary = reactions.clone();
+            if summary.is_empty() {
+                app_state.reactions.remove(&message_id);
+            }
+
+            // 只显示本地可见消息的回应
+            if let Some(message) = app_state.messages.iter().find(|msg| msg.id == message_id) {
+                color_display.display_reactions(&message_preview(message), &summary);
+            }
+        }
+        WsEvent::MessageAck { client_msg_id, message_id } => {
+            info!("消息 {} 已被服务器保存: {}", client_msg_id, message_id);
+            state.lock().await.pending_messages.remove(&client_msg_id);
+        }
+        WsEvent::Mentioned { message } => {
+            // 消息本身随广播显示并高亮，这里只响铃提醒
+            info!("被 {} 在消息 {} 中提及", message.from_nick.as_deref().unwrap_or("匿名用户"), message.id);
+            color_display.ring_bell();
+        }
+        WsEvent::Error { code, message } => {
+            error!("服务器错误 ({:?}): {}", code, message);
             color_display.display_error(&format!("错误: {}", message));
+            if let Some(hint) = error_hint(code) {
+                color_display.display_info(hint);
+            }
         }
         WsEvent::Ping => {
             // 收到服务器心跳，立即回复Pong
@@ -300,18 +973,47 @@ async fn handle_ws_event_with_sender(
 pub enum Command {
     Help,
     Nick(String),
+    Me(String),
+    /// 回应最新一条消息，`remove` 为true时取消回应
+    React { emoji: String, remove: bool },
     Whoami,
-    History(Option<i64>),
+    /// 查看其他用户的公开资料
+    Whois(UserId),
+    Stats,
+    Theme(Option<String>),
+    /// 设置时间戳格式（12h/24h/date/nodate），省略时显示当前格式
+    Time(Option<String>),
+    Login { email: String, password: String },
+    Register { email: String, password: String, display_name: Option<String> },
+    /// 显示消息历史，可只显示指定用户的消息
+    History { limit: Option<i64>, user: Option<HistoryUser> },
     Clear,
+    /// 删除本地数据库中的所有消息（需要输入 yes 确认）
+    ClearHistory,
     Quit,
+    /// 断开当前连接并立即重连
+    Reconnect,
+    /// 显示连接状态
+    Status,
     // 房间相关命令
     CreateRoom(String),                // /create <room_name>
     JoinRoom(String),                  // /join <room_id>
-    LeaveRoom,                         // /leave
+    LeaveRoom(Option<String>),         // /leave [room_id]，省略时离开当前房间
+    RoomMessage { room_id: String, content: String }, // /room <room_id> <message>
     ListRooms,                         // /rooms
+    ListFriends,                       // /friends
     Unknown(String),
 }
 
+/// `/history` 的用户过滤条件
+#[derive(Debug, Clone, PartialEq)]
+pub enum HistoryUser {
+    /// `@<昵称>`，按已知昵称解析为用户ID
+    Nickname(String),
+    /// `user:<用户ID>`
+    Id(UserId),
+}
+
 /// 命令解析结果
 #[derive(Debug)]
 pub struct ParsedCommand {
@@ -323,6 +1025,35 @@ pub struct ParsedCommand {
 pub struct CommandParser;
 
 impl CommandParser {
+    /// 可补全的命令列表（不含别名）
+    pub const COMMANDS: &'static [&'static str] = &[
+        "/help",
+        "/nick",
+        "/me",
+        "/react",
+        "/unreact",
+        "/whoami",
+        "/whois",
+        "/stats",
+        "/theme",
+        "/time",
+        "/login",
+        "/register",
+        "/history",
+        "/clear",
+        "/clear-history",
+        "/quit",
+        "/reconnect",
+        "/status",
+        "/create",
+        "/join",
+        "/leave",
+        "/room",
+        "/rooms",
+        "/friends",
+    ];
+
+
     /// 解析命令字符串
     pub fn parse_command(input: &str) -> ParsedCommand {
         let raw_input = input.to_string();
@@ -351,17 +1082,66 @@ impl CommandParser {
                     Command::Nick(nickname)
                 }
             }
+            "me" => {
+                if parts.len() < 2 {
+                    Command::Unknown("动作内容不能为空，用法: /me <动作>".to_string())
+                } else {
+                    let action = parts[1..].join(" ");
+                    match Message::validate_content(&action) {
+                        Ok(()) => Command::Me(action),
+                        Err(err) => Command::Unknown(format!("动作内容{}", err)),
+                    }
+                }
+            }
+            "react" | "unreact" => {
+                if parts.len() < 2 {
+                    Command::Unknown(format!("表情不能为空，用法: /{} <表情>", parts[0].to_lowercase()))
+                } else {
+                    Command::React {
+                        emoji: parts[1].to_string(),
+                        remove: parts[0].eq_ignore_ascii_case("unreact"),
+                    }
+                }
+            }
             "whoami" | "who" => Command::Whoami,
-            "history" | "hist" => {
-                let limit = if parts.len() > 1 {
-                    parts[1].parse::<i64>().ok()
+            "whois" => match parts.get(1) {
+                Some(id) => match UserId::parse(id) {
+                    Ok(user_id) => Command::Whois(user_id),
+                    Err(_) => Command::Unknown(format!("无效的用户ID: {}，用法: /whois <用户ID>", id)),
+                },
+                None => Command::Unknown("用法: /whois <用户ID>".to_string()),
+            },
+            "stats" => Command::Stats,
+            "theme" => Command::Theme(parts.get(1).map(|name| name.to_string())),
+            "time" => Command::Time(parts.get(1).map(|option| option.to_lowercase())),
+            "login" => {
+                if parts.len() < 3 {
+                    Command::Unknown("用法: /login <邮箱> <密码>".to_string())
                 } else {
-                    None
-                };
-                Command::History(limit)
+                    Command::Login {
+                        email: parts[1].to_string(),
+                        password: parts[2].to_string(),
+                    }
+                }
             }
+            "register" => {
+                if parts.len() < 3 {
+                    Command::Unknown("用法: /register <邮箱> <密码> [显示名称]".to_string())
+                } else {
+                    let display_name = (parts.len() > 3).then(|| parts[3..].join(" "));
+                    Command::Register {
+                        email: parts[1].to_string(),
+                        password: parts[2].to_string(),
+                        display_name,
+                    }
+                }
+            }
+            "history" | "hist" => Self::parse_history_command(&parts[1..]),
             "clear" | "cls" => Command::Clear,
+            "clear-history" => Command::ClearHistory,
             "quit" | "exit" | "q" => Command::Quit,
+            "reconnect" => Command::Reconnect,
+            "status" => Command::Status,
             // 房间相关命令
             "create" => {
                 if parts.len() < 2 {
@@ -371,20 +1151,72 @@ impl CommandParser {
                     Command::CreateRoom(room_name)
                 }
             }
-            "join" => {
-                if parts.len() < 2 {
-                    Command::Unknown("房间ID不能为空，用法: /join <房间ID>".to_string())
-                } else {
-                    Command::JoinRoom(parts[1].to_string())
-                }
-            }
-            "leave" => Command::LeaveRoom,
+            "join" => match parts.get(1) {
+                None => Command::Unknown("房间ID不能为空，用法: /join <房间ID>".to_string()),
+                Some(room_id) => match Self::parse_room_id(room_id) {
+                    Ok(room_id) => Command::JoinRoom(room_id),
+                    Err(msg) => Command::Unknown(msg),
+                },
+            },
+            "leave" => match parts.get(1) {
+                None => Command::LeaveRoom(None),
+                Some(room_id) => match Self::parse_room_id(room_id) {
+                    Ok(room_id) => Command::LeaveRoom(Some(room_id)),
+                    Err(msg) => Command::Unknown(msg),
+                },
+            },
+            "room" => match parts.get(1) {
+                None => Command::Unknown("用法: /room <房间ID> <消息>".to_string()),
+                Some(room_id) => match Self::parse_room_id(room_id) {
+                    Ok(_) if parts.len() < 3 => {
+                        Command::Unknown("消息内容不能为空，用法: /room <房间ID> <消息>".to_string())
+                    }
+                    Ok(room_id) => {
+                        let content = parts[2..].join(" ");
+                        match Message::validate_content(&content) {
+                            Ok(()) => Command::RoomMessage { room_id, content },
+                            Err(err) => Command::Unknown(format!("消息内容{}", err)),
+                        }
+                    }
+                    Err(msg) => Command::Unknown(msg),
+                },
+            },
             "rooms" | "roomlist" => Command::ListRooms,
+            "friends" => Command::ListFriends,
             _ => Command::Unknown(format!("未知命令: {}", parts[0])),
         };
         
         ParsedCommand { command, raw_input }
     }
+
+    /// 校验房间ID格式（UUID），返回规范化后的ID
+    fn parse_room_id(input: &str) -> Result<String, String> {
+        uuid::Uuid::parse_str(input)
+            .map(|uuid| uuid.to_string())
+            .map_err(|_| format!("无效的房间ID: {}", input))
+    }
+
+    /// 解析 `/history [@昵称|user:<用户ID>] [数量]`，参数顺序不限
+    fn parse_history_command(args: &[&str]) -> Command {
+        let mut limit = None;
+        let mut user = None;
+        for arg in args {
+            if let Some(nickname) = arg.strip_prefix('@') {
+                if nickname.is_empty() {
+                    return Command::Unknown("用法: /history [@昵称|user:<用户ID>] [数量]".to_string());
+                }
+                user = Some(HistoryUser::Nickname(nickname.to_string()));
+            } else if let Some(id) = arg.strip_prefix("user:") {
+                match UserId::parse(id) {
+                    Ok(id) => user = Some(HistoryUser::Id(id)),
+                    Err(_) => return Command::Unknown(format!("无效的用户ID: {}", id)),
+                }
+            } else {
+                limit = arg.parse::<i64>().ok();
+            }
+        }
+        Command::History { limit, user }
+    }
 }
 
 /// 命令执行器
@@ -406,36 +1238,89 @@ impl CommandExecutor {    /// 执行命令
             Command::Nick(nickname) => {
                 Self::execute_nick_command(nickname, state, config_manager, ws_sender, color_display).await
             }
+            Command::Me(action) => {
+                Self::execute_me_command(action, state, ws_sender).await?;
+                Ok(true)
+            }
+            Command::React { emoji, remove } => {
+                Self::execute_react_command(emoji, remove, state, ws_sender, color_display).await?;
+                Ok(true)
+            }
             Command::Whoami => {
                 Self::execute_whoami_command(state, color_display).await;
                 Ok(true)
             }
-            Command::History(limit) => {
-                Self::execute_history_command(limit, message_db, color_display).await;
+            Command::Whois(user_id) => {
+                Self::execute_whois_command(user_id, state, message_db, color_display).await;
+                Ok(true)
+            }
+            Command::Stats => {
+                Self::execute_stats_command(state, message_db, color_display).await;
+                Ok(true)
+            }
+            Command::Theme(name) => {
+                Self::execute_theme_command(name, state, config_manager, color_display).await;
+                Ok(true)
+            }
+            Command::Time(option) => {
+                Self::execute_time_command(option, state, config_manager, color_display).await;
+                Ok(true)
+            }
+            Command::Login { email, password } => {
+                Self::execute_login_command(email, password, state, config_manager, color_display).await;
+                Ok(true)
+            }
+            Command::Register { email, password, display_name } => {
+                Self::execute_register_command(email, password, display_name, state, color_display).await;
+                Ok(true)
+            }
+            Command::History { limit, user } => {
+                Self::execute_history_command(limit, user, state, message_db, color_display).await;
                 Ok(true)
             }            Command::Clear => {
                 Self::execute_clear_command(color_display).await;
                 Ok(true)
             }
+            Command::ClearHistory => {
+                Self::execute_clear_history_command(state, color_display).await;
+                Ok(true)
+            }
             Command::Quit => {
+                Self::execute_quit_command(color_display).await;
                 Ok(false)
             }
+            Command::Reconnect => {
+                Self::execute_reconnect_command(state, color_display).await;
+                Ok(true)
+            }
+            Command::Status => {
+                Self::execute_status_command(state, color_display).await;
+                Ok(true)
+            }
             // 房间相关命令
             Command::CreateRoom(room_name) => {
-                Self::execute_create_room_command(room_name, state, color_display).await;
+                Self::execute_create_room_command(room_name, state, ws_sender, color_display).await?;
                 Ok(true)
             }
             Command::JoinRoom(room_id) => {
-                Self::execute_join_room_command(room_id, state, color_display).await;
+                Self::execute_join_room_command(room_id, state, ws_sender, color_display).await?;
+                Ok(true)
+            }
+            Command::LeaveRoom(room_id) => {
+                Self::execute_leave_room_command(room_id, state, ws_sender, color_display).await?;
                 Ok(true)
             }
-            Command::LeaveRoom => {
-                Self::execute_leave_room_command(state, color_display).await;
+            Command::RoomMessage { room_id, content } => {
+                send_client_message(ws_sender, &ClientMessage::SendRoomMessage { room_id, content })?;
                 Ok(true)
             }
             Command::ListRooms => {
                 Self::execute_list_rooms_command(state, color_display).await;
                 Ok(true)
+            }
+            Command::ListFriends => {
+                Self::execute_list_friends_command(state, color_display).await;
+                Ok(true)
             }            Command::Unknown(msg) => {
                 color_display.display_error(&msg);
                 Ok(true)
@@ -443,80 +1328,95 @@ impl CommandExecutor {    /// 执行命令
         }
     }/// 执行帮助命令
     async fn execute_help_command(color_display: &ColorDisplay) {
-        use crossterm::style::{Color, SetForegroundColor, ResetColor};
+        use crossterm::style::Color;
         use std::io::{self, Write};
         
         let mut stdout = io::stdout();
         
         // 标题
-        stdout.execute(SetForegroundColor(Color::Cyan)).unwrap();
+        color_display.set_color(&mut stdout, Color::Cyan);
         println!("📚 RustChat 命令帮助");
         
         // 表格边框颜色
-        stdout.execute(SetForegroundColor(Color::DarkGrey)).unwrap();
+        color_display.set_color(&mut stdout, Color::DarkGrey);
         println!("┌─────────────────────────────────────────────────────────┐");
         
-        stdout.execute(SetForegroundColor(Color::Yellow)).unwrap();
+        color_display.set_color(&mut stdout, Color::Yellow);
         println!("│                      基础命令                           │");
         
-        stdout.execute(SetForegroundColor(Color::DarkGrey)).unwrap();
+        color_display.set_color(&mut stdout, Color::DarkGrey);
         println!("├─────────────────────────────────────────────────────────┤");
         
-        stdout.execute(SetForegroundColor(Color::Green)).unwrap();
+        color_display.set_color(&mut stdout, Color::Green);
         println!("│ /help, /h           - 显示此帮助信息                    │");
         println!("│ /quit, /exit, /q    - 退出程序                         │");
         println!("│ /clear, /cls        - 清空屏幕                          │");
+        println!("│ /status             - 显示连接状态                      │");
+        println!("│ /reconnect          - 断开并立即重新连接                │");
         
-        stdout.execute(SetForegroundColor(Color::DarkGrey)).unwrap();
+        color_display.set_color(&mut stdout, Color::DarkGrey);
         println!("├─────────────────────────────────────────────────────────┤");
         
-        stdout.execute(SetForegroundColor(Color::Yellow)).unwrap();
+        color_display.set_color(&mut stdout, Color::Yellow);
         println!("│                      用户命令                           │");
         
-        stdout.execute(SetForegroundColor(Color::DarkGrey)).unwrap();
+        color_display.set_color(&mut stdout, Color::DarkGrey);
         println!("├─────────────────────────────────────────────────────────┤");
         
-        stdout.execute(SetForegroundColor(Color::Green)).unwrap();
+        color_display.set_color(&mut stdout, Color::Green);
         println!("│ /nick <昵称>        - 设置用户昵称                      │");
         println!("│ /whoami, /who       - 显示当前用户信息                  │");
+        println!("│ /whois <用户ID>     - 查看其他用户的资料                │");
+        println!("│ /stats              - 显示本地消息和连接统计            │");
+        println!("│ /me <动作>          - 发送动作消息，如 /me 挥手         │");
+        println!("│ /react <表情>       - 回应最新消息，/unreact 取消回应   │");
+        println!("│ /theme [主题]       - 切换颜色主题 (default/rainbow/mono)│");
+        println!("│ /time [格式]        - 时间格式 (12h/24h/date/nodate)    │");
+        println!("│ /login <邮箱> <密码> - 登录账户并重新连接               │");
+        println!("│ /register <邮箱> <密码> [名称] - 注册新账户             │");
         
-        stdout.execute(SetForegroundColor(Color::DarkGrey)).unwrap();
+        color_display.set_color(&mut stdout, Color::DarkGrey);
         println!("├─────────────────────────────────────────────────────────┤");
         
-        stdout.execute(SetForegroundColor(Color::Yellow)).unwrap();
+        color_display.set_color(&mut stdout, Color::Yellow);
         println!("│                      消息命令                           │");
         
-        stdout.execute(SetForegroundColor(Color::DarkGrey)).unwrap();
+        color_display.set_color(&mut stdout, Color::DarkGrey);
         println!("├─────────────────────────────────────────────────────────┤");
-          stdout.execute(SetForegroundColor(Color::Green)).unwrap();
+          color_display.set_color(&mut stdout, Color::Green);
         println!("│ /history [数量]     - 显示消息历史 (默认20条)           │");
+        println!("│ /history @昵称 [数量] - 只显示该用户的消息              │");
         println!("│ /hist [数量]        - history的简写                    │");
+        println!("│ /clear-history      - 删除本地保存的所有消息（需确认）  │");
         
-        stdout.execute(SetForegroundColor(Color::DarkGrey)).unwrap();
+        color_display.set_color(&mut stdout, Color::DarkGrey);
         println!("├─────────────────────────────────────────────────────────┤");
         
-        stdout.execute(SetForegroundColor(Color::Yellow)).unwrap();
+        color_display.set_color(&mut stdout, Color::Yellow);
         println!("│                      房间命令                           │");
         
-        stdout.execute(SetForegroundColor(Color::DarkGrey)).unwrap();
+        color_display.set_color(&mut stdout, Color::DarkGrey);
         println!("├─────────────────────────────────────────────────────────┤");
         
-        stdout.execute(SetForegroundColor(Color::Green)).unwrap();
+        color_display.set_color(&mut stdout, Color::Green);
         println!("│ /create <房间名>    - 创建新房间                        │");
-        println!("│ /join <房间ID>      - 加入指定房间                      │");
-        println!("│ /leave              - 离开当前房间                      │");
-        println!("│ /rooms              - 列出我的房间                      │");
+        println!("│ /join <房间ID>      - 加入房间并设为当前房间            │");
+        println!("│ /leave [房间ID]     - 离开房间（默认当前房间）          │");
+        println!("│ /room <ID> <消息>   - 向指定房间发送消息                │");
+        println!("│ /rooms              - 列出服务器上的房间                │");
+        println!("│ /friends            - 列出我的好友（优先显示备注名）    │");
         
-        stdout.execute(SetForegroundColor(Color::DarkGrey)).unwrap();
+        color_display.set_color(&mut stdout, Color::DarkGrey);
         println!("└─────────────────────────────────────────────────────────┘");
         
-        stdout.execute(ResetColor).unwrap();
+        color_display.reset_color(&mut stdout);
         println!();
         
         color_display.display_info("使用技巧:");
         color_display.display_success("   • 直接输入消息内容即可发送（无需命令前缀）");
         color_display.display_success("   • 命令支持简写，如 /h 代替 /help");
         color_display.display_success("   • 昵称可以包含空格，如: /nick 张三 李四");
+        color_display.display_success("   • 按 Tab 补全命令和昵称，上下方向键翻阅输入历史");
         
         println!();
         color_display.display_info("📝 使用示例:");
@@ -541,20 +1441,10 @@ impl CommandExecutor {    /// 执行命令
         config_manager: &UserConfigManager,
         ws_sender: &tokio::sync::mpsc::UnboundedSender<WsMessage>,
         color_display: &ColorDisplay,
-    ) -> Result<bool> {        // 验证昵称格式
-        if nickname.trim().is_empty() {
-            color_display.display_error("昵称不能为空");
-            return Ok(true);
-        }
-        
-        if nickname.len() > 32 {
-            color_display.display_error("昵称长度不能超过32个字符");
-            return Ok(true);
-        }
-        
-        // 检查是否包含非法字符
-        if nickname.contains(['\n', '\r', '\t']) {
-            color_display.display_error("昵称不能包含换行符或制表符");
+    ) -> Result<bool> {
+        // 验证昵称格式（与服务器使用同一套规则）
+        if let Err(err) = validate_nickname(&nickname) {
+            color_display.display_error(&format!("昵称{}", err));
             return Ok(true);
         }
         
@@ -576,6 +1466,45 @@ impl CommandExecutor {    /// 执行命令
         Ok(true)
     }
     
+    /// 执行动作消息命令
+    async fn execute_me_command(
+        action: String,
+        state: Arc<Mutex<AppState>>,
+        ws_sender: &tokio::sync::mpsc::UnboundedSender<WsMessage>,
+    ) -> Result<()> {
+        let nickname = state.lock().await.nickname.clone();
+        
+        let msg = ClientMessage::SendAction { content: action, nickname };
+        let json = serde_json::to_string(&msg)?;
+        ws_sender.send(WsMessage::Text(json.into()))?;
+        
+        Ok(())
+    }
+    
+    /// 执行表情回应命令（作用于最新一条消息）
+    async fn execute_react_command(
+        emoji: String,
+        remove: bool,
+        state: Arc<Mutex<AppState>>,
+        ws_sender: &tokio::sync::mpsc::UnboundedSender<WsMessage>,
+        color_display: &ColorDisplay,
+    ) -> Result<()> {
+        let Some(message_id) = state.lock().await.messages.last().map(|msg| msg.id.clone()) else {
+            color_display.display_error("没有可以回应的消息");
+            return Ok(());
+        };
+
+        let msg = if remove {
+            ClientMessage::Unreact { message_id, emoji }
+        } else {
+            ClientMessage::React { message_id, emoji }
+        };
+        let json = serde_json::to_string(&msg)?;
+        ws_sender.send(WsMessage::Text(json.into()))?;
+
+        Ok(())
+    }
+
     /// 执行用户信息查询命令
     async fn execute_whoami_command(state: Arc<Mutex<AppState>>, color_display: &ColorDisplay) {
         let app_state = state.lock().await;
@@ -590,12 +1519,256 @@ impl CommandExecutor {    /// 执行命令
             color_display.display_info("  📝 昵称: 未设置 (使用 /nick <昵称> 设置)");
         }
         
+        if let Some(session) = &app_state.auth {
+            color_display.display_success(&format!("  📧 邮箱: {}", session.email));
+        } else {
+            color_display.display_info("  📧 邮箱: 未登录 (使用 /login <邮箱> <密码> 登录)");
+        }
+        
         let connection_status = if app_state.connected { "已连接" } else { "未连接" };
         color_display.display_success(&format!("  🔗 连接状态: {}", connection_status));
     }
     
+    /// 执行查看用户资料命令，服务器不可达时显示本地记录
+    async fn execute_whois_command(
+        user_id: UserId,
+        state: Arc<Mutex<AppState>>,
+        message_db: Arc<MessageDatabase>,
+        color_display: &ColorDisplay,
+    ) {
+        let (client, api_base_url, known_nickname) = {
+            let app_state = state.lock().await;
+            (
+                authorized_http_client(&app_state),
+                app_state.api_base_url.clone(),
+                app_state.nicknames.get(&user_id).cloned(),
+            )
+        };
+
+        let info = match fetch_user_profile(&client, &api_base_url, &user_id).await {
+            Ok(Some(profile)) => WhoisInfo {
+                nickname: profile.nickname.or(profile.display_name).or(known_nickname),
+                online: Some(profile.is_online),
+                first_seen_at: profile.first_seen_at,
+                user_id: profile.user_id,
+            },
+            Ok(None) => {
+                color_display.display_error(&format!("用户 {} 不存在", user_id.short()));
+                return;
+            }
+            Err(err) => {
+                warn!("查询用户资料失败: {:#}", err);
+                color_display.display_error("无法连接服务器，以下为本地记录");
+                let first_seen_at = match message_db.get_user_stats(&user_id).await {
+                    Ok(stats) => stats.first_message_at,
+                    Err(err) => {
+                        warn!("查询本地消息统计失败: {}", err);
+                        None
+                    }
+                };
+                if known_nickname.is_none() && first_seen_at.is_none() {
+                    color_display.display_info(&format!("本地没有用户 {} 的记录", user_id.short()));
+                    return;
+                }
+                WhoisInfo { user_id, nickname: known_nickname, online: None, first_seen_at }
+            }
+        };
+
+        color_display.display_info_box("🔎 用户资料", &info.rows(color_display));
+    }
+
+    /// 执行统计信息命令
+    async fn execute_stats_command(
+        state: Arc<Mutex<AppState>>,
+        message_db: Arc<MessageDatabase>,
+        color_display: &ColorDisplay,
+    ) {
+        // 数据库查询失败时只显示“未知”，不影响命令循环
+        let total_messages = match message_db.get_message_count().await {
+            Ok(count) => count.to_string(),
+            Err(err) => {
+                warn!("获取本地消息数量失败: {}", err);
+                "未知".to_string()
+            }
+        };
+
+        let app_state = state.lock().await;
+        let user_id = app_state.user_id.as_ref().map(|id| id.to_string()).unwrap_or_else(|| "未知".to_string());
+        let nickname = app_state.nickname.clone().unwrap_or_else(|| "未设置".to_string());
+        let uptime = match app_state.connected_since {
+            Some(since) => format_uptime(since.elapsed()),
+            None => "未连接".to_string(),
+        };
+
+        color_display.display_info_box(
+            "📊 本地统计",
+            &[
+                ("用户ID", user_id),
+                ("昵称", nickname),
+                ("本地消息总数", total_messages),
+                ("本次会话收到的消息", app_state.messages.len().to_string()),
+                ("连接时长", uptime),
+            ],
+        );
+    }
+    
+    /// 执行重连命令：结束当前会话，由外层重连循环立即重新连接
+    async fn execute_reconnect_command(state: Arc<Mutex<AppState>>, color_display: &ColorDisplay) {
+        color_display.display_info("🔄 正在断开并重新连接...");
+        state.lock().await.reconnect_requested = true;
+    }
+
+    /// 执行连接状态命令
+    async fn execute_status_command(state: Arc<Mutex<AppState>>, color_display: &ColorDisplay) {
+        let (status, connected, api_base_url) = {
+            let app_state = state.lock().await;
+            (app_state.connection_status(), app_state.connected, app_state.api_base_url.clone())
+        };
+        color_display.display_info(&format!("连接状态: {}", status));
+
+        // 旧版服务器的健康检查不返回运行信息，查询失败时只记录日志
+        if connected {
+            match fetch_server_runtime(&api_base_url).await {
+                Ok(runtime) => color_display.display_info(&format!(
+                    "服务器: 版本 {}，已运行 {}",
+                    runtime.version, runtime.uptime
+                )),
+                Err(err) => warn!("查询服务器运行信息失败: {:#}", err),
+            }
+        }
+    }
+    
+    /// 执行主题切换命令
+    async fn execute_theme_command(
+        name: Option<String>,
+        state: Arc<Mutex<AppState>>,
+        config_manager: &UserConfigManager,
+        color_display: &ColorDisplay,
+    ) {
+        let Some(name) = name else {
+            color_display.display_info(&format!("当前主题: {}", color_display.theme_name()));
+            color_display.display_info(&format!("可用主题: {}", ColorTheme::NAMES.join(", ")));
+            return;
+        };
+        
+        let Some(theme) = ColorTheme::by_name(&name) else {
+            color_display.display_error(&format!(
+                "未知主题: {}，可用主题: {}",
+                name,
+                ColorTheme::NAMES.join(", ")
+            ));
+            return;
+        };
+        
+        let mut new_display = color_display.clone();
+        new_display.set_theme(theme);
+        let theme_name = new_display.theme_name();
+        state.lock().await.color_display = new_display.clone();
+        
+        if let Err(err) = config_manager.update_theme(theme_name.to_string()).await {
+            error!("保存主题失败: {}", err);
+        }
+        
+        new_display.display_success(&format!("已切换到 {} 主题", theme_name));
+    }
+    
+    /// 执行时间戳格式命令：`12h`/`24h` 切换时制，`date`/`nodate` 切换非当天消息是否显示日期
+    async fn execute_time_command(
+        option: Option<String>,
+        state: Arc<Mutex<AppState>>,
+        config_manager: &UserConfigManager,
+        color_display: &ColorDisplay,
+    ) {
+        let mut format = color_display.timestamp_format();
+        match option.as_deref() {
+            None => {
+                color_display.display_info(&format!("当前时间格式: {}", format.describe()));
+                color_display.display_info("可用选项: 12h, 24h, date, nodate");
+                return;
+            }
+            Some("12h") => format.hour12 = true,
+            Some("24h") => format.hour12 = false,
+            Some("date") => format.show_date = true,
+            Some("nodate") => format.show_date = false,
+            Some(other) => {
+                color_display.display_error(&format!("未知时间格式: {}，可用选项: 12h, 24h, date, nodate", other));
+                return;
+            }
+        }
+
+        let new_display = color_display.clone().with_timestamp_format(format);
+        state.lock().await.color_display = new_display.clone();
+
+        if let Err(err) = config_manager.update_timestamp_format(format.hour12, format.show_date).await {
+            error!("保存时间格式失败: {}", err);
+        }
+
+        new_display.display_success(&format!("时间格式已设置为: {}", format.describe()));
+    }
+
+    /// 执行登录命令
+    async fn execute_login_command(
+        email: String,
+        password: String,
+        state: Arc<Mutex<AppState>>,
+        config_manager: &UserConfigManager,
+        color_display: &ColorDisplay,
+    ) {
+        let api_base_url = state.lock().await.api_base_url.clone();
+        color_display.display_info(&format!("正在登录 {} ...", email));
+        
+        let session = match AuthApiClient::new(&api_base_url).login(&email, &password).await {
+            Ok(session) => session,
+            Err(err) => {
+                color_display.display_error(&format!("❌ 登录失败: {:#}", err));
+                return;
+            }
+        };
+        
+        // 保存刷新令牌，重启后自动登录
+        if let Err(err) = config_manager.save_login(session.email.clone(), session.refresh_token.clone()).await {
+            error!("保存登录信息失败: {}", err);
+        }
+        
+        color_display.display_success(&format!("✅ 已登录: {}，正在使用账户重新连接...", session.email));
+        
+        let mut app_state = state.lock().await;
+        app_state.auth = Some(session);
+        app_state.reconnect_requested = true;
+    }
+    
+    /// 执行注册命令
+    async fn execute_register_command(
+        email: String,
+        password: String,
+        display_name: Option<String>,
+        state: Arc<Mutex<AppState>>,
+        color_display: &ColorDisplay,
+    ) {
+        let api_base_url = state.lock().await.api_base_url.clone();
+        
+        match AuthApiClient::new(&api_base_url)
+            .register(&email, &password, display_name.as_deref())
+            .await
+        {
+            Ok(message) => {
+                color_display.display_success(&format!("✅ {}", message));
+                color_display.display_info(&format!("使用 /login {} <密码> 登录", email));
+            }
+            Err(err) => {
+                color_display.display_error(&format!("❌ 注册失败: {:#}", err));
+            }
+        }
+    }
+    
     /// 执行历史消息查询命令
-    async fn execute_history_command(limit: Option<i64>, message_db: Arc<MessageDatabase>, color_display: &ColorDisplay) {
+    async fn execute_history_command(
+        limit: Option<i64>,
+        user: Option<HistoryUser>,
+        state: Arc<Mutex<AppState>>,
+        message_db: Arc<MessageDatabase>,
+        color_display: &ColorDisplay,
+    ) {
         let limit = limit.unwrap_or(20);
         
         if limit <= 0 {
@@ -607,14 +1780,44 @@ impl CommandExecutor {    /// 执行命令
             color_display.display_error("一次最多只能查看1000条消息");
             return;
         }
-          match message_db.get_recent_messages(limit).await {
+
+        let (local_user, nicknames) = {
+            let state = state.lock().await;
+            (state.user_id.clone(), state.nicknames.clone())
+        };
+
+        let result = match &user {
+            None => message_db.get_recent_messages(limit).await,
+            Some(user) => {
+                let user_id = match user {
+                    HistoryUser::Id(user_id) => user_id.clone(),
+                    HistoryUser::Nickname(nickname) => {
+                        let recent = message_db.get_recent_messages(1000).await.unwrap_or_default();
+                        match resolve_nickname(nickname, &nicknames, &recent) {
+                            Some(user_id) => user_id,
+                            None => {
+                                color_display.display_error(&format!("找不到昵称为 {} 的用户", nickname));
+                                return;
+                            }
+                        }
+                    }
+                };
+                message_db.get_user_messages(&user_id, limit).await
+            }
+        };
+
+        match result {
             Ok(messages) => {
                 if messages.is_empty() {
-                    color_display.display_info("暂无消息历史");
+                    match &user {
+                        Some(HistoryUser::Nickname(nickname)) => color_display.display_info(&format!("没有来自 {} 的消息", nickname)),
+                        Some(HistoryUser::Id(user_id)) => color_display.display_info(&format!("没有来自用户 {} 的消息", user_id)),
+                        None => color_display.display_info("暂无消息历史"),
+                    }
                 } else {
                     color_display.display_history_separator(messages.len());
                     for msg in &messages {
-                        display_message(msg, color_display);
+                        display_message(msg, color_display, local_user.as_ref());
                     }
                     color_display.display_separator();
                 }
@@ -633,6 +1836,39 @@ impl CommandExecutor {    /// 执行命令
         color_display.display_separator();
     }
     
+    /// 执行清空本地历史命令：只进入确认状态，下一行输入 yes 才真正删除
+    async fn execute_clear_history_command(state: Arc<Mutex<AppState>>, color_display: &ColorDisplay) {
+        state.lock().await.clear_history_pending = true;
+        color_display.display_error("此操作将删除本地数据库中保存的所有消息，且无法恢复（/clear 只清空屏幕）");
+        color_display.display_info("输入 yes 确认删除，输入其他内容取消");
+    }
+
+    /// 处理 /clear-history 的确认输入，只有 `yes` 会删除本地消息
+    async fn confirm_clear_history(
+        input: &str,
+        state: Arc<Mutex<AppState>>,
+        message_db: &MessageDatabase,
+        color_display: &ColorDisplay,
+    ) {
+        if input.trim() != "yes" {
+            color_display.display_info("已取消清空本地消息历史");
+            return;
+        }
+
+        match message_db.clear_all_messages().await {
+            Ok(deleted) => {
+                let mut app_state = state.lock().await;
+                app_state.messages.clear();
+                app_state.reactions.clear();
+                color_display.display_success(&format!("已删除本地保存的 {} 条消息", deleted));
+            }
+            Err(err) => {
+                error!("清空本地消息历史失败: {}", err);
+                color_display.display_error(&format!("清空本地消息历史失败: {}", err));
+            }
+        }
+    }
+
     /// 执行退出命令
     async fn execute_quit_command(color_display: &ColorDisplay) {
         color_display.display_info("正在关闭连接...");
@@ -645,146 +1881,182 @@ impl CommandExecutor {    /// 执行命令
     async fn execute_create_room_command(
         room_name: String,
         state: Arc<Mutex<AppState>>,
+        ws_sender: &tokio::sync::mpsc::UnboundedSender<WsMessage>,
         color_display: &ColorDisplay,
-    ) {
+    ) -> Result<()> {
         let user_id = {
             let app_state = state.lock().await;
             app_state.user_id.clone()
         };
         
         if let Some(user_id) = user_id {
-            let client = RoomApiClient::new();
+            let client = RoomApiClient::from_state(&*state.lock().await);
             match client.create_room(&user_id.to_string(), room_name.clone()).await {
                 Ok(room) => {
-                    {
-                        let mut app_state = state.lock().await;
-                        app_state.current_room_id = Some(room.id.clone());
-                        app_state.current_room_name = Some(room.name.clone());
-                    }                    color_display.display_success(&format!("✅ 成功创建房间 '{}' (ID: {})", room.name, room.id));
+                    // 房主已是成员，通过WebSocket进入房间以接收房间消息
+                    send_client_message(ws_sender, &ClientMessage::JoinRoom { room_id: room.id.clone() })?;
+                    state.lock().await.enter_room(room.id.clone(), room.name.clone());
+                    color_display.display_success(&format!("✅ 成功创建房间 '{}' (ID: {})", room.name, room.id));
                     color_display.display_info(&format!("自动加入房间，成员数: {}", room.member_count));
-                }                Err(e) => {
+                }
+                Err(e) => {
                     color_display.display_error(&format!("❌ 创建房间失败: {}", e));
                 }
             }
         } else {
             color_display.display_error("❌ 未连接到服务器，无法创建房间");
         }
+        Ok(())
     }
     
-    /// 执行加入房间命令
+    /// 执行加入房间命令，加入后直接输入的消息发送到该房间
     async fn execute_join_room_command(
         room_id: String,
         state: Arc<Mutex<AppState>>,
+        ws_sender: &tokio::sync::mpsc::UnboundedSender<WsMessage>,
         color_display: &ColorDisplay,
-    ) {
-        let user_id = {
-            let app_state = state.lock().await;
-            app_state.user_id.clone()
-        };
-        
-        if let Some(user_id) = user_id {
-            let client = RoomApiClient::new();
-            match client.join_room(&user_id.to_string(), room_id.clone()).await {
-                Ok(room) => {
-                    {
-                        let mut app_state = state.lock().await;
-                        app_state.current_room_id = Some(room.id.clone());
-                        app_state.current_room_name = Some(room.name.clone());
-                    }                    color_display.display_success(&format!("✅ 成功加入房间 '{}' (ID: {})", room.name, room.id));
-                    color_display.display_info(&format!("房间成员数: {}，房主: {}", room.member_count, room.owner));
-                }
-                Err(e) => {
-                    color_display.display_error(&format!("❌ 加入房间失败: {}", e));
-                }
-            }
-        } else {
-            color_display.display_error("❌ 未连接到服务器，无法加入房间");
+    ) -> Result<()> {
+        // 新服务器会回复RoomJoined，由事件处理切换房间；房间不存在时服务器返回错误事件
+        if state.lock().await.room_events_supported {
+            send_client_message(ws_sender, &ClientMessage::JoinRoom { room_id })?;
+            return Ok(());
         }
+
+        // 旧服务器不确认加入，先确认房间存在并获取房间名称
+        let client = RoomApiClient::from_state(&*state.lock().await);
+        let room = match client.get_room(&room_id).await {
+            Ok(room) => room,
+            Err(e) => {
+                color_display.display_error(&format!("❌ 加入房间失败: {}", e));
+                return Ok(());
+            }
+        };
+
+        send_client_message(ws_sender, &ClientMessage::JoinRoom { room_id: room.id.clone() })?;
+        state.lock().await.enter_room(room.id.clone(), room.name.clone());
+
+        color_display.display_success(&format!("✅ 当前房间: '{}' (ID: {})", room.name, room.id));
+        color_display.display_info("直接输入的消息将发送到该房间，使用 /leave 离开");
+        Ok(())
     }
     
-    /// 执行离开房间命令
+    /// 执行离开房间命令，未指定房间时离开当前房间
     async fn execute_leave_room_command(
+        room_id: Option<String>,
         state: Arc<Mutex<AppState>>,
+        ws_sender: &tokio::sync::mpsc::UnboundedSender<WsMessage>,
         color_display: &ColorDisplay,
-    ) {
-        let (user_id, current_room_id) = {
-            let app_state = state.lock().await;
-            (app_state.user_id.clone(), app_state.current_room_id.clone())
+    ) -> Result<()> {
+        let mut app_state = state.lock().await;
+        let Some(room_id) = room_id.or_else(|| app_state.current_room_id.clone()) else {
+            color_display.display_error("❌ 当前没有加入任何房间，用法: /leave [房间ID]");
+            return Ok(());
         };
-        
-        if let (Some(user_id), Some(room_id)) = (user_id, current_room_id) {
-            let client = RoomApiClient::new();
-            match client.leave_room(&user_id.to_string(), room_id.clone()).await {
-                Ok(room) => {
-                    {
-                        let mut app_state = state.lock().await;
-                        app_state.current_room_id = None;
-                        app_state.current_room_name = None;
-                    }
-                    color_display.display_success(&format!("✅ 成功离开房间 '{}' (ID: {})", room.name, room.id));
+
+        send_client_message(ws_sender, &ClientMessage::LeaveRoom { room_id: room_id.clone() })?;
+
+        // 新服务器会回复RoomLeft，由事件处理更新当前房间
+        if app_state.room_events_supported {
+            return Ok(());
+        }
+        if app_state.leave_room(&room_id) {
+            color_display.display_info("之后的消息将发送到公共聊天");
+        }
+        color_display.display_success(&format!("✅ 已离开房间 '{}'", app_state.room_label(&room_id)));
+        Ok(())
+    }
+    
+    /// 执行房间列表命令
+    async fn execute_list_rooms_command(
+        state: Arc<Mutex<AppState>>,
+        color_display: &ColorDisplay,
+    ) {
+        let client = RoomApiClient::from_state(&*state.lock().await);
+        match client.list_rooms().await {
+            Ok(rooms) => {
+                if rooms.is_empty() {
+                    color_display.display_info("📝 服务器上还没有房间");
+                    color_display.display_info("使用 /create <房间名> 创建房间");
+                    return;
                 }
-                Err(e) => {
-                    color_display.display_error(&format!("❌ 离开房间失败: {}", e));
+
+                let mut app_state = state.lock().await;
+                color_display.display_info(&format!("📋 房间列表 (共 {} 个房间):", rooms.len()));
+                println!();
+                
+                for room in rooms {
+                    let status_icon = if app_state.current_room_id.as_deref() == Some(room.id.as_str()) {
+                        "▶"
+                    } else if room.is_owner {
+                        "👑"
+                    } else if room.is_member {
+                        "👤"
+                    } else {
+                        "  "
+                    };
+                    let member_info = if let Some(max) = room.max_members {
+                        format!("{}/{}", room.member_count, max)
+                    } else {
+                        room.member_count.to_string()
+                    };
+                    
+                    color_display.display_info(&format!(
+                        "  {} {} (ID: {})",
+                        status_icon, room.name, room.id
+                    ));
+                    color_display.display_info(&format!(
+                        "    成员: {} | 创建时间: {}",
+                        member_info, room.created_at
+                    ));
+                    
+                    if let Some(desc) = &room.description {
+                        color_display.display_info(&format!("    描述: {}", desc));
+                    }
+                    println!();
+
+                    app_state.room_names.insert(room.id, room.name);
                 }
+                
+                color_display.display_info("使用 /join <房间ID> 切换到指定房间，/room <房间ID> <消息> 向房间发送消息");
+            }
+            Err(e) => {
+                color_display.display_error(&format!("❌ 获取房间列表失败: {}", e));
             }
-        } else {
-            color_display.display_error("❌ 当前没有加入任何房间");
         }
     }
-    
-    /// 执行房间列表命令
-    async fn execute_list_rooms_command(
+
+    /// 执行好友列表命令
+    async fn execute_list_friends_command(
         state: Arc<Mutex<AppState>>,
         color_display: &ColorDisplay,
     ) {
-        let user_id = {
+        let client = {
             let app_state = state.lock().await;
-            app_state.user_id.clone()
+            if app_state.auth.is_none() {
+                color_display.display_error("❌ 好友功能需要登录，请先使用 /login 登录");
+                return;
+            }
+            FriendApiClient::from_state(&app_state)
         };
-        
-        if let Some(user_id) = user_id {
-            let client = RoomApiClient::new();
-            match client.list_user_rooms(&user_id.to_string()).await {
-                Ok(rooms) => {
-                    if rooms.is_empty() {
-                        color_display.display_info("📝 您还没有加入任何房间");
-                        color_display.display_info("使用 /create <房间名> 创建房间或 /join <房间ID> 加入房间");
+
+        match client.list_friends().await {
+            Ok(friends) if friends.is_empty() => {
+                color_display.display_info("📝 您还没有好友");
+            }
+            Ok(mut friends) => {
+                friends.sort_by_key(|friend| friend.display_name());
+                color_display.display_info(&format!("📋 您的好友列表 (共 {} 位好友):", friends.len()));
+                for friend in friends {
+                    if friend.alias.is_some() {
+                        color_display.display_info(&format!("  👤 {} (ID: {})", friend.display_name(), friend.user_id));
                     } else {
-                        color_display.display_info(&format!("📋 您的房间列表 (共 {} 个房间):", rooms.len()));
-                        println!();
-                        
-                        for room in rooms {
-                            let status_icon = if room.is_owner { "👑" } else { "👤" };
-                            let member_info = if let Some(max) = room.max_members {
-                                format!("{}/{}", room.member_count, max)
-                            } else {
-                                room.member_count.to_string()
-                            };
-                            
-                            color_display.display_info(&format!(
-                                "  {} {} (ID: {})",
-                                status_icon, room.name, room.id
-                            ));
-                            color_display.display_info(&format!(
-                                "    成员: {} | 创建时间: {}",
-                                member_info, room.created_at
-                            ));
-                            
-                            if let Some(desc) = room.description {
-                                color_display.display_info(&format!("    描述: {}", desc));
-                            }
-                            println!();
-                        }
-                        
-                        color_display.display_info("使用 /join <房间ID> 切换到指定房间");
+                        color_display.display_info(&format!("  👤 {}", friend.user_id));
                     }
                 }
-                Err(e) => {
-                    color_display.display_error(&format!("❌ 获取房间列表失败: {}", e));
-                }
             }
-        } else {
-            color_display.display_error("❌ 未连接到服务器，无法获取房间列表");
+            Err(e) => {
+                color_display.display_error(&format!("❌ 获取好友列表失败: {}", e));
+            }
         }
     }
 }
@@ -808,38 +2080,74 @@ async fn send_message_via_channel(
     state: Arc<Mutex<AppState>>,
     ws_sender: &tokio::sync::mpsc::UnboundedSender<WsMessage>,
 ) -> Result<()> {
-    let app_state = state.lock().await;
+    let mut app_state = state.lock().await;
+    if let Err(err) = Message::validate_content(&content) {
+        app_state.color_display.display_error(&format!("消息内容{}", err));
+        return Ok(());
+    }
     let nickname = app_state.nickname.clone();
+
+    // 设置了当前房间时发送到房间
+    let msg = match app_state.current_room_id.clone() {
+        Some(room_id) => ClientMessage::SendRoomMessage { room_id, content },
+        None => {
+            // 服务器支持确认时记录待确认的消息，超时未确认则提示
+            let client_msg_id = app_state.message_ack_supported.then(|| {
+                let client_msg_id = uuid::Uuid::new_v4().to_string();
+                app_state.pending_messages.insert(client_msg_id.clone(), text_preview(&content));
+                watch_message_ack(state.clone(), client_msg_id.clone());
+                client_msg_id
+            });
+            ClientMessage::SendMessage {
+                content,
+                nickname,
+                client_timestamp: Some(chrono::Utc::now()),
+                client_msg_id,
+            }
+        }
+    };
     drop(app_state);
-      let msg = ClientMessage::SendMessage { content, nickname };
-    let json = serde_json::to_string(&msg)?;
-    ws_sender.send(WsMessage::Text(json.into()))?;
-    
-    Ok(())
+
+    send_client_message(ws_sender, &msg)
 }
 
-/// 连接配置
-#[derive(Clone)]
-pub struct ConnectionConfig {
-    pub url: String,
-    pub max_reconnect_attempts: u32,
-    pub initial_retry_delay: Duration,
-    pub max_retry_delay: Duration,
-    pub retry_backoff_factor: f64,
+/// 等待服务器确认消息，超时仍未确认时提示发送可能失败
+fn watch_message_ack(state: Arc<Mutex<AppState>>, client_msg_id: String) {
+    tokio::spawn(async move {
+        time::sleep(MESSAGE_ACK_TIMEOUT).await;
+        let mut app_state = state.lock().await;
+        if let Some(preview) = app_state.pending_messages.remove(&client_msg_id) {
+            warn!("消息 {} 在 {:?} 内未收到服务器确认", client_msg_id, MESSAGE_ACK_TIMEOUT);
+            app_state
+                .color_display
+                .display_error(&format!("消息「{}」未收到服务器确认，可能发送失败", preview));
+        }
+    });
 }
 
-impl Default for ConnectionConfig {
-    fn default() -> Self {
-        Self {
-            url: "ws://127.0.0.1:8080/ws".to_string(),
-            max_reconnect_attempts: 10,
-            initial_retry_delay: Duration::from_secs(1),
-            max_retry_delay: Duration::from_secs(30),
-            retry_backoff_factor: 2.0,
+/// 解析服务器发送的事件，无法解析时在debug日志中记录原始文本后忽略
+///
+/// 较新的服务器可能发送本客户端不认识的事件，忽略它们不影响后续事件的处理
+fn parse_ws_event(text: &str) -> Option<WsEvent> {
+    match serde_json::from_str(text) {
+        Ok(event) => Some(event),
+        Err(err) => {
+            debug!("忽略无法解析的服务器事件: {}，原始内容: {}", err, text);
+            None
         }
     }
 }
 
+/// 序列化并发送客户端消息
+fn send_client_message(
+    ws_sender: &tokio::sync::mpsc::UnboundedSender<WsMessage>,
+    msg: &ClientMessage,
+) -> Result<()> {
+    let json = serde_json::to_string(msg)?;
+    ws_sender.send(WsMessage::Text(json.into()))?;
+    Ok(())
+}
+
 /// 连接到WebSocket服务器
 async fn connect_to_server(
     url: &str,
@@ -857,6 +2165,7 @@ async fn run_connection_session(
     config_manager: UserConfigManager,
     message_db: Arc<MessageDatabase>,
     input_rx: &mut tokio::sync::mpsc::UnboundedReceiver<String>,
+    pending_input: &mut VecDeque<String>,
 ) -> Result<bool> {
     let (mut ws_sender, mut ws_receiver) = ws_stream.split();
     
@@ -866,10 +2175,15 @@ async fn run_connection_session(
       // WebSocket发送任务
     let mut ws_sender_task = tokio::spawn(async move {
         while let Some(message) = ws_send_rx.recv().await {
+            let closing = matches!(message, WsMessage::Close(_));
             if let Err(err) = ws_sender.send(message).await {
                 error!("WebSocket发送失败: {}", err);
                 break;
             }
+            // 关闭帧之后不能再发送任何消息
+            if closing {
+                break;
+            }
         }
     });
       // WebSocket接收任务
@@ -879,7 +2193,8 @@ async fn run_connection_session(
     let mut ws_task = tokio::spawn(async move {
         while let Some(msg) = ws_receiver.next().await {
             match msg {
-                Ok(WsMessage::Text(text)) => {                    if let Ok(event) = serde_json::from_str::<WsEvent>(&text) {
+                Ok(WsMessage::Text(text)) => {
+                    if let Some(event) = parse_ws_event(&text) {
                         // 获取color_display引用
                         let color_display = {
                             let app_state = state_clone.lock().await;
@@ -896,12 +2211,22 @@ async fn run_connection_session(
                         ).await {
                             error!("处理WebSocket事件失败: {}", err);
                         }
+
+                        // 服务器不兼容时立即结束会话
+                        if state_clone.lock().await.incompatible_server {
+                            break;
+                        }
                     }
                 }                Ok(WsMessage::Close(_)) => {
                     let app_state = state_clone.lock().await;
                     app_state.color_display.display_info("🔌 服务器连接已关闭");
                     break;
                 }
+                // 对方未回应关闭帧直接断开（如退出时服务器先关闭了连接）
+                Err(WsError::Protocol(ProtocolError::ResetWithoutClosingHandshake)) => {
+                    warn!("连接在关闭握手完成前断开");
+                    break;
+                }
                 Err(err) => {
                     error!("WebSocket错误: {}", err);
                     break;
@@ -916,13 +2241,22 @@ async fn run_connection_session(
     
     loop {
         tokio::select! {
-            // 处理用户输入
-            input = input_rx.recv() => {
+            // 处理用户输入（先处理等待重连期间输入的内容）
+            input = next_input(pending_input, input_rx) => {
                 match input {
                     Some(input) => {
                         if input.is_empty() {
                             continue;
                         }
+
+                        // /clear-history 之后的一行输入用于确认，不作为消息或命令处理
+                        let confirming = std::mem::take(&mut state.lock().await.clear_history_pending);
+                        if confirming {
+                            let color_display = state.lock().await.color_display.clone();
+                            CommandExecutor::confirm_clear_history(&input, state.clone(), &message_db, &color_display).await;
+                            continue;
+                        }
+
                           if input.starts_with('/') {
                             let color_display = {
                                 let app_state = state.lock().await;
@@ -942,6 +2276,11 @@ async fn run_connection_session(
                                 should_quit = true;
                                 break;
                             }
+                            
+                            // 登录或 /reconnect 需要重新连接
+                            if state.lock().await.reconnect_requested {
+                                break;
+                            }
                         } else {
                             if let Err(err) = send_message_via_channel(input, state.clone(), &ws_send_tx).await {
                                 error!("发送消息失败: {}", err);
@@ -950,7 +2289,9 @@ async fn run_connection_session(
                         }
                     }
                     None => {
-                        // 输入通道已关闭
+                        // 输入通道已关闭（Ctrl-D，或输入编辑器中按下Ctrl-C）
+                        let color_display = state.lock().await.color_display.clone();
+                        CommandExecutor::execute_quit_command(&color_display).await;
                         should_quit = true;
                         break;
                     }
@@ -960,10 +2301,10 @@ async fn run_connection_session(
             _ = &mut ws_task => {
                 // WebSocket连接断开
                 {
-                    let app_state = state.lock().await;
-                    app_state.color_display.display_error("WebSocket连接断开");
                     let mut app_state = state.lock().await;
+                    app_state.color_display.display_error("WebSocket连接断开");
                     app_state.connected = false;
+                    app_state.connected_since = None;
                 }
                 break;
             }
@@ -973,6 +2314,36 @@ async fn run_connection_session(
                 error!("WebSocket发送任务意外结束");
                 break;
             }
+
+            // Ctrl-C与 /quit 走同样的退出流程
+            _ = tokio::signal::ctrl_c() => {
+                let color_display = state.lock().await.color_display.clone();
+                println!();
+                CommandExecutor::execute_quit_command(&color_display).await;
+                should_quit = true;
+                break;
+            }
+        }
+    }
+    
+    let reconnect_requested = state.lock().await.reconnect_requested;
+    if should_quit {
+        // 退出过程中再次按下Ctrl-C时强制退出
+        tokio::spawn(async {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                eprintln!("强制退出");
+                std::process::exit(130);
+            }
+        });
+    }
+
+    if should_quit || reconnect_requested {
+        // 发送关闭帧，等待服务器回应，期间收到的消息照常处理和保存
+        let close_frame = CloseFrame { code: CloseCode::Normal, reason: "".into() };
+        if ws_send_tx.send(WsMessage::Close(Some(close_frame))).is_ok()
+            && time::timeout(SHUTDOWN_TIMEOUT, &mut ws_task).await.is_err()
+        {
+            warn!("等待服务器关闭连接超时");
         }
     }
     
@@ -980,42 +2351,89 @@ async fn run_connection_session(
     drop(ws_send_tx);
     ws_task.abort();
     ws_sender_task.abort();
+
+    {
+        let mut app_state = state.lock().await;
+        app_state.connected = false;
+        app_state.connected_since = None;
+    }
     
     Ok(should_quit)
 }
 
+/// 读取下一条用户输入，优先返回等待重连期间暂存的输入
+async fn next_input(
+    pending_input: &mut VecDeque<String>,
+    input_rx: &mut tokio::sync::mpsc::UnboundedReceiver<String>,
+) -> Option<String> {
+    match pending_input.pop_front() {
+        Some(input) => Some(input),
+        None => input_rx.recv().await,
+    }
+}
+
 /// 带重连的客户端运行函数
-async fn run_client_with_reconnect() -> Result<()> {
-    let config = ConnectionConfig::default();
-    let mut reconnect_attempts = 0;
-    let mut current_retry_delay = config.initial_retry_delay;
-    
+async fn run_client_with_reconnect(args: &CliArgs) -> Result<()> {
     // 初始化配置管理器
     let config_manager = UserConfigManager::new()?;
+    
+    // 加载连接配置（connection.toml 与 --server 参数）
+    let mut config = ConnectionConfig::load(config_manager.config_dir(), args.server.as_deref()).await?;
+    if let Some(limit) = args.history {
+        config.history_limit = limit;
+    }
+    info!("服务器地址: {}", config.url);
+    let mut reconnect_attempts = 0;
+    let mut current_retry_delay = config.initial_retry_delay;
+    // 是否已经连接过，重连时需要补齐断线期间的消息
+    let mut connected_before = false;
+    // 等待重连期间输入的消息和命令，连接后按顺序处理
+    let mut pending_input = VecDeque::new();
       // 初始化消息数据库
     let message_db = Arc::new(MessageDatabase::new().await
         .context("Failed to initialize message database")?);
+    if let Err(err) = message_db.replay_dead_letters().await {
+        error!("重放死信日志失败: {:#}", err);
+    }
     
-    // 创建临时ColorDisplay用于启动信息
-    let temp_color_display = ColorDisplay::new();
-    
-    // 加载历史消息
-    temp_color_display.display_info("正在加载消息历史...");
-    let history_messages = message_db.get_recent_messages(100).await
-        .context("Failed to load message history")?;
-        
     // 加载或创建用户配置
     let user_config = config_manager.load_config().await?;
     info!("用户ID已加载: {}", user_config.user_id);
+    
+    // 使用保存的颜色主题（输出不是终端时使用无颜色主题）
+    let startup_color_display = ColorDisplay::with_theme(ColorTheme::select(user_config.theme.as_deref()))
+        .with_enabled(colors::colors_allowed(args.no_color))
+        .with_timestamp_format(TimestampFormat {
+            hour12: user_config.clock_12h,
+            show_date: user_config.show_date,
+        });
+    
+    // 加载历史消息（只在启动时加载一次，重连后不再重复加载）
+    let history_messages = if config.history_limit > 0 {
+        startup_color_display.display_info("正在加载消息历史...");
+        message_db.get_recent_messages(config.history_limit as i64).await
+            .context("Failed to load message history")?
+    } else {
+        Vec::new()
+    };
         
     let state = Arc::new(Mutex::new(AppState::new()));
     
     // 初始化应用状态，使用已加载的用户配置
     {
         let mut app_state = state.lock().await;
+        app_state.color_display = startup_color_display;
         app_state.user_id = Some(user_config.user_id.clone());
         app_state.nickname = user_config.nickname.clone();
-        app_state.messages.extend(history_messages.clone());
+        for message in &history_messages {
+            app_state.add_message(message.clone());
+        }
+        app_state.api_base_url = auth::http_base_url(&config.url)?;
+        
+        // 恢复上次的登录会话，连接前会使用刷新令牌换取访问令牌
+        if let (Some(email), Some(refresh_token)) = (&user_config.account_email, &user_config.refresh_token) {
+            app_state.auth = Some(AuthSession::from_refresh_token(email.clone(), refresh_token.clone()));
+        }
     }
     
     // 显示欢迎消息（使用彩色显示）
@@ -1031,47 +2449,106 @@ async fn run_client_with_reconnect() -> Result<()> {
         }
     }
     
-    // 显示历史消息（使用彩色显示）
+    // 显示历史消息（使用彩色显示），在第一条未读消息前插入未读分隔线
     if !history_messages.is_empty() {
         let app_state = state.lock().await;
+        let unread_index = user_config.first_unread_index(&history_messages);
         app_state.color_display.display_history_separator(history_messages.len());
-        for msg in &history_messages {
-            display_message(msg, &app_state.color_display);
+        for (index, msg) in history_messages.iter().enumerate() {
+            if unread_index == Some(index) {
+                app_state.color_display.display_unread_separator(history_messages.len() - index);
+            }
+            display_message(msg, &app_state.color_display, app_state.user_id.as_ref());
         }
         app_state.color_display.display_separator();
     }
       // 创建用户输入通道
     let (input_tx, mut input_rx) = tokio::sync::mpsc::unbounded_channel::<String>();
     
-    // 创建共享的ColorDisplay实例用于输入提示
-    let color_display_for_input = ColorDisplay::new();
+    // 创建输入辅助器（Tab补全命令和昵称，彩色提示符）
+    let input_helper = InputHelper::new(state.clone());
     
-    // 启动用户输入处理任务
-    tokio::spawn(async move {
-        let mut input = String::new();
+    // 启动用户输入线程（rustyline为阻塞读取）。不使用tokio的阻塞线程池：运行时关闭时会等待其中的任务结束，
+    // 而因连接失败等原因退出时readline可能仍在等待输入
+    std::thread::Builder::new().name("rustchat-input".to_string()).spawn(move || {
+        let mut editor = match Editor::<InputHelper, DefaultHistory>::new() {
+            Ok(editor) => editor,
+            Err(err) => {
+                error!("初始化输入编辑器失败: {}", err);
+                return;
+            }
+        };
+        editor.set_helper(Some(input_helper));
+        
         loop {
-            color_display_for_input.display_prompt();
-            
-            input.clear();
-            if let Err(_) = io::stdin().read_line(&mut input) {
-                break;
+            match editor.readline("> ") {
+                Ok(line) => {
+                    let input_trimmed = line.trim().to_string();
+                    // 包含密码的命令不记录到输入历史
+                    let sensitive = input_trimmed.starts_with("/login") || input_trimmed.starts_with("/register");
+                    if !input_trimmed.is_empty() && !sensitive {
+                        // 记录输入历史，支持上下方向键翻阅
+                        let _ = editor.add_history_entry(input_trimmed.as_str());
+                    }
+                    // 退出命令之后不再读取，终端不会停留在readline的原始模式
+                    let quit = matches!(CommandParser::parse_command(&input_trimmed).command, Command::Quit);
+                    if input_tx.send(input_trimmed).is_err() || quit {
+                        break;
+                    }
+                }
+                Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => {
+                    break;
+                }
+                Err(err) => {
+                    error!("读取输入失败: {}", err);
+                    break;
+                }
             }
-            
-            let input_trimmed = input.trim().to_string();
-            if let Err(_) = input_tx.send(input_trimmed) {
-                break;
+        }
+    }).context("Failed to start input thread")?;
+    
+    // 启动令牌自动刷新任务，保证REST请求使用的访问令牌有效
+    let refresh_task = tokio::spawn({
+        let state = state.clone();
+        let config_manager = config_manager.clone();
+        async move {
+            let mut interval = time::interval(Duration::from_secs(30));
+            loop {
+                interval.tick().await;
+                refresh_session_if_needed(&state, &config_manager).await;
             }
         }
     });
     
     loop {
+        // 连接前确保访问令牌有效，登录用户携带令牌连接
+        refresh_session_if_needed(&state, &config_manager).await;
+        let (access_token, resume_token) = {
+            let app_state = state.lock().await;
+            let access_token = app_state.auth.as_ref().and_then(|session| session.valid_access_token().map(str::to_string));
+            (access_token, app_state.resume_token.clone())
+        };
+        // 登录用户以令牌确定身份，匿名用户携带恢复令牌以沿用上次的用户ID
+        let connect_url = match access_token {
+            Some(token) => auth::ws_url_with_token(&config.url, Some(&token))?,
+            None => auth::ws_url_with_resume_token(&config.url, resume_token.as_deref())?,
+        };
+        let connect_url = auth::ws_url_with_protocol(&connect_url)?;
+        let temp_color_display = state.lock().await.color_display.clone();
+        
         // 尝试连接
         info!("正在连接到服务器: {}", config.url);
-          match connect_to_server(&config.url).await {
+          match connect_to_server(&connect_url).await {
             Ok(ws_stream) => {
                 temp_color_display.display_success("已连接到RustChat服务器");
                 reconnect_attempts = 0;
                 current_retry_delay = config.initial_retry_delay;
+                state.lock().await.reconnect_attempt = None;
+
+                if connected_before {
+                    recover_message_gap(&state, &message_db).await;
+                }
+                connected_before = true;
                 
                 // 运行连接会话
                 match run_connection_session(
@@ -1080,11 +2557,24 @@ async fn run_client_with_reconnect() -> Result<()> {
                     config_manager.clone(),
                     message_db.clone(),
                     &mut input_rx,
+                    &mut pending_input,
                 ).await {                    Ok(should_quit) => {
                         if should_quit {
                             temp_color_display.display_success("👋 再见!");
                             break;
                         }
+                        
+                        // 服务器不兼容，重连也无法恢复
+                        if state.lock().await.incompatible_server {
+                            temp_color_display.display_info("已停止自动重连，升级客户端后重新启动即可");
+                            break;
+                        }
+
+                        // 主动请求的重连（如登录、/reconnect）无需等待
+                        let reconnect_requested = std::mem::take(&mut state.lock().await.reconnect_requested);
+                        if reconnect_requested {
+                            continue;
+                        }
                         // 连接断开，但用户没有主动退出，需要重连
                     }
                     Err(err) => {
@@ -1099,6 +2589,7 @@ async fn run_client_with_reconnect() -> Result<()> {
                 }
                 
                 reconnect_attempts += 1;
+                state.lock().await.reconnect_attempt = Some((reconnect_attempts, config.max_reconnect_attempts));
                 
                 if reconnect_attempts > config.max_reconnect_attempts {
                     temp_color_display.display_error(&format!("重连次数已达上限 ({})，停止重连", config.max_reconnect_attempts));
@@ -1106,18 +2597,27 @@ async fn run_client_with_reconnect() -> Result<()> {
                     break;
                 }
                 
+                // 加上随机抖动，避免大量客户端同时重连
+                let retry_delay = config.jittered_delay(current_retry_delay, &mut rand::thread_rng());
                 temp_color_display.display_info(&format!("🔄 连接失败，{:.1}秒后重试 ({}/{})", 
-                    current_retry_delay.as_secs_f64(), 
+                    retry_delay.as_secs_f64(), 
                     reconnect_attempts, 
                     config.max_reconnect_attempts
                 ));
                 
-                time::sleep(current_retry_delay).await;
+                match wait_before_reconnect(retry_delay, &state, &mut input_rx, &mut pending_input).await {
+                    ReconnectWait::Elapsed => {}
+                    ReconnectWait::Skipped => {
+                        // 用户要求立即重连，重新开始计算退避
+                        reconnect_attempts = 0;
+                        current_retry_delay = config.initial_retry_delay;
+                        continue;
+                    }
+                    ReconnectWait::Quit => break,
+                }
                 
                 // 指数退避
-                current_retry_delay = Duration::from_millis(
-                    ((current_retry_delay.as_millis() as f64) * config.retry_backoff_factor) as u64
-                ).min(config.max_retry_delay);
+                current_retry_delay = config.next_retry_delay(current_retry_delay);
                 
                 continue;
             }
@@ -1125,57 +2625,457 @@ async fn run_client_with_reconnect() -> Result<()> {
           // 如果到这里，说明连接断开了，需要重连
         temp_color_display.display_info("🔄 连接断开，正在尝试重连...");
         
-        // 等待一小段时间再重连
-        time::sleep(Duration::from_secs(2)).await;
+        // 等待一小段时间再重连，加上随机抖动避免服务器重启后所有客户端同时重连
+        let retry_delay = config.disconnect_retry_delay(&mut rand::thread_rng());
+        if let ReconnectWait::Quit = wait_before_reconnect(retry_delay, &state, &mut input_rx, &mut pending_input).await {
+            break;
+        }
+    }
+    
+    refresh_task.abort();
+    
+    // 退出前保存已读标记，下次启动时据此标记未读消息
+    save_last_read_marker(&state, &config_manager).await;
+
+    // 等待进行中的写入完成后关闭数据库
+    if let Ok(message_db) = Arc::try_unwrap(message_db) {
+        message_db.close().await;
     }
     
     Ok(())
 }
 
+/// 等待重连间隔的结果
+enum ReconnectWait {
+    /// 等待结束，按计划重连
+    Elapsed,
+    /// 用户输入了 /reconnect，跳过剩余的等待
+    Skipped,
+    /// 用户要求退出（Ctrl-C、/quit 或输入结束）
+    Quit,
+}
+
+/// 等待重连间隔
+///
+/// 等待期间仍然响应 /reconnect、/status 和 /quit，其他输入暂存到 `pending_input`，连接后按顺序处理
+async fn wait_before_reconnect(
+    delay: Duration,
+    state: &Arc<Mutex<AppState>>,
+    input_rx: &mut tokio::sync::mpsc::UnboundedReceiver<String>,
+    pending_input: &mut VecDeque<String>,
+) -> ReconnectWait {
+    let sleep = time::sleep(delay);
+    tokio::pin!(sleep);
+
+    loop {
+        tokio::select! {
+            _ = &mut sleep => return ReconnectWait::Elapsed,
+            _ = tokio::signal::ctrl_c() => {
+                println!();
+                info!("重连等待中收到中断信号，退出");
+                return ReconnectWait::Quit;
+            }
+            input = input_rx.recv() => {
+                let Some(input) = input else {
+                    return ReconnectWait::Quit;
+                };
+                let color_display = state.lock().await.color_display.clone();
+                match CommandParser::parse_command(&input).command {
+                    Command::Reconnect => {
+                        color_display.display_info("🔄 正在立即重连...");
+                        return ReconnectWait::Skipped;
+                    }
+                    Command::Status => CommandExecutor::execute_status_command(state.clone(), &color_display).await,
+                    Command::Quit => {
+                        CommandExecutor::execute_quit_command(&color_display).await;
+                        return ReconnectWait::Quit;
+                    }
+                    _ => pending_input.push_back(input),
+                }
+            }
+        }
+    }
+}
+
+/// 访问令牌即将过期时使用刷新令牌自动续期
+async fn refresh_session_if_needed(state: &Arc<Mutex<AppState>>, config_manager: &UserConfigManager) {
+    let (api_base_url, refresh_token) = {
+        let app_state = state.lock().await;
+        match &app_state.auth {
+            Some(session) if session.needs_refresh() => {
+                (app_state.api_base_url.clone(), session.refresh_token.clone())
+            }
+            _ => return,
+        }
+    };
+    
+    match AuthApiClient::new(&api_base_url).refresh(&refresh_token).await {
+        Ok(tokens) => {
+            if let Some(session) = state.lock().await.auth.as_mut() {
+                session.apply_refresh(tokens);
+            }
+            info!("访问令牌已刷新");
+        }
+        Err(err) if auth::is_network_error(&err) => {
+            // 服务器暂时不可达，保留会话稍后重试
+            warn!("刷新访问令牌失败，稍后重试: {}", err);
+        }
+        Err(err) => {
+            warn!("刷新令牌已失效: {:#}", err);
+            {
+                let mut app_state = state.lock().await;
+                app_state.auth = None;
+                app_state.color_display.display_error("登录已过期，请使用 /login 重新登录");
+            }
+            if let Err(err) = config_manager.clear_login().await {
+                error!("清除登录信息失败: {}", err);
+            }
+        }
+    }
+}
+
+/// 将已显示的最新消息保存为已读标记
+async fn save_last_read_marker(state: &Arc<Mutex<AppState>>, config_manager: &UserConfigManager) {
+    let last_timestamp = {
+        let app_state = state.lock().await;
+        app_state.messages.last().map(|msg| msg.timestamp)
+    };
+    
+    if let Some(timestamp) = last_timestamp {
+        if let Err(err) = config_manager.update_last_read(timestamp).await {
+            error!("保存已读标记失败: {}", err);
+        }
+    }
+}
+
 /// 运行CLI客户端（现在使用带重连的版本）
-async fn run_client() -> Result<()> {
-    run_client_with_reconnect().await
+async fn run_client(args: &CliArgs) -> Result<()> {
+    run_client_with_reconnect(args).await
+}
+
+/// 命令行参数
+#[derive(Parser, Debug)]
+#[command(name = "rustchat-cli", about = "RustChat 命令行客户端")]
+struct CliArgs {
+    /// 服务器WebSocket地址，优先于 ~/.rustchat/connection.toml
+    #[arg(long, value_name = "URL")]
+    server: Option<String>,
+    
+    /// 禁用彩色输出（也可设置 NO_COLOR 环境变量）
+    #[arg(long)]
+    no_color: bool,
+
+    /// 启动时加载的历史消息条数（0为不加载），优先于 connection.toml
+    #[arg(long, value_name = "N")]
+    history: Option<usize>,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let args = CliArgs::parse();
+    
     // 初始化日志
     tracing_subscriber::fmt()
         .with_target(false)
         .compact()        .init();
     
+    // 加载用户配置
+    let user_config = match UserConfigManager::new() {
+        Ok(config_manager) => match config_manager.load_config().await {
+            Ok(config) => Some(config),
+            Err(err) => {
+                error!("加载配置失败: {}", err);
+                None
+            }
+        },
+        Err(err) => {
+            error!("初始化配置管理器失败: {}", err);
+            None
+        }
+    };
+    
     // 创建临时ColorDisplay用于启动信息
-    let temp_color_display = ColorDisplay::new();
+    let saved_theme = user_config.as_ref().and_then(|config| config.theme.as_deref());
+    let temp_color_display = ColorDisplay::with_theme(ColorTheme::select(saved_theme))
+        .with_enabled(colors::colors_allowed(args.no_color));
     temp_color_display.display_welcome();
     temp_color_display.display_info("正在启动...");
     
     // 显示用户配置信息
-    match UserConfigManager::new() {
-        Ok(config_manager) => {
-            match config_manager.load_config().await {
-                Ok(config) => {
-                    temp_color_display.display_info("📁 配置目录: ~/.rustchat/");
-                    temp_color_display.display_success(&format!("🆔 用户ID: {}", config.user_id));
-                    if let Some(nickname) = &config.nickname {
-                        temp_color_display.display_success(&format!("👤 昵称: {}", nickname));
-                    }
-                    println!();
-                }
-                Err(err) => {
-                    error!("加载配置失败: {}", err);
-                }
-            }
-        }
-        Err(err) => {
-            error!("初始化配置管理器失败: {}", err);
+    if let Some(config) = &user_config {
+        temp_color_display.display_info("📁 配置目录: ~/.rustchat/");
+        temp_color_display.display_success(&format!("🆔 用户ID: {}", config.user_id));
+        if let Some(nickname) = &config.nickname {
+            temp_color_display.display_success(&format!("👤 昵称: {}", nickname));
         }
+        println!();
     }
-      if let Err(err) = run_client().await {
+      if let Err(err) = run_client(&args).await {
         error!("客户端运行失败: {}", err);
         temp_color_display.display_error(&format!("连接失败: {}", err));
         temp_color_display.display_info("请确保服务器正在运行: cargo run --bin rustchatd");
         std::process::exit(1);
     }
-    
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_room_commands() {
+        let room_id = "3F2A9C1E-0000-4000-8000-000000000000";
+        let parse = |input: &str| CommandParser::parse_command(input).command;
+
+        // 房间ID规范化为小写，与服务器一致
+        assert!(matches!(parse(&format!("/join {}", room_id)), Command::JoinRoom(id) if id == room_id.to_lowercase()));
+        assert!(matches!(parse("/leave"), Command::LeaveRoom(None)));
+        assert!(matches!(
+            parse(&format!("/room {} 大家 好", room_id)),
+            Command::RoomMessage { content, .. } if content == "大家 好"
+        ));
+
+        // 缺少或无效的参数给出用法提示
+        assert!(matches!(parse("/join"), Command::Unknown(msg) if msg.contains("/join <房间ID>")));
+        assert!(matches!(parse("/join lobby"), Command::Unknown(msg) if msg.contains("无效的房间ID")));
+        assert!(matches!(parse("/leave lobby"), Command::Unknown(msg) if msg.contains("无效的房间ID")));
+        assert!(matches!(parse(&format!("/room {}", room_id)), Command::Unknown(msg) if msg.contains("消息内容不能为空")));
+        assert!(matches!(parse("/room"), Command::Unknown(msg) if msg.contains("/room <房间ID> <消息>")));
+
+        // 超长内容在发送前被拒绝
+        let long = "a".repeat(rustchat_types::validation::MAX_CONTENT_CHARS + 1);
+        assert!(matches!(parse(&format!("/room {} {}", room_id, long)), Command::Unknown(msg) if msg == "消息内容不能超过4000个字符"));
+        assert!(matches!(parse(&format!("/me {}", long)), Command::Unknown(msg) if msg.starts_with("动作内容不能超过")));
+    }
+
+    #[test]
+    fn test_format_uptime() {
+        assert_eq!(format_uptime(Duration::from_secs(5)), "5秒");
+        assert_eq!(format_uptime(Duration::from_secs(125)), "2分5秒");
+        assert_eq!(format_uptime(Duration::from_secs(3 * 3600 + 7)), "3小时0分7秒");
+        assert!(matches!(CommandParser::parse_command("/stats").command, Command::Stats));
+        assert!(matches!(CommandParser::parse_command("/time 12H").command, Command::Time(Some(option)) if option == "12h"));
+        assert!(matches!(CommandParser::parse_command("/clear-history").command, Command::ClearHistory));
+        assert!(matches!(CommandParser::parse_command("/history 50").command, Command::History { limit: Some(50), user: None }));
+        assert!(matches!(CommandParser::parse_command("/clear").command, Command::Clear));
+    }
+
+    #[test]
+    fn test_history_user_filter() {
+        let parse = |input: &str| CommandParser::parse_command(input).command;
+        let alice = UserId::new();
+
+        assert!(matches!(parse("/history @alice 30"), Command::History { limit: Some(30), user: Some(HistoryUser::Nickname(nick)) } if nick == "alice"));
+        assert!(matches!(parse(&format!("/hist user:{}", alice)), Command::History { limit: None, user: Some(HistoryUser::Id(id)) } if id == alice));
+        assert!(matches!(parse("/history user:nope"), Command::Unknown(msg) if msg.contains("无效的用户ID")));
+        assert!(matches!(parse("/history @"), Command::Unknown(_)));
+
+        // 已知昵称优先，其次是最近消息的发送者（最新的优先）
+        let bob = UserId::new();
+        let nicknames = HashMap::from([(alice.clone(), "Alice".to_string())]);
+        let recent = vec![
+            Message::new_text(UserId::new(), "old".to_string(), Some("bob".to_string())),
+            Message::new_text(bob.clone(), "new".to_string(), Some("bob".to_string())),
+        ];
+        assert_eq!(resolve_nickname("alice", &nicknames, &recent), Some(alice));
+        assert_eq!(resolve_nickname("BOB", &nicknames, &recent), Some(bob));
+        assert_eq!(resolve_nickname("carol", &nicknames, &recent), None);
+    }
+
+    #[test]
+    fn test_whois_command() {
+        let user_id = UserId::new();
+        assert!(matches!(CommandParser::parse_command(&format!("/whois {}", user_id)).command, Command::Whois(id) if id == user_id));
+        assert!(matches!(CommandParser::parse_command("/whois bob").command, Command::Unknown(msg) if msg.contains("无效的用户ID")));
+        assert!(matches!(CommandParser::parse_command("/whois").command, Command::Unknown(msg) if msg.contains("用法")));
+
+        // 服务器不可达时在线状态显示为未知
+        let color_display = ColorDisplay::new().with_enabled(false);
+        let info = WhoisInfo { user_id: user_id.clone(), nickname: Some("bob".to_string()), online: None, first_seen_at: None };
+        let rows = info.rows(&color_display);
+        assert_eq!(rows[0], ("用户ID", user_id.short()));
+        assert_eq!(rows[1].1, "bob");
+        assert_eq!(rows[2].1, "未知");
+        assert_eq!(rows[3].1, "未知");
+    }
+
+    #[test]
+    fn test_gap_recovery_cursor() {
+        let mut app_state = AppState::new();
+        assert_eq!(app_state.latest_global_message_time(), None);
+
+        let older = Message::new_text(UserId::new(), "older".to_string(), None);
+        let mut received = Message::new_text(UserId::new(), "received".to_string(), None);
+        received.timestamp = older.timestamp - chrono::Duration::minutes(5);
+        received.received_at = Some(older.timestamp + chrono::Duration::seconds(1));
+        let mut room = Message::new_room_text(UserId::new(), "room".to_string(), None, "room-1".to_string());
+        room.timestamp = older.timestamp + chrono::Duration::minutes(1);
+        for message in [older, received.clone(), room] {
+            app_state.add_message(message);
+        }
+
+        // 以服务器接收时间为准，房间消息不参与
+        assert_eq!(app_state.latest_global_message_time(), received.received_at);
+    }
+
+    #[test]
+    fn test_error_event_code() {
+        let event: WsEvent =
+            serde_json::from_str(r#"{"event":"Error","data":{"code":"RATE_LIMITED","message":"发送过于频繁"}}"#).unwrap();
+        assert!(matches!(event, WsEvent::Error { code: ErrorCode::RateLimited, .. }));
+        assert!(error_hint(ErrorCode::RateLimited).is_some());
+
+        // 旧版服务器的错误事件没有代码
+        let event: WsEvent = serde_json::from_str(r#"{"event":"Error","data":{"message":"出错了"}}"#).unwrap();
+        assert!(matches!(event, WsEvent::Error { code: ErrorCode::Unknown, .. }));
+        assert!(error_hint(ErrorCode::Unknown).is_none());
+    }
+
+    #[test]
+    fn test_connection_status() {
+        assert!(matches!(CommandParser::parse_command("/reconnect").command, Command::Reconnect));
+        assert!(matches!(CommandParser::parse_command("/status").command, Command::Status));
+
+        let mut app_state = AppState::new();
+        assert_eq!(app_state.connection_status(), "未连接");
+
+        app_state.connected = true;
+        app_state.connected_since = Some(Instant::now());
+        assert_eq!(app_state.connection_status(), "已连接（0秒）");
+
+        app_state.connected = false;
+        app_state.reconnect_attempt = Some((2, 10));
+        assert_eq!(app_state.connection_status(), "正在重连（第 2/10 次）");
+    }
+
+    #[test]
+    fn test_nickname_cache_updates_user_label() {
+        let user_id = UserId::parse("3f2a9c1e-0000-4000-8000-000000000000").unwrap();
+        let mut app_state = AppState::new();
+        assert_eq!(app_state.user_label(&user_id), "用户 3f2a9c1e");
+
+        app_state.remember_nickname(&user_id, "Alice");
+        assert_eq!(app_state.user_label(&user_id), "Alice");
+
+        let event: WsEvent = serde_json::from_str(
+            r#"{"event":"NicknameChanged","data":{"user_id":"3f2a9c1e-0000-4000-8000-000000000000","old_nick":"Alice","new_nick":"Alicia"}}"#,
+        )
+        .unwrap();
+        let WsEvent::NicknameChanged { user_id: changed, new_nick, .. } = event else {
+            panic!("unexpected event");
+        };
+        app_state.remember_nickname(&changed, &new_nick);
+        assert_eq!(app_state.user_label(&user_id), "Alicia");
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_message_is_ignored() {
+        let state = Arc::new(Mutex::new(AppState::new()));
+        let config_manager = UserConfigManager::new().unwrap();
+        let message_db = Arc::new(MessageDatabase::new_in_memory().await.unwrap());
+        let (ws_sender, _ws_rx) = tokio::sync::mpsc::unbounded_channel();
+        let color_display = ColorDisplay::new().with_enabled(false);
+
+        let message = Message::new_text(UserId::new(), "hello".to_string(), Some("Bob".to_string()));
+        for _ in 0..2 {
+            handle_ws_event_with_sender(
+                WsEvent::Message(message.clone()),
+                state.clone(),
+                &config_manager,
+                message_db.clone(),
+                &ws_sender,
+                &color_display,
+            )
+            .await
+            .unwrap();
+        }
+
+        let app_state = state.lock().await;
+        assert_eq!(app_state.messages.len(), 1);
+        assert!(app_state.seen_messages.contains(&message.id));
+        assert_eq!(message_db.get_message_count().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_message_batch_is_received_in_order() {
+        let state = Arc::new(Mutex::new(AppState::new()));
+        let config_manager = UserConfigManager::new().unwrap();
+        let message_db = Arc::new(MessageDatabase::new_in_memory().await.unwrap());
+        let (ws_sender, _ws_rx) = tokio::sync::mpsc::unbounded_channel();
+        let color_display = ColorDisplay::new().with_enabled(false);
+
+        let batch: Vec<Message> = ["one", "two", "three"]
+            .iter()
+            .map(|text| Message::new_text(UserId::new(), text.to_string(), None))
+            .collect();
+        let event: WsEvent = serde_json::from_value(serde_json::json!({ "event": "MessageBatch", "data": batch })).unwrap();
+        handle_ws_event_with_sender(event, state.clone(), &config_manager, message_db.clone(), &ws_sender, &color_display)
+            .await
+            .unwrap();
+
+        // 房间消息批次中的消息都归属到该房间
+        let batch: Vec<Message> = ["four", "five"]
+            .iter()
+            .map(|text| Message::new_text(UserId::new(), text.to_string(), None))
+            .collect();
+        let event: WsEvent = serde_json::from_value(serde_json::json!({
+            "event": "RoomMessageBatch",
+            "data": { "room_id": "room-1", "messages": batch }
+        }))
+        .unwrap();
+        handle_ws_event_with_sender(event, state.clone(), &config_manager, message_db.clone(), &ws_sender, &color_display)
+            .await
+            .unwrap();
+
+        let app_state = state.lock().await;
+        let texts: Vec<_> = app_state.messages.iter().filter_map(|m| m.get_text()).collect();
+        assert_eq!(texts, vec!["one", "two", "three", "four", "five"]);
+        assert!(app_state.messages[3..].iter().all(|m| m.room_id.as_deref() == Some("room-1")));
+    }
+
+    #[test]
+    fn test_unparseable_server_events_are_ignored() {
+        assert!(matches!(parse_ws_event(r#"{"event":"Ping"}"#), Some(WsEvent::Ping)));
+        for garbage in ["not json", "{}", r#"{"event":"FutureEvent","data":{}}"#, r#"{"event":"Message","data":42}"#] {
+            assert!(parse_ws_event(garbage).is_none(), "{}", garbage);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_room_joined_and_left_update_current_room() {
+        let state = Arc::new(Mutex::new(AppState::new()));
+        let config_manager = UserConfigManager::new().unwrap();
+        let message_db = Arc::new(MessageDatabase::new_in_memory().await.unwrap());
+        let (ws_sender, _ws_rx) = tokio::sync::mpsc::unbounded_channel();
+        let color_display = ColorDisplay::new().with_enabled(false);
+
+        let joined: WsEvent = serde_json::from_value(serde_json::json!({
+            "event": "RoomJoined",
+            "data": { "room": {
+                "id": "room-1", "name": "lobby", "owner": "owner", "created_at": "2026-01-01T00:00:00Z",
+                "member_count": 2, "description": null, "max_members": null, "is_member": true, "is_owner": false,
+            } },
+        }))
+        .unwrap();
+        handle_ws_event_with_sender(joined, state.clone(), &config_manager, message_db.clone(), &ws_sender, &color_display)
+            .await
+            .unwrap();
+        {
+            let app_state = state.lock().await;
+            assert_eq!(app_state.current_room_id.as_deref(), Some("room-1"));
+            assert_eq!(app_state.current_room_name.as_deref(), Some("lobby"));
+        }
+
+        // 离开其他房间不影响当前房间
+        for (room_id, expected) in [("room-2", Some("room-1")), ("room-1", None)] {
+            let left: WsEvent =
+                serde_json::from_value(serde_json::json!({ "event": "RoomLeft", "data": { "room_id": room_id } })).unwrap();
+            handle_ws_event_with_sender(left, state.clone(), &config_manager, message_db.clone(), &ws_sender, &color_display)
+                .await
+                .unwrap();
+            assert_eq!(state.lock().await.current_room_id.as_deref(), expected);
+        }
+    }
+}
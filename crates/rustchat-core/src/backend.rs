@@ -0,0 +1,128 @@
+use anyhow::{Context, Result};
+use sqlx::any::{AnyPoolOptions, AnyRow};
+use sqlx::{AnyPool, Row};
+use std::borrow::Cow;
+
+/// 数据库后端，由数据库地址的协议前缀决定
+///
+/// 消息、认证和上传记录都通过 `sqlx::Any` 连接池访问，SQL统一使用 `$1`、`$2` 形式的占位符
+/// （SQLite同样支持）。两种后端写法不同的地方集中在这里：
+/// - 时间以RFC3339文本保存，比较时SQLite用 `julianday()`，PostgreSQL转换为 `TIMESTAMPTZ`
+/// - 迁移中的 `INTEGER PRIMARY KEY AUTOINCREMENT` 和 `DATETIME` 在PostgreSQL上分别改为 `BIGSERIAL PRIMARY KEY` 和 `TIMESTAMPTZ`
+/// - `sqlx::Any` 无法读取SQLite的 `BOOLEAN` 列，查询布尔列时用 `CAST(列 AS INTEGER)` 再由 [`row_bool`] 读取
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DatabaseBackend {
+    /// `sqlite:` 地址，默认后端
+    Sqlite,
+    /// `postgres:` / `postgresql:` 地址，需要以 `postgres` 功能编译
+    Postgres,
+}
+
+impl DatabaseBackend {
+    /// 根据数据库地址的协议前缀识别后端
+    pub fn from_url(database_url: &str) -> Result<Self> {
+        let scheme = database_url
+            .split_once(':')
+            .map(|(scheme, _)| scheme.to_ascii_lowercase())
+            .unwrap_or_default();
+
+        match scheme.as_str() {
+            "sqlite" => Ok(Self::Sqlite),
+            "postgres" | "postgresql" => Ok(Self::Postgres),
+            _ => anyhow::bail!("无法识别的数据库地址协议: {:?}（支持 sqlite: 和 postgres:）", scheme),
+        }
+    }
+
+    /// 连接池所连接的后端
+    pub fn of(pool: &AnyPool) -> Self {
+        Self::from_url(pool.connect_options().database_url.as_str()).unwrap_or(Self::Sqlite)
+    }
+
+    /// 把保存RFC3339文本的列或参数转换为可以比较和排序的时间
+    pub fn timestamp(self, expr: &str) -> String {
+        match self {
+            Self::Sqlite => format!("julianday({})", expr),
+            Self::Postgres => format!("CAST({} AS TIMESTAMPTZ)", expr),
+        }
+    }
+
+    /// 当前时间，与 [`Self::timestamp`] 的结果可以直接比较
+    pub fn now(self) -> &'static str {
+        match self {
+            Self::Sqlite => "julianday('now')",
+            Self::Postgres => "CURRENT_TIMESTAMP",
+        }
+    }
+
+    /// 表示插入顺序的隐含列，只用于时间相同的行之间排序
+    pub fn insertion_order(self) -> &'static str {
+        match self {
+            Self::Sqlite => "rowid",
+            Self::Postgres => "ctid",
+        }
+    }
+
+    /// 把按SQLite编写的建表语句改写为当前后端的写法
+    pub fn translate_ddl(self, statement: &str) -> Cow<'_, str> {
+        match self {
+            Self::Sqlite => Cow::Borrowed(statement),
+            Self::Postgres => Cow::Owned(
+                statement
+                    .replace("INTEGER PRIMARY KEY AUTOINCREMENT", "BIGSERIAL PRIMARY KEY")
+                    .replace("DATETIME", "TIMESTAMPTZ"),
+            ),
+        }
+    }
+}
+
+/// 按数据库地址连接，`max_connections` 为None时使用sqlx的默认连接数
+///
+/// SQLite地址没有指定 `mode` 时以 `mode=rwc` 打开，数据库文件不存在时自动创建
+pub async fn connect_pool(database_url: &str, max_connections: Option<u32>) -> Result<AnyPool> {
+    let backend = DatabaseBackend::from_url(database_url)?;
+    if backend == DatabaseBackend::Postgres && !cfg!(feature = "postgres") {
+        anyhow::bail!("当前版本编译时未启用 postgres 功能，请使用 `--features postgres` 重新编译");
+    }
+    sqlx::any::install_default_drivers();
+
+    let mut url = database_url.to_string();
+    if backend == DatabaseBackend::Sqlite && !url.contains("mode=") && !url.contains(":memory:") {
+        url.push(if url.contains('?') { '&' } else { '?' });
+        url.push_str("mode=rwc");
+    }
+
+    let mut options = AnyPoolOptions::new();
+    if let Some(max_connections) = max_connections {
+        options = options.max_connections(max_connections);
+    }
+    options.connect(&url).await.context("Failed to connect to database")
+}
+
+/// 读取以 `CAST(列 AS INTEGER)` 查询的布尔列
+pub fn row_bool(row: &AnyRow, column: &str) -> bool {
+    row.get::<i64, _>(column) != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_backend_from_url() {
+        assert_eq!(DatabaseBackend::from_url("sqlite://chat.db").unwrap(), DatabaseBackend::Sqlite);
+        assert_eq!(DatabaseBackend::from_url("postgres://user@localhost/chat").unwrap(), DatabaseBackend::Postgres);
+        assert_eq!(DatabaseBackend::from_url("PostgreSQL://localhost/chat").unwrap(), DatabaseBackend::Postgres);
+        assert!(DatabaseBackend::from_url("mysql://localhost/chat").is_err());
+        assert!(DatabaseBackend::from_url("chat.db").is_err());
+
+        let pool = connect_pool("sqlite::memory:", Some(1)).await.unwrap();
+        assert_eq!(DatabaseBackend::of(&pool), DatabaseBackend::Sqlite);
+
+        let ddl = "CREATE TABLE t (id INTEGER PRIMARY KEY AUTOINCREMENT, at DATETIME DEFAULT CURRENT_TIMESTAMP)";
+        assert_eq!(DatabaseBackend::Sqlite.translate_ddl(ddl), ddl);
+        assert_eq!(
+            DatabaseBackend::Postgres.translate_ddl(ddl),
+            "CREATE TABLE t (id BIGSERIAL PRIMARY KEY, at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP)"
+        );
+    }
+}
@@ -1,7 +1,10 @@
 use anyhow::Result;
 use async_trait::async_trait;
+use rand::Rng;
 use rustchat_types::{Message, MessageType, UserId};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Instant;
 use tokio::sync::broadcast;
 use tracing::{info, warn};
 
@@ -29,6 +32,13 @@ pub enum BotAction {
     SystemMessage(String),
 }
 
+/// 机器人默认每分钟最多回复的消息数
+pub const DEFAULT_BOT_REPLIES_PER_MINUTE: u32 = 20;
+
+fn default_replies_per_minute() -> u32 {
+    DEFAULT_BOT_REPLIES_PER_MINUTE
+}
+
 /// 机器人配置
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BotConfig {
@@ -37,6 +47,9 @@ pub struct BotConfig {
     pub triggers: Vec<String>,
     pub description: String,
     pub priority: i32,
+    /// 每分钟最多回复的消息数（多条回复按条计算），0表示不限制
+    #[serde(default = "default_replies_per_minute")]
+    pub replies_per_minute: u32,
 }
 
 impl Default for BotConfig {
@@ -47,6 +60,47 @@ impl Default for BotConfig {
             triggers: vec![],
             description: "A chat bot".to_string(),
             priority: 0,
+            replies_per_minute: DEFAULT_BOT_REPLIES_PER_MINUTE,
+        }
+    }
+}
+
+impl BotResponse {
+    /// 响应会发送的消息条数，用于回复频率限制
+    fn message_count(&self) -> u32 {
+        match self {
+            BotResponse::Reply(_) | BotResponse::Action(_) => 1,
+            BotResponse::MultiReply(messages) => messages.len() as u32,
+            BotResponse::NoResponse => 0,
+        }
+    }
+}
+
+/// 机器人的回复额度（令牌桶）：容量为每分钟回复数，额度随时间匀速恢复
+#[derive(Debug)]
+struct ReplyBudget {
+    capacity: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl ReplyBudget {
+    fn new(replies_per_minute: u32, now: Instant) -> Self {
+        let capacity = replies_per_minute as f64;
+        Self { capacity, tokens: capacity, last_refill: now }
+    }
+
+    /// 尝试消耗指定条数的额度，额度不足时不消耗并返回false
+    fn try_spend(&mut self, count: u32, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.capacity / 60.0).min(self.capacity);
+        self.last_refill = now;
+
+        if self.tokens >= count as f64 {
+            self.tokens -= count as f64;
+            true
+        } else {
+            false
         }
     }
 }
@@ -57,12 +111,25 @@ pub trait Bot: Send + Sync {
     /// 获取机器人配置
     fn config(&self) -> BotConfig;
     
+    /// 启用或停用机器人，停用后 `should_handle` 应返回false
+    fn set_enabled(&mut self, enabled: bool);
+    
     /// 检查是否应该处理此消息
     fn should_handle(&self, message: &Message) -> bool;
     
     /// 处理消息并返回响应
     async fn handle_message(&self, message: &Message) -> Result<BotResponse>;
     
+    /// 用户上线时调用（可选），返回的响应与消息响应一样发送
+    async fn on_user_joined(&self, _user_id: &UserId, _nickname: Option<&str>) -> Result<BotResponse> {
+        Ok(BotResponse::NoResponse)
+    }
+    
+    /// 用户离线时调用（可选）
+    async fn on_user_left(&self, _user_id: &UserId) -> Result<BotResponse> {
+        Ok(BotResponse::NoResponse)
+    }
+    
     /// 初始化机器人（可选）
     async fn initialize(&mut self) -> Result<()> {
         Ok(())
@@ -80,6 +147,12 @@ pub struct EchoBot {
     user_id: UserId,
 }
 
+impl Default for EchoBot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl EchoBot {
     pub fn new() -> Self {
         Self {
@@ -89,6 +162,7 @@ impl EchoBot {
                 triggers: vec!["@echo".to_string(), "@回声".to_string()],
                 description: "回声机器人，会重复用户的消息".to_string(),
                 priority: 1,
+                replies_per_minute: DEFAULT_BOT_REPLIES_PER_MINUTE,
             },
             user_id: UserId::new(),
         }
@@ -105,6 +179,10 @@ impl Bot for EchoBot {
         self.config.clone()
     }
     
+    fn set_enabled(&mut self, enabled: bool) {
+        self.config.enabled = enabled;
+    }
+    
     fn should_handle(&self, message: &Message) -> bool {
         if !self.config.enabled {
             return false;
@@ -167,10 +245,223 @@ impl Bot for EchoBot {
     }
 }
 
+/// 单次最多掷的骰子数
+pub const MAX_DICE: u32 = 100;
+/// 骰子的最大面数
+pub const MAX_DICE_SIDES: u32 = 1000;
+
+/// 骰子表达式解析失败的原因
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum DiceError {
+    #[error("骰子表达式格式应为 NdM，例如 2d6")]
+    InvalidFormat,
+    #[error("骰子数量必须在 1 到 {MAX_DICE} 之间")]
+    InvalidCount,
+    #[error("骰子面数必须在 2 到 {MAX_DICE_SIDES} 之间")]
+    InvalidSides,
+}
+
+/// 骰子表达式，如 `2d6` 表示掷两个六面骰，省略数量时为一个
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiceRoll {
+    pub count: u32,
+    pub sides: u32,
+}
+
+impl DiceRoll {
+    /// 解析 `NdM` 格式的骰子表达式（忽略大小写和首尾空白）
+    pub fn parse(expression: &str) -> Result<Self, DiceError> {
+        let expression = expression.trim().to_lowercase();
+        let (count, sides) = expression.split_once('d').ok_or(DiceError::InvalidFormat)?;
+
+        let count = if count.is_empty() { 1 } else { parse_dice_number(count)? };
+        let sides = parse_dice_number(sides)?;
+
+        if !(1..=MAX_DICE).contains(&count) {
+            return Err(DiceError::InvalidCount);
+        }
+        if !(2..=MAX_DICE_SIDES).contains(&sides) {
+            return Err(DiceError::InvalidSides);
+        }
+        Ok(Self { count, sides })
+    }
+
+    /// 掷骰子，返回每个骰子的点数
+    pub fn roll<R: Rng + ?Sized>(&self, rng: &mut R) -> Vec<u32> {
+        (0..self.count).map(|_| rng.gen_range(1..=self.sides)).collect()
+    }
+}
+
+/// 只接受纯数字，避免 `+6` 之类的写法被当作合法数字；超出范围的大数按数量或面数错误处理
+fn parse_dice_number(text: &str) -> Result<u32, DiceError> {
+    if text.is_empty() || !text.chars().all(|c| c.is_ascii_digit()) {
+        return Err(DiceError::InvalidFormat);
+    }
+    Ok(text.parse().unwrap_or(u32::MAX))
+}
+
+/// 命令机器人，响应以 `!` 开头的聊天命令：`!time`、`!roll [NdM]`、`!flip`
+pub struct CommandBot {
+    config: BotConfig,
+}
+
+impl Default for CommandBot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CommandBot {
+    pub fn new() -> Self {
+        Self {
+            config: BotConfig {
+                name: "Command Bot".to_string(),
+                enabled: true,
+                triggers: vec!["!time".to_string(), "!roll".to_string(), "!flip".to_string()],
+                description: "命令机器人：!time 查看时间，!roll 2d6 掷骰子，!flip 抛硬币".to_string(),
+                priority: 1,
+                replies_per_minute: DEFAULT_BOT_REPLIES_PER_MINUTE,
+            },
+        }
+    }
+
+    /// 消息开头的命令及其参数，命令统一转为小写
+    fn parse_command(content: &str) -> Option<(String, &str)> {
+        let content = content.trim();
+        if !content.starts_with('!') {
+            return None;
+        }
+        let (command, args) = content.split_once(char::is_whitespace).unwrap_or((content, ""));
+        Some((command.to_lowercase(), args.trim()))
+    }
+
+    /// 生成命令的回复，不认识的命令返回None
+    fn reply(&self, command: &str, args: &str) -> Option<String> {
+        let reply = match command {
+            "!time" => {
+                let now = chrono::Local::now();
+                format!("🕒 当前时间: {}", now.format("%Y-%m-%d %H:%M:%S (%:z)"))
+            }
+            "!roll" => {
+                let expression = if args.is_empty() { "1d6" } else { args };
+                match DiceRoll::parse(expression) {
+                    Ok(dice) => format_dice_result(expression, &dice.roll(&mut rand::thread_rng())),
+                    Err(err) => format!("🎲 无法掷骰子 \"{}\": {}", expression, err),
+                }
+            }
+            "!flip" => {
+                let side = if rand::thread_rng().gen_bool(0.5) { "正面" } else { "反面" };
+                format!("🪙 抛硬币结果: {}", side)
+            }
+            _ => return None,
+        };
+        Some(reply)
+    }
+}
+
+/// 格式化掷骰结果，多个骰子时附带总和
+fn format_dice_result(expression: &str, rolls: &[u32]) -> String {
+    let expression = expression.trim().to_lowercase();
+    match rolls {
+        [single] => format!("🎲 {}: {}", expression, single),
+        _ => {
+            let total: u32 = rolls.iter().sum();
+            let parts: Vec<String> = rolls.iter().map(u32::to_string).collect();
+            format!("🎲 {}: {} = {}", expression, parts.join(" + "), total)
+        }
+    }
+}
+
+#[async_trait]
+impl Bot for CommandBot {
+    fn config(&self) -> BotConfig {
+        self.config.clone()
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.config.enabled = enabled;
+    }
+
+    fn should_handle(&self, message: &Message) -> bool {
+        if !self.config.enabled {
+            return false;
+        }
+
+        match &message.content {
+            MessageType::Text(content) => Self::parse_command(content)
+                .is_some_and(|(command, _)| self.config.triggers.contains(&command)),
+            _ => false,
+        }
+    }
+
+    async fn handle_message(&self, message: &Message) -> Result<BotResponse> {
+        let MessageType::Text(content) = &message.content else {
+            return Ok(BotResponse::NoResponse);
+        };
+
+        match Self::parse_command(content).and_then(|(command, args)| self.reply(&command, args)) {
+            Some(reply) => Ok(BotResponse::Reply(reply)),
+            None => Ok(BotResponse::NoResponse),
+        }
+    }
+}
+
+/// 欢迎机器人，用户上线时发送欢迎消息
+pub struct GreeterBot {
+    config: BotConfig,
+}
+
+impl Default for GreeterBot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GreeterBot {
+    pub fn new() -> Self {
+        Self {
+            config: BotConfig {
+                name: "Greeter Bot".to_string(),
+                enabled: true,
+                triggers: vec![],
+                description: "欢迎机器人，用户上线时发送欢迎消息".to_string(),
+                priority: 0,
+                replies_per_minute: DEFAULT_BOT_REPLIES_PER_MINUTE,
+            },
+        }
+    }
+}
+
+#[async_trait]
+impl Bot for GreeterBot {
+    fn config(&self) -> BotConfig {
+        self.config.clone()
+    }
+    
+    fn set_enabled(&mut self, enabled: bool) {
+        self.config.enabled = enabled;
+    }
+    
+    fn should_handle(&self, _message: &Message) -> bool {
+        false
+    }
+    
+    async fn handle_message(&self, _message: &Message) -> Result<BotResponse> {
+        Ok(BotResponse::NoResponse)
+    }
+    
+    async fn on_user_joined(&self, user_id: &UserId, nickname: Option<&str>) -> Result<BotResponse> {
+        let name = nickname.map(str::to_string).unwrap_or_else(|| format!("匿名用户 {}", user_id.short()));
+        Ok(BotResponse::Reply(format!("欢迎 {}!", name)))
+    }
+}
+
 /// 机器人管理器，负责管理所有机器人
 pub struct BotManager {
     bots: Vec<Box<dyn Bot>>,
     message_sender: broadcast::Sender<Message>,
+    /// 各机器人的回复额度（按机器人名称）
+    reply_budgets: std::sync::Mutex<HashMap<String, ReplyBudget>>,
 }
 
 impl BotManager {
@@ -178,6 +469,7 @@ impl BotManager {
         Self {
             bots: Vec::new(),
             message_sender,
+            reply_budgets: std::sync::Mutex::new(HashMap::new()),
         }
     }
     
@@ -197,43 +489,97 @@ impl BotManager {
     
     /// 处理消息，让所有相关机器人处理
     pub async fn handle_message(&self, message: &Message) -> Result<()> {
-        // 按优先级排序处理
         let mut bot_responses = Vec::new();
         
         for bot in &self.bots {
             if bot.should_handle(message) {
-                match bot.handle_message(message).await {
-                    Ok(response) => {
-                        let priority = bot.config().priority;
-                        bot_responses.push((priority, response));
-                    }
-                    Err(e) => {
-                        warn!("机器人 {} 处理消息失败: {}", bot.config().name, e);
-                    }
-                }
+                self.collect_response(bot.as_ref(), bot.handle_message(message).await, &mut bot_responses);
             }
         }
         
-        // 按优先级排序（高优先级先执行）
-        bot_responses.sort_by(|a, b| b.0.cmp(&a.0));
+        self.execute_responses(bot_responses).await
+    }
+    
+    /// 通知已启用的机器人有用户上线
+    pub async fn handle_user_joined(&self, user_id: &UserId, nickname: Option<&str>) -> Result<()> {
+        let mut bot_responses = Vec::new();
         
-        // 执行响应
-        for (_, response) in bot_responses {
-            self.execute_response(response).await?;
+        for bot in self.bots.iter().filter(|bot| bot.config().enabled) {
+            self.collect_response(bot.as_ref(), bot.on_user_joined(user_id, nickname).await, &mut bot_responses);
+        }
+        
+        self.execute_responses(bot_responses).await
+    }
+    
+    /// 通知已启用的机器人有用户离线
+    pub async fn handle_user_left(&self, user_id: &UserId) -> Result<()> {
+        let mut bot_responses = Vec::new();
+        
+        for bot in self.bots.iter().filter(|bot| bot.config().enabled) {
+            self.collect_response(bot.as_ref(), bot.on_user_left(user_id).await, &mut bot_responses);
+        }
+        
+        self.execute_responses(bot_responses).await
+    }
+    
+    /// 记录机器人的响应（经过回复频率限制），处理失败时只记录日志
+    fn collect_response(
+        &self,
+        bot: &dyn Bot,
+        result: Result<BotResponse>,
+        bot_responses: &mut Vec<(i32, String, BotResponse)>,
+    ) {
+        let config = bot.config();
+        match result {
+            Ok(response) => {
+                let response = self.limit_reply_rate(&config, response, Instant::now());
+                bot_responses.push((config.priority, config.name, response));
+            }
+            Err(e) => {
+                warn!("机器人 {} 处理事件失败: {}", config.name, e);
+            }
+        }
+    }
+    
+    /// 按优先级（高优先级先执行）执行收集到的响应
+    async fn execute_responses(&self, mut bot_responses: Vec<(i32, String, BotResponse)>) -> Result<()> {
+        bot_responses.sort_by_key(|(priority, _, _)| std::cmp::Reverse(*priority));
+        
+        for (_, bot_name, response) in bot_responses {
+            self.execute_response(&bot_name, response).await?;
         }
         
         Ok(())
     }
     
+    /// 超出回复额度的响应被替换为不响应，避免机器人被用来放大流量
+    fn limit_reply_rate(&self, config: &BotConfig, response: BotResponse, now: Instant) -> BotResponse {
+        let count = response.message_count();
+        if count == 0 || config.replies_per_minute == 0 {
+            return response;
+        }
+
+        let mut budgets = self.reply_budgets.lock().unwrap_or_else(|e| e.into_inner());
+        let budget = budgets
+            .entry(config.name.clone())
+            .or_insert_with(|| ReplyBudget::new(config.replies_per_minute, now));
+        if budget.try_spend(count, now) {
+            response
+        } else {
+            warn!("机器人 {} 回复过于频繁（每分钟最多 {} 条），忽略本次回复", config.name, config.replies_per_minute);
+            BotResponse::NoResponse
+        }
+    }
+    
     /// 执行机器人响应
-    async fn execute_response(&self, response: BotResponse) -> Result<()> {
+    async fn execute_response(&self, bot_name: &str, response: BotResponse) -> Result<()> {
         match response {
             BotResponse::Reply(content) => {
-                self.send_bot_message(content).await?;
+                self.send_bot_message(bot_name, content).await?;
             }
             BotResponse::MultiReply(messages) => {
                 for content in messages {
-                    self.send_bot_message(content).await?;
+                    self.send_bot_message(bot_name, content).await?;
                     // 稍微延迟，避免消息太快
                     tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
                 }
@@ -248,15 +594,11 @@ impl BotManager {
         Ok(())
     }
     
-    /// 发送机器人消息
-    async fn send_bot_message(&self, content: String) -> Result<()> {
-        let bot_message = Message::new_text(
-            UserId::new(), // 机器人消息使用特殊ID
-            content,
-            Some("Echo Bot".to_string()),
-        );
+    /// 以机器人的名称作为昵称发送机器人消息
+    async fn send_bot_message(&self, bot_name: &str, content: String) -> Result<()> {
+        let bot_message = Message::new_bot_text(content, bot_name);
         
-        if let Err(_) = self.message_sender.send(bot_message) {
+        if self.message_sender.send(bot_message).is_err() {
             warn!("发送机器人消息失败：没有活跃的接收者");
         }
         
@@ -275,6 +617,18 @@ impl BotManager {
         self.bots.iter().map(|bot| bot.config()).collect()
     }
     
+    /// 按名称启用或停用机器人，立即影响后续消息的处理；找不到机器人时返回false
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        match self.bots.iter_mut().find(|bot| bot.config().name == name) {
+            Some(bot) => {
+                bot.set_enabled(enabled);
+                info!("机器人 {} 已{}", name, if enabled { "启用" } else { "停用" });
+                true
+            }
+            None => false,
+        }
+    }
+    
     /// 关闭所有机器人
     pub async fn shutdown_all(&mut self) -> Result<()> {
         for bot in &mut self.bots {
@@ -283,3 +637,179 @@ impl BotManager {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_parse_dice() {
+        assert_eq!(DiceRoll::parse("2d6"), Ok(DiceRoll { count: 2, sides: 6 }));
+        assert_eq!(DiceRoll::parse(" D20 "), Ok(DiceRoll { count: 1, sides: 20 }));
+        assert_eq!(DiceRoll::parse("100d1000"), Ok(DiceRoll { count: 100, sides: 1000 }));
+
+        assert_eq!(DiceRoll::parse("abc"), Err(DiceError::InvalidFormat));
+        assert_eq!(DiceRoll::parse("2d"), Err(DiceError::InvalidFormat));
+        assert_eq!(DiceRoll::parse("2d+6"), Err(DiceError::InvalidFormat));
+        assert_eq!(DiceRoll::parse("-1d6"), Err(DiceError::InvalidFormat));
+        assert_eq!(DiceRoll::parse("0d6"), Err(DiceError::InvalidCount));
+        assert_eq!(DiceRoll::parse("99999999999d6"), Err(DiceError::InvalidCount));
+        assert_eq!(DiceRoll::parse("2d1"), Err(DiceError::InvalidSides));
+    }
+
+    #[test]
+    fn test_roll_stays_in_range() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let dice = DiceRoll::parse("50d6").unwrap();
+        let rolls = dice.roll(&mut rng);
+
+        assert_eq!(rolls.len(), 50);
+        assert!(rolls.iter().all(|value| (1..=6).contains(value)));
+        assert_eq!(format_dice_result("2D6", &[3, 5]), "🎲 2d6: 3 + 5 = 8");
+    }
+
+    #[tokio::test]
+    async fn test_command_bot_replies() {
+        let bot = CommandBot::new();
+        let text = |content: &str| Message::new_text(UserId::new(), content.to_string(), None);
+
+        assert!(bot.should_handle(&text("!ROLL 2d6")));
+        assert!(bot.should_handle(&text("!flip")));
+        assert!(!bot.should_handle(&text("say !flip")));
+        assert!(!bot.should_handle(&text("!unknown")));
+
+        match bot.handle_message(&text("!roll banana")).await.unwrap() {
+            BotResponse::Reply(reply) => assert!(reply.contains("NdM")),
+            other => panic!("unexpected response: {:?}", other),
+        }
+        match bot.handle_message(&text("!flip")).await.unwrap() {
+            BotResponse::Reply(reply) => assert!(reply.ends_with("正面") || reply.ends_with("反面")),
+            other => panic!("unexpected response: {:?}", other),
+        }
+    }
+
+    /// 对每条消息都回复指定条数的测试机器人
+    struct FloodBot {
+        config: BotConfig,
+        replies: usize,
+    }
+
+    #[async_trait]
+    impl Bot for FloodBot {
+        fn config(&self) -> BotConfig {
+            self.config.clone()
+        }
+
+        fn set_enabled(&mut self, enabled: bool) {
+            self.config.enabled = enabled;
+        }
+
+        fn should_handle(&self, _message: &Message) -> bool {
+            self.config.enabled
+        }
+
+        async fn handle_message(&self, _message: &Message) -> Result<BotResponse> {
+            Ok(match self.replies {
+                1 => BotResponse::Reply("pong".to_string()),
+                n => BotResponse::MultiReply(vec!["pong".to_string(); n]),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_flooded_bot_is_throttled() {
+        let (tx, mut rx) = broadcast::channel(64);
+        let mut manager = BotManager::new(tx);
+        let config = |name: &str| BotConfig {
+            name: name.to_string(),
+            replies_per_minute: 5,
+            ..BotConfig::default()
+        };
+        manager.register_bot(Box::new(FloodBot { config: config("Single"), replies: 1 }));
+        let message = Message::new_text(UserId::new(), "spam".to_string(), None);
+
+        for _ in 0..20 {
+            manager.handle_message(&message).await.unwrap();
+        }
+        let mut received = 0;
+        while rx.try_recv().is_ok() {
+            received += 1;
+        }
+        assert_eq!(received, 5);
+
+        // 多条回复按条消耗额度，剩余额度不足时整个回复被丢弃
+        let (tx, mut rx) = broadcast::channel(64);
+        let mut manager = BotManager::new(tx);
+        manager.register_bot(Box::new(FloodBot { config: config("Multi"), replies: 2 }));
+        for _ in 0..3 {
+            manager.handle_message(&message).await.unwrap();
+        }
+        let mut received = 0;
+        while rx.try_recv().is_ok() {
+            received += 1;
+        }
+        assert_eq!(received, 4);
+    }
+
+    #[test]
+    fn test_reply_budget_refills_over_time() {
+        let start = Instant::now();
+        let mut budget = ReplyBudget::new(60, start);
+
+        assert!(budget.try_spend(60, start));
+        assert!(!budget.try_spend(1, start));
+        // 每分钟60条即每秒恢复一条
+        assert!(budget.try_spend(1, start + std::time::Duration::from_secs(1)));
+        assert!(!budget.try_spend(1, start + std::time::Duration::from_secs(1)));
+        assert!(budget.try_spend(60, start + std::time::Duration::from_secs(600)));
+    }
+
+    #[tokio::test]
+    async fn test_disabled_bot_stops_responding() {
+        let (tx, mut rx) = broadcast::channel(16);
+        let mut manager = BotManager::new(tx);
+        manager.register_bot(Box::new(CommandBot::new()));
+        let flip = Message::new_text(UserId::new(), "!flip".to_string(), None);
+
+        manager.handle_message(&flip).await.unwrap();
+        let reply = rx.try_recv().unwrap();
+        assert_eq!(reply.from_nick.as_deref(), Some("Command Bot"));
+        assert!(reply.is_bot);
+
+        assert!(manager.set_enabled("Command Bot", false));
+        assert!(!manager.get_bots_info()[0].enabled);
+        manager.handle_message(&flip).await.unwrap();
+        assert!(rx.try_recv().is_err());
+
+        assert!(manager.set_enabled("Command Bot", true));
+        manager.handle_message(&flip).await.unwrap();
+        assert!(rx.try_recv().is_ok());
+
+        assert!(!manager.set_enabled("Missing Bot", true));
+    }
+
+    #[tokio::test]
+    async fn test_greeter_welcomes_joined_users() {
+        let (tx, mut rx) = broadcast::channel(16);
+        let mut manager = BotManager::new(tx);
+        manager.register_bot(Box::new(EchoBot::new()));
+        manager.register_bot(Box::new(GreeterBot::new()));
+        let user_id = UserId::new();
+
+        manager.handle_user_joined(&user_id, Some("alice")).await.unwrap();
+        let welcome = rx.try_recv().unwrap();
+        assert_eq!(welcome.get_text(), Some("欢迎 alice!"));
+        assert_eq!(welcome.from_nick.as_deref(), Some("Greeter Bot"));
+        assert!(welcome.is_bot);
+        // 其他机器人默认忽略上下线事件
+        assert!(rx.try_recv().is_err());
+
+        manager.handle_user_left(&user_id).await.unwrap();
+        assert!(rx.try_recv().is_err());
+
+        assert!(manager.set_enabled("Greeter Bot", false));
+        manager.handle_user_joined(&user_id, Some("alice")).await.unwrap();
+        assert!(rx.try_recv().is_err());
+    }
+}
@@ -1,9 +1,19 @@
+use crate::backend::{connect_pool, row_bool, DatabaseBackend};
+use crate::dead_letter::DeadLetterLog;
+use crate::migrations::{current_version, run_migrations, Migration};
 use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
 use rustchat_types::{Message, MessageId, MessageType, UserId};
-use sqlx::{Row, SqlitePool};
-use std::path::PathBuf;
-use tracing::{debug, error};
+use sqlx::any::{AnyArguments, AnyRow};
+use sqlx::query::Query;
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{Any, AnyConnection, AnyPool, Row};
+use serde::Serialize;
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tracing::{debug, error, info, warn};
 
 /// 数据库消息记录结构
 #[derive(Debug, Clone)]
@@ -16,6 +26,146 @@ pub struct MessageRecord {
     pub from_nickname: Option<String>,
     pub room_id: Option<String>,
     pub additional_data: Option<String>,
+    pub received_at: Option<DateTime<Utc>>,
+    pub client_timestamp: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// 被提及用户ID的JSON数组，没有提及时为None
+    pub mentions: Option<String>,
+    pub is_bot: bool,
+}
+
+/// 查询消息时选取的列，布尔列转换为整数后读取（见 [`row_bool`]）
+const MESSAGE_COLUMNS: &str =
+    "id, from_user_id, content_type, content_data, timestamp, from_nickname, room_id, additional_data, received_at, client_timestamp, expires_at, mentions, CAST(is_bot AS INTEGER) AS is_bot";
+
+/// 写入消息时的列，顺序与 [`MessageRecord::bind`] 的绑定顺序一致
+const INSERT_COLUMNS: &str =
+    "id, from_user_id, content_type, content_data, timestamp, from_nickname, room_id, additional_data, received_at, client_timestamp, expires_at, mentions, is_bot";
+
+/// 与 `INSERT_COLUMNS` 对应的占位符
+const INSERT_PLACEHOLDERS: &str = "$1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13";
+
+/// 删除所属消息已不存在的表情回应
+const DELETE_ORPHANED_REACTIONS: &str =
+    "DELETE FROM message_reactions WHERE message_id NOT IN (SELECT id FROM messages)";
+
+
+/// 消息数据库在 `schema_migrations` 中的范围名
+const MIGRATION_SCOPE: &str = "messages";
+
+/// 消息数据库的结构迁移，新的列或表以更高的版本追加在末尾
+const MESSAGE_MIGRATIONS: &[Migration] = &[Migration {
+    version: 1,
+    description: "消息、房间已读标记和表情回应表",
+    statements: &[
+        r#"
+        CREATE TABLE IF NOT EXISTS messages (
+            id TEXT PRIMARY KEY,
+            from_user_id TEXT NOT NULL,
+            content_type TEXT NOT NULL,
+            content_data TEXT NOT NULL,
+            timestamp TEXT NOT NULL,
+            from_nickname TEXT,
+            room_id TEXT,
+            additional_data TEXT,
+            received_at TEXT,
+            expires_at TEXT,
+            mentions TEXT,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+        "CREATE INDEX IF NOT EXISTS idx_messages_timestamp ON messages(timestamp DESC)",
+        "CREATE INDEX IF NOT EXISTS idx_messages_user ON messages(from_user_id)",
+        "CREATE INDEX IF NOT EXISTS idx_messages_room ON messages(room_id)",
+        // 每个用户在每个房间读到的最新消息
+        r#"
+        CREATE TABLE IF NOT EXISTS room_read_markers (
+            room_id TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            message_id TEXT NOT NULL,
+            last_read_at TEXT NOT NULL,
+            PRIMARY KEY (room_id, user_id)
+        )
+        "#,
+        // 同一用户对同一消息的同一表情只记录一次
+        r#"
+        CREATE TABLE IF NOT EXISTS message_reactions (
+            message_id TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            emoji TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            UNIQUE (message_id, user_id, emoji)
+        )
+        "#,
+    ],
+}, Migration {
+    version: 2,
+    description: "标记机器人发送的消息",
+    statements: &["ALTER TABLE messages ADD COLUMN is_bot BOOLEAN NOT NULL DEFAULT FALSE"],
+}, Migration {
+    version: 3,
+    description: "单独保存客户端声明的发送时间",
+    statements: &["ALTER TABLE messages ADD COLUMN client_timestamp TEXT"],
+}];
+
+impl MessageRecord {
+    /// 从查询结果行构建记录
+    fn from_row(row: &AnyRow) -> Result<Self> {
+        Ok(Self {
+            id: row.get("id"),
+            from_user_id: row.get("from_user_id"),
+            content_type: row.get("content_type"),
+            content_data: row.get("content_data"),
+            timestamp: parse_timestamp(&row.get::<String, _>("timestamp"))?,
+            from_nickname: row.get("from_nickname"),
+            room_id: row.get("room_id"),
+            additional_data: row.get("additional_data"),
+            received_at: row
+                .get::<Option<String>, _>("received_at")
+                .map(|value| parse_timestamp(&value))
+                .transpose()?,
+            client_timestamp: row
+                .get::<Option<String>, _>("client_timestamp")
+                .map(|value| parse_timestamp(&value))
+                .transpose()?,
+            expires_at: row
+                .get::<Option<String>, _>("expires_at")
+                .map(|value| parse_timestamp(&value))
+                .transpose()?,
+            mentions: row.get("mentions"),
+            is_bot: row_bool(row, "is_bot"),
+        })
+    }
+
+    /// 按 `INSERT_COLUMNS` 的顺序绑定各列的值
+    fn bind<'q>(&'q self, query: Query<'q, Any, AnyArguments<'q>>) -> Query<'q, Any, AnyArguments<'q>> {
+        query
+            .bind(&self.id)
+            .bind(&self.from_user_id)
+            .bind(&self.content_type)
+            .bind(&self.content_data)
+            .bind(self.timestamp.to_rfc3339())
+            .bind(&self.from_nickname)
+            .bind(&self.room_id)
+            .bind(&self.additional_data)
+            .bind(self.received_at.map(|received_at| received_at.to_rfc3339()))
+            .bind(self.client_timestamp.map(|client_timestamp| client_timestamp.to_rfc3339()))
+            .bind(self.expires_at.map(|expires_at| expires_at.to_rfc3339()))
+            .bind(&self.mentions)
+            .bind(self.is_bot)
+    }
+}
+
+/// 排除已过期消息的查询条件
+fn not_expired(backend: DatabaseBackend) -> String {
+    format!("(expires_at IS NULL OR {} > {})", backend.timestamp("expires_at"), backend.now())
+}
+
+/// 解析RFC3339格式的时间戳
+fn parse_timestamp(value: &str) -> Result<DateTime<Utc>> {
+    Ok(DateTime::parse_from_rfc3339(value)
+        .context("Invalid timestamp format")?
+        .with_timezone(&Utc))
 }
 
 impl From<&Message> for MessageRecord {
@@ -23,6 +173,8 @@ impl From<&Message> for MessageRecord {
         let (content_type, content_data) = match &msg.content {
             MessageType::Text(text) => ("text".to_string(), text.clone()),
             MessageType::System(text) => ("system".to_string(), text.clone()),
+            MessageType::Action(text) => ("action".to_string(), text.clone()),
+            MessageType::Markdown(source) => ("markdown".to_string(), source.clone()),
             MessageType::NickChange { old_nick, new_nick } => (
                 "nick_change".to_string(),
                 serde_json::json!({
@@ -31,7 +183,7 @@ impl From<&Message> for MessageRecord {
                 })
                 .to_string(),
             ),
-        };        // 从additional_data中提取room_id
+        };        // 房间ID单独成列，按房间查询时无需解析additional_data
         let room_id = msg.room_id.clone();
 
         Self {
@@ -43,6 +195,12 @@ impl From<&Message> for MessageRecord {
             from_nickname: msg.from_nick.clone(),
             room_id,
             additional_data: msg.additional_data.as_ref().map(|data| data.to_string()),
+            received_at: msg.received_at,
+            client_timestamp: msg.client_timestamp,
+            expires_at: msg.expires_at,
+            mentions: (!msg.mentions.is_empty())
+                .then(|| serde_json::to_string(&msg.mentions).unwrap_or_default()),
+            is_bot: msg.is_bot,
         }
     }
 }
@@ -57,6 +215,8 @@ impl TryFrom<MessageRecord> for Message {
         let content = match record.content_type.as_str() {
             "text" => MessageType::Text(record.content_data),
             "system" => MessageType::System(record.content_data),
+            "action" => MessageType::Action(record.content_data),
+            "markdown" => MessageType::Markdown(record.content_data),
             "nick_change" => {
                 let data: serde_json::Value = serde_json::from_str(&record.content_data)?;
                 MessageType::NickChange {
@@ -80,19 +240,164 @@ impl TryFrom<MessageRecord> for Message {
             room_id: record.room_id,
             additional_data: record.additional_data.as_ref()
                 .and_then(|s| serde_json::from_str(s).ok()),
+            received_at: record.received_at,
+            client_timestamp: record.client_timestamp,
+            expires_at: record.expires_at,
+            mentions: record.mentions.as_deref()
+                .and_then(|s| serde_json::from_str(s).ok())
+                .unwrap_or_default(),
+            is_bot: record.is_bot,
         })
     }
 }
 
+/// 批量写入消息的结果
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct BatchInsertResult {
+    /// 新写入的消息数
+    pub inserted: u64,
+    /// ID已存在而跳过的消息数
+    pub skipped: u64,
+}
+
 /// 消息历史数据库管理器
+/// 最近消息的接收延迟统计（接收时间 - 消息时间戳）
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ReceiptLatencyStats {
+    /// 参与统计的消息数
+    pub samples: usize,
+    /// 平均差值（毫秒）
+    pub average_ms: i64,
+    /// 最小差值（毫秒，负值表示客户端时钟超前）
+    pub min_ms: i64,
+    /// 最大差值（毫秒）
+    pub max_ms: i64,
+}
+
+/// 用户的消息统计
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct UserMessageStats {
+    pub total_messages: i64,
+    pub first_message_at: Option<DateTime<Utc>>,
+    pub last_message_at: Option<DateTime<Utc>>,
+    /// 按消息类型（text、action、markdown等）统计的数量
+    pub by_type: HashMap<String, i64>,
+}
+
+/// 消息上某个表情的回应汇总
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReactionSummary {
+    pub emoji: String,
+    /// 使用该表情回应的用户（按回应时间排序）
+    pub users: Vec<UserId>,
+}
+
+impl ReactionSummary {
+    /// 回应数量
+    pub fn count(&self) -> usize {
+        self.users.len()
+    }
+}
+
 pub struct MessageDatabase {
-    pool: SqlitePool,
+    pool: AnyPool,
+    backend: DatabaseBackend,
+    /// 写入失败的消息保存到死信日志，内存数据库和PostgreSQL默认不启用
+    dead_letters: Option<DeadLetterLog>,
+    /// 获取最近消息的查询，只构建一次
+    ///
+    /// sqlx 按SQL文本缓存每个连接上的预编译语句，复用同一个字符串省去每次调用时的拼接。
+    /// 查询沿 `idx_messages_timestamp` 倒序取前N条再在内存中反转，避免为正序分页先统计总数（对比见 `bench_recent_messages_query`）
+    recent_messages_query: String,
 }
 
 impl MessageDatabase {    /// 创建新的数据库管理器
+    ///
+    /// 依次尝试当前目录、用户主目录和临时目录，使用第一个可写的位置
     pub async fn new() -> Result<Self> {
-        let db_path = Self::get_database_path()?;
-        
+        let (db, db_path) = Self::open_first_available(&Self::candidate_database_paths()).await?;
+        info!("消息数据库位置: {:?}", db_path);
+        Ok(db)
+    }
+
+    /// 数据库文件的候选路径，按优先级排列
+    fn candidate_database_paths() -> Vec<PathBuf> {
+        let mut candidates = Vec::new();
+
+        // 开发环境：在项目目录下创建数据库
+        if let Ok(current_dir) = std::env::current_dir() {
+            candidates.push(current_dir.join(".rustchat").join("messages.db"));
+        }
+
+        // 生产环境：在用户主目录下创建数据库
+        if let Some(home_dir) = dirs::home_dir() {
+            candidates.push(home_dir.join(".rustchat").join("messages.db"));
+        }
+
+        // 最后退回到临时目录
+        candidates.push(std::env::temp_dir().join("rustchat").join("messages.db"));
+
+        candidates
+    }
+
+    /// 打开数据库地址（如 `DATABASE_URL`）指定的数据库，支持 `sqlite:` 和 `postgres:` 地址
+    ///
+    /// PostgreSQL需要以 `postgres` 功能编译；它没有本地文件，默认不启用死信日志，可用 [`Self::with_dead_letter_log`] 指定
+    pub async fn connect(database_url: &str) -> Result<Self> {
+        let dead_letters = match DatabaseBackend::from_url(database_url)? {
+            DatabaseBackend::Sqlite => {
+                let options = SqliteConnectOptions::from_str(database_url).context("Invalid SQLite database URL")?;
+                // 文件数据库的死信日志与数据库文件放在同一目录
+                let filename = options.get_filename().to_path_buf();
+                info!("消息数据库位置: {:?}", filename);
+                (filename.as_path() != Path::new(":memory:")).then(|| {
+                    DeadLetterLog::new(filename.with_file_name("dead_letters.jsonl"), DeadLetterLog::DEFAULT_MAX_ENTRIES)
+                })
+            }
+            DatabaseBackend::Postgres => {
+                info!("消息数据库: PostgreSQL");
+                None
+            }
+        };
+
+        let pool = connect_pool(database_url, None).await?;
+        Self::from_pool(pool, dead_letters).await
+    }
+
+    /// 使用已连接的连接池，执行尚未应用的迁移
+    async fn from_pool(pool: AnyPool, dead_letters: Option<DeadLetterLog>) -> Result<Self> {
+        let backend = DatabaseBackend::of(&pool);
+        let recent_messages_query = format!(
+            "SELECT {} FROM messages WHERE {} ORDER BY timestamp DESC LIMIT $1",
+            MESSAGE_COLUMNS,
+            not_expired(backend)
+        );
+        let db = Self { pool, backend, dead_letters, recent_messages_query };
+        db.init_tables().await?;
+        Ok(db)
+    }
+
+    /// 按顺序尝试打开候选路径，返回第一个成功打开的数据库及其路径
+    async fn open_first_available(candidates: &[PathBuf]) -> Result<(Self, PathBuf)> {
+        let mut last_error = None;
+
+        for db_path in candidates {
+            match Self::open(db_path).await {
+                Ok(db) => return Ok((db, db_path.clone())),
+                Err(err) => {
+                    warn!("无法使用数据库路径 {:?}: {:#}", db_path, err);
+                    last_error = Some(err);
+                }
+            }
+        }
+
+        Err(last_error
+            .unwrap_or_else(|| anyhow::anyhow!("没有可用的数据库路径"))
+            .context("所有数据库路径均不可用"))
+    }
+
+    /// 打开指定路径的数据库文件，不存在时创建
+    async fn open(db_path: &Path) -> Result<Self> {
         // 确保数据库目录存在
         if let Some(parent) = db_path.parent() {
             std::fs::create_dir_all(parent)
@@ -100,101 +405,191 @@ impl MessageDatabase {    /// 创建新的数据库管理器
         }
 
         let database_url = format!("sqlite://{}?mode=rwc", db_path.to_string_lossy());
-        let pool = SqlitePool::connect(&database_url)
+        let pool = connect_pool(&database_url, None).await?;
+
+        // 死信日志与数据库文件放在同一目录
+        let dead_letters = DeadLetterLog::new(
+            db_path.with_file_name("dead_letters.jsonl"),
+            DeadLetterLog::DEFAULT_MAX_ENTRIES,
+        );
+        Self::from_pool(pool, Some(dead_letters)).await
+    }
+
+    /// 创建内存数据库（用于测试，数据不会持久化）
+    pub async fn new_in_memory() -> Result<Self> {
+        // 内存数据库按连接隔离，限制为单连接以保证数据可见
+        let pool = connect_pool("sqlite::memory:", Some(1))
             .await
-            .context("Failed to connect to database")?;
+            .context("Failed to connect to memory database")?;
 
-        let db = Self { pool };
-        db.init_tables().await?;
-        
-        Ok(db)
-    }    /// 获取数据库文件路径
-    fn get_database_path() -> Result<PathBuf> {
-        // 开发环境：在项目目录下创建数据库
-        if let Ok(current_dir) = std::env::current_dir() {
-            let db_path = current_dir.join(".rustchat").join("messages.db");
-            return Ok(db_path);
-        }
-        
-        // 生产环境：在用户主目录下创建数据库
-        let home_dir = dirs::home_dir().context("无法获取用户主目录")?;
-        Ok(home_dir.join(".rustchat").join("messages.db"))
-    }    /// 初始化数据库表
+        Self::from_pool(pool, None).await
+    }
+
+    /// 使用指定的死信日志（替换默认位置）
+    pub fn with_dead_letter_log(mut self, path: impl Into<PathBuf>, max_entries: usize) -> Self {
+        self.dead_letters = Some(DeadLetterLog::new(path, max_entries));
+        self
+    }
+
+    /// 当前使用的死信日志
+    pub fn dead_letter_log(&self) -> Option<&DeadLetterLog> {
+        self.dead_letters.as_ref()
+    }
+
+    /// 初始化数据库表
     async fn init_tables(&self) -> Result<()> {
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS messages (
-                id TEXT PRIMARY KEY,
-                from_user_id TEXT NOT NULL,
-                content_type TEXT NOT NULL,
-                content_data TEXT NOT NULL,
-                timestamp TEXT NOT NULL,
-                from_nickname TEXT,
-                room_id TEXT,
-                additional_data TEXT,
-                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
-            )
-            "#,
-        )
-        .execute(&self.pool)
-        .await
-        .context("Failed to create messages table")?;
+        // 迁移框架引入之前创建的旧表先补齐到第1版的结构，这类旧表只可能出现在SQLite中
+        if self.backend == DatabaseBackend::Sqlite
+            && self.table_exists("messages").await?
+            && !self.migrations_recorded().await?
+        {
+            self.upgrade_legacy_messages_table().await?;
+        }
 
-        // 创建索引以提高查询性能
-        sqlx::query(
-            r#"
-            CREATE INDEX IF NOT EXISTS idx_messages_timestamp 
-            ON messages(timestamp DESC)
-            "#,
-        )
-        .execute(&self.pool)
-        .await
-        .context("Failed to create timestamp index")?;        sqlx::query(
-            r#"
-            CREATE INDEX IF NOT EXISTS idx_messages_user 
-            ON messages(from_user_id)
-            "#,
-        )
-        .execute(&self.pool)
-        .await
-        .context("Failed to create user index")?;
+        run_migrations(&self.pool, MIGRATION_SCOPE, MESSAGE_MIGRATIONS).await?;
+        Ok(())
+    }
+
+    /// 检查SQLite数据库中表是否存在
+    async fn table_exists(&self, table: &str) -> Result<bool> {
+        let row = sqlx::query("SELECT COUNT(*) AS count FROM sqlite_master WHERE type = 'table' AND name = $1")
+            .bind(table)
+            .fetch_one(&self.pool)
+            .await
+            .context("Failed to inspect database schema")?;
+        Ok(row.get::<i64, _>("count") > 0)
+    }
+
+    /// 消息数据库是否已经由迁移框架管理
+    async fn migrations_recorded(&self) -> Result<bool> {
+        Ok(self.table_exists("schema_migrations").await?
+            && current_version(&self.pool, MIGRATION_SCOPE).await? > 0)
+    }
+
+    /// 为旧版本的messages表补充后来增加的列，并把房间ID从additional_data回填到room_id列
+    async fn upgrade_legacy_messages_table(&self) -> Result<()> {
+        self.ensure_column("room_id", "TEXT").await?;
+        self.ensure_column("received_at", "TEXT").await?;
+        self.ensure_column("expires_at", "TEXT").await?;
+        self.ensure_column("mentions", "TEXT").await?;
 
-        // 创建房间索引
         sqlx::query(
             r#"
-            CREATE INDEX IF NOT EXISTS idx_messages_room 
-            ON messages(room_id)
+            UPDATE messages
+            SET room_id = json_extract(additional_data, '$.room_id')
+            WHERE room_id IS NULL
+              AND json_valid(additional_data)
+              AND json_extract(additional_data, '$.room_id') IS NOT NULL
             "#,
         )
         .execute(&self.pool)
         .await
-        .context("Failed to create room index")?;
+        .context("Failed to backfill room_id column")?;
+
+        Ok(())
+    }
+
+    /// 为已存在的messages表补充缺失的列
+    async fn ensure_column(&self, column: &str, definition: &str) -> Result<()> {
+        let columns = sqlx::query("PRAGMA table_info(messages)")
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to inspect messages table")?;
+
+        let exists = columns
+            .iter()
+            .any(|row| row.get::<String, _>("name") == column);
+        if !exists {
+            sqlx::query(&format!("ALTER TABLE messages ADD COLUMN {} {}", column, definition))
+                .execute(&self.pool)
+                .await
+                .with_context(|| format!("Failed to add column {}", column))?;
+            info!("已为messages表添加列: {}", column);
+        }
 
         Ok(())
-    }    /// 保存消息到数据库
+    }
+
+    /// 保存消息到数据库
+    ///
+    /// 写入失败时消息会被追加到死信日志，之后可通过 [`Self::replay_dead_letters`] 重新写入
     pub async fn save_message(&self, message: &Message) -> Result<()> {
+        let result = self.insert_message(message).await;
+
+        if let (Err(_), Some(dead_letters)) = (&result, &self.dead_letters) {
+            match dead_letters.append(message).await {
+                Ok(()) => warn!("消息 {} 已写入死信日志 {:?}", message.id, dead_letters.path()),
+                Err(e) => error!("写入死信日志失败，消息 {} 丢失: {:#}", message.id, e),
+            }
+        }
+
+        result
+    }
+
+    /// 在同一个事务中写入多条消息，返回写入和跳过的数量
+    ///
+    /// 保留消息原有的ID和时间戳，ID已存在的消息被跳过而不是覆盖，适合导入其他系统的聊天记录；
+    /// 所有消息只提交一次事务，连续写入大量消息时比逐条调用 [`Self::save_message`] 快得多。
+    /// 任何一条写入失败时整批回滚，且不会写入死信日志
+    pub async fn save_messages_batch<M: Borrow<Message>>(&self, messages: &[M]) -> Result<BatchInsertResult> {
+        let records: Vec<MessageRecord> = messages.iter().map(|message| MessageRecord::from(message.borrow())).collect();
+        let sql = format!("INSERT INTO messages ({}) VALUES ({}) ON CONFLICT (id) DO NOTHING", INSERT_COLUMNS, INSERT_PLACEHOLDERS);
+
+        let mut tx = self.pool.begin().await.context("Failed to begin transaction")?;
+        let mut inserted = 0;
+        for record in &records {
+            inserted += record
+                .bind(sqlx::query(&sql))
+                .execute(&mut *tx)
+                .await
+                .with_context(|| format!("Failed to save message {}", record.id))?
+                .rows_affected();
+        }
+        tx.commit().await.context("Failed to commit transaction")?;
+
+        let result = BatchInsertResult { inserted, skipped: records.len() as u64 - inserted };
+        debug!("批量写入消息: 写入 {} 条，跳过 {} 条", result.inserted, result.skipped);
+        Ok(result)
+    }
+
+    /// 重新写入死信日志中的消息，成功写入的消息从日志中移除
+    ///
+    /// 返回成功重放的消息数量
+    pub async fn replay_dead_letters(&self) -> Result<usize> {
+        let Some(dead_letters) = &self.dead_letters else {
+            return Ok(0);
+        };
+
+        let (replayed, remaining) = dead_letters
+            .replay(|message| async move { self.insert_message(&message).await })
+            .await?;
+        if replayed > 0 || remaining > 0 {
+            info!("死信日志重放完成: 成功 {} 条，剩余 {} 条", replayed, remaining);
+        }
+
+        Ok(replayed)
+    }
+
+    /// 写入单条消息，不经过死信日志
+    async fn insert_message(&self, message: &Message) -> Result<()> {
         let record = MessageRecord::from(message);
 
         // 添加调试信息
         debug!("Saving message to database: id={}, from_user_id={}, content_type={}, content_data={}, timestamp={}, from_nickname={:?}, room_id={:?}", 
             record.id, record.from_user_id, record.content_type, record.content_data, record.timestamp.to_rfc3339(), record.from_nickname, record.room_id);
 
-        let result = sqlx::query(
-            r#"
-            INSERT OR REPLACE INTO messages (id, from_user_id, content_type, content_data, timestamp, from_nickname, room_id, additional_data)
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
-            "#,
-        )
-        .bind(&record.id)
-        .bind(&record.from_user_id)
-        .bind(&record.content_type)
-        .bind(&record.content_data)
-        .bind(record.timestamp.to_rfc3339())
-        .bind(&record.from_nickname)
-        .bind(&record.room_id)
-        .bind(&record.additional_data)
-        .execute(&self.pool)
-        .await;        match result {
+        // 相同ID的消息覆盖原有内容
+        let updates = INSERT_COLUMNS
+            .split(", ")
+            .filter(|column| *column != "id")
+            .map(|column| format!("{0} = excluded.{0}", column))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let sql = format!(
+            "INSERT INTO messages ({}) VALUES ({}) ON CONFLICT (id) DO UPDATE SET {}",
+            INSERT_COLUMNS, INSERT_PLACEHOLDERS, updates
+        );
+        let result = record.bind(sqlx::query(&sql)).execute(&self.pool).await;        match result {
             Ok(_) => {
                 debug!("Message saved successfully to database");
                 Ok(())
@@ -206,31 +601,15 @@ impl MessageDatabase {    /// 创建新的数据库管理器
         }
     }    /// 获取最近的消息（默认100条）
     pub async fn get_recent_messages(&self, limit: i64) -> Result<Vec<Message>> {
-        let rows = sqlx::query(
-            r#"
-            SELECT id, from_user_id, content_type, content_data, timestamp, from_nickname, room_id, additional_data
-            FROM messages
-            ORDER BY timestamp DESC
-            LIMIT ?
-            "#,
-        )
-        .bind(limit)
-        .fetch_all(&self.pool)
-        .await
-        .context("Failed to fetch recent messages")?;        let mut messages = Vec::new();
+        let rows = sqlx::query(&self.recent_messages_query)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to fetch recent messages")?;
+
+        let mut messages = Vec::with_capacity(rows.len());
         for row in rows {
-            let record = MessageRecord {
-                id: row.get("id"),
-                from_user_id: row.get("from_user_id"),
-                content_type: row.get("content_type"),
-                content_data: row.get("content_data"),
-                timestamp: DateTime::parse_from_rfc3339(&row.get::<String, _>("timestamp"))
-                    .context("Invalid timestamp format")?
-                    .with_timezone(&Utc),
-                from_nickname: row.get("from_nickname"),
-                room_id: row.get("room_id"),
-                additional_data: row.get("additional_data"),
-            };
+            let record = MessageRecord::from_row(&row)?;
 
             match Message::try_from(record) {
                 Ok(message) => messages.push(message),
@@ -246,15 +625,10 @@ impl MessageDatabase {    /// 创建新的数据库管理器
         Ok(messages)
     }    /// 获取指定用户的消息历史
     pub async fn get_user_messages(&self, user_id: &UserId, limit: i64) -> Result<Vec<Message>> {
-        let rows = sqlx::query(
-            r#"
-            SELECT id, from_user_id, content_type, content_data, timestamp, from_nickname, room_id, additional_data
-            FROM messages
-            WHERE from_user_id = ?
-            ORDER BY timestamp DESC
-            LIMIT ?
-            "#,
-        )
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM messages WHERE from_user_id = $1 AND {} ORDER BY timestamp DESC LIMIT $2",
+            MESSAGE_COLUMNS, self.not_expired()
+        ))
         .bind(user_id.to_string())
         .bind(limit)
         .fetch_all(&self.pool)
@@ -263,18 +637,7 @@ impl MessageDatabase {    /// 创建新的数据库管理器
 
         let mut messages = Vec::new();
         for row in rows {
-            let record = MessageRecord {
-                id: row.get("id"),
-                from_user_id: row.get("from_user_id"),
-                content_type: row.get("content_type"),
-                content_data: row.get("content_data"),
-                timestamp: DateTime::parse_from_rfc3339(&row.get::<String, _>("timestamp"))
-                    .context("Invalid timestamp format")?
-                    .with_timezone(&Utc),
-                from_nickname: row.get("from_nickname"),
-                room_id: row.get("room_id"),
-                additional_data: row.get("additional_data"),
-            };
+            let record = MessageRecord::from_row(&row)?;
 
             match Message::try_from(record) {
                 Ok(message) => messages.push(message),
@@ -288,17 +651,71 @@ impl MessageDatabase {    /// 创建新的数据库管理器
         Ok(messages)
     }
 
-    /// 获取房间消息
+    /// 分页获取全局消息（不属于任何房间），返回早于 `before` 的最近N条，按时间正序排列
+    ///
+    /// `before` 为None时从最新的消息开始
+    pub async fn get_messages_before(&self, before: Option<DateTime<Utc>>, limit: i64) -> Result<Vec<Message>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM messages WHERE room_id IS NULL AND {} AND ($1 IS NULL OR {} < {}) ORDER BY timestamp DESC LIMIT $2",
+            MESSAGE_COLUMNS, self.not_expired(), self.backend.timestamp("timestamp"), self.backend.timestamp("$1")
+        ))
+        .bind(before.map(|before| before.to_rfc3339()))
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch messages")?;
+
+        let mut messages = Vec::new();
+        for row in rows {
+            let record = MessageRecord::from_row(&row)?;
+
+            match Message::try_from(record) {
+                Ok(message) => messages.push(message),
+                Err(e) => {
+                    eprintln!("Failed to parse message from database: {}", e);
+                    continue;
+                }
+            }
+        }
+
+        messages.reverse();
+        Ok(messages)
+    }
+
+    /// 获取服务器在 `since` 之后接收的全局消息，按接收时间正序排列，最多N条（用于断线重连后补发）
+    pub async fn get_messages_received_since(&self, since: DateTime<Utc>, limit: i64) -> Result<Vec<Message>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {0} FROM messages WHERE room_id IS NULL AND {1} AND {2} > {3} ORDER BY {2} ASC LIMIT $2",
+            MESSAGE_COLUMNS, self.not_expired(), self.backend.timestamp("COALESCE(received_at, timestamp)"), self.backend.timestamp("$1")
+        ))
+        .bind(since.to_rfc3339())
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch messages received since")?;
+
+        let mut messages = Vec::new();
+        for row in rows {
+            let record = MessageRecord::from_row(&row)?;
+
+            match Message::try_from(record) {
+                Ok(message) => messages.push(message),
+                Err(e) => {
+                    eprintln!("Failed to parse message from database: {}", e);
+                    continue;
+                }
+            }
+        }
+
+        Ok(messages)
+    }
+
+    /// 获取房间消息（按偏移量分页，已由 `get_room_messages_before` 取代）
     pub async fn get_room_messages(&self, room_id: &str, limit: usize, offset: usize) -> Result<Vec<Message>> {
-        let rows = sqlx::query(
-            r#"
-            SELECT id, from_user_id, content_type, content_data, timestamp, from_nickname, room_id, additional_data
-            FROM messages
-            WHERE room_id = ?
-            ORDER BY timestamp ASC
-            LIMIT ? OFFSET ?
-            "#,
-        )
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM messages WHERE room_id = $1 AND {} ORDER BY timestamp ASC LIMIT $2 OFFSET $3",
+            MESSAGE_COLUMNS, self.not_expired()
+        ))
         .bind(room_id)
         .bind(limit as i64)
         .bind(offset as i64)
@@ -308,18 +725,7 @@ impl MessageDatabase {    /// 创建新的数据库管理器
 
         let mut messages = Vec::new();
         for row in rows {
-            let record = MessageRecord {
-                id: row.get("id"),
-                from_user_id: row.get("from_user_id"),
-                content_type: row.get("content_type"),
-                content_data: row.get("content_data"),
-                timestamp: DateTime::parse_from_rfc3339(&row.get::<String, _>("timestamp"))
-                    .context("Invalid timestamp format")?
-                    .with_timezone(&Utc),
-                from_nickname: row.get("from_nickname"),
-                room_id: row.get("room_id"),
-                additional_data: row.get("additional_data"),
-            };
+            let record = MessageRecord::from_row(&row)?;
 
             match Message::try_from(record) {
                 Ok(message) => messages.push(message),
@@ -333,67 +739,442 @@ impl MessageDatabase {    /// 创建新的数据库管理器
         Ok(messages)
     }
 
-    /// 获取数据库中的消息总数
-    pub async fn get_message_count(&self) -> Result<i64> {
-        let row = sqlx::query("SELECT COUNT(*) as count FROM messages")
-            .fetch_one(&self.pool)
-            .await
-            .context("Failed to count messages")?;
+    /// 按游标分页获取房间消息，返回排在 `before` 之前的最近N条，按时间正序排列
+    ///
+    /// 消息按（时间戳，消息ID）排序，时间戳相同的消息也不会在翻页时重复或遗漏。
+    /// `before` 为None时从最新的消息开始；游标消息不存在时返回空列表
+    pub async fn get_room_messages_before(
+        &self,
+        room_id: &str,
+        before: Option<&MessageId>,
+        limit: i64,
+    ) -> Result<Vec<Message>> {
+        let before = before.map(|id| id.to_string());
+        let rows = sqlx::query(&format!(
+            r#"
+            SELECT {0} FROM messages
+            WHERE room_id = $1 AND {1}
+              AND ($2 IS NULL OR ({2}, id) < (
+                  SELECT {2}, id FROM messages WHERE id = $2
+              ))
+            ORDER BY {2} DESC, id DESC
+            LIMIT $3
+            "#,
+            MESSAGE_COLUMNS, self.not_expired(), self.backend.timestamp("timestamp")
+        ))
+        .bind(room_id)
+        .bind(before)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch room messages")?;
 
-        Ok(row.get("count"))
+        let mut messages = Vec::new();
+        for row in rows {
+            let record = MessageRecord::from_row(&row)?;
+
+            match Message::try_from(record) {
+                Ok(message) => messages.push(message),
+                Err(e) => {
+                    eprintln!("Failed to parse message from database: {}", e);
+                    continue;
+                }
+            }
+        }
+
+        messages.reverse();
+        Ok(messages)
     }
 
-    /// 清理旧消息（保留最近的N条）
-    pub async fn cleanup_old_messages(&self, keep_count: i64) -> Result<u64> {
-        let result = sqlx::query(
+    /// 按ID获取单条未过期的消息
+    pub async fn get_message(&self, id: &MessageId) -> Result<Option<Message>> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM messages WHERE id = $1 AND {}",
+            MESSAGE_COLUMNS, self.not_expired()
+        ))
+        .bind(id.to_string())
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to fetch message")?;
+
+        row.map(|row| Message::try_from(MessageRecord::from_row(&row)?))
+            .transpose()
+    }
+
+    /// 将用户在房间中的已读位置推进到指定消息，已读位置只前进不后退
+    ///
+    /// 返回已读位置是否发生了变化
+    pub async fn mark_room_read(&self, room_id: &str, user_id: &UserId, message: &Message) -> Result<bool> {
+        let result = sqlx::query(&format!(
             r#"
-            DELETE FROM messages
-            WHERE id NOT IN (
-                SELECT id FROM messages
-                ORDER BY timestamp DESC
-                LIMIT ?
-            )
+            INSERT INTO room_read_markers (room_id, user_id, message_id, last_read_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT(room_id, user_id) DO UPDATE SET
+                message_id = excluded.message_id,
+                last_read_at = excluded.last_read_at
+            WHERE {} > {}
             "#,
-        )
-        .bind(keep_count)
+            self.backend.timestamp("excluded.last_read_at"),
+            self.backend.timestamp("room_read_markers.last_read_at")
+        ))
+        .bind(room_id)
+        .bind(user_id.to_string())
+        .bind(message.id.to_string())
+        .bind(message.timestamp.to_rfc3339())
         .execute(&self.pool)
         .await
-        .context("Failed to cleanup old messages")?;        Ok(result.rows_affected())
+        .context("Failed to update room read marker")?;
+
+        Ok(result.rows_affected() > 0)
     }
 
-    /// 获取数据库连接池
-    pub fn get_pool(&self) -> &SqlitePool {
-        &self.pool
+    /// 将用户在房间中的已读位置设置到指定消息（只前进不后退），返回已读位置是否发生了变化
+    pub async fn set_last_read(&self, user_id: &UserId, room_id: &str, message_id: &MessageId) -> Result<bool> {
+        let message = self
+            .get_message(message_id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Message not found: {}", message_id))?;
+        if message.get_room_id() != Some(room_id) {
+            return Err(anyhow::anyhow!("Message {} does not belong to room {}", message_id, room_id));
+        }
+
+        self.mark_room_read(room_id, user_id, &message).await
     }
 
-    /// 关闭数据库连接
-    pub async fn close(self) {
-        self.pool.close().await;
+    /// 获取用户在房间中的未读消息数（晚于已读位置的消息），从未读过的房间返回全部消息数
+    pub async fn get_unread_count(&self, user_id: &UserId, room_id: &str) -> Result<i64> {
+        let row = sqlx::query(&format!(
+            r#"
+            SELECT COUNT(*) as count FROM messages
+            WHERE room_id = $1 AND {}
+              AND NOT EXISTS (
+                  SELECT 1 FROM room_read_markers
+                  WHERE room_id = $1 AND user_id = $2 AND {} >= {}
+              )
+            "#,
+            self.not_expired(),
+            self.backend.timestamp("last_read_at"),
+            self.backend.timestamp("messages.timestamp")
+        ))
+        .bind(room_id)
+        .bind(user_id.to_string())
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to count unread messages")?;
+
+        Ok(row.get("count"))
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use rustchat_types::{Message, UserId};
+    /// 获取房间内所有用户的已读位置（已读到的消息时间）
+    pub async fn get_room_read_markers(&self, room_id: &str) -> Result<HashMap<UserId, DateTime<Utc>>> {
+        let rows = sqlx::query("SELECT user_id, last_read_at FROM room_read_markers WHERE room_id = $1")
+            .bind(room_id)
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to fetch room read markers")?;
 
-    #[tokio::test]
-    async fn test_database_operations() {
-        // 使用内存数据库进行测试
-        let pool = SqlitePool::connect("sqlite::memory:")
+        let mut markers = HashMap::new();
+        for row in rows {
+            let user_id = UserId::parse(&row.get::<String, _>("user_id"))?;
+            markers.insert(user_id, parse_timestamp(&row.get::<String, _>("last_read_at"))?);
+        }
+
+        Ok(markers)
+    }
+
+    /// 添加表情回应，重复添加同一回应不会重复计数，返回是否新增了回应
+    pub async fn add_reaction(&self, message_id: &MessageId, user_id: &UserId, emoji: &str) -> Result<bool> {
+        let result = sqlx::query(
+            "INSERT INTO message_reactions (message_id, user_id, emoji, created_at) VALUES ($1, $2, $3, $4) ON CONFLICT DO NOTHING",
+        )
+        .bind(message_id.to_string())
+        .bind(user_id.to_string())
+        .bind(emoji)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await
+        .context("Failed to add reaction")?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// 移除表情回应，返回回应是否存在
+    pub async fn remove_reaction(&self, message_id: &MessageId, user_id: &UserId, emoji: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM message_reactions WHERE message_id = $1 AND user_id = $2 AND emoji = $3")
+            .bind(message_id.to_string())
+            .bind(user_id.to_string())
+            .bind(emoji)
+            .execute(&self.pool)
             .await
-            .expect("Failed to connect to memory database");
+            .context("Failed to remove reaction")?;
 
-        let db = MessageDatabase { pool };
-        db.init_tables().await.expect("Failed to init tables");
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// 获取消息的所有表情回应，按每个表情的首次回应时间排序
+    pub async fn get_reactions(&self, message_id: &MessageId) -> Result<Vec<ReactionSummary>> {
+        let rows = sqlx::query(&format!(
+            "SELECT emoji, user_id FROM message_reactions WHERE message_id = $1 ORDER BY created_at, {}",
+            self.backend.insertion_order()
+        ))
+        .bind(message_id.to_string())
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch reactions")?;
+
+        let mut reactions: Vec<ReactionSummary> = Vec::new();
+        for row in rows {
+            let emoji: String = row.get("emoji");
+            let user_id = UserId::parse(&row.get::<String, _>("user_id"))?;
+            match reactions.iter_mut().find(|reaction| reaction.emoji == emoji) {
+                Some(reaction) => reaction.users.push(user_id),
+                None => reactions.push(ReactionSummary { emoji, users: vec![user_id] }),
+            }
+        }
+
+        Ok(reactions)
+    }
+
+    /// 获取数据库中的消息总数
+    pub async fn get_message_count(&self) -> Result<i64> {
+        let row = sqlx::query("SELECT COUNT(*) as count FROM messages")
+            .fetch_one(&self.pool)
+            .await
+            .context("Failed to count messages")?;
+
+        Ok(row.get("count"))
+    }
+
+    /// 统计用户发送的消息（单次分组查询），没有消息时返回全零的统计
+    pub async fn get_user_stats(&self, user_id: &UserId) -> Result<UserMessageStats> {
+        // 时间戳均以UTC的RFC3339格式保存，可以直接按文本取最小/最大值
+        let rows = sqlx::query(&format!(
+            r#"
+            SELECT content_type, COUNT(*) as count, MIN(timestamp) as first_at, MAX(timestamp) as last_at
+            FROM messages
+            WHERE from_user_id = $1 AND {}
+            GROUP BY content_type
+            "#,
+            self.not_expired()
+        ))
+        .bind(user_id.to_string())
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch user message stats")?;
+
+        let mut stats = UserMessageStats::default();
+        for row in rows {
+            let count: i64 = row.get("count");
+            let first_at = parse_timestamp(&row.get::<String, _>("first_at"))?;
+            let last_at = parse_timestamp(&row.get::<String, _>("last_at"))?;
+
+            stats.total_messages += count;
+            stats.by_type.insert(row.get("content_type"), count);
+            stats.first_message_at = Some(stats.first_message_at.map_or(first_at, |at| at.min(first_at)));
+            stats.last_message_at = Some(stats.last_message_at.map_or(last_at, |at| at.max(last_at)));
+        }
+
+        Ok(stats)
+    }
+
+    /// 在事务中删除指定用户发送的所有消息，返回删除数量
+    pub async fn purge_user_messages(&self, user_id: &UserId) -> Result<u64> {
+        let mut tx = self.pool.begin().await.context("Failed to begin transaction")?;
+        let deleted = Self::purge_user_messages_in(&mut tx, user_id).await?;
+        tx.commit().await.context("Failed to commit transaction")?;
+
+        info!("已删除用户 {} 的 {} 条消息", user_id, deleted);
+        Ok(deleted)
+    }
+
+    /// 在调用方的事务中删除用户的消息、已读位置和回应，返回删除的消息数量
+    ///
+    /// 需要与其他表的删除一起提交时使用，例如清除个人数据时与上传记录在同一事务中删除
+    pub async fn purge_user_messages_in(conn: &mut AnyConnection, user_id: &UserId) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM messages WHERE from_user_id = $1")
+            .bind(user_id.to_string())
+            .execute(&mut *conn)
+            .await
+            .context("Failed to delete user messages")?;
+
+        sqlx::query("DELETE FROM room_read_markers WHERE user_id = $1")
+            .bind(user_id.to_string())
+            .execute(&mut *conn)
+            .await
+            .context("Failed to delete user read markers")?;
+
+        // 删除该用户做出的回应，以及被删除消息上的回应
+        sqlx::query("DELETE FROM message_reactions WHERE user_id = $1")
+            .bind(user_id.to_string())
+            .execute(&mut *conn)
+            .await
+            .context("Failed to delete user reactions")?;
+        sqlx::query(DELETE_ORPHANED_REACTIONS)
+            .execute(&mut *conn)
+            .await
+            .context("Failed to delete reactions of purged messages")?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// 删除所有已过期的消息，返回被删除消息的ID
+    pub async fn delete_expired_messages(&self) -> Result<Vec<MessageId>> {
+        let mut tx = self.pool.begin().await.context("Failed to begin transaction")?;
+
+        let expired = format!("expires_at IS NOT NULL AND {} <= {}", self.backend.timestamp("expires_at"), self.backend.now());
+        let rows = sqlx::query(&format!("SELECT id FROM messages WHERE {}", expired))
+        .fetch_all(&mut *tx)
+        .await
+        .context("Failed to fetch expired messages")?;
+
+        sqlx::query(&format!("DELETE FROM messages WHERE {}", expired))
+            .execute(&mut *tx)
+            .await
+            .context("Failed to delete expired messages")?;
+        sqlx::query(DELETE_ORPHANED_REACTIONS)
+            .execute(&mut *tx)
+            .await
+            .context("Failed to delete reactions of expired messages")?;
+
+        tx.commit().await.context("Failed to commit transaction")?;
+
+        let ids = rows
+            .iter()
+            .filter_map(|row| MessageId::parse(&row.get::<String, _>("id")).ok())
+            .collect::<Vec<_>>();
+        if !ids.is_empty() {
+            debug!("已删除 {} 条过期消息", ids.len());
+        }
+        Ok(ids)
+    }
+
+    /// 统计最近N条带有接收时间的消息的接收延迟
+    pub async fn receipt_latency_stats(&self, limit: i64) -> Result<ReceiptLatencyStats> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM messages WHERE received_at IS NOT NULL ORDER BY timestamp DESC LIMIT $1",
+            MESSAGE_COLUMNS
+        ))
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch receipt timestamps")?;
+
+        let mut deltas = Vec::with_capacity(rows.len());
+        for row in rows {
+            let record = MessageRecord::from_row(&row)?;
+            if let Some(received_at) = record.received_at {
+                deltas.push((received_at - record.timestamp).num_milliseconds());
+            }
+        }
+
+        if deltas.is_empty() {
+            return Ok(ReceiptLatencyStats::default());
+        }
+
+        Ok(ReceiptLatencyStats {
+            samples: deltas.len(),
+            average_ms: deltas.iter().sum::<i64>() / deltas.len() as i64,
+            min_ms: deltas.iter().copied().min().unwrap_or_default(),
+            max_ms: deltas.iter().copied().max().unwrap_or_default(),
+        })
+    }
+
+    /// 删除本地数据库中的所有消息及其回应和已读标记，返回删除的消息数量
+    pub async fn clear_all_messages(&self) -> Result<u64> {
+        let mut tx = self.pool.begin().await.context("Failed to begin transaction")?;
+
+        let result = sqlx::query("DELETE FROM messages")
+            .execute(&mut *tx)
+            .await
+            .context("Failed to delete messages")?;
+        sqlx::query("DELETE FROM message_reactions")
+            .execute(&mut *tx)
+            .await
+            .context("Failed to delete reactions")?;
+        sqlx::query("DELETE FROM room_read_markers")
+            .execute(&mut *tx)
+            .await
+            .context("Failed to delete read markers")?;
+
+        tx.commit().await.context("Failed to commit transaction")?;
+
+        info!("已清空本地消息历史，删除 {} 条消息", result.rows_affected());
+        Ok(result.rows_affected())
+    }
+
+    /// 清理旧消息，全局消息和每个房间分别保留最近的N条，返回删除数量
+    ///
+    /// 按范围分别保留，避免消息多的范围把其他房间的历史挤掉
+    pub async fn cleanup_old_messages(&self, keep_count: i64) -> Result<u64> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM messages
+            WHERE id IN (
+                SELECT id FROM (
+                    SELECT id, ROW_NUMBER() OVER (
+                        PARTITION BY COALESCE(room_id, '')
+                        ORDER BY timestamp DESC
+                    ) AS position
+                    FROM messages
+                ) AS ranked
+                WHERE position > $1
+            )
+            "#,
+        )
+        .bind(keep_count)
+        .execute(&self.pool)
+        .await
+        .context("Failed to cleanup old messages")?;
+
+        sqlx::query(DELETE_ORPHANED_REACTIONS)
+            .execute(&self.pool)
+            .await
+            .context("Failed to cleanup reactions of old messages")?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// 获取数据库连接池
+    pub fn get_pool(&self) -> &AnyPool {
+        &self.pool
+    }
+
+    /// 当前连接的数据库后端
+    pub fn backend(&self) -> DatabaseBackend {
+        self.backend
+    }
+
+    /// 排除已过期消息的查询条件
+    fn not_expired(&self) -> String {
+        not_expired(self.backend)
+    }
+
+    /// 关闭数据库连接
+    pub async fn close(self) {
+        self.pool.close().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustchat_types::{Message, UserId};
+    use std::time::Instant;
+
+    #[tokio::test]
+    async fn test_database_operations() {
+        // 使用内存数据库进行测试
+        let db = MessageDatabase::new_in_memory().await.expect("Failed to open memory database");
 
         // 创建测试消息
         let user_id = UserId::new();
-        let message = Message::new_text(
+        let mut message = Message::new_text(
             user_id.clone(),
             "Test message".to_string(),
             Some("TestUser".to_string()),
         );
+        let mentioned = UserId::new();
+        message.mentions = vec![mentioned.clone()];
 
         // 保存消息
         db.save_message(&message)
@@ -409,9 +1190,624 @@ mod tests {
         assert_eq!(messages.len(), 1);
         assert_eq!(messages[0].get_text(), Some("Test message"));
         assert_eq!(messages[0].from_nick, Some("TestUser".to_string()));
+        assert_eq!(messages[0].mentions, vec![mentioned]);
+        assert!(!messages[0].is_bot);
 
         // 测试消息计数
         let count = db.get_message_count().await.expect("Failed to count messages");
         assert_eq!(count, 1);
+
+        // 机器人标记随消息保存
+        let bot_message = Message::new_bot_text("beep".to_string(), "Echo Bot");
+        db.save_message(&bot_message).await.expect("Failed to save message");
+        assert!(db.get_recent_messages(10).await.unwrap().iter().any(|m| m.id == bot_message.id && m.is_bot));
+    }
+
+    #[tokio::test]
+    async fn test_save_messages_batch_skips_existing_ids() {
+        let db = MessageDatabase::new_in_memory().await.expect("Failed to open database");
+        let existing = Message::new_text(UserId::new(), "existing".to_string(), None);
+        db.save_message(&existing).await.unwrap();
+
+        let mut imported = Message::new_text(UserId::new(), "imported".to_string(), Some("legacy".to_string()));
+        imported.timestamp = Utc::now() - chrono::Duration::days(365);
+        let mut renamed = existing.clone();
+        renamed.content = MessageType::Text("should not overwrite".to_string());
+        let batch = vec![imported.clone(), renamed, imported.clone()];
+
+        let result = db.save_messages_batch(&batch).await.unwrap();
+        assert_eq!(result, BatchInsertResult { inserted: 1, skipped: 2 });
+
+        // 保留原有ID和时间戳，已存在的消息不被覆盖
+        let messages = db.get_recent_messages(10).await.unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].id, imported.id);
+        assert_eq!(messages[0].timestamp, imported.timestamp);
+        assert_eq!(messages[1].get_text(), Some("existing"));
+    }
+
+    #[tokio::test]
+    async fn test_action_message_round_trip() {
+        let db = MessageDatabase::new_in_memory().await.expect("Failed to open memory database");
+
+        let message = Message::new_action(
+            UserId::new(),
+            "waves".to_string(),
+            Some("Alice".to_string()),
+        );
+        db.save_message(&message)
+            .await
+            .expect("Failed to save message");
+
+        let messages = db
+            .get_recent_messages(10)
+            .await
+            .expect("Failed to get messages");
+
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].is_action());
+        assert_eq!(messages[0].get_body(), "* Alice waves");
+    }
+
+    #[tokio::test]
+    async fn test_received_at_round_trip_and_stats() {
+        let db = MessageDatabase::new_in_memory().await.expect("Failed to open database");
+
+        let mut message = Message::new_text(UserId::new(), "late".to_string(), None);
+        message.timestamp = Utc::now() - chrono::Duration::seconds(2);
+        message.mark_received();
+        db.save_message(&message).await.expect("Failed to save message");
+
+        // 未记录接收时间的消息不参与统计
+        let legacy = Message::new_text(UserId::new(), "legacy".to_string(), None);
+        db.save_message(&legacy).await.expect("Failed to save message");
+
+        let messages = db.get_recent_messages(10).await.expect("Failed to get messages");
+        let saved = messages.iter().find(|m| m.id == message.id).unwrap();
+        assert_eq!(saved.received_at, message.received_at);
+
+        let stats = db.receipt_latency_stats(10).await.expect("Failed to get stats");
+        assert_eq!(stats.samples, 1);
+        assert!(stats.min_ms >= 2000);
+    }
+
+    #[tokio::test]
+    async fn test_recent_messages_are_newest_in_time_order() {
+        let db = MessageDatabase::new_in_memory().await.expect("Failed to open database");
+        let start = Utc::now() - chrono::Duration::minutes(10);
+        let messages: Vec<Message> = (0..5)
+            .map(|i| {
+                let mut message = Message::new_text(UserId::new(), format!("m{}", i), None);
+                message.timestamp = start + chrono::Duration::seconds(i);
+                message
+            })
+            .collect();
+        // 写入顺序与时间顺序不同
+        for message in messages.iter().rev() {
+            db.save_message(message).await.expect("Failed to save message");
+        }
+
+        // 取最新的3条，最老的在前；重复调用复用同一条语句
+        for _ in 0..2 {
+            let recent = db.get_recent_messages(3).await.expect("Failed to get messages");
+            let texts: Vec<_> = recent.iter().filter_map(|m| m.get_text()).collect();
+            assert_eq!(texts, vec!["m2", "m3", "m4"]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_expired_messages_hidden_and_swept() {
+        let db = MessageDatabase::new_in_memory().await.expect("Failed to open database");
+        let user_id = UserId::new();
+
+        let mut expired = Message::new_text(user_id.clone(), "gone".to_string(), None);
+        expired.expire_after(chrono::Duration::seconds(-1));
+        let mut pending = Message::new_text(user_id.clone(), "soon".to_string(), None);
+        pending.expire_after(chrono::Duration::hours(1));
+        let permanent = Message::new_text(user_id.clone(), "kept".to_string(), None);
+        for message in [&expired, &pending, &permanent] {
+            db.save_message(message).await.expect("Failed to save message");
+        }
+
+        // 过期消息不出现在历史记录中
+        let history = db.get_recent_messages(10).await.expect("Failed to get messages");
+        assert_eq!(history.len(), 2);
+        assert!(history.iter().all(|m| m.id != expired.id));
+        let saved = history.iter().find(|m| m.id == pending.id).unwrap();
+        assert_eq!(saved.expires_at, pending.expires_at);
+        assert_eq!(db.get_user_messages(&user_id, 10).await.unwrap().len(), 2);
+
+        // 清理任务删除过期消息
+        let swept = db.delete_expired_messages().await.expect("Failed to sweep");
+        assert_eq!(swept, vec![expired.id.clone()]);
+        assert_eq!(db.get_message_count().await.unwrap(), 2);
+        assert!(db.delete_expired_messages().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reactions_are_idempotent_and_cleaned_up() {
+        let db = MessageDatabase::new_in_memory().await.expect("Failed to open database");
+        let author = UserId::new();
+        let alice = UserId::new();
+        let bob = UserId::new();
+
+        let message = Message::new_text(author.clone(), "hi".to_string(), None);
+        let mut expiring = Message::new_text(author.clone(), "soon".to_string(), None);
+        expiring.expire_after(chrono::Duration::seconds(-1));
+        for message in [&message, &expiring] {
+            db.save_message(message).await.expect("Failed to save message");
+        }
+
+        assert!(db.add_reaction(&message.id, &alice, "👍").await.unwrap());
+        assert!(!db.add_reaction(&message.id, &alice, "👍").await.unwrap());
+        assert!(db.add_reaction(&message.id, &bob, "👍").await.unwrap());
+        assert!(db.add_reaction(&message.id, &bob, "🎉").await.unwrap());
+        assert!(db.add_reaction(&expiring.id, &alice, "👍").await.unwrap());
+
+        let reactions = db.get_reactions(&message.id).await.unwrap();
+        assert_eq!(reactions.len(), 2);
+        assert_eq!(reactions[0].emoji, "👍");
+        assert_eq!(reactions[0].users, vec![alice.clone(), bob.clone()]);
+        assert_eq!(reactions[1].count(), 1);
+
+        assert!(db.remove_reaction(&message.id, &bob, "🎉").await.unwrap());
+        assert!(!db.remove_reaction(&message.id, &bob, "🎉").await.unwrap());
+        assert_eq!(db.get_reactions(&message.id).await.unwrap().len(), 1);
+
+        // 过期消息的回应随消息一起删除
+        db.delete_expired_messages().await.unwrap();
+        assert!(db.get_reactions(&expiring.id).await.unwrap().is_empty());
+
+        // 清除用户数据时删除其做出的回应和其消息上的回应
+        db.purge_user_messages(&alice).await.unwrap();
+        assert_eq!(db.get_reactions(&message.id).await.unwrap()[0].users, vec![bob.clone()]);
+        db.purge_user_messages(&author).await.unwrap();
+        assert!(db.get_reactions(&message.id).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_old_messages_keeps_each_scope() {
+        let db = MessageDatabase::new_in_memory().await.expect("Failed to open database");
+        let user = UserId::new();
+        let base = Utc::now() - chrono::Duration::minutes(10);
+
+        let mut global = Vec::new();
+        for i in 0..5 {
+            let mut message = Message::new_text(user.clone(), format!("global {}", i), None);
+            message.timestamp = base + chrono::Duration::seconds(i);
+            db.save_message(&message).await.expect("Failed to save message");
+            global.push(message);
+        }
+        let mut room_message = Message::new_text(user.clone(), "room".to_string(), None);
+        room_message.timestamp = base - chrono::Duration::minutes(1);
+        room_message.set_room_id("room-1".to_string());
+        db.save_message(&room_message).await.expect("Failed to save message");
+
+        // 房间里最旧的消息不会因为全局消息多而被删除
+        assert_eq!(db.cleanup_old_messages(2).await.unwrap(), 3);
+        assert_eq!(db.get_message_count().await.unwrap(), 3);
+        assert!(db.get_message(&room_message.id).await.unwrap().is_some());
+        for (i, message) in global.iter().enumerate() {
+            assert_eq!(db.get_message(&message.id).await.unwrap().is_some(), i >= 3);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_clear_all_messages() {
+        let db = MessageDatabase::new_in_memory().await.expect("Failed to open database");
+        let user = UserId::new();
+        let message = Message::new_text(user.clone(), "hi".to_string(), None);
+        db.save_message(&message).await.expect("Failed to save message");
+        db.save_message(&Message::new_text(user.clone(), "again".to_string(), None)).await.expect("Failed to save message");
+        db.add_reaction(&message.id, &user, "👍").await.unwrap();
+
+        assert_eq!(db.clear_all_messages().await.unwrap(), 2);
+        assert_eq!(db.get_message_count().await.unwrap(), 0);
+        assert!(db.get_reactions(&message.id).await.unwrap().is_empty());
+        assert_eq!(db.clear_all_messages().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_room_read_marker_only_moves_forward() {
+        let db = MessageDatabase::new_in_memory().await.expect("Failed to open database");
+        let user_id = UserId::new();
+
+        let mut older = Message::new_room_text(user_id.clone(), "one".to_string(), None, "room".to_string());
+        older.timestamp = Utc::now() - chrono::Duration::minutes(1);
+        let newer = Message::new_room_text(user_id.clone(), "two".to_string(), None, "room".to_string());
+
+        assert!(db.mark_room_read("room", &user_id, &newer).await.unwrap());
+        // 已读位置不会回退
+        assert!(!db.mark_room_read("room", &user_id, &older).await.unwrap());
+
+        let markers = db.get_room_read_markers("room").await.unwrap();
+        assert_eq!(markers.get(&user_id), Some(&newer.timestamp));
+        assert!(db.get_room_read_markers("other").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_messages_before_pages_global_history() {
+        let db = MessageDatabase::new_in_memory().await.expect("Failed to open database");
+        let user_id = UserId::new();
+
+        let mut messages = Vec::new();
+        for minutes_ago in [4, 3, 2, 1] {
+            let mut message = Message::new_text(user_id.clone(), format!("{}", minutes_ago), None);
+            message.timestamp = Utc::now() - chrono::Duration::minutes(minutes_ago);
+            db.save_message(&message).await.expect("Failed to save message");
+            messages.push(message);
+        }
+        let room_message = Message::new_room_text(user_id.clone(), "room".to_string(), None, "room".to_string());
+        db.save_message(&room_message).await.expect("Failed to save message");
+
+        // 最新一页不包含房间消息
+        let page = db.get_messages_before(None, 2).await.unwrap();
+        let ids: Vec<_> = page.iter().map(|m| m.id.clone()).collect();
+        assert_eq!(ids, vec![messages[2].id.clone(), messages[3].id.clone()]);
+
+        // 以第一页最早的消息时间继续向前翻页
+        let page = db.get_messages_before(Some(page[0].timestamp), 10).await.unwrap();
+        let ids: Vec<_> = page.iter().map(|m| m.id.clone()).collect();
+        assert_eq!(ids, vec![messages[0].id.clone(), messages[1].id.clone()]);
+    }
+
+    #[tokio::test]
+    async fn test_room_messages_cursor_pages_without_gaps() {
+        let db = MessageDatabase::new_in_memory().await.expect("Failed to open database");
+        let user_id = UserId::new();
+        let start = Utc::now() - chrono::Duration::hours(1);
+
+        // 每三条消息共用一个时间戳，验证相同时间戳的消息翻页时不重复也不遗漏
+        for index in 0..30 {
+            let mut message = Message::new_room_text(user_id.clone(), format!("{}", index), None, "room".to_string());
+            message.timestamp = start + chrono::Duration::seconds(index / 3);
+            db.save_message(&message).await.expect("Failed to save message");
+        }
+        db.save_message(&Message::new_room_text(user_id.clone(), "other".to_string(), None, "other".to_string()))
+            .await
+            .expect("Failed to save message");
+
+        let mut pages = Vec::new();
+        let mut cursor: Option<MessageId> = None;
+        loop {
+            let page = db.get_room_messages_before("room", cursor.as_ref(), 10).await.unwrap();
+            if page.is_empty() {
+                break;
+            }
+            assert!(page.windows(2).all(|pair| pair[0].timestamp <= pair[1].timestamp));
+            cursor = Some(page[0].id.clone());
+            pages.push(page);
+        }
+
+        assert_eq!(pages.len(), 3);
+        assert!(pages.iter().all(|page| page.len() == 10));
+        let mut contents: Vec<i64> = pages
+            .iter()
+            .flatten()
+            .map(|message| message.get_text().unwrap().parse().unwrap())
+            .collect();
+        contents.sort();
+        assert_eq!(contents, (0..30).collect::<Vec<_>>());
+        // 后一页的消息都早于前一页
+        assert!(pages[1].last().unwrap().timestamp <= pages[0][0].timestamp);
+
+        assert!(db.get_room_messages_before("room", Some(&MessageId::new()), 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_legacy_room_messages_backfilled_into_room_column() {
+        let pool = connect_pool("sqlite::memory:", Some(1))
+            .await
+            .expect("Failed to connect to memory database");
+        // 旧版本的表结构：房间ID只保存在additional_data中
+        sqlx::query(
+            "CREATE TABLE messages (id TEXT PRIMARY KEY, from_user_id TEXT NOT NULL, content_type TEXT NOT NULL, \
+             content_data TEXT NOT NULL, timestamp TEXT NOT NULL, from_nickname TEXT, additional_data TEXT, \
+             created_at DATETIME DEFAULT CURRENT_TIMESTAMP)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let message_id = MessageId::new();
+        sqlx::query("INSERT INTO messages (id, from_user_id, content_type, content_data, timestamp, additional_data) VALUES ($1, $2, 'text', 'legacy', $3, $4)")
+            .bind(message_id.to_string())
+            .bind(UserId::new().to_string())
+            .bind(Utc::now().to_rfc3339())
+            .bind(r#"{"room_id":"room"}"#)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let db = MessageDatabase::from_pool(pool, None).await.expect("Failed to init tables");
+
+        let room_message = Message::new_room_text(UserId::new(), "new".to_string(), None, "room".to_string());
+        db.save_message(&room_message).await.expect("Failed to save message");
+        db.save_message(&Message::new_text(UserId::new(), "global".to_string(), None))
+            .await
+            .expect("Failed to save message");
+
+        let messages = db.get_room_messages_before("room", None, 10).await.unwrap();
+        let ids: Vec<_> = messages.iter().map(|m| m.id.clone()).collect();
+        assert_eq!(ids, vec![message_id, room_message.id.clone()]);
+        assert_eq!(messages[1].room_id.as_deref(), Some("room"));
+        assert!(!messages[0].is_bot);
+        assert!(db.get_room_messages_before("other", None, 10).await.unwrap().is_empty());
+
+        // 重复初始化不会再次执行迁移
+        db.init_tables().await.expect("Failed to init tables");
+        assert_eq!(current_version(&db.pool, MIGRATION_SCOPE).await.unwrap(), MESSAGE_MIGRATIONS.len() as i64);
+        assert_eq!(db.get_room_messages_before("room", None, 10).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_user_stats() {
+        let db = MessageDatabase::new_in_memory().await.expect("Failed to open database");
+        let user_id = UserId::new();
+
+        // 没有消息时返回空统计而不是错误
+        assert_eq!(db.get_user_stats(&user_id).await.unwrap(), UserMessageStats::default());
+
+        let mut first = Message::new_text(user_id.clone(), "one".to_string(), None);
+        first.timestamp = Utc::now() - chrono::Duration::hours(2);
+        let mut action = Message::new_action(user_id.clone(), "waves".to_string(), None);
+        action.timestamp = Utc::now() - chrono::Duration::hours(1);
+        let last = Message::new_text(user_id.clone(), "two".to_string(), None);
+        let other = Message::new_text(UserId::new(), "other".to_string(), None);
+        for message in [&first, &action, &last, &other] {
+            db.save_message(message).await.expect("Failed to save message");
+        }
+
+        let stats = db.get_user_stats(&user_id).await.unwrap();
+        assert_eq!(stats.total_messages, 3);
+        assert_eq!(stats.first_message_at, Some(first.timestamp));
+        assert_eq!(stats.last_message_at, Some(last.timestamp));
+        assert_eq!(stats.by_type.get("text"), Some(&2));
+        assert_eq!(stats.by_type.get("action"), Some(&1));
+    }
+
+    #[tokio::test]
+    async fn test_unread_count_follows_last_read() {
+        let db = MessageDatabase::new_in_memory().await.expect("Failed to open database");
+        let author = UserId::new();
+        let reader = UserId::new();
+
+        let mut messages = Vec::new();
+        for minutes_ago in [3, 2, 1] {
+            let mut message = Message::new_room_text(author.clone(), "hi".to_string(), None, "room".to_string());
+            message.timestamp = Utc::now() - chrono::Duration::minutes(minutes_ago);
+            db.save_message(&message).await.expect("Failed to save message");
+            messages.push(message);
+        }
+        let other_room = Message::new_room_text(author.clone(), "elsewhere".to_string(), None, "other".to_string());
+        db.save_message(&other_room).await.expect("Failed to save message");
+
+        // 从未读过的房间全部未读
+        assert_eq!(db.get_unread_count(&reader, "room").await.unwrap(), 3);
+
+        assert!(db.set_last_read(&reader, "room", &messages[1].id).await.unwrap());
+        assert_eq!(db.get_unread_count(&reader, "room").await.unwrap(), 1);
+        assert!(db.set_last_read(&reader, "room", &messages[2].id).await.unwrap());
+        assert_eq!(db.get_unread_count(&reader, "room").await.unwrap(), 0);
+
+        // 已读位置不回退，也不能使用其他房间或不存在的消息
+        assert!(!db.set_last_read(&reader, "room", &messages[0].id).await.unwrap());
+        assert!(db.set_last_read(&reader, "room", &other_room.id).await.is_err());
+        assert!(db.set_last_read(&reader, "room", &MessageId::new()).await.is_err());
+        assert_eq!(db.get_unread_count(&reader, "other").await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_failed_saves_go_to_dead_letter_log_and_replay() {
+        let path = std::env::temp_dir().join(format!("rustchat-dead-letters-{}.jsonl", UserId::new()));
+        let db = MessageDatabase::new_in_memory()
+            .await
+            .expect("Failed to open database")
+            .with_dead_letter_log(&path, DeadLetterLog::DEFAULT_MAX_ENTRIES);
+
+        // 模拟数据库持续不可写
+        sqlx::query("ALTER TABLE messages RENAME TO messages_offline").execute(&db.pool).await.unwrap();
+        let messages: Vec<Message> = ["one", "two"]
+            .iter()
+            .map(|content| Message::new_text(UserId::new(), content.to_string(), None))
+            .collect();
+        for message in &messages {
+            assert!(db.save_message(message).await.is_err());
+        }
+
+        let dead = db.dead_letter_log().unwrap().read_all().await.unwrap();
+        assert_eq!(dead.iter().map(|m| &m.id).collect::<Vec<_>>(), messages.iter().map(|m| &m.id).collect::<Vec<_>>());
+
+        // 数据库仍不可用时重放失败，消息保留在日志中
+        assert_eq!(db.replay_dead_letters().await.unwrap(), 0);
+        assert_eq!(db.dead_letter_log().unwrap().read_all().await.unwrap().len(), 2);
+
+        // 数据库恢复后重放成功，日志被清空
+        sqlx::query("ALTER TABLE messages_offline RENAME TO messages").execute(&db.pool).await.unwrap();
+        assert_eq!(db.replay_dead_letters().await.unwrap(), 2);
+        assert_eq!(db.get_message_count().await.unwrap(), 2);
+        assert!(db.dead_letter_log().unwrap().read_all().await.unwrap().is_empty());
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_connect_opens_sqlite_url() {
+        let dir = std::env::temp_dir().join(format!("rustchat-url-{}", UserId::new()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("chat.db");
+        let db = MessageDatabase::connect(&format!("sqlite://{}", path.display())).await.expect("Failed to open database");
+        db.save_message(&Message::new_text(UserId::new(), "hi".to_string(), None)).await.unwrap();
+        assert_eq!(db.dead_letter_log().unwrap().path(), dir.join("dead_letters.jsonl"));
+        db.close().await;
+        assert!(path.exists());
+
+        assert!(MessageDatabase::connect("mysql://localhost/chat").await.is_err());
+        assert!(MessageDatabase::connect("chat.db").await.is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_when_primary_path_unwritable() {
+        let base = std::env::temp_dir().join(format!("rustchat-db-{}", UserId::new()));
+        std::fs::create_dir_all(&base).unwrap();
+
+        // 主路径的父目录是一个普通文件，无法在其下创建目录
+        let blocker = base.join("blocker");
+        std::fs::write(&blocker, b"not a directory").unwrap();
+        let primary = blocker.join(".rustchat").join("messages.db");
+        let fallback = base.join("fallback").join("messages.db");
+
+        let (db, used_path) = MessageDatabase::open_first_available(&[primary, fallback.clone()])
+            .await
+            .expect("Failed to open fallback database");
+
+        assert_eq!(used_path, fallback);
+        assert!(fallback.exists());
+        db.close().await;
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    /// 获取最近消息的几种写法在10万条消息上的耗时对比
+    ///
+    /// 运行方式：`cargo test -p rustchat-core --release -- --ignored bench_recent_messages_query --nocapture`
+    #[tokio::test]
+    #[ignore]
+    async fn bench_recent_messages_query() {
+        const MESSAGES: usize = 100_000;
+        const LIMIT: i64 = 100;
+        const RUNS: u32 = 200;
+
+        let dir = std::env::temp_dir().join(format!("rustchat-query-bench-{}", UserId::new()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let db = MessageDatabase::connect(&format!("sqlite://{}", dir.join("bench.db").display())).await.unwrap();
+        let start = Utc::now() - chrono::Duration::days(30);
+        let messages: Vec<Message> = (0..MESSAGES)
+            .map(|i| {
+                let mut message = Message::new_text(UserId::new(), format!("bench {}", i), None);
+                message.timestamp = start + chrono::Duration::seconds(i as i64);
+                message
+            })
+            .collect();
+        for chunk in messages.chunks(5000) {
+            db.save_messages_batch(chunk).await.unwrap();
+        }
+
+        let ascending_with_count = format!(
+            "SELECT {} FROM messages WHERE {} ORDER BY timestamp ASC LIMIT $1 OFFSET $2",
+            MESSAGE_COLUMNS,
+            db.not_expired()
+        );
+        let resorted_subquery = format!(
+            "SELECT * FROM ({} ) AS recent ORDER BY timestamp ASC",
+            db.recent_messages_query
+        );
+
+        let started = Instant::now();
+        for _ in 0..RUNS {
+            assert_eq!(db.get_recent_messages(LIMIT).await.unwrap().len(), LIMIT as usize);
+        }
+        let descending = started.elapsed() / RUNS;
+
+        let started = Instant::now();
+        for _ in 0..RUNS {
+            let total: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM messages WHERE {}", db.not_expired()))
+                .fetch_one(&db.pool)
+                .await
+                .unwrap();
+            let rows = sqlx::query(&ascending_with_count).bind(LIMIT).bind(total - LIMIT).fetch_all(&db.pool).await.unwrap();
+            assert_eq!(rows.len(), LIMIT as usize);
+        }
+        let ascending = started.elapsed() / RUNS;
+
+        let started = Instant::now();
+        for _ in 0..RUNS {
+            let rows = sqlx::query(&resorted_subquery).bind(LIMIT).fetch_all(&db.pool).await.unwrap();
+            assert_eq!(rows.len(), LIMIT as usize);
+        }
+        let resorted = started.elapsed() / RUNS;
+
+        println!("倒序取前{}条再反转: {:?}/次", LIMIT, descending);
+        println!("先统计总数再正序分页: {:?}/次", ascending);
+        println!("子查询内倒序、外层正序: {:?}/次", resorted);
+        db.close().await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// 在 `RUSTCHAT_TEST_POSTGRES_URL` 指定的服务器上创建一个临时数据库，返回管理连接、新数据库的地址和名称
+    #[cfg(feature = "postgres")]
+    async fn create_postgres_database(admin_url: &str) -> (AnyPool, String, String) {
+        let admin = connect_pool(admin_url, Some(1)).await.expect("Failed to connect to PostgreSQL");
+        let name = format!("rustchat_test_{}", UserId::new().to_string().replace('-', "_"));
+        sqlx::query(&format!("CREATE DATABASE {}", name)).execute(&admin).await.unwrap();
+        let (server, _) = admin_url.rsplit_once('/').expect("Invalid PostgreSQL URL");
+        (admin, format!("{}/{}", server, name), name)
+    }
+
+    /// 需要PostgreSQL服务器：
+    /// `RUSTCHAT_TEST_POSTGRES_URL=postgres://postgres@localhost/postgres cargo test -p rustchat-core --features postgres -- --ignored`
+    #[cfg(feature = "postgres")]
+    #[tokio::test]
+    #[ignore]
+    async fn test_postgres_backend() {
+        let admin_url = std::env::var("RUSTCHAT_TEST_POSTGRES_URL").expect("RUSTCHAT_TEST_POSTGRES_URL is not set");
+        let (admin, url, name) = create_postgres_database(&admin_url).await;
+
+        let db = MessageDatabase::connect(&url).await.expect("Failed to open database");
+        assert_eq!(db.backend(), DatabaseBackend::Postgres);
+        assert!(db.dead_letter_log().is_none());
+
+        // 相同ID再次保存时覆盖，批量导入时跳过
+        let user = UserId::new();
+        let mut message = Message::new_text(user.clone(), "first".to_string(), None);
+        db.save_message(&message).await.unwrap();
+        message.content = rustchat_types::MessageType::Text("edited".to_string());
+        db.save_message(&message).await.unwrap();
+        let bot = Message::new_bot_text("beep".to_string(), "bot");
+        let result = db.save_messages_batch(&[message.clone(), bot.clone()]).await.unwrap();
+        assert_eq!((result.inserted, result.skipped), (1, 1));
+        let recent = db.get_recent_messages(10).await.unwrap();
+        assert_eq!(recent.len(), 2);
+        assert_eq!(db.get_message(&message.id).await.unwrap().unwrap().get_text(), Some("edited"));
+        assert!(db.get_message(&bot.id).await.unwrap().unwrap().is_bot);
+        assert_eq!(db.get_messages_before(Some(bot.timestamp), 10).await.unwrap().len(), 1);
+        assert_eq!(db.get_messages_before(None, 10).await.unwrap().len(), 2);
+
+        // 房间分页、已读标记和未读数
+        let reader = UserId::new();
+        let mut room_messages = Vec::new();
+        for i in 0..5 {
+            let message = Message::new_room_text(user.clone(), i.to_string(), None, "room".to_string());
+            db.save_message(&message).await.unwrap();
+            room_messages.push(message);
+        }
+        let page = db.get_room_messages_before("room", Some(&room_messages[3].id), 10).await.unwrap();
+        assert_eq!(page.len(), 3);
+        assert_eq!(db.get_unread_count(&reader, "room").await.unwrap(), 5);
+        assert!(db.mark_room_read("room", &reader, &room_messages[2]).await.unwrap());
+        assert!(!db.mark_room_read("room", &reader, &room_messages[1]).await.unwrap());
+        assert_eq!(db.get_unread_count(&reader, "room").await.unwrap(), 2);
+
+        // 回应按回应顺序返回，重复回应被忽略
+        assert!(db.add_reaction(&message.id, &reader, "👍").await.unwrap());
+        assert!(!db.add_reaction(&message.id, &reader, "👍").await.unwrap());
+        assert!(db.add_reaction(&message.id, &user, "👍").await.unwrap());
+        assert_eq!(db.get_reactions(&message.id).await.unwrap()[0].users, vec![reader.clone(), user.clone()]);
+
+        // 过期消息和每个范围保留的条数
+        let mut expiring = Message::new_text(user.clone(), "gone".to_string(), None);
+        expiring.expire_after(chrono::Duration::seconds(-1));
+        db.save_message(&expiring).await.unwrap();
+        assert!(db.get_message(&expiring.id).await.unwrap().is_none());
+        assert_eq!(db.delete_expired_messages().await.unwrap(), vec![expiring.id.clone()]);
+        assert_eq!(db.cleanup_old_messages(1).await.unwrap(), 5);
+        assert_eq!(db.get_message_count().await.unwrap(), 2);
+
+        // 重新打开时不会重复执行迁移
+        db.close().await;
+        let db = MessageDatabase::connect(&url).await.expect("Failed to reopen database");
+        assert_eq!(db.get_message_count().await.unwrap(), 2);
+        db.close().await;
+
+        sqlx::query(&format!("DROP DATABASE {} WITH (FORCE)", name)).execute(&admin).await.unwrap();
     }
 }
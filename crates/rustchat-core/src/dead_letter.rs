@@ -0,0 +1,192 @@
+use anyhow::{Context, Result};
+use rustchat_types::Message;
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tracing::warn;
+
+/// 死信日志：保存写入数据库失败的消息（JSON Lines格式，每行一条消息）
+///
+/// 日志有条数上限，超出时丢弃最旧的记录
+#[derive(Debug)]
+pub struct DeadLetterLog {
+    path: PathBuf,
+    max_entries: usize,
+    /// 串行化对日志文件的读写
+    lock: Mutex<()>,
+}
+
+impl DeadLetterLog {
+    /// 默认最多保留的消息条数
+    pub const DEFAULT_MAX_ENTRIES: usize = 10_000;
+
+    pub fn new(path: impl Into<PathBuf>, max_entries: usize) -> Self {
+        Self {
+            path: path.into(),
+            max_entries: max_entries.max(1),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// 日志文件路径
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 追加一条消息
+    pub async fn append(&self, message: &Message) -> Result<()> {
+        let _guard = self.lock.lock().await;
+
+        let mut entries = self.read_lines().await?;
+        if entries.len() >= self.max_entries {
+            let dropped = entries.len() + 1 - self.max_entries;
+            warn!("死信日志已达上限 {} 条，丢弃最旧的 {} 条", self.max_entries, dropped);
+            entries.drain(..dropped);
+            entries.push(serde_json::to_string(message).context("Failed to serialize message")?);
+            return self.write_lines(&entries).await;
+        }
+
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .context("Failed to create dead letter directory")?;
+        }
+        let mut line = serde_json::to_string(message).context("Failed to serialize message")?;
+        line.push('\n');
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .context("Failed to open dead letter log")?;
+        file.write_all(line.as_bytes())
+            .await
+            .context("Failed to append to dead letter log")?;
+        file.sync_data().await.context("Failed to flush dead letter log")?;
+
+        Ok(())
+    }
+
+    /// 读取日志中的全部消息，无法解析的行会被跳过
+    pub async fn read_all(&self) -> Result<Vec<Message>> {
+        let _guard = self.lock.lock().await;
+        Ok(parse_messages(&self.read_lines().await?))
+    }
+
+    /// 对日志中的每条消息执行重放，只保留重放失败的消息
+    ///
+    /// 重放期间持有锁，新的死信会等待重放结束后再写入，不会丢失
+    pub(crate) async fn replay<F, Fut>(&self, mut replay: F) -> Result<(usize, usize)>
+    where
+        F: FnMut(Message) -> Fut,
+        Fut: std::future::Future<Output = Result<()>>,
+    {
+        let _guard = self.lock.lock().await;
+
+        let lines = self.read_lines().await?;
+        if lines.is_empty() {
+            return Ok((0, 0));
+        }
+
+        let mut replayed = 0;
+        let mut remaining = Vec::new();
+        for line in lines {
+            let message = match serde_json::from_str::<Message>(&line) {
+                Ok(message) => message,
+                Err(e) => {
+                    // 保留无法解析的行，避免数据被静默丢弃
+                    warn!("死信日志中存在无法解析的记录: {}", e);
+                    remaining.push(line);
+                    continue;
+                }
+            };
+
+            match replay(message).await {
+                Ok(()) => replayed += 1,
+                Err(e) => {
+                    warn!("重放死信消息失败: {:#}", e);
+                    remaining.push(line);
+                }
+            }
+        }
+
+        self.write_lines(&remaining).await?;
+        Ok((replayed, remaining.len()))
+    }
+
+    async fn read_lines(&self) -> Result<Vec<String>> {
+        match tokio::fs::read_to_string(&self.path).await {
+            Ok(content) => Ok(content
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(str::to_string)
+                .collect()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(anyhow::Error::from(e).context("Failed to read dead letter log")),
+        }
+    }
+
+    /// 通过临时文件替换日志内容，写入中途失败不会破坏原有日志
+    async fn write_lines(&self, lines: &[String]) -> Result<()> {
+        if lines.is_empty() {
+            return match tokio::fs::remove_file(&self.path).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    Err(anyhow::Error::from(e).context("Failed to remove dead letter log"))
+                }
+                _ => Ok(()),
+            };
+        }
+
+        let mut content = lines.join("\n");
+        content.push('\n');
+
+        let temp_path = self.path.with_extension("jsonl.tmp");
+        tokio::fs::write(&temp_path, content)
+            .await
+            .context("Failed to write dead letter log")?;
+        tokio::fs::rename(&temp_path, &self.path)
+            .await
+            .context("Failed to replace dead letter log")?;
+
+        Ok(())
+    }
+}
+
+fn parse_messages(lines: &[String]) -> Vec<Message> {
+    lines
+        .iter()
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(message) => Some(message),
+            Err(e) => {
+                warn!("死信日志中存在无法解析的记录: {}", e);
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustchat_types::UserId;
+
+    #[tokio::test]
+    async fn test_log_is_bounded() {
+        let path = std::env::temp_dir().join(format!("rustchat-dead-letter-{}.jsonl", UserId::new()));
+        let log = DeadLetterLog::new(&path, 2);
+
+        let messages: Vec<Message> = (0..3)
+            .map(|i| Message::new_text(UserId::new(), format!("message {}", i), None))
+            .collect();
+        for message in &messages {
+            log.append(message).await.unwrap();
+        }
+
+        // 只保留最新的两条
+        let ids: Vec<_> = log.read_all().await.unwrap().into_iter().map(|m| m.id).collect();
+        assert_eq!(ids, vec![messages[1].id.clone(), messages[2].id.clone()]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,7 +1,13 @@
 pub mod user;
+pub mod backend;
 pub mod database;
+pub mod dead_letter;
 pub mod bot;
+pub mod migrations;
 
 pub use user::{UserConfig, UserConfigManager, generate_user_id};
-pub use database::{MessageDatabase, MessageRecord};
-pub use bot::{Bot, BotManager, BotResponse, BotAction, BotConfig, EchoBot};
+pub use database::{BatchInsertResult, MessageDatabase, MessageRecord, ReactionSummary, ReceiptLatencyStats, UserMessageStats};
+pub use backend::{connect_pool, row_bool, DatabaseBackend};
+pub use dead_letter::DeadLetterLog;
+pub use migrations::{run_migrations, Migration};
+pub use bot::{Bot, BotManager, BotResponse, BotAction, BotConfig, CommandBot, DiceError, DiceRoll, EchoBot, GreeterBot};
@@ -0,0 +1,145 @@
+use crate::backend::DatabaseBackend;
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use sqlx::{AnyPool, Row};
+use tracing::info;
+
+/// 一个版本的数据库结构变更
+///
+/// 同一版本的所有语句在一个事务中执行，成功后记录到 `schema_migrations` 表。
+/// 已发布的迁移不能修改，新的列或表通过追加更高版本的迁移添加。
+/// 语句按SQLite编写，在PostgreSQL上执行前经过 [`DatabaseBackend::translate_ddl`] 改写
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+    pub version: i64,
+    pub description: &'static str,
+    pub statements: &'static [&'static str],
+}
+
+/// 对指定范围（如 `messages`、`auth`）按版本顺序执行尚未应用的迁移，返回执行后的版本号
+///
+/// 多个模块共用同一个数据库文件时以 `scope` 区分各自的版本
+pub async fn run_migrations(pool: &AnyPool, scope: &str, migrations: &[Migration]) -> Result<i64> {
+    if !migrations.windows(2).all(|pair| pair[0].version < pair[1].version) {
+        bail!("Migrations for {} must be in strictly ascending version order", scope);
+    }
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS schema_migrations (
+            scope TEXT NOT NULL,
+            version INTEGER NOT NULL,
+            description TEXT NOT NULL,
+            applied_at TEXT NOT NULL,
+            PRIMARY KEY (scope, version)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to create schema_migrations table")?;
+
+    let backend = DatabaseBackend::of(pool);
+    let applied = current_version(pool, scope).await?;
+    let mut current = applied;
+
+    for migration in migrations.iter().filter(|migration| migration.version > applied) {
+        let mut tx = pool.begin().await.context("Failed to begin migration transaction")?;
+
+        for statement in migration.statements {
+            sqlx::query(&backend.translate_ddl(statement))
+                .execute(&mut *tx)
+                .await
+                .with_context(|| format!("Failed to apply {} migration {}", scope, migration.version))?;
+        }
+
+        sqlx::query("INSERT INTO schema_migrations (scope, version, description, applied_at) VALUES ($1, $2, $3, $4)")
+            .bind(scope)
+            .bind(migration.version)
+            .bind(migration.description)
+            .bind(Utc::now().to_rfc3339())
+            .execute(&mut *tx)
+            .await
+            .context("Failed to record migration")?;
+
+        tx.commit().await.context("Failed to commit migration")?;
+
+        info!("已应用数据库迁移 {} v{}: {}", scope, migration.version, migration.description);
+        current = migration.version;
+    }
+
+    Ok(current)
+}
+
+/// 查询指定范围已应用的最高版本，尚未执行过迁移时为0
+pub async fn current_version(pool: &AnyPool, scope: &str) -> Result<i64> {
+    let row = sqlx::query("SELECT COALESCE(MAX(version), 0) AS version FROM schema_migrations WHERE scope = $1")
+        .bind(scope)
+        .fetch_one(pool)
+        .await
+        .context("Failed to read schema version")?;
+
+    Ok(row.get("version"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::connect_pool;
+
+    const MIGRATIONS: &[Migration] = &[
+        Migration {
+            version: 1,
+            description: "create notes",
+            statements: &["CREATE TABLE notes (id INTEGER PRIMARY KEY, body TEXT NOT NULL)"],
+        },
+        Migration {
+            version: 2,
+            description: "add notes.author",
+            statements: &["ALTER TABLE notes ADD COLUMN author TEXT"],
+        },
+    ];
+
+    async fn memory_pool() -> AnyPool {
+        connect_pool("sqlite::memory:", Some(1))
+            .await
+            .expect("Failed to connect to memory database")
+    }
+
+    #[tokio::test]
+    async fn test_migrations_are_idempotent() {
+        let pool = memory_pool().await;
+
+        assert_eq!(run_migrations(&pool, "notes", &MIGRATIONS[..1]).await.unwrap(), 1);
+        // 再次执行时跳过已应用的版本，只应用新追加的迁移
+        assert_eq!(run_migrations(&pool, "notes", MIGRATIONS).await.unwrap(), 2);
+        assert_eq!(run_migrations(&pool, "notes", MIGRATIONS).await.unwrap(), 2);
+
+        sqlx::query("INSERT INTO notes (body, author) VALUES ('hi', 'alice')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        // 不同范围的版本互不影响
+        assert_eq!(current_version(&pool, "other").await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_failed_migration_is_rolled_back() {
+        let pool = memory_pool().await;
+        run_migrations(&pool, "notes", &MIGRATIONS[..1]).await.unwrap();
+
+        let broken = [Migration {
+            version: 2,
+            description: "broken",
+            statements: &["CREATE TABLE tags (name TEXT)", "ALTER TABLE missing ADD COLUMN x TEXT"],
+        }];
+        assert!(run_migrations(&pool, "notes", &broken).await.is_err());
+
+        // 同一版本中已执行的语句随事务回滚，版本号不变
+        assert_eq!(current_version(&pool, "notes").await.unwrap(), 1);
+        assert!(sqlx::query("SELECT * FROM tags").fetch_all(&pool).await.is_err());
+
+        let unordered = [MIGRATIONS[1], MIGRATIONS[0]];
+        assert!(run_migrations(&pool, "notes", &unordered).await.is_err());
+    }
+}
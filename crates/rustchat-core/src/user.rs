@@ -1,7 +1,8 @@
 use anyhow::{Context, Result};
-use rustchat_types::UserId;
+use chrono::{DateTime, Utc};
+use rustchat_types::{Message, UserId};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tokio::fs;
 
 /// 用户配置信息
@@ -13,6 +14,28 @@ pub struct UserConfig {
     pub nickname: Option<String>,
     /// 配置文件版本
     pub version: String,
+    /// 最后已读消息的时间戳（用于重启后标记未读消息）
+    #[serde(default)]
+    pub last_read_at: Option<DateTime<Utc>>,
+    /// CLI颜色主题名称
+    #[serde(default)]
+    pub theme: Option<String>,
+    /// CLI时间戳使用12小时制
+    #[serde(default)]
+    pub clock_12h: bool,
+    /// CLI时间戳对不是今天的消息显示日期
+    #[serde(default = "default_show_date")]
+    pub show_date: bool,
+    /// 登录账户的邮箱
+    #[serde(default)]
+    pub account_email: Option<String>,
+    /// 登录账户的刷新令牌（用于重启后自动登录）
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+}
+
+fn default_show_date() -> bool {
+    true
 }
 
 impl UserConfig {
@@ -22,6 +45,12 @@ impl UserConfig {
             user_id: UserId::new(),
             nickname: None,
             version: "0.1.0".to_string(),
+            last_read_at: None,
+            theme: None,
+            clock_12h: false,
+            show_date: true,
+            account_email: None,
+            refresh_token: None,
         }
     }
 
@@ -30,6 +59,23 @@ impl UserConfig {
         self.nickname = Some(nickname);
         self
     }
+
+    /// 将已读标记推进到指定时间，标记只会前进不会后退
+    pub fn mark_read_up_to(&mut self, timestamp: DateTime<Utc>) -> bool {
+        match self.last_read_at {
+            Some(last_read_at) if last_read_at >= timestamp => false,
+            _ => {
+                self.last_read_at = Some(timestamp);
+                true
+            }
+        }
+    }
+
+    /// 获取第一条未读消息的位置（消息按时间升序排列）
+    pub fn first_unread_index(&self, messages: &[Message]) -> Option<usize> {
+        let last_read_at = self.last_read_at?;
+        messages.iter().position(|msg| msg.timestamp > last_read_at)
+    }
 }
 
 impl Default for UserConfig {
@@ -59,6 +105,11 @@ impl UserConfigManager {
         Ok(home_dir.join(".rustchat"))
     }
 
+    /// 配置目录路径（~/.rustchat）
+    pub fn config_dir(&self) -> &Path {
+        &self.config_dir
+    }
+
     /// 获取配置文件路径
     fn get_config_file_path(&self) -> PathBuf {
         self.config_dir.join("config.json")
@@ -124,6 +175,50 @@ impl UserConfigManager {
         Ok(config)
     }
 
+    /// 更新颜色主题
+    pub async fn update_theme(&self, theme: String) -> Result<UserConfig> {
+        let mut config = self.load_config().await?;
+        config.theme = Some(theme);
+        self.save_config(&config).await?;
+        Ok(config)
+    }
+
+    /// 更新时间戳显示格式
+    pub async fn update_timestamp_format(&self, clock_12h: bool, show_date: bool) -> Result<UserConfig> {
+        let mut config = self.load_config().await?;
+        config.clock_12h = clock_12h;
+        config.show_date = show_date;
+        self.save_config(&config).await?;
+        Ok(config)
+    }
+
+    /// 更新已读标记
+    pub async fn update_last_read(&self, timestamp: DateTime<Utc>) -> Result<UserConfig> {
+        let mut config = self.load_config().await?;
+        if config.mark_read_up_to(timestamp) {
+            self.save_config(&config).await?;
+        }
+        Ok(config)
+    }
+
+    /// 保存登录信息
+    pub async fn save_login(&self, email: String, refresh_token: String) -> Result<UserConfig> {
+        let mut config = self.load_config().await?;
+        config.account_email = Some(email);
+        config.refresh_token = Some(refresh_token);
+        self.save_config(&config).await?;
+        Ok(config)
+    }
+
+    /// 清除登录信息
+    pub async fn clear_login(&self) -> Result<UserConfig> {
+        let mut config = self.load_config().await?;
+        config.account_email = None;
+        config.refresh_token = None;
+        self.save_config(&config).await?;
+        Ok(config)
+    }
+
     /// 获取用户ID，如果不存在则创建新的
     pub async fn get_or_create_user_id(&self) -> Result<UserId> {
         let config = self.load_config().await?;
@@ -170,4 +265,51 @@ mod tests {
         assert_eq!(config.user_id, deserialized.user_id);
         assert_eq!(config.nickname, deserialized.nickname);
     }
+
+    #[tokio::test]
+    async fn test_config_without_last_read_deserializes() {
+        let json = r#"{"user_id":"550e8400-e29b-41d4-a716-446655440000","nickname":null,"version":"0.1.0"}"#;
+        let config: UserConfig = serde_json::from_str(json).expect("Should deserialize");
+        assert!(config.last_read_at.is_none());
+        // 旧配置文件使用默认的时间戳格式
+        assert!(!config.clock_12h);
+        assert!(config.show_date);
+    }
+
+    #[tokio::test]
+    async fn test_mark_read_only_moves_forward() {
+        let mut config = UserConfig::new();
+        let earlier = Utc::now() - chrono::Duration::minutes(5);
+        let later = Utc::now();
+
+        assert!(config.mark_read_up_to(later));
+        assert_eq!(config.last_read_at, Some(later));
+
+        // 更早的时间不会回退标记
+        assert!(!config.mark_read_up_to(earlier));
+        assert_eq!(config.last_read_at, Some(later));
+    }
+
+    #[tokio::test]
+    async fn test_first_unread_index() {
+        let user_id = UserId::new();
+        let base = Utc::now();
+        let messages: Vec<Message> = (0..4)
+            .map(|i| {
+                let mut msg = Message::new_text(user_id.clone(), format!("msg {}", i), None);
+                msg.timestamp = base + chrono::Duration::seconds(i);
+                msg
+            })
+            .collect();
+
+        let mut config = UserConfig::new();
+        // 没有已读标记时不显示分隔线
+        assert_eq!(config.first_unread_index(&messages), None);
+
+        config.mark_read_up_to(messages[1].timestamp);
+        assert_eq!(config.first_unread_index(&messages), Some(2));
+
+        config.mark_read_up_to(messages[3].timestamp);
+        assert_eq!(config.first_unread_index(&messages), None);
+    }
 }
@@ -0,0 +1,273 @@
+// 原生聊天连接：在Rust端维护WebSocket连接，令牌不暴露给前端
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::Emitter;
+use tokio::sync::mpsc;
+use tokio_tungstenite::{connect_async, tungstenite::Message as WsMessage};
+
+/// 聊天事件名（服务器推送的事件）
+pub const CHAT_EVENT: &str = "chat_event";
+/// 连接状态变化事件名
+pub const CHAT_CONNECTION_EVENT: &str = "chat_connection";
+
+/// 重连策略（与CLI的默认值保持一致）
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    pub max_attempts: u32,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub backoff_factor: f64,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 10,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+            backoff_factor: 2.0,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// 指数退避的下一次重连间隔
+    fn next_delay(&self, current: Duration) -> Duration {
+        Duration::from_millis((current.as_millis() as f64 * self.backoff_factor) as u64)
+            .min(self.max_delay)
+    }
+}
+
+/// 服务器推送的事件，原样转发给前端
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatEvent {
+    pub event: String,
+    #[serde(default)]
+    pub data: serde_json::Value,
+}
+
+/// 连接状态
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum ConnectionState {
+    Connecting { attempt: u32 },
+    Connected,
+    Reconnecting { attempt: u32, retry_in_ms: u64, error: Option<String> },
+    Disconnected { error: Option<String> },
+}
+
+/// 发往连接任务的指令
+enum Outgoing {
+    Text(String),
+    Close,
+}
+
+/// 正在运行的聊天连接
+pub struct ChatConnection {
+    outgoing: mpsc::UnboundedSender<Outgoing>,
+    connected: Arc<AtomicBool>,
+}
+
+impl ChatConnection {
+    /// 启动连接任务，断线后按策略自动重连
+    pub fn start(app_handle: tauri::AppHandle, url: String, policy: ReconnectPolicy) -> Self {
+        let (outgoing, outgoing_rx) = mpsc::unbounded_channel();
+        let connected = Arc::new(AtomicBool::new(false));
+
+        tauri::async_runtime::spawn(run_connection_loop(
+            app_handle,
+            url,
+            policy,
+            outgoing_rx,
+            connected.clone(),
+        ));
+
+        Self { outgoing, connected }
+    }
+
+    /// 发送文本消息
+    pub fn send_message(&self, content: String, nickname: Option<String>) -> Result<(), String> {
+        if !self.connected.load(Ordering::SeqCst) {
+            return Err("Not connected to chat server".to_string());
+        }
+
+        let payload = serde_json::json!({
+            "type": "SendMessage",
+            "data": {
+                "content": content,
+                "nickname": nickname,
+                "client_timestamp": chrono::Utc::now().to_rfc3339()
+            }
+        });
+        self.outgoing
+            .send(Outgoing::Text(payload.to_string()))
+            .map_err(|_| "Chat connection task has stopped".to_string())
+    }
+
+    /// 关闭连接并停止重连
+    pub fn close(self) {
+        let _ = self.outgoing.send(Outgoing::Close);
+    }
+}
+
+/// 在WebSocket地址上附加访问令牌参数
+pub fn url_with_token(url: &str, token: Option<&str>) -> Result<String, String> {
+    let mut parsed = url::Url::parse(url).map_err(|e| format!("Invalid server URL: {}", e))?;
+    if !matches!(parsed.scheme(), "ws" | "wss") {
+        return Err(format!("Unsupported URL scheme: {}", parsed.scheme()));
+    }
+    if let Some(token) = token.filter(|token| !token.is_empty()) {
+        parsed.query_pairs_mut().append_pair("token", token);
+    }
+    Ok(parsed.to_string())
+}
+
+fn emit_state(app_handle: &tauri::AppHandle, state: ConnectionState) {
+    if let Err(e) = app_handle.emit(CHAT_CONNECTION_EVENT, &state) {
+        eprintln!("Failed to emit connection state: {}", e);
+    }
+}
+
+/// 连接循环：连接、运行会话、断线后退避重连
+async fn run_connection_loop(
+    app_handle: tauri::AppHandle,
+    url: String,
+    policy: ReconnectPolicy,
+    mut outgoing_rx: mpsc::UnboundedReceiver<Outgoing>,
+    connected: Arc<AtomicBool>,
+) {
+    let mut attempt = 0;
+    let mut retry_delay = policy.initial_delay;
+
+    loop {
+        emit_state(&app_handle, ConnectionState::Connecting { attempt: attempt + 1 });
+
+        let error = match connect_async(url.as_str()).await {
+            Ok((ws_stream, _)) => {
+                attempt = 0;
+                retry_delay = policy.initial_delay;
+                connected.store(true, Ordering::SeqCst);
+                emit_state(&app_handle, ConnectionState::Connected);
+
+                let result = run_session(&app_handle, ws_stream, &mut outgoing_rx).await;
+                connected.store(false, Ordering::SeqCst);
+
+                match result {
+                    SessionEnd::ClosedByClient => {
+                        emit_state(&app_handle, ConnectionState::Disconnected { error: None });
+                        return;
+                    }
+                    SessionEnd::Lost(error) => error,
+                }
+            }
+            Err(e) => Some(e.to_string()),
+        };
+
+        attempt += 1;
+        if attempt > policy.max_attempts {
+            emit_state(
+                &app_handle,
+                ConnectionState::Disconnected {
+                    error: Some(format!("Gave up after {} reconnect attempts", policy.max_attempts)),
+                },
+            );
+            return;
+        }
+
+        emit_state(
+            &app_handle,
+            ConnectionState::Reconnecting {
+                attempt,
+                retry_in_ms: retry_delay.as_millis() as u64,
+                error,
+            },
+        );
+
+        // 等待重连期间仍然响应断开请求，期间发送的消息直接丢弃
+        let sleep = tokio::time::sleep(retry_delay);
+        tokio::pin!(sleep);
+        loop {
+            tokio::select! {
+                _ = &mut sleep => break,
+                outgoing = outgoing_rx.recv() => match outgoing {
+                    Some(Outgoing::Text(_)) => continue,
+                    Some(Outgoing::Close) | None => {
+                        emit_state(&app_handle, ConnectionState::Disconnected { error: None });
+                        return;
+                    }
+                },
+            }
+        }
+
+        retry_delay = policy.next_delay(retry_delay);
+    }
+}
+
+/// 会话结束原因
+enum SessionEnd {
+    ClosedByClient,
+    Lost(Option<String>),
+}
+
+/// 运行一次连接会话，转发服务器事件并发送前端消息
+async fn run_session<S>(
+    app_handle: &tauri::AppHandle,
+    ws_stream: S,
+    outgoing_rx: &mut mpsc::UnboundedReceiver<Outgoing>,
+) -> SessionEnd
+where
+    S: futures_util::Stream<Item = Result<WsMessage, tokio_tungstenite::tungstenite::Error>>
+        + futures_util::Sink<WsMessage, Error = tokio_tungstenite::tungstenite::Error>
+        + Unpin,
+{
+    let (mut ws_sender, mut ws_receiver) = ws_stream.split();
+
+    loop {
+        tokio::select! {
+            outgoing = outgoing_rx.recv() => match outgoing {
+                Some(Outgoing::Text(text)) => {
+                    if let Err(e) = ws_sender.send(WsMessage::Text(text.into())).await {
+                        return SessionEnd::Lost(Some(e.to_string()));
+                    }
+                }
+                Some(Outgoing::Close) | None => {
+                    let _ = ws_sender.send(WsMessage::Close(None)).await;
+                    return SessionEnd::ClosedByClient;
+                }
+            },
+            incoming = ws_receiver.next() => match incoming {
+                Some(Ok(WsMessage::Text(text))) => {
+                    let event = match serde_json::from_str::<ChatEvent>(&text) {
+                        Ok(event) => event,
+                        Err(e) => {
+                            eprintln!("Failed to parse chat event: {}", e);
+                            continue;
+                        }
+                    };
+
+                    // 服务器心跳直接在后端回复
+                    if event.event == "Ping" {
+                        let pong = serde_json::json!({ "type": "Pong" }).to_string();
+                        if let Err(e) = ws_sender.send(WsMessage::Text(pong.into())).await {
+                            return SessionEnd::Lost(Some(e.to_string()));
+                        }
+                        continue;
+                    }
+
+                    if let Err(e) = app_handle.emit(CHAT_EVENT, &event) {
+                        eprintln!("Failed to emit chat event: {}", e);
+                    }
+                }
+                Some(Ok(WsMessage::Close(frame))) => {
+                    return SessionEnd::Lost(frame.map(|frame| frame.reason.to_string()));
+                }
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return SessionEnd::Lost(Some(e.to_string())),
+                None => return SessionEnd::Lost(None),
+            },
+        }
+    }
+}
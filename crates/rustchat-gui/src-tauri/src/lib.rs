@@ -1,4 +1,11 @@
+mod chat;
+mod log_rotation;
+mod settings_store;
+mod tray;
+mod window_state;
+
 use tauri::{Manager, Emitter};
+use tauri_plugin_notification::{NotificationExt, PermissionState};
 use std::collections::HashMap;
 use std::sync::Mutex;
 use std::io::Write;
@@ -6,6 +13,7 @@ use std::io::Write;
 // 全局状态管理
 pub struct AppState {
     pub settings: Mutex<HashMap<String, serde_json::Value>>,
+    pub chat: Mutex<Option<chat::ChatConnection>>,
 }
 
 impl Default for AppState {
@@ -15,9 +23,11 @@ impl Default for AppState {
         default_settings.insert("notifications".to_string(), serde_json::json!(true));
         default_settings.insert("server_url".to_string(), serde_json::json!("http://localhost:3000"));
         default_settings.insert("auto_connect".to_string(), serde_json::json!(true));
+        default_settings.insert("close_to_tray".to_string(), serde_json::json!(false));
         
         Self {
             settings: Mutex::new(default_settings),
+            chat: Mutex::new(None),
         }
     }
 }
@@ -40,6 +50,49 @@ fn get_app_info() -> serde_json::Value {
     })
 }
 
+// 可选的主题
+const THEMES: &[&str] = &["light", "dark", "auto"];
+
+// 按设置名校验设置值，未知的设置项不做限制
+fn validate_setting(key: &str, value: &serde_json::Value) -> Result<(), String> {
+    match key {
+        "server_url" => {
+            let url = value
+                .as_str()
+                .ok_or_else(|| format!("Setting '{}' must be a string", key))?;
+            let parsed = url::Url::parse(url)
+                .map_err(|e| format!("Invalid server URL '{}': {}", url, e))?;
+            if !matches!(parsed.scheme(), "http" | "https" | "ws" | "wss") {
+                return Err(format!("Unsupported server URL scheme: {}", parsed.scheme()));
+            }
+        }
+        "theme" => {
+            let theme = value
+                .as_str()
+                .ok_or_else(|| format!("Setting '{}' must be a string", key))?;
+            if !THEMES.contains(&theme) {
+                return Err(format!("Unknown theme '{}', expected one of: {}", theme, THEMES.join(", ")));
+            }
+        }
+        "notifications" | "auto_connect" | "close_to_tray" if !value.is_boolean() => {
+            return Err(format!("Setting '{}' must be a boolean, got {}", key, value));
+        }
+        "log_max_size" if value.as_u64().is_none_or(|size| size == 0) => {
+            return Err(format!("Setting '{}' must be a positive integer, got {}", key, value));
+        }
+        "log_backups" if value.as_u64().is_none_or(|backups| backups > 100) => {
+            return Err(format!("Setting '{}' must be an integer between 0 and 100, got {}", key, value));
+        }
+        window_state::WINDOW_GEOMETRY_KEY => {
+            serde_json::from_value::<window_state::WindowGeometry>(value.clone())
+                .map_err(|e| format!("Invalid window geometry: {}", e))?;
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
 // 保存用户设置到内存和文件
 #[tauri::command]
 async fn save_setting(
@@ -48,6 +101,9 @@ async fn save_setting(
     key: String,
     value: serde_json::Value,
 ) -> Result<(), String> {
+    // 校验失败时保持原有设置不变
+    validate_setting(&key, &value)?;
+    
     // 更新内存中的设置
     {
         let mut settings = state.settings.lock().map_err(|e| format!("Failed to lock settings: {}", e))?;
@@ -55,37 +111,35 @@ async fn save_setting(
     }
     
     // 保存到文件
+    write_setting_to_file(&app_handle, key, value)
+}
+
+// 设置文件路径
+fn settings_file_path(app_handle: &tauri::AppHandle) -> Result<std::path::PathBuf, String> {
     let app_dir = app_handle
         .path()
         .app_data_dir()
         .map_err(|e| format!("Failed to get app data directory: {}", e))?;
     
-    // 确保目录存在
-    std::fs::create_dir_all(&app_dir).map_err(|e| format!("Failed to create app directory: {}", e))?;
-    
-    let settings_path = app_dir.join("settings.json");
+    Ok(app_dir.join("settings.json"))
+}
+
+// 将单个设置合并写入设置文件
+fn write_setting_to_file(
+    app_handle: &tauri::AppHandle,
+    key: String,
+    value: serde_json::Value,
+) -> Result<(), String> {
+    let settings_path = settings_file_path(app_handle)?;
     
-    // 读取现有设置或创建新的
-    let mut all_settings: HashMap<String, serde_json::Value> = if settings_path.exists() {
-        let settings_str = std::fs::read_to_string(&settings_path)
-            .map_err(|e| format!("Failed to read settings file: {}", e))?;
-        serde_json::from_str(&settings_str)
-            .map_err(|e| format!("Failed to parse settings: {}", e))?
-    } else {
-        HashMap::new()
-    };
+    // 读取现有设置（文件损坏时从空设置开始）
+    let mut all_settings = settings_store::read_or_default(&settings_path);
     
     // 更新设置
     all_settings.insert(key, value);
     
-    // 写回文件
-    let settings_str = serde_json::to_string_pretty(&all_settings)
-        .map_err(|e| format!("Failed to serialize settings: {}", e))?;
-    
-    std::fs::write(settings_path, settings_str)
-        .map_err(|e| format!("Failed to write settings: {}", e))?;
-    
-    Ok(())
+    // 原子地写回文件
+    settings_store::write(&settings_path, &all_settings)
 }
 
 // 获取单个设置
@@ -114,21 +168,10 @@ async fn load_settings(
     app_handle: tauri::AppHandle,
     state: tauri::State<'_, AppState>,
 ) -> Result<HashMap<String, serde_json::Value>, String> {
-    let app_dir = app_handle
-        .path()
-        .app_data_dir()
-        .map_err(|e| format!("Failed to get app data directory: {}", e))?;
-    
-    let settings_path = app_dir.join("settings.json");
+    let settings_path = settings_file_path(&app_handle)?;
     
-    let loaded_settings: HashMap<String, serde_json::Value> = if settings_path.exists() {
-        let settings_str = std::fs::read_to_string(settings_path)
-            .map_err(|e| format!("Failed to read settings: {}", e))?;
-        serde_json::from_str(&settings_str)
-            .map_err(|e| format!("Failed to parse settings: {}", e))?
-    } else {
-        HashMap::new()
-    };
+    // 设置文件损坏时使用默认设置，不阻止应用启动
+    let loaded_settings = settings_store::read_or_default(&settings_path);
     
     // 合并默认设置和加载的设置
     {
@@ -155,15 +198,11 @@ async fn reset_settings(
         settings.insert("notifications".to_string(), serde_json::json!(true));
         settings.insert("server_url".to_string(), serde_json::json!("http://localhost:3000"));
         settings.insert("auto_connect".to_string(), serde_json::json!(true));
+        settings.insert("close_to_tray".to_string(), serde_json::json!(false));
     }
     
     // 删除设置文件
-    let app_dir = app_handle
-        .path()
-        .app_data_dir()
-        .map_err(|e| format!("Failed to get app data directory: {}", e))?;
-    
-    let settings_path = app_dir.join("settings.json");
+    let settings_path = settings_file_path(&app_handle)?;
     if settings_path.exists() {
         std::fs::remove_file(settings_path)
             .map_err(|e| format!("Failed to remove settings file: {}", e))?;
@@ -179,17 +218,38 @@ async fn show_notification(
     title: String,
     body: String,
 ) -> Result<(), String> {
-    // 注意：在实际应用中，您可能想要使用 tauri-plugin-notification
-    // 这里我们使用一个简单的实现
-    println!("Notification: {} - {}", title, body);
-    
-    // 可以发送事件到前端
+    // 同时发送事件到前端，即使系统通知不可用前端也能响应
     app_handle.emit("notification", serde_json::json!({
         "title": title,
         "body": body,
         "timestamp": chrono::Utc::now().to_rfc3339()
     })).map_err(|e| format!("Failed to emit notification event: {}", e))?;
     
+    let notification = app_handle.notification();
+    
+    // 首次使用时请求通知权限
+    let mut permission = notification
+        .permission_state()
+        .map_err(|e| format!("Failed to query notification permission: {}", e))?;
+    if !matches!(permission, PermissionState::Granted) {
+        permission = notification
+            .request_permission()
+            .map_err(|e| format!("Failed to request notification permission: {}", e))?;
+    }
+    if !matches!(permission, PermissionState::Granted) {
+        return Err(format!(
+            "Notification permission denied by the system ({:?})",
+            permission
+        ));
+    }
+    
+    notification
+        .builder()
+        .title(&title)
+        .body(&body)
+        .show()
+        .map_err(|e| format!("Failed to show notification: {}", e))?;
+    
     Ok(())
 }
 
@@ -241,8 +301,10 @@ async fn get_app_log_dir(app_handle: tauri::AppHandle) -> Result<String, String>
 #[tauri::command]
 async fn write_log(
     app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
     level: String,
     message: String,
+    format: Option<String>,
 ) -> Result<(), String> {
     let log_dir = app_handle
         .path()
@@ -253,7 +315,23 @@ async fn write_log(
     
     let log_file = log_dir.join("rustchat.log");
     let timestamp = chrono::Utc::now().to_rfc3339();
-    let log_entry = format!("[{}] [{}] {}\n", timestamp, level.to_uppercase(), message);
+    // 默认输出文本格式，json 时输出一行JSON（JSON Lines）
+    let log_entry = match format.as_deref().unwrap_or("text") {
+        "text" => format!("[{}] [{}] {}\n", timestamp, level.to_uppercase(), message),
+        "json" => {
+            let entry = serde_json::json!({
+                "ts": timestamp,
+                "level": level.to_lowercase(),
+                "msg": message
+            });
+            format!("{}\n", entry)
+        }
+        other => return Err(format!("Unknown log format: {}", other)),
+    };
+    
+    // 超过大小上限时先轮转
+    log_rotation::rotate_if_needed(&log_file, log_rotation_policy(&state)?)
+        .map_err(|e| format!("Failed to rotate log file: {}", e))?;
     
     std::fs::OpenOptions::new()
         .create(true)
@@ -266,6 +344,24 @@ async fn write_log(
     Ok(())
 }
 
+// 从设置中读取日志轮转配置（log_max_size 字节数、log_backups 备份数）
+fn log_rotation_policy(state: &AppState) -> Result<log_rotation::RotationPolicy, String> {
+    let settings = state.settings.lock().map_err(|e| format!("Failed to lock settings: {}", e))?;
+    let defaults = log_rotation::RotationPolicy::default();
+    
+    Ok(log_rotation::RotationPolicy {
+        max_size: settings
+            .get("log_max_size")
+            .and_then(|value| value.as_u64())
+            .unwrap_or(defaults.max_size),
+        backups: settings
+            .get("log_backups")
+            .and_then(|value| value.as_u64())
+            .map(|backups| backups as u32)
+            .unwrap_or(defaults.backups),
+    })
+}
+
 // 读取日志文件（最近N行）
 #[tauri::command]
 async fn read_logs(
@@ -347,10 +443,15 @@ async fn set_window_state(
     app_handle: tauri::AppHandle,
     action: String,
 ) -> Result<(), String> {
+    apply_window_action(&app_handle, &action)
+}
+
+// 对主窗口执行操作（命令和托盘菜单共用）
+fn apply_window_action(app_handle: &tauri::AppHandle, action: &str) -> Result<(), String> {
     let main_window = app_handle.get_webview_window("main")
         .ok_or("Main window not found")?;
     
-    match action.as_str() {
+    match action {
         "minimize" => main_window.minimize().map_err(|e| format!("Failed to minimize: {}", e))?,
         "maximize" => main_window.maximize().map_err(|e| format!("Failed to maximize: {}", e))?,
         "unmaximize" => main_window.unmaximize().map_err(|e| format!("Failed to unmaximize: {}", e))?,
@@ -364,6 +465,12 @@ async fn set_window_state(
     Ok(())
 }
 
+// 在托盘提示中显示未读消息数量
+#[tauri::command]
+fn set_tray_unread(app_handle: tauri::AppHandle, count: u32) -> Result<(), String> {
+    tray::set_unread(&app_handle, count)
+}
+
 // 获取和设置窗口大小
 #[tauri::command]
 async fn get_window_size(app_handle: tauri::AppHandle) -> Result<serde_json::Value, String> {
@@ -446,12 +553,7 @@ async fn export_settings(
     app_handle: tauri::AppHandle,
     file_path: String,
 ) -> Result<(), String> {
-    let app_dir = app_handle
-        .path()
-        .app_data_dir()
-        .map_err(|e| format!("Failed to get app data directory: {}", e))?;
-    
-    let settings_path = app_dir.join("settings.json");
+    let settings_path = settings_file_path(&app_handle)?;
     
     if settings_path.exists() {
         std::fs::copy(settings_path, file_path)
@@ -482,6 +584,15 @@ async fn import_settings(
     let imported_settings: HashMap<String, serde_json::Value> = serde_json::from_str(&settings_content)
         .map_err(|e| format!("Invalid settings file format: {}", e))?;
     
+    // 任一设置无效时拒绝整个文件，不修改现有设置
+    for (key, value) in imported_settings.iter() {
+        validate_setting(key, value)?;
+    }
+    
+    // 先原子地保存到应用设置文件，写入失败时不修改内存中的设置
+    let settings_path = settings_file_path(&app_handle)?;
+    settings_store::write(&settings_path, &imported_settings)?;
+    
     // 更新内存中的设置
     {
         let mut settings = state.settings.lock().map_err(|e| format!("Failed to lock settings: {}", e))?;
@@ -491,17 +602,58 @@ async fn import_settings(
         }
     }
     
-    // 保存到应用设置文件
-    let app_dir = app_handle
-        .path()
-        .app_data_dir()
-        .map_err(|e| format!("Failed to get app data directory: {}", e))?;
+    Ok(())
+}
+
+// 连接聊天服务器（令牌只保存在后端）
+#[tauri::command]
+fn connect_chat(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    url: String,
+    token: Option<String>,
+) -> Result<(), String> {
+    let connect_url = chat::url_with_token(&url, token.as_deref())?;
+    
+    let mut connection = state.chat.lock().map_err(|e| format!("Failed to lock chat state: {}", e))?;
+    
+    // 替换已有的连接
+    if let Some(previous) = connection.take() {
+        previous.close();
+    }
     
-    std::fs::create_dir_all(&app_dir).map_err(|e| format!("Failed to create app directory: {}", e))?;
+    *connection = Some(chat::ChatConnection::start(
+        app_handle,
+        connect_url,
+        chat::ReconnectPolicy::default(),
+    ));
+    
+    Ok(())
+}
+
+// 通过聊天连接发送消息
+#[tauri::command]
+fn send_chat_message(
+    state: tauri::State<'_, AppState>,
+    content: String,
+    nickname: Option<String>,
+) -> Result<(), String> {
+    let connection = state.chat.lock().map_err(|e| format!("Failed to lock chat state: {}", e))?;
+    
+    connection
+        .as_ref()
+        .ok_or("Not connected to chat server")?
+        .send_message(content, nickname)
+}
+
+// 断开聊天连接
+#[tauri::command]
+fn disconnect_chat(state: tauri::State<'_, AppState>) -> Result<(), String> {
+    let mut connection = state.chat.lock().map_err(|e| format!("Failed to lock chat state: {}", e))?;
     
-    let settings_path = app_dir.join("settings.json");
-    std::fs::copy(file_path, settings_path)
-        .map_err(|e| format!("Failed to import settings: {}", e))?;
+    if let Some(connection) = connection.take() {
+        connection.close();
+    }
     
     Ok(())
 }
@@ -521,6 +673,7 @@ pub fn run() {
     
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
+        .plugin(tauri_plugin_notification::init())
         .manage(app_state)
         .invoke_handler(tauri::generate_handler![
             greet,
@@ -542,15 +695,30 @@ pub fn run() {
             set_window_state,
             get_window_size,
             set_window_size,
+            set_tray_unread,
             validate_server_connection,
             export_settings,
             import_settings,
-            open_external_link
+            open_external_link,
+            connect_chat,
+            send_chat_message,
+            disconnect_chat
         ])
         .setup(|app| {
             // 在这里可以进行应用初始化
             println!("🦀 RustChat GUI is starting...");
             
+            // 恢复上次的窗口大小和位置，窗口在配置中默认隐藏，恢复后再显示
+            if let Some(main_window) = app.get_webview_window("main") {
+                if let Err(e) = restore_window_geometry(app.handle(), &main_window) {
+                    eprintln!("Failed to restore window geometry: {}", e);
+                }
+                main_window.show()?;
+            }
+            
+            // 创建系统托盘图标
+            tray::create_tray(app)?;
+            
             // 加载保存的设置
             let app_handle = app.handle().clone();
             tauri::async_runtime::spawn(async move {
@@ -560,12 +728,26 @@ pub fn run() {
             });
             
             Ok(())
-        })        .on_window_event(|_app_handle, event| {
+        })        .on_window_event(|window, event| {
             match event {
-                tauri::WindowEvent::CloseRequested { api: _, .. } => {
+                tauri::WindowEvent::CloseRequested { api, .. } => {
+                    if window.label() == "main" {
+                        if let Err(e) = save_window_geometry(window.app_handle()) {
+                            eprintln!("Failed to save window geometry: {}", e);
+                        }
+
+                        // 开启"关闭到托盘"时隐藏窗口而不是退出
+                        if close_to_tray_enabled(window.app_handle()) {
+                            api.prevent_close();
+                            if let Err(e) = window.hide() {
+                                eprintln!("Failed to hide window: {}", e);
+                            }
+                            return;
+                        }
+                    }
+
                     // 在窗口关闭时可以进行清理工作
                     println!("🦀 RustChat GUI is closing...");
-                    // api.prevent_close(); // 如果需要阻止关闭
                 }
                 _ => {}
             }
@@ -582,3 +764,62 @@ async fn load_settings_on_startup(app_handle: tauri::AppHandle) -> Result<(), Bo
     println!("✅ Settings loaded successfully");
     Ok(())
 }
+
+// 读取设置文件中保存的窗口几何信息
+fn load_window_geometry(app_handle: &tauri::AppHandle) -> Result<Option<window_state::WindowGeometry>, String> {
+    let mut settings = settings_store::read(&settings_file_path(app_handle)?)?;
+
+    settings
+        .remove(window_state::WINDOW_GEOMETRY_KEY)
+        .map(serde_json::from_value)
+        .transpose()
+        .map_err(|e| format!("Invalid window geometry: {}", e))
+}
+
+// 启动时恢复主窗口的大小和位置
+fn restore_window_geometry(
+    app_handle: &tauri::AppHandle,
+    main_window: &tauri::WebviewWindow,
+) -> Result<(), String> {
+    match load_window_geometry(app_handle)? {
+        Some(geometry) => geometry.apply(main_window),
+        None => Ok(()),
+    }
+}
+
+// 关闭时保存主窗口的大小和位置
+fn save_window_geometry(app_handle: &tauri::AppHandle) -> Result<(), String> {
+    let main_window = app_handle.get_webview_window("main")
+        .ok_or("Main window not found")?;
+
+    let previous = load_window_geometry(app_handle).unwrap_or(None);
+    let Some(geometry) = window_state::WindowGeometry::capture(&main_window, previous)? else {
+        return Ok(());
+    };
+    let value = serde_json::to_value(geometry)
+        .map_err(|e| format!("Failed to serialize window geometry: {}", e))?;
+
+    {
+        let state = app_handle.state::<AppState>();
+        let mut settings = state.settings.lock().map_err(|e| format!("Failed to lock settings: {}", e))?;
+        settings.insert(window_state::WINDOW_GEOMETRY_KEY.to_string(), value.clone());
+    }
+
+    write_setting_to_file(app_handle, window_state::WINDOW_GEOMETRY_KEY.to_string(), value)
+}
+
+// 是否启用了关闭到托盘
+fn close_to_tray_enabled(app_handle: &tauri::AppHandle) -> bool {
+    let state = app_handle.state::<AppState>();
+    let settings = match state.settings.lock() {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("Failed to lock settings: {}", e);
+            return false;
+        }
+    };
+    settings
+        .get("close_to_tray")
+        .and_then(|value| value.as_bool())
+        .unwrap_or(false)
+}
@@ -0,0 +1,91 @@
+// 日志文件轮转：超过大小上限时 rustchat.log -> rustchat.log.1 -> rustchat.log.2 ...
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// 默认单个日志文件的大小上限（5 MB）
+pub const DEFAULT_MAX_SIZE: u64 = 5 * 1024 * 1024;
+/// 默认保留的备份数量
+pub const DEFAULT_BACKUPS: u32 = 3;
+
+/// 轮转配置
+#[derive(Debug, Clone, Copy)]
+pub struct RotationPolicy {
+    pub max_size: u64,
+    pub backups: u32,
+}
+
+impl Default for RotationPolicy {
+    fn default() -> Self {
+        Self {
+            max_size: DEFAULT_MAX_SIZE,
+            backups: DEFAULT_BACKUPS,
+        }
+    }
+}
+
+/// 追加前检查日志大小，超过上限时轮转，返回是否发生了轮转
+///
+/// 备份数量为0时直接清空当前文件
+pub fn rotate_if_needed(path: &Path, policy: RotationPolicy) -> io::Result<bool> {
+    let size = match std::fs::metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    if size < policy.max_size {
+        return Ok(false);
+    }
+
+    if policy.backups == 0 {
+        std::fs::remove_file(path)?;
+        return Ok(true);
+    }
+
+    // 从最旧的备份开始依次后移，超出数量的备份被覆盖
+    for index in (1..policy.backups).rev() {
+        let from = backup_path(path, index);
+        if from.exists() {
+            std::fs::rename(&from, backup_path(path, index + 1))?;
+        }
+    }
+    std::fs::rename(path, backup_path(path, 1))?;
+
+    Ok(true)
+}
+
+/// 第N个备份文件的路径，例如 rustchat.log.1
+fn backup_path(path: &Path, index: u32) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", index));
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotation_shifts_backups() {
+        let dir = std::env::temp_dir().join(format!("rustchat-logs-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("rustchat.log");
+        let policy = RotationPolicy { max_size: 10, backups: 2 };
+
+        // 未超过上限时不轮转
+        std::fs::write(&path, "short").unwrap();
+        assert!(!rotate_if_needed(&path, policy).unwrap());
+
+        for content in ["first log!", "second log", "third log!"] {
+            std::fs::write(&path, content).unwrap();
+            assert!(rotate_if_needed(&path, policy).unwrap());
+            assert!(!path.exists());
+        }
+
+        // 只保留最新的两个备份
+        assert_eq!(std::fs::read_to_string(backup_path(&path, 1)).unwrap(), "third log!");
+        assert_eq!(std::fs::read_to_string(backup_path(&path, 2)).unwrap(), "second log");
+        assert!(!backup_path(&path, 3).exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
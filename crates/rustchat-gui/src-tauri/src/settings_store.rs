@@ -0,0 +1,95 @@
+// 设置文件的读写：原子写入，损坏时回退到默认值
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+pub type Settings = HashMap<String, serde_json::Value>;
+
+/// 读取设置文件，文件不存在时返回空设置
+pub fn read(path: &Path) -> Result<Settings, String> {
+    if !path.exists() {
+        return Ok(Settings::new());
+    }
+
+    let settings_str = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read settings: {}", e))?;
+    serde_json::from_str(&settings_str)
+        .map_err(|e| format!("Failed to parse settings: {}", e))
+}
+
+/// 读取设置文件，内容损坏时把原文件改名为 `.corrupt` 备份并返回空设置
+pub fn read_or_default(path: &Path) -> Settings {
+    match read(path) {
+        Ok(settings) => settings,
+        Err(e) => {
+            let backup = sibling(path, "corrupt");
+            eprintln!("{}; falling back to defaults (backup: {})", e, backup.display());
+            if let Err(e) = std::fs::rename(path, &backup) {
+                eprintln!("Failed to back up corrupt settings file: {}", e);
+            }
+            Settings::new()
+        }
+    }
+}
+
+/// 原子地写入设置：先写入临时文件，再重命名覆盖原文件，读取方不会看到写了一半的文件
+pub fn write(path: &Path, settings: &Settings) -> Result<(), String> {
+    let settings_str = serde_json::to_string_pretty(settings)
+        .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+    write_raw(path, settings_str.as_bytes())
+}
+
+/// 原子地写入任意内容（用于导入已校验过的设置文件）
+pub fn write_raw(path: &Path, content: &[u8]) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create app directory: {}", e))?;
+    }
+
+    let temp_path = sibling(path, "tmp");
+    let result = std::fs::write(&temp_path, content)
+        .and_then(|_| std::fs::File::open(&temp_path)?.sync_all())
+        .and_then(|_| std::fs::rename(&temp_path, path));
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(format!("Failed to write settings: {}", e));
+    }
+
+    Ok(())
+}
+
+/// 在文件名后追加扩展名，例如 settings.json -> settings.json.tmp
+fn sibling(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(extension);
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncated_file_falls_back_to_defaults() {
+        let dir = std::env::temp_dir().join(format!("rustchat-settings-{}", std::process::id()));
+        let path = dir.join("settings.json");
+
+        let mut settings = Settings::new();
+        settings.insert("theme".to_string(), serde_json::json!("dark"));
+        write(&path, &settings).unwrap();
+        assert_eq!(read(&path).unwrap(), settings);
+        assert!(!sibling(&path, "tmp").exists());
+
+        // 模拟写入中途被终止留下的截断文件
+        std::fs::write(&path, r#"{"theme": "da"#).unwrap();
+        assert!(read(&path).is_err());
+        assert!(read_or_default(&path).is_empty());
+        assert!(sibling(&path, "corrupt").exists());
+
+        // 恢复后可以正常写入
+        write(&path, &settings).unwrap();
+        assert_eq!(read_or_default(&path), settings);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
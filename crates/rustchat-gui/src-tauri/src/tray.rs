@@ -0,0 +1,60 @@
+// 系统托盘图标：显示/隐藏主窗口、退出应用、显示未读数
+use tauri::menu::{Menu, MenuItem};
+use tauri::tray::TrayIconBuilder;
+
+/// 托盘图标ID
+pub const TRAY_ID: &str = "main";
+
+const TRAY_TOOLTIP: &str = "RustChat";
+
+/// 创建托盘图标和菜单（Show / Hide / Quit）
+pub fn create_tray(app: &tauri::App) -> tauri::Result<()> {
+    let show = MenuItem::with_id(app, "show", "Show", true, None::<&str>)?;
+    let hide = MenuItem::with_id(app, "hide", "Hide", true, None::<&str>)?;
+    let quit = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
+    let menu = Menu::with_items(app, &[&show, &hide, &quit])?;
+
+    let mut builder = TrayIconBuilder::with_id(TRAY_ID)
+        .tooltip(TRAY_TOOLTIP)
+        .menu(&menu)
+        .on_menu_event(|app_handle, event| {
+            let result = match event.id.as_ref() {
+                "show" => crate::apply_window_action(app_handle, "show")
+                    .and_then(|_| crate::apply_window_action(app_handle, "focus")),
+                "hide" => crate::apply_window_action(app_handle, "hide"),
+                "quit" => {
+                    // 直接退出不会触发CloseRequested，先保存窗口位置
+                    if let Err(e) = crate::save_window_geometry(app_handle) {
+                        eprintln!("Failed to save window geometry: {}", e);
+                    }
+                    app_handle.exit(0);
+                    Ok(())
+                }
+                _ => Ok(()),
+            };
+
+            if let Err(e) = result {
+                eprintln!("Failed to handle tray menu event: {}", e);
+            }
+        });
+    if let Some(icon) = app.default_window_icon() {
+        builder = builder.icon(icon.clone());
+    }
+    builder.build(app)?;
+
+    Ok(())
+}
+
+/// 在托盘提示中显示未读消息数量
+pub fn set_unread(app_handle: &tauri::AppHandle, count: u32) -> Result<(), String> {
+    let tray = app_handle.tray_by_id(TRAY_ID)
+        .ok_or("Tray icon not found")?;
+
+    let tooltip = if count == 0 {
+        TRAY_TOOLTIP.to_string()
+    } else {
+        format!("{} ({} unread)", TRAY_TOOLTIP, count)
+    };
+    tray.set_tooltip(Some(tooltip))
+        .map_err(|e| format!("Failed to set tray tooltip: {}", e))
+}
@@ -0,0 +1,133 @@
+// 主窗口大小和位置的保存与恢复
+use serde::{Deserialize, Serialize};
+use tauri::{PhysicalPosition, PhysicalSize, WebviewWindow};
+
+/// 设置文件中保存窗口几何信息的键
+pub const WINDOW_GEOMETRY_KEY: &str = "window_geometry";
+
+/// 窗口几何信息（物理像素）
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WindowGeometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    #[serde(default)]
+    pub maximized: bool,
+}
+
+/// 显示器区域
+#[derive(Debug, Clone, Copy)]
+struct MonitorArea {
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+}
+
+impl MonitorArea {
+    fn from_monitor(monitor: &tauri::Monitor) -> Self {
+        Self {
+            x: monitor.position().x,
+            y: monitor.position().y,
+            width: monitor.size().width,
+            height: monitor.size().height,
+        }
+    }
+
+    fn contains(&self, x: i32, y: i32) -> bool {
+        let (x, y) = (x as i64, y as i64);
+        x >= self.x as i64
+            && y >= self.y as i64
+            && x < self.x as i64 + self.width as i64
+            && y < self.y as i64 + self.height as i64
+    }
+}
+
+impl WindowGeometry {
+    /// 读取窗口当前的几何信息
+    ///
+    /// 最小化时位置不可靠，返回None；最大化时尽量保留上次记录的普通窗口大小和位置
+    pub fn capture(window: &WebviewWindow, previous: Option<Self>) -> Result<Option<Self>, String> {
+        if window.is_minimized().map_err(|e| format!("Failed to check if minimized: {}", e))? {
+            return Ok(None);
+        }
+
+        let size = window
+            .inner_size()
+            .map_err(|e| format!("Failed to get window size: {}", e))?;
+        let position = window
+            .outer_position()
+            .map_err(|e| format!("Failed to get window position: {}", e))?;
+
+        let current = Self {
+            x: position.x,
+            y: position.y,
+            width: size.width,
+            height: size.height,
+            maximized: false,
+        };
+
+        let maximized = window
+            .is_maximized()
+            .map_err(|e| format!("Failed to check if maximized: {}", e))?;
+        if maximized {
+            let restored = previous.unwrap_or(current);
+            return Ok(Some(Self { maximized: true, ..restored }));
+        }
+
+        Ok(Some(current))
+    }
+
+    /// 将几何信息应用到窗口，位置限制在当前可见的显示器范围内
+    pub fn apply(&self, window: &WebviewWindow) -> Result<(), String> {
+        let monitors = window
+            .available_monitors()
+            .map_err(|e| format!("Failed to get monitors: {}", e))?
+            .iter()
+            .map(MonitorArea::from_monitor)
+            .collect::<Vec<_>>();
+        let primary = window
+            .primary_monitor()
+            .map_err(|e| format!("Failed to get primary monitor: {}", e))?
+            .map(|monitor| MonitorArea::from_monitor(&monitor));
+
+        let geometry = self.clamp_to_monitors(&monitors, primary);
+
+        window
+            .set_size(PhysicalSize::new(geometry.width, geometry.height))
+            .map_err(|e| format!("Failed to set window size: {}", e))?;
+        window
+            .set_position(PhysicalPosition::new(geometry.x, geometry.y))
+            .map_err(|e| format!("Failed to set window position: {}", e))?;
+        if geometry.maximized {
+            window.maximize().map_err(|e| format!("Failed to maximize: {}", e))?;
+        }
+
+        Ok(())
+    }
+
+    /// 保存时所在的显示器已断开时，把窗口移回主显示器（或第一个可用显示器）内
+    fn clamp_to_monitors(&self, monitors: &[MonitorArea], primary: Option<MonitorArea>) -> Self {
+        if monitors.iter().any(|monitor| monitor.contains(self.x, self.y)) {
+            return *self;
+        }
+
+        let Some(target) = primary.or_else(|| monitors.first().copied()) else {
+            return *self;
+        };
+
+        let width = self.width.min(target.width);
+        let height = self.height.min(target.height);
+        let max_x = target.x + (target.width - width) as i32;
+        let max_y = target.y + (target.height - height) as i32;
+
+        Self {
+            x: self.x.clamp(target.x, max_x),
+            y: self.y.clamp(target.y, max_y),
+            width,
+            height,
+            maximized: self.maximized,
+        }
+    }
+}
@@ -1,6 +1,6 @@
 // API 服务
 import axios, { type AxiosResponse } from 'axios';
-import type { User, Room, Message, AuthTokens, ApiResponse, FriendRequest } from './types';
+import type { User, Room, Message, AuthTokens, ApiResponse, FriendRequest, FriendEntry } from './types';
 
 const API_BASE_URL = 'http://127.0.0.1:8080/api';
 
@@ -212,9 +212,9 @@ export const roomApi = {
     return response.data;
   },
 
-  async getRoomMessages(roomId: string, limit = 50, offset = 0): Promise<ApiResponse<Message[]>> {
+  async getRoomMessages(roomId: string, limit = 50, before?: string): Promise<ApiResponse<Message[]>> {
     const response: AxiosResponse<ApiResponse<Message[]>> = await api.get(`/rooms/${roomId}/messages`, {
-      params: { limit, offset },
+      params: { limit, order: 'newest', before },
     });
     return response.data;
   },
@@ -229,43 +229,50 @@ export const roomApi = {
 
 // 好友相关 API
 export const friendApi = {
-  async sendFriendRequest(userId: string, toUserId: string, message?: string): Promise<ApiResponse<FriendRequest>> {
+  async sendFriendRequest(toUserId: string, message?: string): Promise<ApiResponse<FriendRequest>> {
     const response: AxiosResponse<FriendRequest> = await api.post('/friends/request', {
       to_user_id: toUserId,
       message,
-    }, {
-      params: { user_id: userId }
     });
     return { data: response.data };
   },
 
-  async respondToFriendRequest(userId: string, requestId: string, accept: boolean): Promise<ApiResponse<FriendRequest>> {
+  async respondToFriendRequest(requestId: string, accept: boolean): Promise<ApiResponse<FriendRequest>> {
     const response: AxiosResponse<FriendRequest> = await api.post('/friends/request/respond', {
       request_id: requestId,
       accept,
-    }, {
-      params: { user_id: userId }
     });
     return { data: response.data };
   },
 
-  async getFriendRequests(userId: string): Promise<ApiResponse<FriendRequest[]>> {
-    const response: AxiosResponse<FriendRequest[]> = await api.get('/friends/requests', {
-      params: { user_id: userId }
+  async cancelFriendRequest(requestId: string): Promise<ApiResponse<FriendRequest>> {
+    const response: AxiosResponse<FriendRequest> = await api.delete('/friends/request', {
+      params: { request_id: requestId }
     });
     return { data: response.data };
   },
 
-  async getFriends(userId: string): Promise<ApiResponse<string[]>> {
-    const response: AxiosResponse<string[]> = await api.get('/friends/list', {
-      params: { user_id: userId }
+  async getFriendRequests(): Promise<ApiResponse<FriendRequest[]>> {
+    const response: AxiosResponse<FriendRequest[]> = await api.get('/friends/requests');
+    return { data: response.data };
+  },
+
+  async getFriends(): Promise<ApiResponse<FriendEntry[]>> {
+    const response: AxiosResponse<FriendEntry[]> = await api.get('/friends/list');
+    return { data: response.data };
+  },
+
+  async setFriendAlias(friendUserId: string, alias: string): Promise<ApiResponse<FriendEntry>> {
+    const response: AxiosResponse<FriendEntry> = await api.put('/friends/alias', {
+      friend_user_id: friendUserId,
+      alias
     });
     return { data: response.data };
   },
 
-  async removeFriend(userId: string, friendUserId: string): Promise<ApiResponse<{ message: string }>> {
+  async removeFriend(friendUserId: string): Promise<ApiResponse<{ message: string }>> {
     await api.delete('/friends/remove', {
-      params: { user_id: userId, friend_user_id: friendUserId }
+      params: { friend_user_id: friendUserId }
     });
     return { data: { message: 'Friend removed successfully' } };
   },
@@ -1,6 +1,6 @@
 // 应用状态管理
 import { writable, derived } from 'svelte/store';
-import type { User, Room, Message, AppState, FriendRequest } from './types';
+import type { User, Room, Message, AppState, FriendRequest, FriendEntry } from './types';
 import { webSocketClient } from './websocket';
 
 // 创建可写存储
@@ -9,7 +9,7 @@ export const authToken = writable<string | null>(null);
 export const currentRoom = writable<Room | null>(null);
 export const rooms = writable<Room[]>([]);
 export const messages = writable<Message[]>([]);
-export const friends = writable<string[]>([]);
+export const friends = writable<FriendEntry[]>([]);
 export const friendRequests = writable<FriendRequest[]>([]);
 export const isLoading = writable<boolean>(false);
 export const error = writable<string | null>(null);
@@ -78,16 +78,22 @@ export const actions = {
     messages.update(currentMessages => [...currentMessages, message]);
   },
 
-  setFriends: (friendList: string[]) => {
+  setFriends: (friendList: FriendEntry[]) => {
     friends.set(friendList);
   },
 
   addFriend: (friendUserId: string) => {
-    friends.update(currentFriends => [...currentFriends, friendUserId]);
+    friends.update(currentFriends => [...currentFriends, { user_id: friendUserId, alias: null }]);
+  },
+
+  updateFriend: (entry: FriendEntry) => {
+    friends.update(currentFriends =>
+      currentFriends.map(friend => friend.user_id === entry.user_id ? entry : friend)
+    );
   },
 
   removeFriend: (friendUserId: string) => {
-    friends.update(currentFriends => currentFriends.filter(id => id !== friendUserId));
+    friends.update(currentFriends => currentFriends.filter(friend => friend.user_id !== friendUserId));
   },
 
   setFriendRequests: (requestList: FriendRequest[]) => {
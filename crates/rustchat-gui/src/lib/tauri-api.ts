@@ -105,8 +105,8 @@ export const tauriApi = {
   },
 
   // 日志管理
-  async writeLog(level: 'info' | 'warn' | 'error' | 'debug', message: string): Promise<void> {
-    return await invoke('write_log', { level, message });
+  async writeLog(level: 'info' | 'warn' | 'error' | 'debug', message: string, format?: 'text' | 'json'): Promise<void> {
+    return await invoke('write_log', { level, message, format });
   },
 
   async readLogs(lines?: number): Promise<string[]> {
@@ -134,6 +134,11 @@ export const tauriApi = {
     return await invoke('set_window_size', { width, height });
   },
 
+  // 系统托盘
+  async setTrayUnread(count: number): Promise<void> {
+    return await invoke('set_tray_unread', { count });
+  },
+
   // 网络
   async checkConnection(url: string): Promise<boolean> {
     return await invoke('check_connection', { url });
@@ -188,6 +193,14 @@ export const settingsManager = {
   async setAutoConnect(autoConnect: boolean): Promise<void> {
     await tauriApi.saveSetting('auto_connect', autoConnect);
   },
+
+  async getCloseToTray(): Promise<boolean> {
+    return (await tauriApi.getSetting('close_to_tray')) ?? false;
+  },
+
+  async setCloseToTray(closeToTray: boolean): Promise<void> {
+    await tauriApi.saveSetting('close_to_tray', closeToTray);
+  },
 };
 
 // 窗口管理的便捷包装
@@ -36,6 +36,7 @@ export interface ApiResponse<T> {
   data?: T;
   error?: string;
   message?: string;
+  next_cursor?: string;
 }
 
 // 好友请求状态
@@ -52,6 +53,12 @@ export interface FriendRequest {
   updated_at: number;
 }
 
+// 好友列表项，alias 为自己设置的备注名
+export interface FriendEntry {
+  user_id: string;
+  alias?: string | null;
+}
+
 // 好友关系
 export interface Friendship {
   user_id: string;
@@ -65,7 +72,7 @@ export interface AppState {
   currentRoom: Room | null;
   rooms: Room[];
   messages: Message[];
-  friends: string[]; // friend user IDs
+  friends: FriendEntry[]; // friend user IDs
   friendRequests: FriendRequest[];
   isLoading: boolean;
   error: string | null;
@@ -1,11 +1,17 @@
 // WebSocket 事件类型定义
 export interface WsEvent {
-  event: 'Connected' | 'Message' | 'UserJoined' | 'UserLeft' | 'RoomMessage' | 'UserJoinedRoom' | 'UserLeftRoom' | 'Ping' | 'Pong' | 'Error';
+  event: 'Connected' | 'ServerInfo' | 'Message' | 'MessageBatch' | 'UserJoined' | 'UserLeft' | 'RoomMessage' | 'RoomMessageBatch' | 'UserJoinedRoom' | 'UserLeftRoom' | 'RoomJoined' | 'RoomLeft' | 'RoomList' | 'MessagesPurged' | 'MessagesExpired' | 'Mentioned' | 'ReactionUpdated' | 'FriendRemoved' | 'NicknameChanged' | 'MessageAck' | 'Ping' | 'Pong' | 'Error';
   data?: any;
 }
 
 export interface ConnectedEvent {
   user_id: string;
+  resume_token?: string;
+}
+
+export interface ServerInfoEvent {
+  version: string;
+  protocol_version: string;
 }
 
 export interface WsMessageEvent {
@@ -13,8 +19,13 @@ export interface WsMessageEvent {
   user_id: string;
   content: string;
   nickname?: string;
-  message_type: 'Text' | 'NickChange';
+  message_type: 'Text' | 'NickChange' | 'Markdown';
   created_at: string;
+  /** 客户端声明的发送时间，只用于展示 */
+  client_timestamp?: string;
+  expires_at?: string;
+  /** 被@提及的用户ID */
+  mentions?: string[];
   additional_data?: any;
 }
 
@@ -39,6 +50,11 @@ export interface RoomMessageEvent {
   };
 }
 
+export interface RoomMessageBatchEvent {
+  room_id: string;
+  messages: RoomMessageEvent['message'][];
+}
+
 export interface UserJoinedRoomEvent {
   room_id: string;
   user_id: string;
@@ -49,24 +65,126 @@ export interface UserLeftRoomEvent {
   user_id: string;
 }
 
+/** 当前用户所在的房间列表（响应 ListMyRooms，加入或离开房间后也会推送） */
+export interface RoomListEvent {
+  rooms: {
+    id: string;
+    name: string;
+    owner: string;
+    created_at: string;
+    member_count: number;
+    description: string | null;
+    max_members: number | null;
+    persistent?: boolean;
+    is_member: boolean;
+    is_owner: boolean;
+    unread_count?: number;
+  }[];
+}
+
+/** 当前连接已加入房间（只发送给加入的连接） */
+export interface RoomJoinedEvent {
+  room: RoomListEvent['rooms'][number];
+}
+
+/** 当前连接已离开房间（只发送给离开的连接） */
+export interface RoomLeftEvent {
+  room_id: string;
+}
+
+export interface MessagesPurgedEvent {
+  user_id: string;
+}
+
+export interface MessagesExpiredEvent {
+  message_ids: string[];
+}
+
+export interface MentionedEvent {
+  message: WsMessageEvent;
+}
+
+export interface ReactionUpdatedEvent {
+  message_id: string;
+  emoji: string;
+  count: number;
+  users: string[];
+}
+
+export interface FriendRemovedEvent {
+  user_id: string;
+}
+
+export interface MessageAckEvent {
+  client_msg_id: string;
+  message_id: string;
+}
+
+export interface NicknameChangedEvent {
+  user_id: string;
+  old_nick: string | null;
+  new_nick: string;
+}
+
+// 错误代码，协议1.3之前的服务器不提供
+export type ErrorCode =
+  | 'INVALID_REQUEST'
+  | 'MSG_EMPTY'
+  | 'MSG_TOO_LONG'
+  | 'MSG_REJECTED'
+  | 'INVALID_TTL'
+  | 'RATE_LIMITED'
+  | 'NICK_INVALID'
+  | 'NICK_COOLDOWN'
+  | 'INVALID_ROOM_ID'
+  | 'ROOM_NOT_FOUND'
+  | 'ROOM_NOT_MEMBER'
+  | 'ROOM_FULL'
+  | 'PERMISSION_DENIED'
+  | 'MESSAGE_NOT_FOUND'
+  | 'INVALID_REACTION'
+  | 'SLOW_CONSUMER'
+  | 'SESSION_TERMINATED'
+  | 'IDLE_WARNING'
+  | 'IDLE_TIMEOUT'
+  | 'INTERNAL_ERROR';
+
 export interface ErrorEvent {
+  code?: ErrorCode;
   message: string;
 }
 
 // 客户端消息类型
 export interface ClientMessage {
-  type: 'SendMessage' | 'SendRoomMessage' | 'JoinRoom' | 'LeaveRoom' | 'SetNickname' | 'Pong';
+  type: 'SendMessage' | 'SendRoomMessage' | 'JoinRoom' | 'LeaveRoom' | 'ListMyRooms' | 'SetNickname' | 'MarkRead' | 'React' | 'Unreact' | 'Pong';
   data?: any;
 }
 
 export interface SendMessageData {
   content: string;
   nickname?: string;
+  client_timestamp?: string;
+  ttl_secs?: number;
+  /** 内容为Markdown，服务器清理原始HTML和不安全链接后保存 */
+  markdown?: boolean;
+}
+
+/** 将房间内的已读位置推进到指定消息 */
+export interface MarkReadData {
+  room_id: string;
+  message_id: string;
+}
+
+/** React / Unreact 的数据 */
+export interface ReactionData {
+  message_id: string;
+  emoji: string;
 }
 
 export interface SendRoomMessageData {
   room_id: string;
   content: string;
+  markdown?: boolean;
 }
 
 export interface JoinRoomData {
@@ -9,6 +9,7 @@ import type {
   UserJoinedEvent,
   UserLeftEvent,
   RoomMessageEvent,
+  RoomMessageBatchEvent,
   UserJoinedRoomEvent,
   UserLeftRoomEvent,
   ErrorEvent,
@@ -22,6 +23,9 @@ import { ConnectionState } from './websocket-types';
 import type { Message } from './types';
 import { actions } from './store';
 
+// 客户端支持的WebSocket协议版本，与 rustchat_types::PROTOCOL_VERSION 保持一致
+const PROTOCOL_VERSION = '1.6';
+
 class WebSocketClient {
   private ws: WebSocket | null = null;
   private options: WebSocketOptions;
@@ -85,10 +89,10 @@ class WebSocketClient {
 
     try {
       // 构建WebSocket URL，如果有认证令牌则添加到query参数
-      let wsUrl = this.options.url;
+      // 声明客户端协议版本，服务器据此决定是否发送批量事件
+      let wsUrl = `${this.options.url}${this.options.url.includes('?') ? '&' : '?'}protocol=${PROTOCOL_VERSION}`;
       if (this.authToken) {
-        const separator = this.options.url.includes('?') ? '&' : '?';
-        wsUrl = `${this.options.url}${separator}token=${encodeURIComponent(this.authToken)}`;
+        wsUrl = `${wsUrl}&token=${encodeURIComponent(this.authToken)}`;
       }
       
       this.log(`Connecting to ${wsUrl}`);
@@ -268,9 +272,17 @@ class WebSocketClient {
           break;        case 'Message':
           this.handleMessageEvent(wsEvent.data as WsMessageEvent);
           break;
+        case 'MessageBatch':
+          (wsEvent.data as WsMessageEvent[]).forEach(message => this.handleMessageEvent(message));
+          break;
         case 'RoomMessage':
           this.handleRoomMessageEvent(wsEvent.data as RoomMessageEvent);
           break;
+        case 'RoomMessageBatch': {
+          const batch = wsEvent.data as RoomMessageBatchEvent;
+          batch.messages.forEach(message => this.handleRoomMessageEvent({ room_id: batch.room_id, message }));
+          break;
+        }
         case 'UserJoined':
           this.handleUserJoinedEvent(wsEvent.data as UserJoinedEvent);
           break;
@@ -0,0 +1,329 @@
+use super::{api::handle_auth_error, AccountId, AuthError, AuthenticatedUser};
+use crate::AppState;
+use axum::{
+    extract::{Extension, Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Json},
+    routing::{get, post},
+    Router,
+};
+use chrono::{DateTime, Utc};
+use rustchat_types::{Message, MessageId, UserId};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::{error, info, warn};
+
+/// 创建管理员路由（需要依次经过认证中间件和管理员中间件）
+pub fn create_admin_routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/admin/accounts/{id}/sessions", get(list_account_sessions))
+        .route("/api/admin/accounts/{id}/logout-all", post(force_logout_account))
+        .route("/api/admin/stats/receipt-latency", get(receipt_latency_stats))
+        .route("/api/admin/stats/broadcast", get(broadcast_stats))
+        .route("/api/admin/bots/{name}/toggle", post(toggle_bot))
+        .route("/api/messages/import", post(import_messages))
+}
+
+/// 单次导入的最大消息数
+const MAX_IMPORT_MESSAGES: usize = 10_000;
+
+/// 导入的一条聊天记录
+#[derive(Debug, Deserialize)]
+struct ImportedMessage {
+    /// 原消息ID，省略时生成新ID
+    #[serde(default)]
+    id: Option<String>,
+    from_user_id: String,
+    content: String,
+    timestamp: DateTime<Utc>,
+    #[serde(default)]
+    nickname: Option<String>,
+}
+
+impl ImportedMessage {
+    /// 转换为全局文本消息，保留原有的ID和时间戳
+    fn into_message(self) -> Result<Message, String> {
+        let from = UserId::parse(&self.from_user_id).map_err(|_| format!("无效的用户ID: {}", self.from_user_id))?;
+        Message::validate_content(&self.content).map_err(|e| format!("消息内容{}", e))?;
+
+        let mut message = Message::new_text(from, self.content, self.nickname);
+        if let Some(id) = self.id {
+            message.id = MessageId::parse(&id).map_err(|_| format!("无效的消息ID: {}", id))?;
+        }
+        message.timestamp = self.timestamp;
+        Ok(message)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct LatencyQuery {
+    limit: Option<i64>,
+}
+
+/// 查看账户的登录会话
+async fn list_account_sessions(
+    State(state): State<AppState>,
+    Extension(admin): Extension<AuthenticatedUser>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    let (admin_id, target_id) = match parse_ids(&admin, &id) {
+        Ok(ids) => ids,
+        Err(response) => return response,
+    };
+
+    if let Err(e) = state.auth_service.get_account_by_id(&target_id).await {
+        return handle_auth_error(e);
+    }
+
+    let sessions = match state.auth_service.list_sessions(&target_id).await {
+        Ok(sessions) => sessions,
+        Err(e) => {
+            error!("获取会话列表失败: {} - {}", target_id, e);
+            return handle_auth_error(e);
+        }
+    };
+
+    if let Err(e) = state
+        .auth_service
+        .record_audit(&admin_id, "view_sessions", Some(&target_id), None)
+        .await
+    {
+        error!("写入审计日志失败: {}", e);
+        return handle_auth_error(e);
+    }
+
+    info!("管理员 {} 查看了账户 {} 的会话", admin.email, target_id);
+    (
+        StatusCode::OK,
+        Json(json!({
+            "success": true,
+            "message": "获取会话列表成功",
+            "sessions": sessions
+        }))
+    )
+}
+
+/// 强制注销账户的所有会话并断开在线连接
+async fn force_logout_account(
+    State(state): State<AppState>,
+    Extension(admin): Extension<AuthenticatedUser>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    let (admin_id, target_id) = match parse_ids(&admin, &id) {
+        Ok(ids) => ids,
+        Err(response) => return response,
+    };
+
+    if let Err(e) = state.auth_service.get_account_by_id(&target_id).await {
+        return handle_auth_error(e);
+    }
+
+    let revoked_sessions = match state.auth_service.logout_all_devices(&target_id).await {
+        Ok(count) => count,
+        Err(e) => {
+            error!("强制注销失败: {} - {}", target_id, e);
+            return handle_auth_error(e);
+        }
+    };
+    let disconnected = state.disconnect_account(&target_id, "您的会话已被管理员注销").await;
+
+    let details = json!({
+        "revoked_sessions": revoked_sessions,
+        "disconnected_connections": disconnected
+    });
+    if let Err(e) = state
+        .auth_service
+        .record_audit(&admin_id, "force_logout", Some(&target_id), Some(details.to_string()))
+        .await
+    {
+        error!("写入审计日志失败: {}", e);
+        return handle_auth_error(e);
+    }
+
+    warn!("管理员 {} 强制注销了账户 {}", admin.email, target_id);
+    (
+        StatusCode::OK,
+        Json(json!({
+            "success": true,
+            "message": "已注销该账户的所有会话",
+            "revoked_sessions": revoked_sessions,
+            "disconnected_connections": disconnected
+        }))
+    )
+}
+
+/// 最近消息的服务器接收延迟统计（用于诊断时钟偏差和投递延迟）
+async fn receipt_latency_stats(
+    State(state): State<AppState>,
+    Query(query): Query<LatencyQuery>,
+) -> impl IntoResponse {
+    let limit = query.limit.unwrap_or(1000).clamp(1, 10_000);
+
+    match state.message_db.receipt_latency_stats(limit).await {
+        Ok(stats) => (
+            StatusCode::OK,
+            Json(json!({
+                "success": true,
+                "message": "获取接收延迟统计成功",
+                "stats": stats
+            }))
+        ),
+        Err(e) => {
+            error!("获取接收延迟统计失败: {}", e);
+            handle_auth_error(AuthError::DatabaseError(e))
+        }
+    }
+}
+
+/// 查看广播通道的积压情况，积压接近容量时慢客户端将开始丢失消息
+async fn broadcast_stats(State(state): State<AppState>) -> impl IntoResponse {
+    let rooms = state.room_broadcast_manager.get_broadcast_stats().await;
+
+    Json(json!({
+        "success": true,
+        "message": "获取广播通道统计成功",
+        "stats": {
+            "global": {
+                "capacity": state.config.broadcast_capacity,
+                "queued": state.tx.len(),
+                "subscribers": state.tx.receiver_count()
+            },
+            "rooms": rooms
+        }
+    }))
+}
+
+/// 切换机器人的启用状态，立即影响后续消息的处理
+async fn toggle_bot(
+    State(state): State<AppState>,
+    Extension(admin): Extension<AuthenticatedUser>,
+    Path(name): Path<String>,
+) -> impl IntoResponse {
+    let admin_id = match AccountId::parse(&admin.account_id) {
+        Ok(id) => id,
+        Err(_) => return handle_auth_error(AuthError::InvalidToken),
+    };
+
+    let bot = {
+        let mut bot_manager = state.bot_manager.lock().await;
+        let mut bot = bot_manager.get_bots_info().into_iter().find(|bot| bot.name == name);
+        if let Some(bot) = bot.as_mut() {
+            bot.enabled = !bot.enabled;
+            bot_manager.set_enabled(&bot.name, bot.enabled);
+        }
+        bot
+    };
+    let Some(bot) = bot else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "success": false,
+                "message": "机器人不存在"
+            }))
+        );
+    };
+
+    let details = json!({ "bot": bot.name, "enabled": bot.enabled });
+    if let Err(e) = state
+        .auth_service
+        .record_audit(&admin_id, "toggle_bot", None, Some(details.to_string()))
+        .await
+    {
+        error!("写入审计日志失败: {}", e);
+        return handle_auth_error(e);
+    }
+
+    warn!("管理员 {} 将机器人 {} 设为{}", admin.email, bot.name, if bot.enabled { "启用" } else { "停用" });
+    (
+        StatusCode::OK,
+        Json(json!({
+            "success": true,
+            "message": if bot.enabled { "机器人已启用" } else { "机器人已停用" },
+            "bot": bot
+        }))
+    )
+}
+
+/// 从其他系统导入聊天记录，在同一个事务中写入，ID已存在的消息被跳过
+///
+/// 导入的消息不会广播给在线用户，也不交给机器人处理
+async fn import_messages(
+    State(state): State<AppState>,
+    Extension(admin): Extension<AuthenticatedUser>,
+    Json(request): Json<Vec<ImportedMessage>>,
+) -> impl IntoResponse {
+    let admin_id = match AccountId::parse(&admin.account_id) {
+        Ok(id) => id,
+        Err(_) => return handle_auth_error(AuthError::InvalidToken),
+    };
+
+    let bad_request = |message: String| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "success": false,
+                "message": message
+            }))
+        )
+    };
+    if request.len() > MAX_IMPORT_MESSAGES {
+        return bad_request(format!("单次最多导入 {} 条消息", MAX_IMPORT_MESSAGES));
+    }
+
+    let mut messages = Vec::with_capacity(request.len());
+    for (index, imported) in request.into_iter().enumerate() {
+        match imported.into_message() {
+            Ok(message) => messages.push(message),
+            Err(reason) => return bad_request(format!("第 {} 条消息无效: {}", index + 1, reason)),
+        }
+    }
+
+    let result = match state.message_db.save_messages_batch(&messages).await {
+        Ok(result) => result,
+        Err(e) => {
+            error!("导入消息失败: {:#}", e);
+            return handle_auth_error(AuthError::DatabaseError(e));
+        }
+    };
+
+    let details = json!({ "inserted": result.inserted, "skipped": result.skipped });
+    if let Err(e) = state
+        .auth_service
+        .record_audit(&admin_id, "import_messages", None, Some(details.to_string()))
+        .await
+    {
+        error!("写入审计日志失败: {}", e);
+        return handle_auth_error(e);
+    }
+
+    info!("管理员 {} 导入了 {} 条消息，跳过 {} 条已存在的消息", admin.email, result.inserted, result.skipped);
+    (
+        StatusCode::OK,
+        Json(json!({
+            "success": true,
+            "message": "消息导入完成",
+            "inserted": result.inserted,
+            "skipped": result.skipped
+        }))
+    )
+}
+
+/// 解析管理员和目标账户ID
+fn parse_ids(
+    admin: &AuthenticatedUser,
+    target: &str,
+) -> Result<(AccountId, AccountId), (StatusCode, Json<serde_json::Value>)> {
+    let admin_id = AccountId::parse(&admin.account_id)
+        .map_err(|_| handle_auth_error(AuthError::InvalidToken))?;
+    let target_id = AccountId::parse(target).map_err(|_| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "success": false,
+                "message": "无效的账户ID"
+            }))
+        )
+    })?;
+
+    Ok((admin_id, target_id))
+}
@@ -1,17 +1,19 @@
 use super::{
-    AuthError, AuthResponse, LoginRequest, RegisterRequest, 
-    ResendCodeRequest, VerificationPurpose, VerifyEmailRequest, RefreshTokenRequest
+    AccountId, AuthError, AuthResponse, AuthenticatedUser, DeleteAccountRequest, LoginRequest, PurgeDataRequest,
+    RegisterRequest, ResendCodeRequest, SessionInfo, TokenPair, VerificationPurpose, VerifyEmailRequest,
+    LogoutRequest, RefreshTokenRequest, TokenType
 };
 use crate::AppState;
 use axum::{
-    extract::State,
-    http::StatusCode,
+    extract::{Extension, Path, State},
+    http::{header::AUTHORIZATION, HeaderMap, StatusCode},
     response::{IntoResponse, Json},
-    routing::{get, post},
+    routing::{delete, get, post},
     Router,
 };
 use serde_json::json;
 use tracing::{error, info, warn};
+use utoipa::OpenApi;
 
 /// 创建认证相关的路由
 pub fn create_auth_routes() -> Router<AppState> {
@@ -26,7 +28,41 @@ pub fn create_auth_routes() -> Router<AppState> {
         .route("/api/auth/health", get(auth_health_check))
 }
 
+/// 创建需要认证的账户路由
+pub fn create_protected_auth_routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/auth/me", delete(delete_my_account))
+        .route("/api/auth/me/purge", post(purge_my_data))
+        .route("/api/auth/sessions", get(list_my_sessions))
+        .route("/api/auth/sessions/{id}", delete(revoke_my_session))
+}
+
+/// 认证接口的OpenAPI描述
+///
+/// 认证接口的响应体是 `success`/`message` 外加各接口的数据字段，数据字段的结构见 `AuthResponse` 等组件
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        register, login, verify_email, resend_verification_code, refresh_token, get_current_user, logout,
+        auth_health_check, delete_my_account, purge_my_data, list_my_sessions, revoke_my_session
+    ),
+    components(schemas(AuthResponse, TokenPair, SessionInfo))
+)]
+pub(crate) struct AuthApi;
+
 /// 用户注册
+#[utoipa::path(
+    post,
+    path = "/api/auth/register",
+    tag = "auth",
+    request_body = RegisterRequest,
+    responses(
+        (status = 201, description = "注册成功并发送邮箱验证码，account 字段为 AuthResponse", body = Object,
+            example = json!({"success": true, "message": "注册成功，邮箱验证码已发送", "account": {"account_id": "…", "email": "alice@example.com", "display_name": null, "email_verified": false, "created_at": "2025-01-01T00:00:00Z", "tokens": null}})),
+        (status = 400, description = "邮箱或密码不符合要求", body = Object),
+        (status = 409, description = "邮箱已被注册", body = Object)
+    )
+)]
 async fn register(
     State(state): State<AppState>,
     Json(request): Json<RegisterRequest>,
@@ -76,6 +112,18 @@ async fn register(
 }
 
 /// 用户登录
+#[utoipa::path(
+    post,
+    path = "/api/auth/login",
+    tag = "auth",
+    request_body = LoginRequest,
+    responses(
+        (status = 200, description = "登录成功，account 字段为附带令牌对的 AuthResponse", body = Object,
+            example = json!({"success": true, "message": "登录成功", "account": {"account_id": "…", "email": "alice@example.com", "display_name": null, "email_verified": true, "created_at": "2025-01-01T00:00:00Z", "tokens": {"access_token": "…", "refresh_token": "…", "token_type": "Bearer", "expires_in": 3600}}})),
+        (status = 401, description = "邮箱或密码错误", body = Object),
+        (status = 403, description = "账户未验证、已暂停或已删除", body = Object)
+    )
+)]
 async fn login(
     State(state): State<AppState>,
     Json(request): Json<LoginRequest>,
@@ -112,6 +160,16 @@ async fn login(
 }
 
 /// 验证邮箱
+#[utoipa::path(
+    post,
+    path = "/api/auth/verify-email",
+    tag = "auth",
+    request_body = VerifyEmailRequest,
+    responses(
+        (status = 200, description = "邮箱验证成功", body = Object, example = json!({"success": true, "message": "邮箱验证成功"})),
+        (status = 400, description = "验证码无效或已过期", body = Object)
+    )
+)]
 async fn verify_email(
     State(state): State<AppState>,
     Json(request): Json<VerifyEmailRequest>,
@@ -141,6 +199,16 @@ async fn verify_email(
 }
 
 /// 重新发送验证码
+#[utoipa::path(
+    post,
+    path = "/api/auth/resend-code",
+    tag = "auth",
+    request_body = ResendCodeRequest,
+    responses(
+        (status = 200, description = "验证码已重新发送（邮箱未注册时也返回成功）", body = Object),
+        (status = 400, description = "邮箱已验证", body = Object)
+    )
+)]
 async fn resend_verification_code(
     State(state): State<AppState>,
     Json(request): Json<ResendCodeRequest>,
@@ -199,6 +267,15 @@ async fn resend_verification_code(
 }
 
 /// 认证健康检查
+#[utoipa::path(
+    get,
+    path = "/api/auth/health",
+    tag = "auth",
+    responses(
+        (status = 200, description = "认证服务数据库连接正常", body = Object),
+        (status = 503, description = "认证服务数据库连接失败", body = Object)
+    )
+)]
 async fn auth_health_check(State(state): State<AppState>) -> impl IntoResponse {
     // 测试数据库连接
     match sqlx::query("SELECT 1").execute(state.auth_service.get_pool()).await {
@@ -210,6 +287,7 @@ async fn auth_health_check(State(state): State<AppState>) -> impl IntoResponse {
                 "database": "connected"
             }))
         ),
+        // 接口无需认证，具体错误只写入日志，不返回给调用方
         Err(e) => {
             error!("认证服务数据库连接失败: {}", e);
             (
@@ -217,8 +295,7 @@ async fn auth_health_check(State(state): State<AppState>) -> impl IntoResponse {
                 Json(json!({
                     "status": "unhealthy",
                     "service": "auth",
-                    "database": "disconnected",
-                    "error": e.to_string()
+                    "database": "unavailable"
                 }))
             )
         }
@@ -226,6 +303,17 @@ async fn auth_health_check(State(state): State<AppState>) -> impl IntoResponse {
 }
 
 /// 刷新访问令牌
+#[utoipa::path(
+    post,
+    path = "/api/auth/refresh",
+    tag = "auth",
+    request_body = RefreshTokenRequest,
+    responses(
+        (status = 200, description = "令牌刷新成功，tokens 字段为新的 TokenPair", body = Object,
+            example = json!({"success": true, "message": "令牌刷新成功", "tokens": {"access_token": "…", "refresh_token": "…", "token_type": "Bearer", "expires_in": 3600}})),
+        (status = 401, description = "刷新令牌无效或已过期", body = Object)
+    )
+)]
 async fn refresh_token(
     State(state): State<AppState>,
     Json(request): Json<RefreshTokenRequest>,
@@ -252,8 +340,14 @@ async fn refresh_token(
 }
 
 /// 获取当前用户信息
+#[utoipa::path(
+    get,
+    path = "/api/auth/me",
+    tag = "auth",
+    responses((status = 501, description = "尚未实现", body = Object))
+)]
 async fn get_current_user(
-    State(state): State<AppState>,
+    State(_state): State<AppState>,
 ) -> impl IntoResponse {
     // 暂时返回一个简单的响应，等待添加JWT中间件
     info!("收到获取当前用户请求");
@@ -268,12 +362,39 @@ async fn get_current_user(
 }
 
 /// 用户登出
+///
+/// Authorization 头中的访问令牌立即失效；请求体带有刷新令牌时同时撤销对应的会话
+#[utoipa::path(
+    post,
+    path = "/api/auth/logout",
+    tag = "auth",
+    request_body(content = Option<LogoutRequest>, description = "可选，包含要撤销的刷新令牌"),
+    responses((status = 200, description = "登出成功", body = Object))
+)]
 async fn logout(
     State(state): State<AppState>,
+    headers: HeaderMap,
+    request: Option<Json<LogoutRequest>>,
 ) -> impl IntoResponse {
-    // 暂时返回一个简单的响应
     info!("收到登出请求");
-    
+
+    let access_token = headers
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "));
+    // 已过期或已撤销的令牌本来就无法使用，无需处理
+    if let Some(Ok(claims)) = access_token.map(|token| state.auth_service.verify_token(token, TokenType::Access)) {
+        state.auth_service.revoke_access_token(&claims);
+    }
+
+    let Json(request) = request.unwrap_or_default();
+    if let Some(refresh_token) = request.refresh_token {
+        if let Err(e) = state.auth_service.logout(&refresh_token).await {
+            warn!("撤销会话失败: {}", e);
+            return handle_auth_error(e);
+        }
+    }
+
     (
         StatusCode::OK,
         Json(json!({
@@ -283,11 +404,195 @@ async fn logout(
     )
 }
 
+/// 清除当前用户的个人数据（保留账户）
+#[utoipa::path(
+    post,
+    path = "/api/auth/me/purge",
+    tag = "auth",
+    security(("bearer_auth" = [])),
+    request_body = PurgeDataRequest,
+    responses(
+        (status = 200, description = "个人数据已清除，deleted 字段为各类数据的删除数量", body = Object),
+        (status = 401, description = "密码错误或令牌无效", body = Object)
+    )
+)]
+async fn purge_my_data(
+    State(state): State<AppState>,
+    Extension(auth_user): Extension<AuthenticatedUser>,
+    Json(request): Json<PurgeDataRequest>,
+) -> impl IntoResponse {
+    info!("收到清除个人数据请求: {}", auth_user.email);
+
+    let account_id = match AccountId::parse(&auth_user.account_id) {
+        Ok(account_id) => account_id,
+        Err(_) => return handle_auth_error(AuthError::InvalidToken),
+    };
+
+    if let Err(e) = state.auth_service.confirm_password(&account_id, &request.password).await {
+        warn!("清除个人数据密码验证失败: {} - {}", auth_user.email, e);
+        return handle_auth_error(e);
+    }
+
+    match state.purge_user_data(&auth_user.user_id).await {
+        Ok(summary) => {
+            info!("已清除用户 {} 的个人数据: {:?}", auth_user.email, summary);
+            (
+                StatusCode::OK,
+                Json(json!({
+                    "success": true,
+                    "message": "个人数据已清除",
+                    "deleted": summary
+                }))
+            )
+        }
+        Err(e) => {
+            error!("清除个人数据失败: {} - {}", auth_user.email, e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({
+                    "success": false,
+                    "message": "清除个人数据失败"
+                }))
+            )
+        }
+    }
+}
+
+/// 注销当前账户（需要重新输入密码）
+#[utoipa::path(
+    delete,
+    path = "/api/auth/me",
+    tag = "auth",
+    security(("bearer_auth" = [])),
+    request_body = DeleteAccountRequest,
+    responses(
+        (status = 200, description = "账户已注销，deleted 字段为各类数据的删除数量", body = Object),
+        (status = 401, description = "密码错误或令牌无效", body = Object)
+    )
+)]
+async fn delete_my_account(
+    State(state): State<AppState>,
+    Extension(auth_user): Extension<AuthenticatedUser>,
+    Json(request): Json<DeleteAccountRequest>,
+) -> impl IntoResponse {
+    info!("收到注销账户请求: {}", auth_user.email);
+
+    let account_id = match AccountId::parse(&auth_user.account_id) {
+        Ok(account_id) => account_id,
+        Err(_) => return handle_auth_error(AuthError::InvalidToken),
+    };
+
+    if let Err(e) = state.auth_service.confirm_password(&account_id, &request.password).await {
+        warn!("注销账户密码验证失败: {} - {}", auth_user.email, e);
+        return handle_auth_error(e);
+    }
+
+    match state.delete_account(&account_id, &auth_user.user_id).await {
+        Ok(summary) => {
+            info!("账户已注销: {} ({:?})", auth_user.email, summary);
+            (
+                StatusCode::OK,
+                Json(json!({
+                    "success": true,
+                    "message": "账户已注销",
+                    "deleted": summary
+                }))
+            )
+        }
+        Err(e) => {
+            error!("注销账户失败: {} - {}", auth_user.email, e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({
+                    "success": false,
+                    "message": "注销账户失败"
+                }))
+            )
+        }
+    }
+}
+
+/// 获取当前账户的有效会话（登录设备）
+#[utoipa::path(
+    get,
+    path = "/api/auth/sessions",
+    tag = "auth",
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "sessions 字段为 SessionInfo 列表", body = Object),
+        (status = 401, description = "令牌无效", body = Object)
+    )
+)]
+async fn list_my_sessions(
+    State(state): State<AppState>,
+    Extension(auth_user): Extension<AuthenticatedUser>,
+) -> impl IntoResponse {
+    let account_id = match AccountId::parse(&auth_user.account_id) {
+        Ok(account_id) => account_id,
+        Err(_) => return handle_auth_error(AuthError::InvalidToken),
+    };
+
+    match state.auth_service.list_active_sessions(&account_id).await {
+        Ok(sessions) => (
+            StatusCode::OK,
+            Json(json!({
+                "success": true,
+                "message": "获取会话列表成功",
+                "sessions": sessions
+            }))
+        ),
+        Err(e) => {
+            error!("获取会话列表失败: {} - {}", auth_user.email, e);
+            handle_auth_error(e)
+        }
+    }
+}
+
+/// 撤销当前账户的某个会话，只能撤销自己的会话
+#[utoipa::path(
+    delete,
+    path = "/api/auth/sessions/{id}",
+    tag = "auth",
+    security(("bearer_auth" = [])),
+    params(("id" = String, Path, description = "会话ID")),
+    responses(
+        (status = 200, description = "会话已撤销", body = Object),
+        (status = 404, description = "会话不存在", body = Object)
+    )
+)]
+async fn revoke_my_session(
+    State(state): State<AppState>,
+    Extension(auth_user): Extension<AuthenticatedUser>,
+    Path(session_id): Path<String>,
+) -> impl IntoResponse {
+    let account_id = match AccountId::parse(&auth_user.account_id) {
+        Ok(account_id) => account_id,
+        Err(_) => return handle_auth_error(AuthError::InvalidToken),
+    };
+
+    match state.auth_service.revoke_session(&account_id, &session_id).await {
+        Ok(()) => {
+            info!("用户 {} 撤销了会话 {}", auth_user.email, session_id);
+            (
+                StatusCode::OK,
+                Json(json!({
+                    "success": true,
+                    "message": "会话已撤销"
+                }))
+            )
+        }
+        Err(e) => {
+            warn!("撤销会话失败: {} - {}", auth_user.email, e);
+            handle_auth_error(e)
+        }
+    }
+}
+
 /// 处理认证错误，转换为HTTP响应
-fn handle_auth_error(error: AuthError) -> (StatusCode, Json<serde_json::Value>) {
-    let (status, message) = match error {
+pub(super) fn handle_auth_error(error: AuthError) -> (StatusCode, Json<serde_json::Value>) {
+    let (status, message) = match &error {
         AuthError::InvalidEmail => (StatusCode::BAD_REQUEST, "邮箱地址格式无效"),
-        AuthError::InvalidPassword => (StatusCode::BAD_REQUEST, "密码不符合要求（至少6位）"),
+        AuthError::InvalidPassword(_) => (StatusCode::BAD_REQUEST, "密码不符合要求"),
         AuthError::EmailAlreadyExists => (StatusCode::CONFLICT, "邮箱已被注册"),
         AuthError::AccountNotFound => (StatusCode::NOT_FOUND, "账户不存在"),
         AuthError::InvalidCredentials => (StatusCode::UNAUTHORIZED, "邮箱或密码错误"),
@@ -298,9 +603,17 @@ fn handle_auth_error(error: AuthError) -> (StatusCode, Json<serde_json::Value>)
         AuthError::VerificationSendFailed => (StatusCode::SERVICE_UNAVAILABLE, "验证码发送失败"),
         AuthError::TokenExpired => (StatusCode::UNAUTHORIZED, "令牌已过期"),
         AuthError::InvalidToken => (StatusCode::UNAUTHORIZED, "令牌无效"),
+        AuthError::TokenRevoked => (StatusCode::UNAUTHORIZED, "令牌已被撤销"),
+        AuthError::SessionNotFound => (StatusCode::NOT_FOUND, "会话不存在"),
         AuthError::DatabaseError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "数据库错误"),
         AuthError::PasswordHashError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "密码处理错误"),
         AuthError::EmailSendError(_) => (StatusCode::SERVICE_UNAVAILABLE, "邮件发送失败"),
+        AuthError::InvalidJwtConfig(_) => (StatusCode::INTERNAL_SERVER_ERROR, "服务器配置错误"),
+    };
+    // 密码问题附上具体原因，方便用户修改
+    let message = match &error {
+        AuthError::InvalidPassword(problem) => format!("{}：{}", message, problem),
+        _ => message.to_string(),
     };
 
     (
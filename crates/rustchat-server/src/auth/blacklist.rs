@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+
+/// 已撤销的访问令牌
+///
+/// 访问令牌在有效期内无需查询数据库即可通过验证，登出或强制注销后需要在这里记录，
+/// 让尚未过期的令牌立即失效。条目在对应令牌过期后清除。只保存在本实例内存中
+#[derive(Debug, Default)]
+pub struct TokenBlacklist {
+    /// 单个令牌：jti -> 令牌过期时间（Unix秒）
+    tokens: HashMap<String, i64>,
+    /// 整个账户：账户ID -> (早于该时间签发的令牌全部失效（Unix毫秒）, 条目过期时间（Unix秒）)
+    ///
+    /// 令牌的 `iat` 只精确到秒，按秒比较会让撤销后同一秒内重新登录得到的令牌也被拒绝
+    accounts: HashMap<String, (i64, i64)>,
+    /// 单个会话：会话ID -> 条目过期时间（Unix秒），会话签发的访问令牌全部失效
+    sessions: HashMap<String, i64>,
+}
+
+impl TokenBlacklist {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 撤销单个令牌，`exp` 为令牌的过期时间
+    pub fn revoke_token(&mut self, jti: &str, exp: i64, now: i64) {
+        self.prune(now);
+        if exp > now {
+            self.tokens.insert(jti.to_string(), exp);
+        }
+    }
+
+    /// 撤销账户在 `now_millis` 之前签发的所有令牌，`max_lifetime_secs` 为令牌的最长有效期
+    pub fn revoke_account(&mut self, account_id: &str, now_millis: i64, max_lifetime_secs: i64) {
+        let now = now_millis.div_euclid(1000);
+        self.prune(now);
+        self.accounts.insert(account_id.to_string(), (now_millis, now + max_lifetime_secs));
+    }
+
+    /// 撤销会话签发的所有令牌，`max_lifetime_secs` 为令牌的最长有效期
+    pub fn revoke_session(&mut self, session_id: &str, now: i64, max_lifetime_secs: i64) {
+        self.prune(now);
+        self.sessions.insert(session_id.to_string(), now + max_lifetime_secs);
+    }
+
+    /// 会话签发的令牌是否已被撤销
+    pub fn is_session_revoked(&self, session_id: &str) -> bool {
+        self.sessions.contains_key(session_id)
+    }
+
+    /// 令牌是否已被撤销，`issued_at_millis` 为令牌的签发时间（Unix毫秒）
+    pub fn is_revoked(&self, jti: &str, account_id: &str, issued_at_millis: i64) -> bool {
+        if self.tokens.contains_key(jti) {
+            return true;
+        }
+        matches!(self.accounts.get(account_id), Some((revoked_before, _)) if issued_at_millis < *revoked_before)
+    }
+
+    /// 清除已过期的条目
+    fn prune(&mut self, now: i64) {
+        self.tokens.retain(|_, exp| *exp > now);
+        self.accounts.retain(|_, (_, expires)| *expires > now);
+        self.sessions.retain(|_, expires| *expires > now);
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.tokens.len() + self.accounts.len() + self.sessions.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_revoked_entries_expire_with_tokens() {
+        let mut blacklist = TokenBlacklist::new();
+        blacklist.revoke_token("jti-1", 1_100, 1_000);
+        assert!(blacklist.is_revoked("jti-1", "alice", 900_000));
+        assert!(!blacklist.is_revoked("jti-2", "alice", 900_000));
+
+        // 撤销账户后，之前签发的令牌失效，之后签发的不受影响，同一秒内稍后签发的也不受影响
+        blacklist.revoke_account("bob", 1_050_500, 900);
+        assert!(blacklist.is_revoked("jti-3", "bob", 1_050_499));
+        assert!(!blacklist.is_revoked("jti-4", "bob", 1_050_500));
+        assert!(!blacklist.is_revoked("jti-4", "bob", 1_050_900));
+
+        // 撤销会话只影响该会话签发的令牌
+        blacklist.revoke_session("session-1", 1_050, 900);
+        assert!(blacklist.is_session_revoked("session-1"));
+        assert!(!blacklist.is_session_revoked("session-2"));
+
+        // 已过期的令牌不再占用空间
+        blacklist.revoke_token("jti-5", 1_200, 1_150);
+        assert!(!blacklist.is_revoked("jti-1", "alice", 900_000));
+        assert_eq!(blacklist.len(), 3);
+        blacklist.revoke_token("already-expired", 1_000, 2_000);
+        assert_eq!(blacklist.len(), 0);
+    }
+}
@@ -0,0 +1,177 @@
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tracing::warn;
+
+use super::AuthError;
+
+/// JWT签名密钥的最短长度（字节），HS256的密钥不应短于哈希输出长度
+pub const MIN_JWT_SECRET_BYTES: usize = 32;
+
+/// 仅用于本地开发的默认JWT密钥，生产环境禁止使用
+const DEV_JWT_SECRET: &str = "your-256-bit-secret-key-that-should-be-from-env";
+
+/// JWT签名算法和对应的签名、验证密钥
+///
+/// HS256 使用共享密钥；RS256 使用RSA密钥对，其他服务只需公钥即可验证令牌
+#[derive(Clone)]
+pub struct JwtKeys {
+    algorithm: Algorithm,
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+}
+
+impl JwtKeys {
+    /// 使用共享密钥的HS256
+    pub fn hs256(secret: &str) -> Self {
+        Self {
+            algorithm: Algorithm::HS256,
+            encoding_key: EncodingKey::from_secret(secret.as_bytes()),
+            decoding_key: DecodingKey::from_secret(secret.as_bytes()),
+        }
+    }
+
+    /// 使用PEM格式RSA私钥和公钥的RS256，私钥和公钥不是一对时返回错误
+    pub fn rs256_from_pem(private_pem: &[u8], public_pem: &[u8]) -> Result<Self, AuthError> {
+        let encoding_key = EncodingKey::from_rsa_pem(private_pem)
+            .map_err(|e| AuthError::InvalidJwtConfig(format!("无法解析RSA私钥: {}", e)))?;
+        let decoding_key = DecodingKey::from_rsa_pem(public_pem)
+            .map_err(|e| AuthError::InvalidJwtConfig(format!("无法解析RSA公钥: {}", e)))?;
+        let keys = Self { algorithm: Algorithm::RS256, encoding_key, decoding_key };
+
+        // 启动时签发并验证一个探测令牌，尽早发现密钥不匹配
+        #[derive(Serialize, Deserialize)]
+        struct Probe {
+            exp: i64,
+        }
+        let probe = keys
+            .encode(&Probe { exp: i64::MAX })
+            .map_err(|e| AuthError::InvalidJwtConfig(format!("RSA私钥无法签名: {}", e)))?;
+        keys.decode::<Probe>(&probe)
+            .map_err(|_| AuthError::InvalidJwtConfig("RSA私钥和公钥不匹配".to_string()))?;
+
+        Ok(keys)
+    }
+
+    /// 根据环境变量选择算法和密钥
+    ///
+    /// `JWT_ALGORITHM` 为 `HS256`（默认）时使用 `JWT_SECRET`；为 `RS256` 时从
+    /// `JWT_PRIVATE_KEY_PATH` 和 `JWT_PUBLIC_KEY_PATH` 读取PEM密钥
+    pub fn from_env(production: bool) -> Result<Self, AuthError> {
+        let algorithm = std::env::var("JWT_ALGORITHM").unwrap_or_default();
+        match algorithm.trim().to_uppercase().as_str() {
+            "" | "HS256" => Ok(Self::hs256(&resolve_jwt_secret(std::env::var("JWT_SECRET").ok(), production)?)),
+            "RS256" => Self::rs256_from_pem(
+                &read_key_file("JWT_PRIVATE_KEY_PATH")?,
+                &read_key_file("JWT_PUBLIC_KEY_PATH")?,
+            ),
+            other => Err(AuthError::InvalidJwtConfig(format!(
+                "不支持的 JWT_ALGORITHM: {}（可选 HS256 或 RS256）",
+                other
+            ))),
+        }
+    }
+
+    /// 签名使用的算法
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// 签发令牌
+    pub fn encode<T: Serialize>(&self, claims: &T) -> jsonwebtoken::errors::Result<String> {
+        encode(&Header::new(self.algorithm), claims, &self.encoding_key)
+    }
+
+    /// 验证签名和过期时间并解析令牌，只接受配置的算法
+    pub fn decode<T: DeserializeOwned>(&self, token: &str) -> jsonwebtoken::errors::Result<T> {
+        decode::<T>(token, &self.decoding_key, &Validation::new(self.algorithm)).map(|data| data.claims)
+    }
+}
+
+/// 读取环境变量指定路径的密钥文件
+fn read_key_file(key: &str) -> Result<Vec<u8>, AuthError> {
+    let path = std::env::var(key)
+        .map_err(|_| AuthError::InvalidJwtConfig(format!("使用RS256时必须设置 {}", key)))?;
+    std::fs::read(&path).map_err(|e| AuthError::InvalidJwtConfig(format!("无法读取 {} ({}): {}", key, path, e)))
+}
+
+/// 确定HS256的签名密钥
+///
+/// 生产环境缺少密钥或密钥过短时返回错误；开发环境使用默认密钥或过短的密钥时只输出警告
+fn resolve_jwt_secret(secret: Option<String>, production: bool) -> Result<String, AuthError> {
+    let secret = secret.filter(|secret| !secret.is_empty());
+    match secret {
+        Some(secret) if secret.len() >= MIN_JWT_SECRET_BYTES => Ok(secret),
+        Some(_) if production => Err(AuthError::InvalidJwtConfig(format!(
+            "JWT_SECRET 至少需要 {} 字节",
+            MIN_JWT_SECRET_BYTES
+        ))),
+        None if production => Err(AuthError::InvalidJwtConfig("生产环境必须设置 JWT_SECRET".to_string())),
+        Some(secret) => {
+            warn!("⚠️ JWT_SECRET 短于 {} 字节，令牌容易被伪造，生产环境将拒绝启动", MIN_JWT_SECRET_BYTES);
+            Ok(secret)
+        }
+        None => {
+            warn!("⚠️ 未设置 JWT_SECRET，正在使用公开的默认密钥，任何人都能伪造令牌。仅限本地开发使用！");
+            Ok(DEV_JWT_SECRET.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRIVATE_PEM: &[u8] = include_bytes!("testdata/jwt_rs256_private.pem");
+    const PUBLIC_PEM: &[u8] = include_bytes!("testdata/jwt_rs256_public.pem");
+    const OTHER_PRIVATE_PEM: &[u8] = include_bytes!("testdata/jwt_rs256_other_private.pem");
+    const OTHER_PUBLIC_PEM: &[u8] = include_bytes!("testdata/jwt_rs256_other_public.pem");
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Claims {
+        sub: String,
+        exp: i64,
+    }
+
+    #[test]
+    fn test_production_requires_strong_jwt_secret() {
+        let strong = "k".repeat(MIN_JWT_SECRET_BYTES);
+
+        assert_eq!(resolve_jwt_secret(Some(strong.clone()), true).unwrap(), strong);
+        assert!(matches!(resolve_jwt_secret(None, true), Err(AuthError::InvalidJwtConfig(_))));
+        assert!(matches!(resolve_jwt_secret(Some(String::new()), true), Err(AuthError::InvalidJwtConfig(_))));
+        assert!(matches!(resolve_jwt_secret(Some("short".to_string()), true), Err(AuthError::InvalidJwtConfig(_))));
+    }
+
+    #[test]
+    fn test_development_falls_back_with_warning() {
+        assert_eq!(resolve_jwt_secret(None, false).unwrap(), DEV_JWT_SECRET);
+        assert_eq!(resolve_jwt_secret(Some(String::new()), false).unwrap(), DEV_JWT_SECRET);
+        // 过短的密钥在开发环境仍然可用
+        assert_eq!(resolve_jwt_secret(Some("short".to_string()), false).unwrap(), "short");
+    }
+
+    #[test]
+    fn test_rs256_round_trip_and_algorithm_pinning() {
+        let rs256 = JwtKeys::rs256_from_pem(PRIVATE_PEM, PUBLIC_PEM).unwrap();
+        assert_eq!(rs256.algorithm(), Algorithm::RS256);
+
+        let claims = Claims { sub: "alice".to_string(), exp: chrono::Utc::now().timestamp() + 60 };
+        let token = rs256.encode(&claims).unwrap();
+        assert_eq!(rs256.decode::<Claims>(&token).unwrap(), claims);
+
+        // 只接受配置的算法：HS256令牌不能通过RS256验证，反之亦然
+        let hs256 = JwtKeys::hs256(DEV_JWT_SECRET);
+        assert!(rs256.decode::<Claims>(&hs256.encode(&claims).unwrap()).is_err());
+        assert!(hs256.decode::<Claims>(&token).is_err());
+
+        // 另一对密钥签发的令牌不能通过验证
+        let other = JwtKeys::rs256_from_pem(OTHER_PRIVATE_PEM, OTHER_PUBLIC_PEM).unwrap();
+        assert!(rs256.decode::<Claims>(&other.encode(&claims).unwrap()).is_err());
+
+        // 私钥和公钥不匹配、密钥格式错误时拒绝启动
+        assert!(matches!(JwtKeys::rs256_from_pem(PRIVATE_PEM, OTHER_PUBLIC_PEM), Err(AuthError::InvalidJwtConfig(_))));
+        assert!(matches!(JwtKeys::rs256_from_pem(OTHER_PRIVATE_PEM, PUBLIC_PEM), Err(AuthError::InvalidJwtConfig(_))));
+        assert!(matches!(JwtKeys::rs256_from_pem(PRIVATE_PEM, b"not a key"), Err(AuthError::InvalidJwtConfig(_))));
+        assert!(matches!(JwtKeys::rs256_from_pem(PUBLIC_PEM, PUBLIC_PEM), Err(AuthError::InvalidJwtConfig(_))));
+    }
+}
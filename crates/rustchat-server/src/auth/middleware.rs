@@ -2,12 +2,11 @@ use axum::{
     extract::{Request, State},
     http::{header::AUTHORIZATION, StatusCode},
     middleware::Next,
-    response::Response,
-    Extension,
+    response::{IntoResponse, Response},
 };
 use rustchat_types::UserId;
 
-use crate::{AppState, auth::{AuthError, TokenType}};
+use crate::{AppState, auth::{AccountId, AccountStatus, AuthError, TokenType}};
 
 /// 用户认证信息
 #[derive(Debug, Clone)]
@@ -57,6 +56,10 @@ pub async fn auth_middleware(
             
             // 从数据库获取完整的用户信息
             match state.auth_service.get_account_by_id(&account_id).await {
+                Ok(account) if account.status == AccountStatus::Deleted => {
+                    tracing::warn!("认证中间件: 账户已注销 {}", account.id);
+                    Err(StatusCode::UNAUTHORIZED)
+                }
                 Ok(account) => {
                     let user_id = UserId::parse(&account.id.to_string())
                         .map_err(|e| {
@@ -93,6 +96,42 @@ pub async fn auth_middleware(
     }
 }
 
+/// 管理员中间件 - 必须位于认证中间件之后，只允许配置中的管理员访问
+///
+/// 按账户当前的邮箱判断，且要求邮箱已验证：注销账户的邮箱会被释放，
+/// 只凭邮箱字符串判断时任何人都能重新注册管理员用过的邮箱获得管理权限
+pub async fn admin_middleware(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let auth_user = request
+        .extensions()
+        .get::<AuthenticatedUser>()
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    let account_id = AccountId::parse(&auth_user.account_id).map_err(|_| StatusCode::UNAUTHORIZED)?;
+    let account = match state.auth_service.get_account_by_id(&account_id).await {
+        Ok(account) => account,
+        Err(AuthError::AccountNotFound) => return Err(StatusCode::FORBIDDEN),
+        Err(e) => {
+            tracing::error!("管理员中间件: 查询账户 {} 失败: {}", auth_user.account_id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    if account.status != AccountStatus::Active || !state.config.is_admin(&account.email) {
+        tracing::warn!("管理员中间件: 非管理员用户 {} 尝试访问 {}", auth_user.email, request.uri());
+        return Err(StatusCode::FORBIDDEN);
+    }
+    if !account.email_verified {
+        tracing::warn!("管理员中间件: 管理员邮箱 {} 尚未验证，拒绝访问 {}", account.email, request.uri());
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(next.run(request).await)
+}
+
 /// 可选认证中间件 - 如果有token则验证，没有token也继续
 pub async fn optional_auth_middleware(
     State(state): State<AppState>,
@@ -105,12 +144,16 @@ pub async fn optional_auth_middleware(
         .get(AUTHORIZATION)
         .and_then(|header| header.to_str().ok())
     {
-        if auth_header.starts_with("Bearer ") {
-            let token = &auth_header[7..]; // 移除 "Bearer " 前缀            // 验证token并提取用户信息
+        if let Some(token) = auth_header.strip_prefix("Bearer ") {
+            // 验证token并提取用户信息
             if let Ok(claims) = state.auth_service.verify_token(token, TokenType::Access) {
                 // 从claims.sub解析AccountId
                 if let Ok(account_id) = crate::auth::AccountId::parse(&claims.sub) {
-                    if let Ok(account) = state.auth_service.get_account_by_id(&account_id).await {
+                    // 已注销账户的令牌按匿名处理
+                    let account = state.auth_service.get_account_by_id(&account_id).await
+                        .ok()
+                        .filter(|account| account.status != AccountStatus::Deleted);
+                    if let Some(account) = account {
                         if let Ok(user_id) = UserId::parse(&account.id.to_string()) {
                             let auth_user = AuthenticatedUser {
                                 user_id,
@@ -127,5 +170,11 @@ pub async fn optional_auth_middleware(
         }
     }
 
+    // 禁止匿名访问时，可选认证的路由同样要求认证
+    let authenticated = request.extensions().get::<AuthenticatedUser>().is_some();
+    if !state.config.permits_connection(authenticated) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
     next.run(request).await
 }
@@ -1,15 +1,22 @@
 pub mod api;
 pub mod service;
 pub mod middleware;
+pub mod admin;
+pub mod password;
+pub mod jwt;
+pub mod blacklist;
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use uuid::Uuid;
 
 // 重新导出主要类型和函数
-pub use api::create_auth_routes;
+pub use api::{create_auth_routes, create_protected_auth_routes};
+pub use admin::create_admin_routes;
 pub use service::AuthService;
-pub use middleware::{auth_middleware, optional_auth_middleware, AuthenticatedUser};
+pub use password::{PasswordPolicy, PasswordProblem};
+pub use middleware::AuthenticatedUser;
 
 /// 用户账户ID
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -25,11 +32,6 @@ impl AccountId {
     pub fn parse(s: &str) -> Result<Self, uuid::Error> {
         Ok(Self(Uuid::parse_str(s)?))
     }
-    
-    /// 转换为字符串
-    pub fn to_string(&self) -> String {
-        self.0.to_string()
-    }
 }
 
 impl std::fmt::Display for AccountId {
@@ -154,8 +156,8 @@ impl std::str::FromStr for VerificationPurpose {
 pub enum AuthError {
     #[error("邮箱地址无效")]
     InvalidEmail,
-    #[error("密码不符合要求")]
-    InvalidPassword,
+    #[error("密码不符合要求: {0}")]
+    InvalidPassword(PasswordProblem),
     #[error("邮箱已被注册")]
     EmailAlreadyExists,
     #[error("账户不存在")]
@@ -176,16 +178,22 @@ pub enum AuthError {
     TokenExpired,
     #[error("令牌无效")]
     InvalidToken,
+    #[error("令牌已被撤销")]
+    TokenRevoked,
+    #[error("会话不存在")]
+    SessionNotFound,
     #[error("数据库错误: {0}")]
     DatabaseError(#[from] anyhow::Error),
     #[error("密码哈希错误: {0}")]
     PasswordHashError(String),
     #[error("邮件发送错误: {0}")]
     EmailSendError(#[from] lettre::error::Error),
+    #[error("JWT配置无效: {0}")]
+    InvalidJwtConfig(String),
 }
 
 /// 注册请求
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct RegisterRequest {
     pub email: String,
     pub password: String,
@@ -193,21 +201,33 @@ pub struct RegisterRequest {
 }
 
 /// 登录请求
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct LoginRequest {
     pub email: String,
     pub password: String,
 }
 
+/// 清除个人数据请求（需要重新输入密码）
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PurgeDataRequest {
+    pub password: String,
+}
+
+/// 注销账户请求（需要重新输入密码）
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct DeleteAccountRequest {
+    pub password: String,
+}
+
 /// 邮箱验证请求
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct VerifyEmailRequest {
     pub email: String,
     pub code: String,
 }
 
 /// 重发验证码请求
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct ResendCodeRequest {
     pub email: String,
 }
@@ -227,6 +247,22 @@ pub struct JwtClaims {
     pub exp: i64, // expiration time
     /// 令牌类型 (access/refresh)
     pub token_type: String,
+    /// 令牌唯一ID，保证同一秒内签发的令牌互不相同（每个会话的刷新令牌哈希唯一）
+    #[serde(default)]
+    pub jti: String,
+    /// 令牌颁发时间（Unix毫秒），用于判断令牌是否早于账户级撤销；旧令牌没有该字段时按 `iat` 计算
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iat_ms: Option<i64>,
+    /// 签发令牌的会话ID，撤销会话时据此让该会话签发的访问令牌失效
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+}
+
+impl JwtClaims {
+    /// 令牌颁发时间（Unix毫秒）
+    pub fn issued_at_millis(&self) -> i64 {
+        self.iat_ms.unwrap_or(self.iat * 1000)
+    }
 }
 
 /// 令牌类型
@@ -246,7 +282,7 @@ impl std::fmt::Display for TokenType {
 }
 
 /// 令牌对
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct TokenPair {
     pub access_token: String,
     pub refresh_token: String,
@@ -255,13 +291,41 @@ pub struct TokenPair {
 }
 
 /// 刷新令牌请求
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct RefreshTokenRequest {
     pub refresh_token: String,
 }
 
+/// 登出请求，同时提供刷新令牌时一并撤销对应的会话
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct LogoutRequest {
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+}
+
+/// 登录会话信息（不包含令牌）
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SessionInfo {
+    pub id: String,
+    pub device_info: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub is_active: bool,
+}
+
+/// 过期数据清理结果
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExpiredCleanup {
+    /// 删除的过期会话数
+    pub sessions: u64,
+    /// 删除的过期或已使用的验证码数
+    pub verification_codes: u64,
+}
+
 /// 认证响应
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct AuthResponse {
     pub account_id: String,
     pub email: String,
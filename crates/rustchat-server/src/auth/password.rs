@@ -0,0 +1,150 @@
+/// 密码长度上限（字符数）
+pub const MAX_PASSWORD_CHARS: usize = 128;
+
+/// 默认的最短密码长度（字符数）
+pub const DEFAULT_MIN_PASSWORD_CHARS: usize = 6;
+
+/// 内置的常见弱密码列表（比较时忽略大小写）
+const COMMON_PASSWORDS: &[&str] = &[
+    "123456", "1234567", "12345678", "123456789", "1234567890", "111111", "000000", "123123",
+    "654321", "666666", "888888", "password", "password1", "password123", "passw0rd", "qwerty",
+    "qwerty123", "qwe123", "abc123", "abcd1234", "a123456", "123456a", "123abc", "1q2w3e",
+    "1q2w3e4r", "1qaz2wsx", "iloveyou", "iloveyou1", "admin", "admin123", "root123", "welcome",
+    "welcome1", "letmein", "letmein1", "monkey1", "dragon1", "football1", "baseball1", "sunshine1",
+    "princess1", "master123", "test123", "test1234", "woaini1314", "aa123456", "zxcvbnm",
+    "asdf1234", "changeme1",
+];
+
+/// 密码强度策略
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasswordPolicy {
+    /// 最短长度（字符数）
+    pub min_length: usize,
+    /// 是否要求至少包含一个字母
+    pub require_letter: bool,
+    /// 是否要求至少包含一个数字
+    pub require_digit: bool,
+    /// 是否要求至少包含一个符号（非字母、数字和空白的字符）
+    pub require_symbol: bool,
+    /// 是否拒绝常见弱密码
+    pub reject_common: bool,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: DEFAULT_MIN_PASSWORD_CHARS,
+            require_letter: true,
+            require_digit: true,
+            require_symbol: false,
+            reject_common: true,
+        }
+    }
+}
+
+/// 密码不符合策略的具体原因
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum PasswordProblem {
+    #[error("至少需要{0}个字符")]
+    TooShort(usize),
+    #[error("不能超过{0}个字符")]
+    TooLong(usize),
+    #[error("至少需要包含一个字母")]
+    MissingLetter,
+    #[error("至少需要包含一个数字")]
+    MissingDigit,
+    #[error("至少需要包含一个符号")]
+    MissingSymbol,
+    #[error("密码过于常见，请换一个")]
+    TooCommon,
+}
+
+impl PasswordPolicy {
+    /// 不做复杂度检查的宽松策略（用于测试和本地开发）
+    pub fn relaxed() -> Self {
+        Self {
+            min_length: 1,
+            require_letter: false,
+            require_digit: false,
+            require_symbol: false,
+            reject_common: false,
+        }
+    }
+
+    /// 检查密码是否符合策略，返回第一个不满足的条件
+    pub fn validate(&self, password: &str) -> Result<(), PasswordProblem> {
+        let length = password.chars().count();
+        if length < self.min_length {
+            return Err(PasswordProblem::TooShort(self.min_length));
+        }
+        if length > MAX_PASSWORD_CHARS {
+            return Err(PasswordProblem::TooLong(MAX_PASSWORD_CHARS));
+        }
+        if self.require_letter && !password.chars().any(char::is_alphabetic) {
+            return Err(PasswordProblem::MissingLetter);
+        }
+        if self.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+            return Err(PasswordProblem::MissingDigit);
+        }
+        if self.require_symbol && !password.chars().any(is_symbol) {
+            return Err(PasswordProblem::MissingSymbol);
+        }
+        if self.reject_common && is_common_password(password) {
+            return Err(PasswordProblem::TooCommon);
+        }
+        Ok(())
+    }
+}
+
+fn is_symbol(c: char) -> bool {
+    !c.is_alphanumeric() && !c.is_whitespace()
+}
+
+fn is_common_password(password: &str) -> bool {
+    let password = password.to_lowercase();
+    COMMON_PASSWORDS.contains(&password.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_policy_rejections() {
+        let policy = PasswordPolicy::default();
+
+        assert_eq!(policy.validate("a1b2"), Err(PasswordProblem::TooShort(6)));
+        assert_eq!(policy.validate(&"a1".repeat(65)), Err(PasswordProblem::TooLong(128)));
+        assert_eq!(policy.validate("aaaaaa"), Err(PasswordProblem::MissingDigit));
+        assert_eq!(policy.validate("12345678"), Err(PasswordProblem::MissingLetter));
+        assert_eq!(policy.validate("Password1"), Err(PasswordProblem::TooCommon));
+        assert_eq!(policy.validate("secret123"), Ok(()));
+        // 长度按字符计算
+        assert_eq!(policy.validate("密码1a"), Err(PasswordProblem::TooShort(6)));
+        assert_eq!(policy.validate("中文密码12"), Ok(()));
+    }
+
+    #[test]
+    fn test_symbol_requirement() {
+        let policy = PasswordPolicy {
+            require_symbol: true,
+            ..PasswordPolicy::default()
+        };
+
+        assert_eq!(policy.validate("secret123"), Err(PasswordProblem::MissingSymbol));
+        assert_eq!(policy.validate("secret 123"), Err(PasswordProblem::MissingSymbol));
+        assert_eq!(policy.validate("secret-123"), Ok(()));
+    }
+
+    #[test]
+    fn test_relaxed_policy_accepts_simple_passwords() {
+        let policy = PasswordPolicy {
+            min_length: 4,
+            ..PasswordPolicy::relaxed()
+        };
+
+        assert_eq!(policy.validate("aaaa"), Ok(()));
+        assert_eq!(policy.validate("123456"), Ok(()));
+        assert_eq!(policy.validate("abc"), Err(PasswordProblem::TooShort(4)));
+    }
+}
@@ -1,114 +1,121 @@
-use super::{Account, AccountId, AccountStatus, AuthError, EmailVerification, VerificationPurpose, JwtClaims, TokenType, TokenPair};
+use super::blacklist::TokenBlacklist;
+use super::jwt::JwtKeys;
+use super::{Account, AccountId, AccountStatus, AuthError, EmailVerification, ExpiredCleanup, PasswordPolicy, VerificationPurpose, JwtClaims, SessionInfo, TokenType, TokenPair};
 use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
 use argon2::password_hash::{rand_core::OsRng, SaltString};
 use chrono::{DateTime, Duration, Utc};
-use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
 use rand::Rng;
-use sqlx::{Row, SqlitePool};
+use rustchat_core::{row_bool, run_migrations, Migration};
+use sqlx::any::AnyRow;
+use sqlx::{AnyPool, Row};
+use std::sync::{Arc, Mutex};
 use tracing::{debug, info};
 
+/// 认证数据库的结构迁移，新的列或表以更高的版本追加在末尾
+const AUTH_MIGRATIONS: &[Migration] = &[Migration {
+    version: 1,
+    description: "账户、邮箱验证码、会话和审计日志表",
+    statements: &[
+        r#"
+        CREATE TABLE IF NOT EXISTS accounts (
+            id TEXT PRIMARY KEY,
+            email TEXT UNIQUE NOT NULL,
+            password_hash TEXT NOT NULL,
+            display_name TEXT,
+            status TEXT NOT NULL DEFAULT 'active',
+            email_verified BOOLEAN NOT NULL DEFAULT FALSE,
+            created_at TEXT NOT NULL,
+            last_login_at TEXT
+        )
+        "#,
+        r#"
+        CREATE TABLE IF NOT EXISTS email_verifications (
+            email TEXT NOT NULL,
+            code TEXT NOT NULL,
+            purpose TEXT NOT NULL,
+            expires_at TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            used BOOLEAN NOT NULL DEFAULT FALSE,
+            PRIMARY KEY (email, code, purpose)
+        )
+        "#,
+        r#"
+        CREATE TABLE IF NOT EXISTS sessions (
+            id TEXT PRIMARY KEY,
+            account_id TEXT NOT NULL,
+            refresh_token_hash TEXT NOT NULL,
+            device_info TEXT,
+            ip_address TEXT,
+            created_at TEXT NOT NULL,
+            expires_at TEXT NOT NULL,
+            last_used_at TEXT NOT NULL,
+            is_active BOOLEAN NOT NULL DEFAULT TRUE,
+            FOREIGN KEY (account_id) REFERENCES accounts(id) ON DELETE CASCADE
+        )
+        "#,
+        "CREATE INDEX IF NOT EXISTS idx_accounts_email ON accounts(email)",
+        "CREATE INDEX IF NOT EXISTS idx_email_verifications_email ON email_verifications(email)",
+        "CREATE INDEX IF NOT EXISTS idx_sessions_account_id ON sessions(account_id)",
+        "CREATE INDEX IF NOT EXISTS idx_sessions_refresh_token ON sessions(refresh_token_hash)",
+        // 管理操作审计
+        r#"
+        CREATE TABLE IF NOT EXISTS audit_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            actor_account_id TEXT NOT NULL,
+            action TEXT NOT NULL,
+            target_account_id TEXT,
+            details TEXT,
+            created_at TEXT NOT NULL
+        )
+        "#,
+    ],
+}];
+
 /// 认证服务
 #[derive(Clone)]
 pub struct AuthService {
-    db_pool: SqlitePool,
+    db_pool: AnyPool,
     argon2: Argon2<'static>,
-    jwt_secret: String,
+    jwt_keys: JwtKeys,
     access_token_duration: Duration,
     refresh_token_duration: Duration,
+    password_policy: PasswordPolicy,
+    /// 登出或被强制注销后立即失效的访问令牌
+    revoked_tokens: Arc<Mutex<TokenBlacklist>>,
 }
 
-impl AuthService {    /// 创建新的认证服务
-    pub fn new(db_pool: SqlitePool) -> Self {
-        // 在生产环境中，应该从环境变量读取 JWT 密钥
-        let jwt_secret = std::env::var("JWT_SECRET")
-            .unwrap_or_else(|_| "your-256-bit-secret-key-that-should-be-from-env".to_string());
-        
-        Self {
+impl AuthService {    /// 创建新的认证服务，JWT算法和密钥从环境变量读取（见 `JwtKeys::from_env`）
+    ///
+    /// `production` 为true时缺少HS256密钥或密钥短于 `MIN_JWT_SECRET_BYTES` 会返回错误
+    pub fn new(db_pool: AnyPool, production: bool) -> Result<Self, AuthError> {
+        let jwt_keys = JwtKeys::from_env(production)?;
+        info!("JWT签名算法: {:?}", jwt_keys.algorithm());
+
+        Ok(Self {
             db_pool,
             argon2: Argon2::default(),
-            jwt_secret,
+            jwt_keys,
             access_token_duration: Duration::minutes(15), // 15分钟
             refresh_token_duration: Duration::days(7),    // 7天
-        }
+            password_policy: PasswordPolicy::default(),
+            revoked_tokens: Arc::new(Mutex::new(TokenBlacklist::new())),
+        })
+    }
+    
+    /// 设置注册时使用的密码强度策略
+    pub fn with_password_policy(mut self, password_policy: PasswordPolicy) -> Self {
+        self.password_policy = password_policy;
+        self
     }
     
     /// 获取数据库连接池
-    pub fn get_pool(&self) -> &SqlitePool {
+    pub fn get_pool(&self) -> &AnyPool {
         &self.db_pool
     }
     
     /// 初始化数据库表
     pub async fn initialize_database(&self) -> Result<(), AuthError> {
-        // 创建账户表
-        sqlx::query(r#"
-            CREATE TABLE IF NOT EXISTS accounts (
-                id TEXT PRIMARY KEY,
-                email TEXT UNIQUE NOT NULL,
-                password_hash TEXT NOT NULL,
-                display_name TEXT,
-                status TEXT NOT NULL DEFAULT 'active',
-                email_verified BOOLEAN NOT NULL DEFAULT FALSE,
-                created_at TEXT NOT NULL,
-                last_login_at TEXT
-            )
-        "#)
-        .execute(&self.db_pool)
-        .await
-        .map_err(|e| AuthError::DatabaseError(e.into()))?;
-          // 创建邮箱验证码表
-        sqlx::query(r#"
-            CREATE TABLE IF NOT EXISTS email_verifications (
-                email TEXT NOT NULL,
-                code TEXT NOT NULL,
-                purpose TEXT NOT NULL,
-                expires_at TEXT NOT NULL,
-                created_at TEXT NOT NULL,
-                used BOOLEAN NOT NULL DEFAULT FALSE,
-                PRIMARY KEY (email, code, purpose)
-            )
-        "#)
-        .execute(&self.db_pool)
-        .await
-        .map_err(|e| AuthError::DatabaseError(e.into()))?;
-        
-        // 创建会话表
-        sqlx::query(r#"
-            CREATE TABLE IF NOT EXISTS sessions (
-                id TEXT PRIMARY KEY,
-                account_id TEXT NOT NULL,
-                refresh_token_hash TEXT NOT NULL,
-                device_info TEXT,
-                ip_address TEXT,
-                created_at TEXT NOT NULL,
-                expires_at TEXT NOT NULL,
-                last_used_at TEXT NOT NULL,
-                is_active BOOLEAN NOT NULL DEFAULT TRUE,
-                FOREIGN KEY (account_id) REFERENCES accounts(id) ON DELETE CASCADE
-            )
-        "#)
-        .execute(&self.db_pool)
-        .await
-        .map_err(|e| AuthError::DatabaseError(e.into()))?;
-        
-        // 创建索引
-        sqlx::query("CREATE INDEX IF NOT EXISTS idx_accounts_email ON accounts(email)")
-            .execute(&self.db_pool)
-            .await
-            .map_err(|e| AuthError::DatabaseError(e.into()))?;
-              sqlx::query("CREATE INDEX IF NOT EXISTS idx_email_verifications_email ON email_verifications(email)")
-            .execute(&self.db_pool)
-            .await
-            .map_err(|e| AuthError::DatabaseError(e.into()))?;
-            
-        sqlx::query("CREATE INDEX IF NOT EXISTS idx_sessions_account_id ON sessions(account_id)")
-            .execute(&self.db_pool)
-            .await
-            .map_err(|e| AuthError::DatabaseError(e.into()))?;
-            
-        sqlx::query("CREATE INDEX IF NOT EXISTS idx_sessions_refresh_token ON sessions(refresh_token_hash)")
-            .execute(&self.db_pool)
-            .await
-            .map_err(|e| AuthError::DatabaseError(e.into()))?;
+        run_migrations(&self.db_pool, "auth", AUTH_MIGRATIONS).await?;
         
         info!("认证数据库表初始化完成");
         Ok(())
@@ -145,7 +152,7 @@ impl AuthService {    /// 创建新的认证服务
         // 保存到数据库
         sqlx::query(r#"
             INSERT INTO accounts (id, email, password_hash, display_name, status, email_verified, created_at)
-            VALUES (?, ?, ?, ?, ?, ?, ?)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
         "#)
         .bind(account.id.to_string())
         .bind(&account.email)
@@ -185,7 +192,7 @@ impl AuthService {    /// 创建新的认证服务
         
         sqlx::query(r#"
             INSERT INTO email_verifications (email, code, purpose, expires_at, created_at, used)
-            VALUES (?, ?, ?, ?, ?, ?)
+            VALUES ($1, $2, $3, $4, $5, $6)
         "#)
         .bind(&verification.email)
         .bind(&verification.code)
@@ -208,8 +215,8 @@ impl AuthService {    /// 创建新的认证服务
     /// 验证邮箱验证码
     pub async fn verify_email_code(&self, email: String, code: String, purpose: VerificationPurpose) -> Result<(), AuthError> {
         let row = sqlx::query(r#"
-            SELECT expires_at, used FROM email_verifications
-            WHERE email = ? AND code = ? AND purpose = ?
+            SELECT expires_at, CAST(used AS INTEGER) AS used FROM email_verifications
+            WHERE email = $1 AND code = $2 AND purpose = $3
             ORDER BY created_at DESC
             LIMIT 1
         "#)
@@ -223,7 +230,7 @@ impl AuthService {    /// 创建新的认证服务
         let row = row.ok_or(AuthError::InvalidVerificationCode)?;
         
         let expires_at: String = row.get("expires_at");
-        let used: bool = row.get("used");
+        let used = row_bool(&row, "used");
         
         if used {
             return Err(AuthError::InvalidVerificationCode);
@@ -241,7 +248,7 @@ impl AuthService {    /// 创建新的认证服务
         sqlx::query(r#"
             UPDATE email_verifications
             SET used = TRUE
-            WHERE email = ? AND code = ? AND purpose = ?
+            WHERE email = $1 AND code = $2 AND purpose = $3
         "#)
         .bind(&email)
         .bind(&code)
@@ -252,7 +259,7 @@ impl AuthService {    /// 创建新的认证服务
         
         // 如果是邮箱验证，更新账户状态
         if purpose == VerificationPurpose::EmailVerification {
-            sqlx::query("UPDATE accounts SET email_verified = TRUE WHERE email = ?")
+            sqlx::query("UPDATE accounts SET email_verified = TRUE WHERE email = $1")
                 .bind(&email)
                 .execute(&self.db_pool)
                 .await
@@ -281,7 +288,7 @@ impl AuthService {    /// 创建新的认证服务
         
         // 更新最后登录时间
         let now = Utc::now();
-        sqlx::query("UPDATE accounts SET last_login_at = ? WHERE id = ?")
+        sqlx::query("UPDATE accounts SET last_login_at = $1 WHERE id = $2")
             .bind(now.to_rfc3339())
             .bind(account.id.to_string())
             .execute(&self.db_pool)
@@ -298,8 +305,8 @@ impl AuthService {    /// 创建新的认证服务
     /// 根据邮箱获取账户
     pub async fn get_account_by_email(&self, email: &str) -> Result<Account, AuthError> {
         let row = sqlx::query(r#"
-            SELECT id, email, password_hash, display_name, status, email_verified, created_at, last_login_at
-            FROM accounts WHERE email = ?
+            SELECT id, email, password_hash, display_name, status, CAST(email_verified AS INTEGER) AS email_verified, created_at, last_login_at
+            FROM accounts WHERE email = $1
         "#)
         .bind(email)
         .fetch_optional(&self.db_pool)
@@ -316,13 +323,12 @@ impl AuthService {    /// 创建新的认证服务
             display_name: row.get("display_name"),
             status: row.get::<String, _>("status").parse()
                 .map_err(|_| AuthError::DatabaseError(anyhow::anyhow!("Invalid account status")))?,
-            email_verified: row.get("email_verified"),
+            email_verified: row_bool(&row, "email_verified"),
             created_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("created_at"))
                 .map_err(|e| AuthError::DatabaseError(e.into()))?
                 .with_timezone(&Utc),
             last_login_at: row.get::<Option<String>, _>("last_login_at")
-                .map(|s| DateTime::parse_from_rfc3339(&s).ok())
-                .flatten()
+                .and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
                 .map(|dt| dt.with_timezone(&Utc)),
         };
         
@@ -346,20 +352,14 @@ impl AuthService {    /// 创建新的认证服务
     
     /// 验证密码强度
     fn validate_password(&self, password: &str) -> Result<(), AuthError> {
-        if password.len() < 6 {
-            return Err(AuthError::InvalidPassword);
-        }
-        
-        if password.len() > 128 {
-            return Err(AuthError::InvalidPassword);
-        }
-        
-        Ok(())
+        self.password_policy
+            .validate(password)
+            .map_err(AuthError::InvalidPassword)
     }
     
     /// 检查邮箱是否已存在
     async fn email_exists(&self, email: &str) -> Result<bool, AuthError> {
-        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM accounts WHERE email = ?")
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM accounts WHERE email = $1")
             .bind(email)
             .fetch_one(&self.db_pool)
             .await
@@ -391,7 +391,7 @@ impl AuthService {    /// 创建新的认证服务
     async fn cleanup_old_verification_codes(&self, email: &str, purpose: VerificationPurpose) -> Result<(), AuthError> {
         sqlx::query(r#"
             DELETE FROM email_verifications
-            WHERE email = ? AND purpose = ? AND (expires_at < ? OR used = TRUE)
+            WHERE email = $1 AND purpose = $2 AND (expires_at < $3 OR used = TRUE)
         "#)
         .bind(email)
         .bind(purpose.to_string())
@@ -403,26 +403,47 @@ impl AuthService {    /// 创建新的认证服务
         Ok(())
     }
     
+    /// 删除所有已过期的会话，以及过期或已使用的验证码
+    pub async fn cleanup_expired(&self) -> Result<ExpiredCleanup, AuthError> {
+        let now = Utc::now().to_rfc3339();
+        
+        let sessions = sqlx::query("DELETE FROM sessions WHERE expires_at < $1")
+            .bind(&now)
+            .execute(&self.db_pool)
+            .await
+            .map_err(|e| AuthError::DatabaseError(e.into()))?
+            .rows_affected();
+        
+        let verification_codes = sqlx::query("DELETE FROM email_verifications WHERE expires_at < $1 OR used = TRUE")
+            .bind(&now)
+            .execute(&self.db_pool)
+            .await
+            .map_err(|e| AuthError::DatabaseError(e.into()))?
+            .rows_affected();
+        
+        Ok(ExpiredCleanup { sessions, verification_codes })
+    }
+    
     // ============= JWT 相关方法 =============
     
     /// 生成访问令牌和刷新令牌对
     pub async fn generate_token_pair(&self, account: &Account, device_info: Option<String>, ip_address: Option<String>) -> Result<TokenPair, AuthError> {
         let now = Utc::now();
+        let session_id = uuid::Uuid::new_v4().to_string();
         
         // 生成访问令牌
-        let access_token = self.generate_token(account, TokenType::Access, now)?;
+        let access_token = self.generate_token(account, TokenType::Access, now, &session_id)?;
         
         // 生成刷新令牌
-        let refresh_token = self.generate_token(account, TokenType::Refresh, now)?;
+        let refresh_token = self.generate_token(account, TokenType::Refresh, now, &session_id)?;
         
         // 保存会话到数据库
-        let session_id = uuid::Uuid::new_v4().to_string();
         let refresh_token_hash = self.hash_refresh_token(&refresh_token)?;
         let expires_at = now + self.refresh_token_duration;
         
         sqlx::query(r#"
             INSERT INTO sessions (id, account_id, refresh_token_hash, device_info, ip_address, created_at, expires_at, last_used_at)
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
         "#)
         .bind(&session_id)
         .bind(account.id.to_string())
@@ -446,8 +467,8 @@ impl AuthService {    /// 创建新的认证服务
         })
     }
     
-    /// 生成 JWT 令牌
-    fn generate_token(&self, account: &Account, token_type: TokenType, issued_at: DateTime<Utc>) -> Result<String, AuthError> {
+    /// 生成 JWT 令牌，`session_id` 为签发令牌的会话
+    fn generate_token(&self, account: &Account, token_type: TokenType, issued_at: DateTime<Utc>, session_id: &str) -> Result<String, AuthError> {
         let expiration = match token_type {
             TokenType::Access => issued_at + self.access_token_duration,
             TokenType::Refresh => issued_at + self.refresh_token_duration,
@@ -460,25 +481,23 @@ impl AuthService {    /// 创建新的认证服务
             iat: issued_at.timestamp(),
             exp: expiration.timestamp(),
             token_type: token_type.to_string(),
+            jti: uuid::Uuid::new_v4().to_string(),
+            iat_ms: Some(issued_at.timestamp_millis()),
+            sid: Some(session_id.to_string()),
         };
         
-        let header = Header::new(Algorithm::HS256);
-        let encoding_key = EncodingKey::from_secret(self.jwt_secret.as_ref());
-        
-        encode(&header, &claims, &encoding_key)
+        self.jwt_keys
+            .encode(&claims)
             .map_err(|e| AuthError::DatabaseError(anyhow::anyhow!("JWT encoding error: {}", e)))
     }
     
     /// 验证并解析 JWT 令牌
     pub fn verify_token(&self, token: &str, expected_type: TokenType) -> Result<JwtClaims, AuthError> {
-        let decoding_key = DecodingKey::from_secret(self.jwt_secret.as_ref());
-        let validation = Validation::new(Algorithm::HS256);
-        
-        let token_data = decode::<JwtClaims>(token, &decoding_key, &validation)
+        let claims: JwtClaims = self
+            .jwt_keys
+            .decode(token)
             .map_err(|e| AuthError::DatabaseError(anyhow::anyhow!("JWT decoding error: {}", e)))?;
         
-        let claims = token_data.claims;
-        
         // 验证令牌类型
         if claims.token_type != expected_type.to_string() {
             return Err(AuthError::DatabaseError(anyhow::anyhow!("Invalid token type")));
@@ -489,21 +508,57 @@ impl AuthService {    /// 创建新的认证服务
         if claims.exp < now {
             return Err(AuthError::DatabaseError(anyhow::anyhow!("Token expired")));
         }
+
+        // 访问令牌在过期前一直有效，登出或撤销会话后需要查黑名单
+        if expected_type == TokenType::Access {
+            let revoked_tokens = self.revoked_tokens.lock().unwrap();
+            if revoked_tokens.is_revoked(&claims.jti, &claims.sub, claims.issued_at_millis())
+                || claims.sid.as_deref().is_some_and(|sid| revoked_tokens.is_session_revoked(sid))
+            {
+                return Err(AuthError::TokenRevoked);
+            }
+        }
         
         Ok(claims)
     }
     
+    /// 撤销单个访问令牌，令牌过期前都无法再通过验证
+    pub fn revoke_access_token(&self, claims: &JwtClaims) {
+        let now = Utc::now().timestamp();
+        self.revoked_tokens.lock().unwrap().revoke_token(&claims.jti, claims.exp, now);
+        debug!("访问令牌 {} 已撤销", claims.jti);
+    }
+
+    /// 撤销会话签发的所有访问令牌
+    fn revoke_session_access_tokens(&self, session_id: &str) {
+        self.revoked_tokens.lock().unwrap().revoke_session(
+            session_id,
+            Utc::now().timestamp(),
+            self.access_token_duration.num_seconds(),
+        );
+    }
+
+    /// 撤销账户此前签发的所有访问令牌
+    fn revoke_account_access_tokens(&self, account_id: &AccountId) {
+        let now = Utc::now().timestamp_millis();
+        self.revoked_tokens.lock().unwrap().revoke_account(
+            &account_id.to_string(),
+            now,
+            self.access_token_duration.num_seconds(),
+        );
+    }
+    
     /// 刷新访问令牌
     pub async fn refresh_access_token(&self, refresh_token: &str) -> Result<TokenPair, AuthError> {
         // 验证刷新令牌
-        let claims = self.verify_token(refresh_token, TokenType::Refresh)?;
+        let _claims = self.verify_token(refresh_token, TokenType::Refresh)?;
         
         // 验证会话是否存在且有效
         let refresh_token_hash = self.hash_refresh_token(refresh_token)?;
         let session_row = sqlx::query(r#"
-            SELECT account_id, expires_at, is_active, device_info, ip_address
+            SELECT id, account_id, expires_at, CAST(is_active AS INTEGER) AS is_active, device_info, ip_address
             FROM sessions 
-            WHERE refresh_token_hash = ? AND is_active = TRUE
+            WHERE refresh_token_hash = $1 AND is_active = TRUE
         "#)
         .bind(&refresh_token_hash)
         .fetch_optional(&self.db_pool)
@@ -531,7 +586,7 @@ impl AuthService {    /// 创建新的认证服务
         
         // 更新会话最后使用时间
         let now = Utc::now();
-        sqlx::query("UPDATE sessions SET last_used_at = ? WHERE refresh_token_hash = ?")
+        sqlx::query("UPDATE sessions SET last_used_at = $1 WHERE refresh_token_hash = $2")
             .bind(now.to_rfc3339())
             .bind(&refresh_token_hash)
             .execute(&self.db_pool)
@@ -539,7 +594,8 @@ impl AuthService {    /// 创建新的认证服务
             .map_err(|e| AuthError::DatabaseError(e.into()))?;
         
         // 生成新的访问令牌（保持原有刷新令牌）
-        let access_token = self.generate_token(&account, TokenType::Access, now)?;
+        let session_id: String = session.get("id");
+        let access_token = self.generate_token(&account, TokenType::Access, now, &session_id)?;
         
         info!("为用户 {} 刷新了访问令牌", account.email);
         
@@ -554,8 +610,8 @@ impl AuthService {    /// 创建新的认证服务
     /// 根据ID获取账户
     pub async fn get_account_by_id(&self, account_id: &AccountId) -> Result<Account, AuthError> {
         let row = sqlx::query(r#"
-            SELECT id, email, password_hash, display_name, status, email_verified, created_at, last_login_at
-            FROM accounts WHERE id = ?
+            SELECT id, email, password_hash, display_name, status, CAST(email_verified AS INTEGER) AS email_verified, created_at, last_login_at
+            FROM accounts WHERE id = $1
         "#)
         .bind(account_id.to_string())
         .fetch_optional(&self.db_pool)
@@ -572,42 +628,198 @@ impl AuthService {    /// 创建新的认证服务
             display_name: row.get("display_name"),
             status: row.get::<String, _>("status").parse()
                 .map_err(|_| AuthError::DatabaseError(anyhow::anyhow!("Invalid account status")))?,
-            email_verified: row.get("email_verified"),
+            email_verified: row_bool(&row, "email_verified"),
             created_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("created_at"))
                 .map_err(|e| AuthError::DatabaseError(e.into()))?
                 .with_timezone(&Utc),
             last_login_at: row.get::<Option<String>, _>("last_login_at")
-                .map(|s| DateTime::parse_from_rfc3339(&s).ok())
-                .flatten()
+                .and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
                 .map(|dt| dt.with_timezone(&Utc)),
         };
         
         Ok(account)
     }
     
-    /// 注销（撤销刷新令牌）
+    /// 重新验证账户密码（用于敏感操作）
+    pub async fn confirm_password(&self, account_id: &AccountId, password: &str) -> Result<Account, AuthError> {
+        let account = self.get_account_by_id(account_id).await?;
+
+        if !self.verify_password(password, &account.password_hash)? {
+            return Err(AuthError::InvalidCredentials);
+        }
+
+        Ok(account)
+    }
+    
+    /// 注销（撤销刷新令牌及该会话签发的访问令牌）
     pub async fn logout(&self, refresh_token: &str) -> Result<(), AuthError> {
         let refresh_token_hash = self.hash_refresh_token(refresh_token)?;
         
-        sqlx::query("UPDATE sessions SET is_active = FALSE WHERE refresh_token_hash = ?")
+        let sessions = sqlx::query("UPDATE sessions SET is_active = FALSE WHERE refresh_token_hash = $1 RETURNING id")
             .bind(&refresh_token_hash)
-            .execute(&self.db_pool)
+            .fetch_all(&self.db_pool)
             .await
             .map_err(|e| AuthError::DatabaseError(e.into()))?;
+        for session in sessions {
+            self.revoke_session_access_tokens(&session.get::<String, _>("id"));
+        }
         
         info!("用户会话已注销");
         Ok(())
     }
     
-    /// 注销所有设备
-    pub async fn logout_all_devices(&self, account_id: &AccountId) -> Result<(), AuthError> {
-        sqlx::query("UPDATE sessions SET is_active = FALSE WHERE account_id = ?")
+    /// 注销所有设备，返回被注销的会话数
+    pub async fn logout_all_devices(&self, account_id: &AccountId) -> Result<u64, AuthError> {
+        let result = sqlx::query("UPDATE sessions SET is_active = FALSE WHERE account_id = $1 AND is_active = TRUE")
             .bind(account_id.to_string())
             .execute(&self.db_pool)
             .await
             .map_err(|e| AuthError::DatabaseError(e.into()))?;
         
+        self.revoke_account_access_tokens(account_id);
         info!("用户 {} 的所有设备会话已注销", account_id);
+        Ok(result.rows_affected())
+    }
+    
+    /// 注销账户：标记为已删除、匿名化邮箱并撤销所有会话，返回被撤销的会话数
+    ///
+    /// 账户行本身保留（审计日志仍引用它），原邮箱释放后可以重新注册
+    pub async fn delete_account(&self, account_id: &AccountId) -> Result<u64, AuthError> {
+        let account = self.get_account_by_id(account_id).await?;
+        if account.status == AccountStatus::Deleted {
+            return Err(AuthError::AccountDeleted);
+        }
+
+        let mut tx = self.db_pool.begin().await
+            .map_err(|e| AuthError::DatabaseError(e.into()))?;
+
+        sqlx::query(r#"
+            UPDATE accounts SET status = $1, email = $2, display_name = NULL, email_verified = FALSE
+            WHERE id = $3
+        "#)
+        .bind(AccountStatus::Deleted.to_string())
+        .bind(format!("deleted-{}@deleted.invalid", account_id))
+        .bind(account_id.to_string())
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| AuthError::DatabaseError(e.into()))?;
+
+        let sessions = sqlx::query("UPDATE sessions SET is_active = FALSE WHERE account_id = $1 AND is_active = TRUE")
+            .bind(account_id.to_string())
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| AuthError::DatabaseError(e.into()))?
+            .rows_affected();
+
+        sqlx::query("DELETE FROM email_verifications WHERE email = $1")
+            .bind(&account.email)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| AuthError::DatabaseError(e.into()))?;
+
+        tx.commit().await
+            .map_err(|e| AuthError::DatabaseError(e.into()))?;
+        self.revoke_account_access_tokens(account_id);
+
+        info!("账户 {} 已注销，撤销了 {} 个会话", account_id, sessions);
+        Ok(sessions)
+    }
+
+    /// 获取账户的所有会话（按最后使用时间倒序）
+    pub async fn list_sessions(&self, account_id: &AccountId) -> Result<Vec<SessionInfo>, AuthError> {
+        let rows = sqlx::query(r#"
+            SELECT id, device_info, ip_address, created_at, last_used_at, expires_at, CAST(is_active AS INTEGER) AS is_active
+            FROM sessions WHERE account_id = $1
+            ORDER BY last_used_at DESC
+        "#)
+        .bind(account_id.to_string())
+        .fetch_all(&self.db_pool)
+        .await
+        .map_err(|e| AuthError::DatabaseError(e.into()))?;
+        
+        Self::sessions_from_rows(rows)
+    }
+    
+    /// 获取账户当前有效的会话（未撤销且未过期，按最后使用时间倒序）
+    pub async fn list_active_sessions(&self, account_id: &AccountId) -> Result<Vec<SessionInfo>, AuthError> {
+        let rows = sqlx::query(r#"
+            SELECT id, device_info, ip_address, created_at, last_used_at, expires_at, CAST(is_active AS INTEGER) AS is_active
+            FROM sessions WHERE account_id = $1 AND is_active = TRUE AND expires_at > $2
+            ORDER BY last_used_at DESC
+        "#)
+        .bind(account_id.to_string())
+        .bind(Utc::now().to_rfc3339())
+        .fetch_all(&self.db_pool)
+        .await
+        .map_err(|e| AuthError::DatabaseError(e.into()))?;
+        
+        Self::sessions_from_rows(rows)
+    }
+    
+    /// 撤销账户的某个会话，会话不存在、已撤销或属于其他账户时返回 `SessionNotFound`
+    pub async fn revoke_session(&self, account_id: &AccountId, session_id: &str) -> Result<(), AuthError> {
+        let result = sqlx::query("UPDATE sessions SET is_active = FALSE WHERE id = $1 AND account_id = $2 AND is_active = TRUE")
+            .bind(session_id)
+            .bind(account_id.to_string())
+            .execute(&self.db_pool)
+            .await
+            .map_err(|e| AuthError::DatabaseError(e.into()))?;
+        
+        if result.rows_affected() == 0 {
+            return Err(AuthError::SessionNotFound);
+        }
+
+        // 会话已签发的访问令牌同时失效
+        self.revoke_session_access_tokens(session_id);
+        
+        info!("用户 {} 的会话 {} 已撤销", account_id, session_id);
+        Ok(())
+    }
+    
+    fn sessions_from_rows(rows: Vec<AnyRow>) -> Result<Vec<SessionInfo>, AuthError> {
+        let parse_time = |value: String| {
+            DateTime::parse_from_rfc3339(&value)
+                .map(|dt| dt.with_timezone(&Utc))
+                .map_err(|e| AuthError::DatabaseError(e.into()))
+        };
+        
+        rows.into_iter()
+            .map(|row| {
+                Ok(SessionInfo {
+                    id: row.get("id"),
+                    device_info: row.get("device_info"),
+                    ip_address: row.get("ip_address"),
+                    created_at: parse_time(row.get("created_at"))?,
+                    last_used_at: parse_time(row.get("last_used_at"))?,
+                    expires_at: parse_time(row.get("expires_at"))?,
+                    is_active: row_bool(&row, "is_active"),
+                })
+            })
+            .collect()
+    }
+    
+    /// 记录管理操作到审计日志
+    pub async fn record_audit(
+        &self,
+        actor: &AccountId,
+        action: &str,
+        target: Option<&AccountId>,
+        details: Option<String>,
+    ) -> Result<(), AuthError> {
+        sqlx::query(r#"
+            INSERT INTO audit_log (actor_account_id, action, target_account_id, details, created_at)
+            VALUES ($1, $2, $3, $4, $5)
+        "#)
+        .bind(actor.to_string())
+        .bind(action)
+        .bind(target.map(|target| target.to_string()))
+        .bind(&details)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.db_pool)
+        .await
+        .map_err(|e| AuthError::DatabaseError(e.into()))?;
+        
+        info!(target: "audit", "管理操作: {} 执行 {}，目标: {:?}", actor, action, target.map(|t| t.to_string()));
         Ok(())
     }
     
@@ -622,3 +834,4 @@ impl AuthService {    /// 创建新的认证服务
         Ok(format!("{:x}", hasher.finish()))
     }
 }
+
@@ -0,0 +1,20 @@
+use axum::{extract::State, response::Json, routing::get, Router};
+use serde_json::json;
+
+use crate::AppState;
+
+/// 创建机器人信息路由（公开，启用/停用在管理员路由中）
+pub fn create_bot_routes() -> Router<AppState> {
+    Router::new().route("/api/bots", get(list_bots))
+}
+
+/// 列出已注册的机器人及其配置
+async fn list_bots(State(state): State<AppState>) -> Json<serde_json::Value> {
+    let bots = state.bot_manager.lock().await.get_bots_info();
+
+    Json(json!({
+        "success": true,
+        "message": "获取机器人列表成功",
+        "bots": bots
+    }))
+}
@@ -0,0 +1,81 @@
+use rustchat_types::{ErrorCode, ValidationError};
+
+use crate::room::RoomError;
+use crate::WsEvent;
+
+/// 客户端请求不合法时返回给发送连接的错误
+///
+/// 处理客户端消息时以 `anyhow::Error` 传递，其他类型的错误视为服务器内部错误
+#[derive(Debug, thiserror::Error)]
+#[error("{message}")]
+pub struct ClientError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl ClientError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+
+    /// 消息内容校验失败，`subject` 为“消息内容”“动作内容”等前缀
+    pub fn content(subject: &str, error: ValidationError) -> Self {
+        let code = match error {
+            ValidationError::Empty => ErrorCode::MsgEmpty,
+            _ => ErrorCode::MsgTooLong,
+        };
+        Self::new(code, format!("{}{}", subject, error))
+    }
+
+    /// 房间ID格式错误
+    pub fn invalid_room_id(room_id: &str) -> Self {
+        Self::new(ErrorCode::InvalidRoomId, format!("无效的房间ID: {}", room_id))
+    }
+
+    /// 用户不是房间成员
+    pub fn not_in_room(room_id: impl std::fmt::Display) -> Self {
+        Self::new(ErrorCode::RoomNotMember, format!("用户不在房间 {} 中", room_id))
+    }
+
+    /// 房间操作失败，数据库错误视为服务器内部错误
+    pub fn room(action: &str, error: RoomError) -> anyhow::Error {
+        let code = match &error {
+            RoomError::RoomNotFound => ErrorCode::RoomNotFound,
+            RoomError::UserNotInRoom => ErrorCode::RoomNotMember,
+            RoomError::RoomFull => ErrorCode::RoomFull,
+            RoomError::PermissionDenied => ErrorCode::PermissionDenied,
+            RoomError::UserAlreadyInRoom | RoomError::InvalidRoomName => ErrorCode::InvalidRequest,
+            RoomError::DatabaseError(_) => return anyhow::anyhow!("{}: {}", action, error),
+        };
+        Self::new(code, format!("{}: {}", action, error)).into()
+    }
+
+    pub fn into_event(self) -> WsEvent {
+        WsEvent::Error { code: self.code, message: self.message }
+    }
+}
+
+/// 把处理客户端消息的错误转换为发送给该连接的错误事件，内部错误不向客户端暴露细节
+pub fn error_event(error: anyhow::Error) -> WsEvent {
+    match error.downcast::<ClientError>() {
+        Ok(client_error) => client_error.into_event(),
+        Err(_) => ClientError::new(ErrorCode::InternalError, "服务器处理消息失败，请稍后重试").into_event(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_event_hides_internal_errors() {
+        let event = error_event(ClientError::content("消息内容", ValidationError::TooLong(4000)).into());
+        assert!(matches!(event, WsEvent::Error { code: ErrorCode::MsgTooLong, message } if message == "消息内容不能超过4000个字符"));
+
+        let event = error_event(ClientError::room("加入房间失败", RoomError::RoomFull));
+        assert!(matches!(event, WsEvent::Error { code: ErrorCode::RoomFull, .. }));
+
+        let event = error_event(anyhow::anyhow!("database is locked"));
+        assert!(matches!(event, WsEvent::Error { code: ErrorCode::InternalError, message } if !message.contains("database")));
+    }
+}
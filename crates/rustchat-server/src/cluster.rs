@@ -0,0 +1,196 @@
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, OnceLock};
+use tokio::sync::mpsc;
+use tracing::debug;
+#[cfg(feature = "redis")]
+use tracing::{error, info, warn};
+
+use crate::room::RoomId;
+use crate::{AppState, WsEvent};
+
+/// Redis中转发广播事件的频道
+#[cfg(feature = "redis")]
+const REDIS_CHANNEL: &str = "rustchat:events";
+/// Redis连接断开后的重连间隔
+#[cfg(feature = "redis")]
+const REDIS_RETRY_DELAY: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// 在实例之间转发的广播事件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelayEnvelope {
+    /// 发布事件的实例ID，用于忽略自己发布的事件
+    pub origin: String,
+    /// 房间事件所属的房间，全局事件为None
+    pub room_id: Option<RoomId>,
+    pub event: WsEvent,
+}
+
+/// 跨实例广播中继
+///
+/// 本地广播仍然直接走进程内的广播通道，同时把事件交给中继发布给其他实例。
+/// 未接入外部消息总线（如Redis）时发布操作为空操作
+#[derive(Debug, Clone)]
+pub struct ClusterRelay {
+    instance_id: Arc<str>,
+    outbound: Arc<OnceLock<mpsc::UnboundedSender<RelayEnvelope>>>,
+}
+
+impl Default for ClusterRelay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClusterRelay {
+    pub fn new() -> Self {
+        Self {
+            instance_id: uuid::Uuid::new_v4().to_string().into(),
+            outbound: Arc::new(OnceLock::new()),
+        }
+    }
+
+    /// 当前实例的ID
+    pub fn instance_id(&self) -> &str {
+        &self.instance_id
+    }
+
+    /// 接入外部消息总线，返回待发布事件的接收端；只能接入一次
+    #[cfg_attr(not(feature = "redis"), allow(dead_code))]
+    pub fn attach(&self) -> Option<mpsc::UnboundedReceiver<RelayEnvelope>> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.outbound.set(sender).ok().map(|()| receiver)
+    }
+
+    /// 发布全局广播事件
+    pub fn publish_global(&self, event: &WsEvent) {
+        self.publish(None, event);
+    }
+
+    /// 发布房间广播事件
+    pub fn publish_room(&self, room_id: RoomId, event: &WsEvent) {
+        self.publish(Some(room_id), event);
+    }
+
+    fn publish(&self, room_id: Option<RoomId>, event: &WsEvent) {
+        let Some(outbound) = self.outbound.get() else {
+            return;
+        };
+
+        let envelope = RelayEnvelope {
+            origin: self.instance_id.to_string(),
+            room_id,
+            event: event.clone(),
+        };
+        if outbound.send(envelope).is_err() {
+            debug!("跨实例中继已停止，事件只在本地广播");
+        }
+    }
+}
+
+/// 把其他实例发布的事件投递给本地客户端，不再转发回总线
+///
+/// 返回事件是否被投递（自己发布的事件会被忽略）
+#[cfg_attr(not(feature = "redis"), allow(dead_code))]
+pub async fn deliver_remote(state: &AppState, envelope: RelayEnvelope) -> bool {
+    if envelope.origin == state.relay.instance_id() {
+        return false;
+    }
+
+    match envelope.room_id {
+        Some(room_id) => {
+            state.room_broadcast_manager.deliver_to_room(room_id, envelope.event).await;
+        }
+        None => {
+            // 没有本地订阅者时发送失败，可以忽略
+            let _ = state.tx.send(envelope.event);
+        }
+    }
+    true
+}
+
+/// 连接Redis并启动发布和订阅任务，把本实例的广播转发给其他实例
+#[cfg(feature = "redis")]
+pub fn start_redis_bridge(redis_url: &str, state: AppState) -> anyhow::Result<()> {
+    use anyhow::Context;
+
+    let client = redis::Client::open(redis_url).context("Invalid REDIS_URL")?;
+    let Some(outbound) = state.relay.attach() else {
+        anyhow::bail!("跨实例中继已经接入");
+    };
+
+    tokio::spawn(redis_publish_task(client.clone(), outbound));
+    tokio::spawn(redis_subscribe_task(client, state));
+    Ok(())
+}
+
+/// 把本实例的广播事件发布到Redis
+#[cfg(feature = "redis")]
+async fn redis_publish_task(client: redis::Client, mut outbound: mpsc::UnboundedReceiver<RelayEnvelope>) {
+    use redis::AsyncCommands;
+
+    let mut connection = None;
+    while let Some(envelope) = outbound.recv().await {
+        let payload = match serde_json::to_string(&envelope) {
+            Ok(payload) => payload,
+            Err(e) => {
+                error!("序列化跨实例事件失败: {}", e);
+                continue;
+            }
+        };
+
+        if connection.is_none() {
+            match client.get_multiplexed_async_connection().await {
+                Ok(conn) => connection = Some(conn),
+                Err(e) => {
+                    warn!("连接Redis失败，事件只在本地广播: {}", e);
+                    continue;
+                }
+            }
+        }
+
+        if let Some(conn) = connection.as_mut() {
+            let result: redis::RedisResult<()> = conn.publish(REDIS_CHANNEL, payload).await;
+            if let Err(e) = result {
+                warn!("发布跨实例事件失败: {}", e);
+                connection = None;
+            }
+        }
+    }
+}
+
+/// 订阅Redis频道，把其他实例的事件投递给本地客户端，断开后自动重连
+#[cfg(feature = "redis")]
+async fn redis_subscribe_task(client: redis::Client, state: AppState) {
+    use futures_util::StreamExt;
+
+    loop {
+        match client.get_async_pubsub().await {
+            Ok(mut pubsub) => match pubsub.subscribe(REDIS_CHANNEL).await {
+                Ok(()) => {
+                    info!("已订阅Redis频道 {}，实例ID: {}", REDIS_CHANNEL, state.relay.instance_id());
+                    let mut messages = pubsub.on_message();
+                    while let Some(message) = messages.next().await {
+                        let payload: String = match message.get_payload() {
+                            Ok(payload) => payload,
+                            Err(e) => {
+                                warn!("读取跨实例事件失败: {}", e);
+                                continue;
+                            }
+                        };
+                        match serde_json::from_str::<RelayEnvelope>(&payload) {
+                            Ok(envelope) => {
+                                deliver_remote(&state, envelope).await;
+                            }
+                            Err(e) => warn!("解析跨实例事件失败: {}", e),
+                        }
+                    }
+                    warn!("Redis订阅已断开，稍后重连");
+                }
+                Err(e) => error!("订阅Redis频道失败: {}", e),
+            },
+            Err(e) => error!("连接Redis失败: {}", e),
+        }
+
+        tokio::time::sleep(REDIS_RETRY_DELAY).await;
+    }
+}
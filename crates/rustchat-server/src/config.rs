@@ -0,0 +1,450 @@
+use anyhow::bail;
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::warn;
+
+use crate::auth::PasswordPolicy;
+use crate::word_filter::FilterMode;
+
+/// 服务器配置
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// 是否为生产环境（`RUSTCHAT_ENV=production`），生产环境拒绝使用不安全的默认配置
+    pub production: bool,
+    /// 是否允许匿名连接（未携带有效令牌的WebSocket连接和REST请求）
+    pub allow_anonymous: bool,
+    /// 同一用户两次修改昵称的最小间隔，为零时不限制
+    pub nick_change_cooldown: Duration,
+    /// 管理员账户邮箱（小写），账户还需完成邮箱验证
+    pub admin_emails: Vec<String>,
+    /// 全局广播通道容量（事件条数）
+    ///
+    /// 通道中保留尚未被所有订阅者读取的事件，落后超过容量的订阅者会丢失最早的事件。
+    /// 容量越大越能容忍突发流量和慢客户端，但积压的事件都占用内存
+    pub broadcast_capacity: usize,
+    /// 每个房间广播通道的容量，含义同 `broadcast_capacity`
+    pub room_broadcast_capacity: usize,
+    /// 清理过期会话和验证码的间隔，为零时不启动清理任务
+    pub auth_cleanup_interval: Duration,
+    /// 注册时的密码强度策略
+    pub password_policy: PasswordPolicy,
+    /// 服务器向每个连接发送心跳Ping的间隔
+    pub heartbeat_interval: Duration,
+    /// 超过该时间未收到Pong即断开连接，必须大于心跳间隔
+    pub heartbeat_timeout: Duration,
+    /// 屏蔽词表文件，未设置时不过滤消息内容
+    pub word_filter_path: Option<PathBuf>,
+    /// 命中屏蔽词时的处理方式
+    pub word_filter_mode: FilterMode,
+    /// 匿名连接断开后恢复令牌的有效期，为零时不签发恢复令牌
+    pub resume_token_ttl: Duration,
+    /// 数据库地址（`DATABASE_URL`），支持 `sqlite:` 和 `postgres:`（需要 `postgres` 功能），未设置时使用默认位置的SQLite数据库
+    pub database_url: Option<String>,
+    /// Redis地址（`REDIS_URL`），设置后通过Redis在多个实例之间转发广播事件
+    pub redis_url: Option<String>,
+    /// 每个连接每秒允许发送的消息数，为零时不限制
+    pub message_rate_per_sec: u32,
+    /// 每个连接允许的突发消息数
+    pub message_burst: u32,
+    /// 全局消息和每个房间各自最多保留的消息数，为None时不清理
+    pub max_stored_messages: Option<usize>,
+    /// 连接超过该时间没有发送任何消息（心跳Pong除外）即断开，为None时不限制
+    ///
+    /// 在每次心跳时检查，空闲达到80%时先发送警告
+    pub idle_timeout: Option<Duration>,
+    /// 合并写入消息时等待后续消息的最长时间，为零时不额外等待
+    ///
+    /// 等待期间发送者收不到确认，调大可提高写入吞吐但会增加单条消息的延迟
+    pub message_write_window: Duration,
+    /// 向同一连接合并发送消息的时间窗口，为零时逐条发送
+    ///
+    /// 启用后窗口内连续到达的消息以一个 `MessageBatch` / `RoomMessageBatch` 事件发送，只对连接时声明了支持的协议版本的客户端生效
+    pub message_batch_window: Duration,
+    /// 允许跨域访问的来源（`RUSTCHAT_CORS_ORIGINS`），为None时允许任意来源
+    ///
+    /// 允许任意来源意味着任何网站都能带着用户的令牌调用需要认证的接口，生产环境应当配置
+    pub cors_origins: Option<Vec<String>>,
+    /// 上传文件的保存目录（`RUSTCHAT_UPLOAD_DIR`）
+    pub upload_dir: PathBuf,
+    /// 单个上传文件的大小上限（字节）
+    pub max_upload_bytes: usize,
+    /// 每个用户上传文件的总大小上限（`RUSTCHAT_UPLOAD_QUOTA_BYTES`，字节）
+    pub upload_quota_bytes: u64,
+}
+
+/// 广播通道的默认容量
+pub const DEFAULT_BROADCAST_CAPACITY: usize = 1000;
+
+/// 单个上传文件的默认大小上限（10 MiB）
+pub const DEFAULT_MAX_UPLOAD_BYTES: usize = 10 * 1024 * 1024;
+
+/// 每个用户上传文件的默认总配额（100 MiB）
+pub const DEFAULT_UPLOAD_QUOTA_BYTES: u64 = 100 * 1024 * 1024;
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            production: false,
+            allow_anonymous: true,
+            nick_change_cooldown: Duration::from_secs(30),
+            admin_emails: Vec::new(),
+            broadcast_capacity: DEFAULT_BROADCAST_CAPACITY,
+            room_broadcast_capacity: DEFAULT_BROADCAST_CAPACITY,
+            auth_cleanup_interval: Duration::from_secs(60 * 60),
+            password_policy: PasswordPolicy::default(),
+            heartbeat_interval: Duration::from_secs(30),
+            heartbeat_timeout: Duration::from_secs(90),
+            word_filter_path: None,
+            word_filter_mode: FilterMode::default(),
+            resume_token_ttl: Duration::from_secs(5 * 60),
+            database_url: None,
+            redis_url: None,
+            message_rate_per_sec: 10,
+            message_burst: 20,
+            max_stored_messages: None,
+            idle_timeout: None,
+            message_write_window: Duration::ZERO,
+            message_batch_window: Duration::ZERO,
+            cors_origins: None,
+            upload_dir: PathBuf::from(".rustchat").join("uploads"),
+            max_upload_bytes: DEFAULT_MAX_UPLOAD_BYTES,
+            upload_quota_bytes: DEFAULT_UPLOAD_QUOTA_BYTES,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// 从环境变量加载配置，未设置的项使用默认值
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+
+        if let Ok(env) = std::env::var("RUSTCHAT_ENV") {
+            config.production = is_production_env(&env);
+        }
+
+        if let Some(allow_anonymous) = env_bool("RUSTCHAT_ALLOW_ANONYMOUS") {
+            config.allow_anonymous = allow_anonymous;
+        }
+
+        if let Some(secs) = env_u64("RUSTCHAT_NICK_CHANGE_COOLDOWN_SECS") {
+            config.nick_change_cooldown = Duration::from_secs(secs);
+        }
+
+        if let Ok(emails) = std::env::var("RUSTCHAT_ADMIN_EMAILS") {
+            config.admin_emails = parse_email_list(&emails);
+        }
+
+        if let Some(capacity) = env_positive("RUSTCHAT_BROADCAST_CAPACITY") {
+            config.broadcast_capacity = capacity;
+        }
+
+        if let Some(capacity) = env_positive("RUSTCHAT_ROOM_BROADCAST_CAPACITY") {
+            config.room_broadcast_capacity = capacity;
+        }
+
+        if let Some(secs) = env_u64("RUSTCHAT_AUTH_CLEANUP_INTERVAL_SECS") {
+            config.auth_cleanup_interval = Duration::from_secs(secs);
+        }
+
+        // 本地开发时可以关闭复杂度检查，再单独调整最短长度
+        if env_bool("RUSTCHAT_PASSWORD_RELAXED") == Some(true) {
+            config.password_policy = PasswordPolicy::relaxed();
+        }
+
+        if let Some(min_length) = env_positive("RUSTCHAT_PASSWORD_MIN_LENGTH") {
+            config.password_policy.min_length = min_length;
+        }
+
+        if let Some(require_symbol) = env_bool("RUSTCHAT_PASSWORD_REQUIRE_SYMBOL") {
+            config.password_policy.require_symbol = require_symbol;
+        }
+
+        if let Some(secs) = env_positive("RUSTCHAT_HEARTBEAT_SECS") {
+            config.heartbeat_interval = Duration::from_secs(secs as u64);
+        }
+
+        if let Some(secs) = env_positive("RUSTCHAT_HEARTBEAT_TIMEOUT_SECS") {
+            config.heartbeat_timeout = Duration::from_secs(secs as u64);
+        }
+
+        if let Ok(path) = std::env::var("RUSTCHAT_WORDFILTER_PATH") {
+            if !path.trim().is_empty() {
+                config.word_filter_path = Some(PathBuf::from(path.trim()));
+            }
+        }
+
+        if let Ok(mode) = std::env::var("RUSTCHAT_WORDFILTER_MODE") {
+            match FilterMode::parse(&mode) {
+                Some(mode) => config.word_filter_mode = mode,
+                None => warn!("环境变量 RUSTCHAT_WORDFILTER_MODE 的值无效: {}（可选 mask 或 reject）", mode),
+            }
+        }
+
+        if let Some(secs) = env_u64("RUSTCHAT_RESUME_TTL_SECS") {
+            config.resume_token_ttl = Duration::from_secs(secs);
+        }
+
+        if let Ok(url) = std::env::var("DATABASE_URL") {
+            if !url.trim().is_empty() {
+                config.database_url = Some(url.trim().to_string());
+            }
+        }
+
+        if let Some(rate) = env_u64("RUSTCHAT_MESSAGE_RATE") {
+            config.message_rate_per_sec = u32::try_from(rate).unwrap_or(u32::MAX);
+        }
+
+        if let Some(burst) = env_positive("RUSTCHAT_MESSAGE_BURST") {
+            config.message_burst = u32::try_from(burst).unwrap_or(u32::MAX);
+        }
+
+        if let Some(max) = env_positive("RUSTCHAT_MAX_STORED_MESSAGES") {
+            config.max_stored_messages = Some(max);
+        }
+
+        if let Some(secs) = env_positive("RUSTCHAT_IDLE_TIMEOUT_SECS") {
+            config.idle_timeout = Some(Duration::from_secs(secs as u64));
+        }
+
+        if let Some(ms) = env_u64("RUSTCHAT_MESSAGE_WRITE_WINDOW_MS") {
+            config.message_write_window = Duration::from_millis(ms);
+        }
+
+        if let Some(ms) = env_u64("RUSTCHAT_MESSAGE_BATCH_WINDOW_MS") {
+            config.message_batch_window = Duration::from_millis(ms);
+        }
+
+        // 变量存在但为空时拒绝所有跨域请求，只有未设置时才允许任意来源
+        if let Ok(origins) = std::env::var("RUSTCHAT_CORS_ORIGINS") {
+            config.cors_origins = Some(parse_origin_list(&origins));
+        }
+
+        if let Ok(path) = std::env::var("RUSTCHAT_UPLOAD_DIR") {
+            if !path.trim().is_empty() {
+                config.upload_dir = PathBuf::from(path.trim());
+            }
+        }
+
+        if let Some(bytes) = env_positive("RUSTCHAT_MAX_UPLOAD_BYTES") {
+            config.max_upload_bytes = bytes;
+        }
+
+        if let Some(bytes) = env_positive("RUSTCHAT_UPLOAD_QUOTA_BYTES") {
+            config.upload_quota_bytes = bytes as u64;
+        }
+
+        if let Ok(url) = std::env::var("REDIS_URL") {
+            if !url.trim().is_empty() {
+                config.redis_url = Some(url.trim().to_string());
+            }
+        }
+
+        config
+    }
+
+    /// 检查配置项之间的约束，启动时调用
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.heartbeat_interval.is_zero() {
+            bail!("心跳间隔必须大于0");
+        }
+        if self.heartbeat_timeout <= self.heartbeat_interval {
+            bail!(
+                "心跳超时 ({}秒) 必须大于心跳间隔 ({}秒)",
+                self.heartbeat_timeout.as_secs_f64(),
+                self.heartbeat_interval.as_secs_f64()
+            );
+        }
+        Ok(())
+    }
+
+    /// 检查连接是否被允许
+    pub fn permits_connection(&self, authenticated: bool) -> bool {
+        authenticated || self.allow_anonymous
+    }
+
+    /// 检查邮箱是否属于管理员
+    pub fn is_admin(&self, email: &str) -> bool {
+        let email = email.trim().to_lowercase();
+        self.admin_emails.contains(&email)
+    }
+}
+
+/// 日志输出格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// 便于阅读的单行文本
+    #[default]
+    Text,
+    /// JSON Lines，便于日志采集工具处理
+    Json,
+}
+
+impl LogFormat {
+    /// 从 `RUSTCHAT_LOG_FORMAT` 读取日志格式，值无效时返回该值
+    ///
+    /// 日志系统初始化前调用，因此不在这里输出警告
+    pub fn from_env() -> Result<Self, String> {
+        match std::env::var("RUSTCHAT_LOG_FORMAT") {
+            Ok(value) => Self::parse(&value).ok_or(value),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "" | "text" => Some(Self::Text),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+/// `RUSTCHAT_ENV` 是否表示生产环境
+fn is_production_env(value: &str) -> bool {
+    matches!(value.trim().to_lowercase().as_str(), "production" | "prod")
+}
+
+/// 读取布尔类型的环境变量
+fn env_bool(key: &str) -> Option<bool> {
+    let value = std::env::var(key).ok()?;
+    let parsed = parse_bool(&value);
+    if parsed.is_none() {
+        warn!("环境变量 {} 的值无效: {}", key, value);
+    }
+    parsed
+}
+
+/// 读取非负整数类型的环境变量
+fn env_u64(key: &str) -> Option<u64> {
+    let value = std::env::var(key).ok()?;
+    let parsed = value.trim().parse().ok();
+    if parsed.is_none() {
+        warn!("环境变量 {} 的值无效: {}", key, value);
+    }
+    parsed
+}
+
+/// 读取正整数类型的环境变量（通道容量、密码长度等）
+fn env_positive(key: &str) -> Option<usize> {
+    let value = std::env::var(key).ok()?;
+    let parsed = parse_capacity(&value);
+    if parsed.is_none() {
+        warn!("环境变量 {} 的值无效: {}（需要正整数）", key, value);
+    }
+    parsed
+}
+
+/// 解析通道容量，零和非数字都无效
+fn parse_capacity(value: &str) -> Option<usize> {
+    value.trim().parse().ok().filter(|capacity| *capacity > 0)
+}
+
+/// 解析逗号分隔的邮箱列表
+fn parse_email_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|email| email.trim().to_lowercase())
+        .filter(|email| !email.is_empty())
+        .collect()
+}
+
+/// 解析逗号分隔的来源列表，去掉末尾的斜杠以匹配浏览器发送的Origin头
+fn parse_origin_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|origin| origin.trim().trim_end_matches('/').to_string())
+        .filter(|origin| !origin.is_empty())
+        .collect()
+}
+
+/// 解析布尔值字符串
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.trim().to_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_allows_anonymous() {
+        let config = ServerConfig::default();
+        assert!(config.permits_connection(false));
+        assert!(config.permits_connection(true));
+    }
+
+    #[test]
+    fn test_auth_only_rejects_anonymous() {
+        let config = ServerConfig {
+            allow_anonymous: false,
+            ..ServerConfig::default()
+        };
+        assert!(!config.permits_connection(false));
+        assert!(config.permits_connection(true));
+    }
+
+    #[test]
+    fn test_admin_emails_case_insensitive() {
+        let config = ServerConfig {
+            admin_emails: parse_email_list(" Admin@Example.com, ,ops@example.com"),
+            ..ServerConfig::default()
+        };
+        assert_eq!(config.admin_emails, vec!["admin@example.com", "ops@example.com"]);
+        assert!(config.is_admin("ADMIN@example.com"));
+        assert!(!config.is_admin("user@example.com"));
+    }
+
+    #[test]
+    fn test_parse_log_format() {
+        assert_eq!(LogFormat::parse("JSON"), Some(LogFormat::Json));
+        assert_eq!(LogFormat::parse(" text "), Some(LogFormat::Text));
+        assert_eq!(LogFormat::parse(""), Some(LogFormat::Text));
+        assert_eq!(LogFormat::parse("xml"), None);
+    }
+
+    #[test]
+    fn test_production_env() {
+        assert!(is_production_env("production"));
+        assert!(is_production_env(" PROD "));
+        assert!(!is_production_env("development"));
+        assert!(!is_production_env(""));
+    }
+
+    #[test]
+    fn test_parse_bool() {
+        assert_eq!(parse_bool("false"), Some(false));
+        assert_eq!(parse_bool(" ON "), Some(true));
+        assert_eq!(parse_bool("maybe"), None);
+    }
+
+    #[test]
+    fn test_parse_capacity() {
+        assert_eq!(parse_capacity(" 4096 "), Some(4096));
+        assert_eq!(parse_capacity("0"), None);
+        assert_eq!(parse_capacity("-1"), None);
+        assert_eq!(parse_capacity("lots"), None);
+    }
+
+    #[test]
+    fn test_heartbeat_timeout_must_exceed_interval() {
+        assert!(ServerConfig::default().validate().is_ok());
+
+        let config = ServerConfig {
+            heartbeat_interval: Duration::from_secs(60),
+            heartbeat_timeout: Duration::from_secs(60),
+            ..ServerConfig::default()
+        };
+        assert!(config.validate().is_err());
+
+        let config = ServerConfig {
+            heartbeat_interval: Duration::from_secs(5),
+            heartbeat_timeout: Duration::from_secs(15),
+            ..ServerConfig::default()
+        };
+        assert!(config.validate().is_ok());
+    }
+}
@@ -0,0 +1,78 @@
+use anyhow::{bail, Context};
+use axum::http::{header, HeaderValue, Method};
+use std::time::Duration;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+/// 浏览器缓存预检结果的时间
+const PREFLIGHT_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// 根据允许的来源构建CORS层
+///
+/// `origins` 为None时允许任意来源，仅适合本地开发：任何网站的脚本都能带着用户的令牌调用需要认证的接口。
+/// 配置了来源列表后只有列表中的来源可以跨域访问，列表为空时拒绝所有跨域请求。
+/// 令牌通过 `Authorization` 头传递而不是Cookie，因此不需要允许携带凭据
+pub fn cors_layer(origins: Option<&[String]>) -> anyhow::Result<CorsLayer> {
+    let Some(origins) = origins else {
+        return Ok(CorsLayer::permissive());
+    };
+
+    let mut allowed = Vec::with_capacity(origins.len());
+    for origin in origins {
+        if origin == "*" {
+            bail!("RUSTCHAT_CORS_ORIGINS 不能包含 *，允许任意来源请不要设置该变量");
+        }
+        allowed.push(HeaderValue::from_str(origin).with_context(|| format!("Invalid CORS origin: {}", origin))?);
+    }
+
+    Ok(CorsLayer::new()
+        .allow_origin(AllowOrigin::list(allowed))
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE, Method::OPTIONS])
+        .allow_headers([header::AUTHORIZATION, header::CONTENT_TYPE, header::ACCEPT])
+        .max_age(PREFLIGHT_MAX_AGE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use axum::routing::get;
+    use axum::Router;
+    use tower::ServiceExt;
+
+    async fn allowed_origin(app: &Router, method: Method, origin: &str) -> Option<HeaderValue> {
+        let mut request = Request::builder().method(method.clone()).uri("/api/ping").header(header::ORIGIN, origin);
+        if method == Method::OPTIONS {
+            request = request
+                .header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET")
+                .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "authorization");
+        }
+        let response = app.clone().oneshot(request.body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        response.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).cloned()
+    }
+
+    #[tokio::test]
+    async fn test_only_listed_origins_are_allowed() {
+        let origins = vec!["https://chat.example.com".to_string()];
+        let app = Router::new()
+            .route("/api/ping", get(|| async { "pong" }))
+            .layer(cors_layer(Some(&origins)).unwrap());
+
+        for method in [Method::GET, Method::OPTIONS] {
+            assert_eq!(
+                allowed_origin(&app, method.clone(), "https://chat.example.com").await,
+                Some(HeaderValue::from_static("https://chat.example.com"))
+            );
+            // 未列出的来源得不到允许头，浏览器会拦截响应
+            assert_eq!(allowed_origin(&app, method, "https://evil.example.com").await, None);
+        }
+
+        // 未配置时允许任意来源
+        let app = Router::new().route("/api/ping", get(|| async { "pong" })).layer(cors_layer(None).unwrap());
+        assert!(allowed_origin(&app, Method::GET, "https://evil.example.com").await.is_some());
+
+        assert!(cors_layer(Some(&["*".to_string()])).is_err());
+        assert!(cors_layer(Some(&["https://bad\norigin".to_string()])).is_err());
+    }
+}
@@ -0,0 +1,230 @@
+use std::time::Duration;
+
+use rustchat_types::Message;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::time::{self, Instant};
+
+use crate::WsEvent;
+
+/// 单个批量帧最多包含的消息数
+pub const MAX_EVENT_BATCH: usize = 100;
+
+/// 连接能够接收的批量事件，由客户端连接时通过 `?protocol=` 声明的协议版本决定
+///
+/// 未声明版本的客户端（包括不认识批量事件的旧客户端）始终逐条接收
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BatchSupport {
+    /// 协议1.4起支持 `MessageBatch`
+    messages: bool,
+    /// 协议1.6起支持 `RoomMessageBatch`
+    room_messages: bool,
+}
+
+impl BatchSupport {
+    /// 根据客户端声明的协议版本（"主版本.次版本"）判断支持的批量事件，无法解析时不合并
+    pub fn for_client_protocol(protocol: Option<&str>) -> Self {
+        let version = protocol.and_then(|protocol| {
+            let (major, minor) = protocol.trim().split_once('.')?;
+            Some((major.parse::<u64>().ok()?, minor.parse::<u64>().ok()?))
+        });
+        match version {
+            Some((1, minor)) => Self { messages: minor >= 4, room_messages: minor >= 6 },
+            _ => Self::default(),
+        }
+    }
+
+    /// 事件能否与后续事件合并发送
+    fn can_batch(&self, event: &WsEvent) -> bool {
+        match event {
+            WsEvent::Message(_) => self.messages,
+            WsEvent::RoomMessage { .. } => self.room_messages,
+            _ => false,
+        }
+    }
+}
+
+/// 从发送队列取出下一组要发送的事件
+///
+/// 第一个事件是连接支持合并的消息时，在 `window` 内继续收集，直到遇到不能合并的事件、攒够
+/// [`MAX_EVENT_BATCH`] 条或时间窗口结束；其他事件以及 `window` 为零时直接返回。通道已关闭时返回空列表
+pub async fn next_events(rx: &mut UnboundedReceiver<WsEvent>, window: Duration, support: BatchSupport) -> Vec<WsEvent> {
+    let Some(first) = rx.recv().await else {
+        return Vec::new();
+    };
+    if window.is_zero() || !support.can_batch(&first) {
+        return vec![first];
+    }
+
+    let deadline = Instant::now() + window;
+    let mut events = vec![first];
+    while events.len() < MAX_EVENT_BATCH {
+        match time::timeout_at(deadline, rx.recv()).await {
+            Ok(Some(event)) => {
+                let batchable = support.can_batch(&event);
+                events.push(event);
+                if !batchable {
+                    break;
+                }
+            }
+            Ok(None) | Err(_) => break,
+        }
+    }
+    coalesce_messages(events)
+}
+
+/// 把连续的多条全局消息合并为一个 `MessageBatch`，同一房间连续的多条房间消息合并为一个
+/// `RoomMessageBatch`，其他事件保持原有的先后位置
+pub fn coalesce_messages(events: Vec<WsEvent>) -> Vec<WsEvent> {
+    let mut coalesced = Vec::with_capacity(events.len());
+    let mut pending: Option<Pending> = None;
+
+    for event in events {
+        pending = match (pending.take(), event) {
+            (Some(Pending::Global(mut messages)), WsEvent::Message(message)) => {
+                messages.push(message);
+                Some(Pending::Global(messages))
+            }
+            (Some(Pending::Room(room_id, mut messages)), WsEvent::RoomMessage { room_id: next, message }) if next == room_id => {
+                messages.push(message);
+                Some(Pending::Room(room_id, messages))
+            }
+            (previous, event) => {
+                if let Some(previous) = previous {
+                    coalesced.push(previous.into_event());
+                }
+                match event {
+                    WsEvent::Message(message) => Some(Pending::Global(vec![message])),
+                    WsEvent::RoomMessage { room_id, message } => Some(Pending::Room(room_id, vec![message])),
+                    other => {
+                        coalesced.push(other);
+                        None
+                    }
+                }
+            }
+        };
+    }
+    if let Some(pending) = pending {
+        coalesced.push(pending.into_event());
+    }
+    coalesced
+}
+
+/// 正在合并的一组连续消息
+enum Pending {
+    Global(Vec<Message>),
+    Room(String, Vec<Message>),
+}
+
+impl Pending {
+    /// 只有一条消息时仍按普通消息发送
+    fn into_event(self) -> WsEvent {
+        match self {
+            Pending::Global(mut messages) if messages.len() == 1 => WsEvent::Message(messages.remove(0)),
+            Pending::Global(messages) => WsEvent::MessageBatch(messages),
+            Pending::Room(room_id, mut messages) if messages.len() == 1 => {
+                WsEvent::RoomMessage { room_id, message: messages.remove(0) }
+            }
+            Pending::Room(room_id, messages) => WsEvent::RoomMessageBatch { room_id, messages },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustchat_types::UserId;
+
+    fn message(text: &str) -> Message {
+        Message::new_text(UserId::new(), text.to_string(), None)
+    }
+
+    fn texts(messages: &[Message]) -> Vec<&str> {
+        messages.iter().filter_map(|m| m.get_text()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_messages_in_window_are_batched_in_order() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        for text in ["a", "b", "c"] {
+            tx.send(WsEvent::Message(message(text))).unwrap();
+        }
+        tx.send(WsEvent::Ping).unwrap();
+        tx.send(WsEvent::Message(message("d"))).unwrap();
+
+        // 非消息事件结束当前批次，并排在批次之后
+        let support = BatchSupport::for_client_protocol(Some("1.6"));
+        let events = next_events(&mut rx, Duration::from_millis(20), support).await;
+        assert_eq!(events.len(), 2);
+        match &events[0] {
+            WsEvent::MessageBatch(messages) => assert_eq!(texts(messages), vec!["a", "b", "c"]),
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert!(matches!(events[1], WsEvent::Ping));
+
+        // 窗口内只有一条消息时按普通消息发送
+        let events = next_events(&mut rx, Duration::from_millis(20), support).await;
+        assert!(matches!(&events[..], [WsEvent::Message(m)] if m.get_text() == Some("d")));
+
+        // 未启用合并时逐条发送
+        tx.send(WsEvent::Message(message("e"))).unwrap();
+        tx.send(WsEvent::Message(message("f"))).unwrap();
+        let events = next_events(&mut rx, Duration::ZERO, support).await;
+        assert!(matches!(&events[..], [WsEvent::Message(m)] if m.get_text() == Some("e")));
+
+        drop(tx);
+        assert_eq!(next_events(&mut rx, Duration::ZERO, support).await.len(), 1);
+        assert!(next_events(&mut rx, Duration::ZERO, support).await.is_empty());
+    }
+
+    fn room_message(room_id: &str, text: &str) -> WsEvent {
+        WsEvent::RoomMessage { room_id: room_id.to_string(), message: message(text) }
+    }
+
+    #[tokio::test]
+    async fn test_room_messages_are_batched_per_room() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        for event in [room_message("r1", "a"), room_message("r1", "b"), room_message("r2", "c"), WsEvent::Message(message("d"))] {
+            tx.send(event).unwrap();
+        }
+
+        // 不同房间的消息分开合并，全局消息单独成组，顺序不变
+        let support = BatchSupport::for_client_protocol(Some("1.6"));
+        let events = next_events(&mut rx, Duration::from_millis(20), support).await;
+        assert_eq!(events.len(), 3);
+        match &events[0] {
+            WsEvent::RoomMessageBatch { room_id, messages } => {
+                assert_eq!(room_id, "r1");
+                assert_eq!(texts(messages), vec!["a", "b"]);
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert!(matches!(&events[1], WsEvent::RoomMessage { room_id, .. } if room_id == "r2"));
+        assert!(matches!(&events[2], WsEvent::Message(m) if m.get_text() == Some("d")));
+    }
+
+    #[tokio::test]
+    async fn test_batching_follows_client_protocol() {
+        assert_eq!(BatchSupport::for_client_protocol(None), BatchSupport::default());
+        assert_eq!(BatchSupport::for_client_protocol(Some("1.3")), BatchSupport::default());
+        assert_eq!(BatchSupport::for_client_protocol(Some("2.0")), BatchSupport::default());
+        assert_eq!(BatchSupport::for_client_protocol(Some("garbage")), BatchSupport::default());
+
+        // 未声明协议版本的旧客户端即使开启了合并也逐条接收
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        for text in ["a", "b"] {
+            tx.send(WsEvent::Message(message(text))).unwrap();
+        }
+        let events = next_events(&mut rx, Duration::from_millis(20), BatchSupport::default()).await;
+        assert!(matches!(&events[..], [WsEvent::Message(m)] if m.get_text() == Some("a")));
+
+        // 协议1.4~1.5的客户端只合并全局消息
+        let support = BatchSupport::for_client_protocol(Some("1.5"));
+        for event in [room_message("r1", "c"), room_message("r1", "d")] {
+            tx.send(event).unwrap();
+        }
+        let events = next_events(&mut rx, Duration::from_millis(20), support).await;
+        assert!(matches!(&events[..], [WsEvent::Message(m), WsEvent::RoomMessage { .. }] if m.get_text() == Some("b")));
+        let events = next_events(&mut rx, Duration::from_millis(20), support).await;
+        assert!(matches!(&events[..], [WsEvent::RoomMessage { .. }]));
+    }
+}
@@ -1,69 +1,112 @@
 use axum::{
-    extract::{Query, State},
+    extract::{Extension, Query, State},
     http::StatusCode,
     response::{IntoResponse, Json},
-    routing::{delete, get, post},
+    routing::{delete, get, post, put},
     Router,
 };
-use rustchat_types::UserId;
+use rustchat_types::{FriendRequest, UserId};
 use serde::Deserialize;
 use tracing::{debug, error, info};
+use utoipa::{IntoParams, OpenApi, ToSchema};
 
-/// 创建好友路由
+use super::{FriendEntry, FriendError};
+use crate::auth::AuthenticatedUser;
+
+/// 创建好友路由，操作者身份来自认证信息，需要挂在认证中间件之后
 pub fn create_friend_routes() -> Router<crate::AppState> {
     Router::new()
-        .route("/request", post(send_friend_request))
+        .route("/request", post(send_friend_request).delete(cancel_friend_request))
         .route("/request/respond", post(respond_friend_request))
         .route("/requests", get(get_friend_requests))
         .route("/list", get(get_friends))
+        .route("/alias", put(set_friend_alias))
         .route("/remove", delete(remove_friend))
 }
 
+/// 好友接口的OpenAPI描述
+#[derive(OpenApi)]
+#[openapi(paths(
+    send_friend_request, respond_friend_request, cancel_friend_request, get_friend_requests, get_friends,
+    set_friend_alias, remove_friend
+))]
+pub(crate) struct FriendApi;
+
+/// 好友错误对应的HTTP状态码
+fn error_status(error: &FriendError) -> StatusCode {
+    match error {
+        FriendError::FriendshipNotFound => StatusCode::NOT_FOUND,
+        FriendError::NotAuthorized => StatusCode::FORBIDDEN,
+        FriendError::InvalidStatus => StatusCode::CONFLICT,
+        FriendError::InvalidAlias => StatusCode::BAD_REQUEST,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
 /// 发送好友请求的请求体
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 struct SendFriendRequestBody {
     to_user_id: UserId,
     message: Option<String>,
 }
 
 /// 响应好友请求的请求体
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 struct RespondFriendRequestBody {
     request_id: String,
     accept: bool,
 }
 
-/// 删除好友的查询参数
-#[derive(Debug, Deserialize)]
-struct RemoveFriendQuery {
-    user_id: UserId,
+/// 撤回好友请求的查询参数
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct CancelFriendRequestQuery {
+    request_id: String,
+}
+
+/// 设置好友备注名的请求体，alias 为空表示清除
+#[derive(Debug, Deserialize, ToSchema)]
+struct SetFriendAliasBody {
     friend_user_id: UserId,
+    alias: String,
 }
 
-/// 获取好友列表/请求的查询参数
-#[derive(Debug, Deserialize)]
-struct GetFriendsQuery {
-    user_id: UserId,
+/// 删除好友的查询参数
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct RemoveFriendQuery {
+    friend_user_id: UserId,
 }
 
 /// 发送好友请求
+#[utoipa::path(
+    post,
+    path = "/api/friends/request",
+    tag = "friends",
+    security(("bearer_auth" = [])),
+    request_body = SendFriendRequestBody,
+    responses(
+        (status = 200, description = "好友请求已发送", body = FriendRequest),
+        (status = 500, description = "内部错误，响应体为错误描述文本", body = String)
+    )
+)]
 async fn send_friend_request(
-    Query(query): Query<GetFriendsQuery>,
     State(state): State<crate::AppState>,
+    Extension(auth_user): Extension<AuthenticatedUser>,
     Json(body): Json<SendFriendRequestBody>,
 ) -> impl IntoResponse {
     debug!(
         "Sending friend request from {} to {} with message: {:?}",
-        query.user_id, body.to_user_id, body.message
+        auth_user.user_id, body.to_user_id, body.message
     );
 
     let mut manager = state.friend_manager.lock().await;
     
-    match manager.send_friend_request(query.user_id.clone(), body.to_user_id.clone(), body.message).await {
+    match manager.send_friend_request(auth_user.user_id.clone(), body.to_user_id.clone(), body.message).await {
         Ok(request) => {
             info!(
                 "Friend request sent from {} to {}, request_id: {}",
-                query.user_id, body.to_user_id, request.id
+                auth_user.user_id, body.to_user_id, request.id
             );
             Json(request).into_response()
         }
@@ -75,22 +118,35 @@ async fn send_friend_request(
 }
 
 /// 响应好友请求（接受或拒绝）
+#[utoipa::path(
+    post,
+    path = "/api/friends/request/respond",
+    tag = "friends",
+    security(("bearer_auth" = [])),
+    request_body = RespondFriendRequestBody,
+    responses(
+        (status = 200, description = "处理后的好友请求", body = FriendRequest),
+        (status = 403, description = "无权操作该好友请求", body = String),
+        (status = 404, description = "好友请求不存在", body = String),
+        (status = 409, description = "好友请求已处理", body = String)
+    )
+)]
 async fn respond_friend_request(
-    Query(query): Query<GetFriendsQuery>,
     State(state): State<crate::AppState>,
+    Extension(auth_user): Extension<AuthenticatedUser>,
     Json(body): Json<RespondFriendRequestBody>,
 ) -> impl IntoResponse {
     debug!(
         "User {} responding to friend request {}: {}",
-        query.user_id, body.request_id, if body.accept { "accept" } else { "reject" }
+        auth_user.user_id, body.request_id, if body.accept { "accept" } else { "reject" }
     );
 
     let mut manager = state.friend_manager.lock().await;
     
     let result = if body.accept {
-        manager.accept_friend_request(&body.request_id).await
+        manager.accept_friend_request(&body.request_id, &auth_user.user_id).await
     } else {
-        manager.reject_friend_request(&body.request_id).await
+        manager.reject_friend_request(&body.request_id, &auth_user.user_id).await
     };
 
     match result {
@@ -99,29 +155,74 @@ async fn respond_friend_request(
                 "Friend request {} {} by user {}",
                 body.request_id,
                 if body.accept { "accepted" } else { "rejected" },
-                query.user_id
+                auth_user.user_id
             );
             Json(request).into_response()
         }
         Err(e) => {
             error!("Failed to respond to friend request: {}", e);
-            (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to respond to friend request: {}", e)).into_response()
+            (error_status(&e), format!("Failed to respond to friend request: {}", e)).into_response()
+        }
+    }
+}
+
+/// 撤回自己发送的好友请求
+#[utoipa::path(
+    delete,
+    path = "/api/friends/request",
+    tag = "friends",
+    security(("bearer_auth" = [])),
+    params(CancelFriendRequestQuery),
+    responses(
+        (status = 200, description = "已撤回的好友请求", body = FriendRequest),
+        (status = 403, description = "无权操作该好友请求", body = String),
+        (status = 404, description = "好友请求不存在", body = String),
+        (status = 409, description = "好友请求已处理", body = String)
+    )
+)]
+async fn cancel_friend_request(
+    State(state): State<crate::AppState>,
+    Extension(auth_user): Extension<AuthenticatedUser>,
+    Query(query): Query<CancelFriendRequestQuery>,
+) -> impl IntoResponse {
+    debug!("User {} cancelling friend request {}", auth_user.user_id, query.request_id);
+
+    let mut manager = state.friend_manager.lock().await;
+
+    match manager.cancel_friend_request(&query.request_id, &auth_user.user_id).await {
+        Ok(request) => {
+            info!("Friend request {} cancelled by user {}", query.request_id, auth_user.user_id);
+            Json(request).into_response()
+        }
+        Err(e) => {
+            error!("Failed to cancel friend request: {}", e);
+            (error_status(&e), format!("Failed to cancel friend request: {}", e)).into_response()
         }
     }
 }
 
 /// 获取好友请求列表
+#[utoipa::path(
+    get,
+    path = "/api/friends/requests",
+    tag = "friends",
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "与当前用户相关的好友请求", body = Vec<FriendRequest>),
+        (status = 500, description = "内部错误，响应体为错误描述文本", body = String)
+    )
+)]
 async fn get_friend_requests(
-    Query(query): Query<GetFriendsQuery>,
     State(state): State<crate::AppState>,
+    Extension(auth_user): Extension<AuthenticatedUser>,
 ) -> impl IntoResponse {
-    debug!("Getting friend requests for user {}", query.user_id);
+    debug!("Getting friend requests for user {}", auth_user.user_id);
 
     let manager = state.friend_manager.lock().await;
     
-    match manager.get_friend_requests(query.user_id.clone()).await {
+    match manager.get_friend_requests(auth_user.user_id.clone()).await {
         Ok(requests) => {
-            debug!("Found {} friend requests for user {}", requests.len(), query.user_id);
+            debug!("Found {} friend requests for user {}", requests.len(), auth_user.user_id);
             Json(requests).into_response()
         }
         Err(e) => {
@@ -132,17 +233,27 @@ async fn get_friend_requests(
 }
 
 /// 获取好友列表
+#[utoipa::path(
+    get,
+    path = "/api/friends/list",
+    tag = "friends",
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "好友列表", body = Vec<FriendEntry>),
+        (status = 500, description = "内部错误，响应体为错误描述文本", body = String)
+    )
+)]
 async fn get_friends(
-    Query(query): Query<GetFriendsQuery>,
     State(state): State<crate::AppState>,
+    Extension(auth_user): Extension<AuthenticatedUser>,
 ) -> impl IntoResponse {
-    debug!("Getting friends for user {}", query.user_id);
+    debug!("Getting friends for user {}", auth_user.user_id);
 
     let manager = state.friend_manager.lock().await;
     
-    match manager.get_friends(query.user_id.clone()).await {
+    match manager.get_friend_entries(auth_user.user_id.clone()).await {
         Ok(friends) => {
-            debug!("Found {} friends for user {}", friends.len(), query.user_id);
+            debug!("Found {} friends for user {}", friends.len(), auth_user.user_id);
             Json(friends).into_response()
         }
         Err(e) => {
@@ -152,18 +263,65 @@ async fn get_friends(
     }
 }
 
+/// 设置好友备注名
+#[utoipa::path(
+    put,
+    path = "/api/friends/alias",
+    tag = "friends",
+    security(("bearer_auth" = [])),
+    request_body = SetFriendAliasBody,
+    responses(
+        (status = 200, description = "更新后的好友信息", body = FriendEntry),
+        (status = 400, description = "备注名无效", body = String),
+        (status = 404, description = "对方不是好友", body = String)
+    )
+)]
+async fn set_friend_alias(
+    State(state): State<crate::AppState>,
+    Extension(auth_user): Extension<AuthenticatedUser>,
+    Json(body): Json<SetFriendAliasBody>,
+) -> impl IntoResponse {
+    debug!("User {} setting alias for friend {}", auth_user.user_id, body.friend_user_id);
+
+    let mut manager = state.friend_manager.lock().await;
+
+    match manager.set_alias(&auth_user.user_id, &body.friend_user_id, &body.alias).await {
+        Ok(()) => {
+            info!("Alias for friend {} updated by user {}", body.friend_user_id, auth_user.user_id);
+            let alias = manager.get_alias(&auth_user.user_id, &body.friend_user_id).await;
+            Json(FriendEntry { user_id: body.friend_user_id, alias }).into_response()
+        }
+        Err(e) => {
+            error!("Failed to set friend alias: {}", e);
+            (error_status(&e), format!("Failed to set friend alias: {}", e)).into_response()
+        }
+    }
+}
+
 /// 删除好友
+#[utoipa::path(
+    delete,
+    path = "/api/friends/remove",
+    tag = "friends",
+    security(("bearer_auth" = [])),
+    params(RemoveFriendQuery),
+    responses(
+        (status = 200, description = "好友已删除"),
+        (status = 500, description = "内部错误，响应体为错误描述文本", body = String)
+    )
+)]
 async fn remove_friend(
-    Query(query): Query<RemoveFriendQuery>,
     State(state): State<crate::AppState>,
+    Extension(auth_user): Extension<AuthenticatedUser>,
+    Query(query): Query<RemoveFriendQuery>,
 ) -> impl IntoResponse {
-    debug!("Removing friend {} for user {}", query.friend_user_id, query.user_id);
+    debug!("Removing friend {} for user {}", query.friend_user_id, auth_user.user_id);
 
     let mut manager = state.friend_manager.lock().await;
     
-    match manager.remove_friend(query.user_id.clone(), query.friend_user_id.clone()).await {
+    match manager.remove_friend(auth_user.user_id.clone(), query.friend_user_id.clone()).await {
         Ok(_) => {
-            info!("Friend {} removed for user {}", query.friend_user_id, query.user_id);
+            info!("Friend {} removed for user {}", query.friend_user_id, auth_user.user_id);
             StatusCode::OK.into_response()
         }
         Err(e) => {
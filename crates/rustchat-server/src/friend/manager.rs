@@ -1,12 +1,26 @@
 use rustchat_types::{UserId, FriendRequest, FriendRequestStatus};
+use serde::Serialize;
 use std::collections::{HashMap, HashSet};
 use tokio::sync::RwLock;
 use tracing::info;
+use utoipa::ToSchema;
 
 /// 好友管理器
 pub struct FriendManager {
     friend_requests: RwLock<HashMap<String, FriendRequest>>,
     friendships: RwLock<HashMap<UserId, HashSet<UserId>>>,
+    /// 好友备注名，按设置者分别保存：设置者 -> 好友 -> 备注名
+    aliases: RwLock<HashMap<UserId, HashMap<UserId, String>>>,
+}
+
+/// 备注名的最大字符数
+pub const MAX_ALIAS_CHARS: usize = 32;
+
+/// 好友列表中的一项
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct FriendEntry {
+    pub user_id: UserId,
+    pub alias: Option<String>,
 }
 
 impl FriendManager {
@@ -15,6 +29,7 @@ impl FriendManager {
         Self {
             friend_requests: RwLock::new(HashMap::new()),
             friendships: RwLock::new(HashMap::new()),
+            aliases: RwLock::new(HashMap::new()),
         }
     }
       /// 发送好友请求
@@ -52,10 +67,15 @@ impl FriendManager {
     }
     
     /// 接受好友请求
-    pub async fn accept_friend_request(&mut self, request_id: &str) -> Result<FriendRequest, FriendError> {
+    pub async fn accept_friend_request(&mut self, request_id: &str, responder: &UserId) -> Result<FriendRequest, FriendError> {
         let mut friend_requests = self.friend_requests.write().await;
         let request = friend_requests.get_mut(request_id).ok_or(FriendError::FriendshipNotFound)?;
         
+        // 只有接收者可以处理
+        if &request.to_user_id != responder {
+            return Err(FriendError::NotAuthorized);
+        }
+        
         // 检查状态是否为pending
         if request.status != FriendRequestStatus::Pending {
             return Err(FriendError::InvalidStatus);
@@ -75,10 +95,15 @@ impl FriendManager {
     }
     
     /// 拒绝好友请求
-    pub async fn reject_friend_request(&mut self, request_id: &str) -> Result<FriendRequest, FriendError> {
+    pub async fn reject_friend_request(&mut self, request_id: &str, responder: &UserId) -> Result<FriendRequest, FriendError> {
         let mut friend_requests = self.friend_requests.write().await;
         let request = friend_requests.get_mut(request_id).ok_or(FriendError::FriendshipNotFound)?;
         
+        // 只有接收者可以处理
+        if &request.to_user_id != responder {
+            return Err(FriendError::NotAuthorized);
+        }
+        
         // 检查状态是否为pending
         if request.status != FriendRequestStatus::Pending {
             return Err(FriendError::InvalidStatus);
@@ -90,6 +115,25 @@ impl FriendManager {
         Ok(request.clone())
     }
     
+    /// 撤回自己发送且尚未处理的好友请求
+    pub async fn cancel_friend_request(&mut self, request_id: &str, requester: &UserId) -> Result<FriendRequest, FriendError> {
+        let mut friend_requests = self.friend_requests.write().await;
+        let request = friend_requests.get(request_id).ok_or(FriendError::FriendshipNotFound)?;
+
+        // 只有发送者可以撤回
+        if &request.from_user_id != requester {
+            return Err(FriendError::NotAuthorized);
+        }
+        if request.status != FriendRequestStatus::Pending {
+            return Err(FriendError::InvalidStatus);
+        }
+
+        let request = friend_requests.remove(request_id).ok_or(FriendError::FriendshipNotFound)?;
+
+        info!("用户 {} 撤回了发给用户 {} 的好友请求", request.from_user_id, request.to_user_id);
+        Ok(request)
+    }
+
     /// 获取用户的好友请求（收到的和发送的）
     pub async fn get_friend_requests(&self, user_id: UserId) -> Result<Vec<FriendRequest>, FriendError> {
         let friend_requests = self.friend_requests.read().await;
@@ -116,6 +160,51 @@ impl FriendManager {
         }
     }
     
+    /// 获取带备注名的好友列表，备注名只对设置者本人可见
+    pub async fn get_friend_entries(&self, user_id: UserId) -> Result<Vec<FriendEntry>, FriendError> {
+        let friends = self.get_friends(user_id.clone()).await?;
+        let aliases = self.aliases.read().await;
+        let own_aliases = aliases.get(&user_id);
+
+        Ok(friends
+            .into_iter()
+            .map(|friend| FriendEntry {
+                alias: own_aliases.and_then(|map| map.get(&friend)).cloned(),
+                user_id: friend,
+            })
+            .collect())
+    }
+
+    /// 设置好友备注名，空白备注名表示清除
+    pub async fn set_alias(&mut self, owner: &UserId, friend: &UserId, alias: &str) -> Result<(), FriendError> {
+        if !self.are_friends(owner, friend).await {
+            return Err(FriendError::FriendshipNotFound);
+        }
+
+        let alias = alias.trim();
+        if alias.chars().count() > MAX_ALIAS_CHARS || alias.chars().any(char::is_control) {
+            return Err(FriendError::InvalidAlias);
+        }
+
+        let mut aliases = self.aliases.write().await;
+        if alias.is_empty() {
+            if let Some(own_aliases) = aliases.get_mut(owner) {
+                own_aliases.remove(friend);
+            }
+            info!("用户 {} 清除了好友 {} 的备注名", owner, friend);
+        } else {
+            aliases.entry(owner.clone()).or_default().insert(friend.clone(), alias.to_string());
+            info!("用户 {} 将好友 {} 的备注名设置为 {}", owner, friend, alias);
+        }
+        Ok(())
+    }
+
+    /// 获取用户为好友设置的备注名
+    pub async fn get_alias(&self, owner: &UserId, friend: &UserId) -> Option<String> {
+        let aliases = self.aliases.read().await;
+        aliases.get(owner).and_then(|own_aliases| own_aliases.get(friend)).cloned()
+    }
+
     /// 删除好友关系
     pub async fn remove_friend(&mut self, user_id: UserId, friend_user_id: UserId) -> Result<(), FriendError> {
         let mut friendships = self.friendships.write().await;
@@ -127,11 +216,62 @@ impl FriendManager {
         if let Some(friends) = friendships.get_mut(&friend_user_id) {
             friends.remove(&user_id);
         }
+        drop(friendships);
+
+        // 双方的备注名随好友关系一起清除
+        {
+            let mut aliases = self.aliases.write().await;
+            if let Some(own_aliases) = aliases.get_mut(&user_id) {
+                own_aliases.remove(&friend_user_id);
+            }
+            if let Some(their_aliases) = aliases.get_mut(&friend_user_id) {
+                their_aliases.remove(&user_id);
+            }
+        }
         
         info!("用户 {} 删除了与用户 {} 的好友关系", user_id, friend_user_id);
         Ok(())
     }
     
+    /// 移除用户的所有好友关系和相关好友请求，返回原好友列表和删除的请求数
+    pub async fn remove_all_relationships(&mut self, user_id: &UserId) -> (Vec<UserId>, usize) {
+        let former_friends: Vec<UserId> = {
+            let mut friendships = self.friendships.write().await;
+            let friends = friendships.remove(user_id).unwrap_or_default();
+            for friend in &friends {
+                if let Some(their_friends) = friendships.get_mut(friend) {
+                    their_friends.remove(user_id);
+                }
+            }
+            friends.into_iter().collect()
+        };
+
+        {
+            let mut aliases = self.aliases.write().await;
+            aliases.remove(user_id);
+            for own_aliases in aliases.values_mut() {
+                own_aliases.remove(user_id);
+            }
+        }
+
+        let removed_requests = {
+            let mut friend_requests = self.friend_requests.write().await;
+            let before = friend_requests.len();
+            friend_requests.retain(|_, request| {
+                &request.from_user_id != user_id && &request.to_user_id != user_id
+            });
+            before - friend_requests.len()
+        };
+
+        info!(
+            "已移除用户 {} 的 {} 个好友关系和 {} 个好友请求",
+            user_id,
+            former_friends.len(),
+            removed_requests
+        );
+        (former_friends, removed_requests)
+    }
+
     /// 检查两个用户是否为好友
     pub async fn are_friends(&self, user1: &UserId, user2: &UserId) -> bool {
         let friendships = self.friendships.read().await;
@@ -157,6 +297,94 @@ pub enum FriendError {
     NotAuthorized,
     #[error("好友关系状态无效")]
     InvalidStatus,
+    #[error("备注名无效")]
+    InvalidAlias,
     #[error("数据库错误: {0}")]
     DatabaseError(#[from] anyhow::Error),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_only_sender_can_cancel_pending_request() {
+        let mut manager = FriendManager::new();
+        let alice = UserId::new();
+        let bob = UserId::new();
+        let mallory = UserId::new();
+
+        let request = manager.send_friend_request(alice.clone(), bob.clone(), None).await.unwrap();
+
+        // 第三方和接收者都不能撤回
+        assert!(matches!(
+            manager.cancel_friend_request(&request.id, &mallory).await,
+            Err(FriendError::NotAuthorized)
+        ));
+        assert!(matches!(
+            manager.cancel_friend_request(&request.id, &bob).await,
+            Err(FriendError::NotAuthorized)
+        ));
+        assert_eq!(manager.get_friend_requests(bob.clone()).await.unwrap().len(), 1);
+
+        // 发送者撤回后请求消失，可以重新发送
+        let cancelled = manager.cancel_friend_request(&request.id, &alice).await.unwrap();
+        assert_eq!(cancelled.id, request.id);
+        assert!(manager.get_friend_requests(bob.clone()).await.unwrap().is_empty());
+        assert!(matches!(
+            manager.cancel_friend_request(&request.id, &alice).await,
+            Err(FriendError::FriendshipNotFound)
+        ));
+
+        // 已处理的请求不能撤回
+        let request = manager.send_friend_request(alice.clone(), bob.clone(), None).await.unwrap();
+        manager.reject_friend_request(&request.id, &bob).await.unwrap();
+        assert!(matches!(
+            manager.cancel_friend_request(&request.id, &alice).await,
+            Err(FriendError::InvalidStatus)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_friend_aliases_are_private_and_cleared_on_removal() {
+        let mut manager = FriendManager::new();
+        let alice = UserId::new();
+        let bob = UserId::new();
+        let carol = UserId::new();
+
+        // 不是好友时不能设置备注名
+        assert!(matches!(
+            manager.set_alias(&alice, &bob, "小鲍").await,
+            Err(FriendError::FriendshipNotFound)
+        ));
+
+        let request = manager.send_friend_request(alice.clone(), bob.clone(), None).await.unwrap();
+        manager.accept_friend_request(&request.id, &bob).await.unwrap();
+
+        manager.set_alias(&alice, &bob, "  小鲍 ").await.unwrap();
+        assert_eq!(manager.get_alias(&alice, &bob).await.as_deref(), Some("小鲍"));
+        assert!(matches!(
+            manager.set_alias(&alice, &bob, &"长".repeat(MAX_ALIAS_CHARS + 1)).await,
+            Err(FriendError::InvalidAlias)
+        ));
+
+        // 备注名只对设置者可见
+        let entries = manager.get_friend_entries(alice.clone()).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].alias.as_deref(), Some("小鲍"));
+        assert_eq!(manager.get_alias(&bob, &alice).await, None);
+        assert_eq!(manager.get_friend_entries(bob.clone()).await.unwrap()[0].alias, None);
+        assert!(manager.get_friend_entries(carol.clone()).await.unwrap().is_empty());
+
+        // 空白备注名表示清除
+        manager.set_alias(&alice, &bob, " ").await.unwrap();
+        assert_eq!(manager.get_alias(&alice, &bob).await, None);
+
+        // 删除好友后备注名一并清除，重新成为好友也不会恢复
+        manager.set_alias(&alice, &bob, "小鲍").await.unwrap();
+        manager.remove_friend(bob.clone(), alice.clone()).await.unwrap();
+        let request = manager.send_friend_request(alice.clone(), bob.clone(), None).await.unwrap();
+        manager.accept_friend_request(&request.id, &bob).await.unwrap();
+        assert_eq!(manager.get_alias(&alice, &bob).await, None);
+    }
+}
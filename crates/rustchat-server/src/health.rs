@@ -0,0 +1,96 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Json},
+    routing::get,
+    Router,
+};
+use serde_json::json;
+use std::time::Duration;
+use tracing::error;
+
+use crate::{AppState, SERVER_VERSION};
+
+/// 创建健康检查路由
+///
+/// `/health/live` 只确认进程在运行；`/health` 和 `/health/ready` 检查数据库连接，
+/// 数据库不可用时返回503，供负载均衡和编排系统判断实例是否可以接收流量
+pub fn create_health_routes() -> Router<AppState> {
+    Router::new()
+        .route("/health", get(readiness_check))
+        .route("/health/ready", get(readiness_check))
+        .route("/health/live", get(liveness_check))
+}
+
+/// 存活检查，不访问任何依赖
+async fn liveness_check() -> impl IntoResponse {
+    Json(json!({ "status": "alive", "version": SERVER_VERSION }))
+}
+
+/// 就绪检查：测试消息数据库连接，并报告连接数、房间数、版本和运行时长
+async fn readiness_check(State(state): State<AppState>) -> impl IntoResponse {
+    let connected_clients = state.clients.read().await.len();
+    let rooms = state.room_manager.get_stats().await.total_rooms;
+    let uptime = state.uptime();
+    let server = json!({
+        "version": SERVER_VERSION,
+        "started_at": state.started_at_utc,
+        "uptime_secs": uptime.as_secs(),
+        "uptime": format_uptime(uptime)
+    });
+
+    match sqlx::query("SELECT 1").execute(state.message_db.get_pool()).await {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(json!({
+                "status": "healthy",
+                "database": "connected",
+                "connected_clients": connected_clients,
+                "rooms": rooms,
+                "server": server
+            })),
+        ),
+        // 接口无需认证，具体错误只写入日志，不返回给调用方
+        Err(e) => {
+            error!("消息数据库连接失败: {}", e);
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(json!({
+                    "status": "unhealthy",
+                    "database": "unavailable",
+                    "connected_clients": connected_clients,
+                    "rooms": rooms,
+                    "server": server
+                })),
+            )
+        }
+    }
+}
+
+/// 把运行时长格式化为便于阅读的文本，例如 `2天3小时4分5秒`
+pub fn format_uptime(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (days, hours, minutes, seconds) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60, secs % 60);
+    if days > 0 {
+        format!("{}天{}小时{}分{}秒", days, hours, minutes, seconds)
+    } else if hours > 0 {
+        format!("{}小时{}分{}秒", hours, minutes, seconds)
+    } else if minutes > 0 {
+        format!("{}分{}秒", minutes, seconds)
+    } else {
+        format!("{}秒", seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_uptime() {
+        assert_eq!(format_uptime(Duration::from_secs(42)), "42秒");
+        assert_eq!(format_uptime(Duration::from_secs(3 * 60 + 5)), "3分5秒");
+        assert_eq!(format_uptime(Duration::from_secs(3600)), "1小时0分0秒");
+        assert_eq!(format_uptime(Duration::from_secs(2 * 86400 + 3 * 3600 + 4 * 60 + 5)), "2天3小时4分5秒");
+    }
+}
@@ -0,0 +1,133 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+    routing::{get, post},
+    Extension, Router,
+};
+use chrono::{DateTime, Utc};
+use rustchat_core::UserMessageStats;
+use rustchat_types::{validate_nickname, Message, UserId};
+use serde::Deserialize;
+
+use crate::auth::AuthenticatedUser;
+use crate::room::ApiResponse;
+use crate::AppState;
+
+/// 单次最多返回的消息数量
+const MAX_HISTORY_LIMIT: i64 = 100;
+/// 默认返回的消息数量
+const DEFAULT_HISTORY_LIMIT: i64 = 50;
+
+/// 创建全局消息历史和用户消息统计路由（需配合可选认证中间件使用）
+pub fn create_history_routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/messages", get(get_messages))
+        .route("/api/users/{user_id}/stats", get(get_user_stats))
+}
+
+/// 创建需要认证的全局消息路由
+pub fn create_protected_history_routes() -> Router<AppState> {
+    Router::new().route("/api/messages", post(create_message))
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateMessageRequest {
+    content: String,
+    nickname: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryQuery {
+    limit: Option<i64>,
+    /// 只返回早于该时间的消息，用于向前翻页
+    before: Option<DateTime<Utc>>,
+    /// 只返回服务器在该时间之后接收的消息，用于断线重连后补齐，不能与 `before` 同时使用
+    after: Option<DateTime<Utc>>,
+}
+
+/// 分页获取全局（非房间）消息历史，按时间正序排列
+///
+/// 指定 `after` 时返回该时间之后接收的最早的N条消息，按接收时间正序排列
+async fn get_messages(
+    State(state): State<AppState>,
+    Query(query): Query<HistoryQuery>,
+    auth_user: Option<Extension<AuthenticatedUser>>,
+) -> Result<Json<ApiResponse<Vec<Message>>>, StatusCode> {
+    // 禁用匿名访问时与WebSocket连接保持一致，要求登录
+    if !state.config.permits_connection(auth_user.is_some()) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_HISTORY_LIMIT).clamp(1, MAX_HISTORY_LIMIT);
+
+    let result = match (query.before, query.after) {
+        (Some(_), Some(_)) => return Err(StatusCode::BAD_REQUEST),
+        (before, None) => state.message_db.get_messages_before(before, limit).await,
+        (None, Some(after)) => state.message_db.get_messages_received_since(after, limit).await,
+    };
+
+    match result {
+        Ok(messages) => Ok(Json(ApiResponse::success(messages))),
+        Err(e) => {
+            tracing::error!("获取消息历史失败: {}", e);
+            Ok(Json(ApiResponse::error(e.to_string())))
+        }
+    }
+}
+
+/// 以认证用户的身份发送全局消息，与WebSocket的SendMessage走相同的处理流程
+async fn create_message(
+    State(state): State<AppState>,
+    Extension(auth_user): Extension<AuthenticatedUser>,
+    Json(request): Json<CreateMessageRequest>,
+) -> Result<Json<ApiResponse<Message>>, StatusCode> {
+    if let Err(e) = Message::validate_content(&request.content) {
+        return Ok(Json(ApiResponse::error(format!("消息内容{}", e))));
+    }
+    let nickname = match request.nickname {
+        Some(nickname) => match validate_nickname(&nickname) {
+            Ok(()) => Some(nickname.trim().to_string()),
+            Err(e) => return Ok(Json(ApiResponse::error(format!("昵称{}", e)))),
+        },
+        None => None,
+    };
+
+    let content = match state.apply_word_filter(request.content) {
+        Ok(content) => content,
+        Err(rejected) => return Ok(Json(ApiResponse::error(rejected.to_string()))),
+    };
+
+    let mut message = Message::new_text(auth_user.user_id, content, nickname);
+    message.mark_received();
+    state.resolve_mentions(&mut message).await;
+
+    if let Err(e) = state.message_writer.save(&message).await {
+        tracing::error!("保存消息到数据库失败: {}", e);
+        return Ok(Json(ApiResponse::error(e.to_string())));
+    }
+
+    state.publish_message(&message).await;
+    Ok(Json(ApiResponse::success(message)))
+}
+
+/// 获取用户的消息统计，没有消息的用户返回全零的统计
+async fn get_user_stats(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+    auth_user: Option<Extension<AuthenticatedUser>>,
+) -> Result<Json<ApiResponse<UserMessageStats>>, StatusCode> {
+    if !state.config.permits_connection(auth_user.is_some()) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    let user_id = UserId::parse(&user_id).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    match state.message_db.get_user_stats(&user_id).await {
+        Ok(stats) => Ok(Json(ApiResponse::success(stats))),
+        Err(e) => {
+            tracing::error!("获取用户消息统计失败: {}", e);
+            Ok(Json(ApiResponse::error(e.to_string())))
+        }
+    }
+}
@@ -0,0 +1,41 @@
+use std::time::Duration;
+
+/// 空闲达到超时时间的该比例时发送警告
+const IDLE_WARNING_RATIO: f64 = 0.8;
+
+/// 连接的空闲状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdleState {
+    /// 最近有活动
+    Active,
+    /// 即将因空闲被断开，附带剩余时间
+    Warning(Duration),
+    /// 空闲已超时，应断开连接
+    Expired,
+}
+
+impl IdleState {
+    /// 根据已空闲的时间判断状态
+    pub fn of(idle_for: Duration, timeout: Duration) -> Self {
+        if idle_for >= timeout {
+            IdleState::Expired
+        } else if idle_for >= timeout.mul_f64(IDLE_WARNING_RATIO) {
+            IdleState::Warning(timeout - idle_for)
+        } else {
+            IdleState::Active
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_idle_state_thresholds() {
+        let timeout = Duration::from_secs(100);
+        assert_eq!(IdleState::of(Duration::from_secs(79), timeout), IdleState::Active);
+        assert_eq!(IdleState::of(Duration::from_secs(80), timeout), IdleState::Warning(Duration::from_secs(20)));
+        assert_eq!(IdleState::of(Duration::from_secs(100), timeout), IdleState::Expired);
+    }
+}
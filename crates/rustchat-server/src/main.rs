@@ -1,60 +1,142 @@
 mod auth;
+mod config;
 mod room;
 mod friend;
+mod nickname;
+mod markdown;
+mod mention;
+mod history;
+mod profile;
+mod metrics;
+mod health;
+mod word_filter;
+mod bots;
+mod resume;
+mod cluster;
+mod rate_limit;
+mod openapi;
+mod client_error;
+mod idle;
+mod message_writer;
+mod event_batch;
+mod cors;
+mod upload;
 
 use axum::{
-    extract::ws::{Message as WsMessage, WebSocket, WebSocketUpgrade},
-    http::StatusCode,
-    response::{IntoResponse, Response},
+    extract::ws::{close_code, CloseFrame, Message as WsMessage, WebSocket, WebSocketUpgrade},
+    response::Response,
     routing::get,
     Router,
 };
+use anyhow::Context;
 use futures_util::{SinkExt, StreamExt};
-use rustchat_core::{generate_user_id, MessageDatabase, BotManager, EchoBot};
-use rustchat_types::{Message, UserId};
+use rustchat_core::{generate_user_id, MessageDatabase, BotManager, CommandBot, EchoBot, GreeterBot};
+use rustchat_types::{validate_nickname, ErrorCode, Message, MessageId, UserId, PROTOCOL_VERSION};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tokio::sync::{broadcast, Mutex};
+use tokio::sync::{broadcast, Mutex, RwLock};
 use tokio::time;
-use tower_http::cors::CorsLayer;
 use tower_http::trace::TraceLayer;
 use tracing::{debug, error, info, warn};
 
 // 导入房间相关模块
-use room::{RoomManager, RoomBroadcastManager, RoomMessageRouter, create_protected_room_routes, create_public_room_routes};
+use room::{RoomManager, RoomBroadcastManager, RoomMessageRouter};
 
 // 导入认证相关模块
-use auth::{AuthService, create_auth_routes};
+use auth::{AccountId, AuthService, create_admin_routes, create_auth_routes, create_protected_auth_routes};
 
 // 导入好友相关模块
 use friend::{FriendManager, create_friend_routes};
+use config::{LogFormat, ServerConfig};
+use nickname::NickChangeLimiter;
+use word_filter::MessageFilter;
+use resume::ResumeTokens;
+use cluster::ClusterRelay;
+use rate_limit::MessageRateLimiter;
+use client_error::ClientError;
+use idle::IdleState;
+use message_writer::MessageWriter;
+use upload::UploadStore;
+
+/// 阅后即焚消息的最长存活时间（7天）
+const MAX_MESSAGE_TTL_SECS: u64 = 7 * 24 * 60 * 60;
+/// 表情回应的最大长度（字符数，组合表情由多个字符组成）
+const MAX_REACTION_EMOJI_CHARS: usize = 16;
+/// 过期消息清理间隔
+const EXPIRED_MESSAGE_SWEEP_INTERVAL: Duration = Duration::from_secs(30);
+/// 服务器版本号
+pub const SERVER_VERSION: &str = env!("CARGO_PKG_VERSION");
+/// 按保留条数清理旧消息的间隔
+const MESSAGE_RETENTION_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+/// 客户端消息无法解析时附带的格式提示
+const CLIENT_MESSAGE_FORMAT_HINT: &str = r#"应为 {"type": "SendMessage", "data": {...}} 形式的JSON，type 为消息类型"#;
+
+/// 恢复会话时最多补发的消息条数
+const RESUME_REPLAY_LIMIT: i64 = 100;
 
 /// WebSocket事件类型
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "event", content = "data")]
 pub enum WsEvent {
     /// 连接建立，服务器返回用户ID
-    Connected { user_id: UserId },
+    ///
+    /// 匿名连接会附带恢复令牌，断线后携带 `?resume=<token>` 重连可沿用同一用户ID
+    Connected {
+        user_id: UserId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        resume_token: Option<String>,
+    },
+    /// 服务器版本信息，连接建立后紧随Connected发送
+    ServerInfo { version: String, protocol_version: String },
     /// 新消息
     Message(Message),
+    /// 短时间内连续到达的多条新消息，按时间先后排列（仅在配置了合并窗口时发送给声明协议1.4及以上的连接）
+    MessageBatch(Vec<Message>),
     /// 用户加入
     UserJoined { user_id: UserId, nickname: Option<String> },
     /// 用户离开
     UserLeft { user_id: UserId },
     /// 房间消息
     RoomMessage { room_id: String, message: Message },
+    /// 同一房间短时间内连续到达的多条消息，按时间先后排列（仅发送给声明协议1.6及以上的连接）
+    RoomMessageBatch { room_id: String, messages: Vec<Message> },
     /// 用户加入房间
     UserJoinedRoom { room_id: String, user_id: UserId },
     /// 用户离开房间
     UserLeftRoom { room_id: String, user_id: UserId },
+    /// 当前连接已加入房间（已是成员时同样发送），只发送给加入的连接
+    RoomJoined { room: room::RoomResponse },
+    /// 当前连接已离开房间，只发送给离开的连接
+    RoomLeft { room_id: String },
+    /// 当前用户所在的房间列表（响应ListMyRooms，加入或离开房间后也会推送）
+    RoomList { rooms: Vec<room::RoomResponse> },
+    /// 用户的全部消息已被清除
+    MessagesPurged { user_id: UserId },
+    /// 好友关系已被移除
+    FriendRemoved { user_id: UserId },
+    /// 用户修改了昵称（同时仍会广播一条昵称变更消息）
+    NicknameChanged { user_id: UserId, old_nick: Option<String>, new_nick: String },
+    /// 阅后即焚消息已过期并被删除
+    MessagesExpired { message_ids: Vec<MessageId> },
+    /// 当前用户在消息中被@提及（除正常广播外单独发送给被提及的用户）
+    Mentioned { message: Message },
+    /// 消息上某个表情的回应发生变化
+    ReactionUpdated {
+        message_id: MessageId,
+        emoji: String,
+        count: usize,
+        users: Vec<UserId>,
+    },
+    /// 消息已被服务器保存（只发送给提供了client_msg_id的发送连接）
+    MessageAck { client_msg_id: String, message_id: MessageId },
     /// 心跳ping
     Ping,
     /// 心跳pong
     Pong,
-    /// 错误消息
-    Error { message: String },
+    /// 错误消息，`code` 供客户端区分错误类型
+    Error { code: ErrorCode, message: String },
 }
 
 /// 客户端消息类型
@@ -62,30 +144,85 @@ pub enum WsEvent {
 #[serde(tag = "type", content = "data")]
 pub enum ClientMessage {
     /// 发送文本消息
-    SendMessage { content: String, nickname: Option<String> },
+    SendMessage {
+        content: String,
+        nickname: Option<String>,
+        /// 客户端发送时间（可选，单独保存，消息时间戳始终使用服务器时钟）
+        #[serde(default)]
+        client_timestamp: Option<chrono::DateTime<chrono::Utc>>,
+        /// 消息存活时间（秒，可选，设置后消息到期自动删除）
+        #[serde(default)]
+        ttl_secs: Option<u64>,
+        /// 内容是否为Markdown格式（服务器会清理后保存）
+        #[serde(default)]
+        markdown: bool,
+        /// 客户端生成的消息ID（可选，提供时保存成功后向发送者回复MessageAck）
+        #[serde(default)]
+        client_msg_id: Option<String>,
+    },
+    /// 发送动作消息（/me）
+    SendAction {
+        content: String,
+        #[serde(default)]
+        nickname: Option<String>,
+    },
     /// 发送房间消息
     SendRoomMessage { room_id: String, content: String },
     /// 加入房间
     JoinRoom { room_id: String },
     /// 离开房间
     LeaveRoom { room_id: String },
+    /// 获取自己所在的房间列表
+    ListMyRooms,
     /// 设置昵称
     SetNickname { nickname: String },
+    /// 将房间内的已读位置推进到指定消息
+    MarkRead { room_id: String, message_id: MessageId },
+    /// 对消息添加表情回应
+    React { message_id: MessageId, emoji: String },
+    /// 取消对消息的表情回应
+    Unreact { message_id: MessageId, emoji: String },
     /// 心跳响应
     Pong,
 }
 
+/// WebSocket连接ID，同一用户的每个设备连接各不相同
+pub type ConnectionId = uuid::Uuid;
+
 /// 连接的客户端信息
 #[derive(Debug, Clone)]
 pub struct ConnectedClient {
+    pub connection_id: ConnectionId,
     pub user_id: UserId,
+    /// 认证用户的账户ID，匿名连接为None
+    pub account_id: Option<AccountId>,
     pub nickname: Option<String>,
     pub email: Option<String>,
     pub sender: tokio::sync::mpsc::UnboundedSender<WsEvent>,
     pub last_pong: Arc<Mutex<Instant>>,
+    /// 最近一次收到客户端消息（心跳Pong除外）的时间，用于空闲超时
+    pub last_activity: Arc<Mutex<Instant>>,
     pub connected_at: Instant,
     /// 当前所在房间的广播接收器
-    pub room_receiver: Arc<Mutex<Option<tokio::sync::broadcast::Receiver<WsEvent>>>>,
+    pub room_receiver: RoomReceiverSlot,
+}
+
+/// 连接当前所在房间的广播接收器，不在房间中时为None
+pub type RoomReceiverSlot = Arc<Mutex<Option<tokio::sync::broadcast::Receiver<WsEvent>>>>;
+
+/// 在线用户的只读快照
+///
+/// 字段都是复制出来的，拿到快照后不再持有连接表的锁，可以放心地继续查询数据库等耗时操作
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientSnapshot {
+    pub user_id: UserId,
+    /// 最近建立的、设置过昵称的连接上的昵称
+    pub nickname: Option<String>,
+    pub email: Option<String>,
+    /// 该用户最早的连接建立时间
+    pub connected_at: Instant,
+    /// 该用户当前的连接数（认证用户可能有多个设备）
+    pub connections: usize,
 }
 
 /// 应用状态
@@ -93,10 +230,16 @@ pub struct ConnectedClient {
 pub struct AppState {
     /// 广播通道发送端
     pub tx: broadcast::Sender<WsEvent>,
-    /// 连接的客户端
-    pub clients: Arc<Mutex<HashMap<UserId, ConnectedClient>>>,
+    /// 连接的客户端（按连接ID索引）
+    ///
+    /// 只有连接建立、断开和修改昵称时写入，其余查找和投递都只需读锁，互不阻塞
+    pub clients: Arc<RwLock<HashMap<ConnectionId, ConnectedClient>>>,
+    /// 账户到其所有连接的二级索引，用于多设备定向投递
+    pub account_connections: Arc<Mutex<HashMap<AccountId, Vec<ConnectionId>>>>,
     /// 消息数据库
     pub message_db: Arc<MessageDatabase>,
+    /// 合并写入消息的缓冲写入器，聊天消息都通过它保存
+    pub message_writer: MessageWriter,
     /// 机器人管理器
     pub bot_manager: Arc<Mutex<BotManager>>,
     /// 消息广播发送端（用于机器人发送消息）
@@ -111,12 +254,48 @@ pub struct AppState {
     pub auth_service: AuthService,
     /// 好友管理器
     pub friend_manager: Arc<Mutex<FriendManager>>,
+    /// 昵称修改频率限制
+    pub nick_limiter: Arc<Mutex<NickChangeLimiter>>,
+    /// 认证用户最近一次设置的昵称，重连后重复设置相同昵称时不再广播
+    pub known_nicknames: Arc<Mutex<HashMap<UserId, String>>>,
+    /// 消息内容过滤器，未配置屏蔽词表时为None
+    pub message_filter: Option<Arc<MessageFilter>>,
+    /// 匿名连接的断线恢复令牌
+    pub resume_tokens: Arc<Mutex<ResumeTokens>>,
+    /// 跨实例广播中继，未配置Redis时只在本地广播
+    pub relay: ClusterRelay,
+    /// 按内容哈希保存的上传文件
+    pub uploads: UploadStore,
+    /// 服务器配置
+    pub config: Arc<ServerConfig>,
+    /// 应用状态创建的时间，用于计算运行时长
+    pub started_at: Instant,
+    /// 服务器启动的时刻，用于对外报告启动时间
+    pub started_at_utc: chrono::DateTime<chrono::Utc>,
 }
 
-impl AppState {    pub async fn new() -> anyhow::Result<Self> {
-        let (tx, _rx) = broadcast::channel(1000);
-        let (message_tx, _message_rx) = broadcast::channel(1000);
-        let message_db = MessageDatabase::new().await?;
+impl AppState {
+    pub async fn new(config: ServerConfig) -> anyhow::Result<Self> {
+        let message_db = match &config.database_url {
+            Some(url) => MessageDatabase::connect(url).await?,
+            None => MessageDatabase::new().await?,
+        };
+        // 重新写入上次运行时未能保存的消息
+        if let Err(err) = message_db.replay_dead_letters().await {
+            error!("重放死信日志失败: {:#}", err);
+        }
+        let state = Self::with_database(config, message_db).await?;
+        start_auth_cleanup_task(state.auth_service.clone(), state.config.auth_cleanup_interval);
+        start_message_retention_task(state.message_db.clone(), state.config.max_stored_messages);
+        start_cluster_relay(&state)?;
+        Ok(state)
+    }
+
+    /// 使用指定的消息数据库创建应用状态
+    pub async fn with_database(config: ServerConfig, message_db: MessageDatabase) -> anyhow::Result<Self> {
+        config.validate()?;
+        let (tx, _rx) = broadcast::channel(config.broadcast_capacity);
+        let (message_tx, _message_rx) = broadcast::channel(config.broadcast_capacity);
         
         // 创建并初始化机器人管理器
         let mut bot_manager = BotManager::new(message_tx.clone());
@@ -124,27 +303,58 @@ impl AppState {    pub async fn new() -> anyhow::Result<Self> {
         // 注册Echo机器人
         let echo_bot = EchoBot::new();
         bot_manager.register_bot(Box::new(echo_bot));
+
+        // 注册命令机器人（!time、!roll、!flip）
+        bot_manager.register_bot(Box::new(CommandBot::new()));
+
+        // 注册欢迎机器人，默认关闭，管理员可通过 /api/admin/bots/{name}/toggle 开启
+        bot_manager.register_bot(Box::new(GreeterBot::new()));
+        bot_manager.set_enabled("Greeter Bot", false);
         
         // 初始化所有机器人
         bot_manager.initialize_all().await?;
         
         // 创建房间相关组件
         let room_manager = Arc::new(RoomManager::new());
-        let room_broadcast_manager = RoomBroadcastManager::new();
+        let relay = ClusterRelay::new();
+        let room_broadcast_manager = RoomBroadcastManager::with_capacity(config.room_broadcast_capacity)
+            .with_relay(relay.clone());
         let room_message_router = Arc::new(RoomMessageRouter::new(room_broadcast_manager.clone()));
           // 创建认证服务
-        let auth_service = AuthService::new(message_db.get_pool().clone());
+        let auth_service = AuthService::new(message_db.get_pool().clone(), config.production)?
+            .with_password_policy(config.password_policy.clone());
         
         // 初始化认证数据库表
         auth_service.initialize_database().await?;
+
+        let uploads = UploadStore::new(
+            config.upload_dir.clone(),
+            config.max_upload_bytes,
+            config.upload_quota_bytes,
+            message_db.get_pool().clone(),
+        );
+        uploads.initialize().await?;
         
         // 创建好友管理器
         let friend_manager = Arc::new(Mutex::new(FriendManager::new()));
-        
+
+        // 加载屏蔽词表
+        let message_filter = match &config.word_filter_path {
+            Some(path) => {
+                let filter = MessageFilter::load(path, config.word_filter_mode)?;
+                info!("已加载 {} 个屏蔽词，处理方式: {:?}", filter.words().len(), filter.mode());
+                Some(Arc::new(filter))
+            }
+            None => None,
+        };
+
+        let message_db = Arc::new(message_db);
         Ok(Self {
             tx,
-            clients: Arc::new(Mutex::new(HashMap::new())),
-            message_db: Arc::new(message_db),
+            clients: Arc::new(RwLock::new(HashMap::new())),
+            account_connections: Arc::new(Mutex::new(HashMap::new())),
+            message_writer: MessageWriter::start(message_db.clone(), config.message_write_window),
+            message_db,
             bot_manager: Arc::new(Mutex::new(bot_manager)),
             message_tx,
             room_manager,
@@ -152,9 +362,28 @@ impl AppState {    pub async fn new() -> anyhow::Result<Self> {
             room_message_router,
             auth_service,
             friend_manager,
+            nick_limiter: Arc::new(Mutex::new(NickChangeLimiter::new(config.nick_change_cooldown))),
+            known_nicknames: Arc::new(Mutex::new(HashMap::new())),
+            message_filter,
+            resume_tokens: Arc::new(Mutex::new(ResumeTokens::new(config.resume_token_ttl))),
+            relay,
+            uploads,
+            config: Arc::new(config),
+            started_at: Instant::now(),
+            started_at_utc: chrono::Utc::now(),
         })
-    }/// 广播事件给所有客户端
+    }
+
+    /// 服务器已运行的时长
+    pub fn uptime(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    /// 广播事件给所有客户端
     pub fn broadcast(&self, event: WsEvent) {
+        // 其他实例上的客户端通过中继接收
+        self.relay.publish_global(&event);
+
         // 只有在有订阅者时才发送消息
         if self.tx.receiver_count() > 0 {
             if let Err(err) = self.tx.send(event) {
@@ -163,28 +392,334 @@ impl AppState {    pub async fn new() -> anyhow::Result<Self> {
         }
     }    /// 添加客户端连接
     pub async fn add_client(&self, client: ConnectedClient) {
+        let connection_id = client.connection_id;
         let user_id = client.user_id.clone();
         let nickname = client.nickname.clone();
-        
-        self.clients.lock().await.insert(user_id.clone(), client);
-        
-        // 广播用户加入事件
-        self.broadcast(WsEvent::UserJoined { user_id, nickname });
-        
-        info!("客户端已连接，总连接数: {}", self.clients.lock().await.len());
+        let account_id = client.account_id;
+
+        let total = {
+            let mut clients = self.clients.write().await;
+            clients.insert(connection_id, client);
+            clients.len()
+        };
+
+        // 同一账户的首个连接才广播上线事件
+        let first_connection = match account_id {
+            Some(account_id) => {
+                let mut account_connections = self.account_connections.lock().await;
+                let connections = account_connections.entry(account_id).or_default();
+                connections.push(connection_id);
+                connections.len() == 1
+            }
+            None => true,
+        };
+
+        if first_connection {
+            self.broadcast(WsEvent::UserJoined { user_id: user_id.clone(), nickname: nickname.clone() });
+            if let Err(err) = self.bot_manager.lock().await.handle_user_joined(&user_id, nickname.as_deref()).await {
+                error!("机器人处理上线事件失败: {}", err);
+            }
+        }
+
+        info!("客户端已连接，总连接数: {}", total);
     }
 
     /// 移除客户端连接
-    pub async fn remove_client(&self, user_id: &UserId) {
-        self.clients.lock().await.remove(user_id);
-        
-        // 广播用户离开事件
-        self.broadcast(WsEvent::UserLeft { user_id: user_id.clone() });
-        
-        info!("客户端已断开，总连接数: {}", self.clients.lock().await.len());
+    pub async fn remove_client(&self, connection_id: &ConnectionId) {
+        let (client, total) = {
+            let mut clients = self.clients.write().await;
+            let client = clients.remove(connection_id);
+            (client, clients.len())
+        };
+
+        // 连接可能已被心跳任务移除
+        let Some(client) = client else {
+            return;
+        };
+
+        // 同一账户的最后一个连接断开才广播离线事件
+        let last_connection = match client.account_id {
+            Some(account_id) => {
+                let mut account_connections = self.account_connections.lock().await;
+                match account_connections.get_mut(&account_id) {
+                    Some(connections) => {
+                        connections.retain(|id| id != connection_id);
+                        if connections.is_empty() {
+                            account_connections.remove(&account_id);
+                            true
+                        } else {
+                            false
+                        }
+                    }
+                    None => true,
+                }
+            }
+            None => true,
+        };
+
+        if last_connection {
+            self.broadcast(WsEvent::UserLeft { user_id: client.user_id.clone() });
+            if let Err(err) = self.bot_manager.lock().await.handle_user_left(&client.user_id).await {
+                error!("机器人处理离线事件失败: {}", err);
+            }
+        }
+
+        info!("客户端已断开，总连接数: {}", total);
+    }
+
+    /// 获取在线用户的快照，用户不在线时返回None
+    ///
+    /// 只在复制数据期间持有连接表的锁，读取在线状态和昵称时应优先使用它，而不是自己锁住 `clients`
+    pub async fn client_snapshot(&self, user_id: &UserId) -> Option<ClientSnapshot> {
+        let clients = self.clients.read().await;
+        let mut connections: Vec<&ConnectedClient> = clients.values().filter(|client| &client.user_id == user_id).collect();
+        connections.sort_by_key(|client| client.connected_at);
+        let first = connections.first()?;
+
+        Some(ClientSnapshot {
+            user_id: user_id.clone(),
+            nickname: connections.iter().rev().find_map(|client| client.nickname.clone()),
+            email: connections.iter().find_map(|client| client.email.clone()),
+            connected_at: first.connected_at,
+            connections: connections.len(),
+        })
+    }
+
+    /// 取出连接的心跳和活动时间，之后在不持有连接表锁的情况下读写，连接不存在时返回None
+    pub async fn connection_clocks(&self, connection_id: &ConnectionId) -> Option<(Arc<Mutex<Instant>>, Arc<Mutex<Instant>>)> {
+        let clients = self.clients.read().await;
+        let client = clients.get(connection_id)?;
+        Some((client.last_pong.clone(), client.last_activity.clone()))
+    }
+
+    /// 设置连接的昵称，连接不存在时返回false
+    pub async fn set_connection_nickname(&self, connection_id: &ConnectionId, nickname: String) -> bool {
+        match self.clients.write().await.get_mut(connection_id) {
+            Some(client) => {
+                client.nickname = Some(nickname);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 取出连接的房间接收器槽位，之后在不持有连接表锁的情况下读写，连接不存在时返回None
+    pub async fn room_receiver_slot(&self, connection_id: &ConnectionId) -> Option<RoomReceiverSlot> {
+        let clients = self.clients.read().await;
+        clients.get(connection_id).map(|client| client.room_receiver.clone())
+    }
+
+    /// 获取用户的所有连接ID（认证用户可能有多个设备）
+    pub async fn connections_for_user(&self, user_id: &UserId) -> Vec<ConnectionId> {
+        if let Ok(account_id) = AccountId::parse(&user_id.to_string()) {
+            if let Some(connections) = self.account_connections.lock().await.get(&account_id) {
+                return connections.clone();
+            }
+        }
+
+        // 匿名用户只有一个连接，直接查找
+        self.clients
+            .read()
+            .await
+            .values()
+            .filter(|client| &client.user_id == user_id)
+            .map(|client| client.connection_id)
+            .collect()
+    }
+
+    /// 向指定用户的所有连接定向发送事件，返回成功投递的连接数
+    pub async fn send_to_user(&self, user_id: &UserId, event: WsEvent) -> usize {
+        let connections = self.connections_for_user(user_id).await;
+        let clients = self.clients.read().await;
+
+        connections
+            .iter()
+            .filter_map(|connection_id| clients.get(connection_id))
+            .filter(|client| client.sender.send(event.clone()).is_ok())
+            .count()
+    }
+
+    /// 向多个用户的所有连接定向发送同一事件，返回成功投递的连接总数
+    pub async fn send_to_users(&self, user_ids: &[UserId], event: WsEvent) -> usize {
+        let mut delivered = 0;
+        for user_id in user_ids {
+            delivered += self.send_to_user(user_id, event.clone()).await;
+        }
+        delivered
+    }
+
+    /// 向单个连接发送事件，连接不存在或已关闭时返回false
+    pub async fn send_to_connection(&self, connection_id: &ConnectionId, event: WsEvent) -> bool {
+        self.clients
+            .read()
+            .await
+            .get(connection_id)
+            .is_some_and(|client| client.sender.send(event).is_ok())
+    }
+
+    /// 向连接发送其用户当前所在的房间列表
+    pub async fn send_room_list(&self, connection_id: &ConnectionId, user_id: &UserId) {
+        let rooms = room::user_room_responses(self, user_id).await;
+        self.send_to_connection(connection_id, WsEvent::RoomList { rooms }).await;
+    }
+
+    /// 按屏蔽词表过滤消息内容，消息被拒绝时通知发送连接并返回None
+    pub async fn filter_content(&self, connection_id: &ConnectionId, content: String) -> Option<String> {
+        match self.apply_word_filter(content) {
+            Ok(filtered) => Some(filtered),
+            Err(rejected) => {
+                info!("连接 {} 的消息包含屏蔽词，已拒绝", connection_id);
+                self.send_to_connection(connection_id, ClientError::new(ErrorCode::MsgRejected, rejected.to_string()).into_event()).await;
+                None
+            }
+        }
+    }
+
+    /// 对消息内容应用屏蔽词过滤，未配置屏蔽词表时原样返回
+    pub fn apply_word_filter(&self, content: String) -> Result<String, word_filter::ContentRejected> {
+        match &self.message_filter {
+            Some(filter) => filter.apply(&content),
+            None => Ok(content),
+        }
+    }
+
+    /// 根据在线用户的昵称解析消息中的@提及
+    pub async fn resolve_mentions(&self, message: &mut Message) {
+        let mention_names = message.extract_mentions();
+        if mention_names.is_empty() {
+            return;
+        }
+
+        let online: Vec<(UserId, String)> = {
+            let clients = self.clients.read().await;
+            clients
+                .values()
+                .filter_map(|client| client.nickname.clone().map(|nick| (client.user_id.clone(), nick)))
+                .collect()
+        };
+        message.mentions = mention::resolve_mentions(&mention_names, &online, &message.from);
+    }
+
+    /// 投递新的全局消息：广播给所有客户端、通知被提及的用户并交给机器人处理
+    pub async fn publish_message(&self, message: &Message) {
+        debug!("广播消息给所有客户端: ID={}", message.id);
+        self.broadcast(WsEvent::Message(message.clone()));
+
+        if !message.mentions.is_empty() {
+            self.send_to_users(&message.mentions, WsEvent::Mentioned { message: message.clone() }).await;
+        }
+
+        // 让机器人处理消息
+        let bot_manager = self.bot_manager.lock().await;
+        if let Err(err) = bot_manager.handle_message(message).await {
+            error!("机器人处理消息失败: {}", err);
+        }
+    }
+
+    /// 断开账户的所有在线连接，返回断开的连接数
+    pub async fn disconnect_account(&self, account_id: &AccountId, reason: &str) -> usize {
+        let connections = self
+            .account_connections
+            .lock()
+            .await
+            .get(account_id)
+            .cloned()
+            .unwrap_or_default();
+
+        for connection_id in &connections {
+            self.send_to_connection(connection_id, ClientError::new(ErrorCode::SessionTerminated, reason).into_event()).await;
+            self.remove_client(connection_id).await;
+        }
+
+        connections.len()
+    }
+
+    /// 删除已过期的消息并通知客户端，返回删除的消息数量
+    pub async fn sweep_expired_messages(&self) -> anyhow::Result<usize> {
+        let message_ids = self.message_db.delete_expired_messages().await?;
+        let count = message_ids.len();
+
+        if count > 0 {
+            info!("已清理 {} 条过期消息", count);
+            self.broadcast(WsEvent::MessagesExpired { message_ids });
+        }
+
+        Ok(count)
+    }
+
+    /// 清除用户的消息、上传文件、房间成员关系和好友关系，账户本身保留
+    ///
+    /// 按以下顺序执行：
+    /// 1. 在同一个数据库事务中删除消息（连同已读位置和回应）和上传记录，任一步失败时整体回滚
+    /// 2. 事务提交后删除已没有上传记录的文件，删除失败只记录警告
+    /// 3. 退出所有房间并移除好友关系，最后通知在线客户端
+    ///
+    /// 数据库操作失败时返回错误，数据库和内存中的状态都保持不变，可以直接重试
+    pub async fn purge_user_data(&self, user_id: &UserId) -> anyhow::Result<PurgeSummary> {
+        let mut tx = self.message_db.get_pool().begin().await.context("Failed to begin transaction")?;
+        let messages = MessageDatabase::purge_user_messages_in(&mut tx, user_id).await?;
+        let removed = UploadStore::remove_user_upload_records(&mut tx, user_id)
+            .await
+            .context("Failed to delete user uploads")?;
+        tx.commit().await.context("Failed to commit transaction")?;
+        info!("已删除用户 {} 的 {} 条消息和 {} 条上传记录", user_id, messages, removed.records);
+
+        self.uploads.remove_unreferenced_files(&removed.unreferenced).await;
+        let uploads = removed.records;
+
+        let rooms = self.room_manager.leave_all_rooms(user_id).await;
+        self.room_message_router.handle_user_leave_room(user_id.clone()).await;
+
+        let (former_friends, friend_requests) = {
+            let mut friend_manager = self.friend_manager.lock().await;
+            friend_manager.remove_all_relationships(user_id).await
+        };
+
+        // 通知在线客户端更新
+        self.broadcast(WsEvent::MessagesPurged { user_id: user_id.clone() });
+        for room_id in &rooms {
+            self.broadcast(WsEvent::UserLeftRoom {
+                room_id: room_id.to_string(),
+                user_id: user_id.clone(),
+            });
+        }
+        self.send_to_users(&former_friends, WsEvent::FriendRemoved { user_id: user_id.clone() }).await;
+
+        Ok(PurgeSummary {
+            messages,
+            uploads,
+            rooms: rooms.len(),
+            friendships: former_friends.len(),
+            friend_requests,
+        })
+    }
+
+    /// 注销账户：先清除该用户的全部个人数据（消息、上传文件、房间、好友），再删除账户并断开其在线连接
+    ///
+    /// 消息随账户一并删除而不是保留为匿名消息，与 `/api/auth/me/purge` 的行为一致
+    pub async fn delete_account(&self, account_id: &AccountId, user_id: &UserId) -> anyhow::Result<PurgeSummary> {
+        let summary = self.purge_user_data(user_id).await?;
+        self.auth_service.delete_account(account_id).await?;
+        self.disconnect_account(account_id, "账户已注销").await;
+        Ok(summary)
     }
 }
 
+/// 清除个人数据的统计结果
+#[derive(Debug, Clone, Serialize)]
+pub struct PurgeSummary {
+    /// 删除的消息数
+    pub messages: u64,
+    /// 删除的上传文件数
+    pub uploads: usize,
+    /// 退出的房间数
+    pub rooms: usize,
+    /// 移除的好友关系数
+    pub friendships: usize,
+    /// 删除的好友请求数
+    pub friend_requests: usize,
+}
+
 /// WebSocket升级处理
 pub async fn websocket_handler(
     ws: WebSocketUpgrade,
@@ -199,8 +734,31 @@ pub async fn websocket_handler(
     } else {
         auth_user
     };
-    
-    ws.on_upgrade(move |socket| handle_socket(socket, state, auth_user))
+
+    // 禁止匿名连接时，未认证的连接在握手后立即以关闭帧拒绝
+    if !state.config.permits_connection(auth_user.is_some()) {
+        info!("拒绝匿名WebSocket连接（服务器已禁用匿名访问）");
+        return ws.on_upgrade(reject_anonymous_socket);
+    }
+
+    // 认证连接的身份由令牌决定，恢复令牌只对匿名连接生效
+    let resume = auth_user.is_none().then(|| params.get("resume").cloned()).flatten();
+    // 只对声明了支持批量事件的协议版本的客户端合并消息
+    let batch_support = event_batch::BatchSupport::for_client_protocol(params.get("protocol").map(String::as_str));
+
+    ws.on_upgrade(move |socket| handle_socket(socket, state, auth_user, resume, batch_support))
+}
+
+/// 以关闭帧拒绝匿名连接
+async fn reject_anonymous_socket(mut socket: WebSocket) {
+    let frame = CloseFrame {
+        code: close_code::POLICY,
+        reason: "服务器不允许匿名连接，请先登录".into(),
+    };
+
+    if let Err(e) = socket.send(WsMessage::Close(Some(frame))).await {
+        debug!("发送关闭帧失败: {}", e);
+    }
 }
 
 /// 从query参数中提取认证用户信息
@@ -221,6 +779,7 @@ async fn extract_user_from_query(
             
             // 从数据库获取完整的用户信息
             match state.auth_service.get_account_by_id(&account_id).await {
+                Ok(account) if account.status == auth::AccountStatus::Deleted => None,
                 Ok(account) => {
                     let user_id = UserId::parse(&account.id.to_string()).ok()?;
                     
@@ -264,6 +823,7 @@ async fn extract_user_from_headers(
             
             // 从数据库获取完整的用户信息
             match state.auth_service.get_account_by_id(&account_id).await {
+                Ok(account) if account.status == auth::AccountStatus::Deleted => None,
                 Ok(account) => {
                     let user_id = UserId::parse(&account.id.to_string()).ok()?;
                     
@@ -281,50 +841,94 @@ async fn extract_user_from_headers(
 }
 
 /// 处理WebSocket连接
-async fn handle_socket(socket: WebSocket, state: AppState, auth_user: Option<auth::AuthenticatedUser>) {
-    // 使用认证用户的ID或生成新的用户ID
-    let (user_id, user_email) = if let Some(auth) = auth_user {
-        (auth.user_id, Some(auth.email))
+async fn handle_socket(
+    socket: WebSocket,
+    state: AppState,
+    auth_user: Option<auth::AuthenticatedUser>,
+    resume: Option<String>,
+    batch_support: event_batch::BatchSupport,
+) {
+    // 使用认证用户的ID、恢复令牌对应的ID或生成新的用户ID
+    let anonymous = auth_user.is_none();
+    let mut resumed = None;
+    let (user_id, user_email, account_id) = if let Some(auth) = auth_user {
+        let account_id = AccountId::parse(&auth.account_id).ok();
+        (auth.user_id, Some(auth.email), account_id)
+    } else {
+        resumed = match resume {
+            Some(token) => resume_session(&state, &token).await,
+            None => None,
+        };
+        let user_id = resumed
+            .as_ref()
+            .map(|session| session.user_id.clone())
+            .unwrap_or_else(generate_user_id);
+        (user_id, None, None)
+    };
+    let connection_id = ConnectionId::new_v4();
+
+    // 匿名连接签发新的恢复令牌
+    let resume_token = if anonymous {
+        let mut resume_tokens = state.resume_tokens.lock().await;
+        resume_tokens.enabled().then(|| resume_tokens.issue(&user_id))
     } else {
-        (generate_user_id(), None)
+        None
     };
     
-    info!("新的WebSocket连接，用户ID: {}，邮箱: {:?}", user_id, user_email);let (mut ws_sender, ws_receiver) = socket.split();
+    info!("新的WebSocket连接，用户ID: {}，邮箱: {:?}，连接ID: {}", user_id, user_email, connection_id);let (mut ws_sender, ws_receiver) = socket.split();
     let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<WsEvent>();
 
-    // 发送连接建立事件
-    let connected_event = WsEvent::Connected { user_id: user_id.clone() };
-    if let Ok(msg) = serde_json::to_string(&connected_event) {
-        if ws_sender.send(WsMessage::Text(msg.into())).await.is_err() {
-            error!("发送连接建立消息失败");
-            return;
+    // 发送连接建立事件和服务器版本信息
+    let connected_events = [
+        WsEvent::Connected { user_id: user_id.clone(), resume_token: resume_token.clone() },
+        WsEvent::ServerInfo {
+            version: SERVER_VERSION.to_string(),
+            protocol_version: PROTOCOL_VERSION.to_string(),
+        },
+    ];
+    for event in &connected_events {
+        if let Ok(msg) = serde_json::to_string(event) {
+            if ws_sender.send(WsMessage::Text(msg.into())).await.is_err() {
+                error!("发送连接建立消息失败");
+                return;
+            }
         }
     }    // 创建客户端信息（但先不添加到列表中）
     let now = Instant::now();
     let client = ConnectedClient {
+        connection_id,
         user_id: user_id.clone(),
+        account_id,
         nickname: None,
         email: user_email,
         sender: tx.clone(),
         last_pong: Arc::new(Mutex::new(now)),
+        last_activity: Arc::new(Mutex::new(now)),
         connected_at: now,
         room_receiver: Arc::new(Mutex::new(None)),
     };// 订阅广播频道
     let broadcast_rx = state.tx.subscribe();    // 启动广播消息处理任务
-    let broadcast_task = tokio::spawn(broadcast_message_task(broadcast_rx, tx.clone()));
+    let broadcast_task = tokio::spawn(broadcast_message_task(user_id.clone(), broadcast_rx, tx.clone()));
 
     // 启动房间消息监听任务
-    let room_message_task = tokio::spawn(room_message_task(user_id.clone(), state.clone(), tx.clone()));
+    let room_message_task = tokio::spawn(room_message_task(connection_id, user_id.clone(), state.clone(), tx.clone()));
 
     // 现在添加到客户端列表（此时广播频道已有订阅者）
-    state.add_client(client).await;// 启动消息发送任务
-    let send_task = tokio::spawn(message_send_task(ws_sender, rx));
+    state.add_client(client).await;
+
+    // 补发断线期间错过的消息
+    if let Some(disconnected_at) = resumed.and_then(|session| session.disconnected_at) {
+        replay_missed_messages(&state, disconnected_at, &tx).await;
+    }
+
+    // 启动消息发送任务
+    let send_task = tokio::spawn(message_send_task(ws_sender, rx, state.config.message_batch_window, batch_support));
 
     // 启动心跳任务
-    let heartbeat_task = tokio::spawn(heartbeat_task(user_id.clone(), state.clone()));
+    let heartbeat_task = tokio::spawn(heartbeat_task(connection_id, user_id.clone(), state.clone()));
 
     // 启动消息接收循环
-    let receive_task = tokio::spawn(message_receive_loop(ws_receiver, user_id.clone(), state.clone()));    // 等待任何一个任务完成
+    let receive_task = tokio::spawn(message_receive_loop(ws_receiver, connection_id, user_id.clone(), state.clone()));    // 等待任何一个任务完成
     tokio::select! {
         _ = send_task => {},
         _ = receive_task => {},
@@ -332,128 +936,242 @@ async fn handle_socket(socket: WebSocket, state: AppState, auth_user: Option<aut
         _ = room_message_task => {},
         _ = heartbeat_task => {},
     }// 清理客户端连接
-    state.remove_client(&user_id).await;
+    state.remove_client(&connection_id).await;
+
+    // 恢复令牌的有效期从断开时开始计算
+    if let Some(token) = resume_token {
+        state.resume_tokens.lock().await.mark_disconnected(&token, chrono::Utc::now());
+    }
+}
+
+/// 使用恢复令牌找回之前的匿名会话
+async fn resume_session(state: &AppState, token: &str) -> Option<resume::ResumedSession> {
+    let session = state.resume_tokens.lock().await.take(token, chrono::Utc::now());
+    match &session {
+        Some(session) => info!("通过恢复令牌恢复用户 {} 的会话", session.user_id),
+        None => debug!("恢复令牌无效或已过期，分配新的用户ID"),
+    }
+    session
+}
+
+/// 向恢复会话的连接补发断线期间的全局消息
+async fn replay_missed_messages(
+    state: &AppState,
+    since: chrono::DateTime<chrono::Utc>,
+    tx: &tokio::sync::mpsc::UnboundedSender<WsEvent>,
+) {
+    match state.message_db.get_messages_received_since(since, RESUME_REPLAY_LIMIT).await {
+        Ok(messages) => {
+            debug!("补发 {} 条断线期间的消息", messages.len());
+            for message in messages {
+                if tx.send(WsEvent::Message(message)).is_err() {
+                    break;
+                }
+            }
+        }
+        Err(e) => error!("获取断线期间的消息失败: {}", e),
+    }
 }
 
 /// 处理客户端消息
 async fn handle_client_message(
     text: &str,
+    connection_id: &ConnectionId,
     user_id: &UserId,
     state: &AppState,
 ) -> anyhow::Result<()> {
     // 消息解析
     let client_msg: ClientMessage = serde_json::from_str(text)
-        .map_err(|e| anyhow::anyhow!("解析客户端消息失败: {}", e))?;
+        .map_err(|e| ClientError::new(ErrorCode::InvalidRequest, format!("解析客户端消息失败: {}（{}）", e, CLIENT_MESSAGE_FORMAT_HINT)))?;
 
-    info!("收到来自用户 {} 的消息: {:?}", user_id, client_msg);    // 消息分发逻辑
-    match client_msg {        ClientMessage::SendMessage { content, nickname } => {
-            // 处理文本消息
-            let message = Message::new_text(user_id.clone(), content.clone(), nickname.clone());
-            info!("广播文本消息: {} 来自用户 {}", content, user_id);
-            debug!("创建的消息ID: {}", message.id);
-            
-            // 保存消息到数据库
-            if let Err(err) = state.message_db.save_message(&message).await {
-                error!("保存消息到数据库失败: {}", err);
+    info!("收到来自用户 {} 的消息: {:?}", user_id, client_msg);
+
+    if !matches!(client_msg, ClientMessage::Pong) {
+        if let Some((_, last_activity)) = state.connection_clocks(connection_id).await {
+            *last_activity.lock().await = Instant::now();
+        }
+    }
+    // 消息分发逻辑
+    match client_msg {        ClientMessage::SendMessage { content, nickname, client_timestamp, ttl_secs, markdown, client_msg_id } => {
+            if let Some(ttl_secs) = ttl_secs {
+                if !(1..=MAX_MESSAGE_TTL_SECS).contains(&ttl_secs) {
+                    return Err(ClientError::new(
+                        ErrorCode::InvalidTtl,
+                        format!("消息存活时间必须在 1 到 {} 秒之间", MAX_MESSAGE_TTL_SECS),
+                    )
+                    .into());
+                }
+            }
+            Message::validate_content(&content).map_err(|e| ClientError::content("消息内容", e))?;
+
+            let Some(content) = state.filter_content(connection_id, content).await else {
+                return Ok(());
+            };
+
+            // 处理文本消息，Markdown内容先清理为安全子集
+            let mut message = if markdown {
+                Message::new_markdown(user_id.clone(), markdown::sanitize_markdown(&content), nickname.clone())
             } else {
-                debug!("消息已保存到服务器数据库");
+                Message::new_text(user_id.clone(), content.clone(), nickname.clone())
+            };
+            // 时间戳以服务器时钟为准，客户端时间单独保存，不能用来伪造排序
+            message.client_timestamp = client_timestamp;
+            message.mark_received();
+            // 过期时间以服务器时钟为准
+            if let Some(ttl_secs) = ttl_secs {
+                message.expire_after(chrono::Duration::seconds(ttl_secs as i64));
             }
+            state.resolve_mentions(&mut message).await;
+            info!("广播文本消息: {} 来自用户 {}", content, user_id);
+            debug!("创建的消息ID: {}", message.id);
             
-            // 广播消息给所有客户端
-            debug!("广播消息给所有客户端: ID={}", message.id);
-            state.broadcast(WsEvent::Message(message.clone()));// 让机器人处理消息
-            {
-                let bot_manager = state.bot_manager.lock().await;
-                if let Err(err) = bot_manager.handle_message(&message).await {
-                    error!("机器人处理消息失败: {}", err);
+            // 保存消息到数据库，成功后向发送者确认
+            match state.message_writer.save(&message).await {
+                Ok(()) => {
+                    debug!("消息已保存到服务器数据库");
+                    if let Some(client_msg_id) = client_msg_id {
+                        state
+                            .send_to_connection(connection_id, WsEvent::MessageAck { client_msg_id, message_id: message.id.clone() })
+                            .await;
+                    }
                 }
+                Err(err) => error!("保存消息到数据库失败: {}", err),
+            }
+
+            state.publish_message(&message).await;
+        }
+        ClientMessage::SendAction { content, nickname } => {
+            Message::validate_content(&content).map_err(|e| ClientError::content("动作内容", e))?;
+            let content = content.trim().to_string();
+            let Some(content) = state.filter_content(connection_id, content).await else {
+                return Ok(());
+            };
+
+            // 优先使用服务器记录的昵称，否则使用客户端提供的昵称
+            let nickname = state.client_snapshot(user_id).await.and_then(|snapshot| snapshot.nickname).or(nickname);
+
+            let mut message = Message::new_action(user_id.clone(), content.clone(), nickname);
+            message.mark_received();
+            info!("广播动作消息: {} 来自用户 {}", content, user_id);
+
+            // 保存消息到数据库
+            if let Err(err) = state.message_writer.save(&message).await {
+                error!("保存动作消息到数据库失败: {}", err);
             }
+
+            state.broadcast(WsEvent::Message(message));
         }
         ClientMessage::SetNickname { nickname } => {
             // 验证昵称
+            validate_nickname(&nickname).map_err(|e| ClientError::new(ErrorCode::NickInvalid, format!("昵称{}", e)))?;
             let nickname = nickname.trim().to_string();
-            if nickname.is_empty() {
-                return Err(anyhow::anyhow!("昵称不能为空"));
+            // 复制出需要的连接信息后立即释放连接表的锁，再查询昵称记录和冷却时间
+            let Some((current_nick, account_id)) = ({
+                let clients = state.clients.read().await;
+                clients.get(connection_id).map(|client| (client.nickname.clone(), client.account_id))
+            }) else {
+                return Err(anyhow::anyhow!("用户 {} 不在连接列表中", user_id));
+            };
+
+            // 如果昵称没有变化，不需要广播
+            if current_nick.as_ref() == Some(&nickname) {
+                info!("用户 {} 昵称无变化: {}", user_id, nickname);
+                return Ok(());
             }
-            
-            if nickname.len() > 32 {
-                return Err(anyhow::anyhow!("昵称长度不能超过32个字符"));
+
+            // 认证用户的新连接还没有昵称，与该用户上次设置的昵称比较，
+            // 避免重连后重新发送相同昵称时产生"Alice 将昵称改为 Alice"
+            let known_nick = match account_id {
+                Some(_) => state.known_nicknames.lock().await.get(user_id).cloned(),
+                None => None,
+            };
+            if known_nick.as_ref() == Some(&nickname) {
+                info!("用户 {} 重连后昵称无变化: {}", user_id, nickname);
+                state.set_connection_nickname(connection_id, nickname).await;
+                return Ok(());
             }
-            
-            if nickname.contains(['\n', '\r', '\t']) {
-                return Err(anyhow::anyhow!("昵称不能包含非法字符"));
-            }
-              // 处理昵称设置
-            let nick_change_msg = {
-                let mut clients = state.clients.lock().await;
-                if let Some(client) = clients.get_mut(user_id) {
-                    let old_nick = client.nickname.clone().unwrap_or_else(|| "匿名用户".to_string());
-                    
-                    // 如果昵称没有变化，不需要广播
-                    if client.nickname.as_ref() == Some(&nickname) {
-                        info!("用户 {} 昵称无变化: {}", user_id, nickname);
-                        return Ok(());
-                    }
-                    
-                    client.nickname = Some(nickname.clone());
-                    
-                    info!("用户 {} 昵称变更: {} -> {}", user_id, old_nick, nickname);
-                    
-                    // 创建昵称变更消息
-                    Some(Message::new_nick_change(
-                        user_id.clone(),
-                        old_nick,
-                        nickname.clone(),
-                        Some(nickname),
-                    ))
-                } else {
-                    None
-                }
-            }; // 这里释放锁
-            
-            if let Some(nick_change_msg) = nick_change_msg {
-                // 保存昵称变更消息到数据库
-                if let Err(err) = state.message_db.save_message(&nick_change_msg).await {
-                    error!("保存昵称变更消息到数据库失败: {}", err);
+
+            // 冷却时间内拒绝修改，避免刷屏；第一次设置昵称不算修改，不计入冷却
+            let previous_nick = current_nick.or(known_nick);
+            if previous_nick.is_some() {
+                let cooldown = state.nick_limiter.lock().await.try_change(user_id, Instant::now());
+                if let Err(remaining) = cooldown {
+                    warn!("用户 {} 修改昵称过于频繁", user_id);
+                    let message = format!("修改昵称过于频繁，请在 {} 秒后重试", remaining.as_secs_f64().ceil());
+                    state.send_to_connection(connection_id, ClientError::new(ErrorCode::NickCooldown, message).into_event()).await;
+                    return Ok(());
                 }
-                
-                state.broadcast(WsEvent::Message(nick_change_msg));
-            } else {
+            }
+
+            if !state.set_connection_nickname(connection_id, nickname.clone()).await {
                 return Err(anyhow::anyhow!("用户 {} 不在连接列表中", user_id));
             }
+            if account_id.is_some() {
+                state.known_nicknames.lock().await.insert(user_id.clone(), nickname.clone());
+            }
+
+            let old_nick = previous_nick.clone().unwrap_or_else(|| "匿名用户".to_string());
+            info!("用户 {} 昵称变更: {} -> {}", user_id, old_nick, nickname);
+
+            // 创建昵称变更消息
+            let mut nick_change_msg = Message::new_nick_change(
+                user_id.clone(),
+                old_nick,
+                nickname.clone(),
+                Some(nickname.clone()),
+            );
+            nick_change_msg.mark_received();
+
+            // 保存昵称变更消息到数据库
+            if let Err(err) = state.message_writer.save(&nick_change_msg).await {
+                error!("保存昵称变更消息到数据库失败: {}", err);
+            }
+
+            state.broadcast(WsEvent::Message(nick_change_msg));
+            // 结构化事件便于客户端更新昵称缓存，无需解析消息文本
+            state.broadcast(WsEvent::NicknameChanged {
+                user_id: user_id.clone(),
+                old_nick: previous_nick,
+                new_nick: nickname,
+            });
         }        ClientMessage::Pong => {
             // 处理心跳响应
             info!("收到用户 {} 的心跳响应", user_id);            // 更新最后心跳时间
-            {
-                let clients = state.clients.lock().await;
-                if let Some(client) = clients.get(user_id) {
-                    *client.last_pong.lock().await = Instant::now();
-                }
+            if let Some((last_pong, _)) = state.connection_clocks(connection_id).await {
+                *last_pong.lock().await = Instant::now();
             }
         }
         ClientMessage::SendRoomMessage { room_id, content } => {
             // 处理房间消息
             let room_id_parsed = match room::RoomId::parse(&room_id) {
                 Ok(id) => id,
-                Err(_) => return Err(anyhow::anyhow!("无效的房间ID: {}", room_id)),
+                Err(_) => return Err(ClientError::invalid_room_id(&room_id).into()),
             };
 
             // 检查用户是否在房间中
             if !state.room_manager.is_user_in_room(room_id_parsed, user_id).await {
-                return Err(anyhow::anyhow!("用户不在房间 {} 中", room_id));
+                return Err(ClientError::not_in_room(&room_id).into());
             }
+            Message::validate_content(&content).map_err(|e| ClientError::content("消息内容", e))?;
+
+            let Some(content) = state.filter_content(connection_id, content).await else {
+                return Ok(());
+            };
 
             // 创建房间消息
             let mut message = Message::new_text(user_id.clone(), content.clone(), None);
-            message.additional_data = Some(serde_json::json!({
-                "room_id": room_id
-            }));
+            message.set_room_id(room_id.clone());
+            message.mark_received();
 
             info!("广播房间消息: {} 来自用户 {} 到房间 {}", content, user_id, room_id);
 
-            // 保存消息到数据库
-            if let Err(err) = state.message_db.save_message(&message).await {
+            // 保存消息到数据库，发送者视为已读自己的消息；不保存历史的房间只转发
+            if !state.room_manager.is_room_persistent(room_id_parsed).await {
+                debug!("房间 {} 不保存消息历史", room_id);
+            } else if let Err(err) = state.message_writer.save(&message).await {
                 error!("保存房间消息到数据库失败: {}", err);
+            } else if let Err(err) = state.message_db.mark_room_read(&room_id, user_id, &message).await {
+                warn!("更新发送者已读位置失败: {}", err);
             }
 
             // 通过房间消息路由器广播
@@ -465,20 +1183,17 @@ async fn handle_client_message(
             // 处理加入房间
             let room_id_parsed = match room::RoomId::parse(&room_id) {
                 Ok(id) => id,
-                Err(_) => return Err(anyhow::anyhow!("无效的房间ID: {}", room_id)),
+                Err(_) => return Err(ClientError::invalid_room_id(&room_id).into()),
             };
 
             // 尝试加入房间
             match state.room_manager.join_room(room_id_parsed, user_id.clone()).await {
-                Ok(_) => {
+                Ok(room) => {
                     // 在房间消息路由器中注册用户并获取接收器
                     if let Some(room_receiver) = state.room_message_router.handle_user_enter_room(user_id.clone(), room_id_parsed).await {
                         // 更新客户端的房间接收器
-                        {
-                            let clients = state.clients.lock().await;
-                            if let Some(client) = clients.get(user_id) {
-                                *client.room_receiver.lock().await = Some(room_receiver);
-                            }
+                        if let Some(slot) = state.room_receiver_slot(connection_id).await {
+                            *slot.lock().await = Some(room_receiver);
                         }
 
                         info!("用户 {} 通过WebSocket加入房间: {}", user_id, room_id);
@@ -489,21 +1204,25 @@ async fn handle_client_message(
                             user_id: user_id.clone() 
                         });
                     }
+                    let room = room::RoomResponse::from_room(&room, user_id);
+                    state.send_to_connection(connection_id, WsEvent::RoomJoined { room }).await;
+                    state.send_room_list(connection_id, user_id).await;
                 }
                 Err(room::RoomError::UserAlreadyInRoom) => {
-                    // 用户已经在房间中，仍然需要设置接收器
+                    // 用户已经在房间中，仍然需要设置接收器（不新增成员，无需检查人数上限）
                     if let Some(room_receiver) = state.room_message_router.handle_user_enter_room(user_id.clone(), room_id_parsed).await {
-                        {
-                            let clients = state.clients.lock().await;
-                            if let Some(client) = clients.get(user_id) {
-                                *client.room_receiver.lock().await = Some(room_receiver);
-                            }
+                        if let Some(slot) = state.room_receiver_slot(connection_id).await {
+                            *slot.lock().await = Some(room_receiver);
                         }
                         info!("用户 {} 重新连接到房间: {}", user_id, room_id);
                     }
+                    if let Ok(room) = state.room_manager.get_room(room_id_parsed).await {
+                        let room = room::RoomResponse::from_room(&room, user_id);
+                        state.send_to_connection(connection_id, WsEvent::RoomJoined { room }).await;
+                    }
                 }
                 Err(e) => {
-                    return Err(anyhow::anyhow!("加入房间失败: {}", e));
+                    return Err(ClientError::room("加入房间失败", e));
                 }
             }
         }
@@ -511,7 +1230,7 @@ async fn handle_client_message(
             // 处理离开房间
             let room_id_parsed = match room::RoomId::parse(&room_id) {
                 Ok(id) => id,
-                Err(_) => return Err(anyhow::anyhow!("无效的房间ID: {}", room_id)),
+                Err(_) => return Err(ClientError::invalid_room_id(&room_id).into()),
             };
 
             // 尝试离开房间
@@ -521,11 +1240,8 @@ async fn handle_client_message(
                     state.room_message_router.handle_user_leave_room(user_id.clone()).await;
 
                     // 清除客户端的房间接收器
-                    {
-                        let clients = state.clients.lock().await;
-                        if let Some(client) = clients.get(user_id) {
-                            *client.room_receiver.lock().await = None;
-                        }
+                    if let Some(slot) = state.room_receiver_slot(connection_id).await {
+                        *slot.lock().await = None;
                     }
 
                     info!("用户 {} 通过WebSocket离开房间: {}", user_id, room_id);
@@ -535,12 +1251,110 @@ async fn handle_client_message(
                         room_id: room_id.clone(), 
                         user_id: user_id.clone() 
                     });
+                    state.send_to_connection(connection_id, WsEvent::RoomLeft { room_id: room_id.clone() }).await;
+                    state.send_room_list(connection_id, user_id).await;
                 }
                 Err(e) => {
-                    return Err(anyhow::anyhow!("离开房间失败: {}", e));
+                    return Err(ClientError::room("离开房间失败", e));
                 }
             }
         }
+        ClientMessage::ListMyRooms => {
+            state.send_room_list(connection_id, user_id).await;
+        }
+        ClientMessage::MarkRead { room_id, message_id } => {
+            let room_id_parsed = room::RoomId::parse(&room_id)
+                .map_err(|_| ClientError::invalid_room_id(&room_id))?;
+            if !state.room_manager.is_user_in_room(room_id_parsed, user_id).await {
+                return Err(ClientError::not_in_room(&room_id).into());
+            }
+
+            if state.message_db.set_last_read(user_id, &room_id, &message_id).await? {
+                debug!("用户 {} 在房间 {} 中已读到消息 {}", user_id, room_id, message_id);
+            }
+        }
+        ClientMessage::React { message_id, emoji } => {
+            update_reaction(state, user_id, &message_id, &emoji, true).await?;
+        }
+        ClientMessage::Unreact { message_id, emoji } => {
+            update_reaction(state, user_id, &message_id, &emoji, false).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// 添加或取消表情回应，回应有变化时把该表情的最新统计发送给能看到消息的用户
+async fn update_reaction(
+    state: &AppState,
+    user_id: &UserId,
+    message_id: &MessageId,
+    emoji: &str,
+    add: bool,
+) -> anyhow::Result<()> {
+    let emoji = emoji.trim();
+    if emoji.is_empty() || emoji.chars().count() > MAX_REACTION_EMOJI_CHARS {
+        return Err(ClientError::new(
+            ErrorCode::InvalidReaction,
+            format!("表情回应长度必须在 1 到 {} 个字符之间", MAX_REACTION_EMOJI_CHARS),
+        )
+        .into());
+    }
+    if emoji.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return Err(ClientError::new(ErrorCode::InvalidReaction, "表情回应不能包含空白或控制字符").into());
+    }
+
+    let message = state
+        .message_db
+        .get_message(message_id)
+        .await?
+        .ok_or_else(|| ClientError::new(ErrorCode::MessageNotFound, format!("消息 {} 不存在", message_id)))?;
+
+    // 房间消息只有房间成员可以回应
+    let room_id = match message.get_room_id() {
+        Some(room_id) => {
+            let room_id = room::RoomId::parse(room_id)
+                .map_err(|_| ClientError::invalid_room_id(room_id))?;
+            if !state.room_manager.is_user_in_room(room_id, user_id).await {
+                return Err(ClientError::not_in_room(room_id).into());
+            }
+            Some(room_id)
+        }
+        None => None,
+    };
+
+    let changed = if add {
+        state.message_db.add_reaction(message_id, user_id, emoji).await?
+    } else {
+        state.message_db.remove_reaction(message_id, user_id, emoji).await?
+    };
+    if !changed {
+        // 重复添加或取消不存在的回应，无需通知
+        return Ok(());
+    }
+
+    let users = state
+        .message_db
+        .get_reactions(message_id)
+        .await?
+        .into_iter()
+        .find(|reaction| reaction.emoji == emoji)
+        .map(|reaction| reaction.users)
+        .unwrap_or_default();
+    let event = WsEvent::ReactionUpdated {
+        message_id: message_id.clone(),
+        emoji: emoji.to_string(),
+        count: users.len(),
+        users,
+    };
+
+    match room_id {
+        Some(room_id) => {
+            if let Err(e) = state.room_broadcast_manager.broadcast_to_room(room_id, event).await {
+                debug!("房间 {} 中没有在线用户接收回应更新: {}", room_id, e);
+            }
+        }
+        None => state.broadcast(event),
     }
 
     Ok(())
@@ -549,15 +1363,20 @@ async fn handle_client_message(
 /// 异步消息接收循环
 async fn message_receive_loop(
     mut ws_receiver: futures_util::stream::SplitStream<WebSocket>,
+    connection_id: ConnectionId,
     user_id: UserId,
     state: AppState,
 ) {
+    let mut limiter = MessageRateLimiter::new(
+        state.config.message_rate_per_sec,
+        state.config.message_burst,
+        Instant::now(),
+    );
+
     while let Some(msg) = ws_receiver.next().await {
         match msg {
             Ok(WsMessage::Text(text)) => {
-                if let Err(err) = handle_client_message(&text, &user_id, &state).await {
-                    error!("处理客户端消息失败: {}", err);
-                }
+                handle_incoming_text(&text, &mut limiter, Instant::now(), &connection_id, &user_id, &state).await;
             }
             Ok(WsMessage::Close(_)) => {
                 info!("客户端主动关闭连接: {}", user_id);
@@ -583,29 +1402,83 @@ async fn message_receive_loop(
     }
 }
 
-/// 异步消息发送任务
+/// 处理连接收到的一条文本消息，超出发送频率的消息直接拒绝
+async fn handle_incoming_text(
+    text: &str,
+    limiter: &mut MessageRateLimiter,
+    now: Instant,
+    connection_id: &ConnectionId,
+    user_id: &UserId,
+    state: &AppState,
+) {
+    if !limiter.try_acquire(now) {
+        warn!("连接 {} 发送消息过于频繁，已拒绝", connection_id);
+        state
+            .send_to_connection(connection_id, ClientError::new(ErrorCode::RateLimited, "发送过于频繁").into_event())
+            .await;
+        return;
+    }
+
+    // 处理失败时把错误返回给发送连接
+    if let Err(err) = handle_client_message(text, connection_id, user_id, state).await {
+        match err.downcast_ref::<ClientError>() {
+            Some(client_error) => warn!("拒绝用户 {} 的请求: {}", user_id, client_error),
+            None => error!("处理客户端消息失败: {:#}", err),
+        }
+        state.send_to_connection(connection_id, client_error::error_event(err)).await;
+    }
+}
+
+/// 异步消息发送任务
+///
+/// `batch_window` 不为零时，窗口内连续到达的消息按连接支持的批量事件合并为一帧发送
 async fn message_send_task(
     mut ws_sender: futures_util::stream::SplitSink<WebSocket, WsMessage>,
     mut rx: tokio::sync::mpsc::UnboundedReceiver<WsEvent>,
+    batch_window: Duration,
+    batch_support: event_batch::BatchSupport,
 ) {
-    while let Some(event) = rx.recv().await {
-        if let Ok(msg) = serde_json::to_string(&event) {
-            if ws_sender.send(WsMessage::Text(msg.into())).await.is_err() {
-                error!("发送消息失败，连接可能已断开");
-                break;
+    loop {
+        let events = event_batch::next_events(&mut rx, batch_window, batch_support).await;
+        if events.is_empty() {
+            break;
+        }
+        for event in events {
+            if let Ok(msg) = serde_json::to_string(&event) {
+                if ws_sender.send(WsMessage::Text(msg.into())).await.is_err() {
+                    error!("发送消息失败，连接可能已断开");
+                    return;
+                }
+            } else {
+                error!("序列化消息失败");
             }
-        } else {
-            error!("序列化消息失败");
         }
     }
 }
 
 /// 广播消息处理任务
+///
+/// 客户端处理过慢、落后于广播频道容量时会丢失部分消息，此时通知客户端丢失的数量并继续转发，
+/// 客户端可据此重新获取历史记录
 async fn broadcast_message_task(
+    user_id: UserId,
     mut broadcast_rx: broadcast::Receiver<WsEvent>,
     tx: tokio::sync::mpsc::UnboundedSender<WsEvent>,
 ) {
-    while let Ok(event) = broadcast_rx.recv().await {
+    loop {
+        let event = match broadcast_rx.recv().await {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                warn!("用户 {} 的广播消息滞后，丢失了 {} 条消息", user_id, missed);
+                ClientError::new(
+                    ErrorCode::SlowConsumer,
+                    format!("连接过慢，错过了 {} 条消息，请重新获取消息历史", missed),
+                )
+                .into_event()
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+
         if tx.send(event).is_err() {
             // 客户端通道已关闭，退出任务
             break;
@@ -614,74 +1487,79 @@ async fn broadcast_message_task(
 }
 
 /// 心跳任务
-async fn heartbeat_task(user_id: UserId, state: AppState) {
-    let mut interval = time::interval(Duration::from_secs(30)); // 30秒心跳间隔
-    let timeout_duration = Duration::from_secs(90); // 90秒超时
+async fn heartbeat_task(connection_id: ConnectionId, user_id: UserId, state: AppState) {
+    let mut interval = time::interval(state.config.heartbeat_interval);
+    let timeout_duration = state.config.heartbeat_timeout;
+    // 本轮空闲是否已经警告过，有新活动后重新计算
+    let mut idle_warned = false;
     
     loop {
         interval.tick().await;
-          // 检查客户端是否仍然连接
-        let client_exists = {
-            let clients = state.clients.lock().await;
-            clients.contains_key(&user_id)
-        };
-        
-        if !client_exists {
+        // 检查客户端是否仍然连接
+        let Some((last_pong, last_activity)) = state.connection_clocks(&connection_id).await else {
             info!("用户 {} 已断开连接，停止心跳任务", user_id);
             break;
-        }
-        
-        // 检查是否超时
-        let should_disconnect = {
-            let clients = state.clients.lock().await;
-            if let Some(client) = clients.get(&user_id) {
-                let last_pong = *client.last_pong.lock().await;
-                let elapsed = last_pong.elapsed();
-                
-                if elapsed > timeout_duration {
-                    warn!("用户 {} 心跳超时 ({}s)，将断开连接", user_id, elapsed.as_secs());
-                    true
-                } else {
-                    false
-                }
-            } else {
-                true // 客户端不存在，退出
-            }
         };
-          if should_disconnect {
+
+        // 检查是否超时
+        let elapsed = last_pong.lock().await.elapsed();
+        if elapsed > timeout_duration {
+            warn!("用户 {} 心跳超时 ({}s)，将断开连接", user_id, elapsed.as_secs());
             // 移除超时的客户端
-            state.remove_client(&user_id).await;
+            state.remove_client(&connection_id).await;
             break;
         }
-          // 发送心跳Ping
-        {
-            let clients = state.clients.lock().await;
-            if let Some(client) = clients.get(&user_id) {
-                if let Err(err) = client.sender.send(WsEvent::Ping) {
-                    warn!("发送心跳到用户 {} 失败: {}", user_id, err);
+
+        // 检查空闲超时
+        if let Some(idle_timeout) = state.config.idle_timeout {
+            let idle_for = last_activity.lock().await.elapsed();
+            match IdleState::of(idle_for, idle_timeout) {
+                IdleState::Expired => {
+                    warn!("用户 {} 空闲超时 ({}s)，将断开连接", user_id, idle_for.as_secs());
+                    let event = ClientError::new(ErrorCode::IdleTimeout, "长时间没有活动，连接已断开").into_event();
+                    state.send_to_connection(&connection_id, event).await;
+                    state.remove_client(&connection_id).await;
                     break;
                 }
+                IdleState::Warning(remaining) if !idle_warned => {
+                    idle_warned = true;
+                    let message = format!("长时间没有活动，连接将在 {} 秒后断开", remaining.as_secs().max(1));
+                    state.send_to_connection(&connection_id, ClientError::new(ErrorCode::IdleWarning, message).into_event()).await;
+                }
+                IdleState::Warning(_) => {}
+                IdleState::Active => idle_warned = false,
             }
         }
+          // 发送心跳Ping
+        if !state.send_to_connection(&connection_id, WsEvent::Ping).await {
+            warn!("发送心跳到用户 {} 失败，连接已关闭", user_id);
+            break;
+        }
         
         info!("发送心跳到用户 {}", user_id);
     }
 }
 
-/// 健康检查端点
-async fn health_check() -> impl IntoResponse {
-    (StatusCode::OK, "RustChat Server is running")
-}
-
 /// 创建应用路由
-async fn create_app() -> anyhow::Result<Router> {
-    let state = AppState::new().await?;
+async fn create_app(config: ServerConfig) -> anyhow::Result<Router> {
+    let cors = cors::cors_layer(config.cors_origins.as_deref())?;
+    match &config.cors_origins {
+        Some(origins) => info!("允许跨域访问的来源: {:?}", origins),
+        None if config.production => warn!("未设置 RUSTCHAT_CORS_ORIGINS，允许任意来源跨域访问，生产环境请配置允许的来源"),
+        None => info!("未设置 RUSTCHAT_CORS_ORIGINS，允许任意来源跨域访问（仅适合开发环境）"),
+    }
+    let state = AppState::new(config).await?;
 
     // 启动机器人消息监听任务
     start_bot_message_listener(state.clone()).await;
+    // 启动过期消息清理任务
+    start_expired_message_sweeper(state.clone());
     
     Ok(Router::new()
-        .route("/health", get(health_check))
+        .merge(health::create_health_routes())
+        .merge(metrics::create_metrics_routes())
+        .merge(bots::create_bot_routes())
+        .merge(openapi::create_openapi_routes())
         .route("/ws", get(websocket_handler))
         // 需要认证的房间路由
         .merge(room::create_protected_room_routes()
@@ -695,22 +1573,82 @@ async fn create_app() -> anyhow::Result<Router> {
                 state.clone(),
                 auth::middleware::optional_auth_middleware
             )))
+        // 全局消息历史
+        .merge(history::create_history_routes()
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                auth::middleware::optional_auth_middleware
+            )))
+        // 用户公开资料
+        .merge(profile::create_profile_routes()
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                auth::middleware::optional_auth_middleware
+            )))
+        // 通过REST发送全局消息（供机器人和外部集成使用）
+        .merge(history::create_protected_history_routes()
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                auth::middleware::auth_middleware
+            )))
+        // 上传文件：上传需要认证，按哈希读取不需要
+        .merge(upload::create_upload_routes(state.config.max_upload_bytes)
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                auth::middleware::auth_middleware
+            )))
+        .merge(upload::create_public_upload_routes())
         .merge(create_auth_routes()) // 添加认证API路由
-        .nest("/api/friends", create_friend_routes()) // 添加好友API路由
-        .layer(CorsLayer::permissive())
+        .merge(create_protected_auth_routes()
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                auth::middleware::auth_middleware
+            )))
+        // 管理员路由：先认证，再检查管理员身份
+        .merge(create_admin_routes()
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                auth::middleware::admin_middleware
+            ))
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                auth::middleware::auth_middleware
+            )))
+        // 好友路由：操作者身份来自访问令牌
+        .nest("/api/friends", create_friend_routes()
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                auth::middleware::auth_middleware
+            )))
+        .layer(cors)
         .layer(TraceLayer::new_for_http())
         .with_state(state))
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    // 初始化日志
-    tracing_subscriber::fmt()
-        .with_target(false)
-        .compact()
-        .init();
+    // 初始化日志，RUSTCHAT_LOG_FORMAT=json 时输出JSON Lines
+    let log_format = LogFormat::from_env();
+    match log_format.clone().unwrap_or_default() {
+        LogFormat::Text => tracing_subscriber::fmt()
+            .with_target(false)
+            .compact()
+            .init(),
+        LogFormat::Json => tracing_subscriber::fmt()
+            .with_target(false)
+            .json()
+            .init(),
+    }
+    if let Err(value) = log_format {
+        warn!("环境变量 RUSTCHAT_LOG_FORMAT 的值无效: {}，使用文本格式", value);
+    }
+
+    let config = ServerConfig::from_env();
+    if !config.allow_anonymous {
+        info!("匿名访问已禁用，所有连接都需要认证");
+    }
 
-    let app = create_app().await?;
+    let app = create_app(config).await?;
     
     let listener = tokio::net::TcpListener::bind("127.0.0.1:8080")
         .await
@@ -733,11 +1671,12 @@ async fn start_bot_message_listener(state: AppState) {
     tokio::spawn(async move {
         info!("机器人消息监听器已启动");
         
-        while let Ok(bot_message) = message_rx.recv().await {
+        while let Ok(mut bot_message) = message_rx.recv().await {
+            bot_message.mark_received();
             info!("收到机器人消息: {:?}", bot_message);
             
             // 保存机器人消息到数据库
-            if let Err(err) = state.message_db.save_message(&bot_message).await {
+            if let Err(err) = state.message_writer.save(&bot_message).await {
                 error!("保存机器人消息到数据库失败: {}", err);
             }
             
@@ -749,8 +1688,86 @@ async fn start_bot_message_listener(state: AppState) {
     });
 }
 
+/// 配置了Redis时启动跨实例广播中继
+fn start_cluster_relay(state: &AppState) -> anyhow::Result<()> {
+    let Some(redis_url) = &state.config.redis_url else {
+        return Ok(());
+    };
+
+    #[cfg(feature = "redis")]
+    {
+        cluster::start_redis_bridge(redis_url, state.clone())?;
+        info!("已启用Redis跨实例广播，实例ID: {}", state.relay.instance_id());
+    }
+    #[cfg(not(feature = "redis"))]
+    {
+        let _ = redis_url;
+        warn!("已设置 REDIS_URL，但服务器编译时未启用 redis 功能，广播只在本实例内生效");
+    }
+
+    Ok(())
+}
+
+/// 启动过期消息清理任务
+fn start_expired_message_sweeper(state: AppState) {
+    tokio::spawn(async move {
+        let mut interval = time::interval(EXPIRED_MESSAGE_SWEEP_INTERVAL);
+
+        loop {
+            interval.tick().await;
+            if let Err(err) = state.sweep_expired_messages().await {
+                error!("清理过期消息失败: {}", err);
+            }
+        }
+    });
+}
+
+/// 启动旧消息清理任务：启动时立即清理一次，之后每天清理一次，未设置保留条数时不启动
+fn start_message_retention_task(message_db: Arc<MessageDatabase>, max_stored_messages: Option<usize>) {
+    let Some(keep_count) = max_stored_messages else {
+        return;
+    };
+    let keep_count = i64::try_from(keep_count).unwrap_or(i64::MAX);
+
+    tokio::spawn(async move {
+        let mut interval = time::interval(MESSAGE_RETENTION_INTERVAL);
+
+        loop {
+            interval.tick().await;
+            match message_db.cleanup_old_messages(keep_count).await {
+                Ok(removed) => info!("已清理 {} 条旧消息（全局和每个房间各保留最近 {} 条）", removed, keep_count),
+                Err(err) => error!("清理旧消息失败: {}", err),
+            }
+        }
+    });
+}
+
+/// 启动过期会话和验证码的定期清理任务，间隔为零时不启动
+fn start_auth_cleanup_task(auth_service: AuthService, period: Duration) {
+    if period.is_zero() {
+        info!("过期会话清理任务已禁用");
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut interval = time::interval(period);
+
+        loop {
+            interval.tick().await;
+            match auth_service.cleanup_expired().await {
+                Ok(cleanup) => info!(
+                    "已清理 {} 个过期会话和 {} 个过期或已使用的验证码",
+                    cleanup.sessions, cleanup.verification_codes
+                ),
+                Err(err) => error!("清理过期会话和验证码失败: {}", err),
+            }
+        }
+    });
+}
+
 /// 房间消息监听任务
 async fn room_message_task(
+    connection_id: ConnectionId,
     user_id: UserId,
     state: AppState,
     tx: tokio::sync::mpsc::UnboundedSender<WsEvent>,
@@ -761,45 +1778,34 @@ async fn room_message_task(
         interval.tick().await;
         
         // 检查用户是否有房间接收器
-        let room_receiver = {
-            let clients = state.clients.lock().await;
-            if let Some(client) = clients.get(&user_id) {
-                let mut room_receiver_guard = client.room_receiver.lock().await;
-                room_receiver_guard.take()
-            } else {
-                // 用户已断开连接
-                break;
-            }
+        let Some(slot) = state.room_receiver_slot(&connection_id).await else {
+            // 用户已断开连接
+            break;
         };
-        
-        if let Some(mut receiver) = room_receiver {
-            // 尝试接收房间消息
+
+        // 每次转发通道中已有的全部消息，转发期间持有锁，加入或离开房间时替换的接收器不会被覆盖
+        let mut room_receiver = slot.lock().await;
+        while let Some(receiver) = room_receiver.as_mut() {
             match receiver.try_recv() {
                 Ok(event) => {
                     // 转发房间消息到WebSocket
-                    if let Err(_) = tx.send(event) {
+                    if tx.send(event).is_err() {
                         error!("转发房间消息失败，用户可能已断开连接: {}", user_id);
-                        break;
+                        return;
                     }
                 }
                 Err(tokio::sync::broadcast::error::TryRecvError::Empty) => {
-                    // 没有消息，继续监听
+                    // 没有更多消息，等待下一次检查
+                    break;
                 }
                 Err(tokio::sync::broadcast::error::TryRecvError::Closed) => {
                     // 房间通道已关闭
                     debug!("房间消息通道已关闭，用户: {}", user_id);
+                    *room_receiver = None;
                 }
-                Err(tokio::sync::broadcast::error::TryRecvError::Lagged(_)) => {
-                    // 消息滞后，继续监听
-                    warn!("房间消息滞后，用户: {}", user_id);
-                }
-            }
-            
-            // 将接收器放回
-            {
-                let clients = state.clients.lock().await;
-                if let Some(client) = clients.get(&user_id) {
-                    *client.room_receiver.lock().await = Some(receiver);
+                Err(tokio::sync::broadcast::error::TryRecvError::Lagged(skipped)) => {
+                    // 消息滞后，跳过的消息已丢失，继续转发后面的消息
+                    warn!("房间消息滞后，用户: {}，跳过 {} 条", user_id, skipped);
                 }
             }
         }
@@ -807,3 +1813,2806 @@ async fn room_message_task(
     
     debug!("房间消息监听任务结束，用户: {}", user_id);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 创建使用内存数据库的测试状态
+    async fn test_state() -> AppState {
+        test_state_with_config(ServerConfig::default()).await
+    }
+
+    async fn test_state_with_config(config: ServerConfig) -> AppState {
+        let message_db = MessageDatabase::new_in_memory().await.unwrap();
+        AppState::with_database(config, message_db)
+            .await
+            .unwrap()
+    }
+
+    /// 创建测试连接，返回连接信息和事件接收端
+    fn test_client(
+        user_id: &UserId,
+        account_id: Option<AccountId>,
+    ) -> (ConnectedClient, tokio::sync::mpsc::UnboundedReceiver<WsEvent>) {
+        let (sender, rx) = tokio::sync::mpsc::unbounded_channel();
+        let now = Instant::now();
+        let client = ConnectedClient {
+            connection_id: ConnectionId::new_v4(),
+            user_id: user_id.clone(),
+            account_id,
+            nickname: None,
+            email: None,
+            sender,
+            last_pong: Arc::new(Mutex::new(now)),
+            last_activity: Arc::new(Mutex::new(now)),
+            connected_at: now,
+            room_receiver: Arc::new(Mutex::new(None)),
+        };
+        (client, rx)
+    }
+
+    #[tokio::test]
+    async fn test_received_at_uses_server_clock() {
+        let state = test_state().await;
+        let user_id = UserId::new();
+        let (client, _rx) = test_client(&user_id, None);
+        let connection_id = client.connection_id;
+        state.add_client(client).await;
+
+        // 客户端时钟严重偏差
+        let client_timestamp = chrono::Utc::now() - chrono::Duration::days(3);
+        let text = serde_json::json!({
+            "type": "SendMessage",
+            "data": {
+                "content": "hello",
+                "nickname": null,
+                "client_timestamp": client_timestamp,
+            }
+        })
+        .to_string();
+
+        let before = chrono::Utc::now();
+        handle_client_message(&text, &connection_id, &user_id, &state)
+            .await
+            .unwrap();
+        let after = chrono::Utc::now();
+
+        let saved = state.message_db.get_recent_messages(1).await.unwrap();
+        let message = &saved[0];
+        assert_eq!(message.client_timestamp, Some(client_timestamp));
+        assert!(before <= message.timestamp && message.timestamp <= after);
+        let received_at = message.received_at.expect("应记录服务器接收时间");
+        assert!(before <= received_at && received_at <= after);
+        assert!(message.receipt_delta().unwrap() >= chrono::Duration::days(3));
+    }
+
+    #[tokio::test]
+    async fn test_resume_token_restores_user_and_replays_missed_messages() {
+        let state = test_state().await;
+        let user_id = UserId::new();
+        let token = state.resume_tokens.lock().await.issue(&user_id);
+        let disconnected_at = chrono::Utc::now() - chrono::Duration::seconds(10);
+
+        let mut before = Message::new_text(UserId::new(), "before".to_string(), None);
+        before.received_at = Some(disconnected_at - chrono::Duration::seconds(1));
+        let mut missed = Message::new_text(UserId::new(), "missed".to_string(), None);
+        missed.received_at = Some(disconnected_at + chrono::Duration::seconds(1));
+        state.message_db.save_message(&before).await.unwrap();
+        state.message_db.save_message(&missed).await.unwrap();
+
+        state.resume_tokens.lock().await.mark_disconnected(&token, disconnected_at);
+        let session = resume_session(&state, &token).await.expect("令牌应可用于恢复会话");
+        assert_eq!(session.user_id, user_id);
+        assert!(resume_session(&state, &token).await.is_none(), "令牌只能使用一次");
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        replay_missed_messages(&state, session.disconnected_at.unwrap(), &tx).await;
+        match rx.try_recv() {
+            Ok(WsEvent::Message(message)) => assert_eq!(message.id, missed.id),
+            other => panic!("应补发断线期间的消息，实际: {:?}", other),
+        }
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_message_flood_is_rate_limited() {
+        let state = test_state().await;
+        let user_id = UserId::new();
+        let (client, mut rx) = test_client(&user_id, None);
+        let connection_id = client.connection_id;
+        state.add_client(client).await;
+        let text = serde_json::json!({
+            "type": "SendMessage",
+            "data": { "content": "spam", "nickname": null }
+        })
+        .to_string();
+        let count_errors = |rx: &mut tokio::sync::mpsc::UnboundedReceiver<WsEvent>| {
+            let mut errors = 0;
+            while let Ok(event) = rx.try_recv() {
+                if let WsEvent::Error { code, message } = event {
+                    assert_eq!(code, ErrorCode::RateLimited);
+                    assert_eq!(message, "发送过于频繁");
+                    errors += 1;
+                }
+            }
+            errors
+        };
+
+        // 同一时刻突发25条消息，超出突发上限的部分被拒绝
+        let start = Instant::now();
+        let mut limiter = MessageRateLimiter::new(10, 20, start);
+        for _ in 0..25 {
+            handle_incoming_text(&text, &mut limiter, start, &connection_id, &user_id, &state).await;
+        }
+        assert_eq!(count_errors(&mut rx), 5);
+        assert_eq!(state.message_db.get_message_count().await.unwrap(), 20);
+
+        // 按限定频率发送的消息全部通过
+        for index in 1..=30 {
+            let now = start + Duration::from_millis(100 * index);
+            handle_incoming_text(&text, &mut limiter, now, &connection_id, &user_id, &state).await;
+        }
+        assert_eq!(count_errors(&mut rx), 0);
+        assert_eq!(state.message_db.get_message_count().await.unwrap(), 50);
+    }
+
+    #[tokio::test]
+    async fn test_rejected_requests_return_coded_errors() {
+        let state = test_state().await;
+        let user_id = UserId::new();
+        let (client, mut rx) = test_client(&user_id, None);
+        let connection_id = client.connection_id;
+        state.add_client(client).await;
+        let mut limiter = MessageRateLimiter::new(0, 1, Instant::now());
+
+        let room_message = serde_json::json!({
+            "type": "SendRoomMessage",
+            "data": { "room_id": uuid::Uuid::new_v4().to_string(), "content": "hi" }
+        });
+        let too_long = serde_json::json!({
+            "type": "SendMessage",
+            "data": { "content": "a".repeat(rustchat_types::validation::MAX_CONTENT_CHARS + 1), "nickname": null }
+        });
+        let cases = [
+            (room_message.to_string(), ErrorCode::RoomNotMember),
+            (too_long.to_string(), ErrorCode::MsgTooLong),
+            ("not json".to_string(), ErrorCode::InvalidRequest),
+        ];
+
+        for (text, expected) in cases {
+            handle_incoming_text(&text, &mut limiter, Instant::now(), &connection_id, &user_id, &state).await;
+            match rx.try_recv() {
+                Ok(WsEvent::Error { code, .. }) => assert_eq!(code, expected),
+                other => panic!("expected {:?} error, got {:?}", expected, other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_malformed_client_json_is_reported_with_format_hint() {
+        let state = test_state().await;
+        let user_id = UserId::new();
+        let (client, mut rx) = test_client(&user_id, None);
+        let connection_id = client.connection_id;
+        state.add_client(client).await;
+        let mut limiter = MessageRateLimiter::new(0, 1, Instant::now());
+
+        for garbage in ["{\"type\": \"SendMessage\", \"data\": ", "[1, 2, 3]", "{}", r#"{"type": "Teleport"}"#, "\u{0}\u{1}"] {
+            handle_incoming_text(garbage, &mut limiter, Instant::now(), &connection_id, &user_id, &state).await;
+            match rx.try_recv() {
+                Ok(WsEvent::Error { code, message }) => {
+                    assert_eq!(code, ErrorCode::InvalidRequest);
+                    assert!(message.contains(CLIENT_MESSAGE_FORMAT_HINT), "{}", message);
+                }
+                other => panic!("expected error for {:?}, got {:?}", garbage, other),
+            }
+        }
+
+        // 解析失败不影响连接继续处理正常消息
+        let text = serde_json::json!({ "type": "SendMessage", "data": { "content": "still here", "nickname": null } }).to_string();
+        handle_incoming_text(&text, &mut limiter, Instant::now(), &connection_id, &user_id, &state).await;
+        assert_eq!(state.message_db.get_message_count().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_room_join_reports_error_to_sender() {
+        let state = test_state().await;
+        let user_id = UserId::new();
+        let (client, mut rx) = test_client(&user_id, None);
+        let connection_id = client.connection_id;
+        state.add_client(client).await;
+        let mut limiter = MessageRateLimiter::new(0, 1, Instant::now());
+
+        let join = |room_id: String| {
+            serde_json::json!({ "type": "JoinRoom", "data": { "room_id": room_id } }).to_string()
+        };
+        let cases = [
+            (join("lobby".to_string()), ErrorCode::InvalidRoomId),
+            (join(uuid::Uuid::new_v4().to_string()), ErrorCode::RoomNotFound),
+        ];
+
+        for (text, expected) in cases {
+            handle_incoming_text(&text, &mut limiter, Instant::now(), &connection_id, &user_id, &state).await;
+            match rx.try_recv() {
+                Ok(WsEvent::Error { code, message }) => {
+                    assert_eq!(code, expected);
+                    assert!(!message.is_empty());
+                }
+                other => panic!("expected {:?} error, got {:?}", expected, other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_message_ack_sent_only_when_requested() {
+        let state = test_state().await;
+        let user_id = UserId::new();
+        let (client, mut rx) = test_client(&user_id, None);
+        let connection_id = client.connection_id;
+        state.add_client(client).await;
+        let (other, mut other_rx) = test_client(&user_id, None);
+        state.add_client(other).await;
+
+        let text = serde_json::json!({
+            "type": "SendMessage",
+            "data": { "content": "hello", "nickname": null, "client_msg_id": "local-1" }
+        })
+        .to_string();
+        handle_client_message(&text, &connection_id, &user_id, &state).await.unwrap();
+
+        let saved = state.message_db.get_recent_messages(1).await.unwrap();
+        match rx.try_recv() {
+            Ok(WsEvent::MessageAck { client_msg_id, message_id }) => {
+                assert_eq!(client_msg_id, "local-1");
+                assert_eq!(message_id, saved[0].id);
+            }
+            other => panic!("应收到消息确认，实际为 {:?}", other),
+        }
+        // 同一用户的其他连接不会收到确认
+        assert!(other_rx.try_recv().is_err());
+
+        // 未提供client_msg_id时保持原有行为
+        let text = serde_json::json!({ "type": "SendMessage", "data": { "content": "again", "nickname": null } }).to_string();
+        handle_client_message(&text, &connection_id, &user_id, &state).await.unwrap();
+        assert_eq!(state.message_db.get_message_count().await.unwrap(), 2);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_room_list_sent_on_request_and_after_join_leave() {
+        let state = test_state().await;
+        let owner = UserId::new();
+        let room = state
+            .room_manager
+            .create_room(room::CreateRoomRequest { name: "lobby".to_string(), description: None, max_members: None, persistent: true }, owner)
+            .await
+            .unwrap();
+        let room_id = room.id.to_string();
+
+        let user_id = UserId::new();
+        let (client, mut rx) = test_client(&user_id, None);
+        let connection_id = client.connection_id;
+        state.add_client(client).await;
+
+        let next_room_list = |rx: &mut tokio::sync::mpsc::UnboundedReceiver<WsEvent>| match rx.try_recv() {
+            Ok(WsEvent::RoomList { rooms }) => rooms,
+            other => panic!("应收到房间列表，实际为 {:?}", other),
+        };
+
+        let list = serde_json::json!({ "type": "ListMyRooms" }).to_string();
+        handle_client_message(&list, &connection_id, &user_id, &state).await.unwrap();
+        assert!(next_room_list(&mut rx).is_empty());
+
+        let join = serde_json::json!({ "type": "JoinRoom", "data": { "room_id": room_id } }).to_string();
+        handle_client_message(&join, &connection_id, &user_id, &state).await.unwrap();
+        // 加入确认先于房间列表到达
+        assert!(matches!(rx.try_recv(), Ok(WsEvent::RoomJoined { .. })));
+        let rooms = next_room_list(&mut rx);
+        assert_eq!(rooms.len(), 1);
+        assert_eq!(rooms[0].id, room_id);
+        assert!(rooms[0].is_member && !rooms[0].is_owner);
+        assert_eq!(rooms[0].unread_count, Some(0));
+
+        let leave = serde_json::json!({ "type": "LeaveRoom", "data": { "room_id": room_id } }).to_string();
+        handle_client_message(&leave, &connection_id, &user_id, &state).await.unwrap();
+        assert!(matches!(rx.try_recv(), Ok(WsEvent::RoomLeft { .. })));
+        assert!(next_room_list(&mut rx).is_empty());
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_word_filter_masks_or_rejects_messages() {
+        use word_filter::FilterMode;
+
+        let mut state = test_state().await;
+        let user_id = UserId::new();
+        let (client, mut rx) = test_client(&user_id, None);
+        let connection_id = client.connection_id;
+        state.add_client(client).await;
+        let send = |content: &str| {
+            serde_json::json!({ "type": "SendMessage", "data": { "content": content, "nickname": null } }).to_string()
+        };
+
+        state.message_filter = Some(Arc::new(MessageFilter::from_words(["笨蛋"], FilterMode::Mask)));
+        handle_client_message(&send("你是笨蛋"), &connection_id, &user_id, &state).await.unwrap();
+        let saved = state.message_db.get_recent_messages(10).await.unwrap();
+        assert_eq!(saved.len(), 1);
+        assert_eq!(saved[0].render_plain(), "你是***");
+
+        state.message_filter = Some(Arc::new(MessageFilter::from_words(["笨蛋"], FilterMode::Reject)));
+        handle_client_message(&send("你是笨蛋"), &connection_id, &user_id, &state).await.unwrap();
+        assert_eq!(state.message_db.get_message_count().await.unwrap(), 1);
+        assert!(matches!(rx.try_recv(), Ok(WsEvent::Error { code: ErrorCode::MsgRejected, message }) if message.contains("被禁止的词语")));
+
+        // 未命中的消息不受影响
+        handle_client_message(&send("你好"), &connection_id, &user_id, &state).await.unwrap();
+        assert_eq!(state.message_db.get_message_count().await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_markdown_message_is_sanitized_and_round_trips() {
+        let state = test_state().await;
+        let user_id = UserId::new();
+        let (client, _rx) = test_client(&user_id, None);
+        let connection_id = client.connection_id;
+        state.add_client(client).await;
+
+        let text = serde_json::json!({
+            "type": "SendMessage",
+            "data": {
+                "content": "**hi** <script>x</script> [a](javascript:alert(1))",
+                "nickname": null,
+                "markdown": true,
+            }
+        })
+        .to_string();
+        handle_client_message(&text, &connection_id, &user_id, &state)
+            .await
+            .unwrap();
+
+        // 数据库保存清理后的Markdown源文本，而不是渲染结果
+        let saved = state.message_db.get_recent_messages(1).await.unwrap();
+        match &saved[0].content {
+            rustchat_types::MessageType::Markdown(source) => {
+                assert_eq!(source, "**hi** &lt;script>x&lt;/script> [a](#)")
+            }
+            other => panic!("应保存为Markdown消息: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mentioned_user_receives_direct_event() {
+        let state = test_state().await;
+        let sender_id = UserId::new();
+        let (sender, _sender_rx) = test_client(&sender_id, None);
+        let connection_id = sender.connection_id;
+        state.add_client(sender).await;
+
+        let bob_id = UserId::new();
+        let (mut bob, mut bob_rx) = test_client(&bob_id, None);
+        bob.nickname = Some("bob".to_string());
+        state.add_client(bob).await;
+
+        let text = serde_json::json!({
+            "type": "SendMessage",
+            "data": { "content": "hi @bob and @nobody", "nickname": null }
+        })
+        .to_string();
+        handle_client_message(&text, &connection_id, &sender_id, &state)
+            .await
+            .unwrap();
+
+        match bob_rx.try_recv() {
+            Ok(WsEvent::Mentioned { message }) => assert_eq!(message.mentions, vec![bob_id.clone()]),
+            other => panic!("应收到提及通知: {:?}", other),
+        }
+
+        // 提及信息随消息一起保存
+        let saved = state.message_db.get_recent_messages(1).await.unwrap();
+        assert!(saved[0].mentions_user(&bob_id));
+    }
+
+    #[tokio::test]
+    async fn test_reactions_broadcast_counts() {
+        let state = test_state().await;
+        let user_id = UserId::new();
+        let (client, _rx) = test_client(&user_id, None);
+        let connection_id = client.connection_id;
+        state.add_client(client).await;
+
+        let message = Message::new_text(UserId::new(), "hello".to_string(), None);
+        state.message_db.save_message(&message).await.unwrap();
+        let mut events = state.tx.subscribe();
+
+        let reaction = |kind: &str, emoji: &str| {
+            serde_json::json!({
+                "type": kind,
+                "data": { "message_id": message.id, "emoji": emoji }
+            })
+            .to_string()
+        };
+
+        // 重复回应只计一次，也只通知一次
+        for _ in 0..2 {
+            handle_client_message(&reaction("React", "👍"), &connection_id, &user_id, &state)
+                .await
+                .unwrap();
+        }
+        match events.try_recv() {
+            Ok(WsEvent::ReactionUpdated { emoji, count, users, .. }) => {
+                assert_eq!(emoji, "👍");
+                assert_eq!(count, 1);
+                assert_eq!(users, vec![user_id.clone()]);
+            }
+            other => panic!("应收到回应更新: {:?}", other),
+        }
+        assert!(events.try_recv().is_err());
+
+        handle_client_message(&reaction("Unreact", "👍"), &connection_id, &user_id, &state)
+            .await
+            .unwrap();
+        assert!(matches!(events.try_recv(), Ok(WsEvent::ReactionUpdated { count: 0, .. })));
+
+        // 无效表情和不存在的消息被拒绝
+        assert!(handle_client_message(&reaction("React", "a b"), &connection_id, &user_id, &state)
+            .await
+            .is_err());
+        let missing = serde_json::json!({
+            "type": "React",
+            "data": { "message_id": MessageId::new(), "emoji": "👍" }
+        })
+        .to_string();
+        assert!(handle_client_message(&missing, &connection_id, &user_id, &state).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_ephemeral_message_expires_and_is_swept() {
+        let state = test_state().await;
+        let user_id = UserId::new();
+        let (client, _rx) = test_client(&user_id, None);
+        let connection_id = client.connection_id;
+        state.add_client(client).await;
+        let mut events = state.tx.subscribe();
+
+        let text = serde_json::json!({
+            "type": "SendMessage",
+            "data": { "content": "secret", "nickname": null, "ttl_secs": 1 }
+        })
+        .to_string();
+        handle_client_message(&text, &connection_id, &user_id, &state)
+            .await
+            .unwrap();
+
+        let saved = state.message_db.get_recent_messages(10).await.unwrap();
+        assert_eq!(saved.len(), 1);
+        let message_id = saved[0].id.clone();
+        assert!(saved[0].expires_at.is_some());
+
+        // 过期前清理任务不删除消息
+        assert_eq!(state.sweep_expired_messages().await.unwrap(), 0);
+
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        assert!(state.message_db.get_recent_messages(10).await.unwrap().is_empty());
+        assert_eq!(state.sweep_expired_messages().await.unwrap(), 1);
+        assert_eq!(state.message_db.get_message_count().await.unwrap(), 0);
+
+        // 跳过发送时广播的消息，检查过期通知
+        let expired = loop {
+            match events.try_recv().unwrap() {
+                WsEvent::MessagesExpired { message_ids } => break message_ids,
+                _ => continue,
+            }
+        };
+        assert_eq!(expired, vec![message_id]);
+
+        // 超出范围的存活时间被拒绝
+        let text = serde_json::json!({
+            "type": "SendMessage",
+            "data": { "content": "forever", "nickname": null, "ttl_secs": 0 }
+        })
+        .to_string();
+        assert!(handle_client_message(&text, &connection_id, &user_id, &state).await.is_err());
+    }
+
+    /// 注册测试账户，返回账户和对应的用户ID
+    async fn register_account(state: &AppState, email: &str) -> (auth::Account, UserId) {
+        let account = state
+            .auth_service
+            .register(email.to_string(), "secret123".to_string(), None)
+            .await
+            .unwrap();
+        let user_id = UserId::parse(&account.id.to_string()).unwrap();
+        (account, user_id)
+    }
+
+    /// 注册账户并标记邮箱已验证，邮箱需在配置的管理员列表中才有管理权限
+    async fn register_admin(state: &AppState, email: &str) -> auth::Account {
+        let (mut account, _) = register_account(state, email).await;
+        sqlx::query("UPDATE accounts SET email_verified = TRUE WHERE id = $1")
+            .bind(account.id.to_string())
+            .execute(state.auth_service.get_pool())
+            .await
+            .unwrap();
+        account.email_verified = true;
+        account
+    }
+
+    #[tokio::test]
+    async fn test_purge_removes_user_footprint() {
+        let upload_dir = std::env::temp_dir().join(format!("rustchat-uploads-{}", uuid::Uuid::new_v4()));
+        let state = test_state_with_config(ServerConfig { upload_dir: upload_dir.clone(), ..ServerConfig::default() }).await;
+        let (_, user_id) = register_account(&state, "alice@example.com").await;
+        let (friend_account, friend_id) = register_account(&state, "bob@example.com").await;
+        let stranger_id = UserId::new();
+
+        let (friend_client, mut friend_rx) = test_client(&friend_id, Some(friend_account.id));
+        state.add_client(friend_client).await;
+
+        // 消息
+        for content in ["one", "two"] {
+            let message = Message::new_text(user_id.clone(), content.to_string(), None);
+            state.message_db.save_message(&message).await.unwrap();
+        }
+        let kept = Message::new_text(friend_id.clone(), "kept".to_string(), None);
+        state.message_db.save_message(&kept).await.unwrap();
+
+        // 上传的文件
+        let (upload_hash, _) = state.uploads.save(&user_id, b"notes", "text/plain").await.unwrap();
+
+        // 房间：自己创建的房间和加入的他人房间
+        let own_room = state
+            .room_manager
+            .create_room(room::CreateRoomRequest { name: "mine".to_string(), description: None, max_members: None, persistent: true }, user_id.clone())
+            .await
+            .unwrap();
+        let shared_room = state
+            .room_manager
+            .create_room(room::CreateRoomRequest { name: "shared".to_string(), description: None, max_members: None, persistent: true }, friend_id.clone())
+            .await
+            .unwrap();
+        state.room_manager.join_room(shared_room.id, user_id.clone()).await.unwrap();
+
+        // 好友关系和待处理的好友请求
+        {
+            let mut friend_manager = state.friend_manager.lock().await;
+            let request = friend_manager
+                .send_friend_request(user_id.clone(), friend_id.clone(), None)
+                .await
+                .unwrap();
+            friend_manager.accept_friend_request(&request.id, &friend_id).await.unwrap();
+            friend_manager
+                .send_friend_request(stranger_id.clone(), user_id.clone(), None)
+                .await
+                .unwrap();
+        }
+
+        let summary = state.purge_user_data(&user_id).await.unwrap();
+        assert_eq!(summary.messages, 2);
+        assert_eq!(summary.uploads, 1);
+        assert_eq!(summary.rooms, 2);
+        assert_eq!(summary.friendships, 1);
+        assert_eq!(summary.friend_requests, 2);
+
+        assert!(state.message_db.get_user_messages(&user_id, 10).await.unwrap().is_empty());
+        assert_eq!(state.message_db.get_message_count().await.unwrap(), 1);
+        assert_eq!(state.uploads.load(&upload_hash).await.unwrap(), None);
+
+        assert!(state.room_manager.get_user_rooms(&user_id).await.is_empty());
+        assert!(state.room_manager.get_room(own_room.id).await.is_err());
+        assert!(!state.room_manager.is_user_in_room(shared_room.id, &user_id).await);
+        assert!(state.room_manager.is_user_in_room(shared_room.id, &friend_id).await);
+
+        {
+            let friend_manager = state.friend_manager.lock().await;
+            assert!(friend_manager.get_friends(user_id.clone()).await.unwrap().is_empty());
+            assert!(friend_manager.get_friends(friend_id.clone()).await.unwrap().is_empty());
+            assert!(friend_manager.get_friend_requests(user_id.clone()).await.unwrap().is_empty());
+            assert!(friend_manager.get_friend_requests(stranger_id).await.unwrap().is_empty());
+        }
+
+        // 账户本身保留
+        assert!(state.auth_service.get_account_by_email("alice@example.com").await.is_ok());
+
+        match friend_rx.try_recv() {
+            Ok(WsEvent::FriendRemoved { user_id: removed }) => assert_eq!(removed, user_id),
+            other => panic!("未收到好友移除通知: {:?}", other),
+        }
+
+        std::fs::remove_dir_all(upload_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_purge_rolls_back_when_uploads_fail() {
+        let upload_dir = std::env::temp_dir().join(format!("rustchat-uploads-{}", uuid::Uuid::new_v4()));
+        let state = test_state_with_config(ServerConfig { upload_dir: upload_dir.clone(), ..ServerConfig::default() }).await;
+        let (_, user_id) = register_account(&state, "alice@example.com").await;
+
+        let message = Message::new_text(user_id.clone(), "hello".to_string(), None);
+        state.message_db.save_message(&message).await.unwrap();
+        let (upload_hash, _) = state.uploads.save(&user_id, b"notes", "text/plain").await.unwrap();
+        let room = state
+            .room_manager
+            .create_room(room::CreateRoomRequest { name: "mine".to_string(), description: None, max_members: None, persistent: true }, user_id.clone())
+            .await
+            .unwrap();
+
+        // 删除消息之后删除上传记录失败，已删除的消息随事务回滚
+        let pool = state.message_db.get_pool();
+        sqlx::query("ALTER TABLE uploads RENAME TO uploads_offline").execute(pool).await.unwrap();
+        assert!(state.purge_user_data(&user_id).await.is_err());
+        assert_eq!(state.message_db.get_user_messages(&user_id, 10).await.unwrap().len(), 1);
+        assert!(state.room_manager.is_user_in_room(room.id, &user_id).await);
+        sqlx::query("ALTER TABLE uploads_offline RENAME TO uploads").execute(pool).await.unwrap();
+        assert!(state.uploads.load(&upload_hash).await.unwrap().is_some());
+
+        // 重试可以完整清除
+        let summary = state.purge_user_data(&user_id).await.unwrap();
+        assert_eq!(summary.messages, 1);
+        assert_eq!(summary.uploads, 1);
+        assert_eq!(summary.rooms, 1);
+        assert!(state.message_db.get_user_messages(&user_id, 10).await.unwrap().is_empty());
+        assert_eq!(state.uploads.load(&upload_hash).await.unwrap(), None);
+
+        std::fs::remove_dir_all(upload_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_purge_endpoint_requires_password() {
+        use axum::body::Body;
+        use axum::http::{header, Request, StatusCode};
+        use tower::ServiceExt;
+
+        let state = test_state().await;
+        let (account, user_id) = register_account(&state, "carol@example.com").await;
+        let message = Message::new_text(user_id.clone(), "private".to_string(), None);
+        state.message_db.save_message(&message).await.unwrap();
+
+        let tokens = state
+            .auth_service
+            .generate_token_pair(&account, None, None)
+            .await
+            .unwrap();
+        let app = create_protected_auth_routes()
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                auth::middleware::auth_middleware,
+            ))
+            .with_state(state.clone());
+
+        let purge_request = |password: &str| {
+            Request::post("/api/auth/me/purge")
+                .header(header::AUTHORIZATION, format!("Bearer {}", tokens.access_token))
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(serde_json::json!({ "password": password }).to_string()))
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(purge_request("wrong-password")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(state.message_db.get_message_count().await.unwrap(), 1);
+
+        let response = app.oneshot(purge_request("secret123")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(state.message_db.get_message_count().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_logout_blacklists_access_token() {
+        use axum::body::Body;
+        use axum::http::{header, Request, StatusCode};
+        use tower::ServiceExt;
+
+        let state = test_state().await;
+        let (account, _) = register_account(&state, "dave@example.com").await;
+        let tokens = state
+            .auth_service
+            .generate_token_pair(&account, None, None)
+            .await
+            .unwrap();
+        let other_tokens = state
+            .auth_service
+            .generate_token_pair(&account, None, None)
+            .await
+            .unwrap();
+        assert!(state.auth_service.verify_token(&tokens.access_token, auth::TokenType::Access).is_ok());
+
+        let response = create_auth_routes()
+            .with_state(state.clone())
+            .oneshot(
+                Request::post("/api/auth/logout")
+                    .header(header::AUTHORIZATION, format!("Bearer {}", tokens.access_token))
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(serde_json::json!({ "refresh_token": tokens.refresh_token }).to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // 未过期的访问令牌立即失效，刷新令牌也不能再用；同一账户的其他令牌不受影响
+        assert!(matches!(
+            state.auth_service.verify_token(&tokens.access_token, auth::TokenType::Access),
+            Err(auth::AuthError::TokenRevoked)
+        ));
+        assert!(state.auth_service.refresh_access_token(&tokens.refresh_token).await.is_err());
+        assert!(state.auth_service.verify_token(&other_tokens.access_token, auth::TokenType::Access).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_weak_password_rejected_with_reason() {
+        use axum::body::Body;
+        use axum::http::{header, Request, StatusCode};
+        use tower::ServiceExt;
+
+        let state = test_state().await;
+        let response = create_auth_routes()
+            .with_state(state.clone())
+            .oneshot(
+                Request::post("/api/auth/register")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(
+                        serde_json::json!({ "email": "weak@example.com", "password": "aaaaaa" }).to_string(),
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["message"], "密码不符合要求：至少需要包含一个数字");
+        assert!(state.auth_service.get_account_by_email("weak@example.com").await.is_err());
+
+        // 放宽策略后同样的密码可以注册
+        let relaxed = test_state_with_config(ServerConfig {
+            password_policy: auth::PasswordPolicy::relaxed(),
+            ..ServerConfig::default()
+        })
+        .await;
+        relaxed
+            .auth_service
+            .register("weak@example.com".to_string(), "aaaaaa".to_string(), None)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_deleted_account_cannot_log_in() {
+        use axum::body::Body;
+        use axum::http::{header, Request, StatusCode};
+        use tower::ServiceExt;
+
+        let state = test_state().await;
+        let (account, user_id) = register_account(&state, "dave@example.com").await;
+        let message = Message::new_text(user_id.clone(), "bye".to_string(), None);
+        state.message_db.save_message(&message).await.unwrap();
+
+        let (client, mut rx) = test_client(&user_id, Some(account.id));
+        state.add_client(client).await;
+
+        let tokens = state
+            .auth_service
+            .generate_token_pair(&account, None, None)
+            .await
+            .unwrap();
+        // 与 create_app 一样合并公开和需要认证的账户路由（GET 和 DELETE /api/auth/me 共用路径）
+        let app = create_auth_routes()
+            .merge(create_protected_auth_routes()
+                .layer(axum::middleware::from_fn_with_state(
+                    state.clone(),
+                    auth::middleware::auth_middleware,
+                )))
+            .with_state(state.clone());
+
+        let delete_request = |password: &str| {
+            Request::delete("/api/auth/me")
+                .header(header::AUTHORIZATION, format!("Bearer {}", tokens.access_token))
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(serde_json::json!({ "password": password }).to_string()))
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(delete_request("wrong-password")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = app.clone().oneshot(delete_request("secret123")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // 消息已删除，在线连接被断开
+        assert_eq!(state.message_db.get_message_count().await.unwrap(), 0);
+        assert!(state.clients.read().await.is_empty());
+        let mut saw_notice = false;
+        while let Ok(event) = rx.try_recv() {
+            saw_notice |= matches!(event, WsEvent::Error { code: ErrorCode::SessionTerminated, ref message } if message == "账户已注销");
+        }
+        assert!(saw_notice);
+
+        // 无法再登录、刷新令牌或使用旧的访问令牌
+        assert!(matches!(
+            state.auth_service.login("dave@example.com".to_string(), "secret123".to_string()).await,
+            Err(auth::AuthError::AccountNotFound)
+        ));
+        assert!(state.auth_service.refresh_access_token(&tokens.refresh_token).await.is_err());
+        let response = app.oneshot(delete_request("secret123")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let deleted = state.auth_service.get_account_by_id(&account.id).await.unwrap();
+        assert_eq!(deleted.status, auth::AccountStatus::Deleted);
+        assert_ne!(deleted.email, "dave@example.com");
+
+        // 原邮箱可以重新注册
+        register_account(&state, "dave@example.com").await;
+    }
+
+    #[tokio::test]
+    async fn test_nick_change_within_cooldown_is_rejected() {
+        let state = test_state().await;
+        let user_id = UserId::new();
+        let (client, mut rx) = test_client(&user_id, None);
+        let connection_id = client.connection_id;
+        state.add_client(client).await;
+
+        let set_nickname = |nickname: &str| {
+            serde_json::json!({ "type": "SetNickname", "data": { "nickname": nickname } }).to_string()
+        };
+
+        // 第一次设置昵称不计入冷却，紧接着的修改仍然允许
+        for nickname in ["Alice", "Bob", "Mallory"] {
+            handle_client_message(&set_nickname(nickname), &connection_id, &user_id, &state)
+                .await
+                .unwrap();
+        }
+
+        assert!(matches!(rx.try_recv(), Ok(WsEvent::Error { code: ErrorCode::NickCooldown, .. })));
+        let nickname = state.clients.read().await[&connection_id].nickname.clone();
+        assert_eq!(nickname.as_deref(), Some("Bob"));
+
+        // 冷却中被拒绝的修改不产生昵称变更消息
+        let messages = state.message_db.get_recent_messages(10).await.unwrap();
+        assert_eq!(messages.len(), 2);
+        assert!(messages.iter().all(|message| message.from_nick.as_deref() != Some("Mallory")));
+    }
+
+    #[tokio::test]
+    async fn test_nick_change_broadcasts_nickname_changed_event() {
+        let state = test_state_with_config(ServerConfig {
+            nick_change_cooldown: Duration::ZERO,
+            ..ServerConfig::default()
+        })
+        .await;
+        let user_id = UserId::new();
+        let (client, _rx) = test_client(&user_id, None);
+        let connection_id = client.connection_id;
+        state.add_client(client).await;
+        let mut broadcast_rx = state.tx.subscribe();
+
+        for nickname in ["Alice", "Alicia"] {
+            let message = serde_json::json!({ "type": "SetNickname", "data": { "nickname": nickname } }).to_string();
+            handle_client_message(&message, &connection_id, &user_id, &state).await.unwrap();
+        }
+
+        let mut changes = Vec::new();
+        while let Ok(event) = broadcast_rx.try_recv() {
+            if let WsEvent::NicknameChanged { user_id: changed, old_nick, new_nick } = event {
+                assert_eq!(changed, user_id);
+                changes.push((old_nick, new_nick));
+            }
+        }
+        assert_eq!(
+            changes,
+            vec![
+                (None, "Alice".to_string()),
+                (Some("Alice".to_string()), "Alicia".to_string()),
+            ]
+        );
+
+        // 昵称变更消息仍照常保存
+        assert_eq!(state.message_db.get_recent_messages(10).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_client_snapshot_merges_connections_of_user() {
+        let state = test_state().await;
+        let (account, user_id) = register_account(&state, "snapshot@example.com").await;
+        assert!(state.client_snapshot(&user_id).await.is_none());
+
+        let (mut first, _first_rx) = test_client(&user_id, Some(account.id));
+        first.nickname = Some("Alice".to_string());
+        first.email = Some("snapshot@example.com".to_string());
+        let connected_at = first.connected_at;
+        state.add_client(first).await;
+        let (mut second, _second_rx) = test_client(&user_id, Some(account.id));
+        second.connected_at = connected_at + Duration::from_secs(1);
+        state.add_client(second.clone()).await;
+
+        // 较新的连接没有昵称时沿用旧连接的昵称
+        let snapshot = state.client_snapshot(&user_id).await.unwrap();
+        assert_eq!(snapshot.nickname.as_deref(), Some("Alice"));
+        assert_eq!(snapshot.email.as_deref(), Some("snapshot@example.com"));
+        assert_eq!(snapshot.connected_at, connected_at);
+        assert_eq!(snapshot.connections, 2);
+
+        state.clients.write().await.get_mut(&second.connection_id).unwrap().nickname = Some("Alicia".to_string());
+        assert_eq!(state.client_snapshot(&user_id).await.unwrap().nickname.as_deref(), Some("Alicia"));
+
+        // 快照不持有锁，拿到后仍可修改连接表
+        let snapshot = state.client_snapshot(&user_id).await.unwrap();
+        state.remove_client(&second.connection_id).await;
+        assert_eq!(snapshot.connections, 2);
+        assert_eq!(state.client_snapshot(&user_id).await.unwrap().connections, 1);
+        assert!(state.client_snapshot(&UserId::new()).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_set_nickname_does_not_hold_clients_lock_while_rate_limited() {
+        let state = test_state().await;
+        let user_id = UserId::new();
+        let (mut client, _rx) = test_client(&user_id, None);
+        client.nickname = Some("Guest".to_string());
+        let connection_id = client.connection_id;
+        state.add_client(client).await;
+
+        // 占住昵称限流器，设置昵称会停在查询冷却时间这一步
+        let limiter = state.nick_limiter.lock().await;
+        let task = {
+            let state = state.clone();
+            let user_id = user_id.clone();
+            tokio::spawn(async move {
+                let set_nickname = serde_json::json!({ "type": "SetNickname", "data": { "nickname": "Alice" } }).to_string();
+                handle_client_message(&set_nickname, &connection_id, &user_id, &state).await
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!task.is_finished());
+
+        // 此时连接表的写锁仍然可以获取，其他连接的注册和断开不会被阻塞
+        let write = tokio::time::timeout(Duration::from_millis(500), state.clients.write()).await;
+        assert!(write.is_ok());
+        drop(write);
+
+        drop(limiter);
+        task.await.unwrap().unwrap();
+        assert_eq!(state.client_snapshot(&user_id).await.unwrap().nickname.as_deref(), Some("Alice"));
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_with_same_nickname_is_not_broadcast() {
+        // 关闭冷却时间，确保第二次没有广播不是因为被频率限制拒绝
+        let state = test_state_with_config(ServerConfig {
+            nick_change_cooldown: Duration::ZERO,
+            ..ServerConfig::default()
+        })
+        .await;
+        let (account, user_id) = register_account(&state, "nick@example.com").await;
+        let mut broadcast_rx = state.tx.subscribe();
+        let set_nickname = serde_json::json!({ "type": "SetNickname", "data": { "nickname": "Alice" } }).to_string();
+
+        // 第一个连接设置昵称后断开，重连的新连接再次发送相同昵称
+        for _ in 0..2 {
+            let (client, _rx) = test_client(&user_id, Some(account.id));
+            let connection_id = client.connection_id;
+            state.add_client(client).await;
+            handle_client_message(&set_nickname, &connection_id, &user_id, &state).await.unwrap();
+            assert_eq!(state.clients.read().await[&connection_id].nickname.as_deref(), Some("Alice"));
+            state.remove_client(&connection_id).await;
+        }
+
+        let mut changes = 0;
+        while let Ok(event) = broadcast_rx.try_recv() {
+            if matches!(event, WsEvent::NicknameChanged { .. }) {
+                changes += 1;
+            }
+        }
+        assert_eq!(changes, 1);
+        assert_eq!(state.message_db.get_recent_messages(10).await.unwrap().len(), 1);
+    }
+
+    /// 管理员路由（带认证和管理员中间件）
+    fn admin_app(state: &AppState) -> Router {
+        create_admin_routes()
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                auth::middleware::admin_middleware,
+            ))
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                auth::middleware::auth_middleware,
+            ))
+            .with_state(state.clone())
+    }
+
+    fn authorized_request(method: &str, uri: &str, access_token: &str) -> axum::http::Request<axum::body::Body> {
+        axum::http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(axum::http::header::AUTHORIZATION, format!("Bearer {}", access_token))
+            .body(axum::body::Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_upload_is_stored_by_hash_and_served_back() {
+        use tower::ServiceExt;
+
+        let upload_dir = std::env::temp_dir().join(format!("rustchat-uploads-{}", uuid::Uuid::new_v4()));
+        let state = test_state_with_config(ServerConfig {
+            upload_dir: upload_dir.clone(),
+            max_upload_bytes: 64,
+            ..ServerConfig::default()
+        })
+        .await;
+        let app = upload::create_upload_routes(state.config.max_upload_bytes)
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                auth::middleware::auth_middleware,
+            ))
+            .merge(upload::create_public_upload_routes())
+            .with_state(state.clone());
+        let (account, _) = register_account(&state, "uploader@example.com").await;
+        let token = state.auth_service.generate_token_pair(&account, None, None).await.unwrap().access_token;
+
+        let upload = |token: Option<&str>, mime: &str, content: &[u8]| {
+            let mut body = b"--BOUNDARY\r\nContent-Disposition: form-data; name=\"file\"; filename=\"f\"\r\n".to_vec();
+            body.extend_from_slice(format!("Content-Type: {}\r\n\r\n", mime).as_bytes());
+            body.extend_from_slice(content);
+            body.extend_from_slice(b"\r\n--BOUNDARY--\r\n");
+            let mut request = axum::http::Request::post("/api/uploads")
+                .header(axum::http::header::CONTENT_TYPE, "multipart/form-data; boundary=BOUNDARY");
+            if let Some(token) = token {
+                request = request.header(axum::http::header::AUTHORIZATION, format!("Bearer {}", token));
+            }
+            request.body(axum::body::Body::from(body)).unwrap()
+        };
+        let json_body = |response: axum::response::Response| async move {
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            serde_json::from_slice::<serde_json::Value>(&bytes).unwrap()
+        };
+
+        let content = b"\x89PNG\r\n\x1a\nimage bytes";
+        let response = app.clone().oneshot(upload(Some(&token), "image/png", content)).await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        let info: upload::UploadInfo = serde_json::from_value(json_body(response).await["data"].clone()).unwrap();
+        assert_eq!(info.size, content.len() as u64);
+        assert_eq!(info.mime, "image/png");
+
+        // 相同内容重复上传得到同一地址
+        let response = app.clone().oneshot(upload(Some(&token), "image/png", content)).await.unwrap();
+        assert_eq!(json_body(response).await["data"]["url"], info.url.as_str());
+
+        let response = app.clone().oneshot(axum::http::Request::get(&info.url).body(axum::body::Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        assert_eq!(response.headers()[axum::http::header::CONTENT_TYPE], "image/png");
+        assert_eq!(response.headers()[axum::http::header::CONTENT_DISPOSITION], "inline");
+        assert_eq!(response.headers()[axum::http::header::CONTENT_SECURITY_POLICY], "sandbox");
+        assert_eq!(&axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap()[..], content);
+
+        // 非图片类型要求浏览器下载，不在本站页面中打开
+        let response = app.clone().oneshot(upload(Some(&token), "application/pdf", b"%PDF-1.7")).await.unwrap();
+        let url = json_body(response).await["data"]["url"].as_str().unwrap().to_string();
+        let response = app.clone().oneshot(axum::http::Request::get(&url).body(axum::body::Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.headers()[axum::http::header::CONTENT_DISPOSITION], "attachment");
+        assert_eq!(response.headers()[axum::http::header::CONTENT_SECURITY_POLICY], "sandbox");
+
+        let cases = [
+            (None, "image/png", &content[..], axum::http::StatusCode::UNAUTHORIZED),
+            (Some(token.as_str()), "text/html", b"<script>".as_slice(), axum::http::StatusCode::UNSUPPORTED_MEDIA_TYPE),
+            (Some(token.as_str()), "image/png", b"not a png".as_slice(), axum::http::StatusCode::BAD_REQUEST),
+            (Some(token.as_str()), "text/plain", [b'a'; 65].as_slice(), axum::http::StatusCode::PAYLOAD_TOO_LARGE),
+        ];
+        for (token, mime, content, expected) in cases {
+            let response = app.clone().oneshot(upload(token, mime, content)).await.unwrap();
+            assert_eq!(response.status(), expected, "{}", mime);
+        }
+
+        let missing = format!("/api/uploads/{}", "0".repeat(64));
+        let response = app.oneshot(axum::http::Request::get(&missing).body(axum::body::Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::NOT_FOUND);
+
+        std::fs::remove_dir_all(upload_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_admin_views_and_revokes_sessions() {
+        use tower::ServiceExt;
+
+        let state = test_state_with_config(ServerConfig {
+            admin_emails: vec!["admin@example.com".to_string()],
+            ..ServerConfig::default()
+        })
+        .await;
+        let admin = register_admin(&state, "admin@example.com").await;
+        let (victim, victim_user_id) = register_account(&state, "victim@example.com").await;
+
+        let admin_tokens = state.auth_service.generate_token_pair(&admin, None, None).await.unwrap();
+        let victim_tokens = state
+            .auth_service
+            .generate_token_pair(&victim, Some("laptop".to_string()), Some("10.0.0.1".to_string()))
+            .await
+            .unwrap();
+        state
+            .auth_service
+            .generate_token_pair(&victim, Some("phone".to_string()), None)
+            .await
+            .unwrap();
+
+        let (victim_client, mut victim_rx) = test_client(&victim_user_id, Some(victim.id));
+        state.add_client(victim_client).await;
+
+        let app = admin_app(&state);
+
+        let response = app
+            .clone()
+            .oneshot(authorized_request("GET", &format!("/api/admin/accounts/{}/sessions", victim.id), &admin_tokens.access_token))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let sessions = body["sessions"].as_array().unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert!(sessions.iter().any(|s| s["device_info"] == "laptop" && s["ip_address"] == "10.0.0.1"));
+        assert!(sessions.iter().all(|s| s["last_used_at"].is_string()));
+
+        let response = app
+            .oneshot(authorized_request("POST", &format!("/api/admin/accounts/{}/logout-all", victim.id), &admin_tokens.access_token))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["revoked_sessions"], 2);
+        assert_eq!(body["disconnected_connections"], 1);
+
+        // 会话全部失效，刷新令牌不可再用，在线连接被断开
+        let sessions = state.auth_service.list_sessions(&victim.id).await.unwrap();
+        assert!(sessions.iter().all(|session| !session.is_active));
+        assert!(state.auth_service.refresh_access_token(&victim_tokens.refresh_token).await.is_err());
+        assert!(matches!(
+            state.auth_service.verify_token(&victim_tokens.access_token, auth::TokenType::Access),
+            Err(auth::AuthError::TokenRevoked)
+        ));
+        // 撤销后立即重新登录（通常与撤销在同一秒内）得到的令牌可以正常使用
+        let relogin = state.auth_service.generate_token_pair(&victim, None, None).await.unwrap();
+        assert!(state.auth_service.verify_token(&relogin.access_token, auth::TokenType::Access).is_ok());
+        assert!(matches!(victim_rx.try_recv(), Ok(WsEvent::Error { .. })));
+        assert!(state.connections_for_user(&victim_user_id).await.is_empty());
+
+        // 两次操作都写入了审计日志
+        let actions: Vec<(String, String)> = sqlx::query_as(
+            "SELECT action, target_account_id FROM audit_log WHERE actor_account_id = $1 ORDER BY id",
+        )
+        .bind(admin.id.to_string())
+        .fetch_all(state.auth_service.get_pool())
+        .await
+        .unwrap();
+        assert_eq!(
+            actions,
+            vec![
+                ("view_sessions".to_string(), victim.id.to_string()),
+                ("force_logout".to_string(), victim.id.to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_admin_imports_messages_in_one_batch() {
+        use axum::http::{header, StatusCode};
+        use tower::ServiceExt;
+
+        let state = test_state_with_config(ServerConfig {
+            admin_emails: vec!["admin@example.com".to_string()],
+            ..ServerConfig::default()
+        })
+        .await;
+        let admin = register_admin(&state, "admin@example.com").await;
+        let (user, _) = register_account(&state, "user@example.com").await;
+        let admin_tokens = state.auth_service.generate_token_pair(&admin, None, None).await.unwrap();
+        let user_tokens = state.auth_service.generate_token_pair(&user, None, None).await.unwrap();
+
+        let author = UserId::new();
+        let start = chrono::Utc::now() - chrono::Duration::days(30);
+        let rows: Vec<serde_json::Value> = (0..1000)
+            .map(|i| {
+                serde_json::json!({
+                    "id": MessageId::new().to_string(),
+                    "from_user_id": author.to_string(),
+                    "content": format!("legacy {}", i),
+                    "timestamp": start + chrono::Duration::seconds(i),
+                    "nickname": "legacy"
+                })
+            })
+            .collect();
+        let import = |token: &str, rows: &[serde_json::Value]| {
+            axum::http::Request::post("/api/messages/import")
+                .header(header::AUTHORIZATION, format!("Bearer {}", token))
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(axum::body::Body::from(serde_json::to_string(rows).unwrap()))
+                .unwrap()
+        };
+        let counts = |body: &serde_json::Value| (body["inserted"].as_u64(), body["skipped"].as_u64());
+
+        let response = admin_app(&state).oneshot(import(&user_tokens.access_token, &rows)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = admin_app(&state).oneshot(import(&admin_tokens.access_token, &rows)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(counts(&body), (Some(1000), Some(0)));
+
+        // 保留原有的ID和时间戳
+        let messages = state.message_db.get_user_messages(&author, 1000).await.unwrap();
+        assert_eq!(messages.len(), 1000);
+        assert_eq!(messages[0].id.to_string(), rows[0]["id"].as_str().unwrap());
+        assert_eq!(messages[999].timestamp, start + chrono::Duration::seconds(999));
+
+        // 重复导入时全部跳过
+        let response = admin_app(&state).oneshot(import(&admin_tokens.access_token, &rows[..10])).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(counts(&body), (Some(0), Some(10)));
+
+        // 任何一条无效时整批拒绝
+        let mut invalid = rows[..2].to_vec();
+        invalid[1]["from_user_id"] = serde_json::json!("nobody");
+        let response = admin_app(&state).oneshot(import(&admin_tokens.access_token, &invalid)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(state.message_db.get_message_count().await.unwrap(), 1000);
+    }
+
+    #[tokio::test]
+    async fn test_admin_toggles_bot() {
+        use axum::http::StatusCode;
+        use tower::ServiceExt;
+
+        let state = test_state_with_config(ServerConfig {
+            admin_emails: vec!["admin@example.com".to_string()],
+            ..ServerConfig::default()
+        })
+        .await;
+        let admin = register_admin(&state, "admin@example.com").await;
+        let (user, _) = register_account(&state, "user@example.com").await;
+        let admin_tokens = state.auth_service.generate_token_pair(&admin, None, None).await.unwrap();
+        let user_tokens = state.auth_service.generate_token_pair(&user, None, None).await.unwrap();
+
+        let response = admin_app(&state)
+            .oneshot(authorized_request("POST", "/api/admin/bots/Command%20Bot/toggle", &admin_tokens.access_token))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["bot"]["enabled"], false);
+
+        // 公开的机器人列表反映新的状态
+        let response = bots::create_bot_routes()
+            .with_state(state.clone())
+            .oneshot(axum::http::Request::get("/api/bots").body(axum::body::Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let bots = body["bots"].as_array().unwrap();
+        assert!(bots.iter().any(|bot| bot["name"] == "Echo Bot" && bot["enabled"] == true));
+        assert!(bots.iter().any(|bot| bot["name"] == "Command Bot" && bot["enabled"] == false));
+
+        let response = admin_app(&state)
+            .oneshot(authorized_request("POST", "/api/admin/bots/Missing/toggle", &admin_tokens.access_token))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = admin_app(&state)
+            .oneshot(authorized_request("POST", "/api/admin/bots/Echo%20Bot/toggle", &user_tokens.access_token))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(state.bot_manager.lock().await.get_bots_info().iter().all(|bot| bot.name != "Echo Bot" || bot.enabled));
+    }
+
+    #[tokio::test]
+    async fn test_greeter_bot_welcomes_when_enabled() {
+        let state = test_state().await;
+        let mut bot_messages = state.message_tx.subscribe();
+
+        // 默认关闭，不发送欢迎消息
+        let (client, _rx) = test_client(&UserId::new(), None);
+        state.add_client(client).await;
+        assert!(bot_messages.try_recv().is_err());
+
+        assert!(state.bot_manager.lock().await.set_enabled("Greeter Bot", true));
+        let (mut client, _rx) = test_client(&UserId::new(), None);
+        client.nickname = Some("alice".to_string());
+        state.add_client(client).await;
+        let welcome = bot_messages.try_recv().unwrap();
+        assert_eq!(welcome.get_text(), Some("欢迎 alice!"));
+        assert!(welcome.is_bot);
+    }
+
+    #[tokio::test]
+    async fn test_non_admin_cannot_view_sessions() {
+        use tower::ServiceExt;
+
+        let state = test_state_with_config(ServerConfig {
+            admin_emails: vec!["admin@example.com".to_string()],
+            ..ServerConfig::default()
+        })
+        .await;
+        let (user, _) = register_account(&state, "user@example.com").await;
+        let (other, _) = register_account(&state, "other@example.com").await;
+        let tokens = state.auth_service.generate_token_pair(&user, None, None).await.unwrap();
+
+        let response = admin_app(&state)
+            .oneshot(authorized_request("GET", &format!("/api/admin/accounts/{}/sessions", other.id), &tokens.access_token))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::FORBIDDEN);
+
+        // 邮箱在管理员列表中但未验证的账户同样没有权限，避免有人注册管理员已释放的邮箱
+        let (unverified, _) = register_account(&state, "admin@example.com").await;
+        let tokens = state.auth_service.generate_token_pair(&unverified, None, None).await.unwrap();
+        let response = admin_app(&state)
+            .oneshot(authorized_request("GET", &format!("/api/admin/accounts/{}/sessions", other.id), &tokens.access_token))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_user_lists_and_revokes_own_sessions() {
+        use axum::http::StatusCode;
+        use tower::ServiceExt;
+
+        let state = test_state().await;
+        let (user, _) = register_account(&state, "user@example.com").await;
+        let (other, _) = register_account(&state, "other@example.com").await;
+
+        let laptop = state
+            .auth_service
+            .generate_token_pair(&user, Some("laptop".to_string()), Some("10.0.0.1".to_string()))
+            .await
+            .unwrap();
+        let phone = state
+            .auth_service
+            .generate_token_pair(&user, Some("phone".to_string()), None)
+            .await
+            .unwrap();
+        state.auth_service.generate_token_pair(&other, None, None).await.unwrap();
+        let other_session = state.auth_service.list_active_sessions(&other.id).await.unwrap().remove(0);
+
+        let app = create_protected_auth_routes()
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                auth::middleware::auth_middleware,
+            ))
+            .with_state(state.clone());
+        let list_sessions = || async {
+            let response = app
+                .clone()
+                .oneshot(authorized_request("GET", "/api/auth/sessions", &laptop.access_token))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            body["sessions"].as_array().unwrap().clone()
+        };
+
+        let sessions = list_sessions().await;
+        assert_eq!(sessions.len(), 2);
+        assert!(sessions.iter().any(|s| s["device_info"] == "laptop" && s["ip_address"] == "10.0.0.1"));
+        assert!(sessions.iter().all(|s| s.get("refresh_token_hash").is_none()));
+        let phone_session_id = sessions
+            .iter()
+            .find(|s| s["device_info"] == "phone")
+            .unwrap()["id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        // 刷新得到的访问令牌同样属于该会话
+        let phone_refreshed = state.auth_service.refresh_access_token(&phone.refresh_token).await.unwrap();
+
+        // 不能撤销其他账户的会话
+        let response = app
+            .clone()
+            .oneshot(authorized_request("DELETE", &format!("/api/auth/sessions/{}", other_session.id), &laptop.access_token))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(state.auth_service.list_active_sessions(&other.id).await.unwrap().len(), 1);
+
+        let response = app
+            .clone()
+            .oneshot(authorized_request("DELETE", &format!("/api/auth/sessions/{}", phone_session_id), &laptop.access_token))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // 被撤销的会话不再列出，其刷新令牌失效
+        let sessions = list_sessions().await;
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0]["device_info"], "laptop");
+        assert!(state.auth_service.refresh_access_token(&phone.refresh_token).await.is_err());
+
+        // 该会话签发的访问令牌立即失效，其他会话不受影响
+        for access_token in [&phone.access_token, &phone_refreshed.access_token] {
+            let response = app
+                .clone()
+                .oneshot(authorized_request("GET", "/api/auth/sessions", access_token))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+        assert!(matches!(
+            state.auth_service.verify_token(&phone.access_token, auth::TokenType::Access),
+            Err(auth::AuthError::TokenRevoked)
+        ));
+
+        let response = app
+            .oneshot(authorized_request("DELETE", &format!("/api/auth/sessions/{}", phone_session_id), &laptop.access_token))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_removes_expired_sessions_and_codes() {
+        let state = test_state().await;
+        let (account, _) = register_account(&state, "user@example.com").await;
+        let pool = state.auth_service.get_pool();
+        let past = (chrono::Utc::now() - chrono::Duration::hours(1)).to_rfc3339();
+        let future = (chrono::Utc::now() + chrono::Duration::hours(1)).to_rfc3339();
+
+        // 一个有效会话和一个已过期会话
+        state.auth_service.generate_token_pair(&account, None, None).await.unwrap();
+        sqlx::query(
+            "INSERT INTO sessions (id, account_id, refresh_token_hash, created_at, expires_at, last_used_at)
+             VALUES ('expired', $1, 'hash', $2, $3, $4)",
+        )
+        .bind(account.id.to_string())
+        .bind(&past)
+        .bind(&past)
+        .bind(&past)
+        .execute(pool)
+        .await
+        .unwrap();
+
+        // 过期、已使用和仍有效的验证码各一个
+        for (code, expires_at, used) in [("111111", &past, false), ("222222", &future, true), ("333333", &future, false)] {
+            sqlx::query(
+                "INSERT INTO email_verifications (email, code, purpose, expires_at, created_at, used)
+                 VALUES ('user@example.com', $1, 'email_verification', $2, $3, $4)",
+            )
+            .bind(code)
+            .bind(expires_at)
+            .bind(&past)
+            .bind(used)
+            .execute(pool)
+            .await
+            .unwrap();
+        }
+
+        let cleanup = state.auth_service.cleanup_expired().await.unwrap();
+        assert_eq!(cleanup, auth::ExpiredCleanup { sessions: 1, verification_codes: 2 });
+
+        let sessions = state.auth_service.list_sessions(&account.id).await.unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_ne!(sessions[0].id, "expired");
+        let codes: Vec<String> = sqlx::query_scalar("SELECT code FROM email_verifications")
+            .fetch_all(pool)
+            .await
+            .unwrap();
+        assert_eq!(codes, vec!["333333"]);
+
+        // 再次清理没有可删除的数据
+        let cleanup = state.auth_service.cleanup_expired().await.unwrap();
+        assert_eq!(cleanup, auth::ExpiredCleanup::default());
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_capacity_is_configurable_and_reported() {
+        use tower::ServiceExt;
+
+        let state = test_state_with_config(ServerConfig {
+            admin_emails: vec!["admin@example.com".to_string()],
+            broadcast_capacity: 4,
+            room_broadcast_capacity: 2,
+            ..ServerConfig::default()
+        })
+        .await;
+        let admin = register_admin(&state, "admin@example.com").await;
+        let tokens = state.auth_service.generate_token_pair(&admin, None, None).await.unwrap();
+
+        // 一个订阅者尚未读取的事件会积压在通道中
+        let _slow_subscriber = state.tx.subscribe();
+        for _ in 0..3 {
+            state.broadcast(WsEvent::Ping);
+        }
+        let room_id = room::RoomId::new();
+        let _room_subscriber = state.room_broadcast_manager.user_enter_room(UserId::new(), room_id).await;
+        state.room_broadcast_manager.broadcast_to_room(room_id, WsEvent::Ping).await.unwrap();
+
+        let response = admin_app(&state)
+            .oneshot(authorized_request("GET", "/api/admin/stats/broadcast", &tokens.access_token))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let stats = &body["stats"];
+        assert_eq!(stats["global"], serde_json::json!({ "capacity": 4, "queued": 3, "subscribers": 1 }));
+        assert_eq!(stats["rooms"]["channel_capacity"], 2);
+        assert_eq!(stats["rooms"]["max_queued"], 1);
+        assert_eq!(stats["rooms"]["total_subscribers"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_endpoint_reports_current_state() {
+        use tower::ServiceExt;
+
+        let state = test_state().await;
+        let (client, _rx) = test_client(&UserId::new(), None);
+        state.add_client(client).await;
+        let message = Message::new_text(UserId::new(), "hi".to_string(), None);
+        state.message_db.save_message(&message).await.unwrap();
+
+        let response = metrics::create_metrics_routes()
+            .with_state(state.clone())
+            .oneshot(axum::http::Request::get("/metrics").body(axum::body::Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        assert!(response.headers()[axum::http::header::CONTENT_TYPE].to_str().unwrap().starts_with("text/plain"));
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("\nrustchat_connected_clients 1\n"));
+        assert!(body.contains("\nrustchat_messages_total 1\n"));
+        assert!(body.contains("\nrustchat_rooms_total 0\n"));
+    }
+
+    #[tokio::test]
+    async fn test_health_endpoints_report_database_status() {
+        use tower::ServiceExt;
+
+        let state = test_state().await;
+        let (client, _rx) = test_client(&UserId::new(), None);
+        state.add_client(client).await;
+        let app = health::create_health_routes().with_state(state.clone());
+        let get = |uri: &str| axum::http::Request::get(uri).body(axum::body::Body::empty()).unwrap();
+
+        for uri in ["/health", "/health/ready"] {
+            let response = app.clone().oneshot(get(uri)).await.unwrap();
+            assert_eq!(response.status(), axum::http::StatusCode::OK);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["database"], "connected");
+            assert_eq!(body["connected_clients"], 1);
+            assert_eq!(body["rooms"], 0);
+            assert_eq!(body["server"]["version"], SERVER_VERSION);
+            assert!(body["server"]["uptime"].as_str().unwrap().ends_with('秒'));
+        }
+
+        // 数据库不可用时就绪检查返回503且不带出具体错误，存活检查不受影响
+        state.message_db.get_pool().close().await;
+        let response = app.clone().oneshot(get("/health/ready")).await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::SERVICE_UNAVAILABLE);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["database"], "unavailable");
+        assert!(body.get("error").is_none());
+        let response = app.oneshot(get("/health/live")).await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_openapi_document_is_served() {
+        use tower::ServiceExt;
+
+        let app = openapi::create_openapi_routes().with_state(test_state().await);
+
+        let response = app
+            .clone()
+            .oneshot(axum::http::Request::get("/api/openapi.json").body(axum::body::Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let doc: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(doc["paths"]["/api/auth/login"]["post"].is_object());
+        assert!(doc["components"]["securitySchemes"]["bearer_auth"].is_object());
+
+        let response = app
+            .oneshot(axum::http::Request::get("/api/docs/").body(axum::body::Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_direct_message_reaches_every_device() {
+        let state = test_state().await;
+        let account_id = AccountId::new();
+        let user_id = UserId::parse(&account_id.to_string()).unwrap();
+
+        let (laptop, mut laptop_rx) = test_client(&user_id, Some(account_id));
+        let (phone, mut phone_rx) = test_client(&user_id, Some(account_id));
+        let laptop_id = laptop.connection_id;
+        state.add_client(laptop).await;
+        state.add_client(phone).await;
+
+        assert_eq!(state.account_connections.lock().await[&account_id].len(), 2);
+
+        let dm = Message::new_text(UserId::new(), "hi".to_string(), None);
+        let delivered = state.send_to_user(&user_id, WsEvent::Message(dm.clone())).await;
+        assert_eq!(delivered, 2);
+
+        for rx in [&mut laptop_rx, &mut phone_rx] {
+            match rx.try_recv() {
+                Ok(WsEvent::Message(received)) => assert_eq!(received.id, dm.id),
+                other => panic!("未收到私信: {:?}", other),
+            }
+        }
+
+        // 断开一个设备后，另一个设备仍能收到
+        state.remove_client(&laptop_id).await;
+        let delivered = state.send_to_user(&user_id, WsEvent::Ping).await;
+        assert_eq!(delivered, 1);
+        assert!(matches!(phone_rx.try_recv(), Ok(WsEvent::Ping)));
+        assert!(laptop_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_account_index_cleared_after_last_connection() {
+        let state = test_state().await;
+        let account_id = AccountId::new();
+        let user_id = UserId::parse(&account_id.to_string()).unwrap();
+
+        let (first, _first_rx) = test_client(&user_id, Some(account_id));
+        let (second, _second_rx) = test_client(&user_id, Some(account_id));
+        let ids = [first.connection_id, second.connection_id];
+        state.add_client(first).await;
+        state.add_client(second).await;
+
+        for id in &ids {
+            state.remove_client(id).await;
+        }
+
+        assert!(state.clients.read().await.is_empty());
+        assert!(state.account_connections.lock().await.is_empty());
+        assert!(state.connections_for_user(&user_id).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_anonymous_connection_not_indexed() {
+        let state = test_state().await;
+        let user_id = UserId::new();
+
+        let (client, mut rx) = test_client(&user_id, None);
+        state.add_client(client).await;
+
+        assert!(state.account_connections.lock().await.is_empty());
+        assert_eq!(state.send_to_user(&user_id, WsEvent::Ping).await, 1);
+        assert!(matches!(rx.try_recv(), Ok(WsEvent::Ping)));
+    }
+
+    #[tokio::test]
+    async fn test_send_to_users_skips_offline_users() {
+        let state = test_state().await;
+        let (alice, bob, offline) = (UserId::new(), UserId::new(), UserId::new());
+
+        let (alice_client, mut alice_rx) = test_client(&alice, None);
+        let (bob_client, mut bob_rx) = test_client(&bob, None);
+        state.add_client(alice_client).await;
+        state.add_client(bob_client).await;
+
+        let delivered = state.send_to_users(&[alice, bob, offline], WsEvent::Ping).await;
+        assert_eq!(delivered, 2);
+        assert!(matches!(alice_rx.try_recv(), Ok(WsEvent::Ping)));
+        assert!(matches!(bob_rx.try_recv(), Ok(WsEvent::Ping)));
+    }
+
+    #[tokio::test]
+    async fn test_room_messages_endpoint_pages_with_cursor() {
+        use tower::ServiceExt;
+
+        let state = test_state().await;
+        let app = room::create_protected_room_routes()
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                auth::middleware::auth_middleware,
+            ))
+            .with_state(state.clone());
+
+        let (account, user_id) = register_account(&state, "pager@example.com").await;
+        let token = state.auth_service.generate_token_pair(&account, None, None).await.unwrap().access_token;
+        let room = state
+            .room_manager
+            .create_room(room::CreateRoomRequest { name: "paging".to_string(), description: None, max_members: None, persistent: true }, user_id.clone())
+            .await
+            .unwrap();
+
+        let start = chrono::Utc::now() - chrono::Duration::hours(1);
+        for index in 0..30 {
+            let mut message = Message::new_room_text(user_id.clone(), format!("{}", index), None, room.id.to_string());
+            message.timestamp = start + chrono::Duration::seconds(index);
+            state.message_db.save_message(&message).await.unwrap();
+        }
+
+        let get_page = |uri: String| {
+            let app = app.clone();
+            let token = token.clone();
+            async move {
+                let response = app.oneshot(authorized_request("GET", &uri, &token)).await.unwrap();
+                assert_eq!(response.status(), axum::http::StatusCode::OK);
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+            }
+        };
+        let contents = |body: &serde_json::Value| -> Vec<String> {
+            body["data"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|message| message["content"]["data"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        // 从最新的消息开始，每页10条向前翻
+        let mut uri = format!("/api/rooms/{}/messages?limit=10&order=newest", room.id);
+        let mut pages = Vec::new();
+        loop {
+            let body = get_page(uri.clone()).await;
+            pages.push(contents(&body));
+            match body["next_cursor"].as_str() {
+                Some(cursor) => uri = format!("/api/rooms/{}/messages?limit=10&before={}", room.id, cursor),
+                None => break,
+            }
+        }
+        let expected = |range: std::ops::Range<i64>| range.map(|index| index.to_string()).collect::<Vec<_>>();
+        assert_eq!(pages, vec![expected(20..30), expected(10..20), expected(0..10), Vec::new()]);
+
+        // 不指定分页方式时保持旧的行为，按偏移量从最早的消息开始
+        let body = get_page(format!("/api/rooms/{}/messages?limit=10", room.id)).await;
+        assert_eq!(contents(&body), expected(0..10));
+        assert!(body.get("next_cursor").is_none());
+        let body = get_page(format!("/api/rooms/{}/messages?limit=10&offset=10", room.id)).await;
+        assert_eq!(contents(&body), expected(10..20));
+        assert!(body.get("next_cursor").is_none());
+
+        // 游标不存在或属于其他房间时返回404，而不是看起来像已经翻到底的空页
+        let other = state
+            .room_manager
+            .create_room(room::CreateRoomRequest { name: "other".to_string(), description: None, max_members: None, persistent: true }, user_id.clone())
+            .await
+            .unwrap();
+        let foreign = Message::new_room_text(user_id.clone(), "elsewhere".to_string(), None, other.id.to_string());
+        state.message_db.save_message(&foreign).await.unwrap();
+        for cursor in [MessageId::new(), foreign.id.clone()] {
+            let uri = format!("/api/rooms/{}/messages?before={}", room.id, cursor);
+            let response = app.clone().oneshot(authorized_request("GET", &uri, &token)).await.unwrap();
+            assert_eq!(response.status(), axum::http::StatusCode::NOT_FOUND);
+        }
+        let uri = format!("/api/rooms/{}/messages?order=newest&offset=10", room.id);
+        let response = app.clone().oneshot(authorized_request("GET", &uri, &token)).await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_room_message_seen_count() {
+        use tower::ServiceExt;
+
+        let state = test_state().await;
+        let app = room::create_protected_room_routes()
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                auth::middleware::auth_middleware,
+            ))
+            .with_state(state.clone());
+
+        let mut members = Vec::new();
+        for email in ["owner@example.com", "a@example.com", "b@example.com", "c@example.com"] {
+            let (account, user_id) = register_account(&state, email).await;
+            let tokens = state.auth_service.generate_token_pair(&account, None, None).await.unwrap();
+            members.push((user_id, tokens.access_token));
+        }
+        let (outsider, _) = register_account(&state, "outsider@example.com").await;
+        let outsider_token = state.auth_service.generate_token_pair(&outsider, None, None).await.unwrap().access_token;
+
+        let room = state
+            .room_manager
+            .create_room(room::CreateRoomRequest { name: "team".to_string(), description: None, max_members: None, persistent: true }, members[0].0.clone())
+            .await
+            .unwrap();
+        for (user_id, _) in &members[1..] {
+            state.room_manager.join_room(room.id, user_id.clone()).await.unwrap();
+        }
+
+        // 三条消息，时间依次递增
+        let mut messages = Vec::new();
+        for (index, content) in ["first", "second", "third"].iter().enumerate() {
+            let mut message = Message::new_room_text(members[0].0.clone(), content.to_string(), None, room.id.to_string());
+            message.timestamp = chrono::Utc::now() - chrono::Duration::minutes(10 - index as i64);
+            state.message_db.save_message(&message).await.unwrap();
+            messages.push(message);
+        }
+
+        // owner未读，a读到第一条，b读到第二条，c读到第三条
+        for (member, read_up_to) in members[1..].iter().zip(&messages) {
+            let uri = format!("/api/rooms/{}/messages/{}/read", room.id, read_up_to.id);
+            let response = app.clone().oneshot(authorized_request("POST", &uri, &member.1)).await.unwrap();
+            assert_eq!(response.status(), axum::http::StatusCode::OK);
+        }
+        // 已读位置不会回退
+        let uri = format!("/api/rooms/{}/messages/{}/read", room.id, messages[0].id);
+        app.clone().oneshot(authorized_request("POST", &uri, &members[3].1)).await.unwrap();
+
+        for (message, expected) in messages.iter().zip([3, 2, 1]) {
+            let uri = format!("/api/rooms/{}/messages/{}/seen-count", room.id, message.id);
+            let response = app.clone().oneshot(authorized_request("GET", &uri, &members[0].1)).await.unwrap();
+            assert_eq!(response.status(), axum::http::StatusCode::OK);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["data"]["seen_count"], expected);
+            assert_eq!(body["data"]["member_count"], 4);
+        }
+
+        // 离开房间的成员不再计入
+        state.room_manager.leave_room(room.id, members[3].0.clone()).await.unwrap();
+        let uri = format!("/api/rooms/{}/messages/{}/seen-count", room.id, messages[2].id);
+        let response = app.clone().oneshot(authorized_request("GET", &uri, &members[0].1)).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["data"]["seen_count"], 0);
+
+        // 非成员无权查看
+        let response = app.clone().oneshot(authorized_request("GET", &uri, &outsider_token)).await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_global_history_endpoint_pages_and_respects_anonymous_setting() {
+        use tower::ServiceExt;
+
+        async fn get_json(app: Router, request: axum::http::Request<axum::body::Body>) -> (axum::http::StatusCode, serde_json::Value) {
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, serde_json::from_slice(&body).unwrap_or_default())
+        }
+        fn history_app(state: &AppState) -> Router {
+            history::create_history_routes()
+                .layer(axum::middleware::from_fn_with_state(
+                    state.clone(),
+                    auth::middleware::optional_auth_middleware,
+                ))
+                .with_state(state.clone())
+        }
+        let anonymous = |uri: &str| axum::http::Request::builder().uri(uri).body(axum::body::Body::empty()).unwrap();
+
+        let state = test_state().await;
+        let user_id = UserId::new();
+        let mut messages = Vec::new();
+        for index in 0..3 {
+            let mut message = Message::new_text(user_id.clone(), format!("global {}", index), None);
+            message.timestamp = chrono::Utc::now() - chrono::Duration::minutes(10 - index);
+            state.message_db.save_message(&message).await.unwrap();
+            messages.push(message);
+        }
+        let room_message = Message::new_room_text(user_id.clone(), "room".to_string(), None, room::RoomId::new().to_string());
+        state.message_db.save_message(&room_message).await.unwrap();
+
+        let (status, body) = get_json(history_app(&state), anonymous("/api/messages?limit=2")).await;
+        assert_eq!(status, axum::http::StatusCode::OK);
+        let contents: Vec<_> = body["data"].as_array().unwrap().iter().map(|m| m["content"]["data"].clone()).collect();
+        assert_eq!(contents, vec!["global 1", "global 2"]);
+
+        let before = messages[1].timestamp.to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+        let (_, body) = get_json(history_app(&state), anonymous(&format!("/api/messages?before={}", before))).await;
+        assert_eq!(body["data"].as_array().unwrap().len(), 1);
+        assert_eq!(body["data"][0]["content"]["data"], "global 0");
+
+        // 断线补齐：返回之后接收的消息，最早的在前
+        let after = (messages[0].timestamp + chrono::Duration::seconds(1)).to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+        let (_, body) = get_json(history_app(&state), anonymous(&format!("/api/messages?after={}&limit=1", after))).await;
+        assert_eq!(body["data"].as_array().unwrap().len(), 1);
+        assert_eq!(body["data"][0]["content"]["data"], "global 1");
+        let (status, _) = get_json(history_app(&state), anonymous(&format!("/api/messages?after={}&before={}", after, before))).await;
+        assert_eq!(status, axum::http::StatusCode::BAD_REQUEST);
+
+        // 禁用匿名访问时需要登录
+        let state = test_state_with_config(ServerConfig {
+            allow_anonymous: false,
+            ..ServerConfig::default()
+        })
+        .await;
+        let (status, _) = get_json(history_app(&state), anonymous("/api/messages")).await;
+        assert_eq!(status, axum::http::StatusCode::UNAUTHORIZED);
+
+        let (account, _) = register_account(&state, "history@example.com").await;
+        let token = state.auth_service.generate_token_pair(&account, None, None).await.unwrap().access_token;
+        let (status, _) = get_json(history_app(&state), authorized_request("GET", "/api/messages", &token)).await;
+        assert_eq!(status, axum::http::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_websocket_rejects_anonymous_when_disabled() {
+        use futures_util::StreamExt;
+        use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+        use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+        use tokio_tungstenite::tungstenite::Message as WsFrame;
+
+        let state = test_state_with_config(ServerConfig {
+            allow_anonymous: false,
+            ..ServerConfig::default()
+        })
+        .await;
+        let app = Router::new().route("/ws", get(websocket_handler)).with_state(state.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/ws", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        // 匿名连接完成握手后立即收到策略违规的关闭帧
+        let (mut socket, _) = tokio_tungstenite::connect_async(url.as_str()).await.unwrap();
+        match socket.next().await {
+            Some(Ok(WsFrame::Close(Some(frame)))) => assert_eq!(frame.code, CloseCode::Policy),
+            other => panic!("匿名连接应被关闭，实际收到 {:?}", other),
+        }
+        assert!(state.clients.read().await.is_empty());
+
+        // 携带有效令牌的连接被接受，首先收到 Connected 事件
+        let (account, user_id) = register_account(&state, "ws@example.com").await;
+        let token = state.auth_service.generate_token_pair(&account, None, None).await.unwrap().access_token;
+        let mut request = url.as_str().into_client_request().unwrap();
+        request.headers_mut().insert("Authorization", format!("Bearer {}", token).parse().unwrap());
+        let (mut socket, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+        match socket.next().await {
+            Some(Ok(WsFrame::Text(text))) => match serde_json::from_str::<WsEvent>(&text).unwrap() {
+                WsEvent::Connected { user_id: connected, .. } => assert_eq!(connected, user_id),
+                other => panic!("认证连接应先收到 Connected，实际收到 {:?}", other),
+            },
+            other => panic!("认证连接应被接受，实际收到 {:?}", other),
+        }
+        // 连接建立事件在登记客户端之前发出，等待登记完成
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while state.connections_for_user(&user_id).await.is_empty() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("认证连接应被登记");
+        assert_eq!(state.connections_for_user(&user_id).await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_cluster_relay_publishes_and_ignores_own_events() {
+        let state = test_state().await;
+        let mut outbound = state.relay.attach().expect("中继只能接入一次");
+        assert!(state.relay.attach().is_none());
+        let mut local = state.tx.subscribe();
+
+        // 本地广播同时发布给其他实例
+        let message = Message::new_text(UserId::new(), "hello".to_string(), None);
+        state.broadcast(WsEvent::Message(message.clone()));
+        let envelope = outbound.try_recv().unwrap();
+        assert_eq!(envelope.origin, state.relay.instance_id());
+        assert!(envelope.room_id.is_none());
+        assert!(matches!(local.try_recv(), Ok(WsEvent::Message(_))));
+
+        let room_id = room::RoomId::new();
+        let mut room_rx = state.room_broadcast_manager.user_enter_room(UserId::new(), room_id).await.unwrap();
+        state.room_broadcast_manager.broadcast_to_room(room_id, WsEvent::Ping).await.unwrap();
+        assert_eq!(outbound.try_recv().unwrap().room_id, Some(room_id));
+        assert!(matches!(room_rx.try_recv(), Ok(WsEvent::Ping)));
+
+        // 自己发布的事件被忽略
+        assert!(!cluster::deliver_remote(&state, envelope.clone()).await);
+        assert!(local.try_recv().is_err());
+
+        // 其他实例的事件投递给本地客户端，且不会再次发布
+        let remote = cluster::RelayEnvelope { origin: "other".to_string(), ..envelope };
+        assert!(cluster::deliver_remote(&state, remote).await);
+        match local.try_recv() {
+            Ok(WsEvent::Message(received)) => assert_eq!(received.id, message.id),
+            other => panic!("应投递远程消息，实际: {:?}", other),
+        }
+        let remote_room = cluster::RelayEnvelope { origin: "other".to_string(), room_id: Some(room_id), event: WsEvent::Pong };
+        assert!(cluster::deliver_remote(&state, remote_room).await);
+        assert!(matches!(room_rx.try_recv(), Ok(WsEvent::Pong)));
+        assert!(outbound.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_create_message_endpoint() {
+        use tower::ServiceExt;
+
+        let state = test_state().await;
+        let app = history::create_history_routes()
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                auth::middleware::optional_auth_middleware,
+            ))
+            .merge(history::create_protected_history_routes().layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                auth::middleware::auth_middleware,
+            )))
+            .with_state(state.clone());
+        let post = |token: Option<&str>, body: serde_json::Value| {
+            let mut builder = axum::http::Request::builder()
+                .method("POST")
+                .uri("/api/messages")
+                .header(axum::http::header::CONTENT_TYPE, "application/json");
+            if let Some(token) = token {
+                builder = builder.header(axum::http::header::AUTHORIZATION, format!("Bearer {}", token));
+            }
+            builder.body(axum::body::Body::from(body.to_string())).unwrap()
+        };
+
+        let (account, user_id) = register_account(&state, "ci@example.com").await;
+        let token = state.auth_service.generate_token_pair(&account, None, None).await.unwrap().access_token;
+        let mut events = state.tx.subscribe();
+        let mut bot_replies = state.message_tx.subscribe();
+
+        let response = app
+            .clone()
+            .oneshot(post(Some(&token), serde_json::json!({ "content": "!flip", "nickname": " CI " })))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["success"], true);
+        assert_eq!(body["data"]["from"], user_id.to_string());
+        assert_eq!(body["data"]["from_nick"], "CI");
+
+        let saved = state.message_db.get_recent_messages(1).await.unwrap();
+        assert_eq!(saved[0].id.to_string(), body["data"]["id"].as_str().unwrap());
+        assert!(saved[0].received_at.is_some());
+        match events.try_recv() {
+            Ok(WsEvent::Message(message)) => assert_eq!(message.id, saved[0].id),
+            other => panic!("应广播新消息，实际: {:?}", other),
+        }
+        assert!(bot_replies.try_recv().is_ok(), "机器人应处理REST发送的消息");
+
+        // 超长内容被拒绝且不保存
+        let response = app
+            .clone()
+            .oneshot(post(Some(&token), serde_json::json!({ "content": "x".repeat(rustchat_types::validation::MAX_CONTENT_CHARS + 1) })))
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["success"], false);
+        assert_eq!(state.message_db.get_recent_messages(10).await.unwrap().len(), 1);
+
+        // 必须登录
+        let response = app.oneshot(post(None, serde_json::json!({ "content": "hi" }))).await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_user_stats_endpoint() {
+        use tower::ServiceExt;
+
+        let state = test_state().await;
+        let app = history::create_history_routes()
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                auth::middleware::optional_auth_middleware,
+            ))
+            .with_state(state.clone());
+        let get = |uri: String| axum::http::Request::builder().uri(uri).body(axum::body::Body::empty()).unwrap();
+
+        let user_id = UserId::new();
+        state.message_db.save_message(&Message::new_text(user_id.clone(), "hi".to_string(), None)).await.unwrap();
+        state.message_db.save_message(&Message::new_action(user_id.clone(), "waves".to_string(), None)).await.unwrap();
+
+        let response = app.clone().oneshot(get(format!("/api/users/{}/stats", user_id))).await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["data"]["total_messages"], 2);
+        assert_eq!(body["data"]["by_type"]["action"], 1);
+
+        // 没有消息的用户返回空统计
+        let response = app.clone().oneshot(get(format!("/api/users/{}/stats", UserId::new()))).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["data"]["total_messages"], 0);
+        assert!(body["data"]["first_message_at"].is_null());
+
+        let response = app.oneshot(get("/api/users/not-a-uuid/stats".to_string())).await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_user_profile_endpoint() {
+        use tower::ServiceExt;
+
+        let state = test_state().await;
+        let app = profile::create_profile_routes()
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                auth::middleware::optional_auth_middleware,
+            ))
+            .with_state(state.clone());
+        let get = |uri: String| axum::http::Request::builder().uri(uri).body(axum::body::Body::empty()).unwrap();
+        let read_body = |response: axum::response::Response| async move {
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        };
+
+        // 离线的账户用户：显示名称来自账户表
+        let account = state
+            .auth_service
+            .register("dave@example.com".to_string(), "secret123".to_string(), Some("Dave".to_string()))
+            .await
+            .unwrap();
+        let account_user = UserId::parse(&account.id.to_string()).unwrap();
+        let response = app.clone().oneshot(get(format!("/api/users/{}", account_user))).await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        let body = read_body(response).await;
+        assert_eq!(body["data"]["display_name"], "Dave");
+        assert_eq!(body["data"]["is_online"], false);
+        assert!(body["data"]["nickname"].is_null());
+        let first_seen: chrono::DateTime<chrono::Utc> = serde_json::from_value(body["data"]["first_seen_at"].clone()).unwrap();
+        assert_eq!(first_seen, account.created_at);
+
+        // 在线的匿名用户：昵称来自当前连接
+        let anonymous = UserId::new();
+        let (mut client, _rx) = test_client(&anonymous, None);
+        client.nickname = Some("guest".to_string());
+        state.add_client(client).await;
+        let body = read_body(app.clone().oneshot(get(format!("/api/users/{}", anonymous))).await.unwrap()).await;
+        assert_eq!(body["data"]["user_id"], anonymous.to_string());
+        assert_eq!(body["data"]["nickname"], "guest");
+        assert_eq!(body["data"]["is_online"], true);
+        assert!(body["data"]["display_name"].is_null());
+
+        // 离线但发过消息的匿名用户：首次出现时间来自最早的消息
+        let mut message = Message::new_text(UserId::new(), "earlier".to_string(), None);
+        message.timestamp = chrono::Utc::now() - chrono::Duration::days(1);
+        state.message_db.save_message(&message).await.unwrap();
+        let body = read_body(app.clone().oneshot(get(format!("/api/users/{}", message.from))).await.unwrap()).await;
+        assert_eq!(body["data"]["is_online"], false);
+        let first_seen: chrono::DateTime<chrono::Utc> = serde_json::from_value(body["data"]["first_seen_at"].clone()).unwrap();
+        assert_eq!(first_seen, message.timestamp);
+
+        let response = app.clone().oneshot(get(format!("/api/users/{}", UserId::new()))).await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::NOT_FOUND);
+
+        let response = app.oneshot(get("/api/users/not-a-uuid".to_string())).await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_non_persistent_room_keeps_no_history() {
+        use tower::ServiceExt;
+
+        let state = test_state().await;
+        let app = room::create_protected_room_routes()
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                auth::middleware::auth_middleware,
+            ))
+            .with_state(state.clone());
+        let (account, user_id) = register_account(&state, "support@example.com").await;
+        let token = state.auth_service.generate_token_pair(&account, None, None).await.unwrap().access_token;
+        let json_request = |method: &str, uri: String, body: serde_json::Value| {
+            axum::http::Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(axum::http::header::AUTHORIZATION, format!("Bearer {}", token))
+                .header(axum::http::header::CONTENT_TYPE, "application/json")
+                .body(axum::body::Body::from(body.to_string()))
+                .unwrap()
+        };
+        let read_body = |response: axum::response::Response| async move {
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        };
+
+        let body = read_body(
+            app.clone()
+                .oneshot(json_request("POST", "/api/rooms".to_string(), serde_json::json!({ "name": "support", "persistent": false })))
+                .await
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(body["data"]["persistent"], false);
+        let room_id = body["data"]["id"].as_str().unwrap().to_string();
+
+        // 通过HTTP和WebSocket发送的房间消息都不保存
+        let uri = format!("/api/rooms/{}/messages", room_id);
+        let body = read_body(app.clone().oneshot(json_request("POST", uri.clone(), serde_json::json!({ "content": "via http" }))).await.unwrap()).await;
+        assert_eq!(body["success"], true);
+        let (client, _rx) = test_client(&user_id, None);
+        let connection_id = client.connection_id;
+        state.add_client(client).await;
+        let text = serde_json::json!({ "type": "SendRoomMessage", "data": { "room_id": room_id, "content": "via ws" } }).to_string();
+        handle_client_message(&text, &connection_id, &user_id, &state).await.unwrap();
+
+        assert_eq!(state.message_db.get_message_count().await.unwrap(), 0);
+        let body = read_body(app.oneshot(authorized_request("GET", &uri, &token)).await.unwrap()).await;
+        assert_eq!(body["data"].as_array().unwrap().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_room_member_cap_holds_for_concurrent_joins() {
+        use tower::ServiceExt;
+
+        let state = test_state().await;
+        let app = room::create_protected_room_routes()
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                auth::middleware::auth_middleware,
+            ))
+            .with_state(state.clone());
+
+        let owner = UserId::new();
+        let room = state
+            .room_manager
+            .create_room(room::CreateRoomRequest { name: "small".to_string(), description: None, max_members: Some(4), persistent: true }, owner.clone())
+            .await
+            .unwrap();
+
+        // 同时通过HTTP和WebSocket加入，只剩3个名额
+        let mut joins = Vec::new();
+        for index in 0..4 {
+            let (account, _) = register_account(&state, &format!("joiner{}@example.com", index)).await;
+            let token = state.auth_service.generate_token_pair(&account, None, None).await.unwrap().access_token;
+            let app = app.clone();
+            let uri = format!("/api/rooms/{}/join", room.id);
+            joins.push(tokio::spawn(async move {
+                let response = app.oneshot(authorized_request("POST", &uri, &token)).await.unwrap();
+                match response.status() {
+                    axum::http::StatusCode::OK => true,
+                    axum::http::StatusCode::CONFLICT => false,
+                    status => panic!("unexpected status: {}", status),
+                }
+            }));
+        }
+        let mut receivers = Vec::new();
+        for _ in 0..8 {
+            let user_id = UserId::new();
+            let (client, rx) = test_client(&user_id, None);
+            let connection_id = client.connection_id;
+            state.add_client(client).await;
+            receivers.push(rx);
+
+            let state = state.clone();
+            let text = serde_json::json!({ "type": "JoinRoom", "data": { "room_id": room.id.to_string() } }).to_string();
+            joins.push(tokio::spawn(async move {
+                match handle_client_message(&text, &connection_id, &user_id, &state).await {
+                    Ok(()) => true,
+                    Err(err) => {
+                        assert_eq!(err.downcast_ref::<ClientError>().map(|e| e.code), Some(ErrorCode::RoomFull));
+                        false
+                    }
+                }
+            }));
+        }
+
+        let joined = futures_util::future::join_all(joins).await.into_iter().filter(|joined| *joined.as_ref().unwrap()).count();
+        assert_eq!(joined, 3);
+        assert_eq!(state.room_manager.get_room(room.id).await.unwrap().member_count(), 4);
+
+        // 已是成员的用户再次加入满员房间不会被当作新成员拒绝
+        assert!(matches!(
+            state.room_manager.join_room(room.id, owner).await,
+            Err(room::RoomError::UserAlreadyInRoom)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_room_join_and_leave_are_confirmed_to_connection() {
+        let state = test_state().await;
+        let user_id = UserId::new();
+        let (client, mut rx) = test_client(&user_id, None);
+        let connection_id = client.connection_id;
+        state.add_client(client).await;
+
+        let room = state
+            .room_manager
+            .create_room(room::CreateRoomRequest { name: "lobby".to_string(), description: None, max_members: None, persistent: true }, UserId::new())
+            .await
+            .unwrap();
+        let room_id = room.id.to_string();
+
+        let drain = |rx: &mut tokio::sync::mpsc::UnboundedReceiver<WsEvent>| {
+            std::iter::from_fn(|| rx.try_recv().ok()).collect::<Vec<_>>()
+        };
+        let join = serde_json::json!({ "type": "JoinRoom", "data": { "room_id": room_id } }).to_string();
+
+        // 首次加入和已是成员时再次加入都会收到带房间信息的确认
+        for _ in 0..2 {
+            handle_client_message(&join, &connection_id, &user_id, &state).await.unwrap();
+            let joined = drain(&mut rx)
+                .into_iter()
+                .find_map(|event| match event {
+                    WsEvent::RoomJoined { room } => Some(room),
+                    _ => None,
+                })
+                .expect("missing RoomJoined");
+            assert_eq!(joined.id, room_id);
+            assert_eq!(joined.name, "lobby");
+            assert_eq!(joined.member_count, 2);
+            assert!(joined.is_member);
+        }
+
+        let leave = serde_json::json!({ "type": "LeaveRoom", "data": { "room_id": room_id } }).to_string();
+        handle_client_message(&leave, &connection_id, &user_id, &state).await.unwrap();
+        assert!(drain(&mut rx).iter().any(|event| matches!(event, WsEvent::RoomLeft { room_id: left } if *left == room_id)));
+    }
+
+    #[tokio::test]
+    async fn test_room_message_task_forwards_every_pending_message() {
+        let state = test_state().await;
+        let user_id = UserId::new();
+        let (client, mut rx) = test_client(&user_id, None);
+        let connection_id = client.connection_id;
+        let sender = client.sender.clone();
+        state.add_client(client).await;
+
+        let room = state
+            .room_manager
+            .create_room(room::CreateRoomRequest { name: "lobby".to_string(), description: None, max_members: None, persistent: true }, UserId::new())
+            .await
+            .unwrap();
+        let join = serde_json::json!({ "type": "JoinRoom", "data": { "room_id": room.id.to_string() } }).to_string();
+        handle_client_message(&join, &connection_id, &user_id, &state).await.unwrap();
+        while rx.try_recv().is_ok() {}
+
+        // 一次性发出的消息在一两次检查内全部转发，而不是每次检查只转发一条
+        let task = tokio::spawn(room_message_task(connection_id, user_id.clone(), state.clone(), sender));
+        for i in 0..50 {
+            let message = Message::new_room_text(UserId::new(), i.to_string(), None, room.id.to_string());
+            state.room_message_router.broadcast_to_room(room.id, message).await.unwrap();
+        }
+        let received = tokio::time::timeout(Duration::from_secs(1), async {
+            let mut contents = Vec::new();
+            while contents.len() < 50 {
+                if let Some(WsEvent::Message(message)) = rx.recv().await {
+                    contents.push(message.get_text().unwrap().to_string());
+                }
+            }
+            contents
+        })
+        .await
+        .expect("room messages were not forwarded in time");
+        assert_eq!(received, (0..50).map(|i| i.to_string()).collect::<Vec<_>>());
+
+        // 离开房间后接收器被移除，连接断开后任务结束
+        let leave = serde_json::json!({ "type": "LeaveRoom", "data": { "room_id": room.id.to_string() } }).to_string();
+        handle_client_message(&leave, &connection_id, &user_id, &state).await.unwrap();
+        assert!(state.room_receiver_slot(&connection_id).await.unwrap().lock().await.is_none());
+        state.remove_client(&connection_id).await;
+        tokio::time::timeout(Duration::from_secs(1), task).await.unwrap().unwrap();
+    }
+
+    /// 按用户ID遍历连接表，与 `client_snapshot` 和匿名用户的 `connections_for_user` 的查找方式相同
+    fn count_user_connections(clients: &HashMap<ConnectionId, ConnectedClient>, user_id: &UserId) -> usize {
+        clients.values().filter(|client| &client.user_id == user_id).count()
+    }
+
+    /// 多个任务并发执行查找，另有一个任务不断增删连接，返回每次查找的耗时
+    async fn measure_lookups<L, LF, W, WF>(readers: usize, lookups: usize, user_ids: Arc<Vec<UserId>>, lookup: L, churn: W) -> Vec<Duration>
+    where
+        L: Fn(UserId) -> LF + Clone + Send + 'static,
+        LF: std::future::Future<Output = usize> + Send,
+        W: Fn() -> WF + Send + 'static,
+        WF: std::future::Future<Output = ()> + Send,
+    {
+        let done = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let writer = tokio::spawn({
+            let done = done.clone();
+            async move {
+                while !done.load(std::sync::atomic::Ordering::Relaxed) {
+                    churn().await;
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                }
+            }
+        });
+
+        let tasks: Vec<_> = (0..readers)
+            .map(|reader| {
+                let lookup = lookup.clone();
+                let user_ids = user_ids.clone();
+                tokio::spawn(async move {
+                    let mut latencies = Vec::with_capacity(lookups);
+                    for i in 0..lookups {
+                        let user_id = user_ids[(reader * lookups + i) % user_ids.len()].clone();
+                        let started = Instant::now();
+                        assert_eq!(lookup(user_id).await, 1);
+                        latencies.push(started.elapsed());
+                    }
+                    latencies
+                })
+            })
+            .collect();
+
+        let mut latencies = Vec::with_capacity(readers * lookups);
+        for task in tasks {
+            latencies.extend(task.await.unwrap());
+        }
+        done.store(true, std::sync::atomic::Ordering::Relaxed);
+        writer.await.unwrap();
+        latencies.sort();
+        latencies
+    }
+
+    /// 连接表使用读写锁和互斥锁时，并发查找在线用户的延迟对比
+    ///
+    /// 同一份连接表分别放在 `AppState` 的读写锁和一个互斥锁中，执行相同的查找和增删，输出平均和P99延迟。
+    /// 运行方式：`cargo test -p rustchat-server --release -- --ignored bench_concurrent_client_lookups --nocapture`
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    #[ignore]
+    async fn bench_concurrent_client_lookups() {
+        const CONNECTIONS: usize = 2000;
+        const READERS: usize = 64;
+        const LOOKUPS: usize = 200;
+
+        let state = test_state().await;
+        let mut user_ids = Vec::with_capacity(CONNECTIONS);
+        let mut receivers = Vec::with_capacity(CONNECTIONS);
+        for _ in 0..CONNECTIONS {
+            let user_id = UserId::new();
+            let (client, rx) = test_client(&user_id, None);
+            state.add_client(client).await;
+            user_ids.push(user_id);
+            receivers.push(rx);
+        }
+        let user_ids = Arc::new(user_ids);
+        let mutex_clients = Arc::new(Mutex::new(state.clients.read().await.clone()));
+
+        let rwlock = measure_lookups(
+            READERS,
+            LOOKUPS,
+            user_ids.clone(),
+            {
+                let clients = state.clients.clone();
+                move |user_id| {
+                    let clients = clients.clone();
+                    async move { count_user_connections(&*clients.read().await, &user_id) }
+                }
+            },
+            {
+                let clients = state.clients.clone();
+                move || {
+                    let clients = clients.clone();
+                    async move {
+                        let (client, _) = test_client(&UserId::new(), None);
+                        let connection_id = client.connection_id;
+                        clients.write().await.insert(connection_id, client);
+                        clients.write().await.remove(&connection_id);
+                    }
+                }
+            },
+        )
+        .await;
+
+        let mutex = measure_lookups(
+            READERS,
+            LOOKUPS,
+            user_ids,
+            {
+                let clients = mutex_clients.clone();
+                move |user_id| {
+                    let clients = clients.clone();
+                    async move { count_user_connections(&*clients.lock().await, &user_id) }
+                }
+            },
+            {
+                let clients = mutex_clients.clone();
+                move || {
+                    let clients = clients.clone();
+                    async move {
+                        let (client, _) = test_client(&UserId::new(), None);
+                        let connection_id = client.connection_id;
+                        clients.lock().await.insert(connection_id, client);
+                        clients.lock().await.remove(&connection_id);
+                    }
+                }
+            },
+        )
+        .await;
+
+        for (name, latencies) in [("读写锁", &rwlock), ("互斥锁", &mutex)] {
+            let mean = latencies.iter().sum::<Duration>() / latencies.len() as u32;
+            let p99 = latencies[latencies.len() * 99 / 100];
+            println!("{}: {}个连接、{}个并发任务，平均 {:?}，P99 {:?}", name, CONNECTIONS, READERS, mean, p99);
+        }
+        drop(receivers);
+    }
+
+    #[tokio::test]
+    async fn test_mark_read_updates_unread_count_in_user_rooms() {
+        use tower::ServiceExt;
+
+        let state = test_state().await;
+        let app = room::create_protected_room_routes()
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                auth::middleware::auth_middleware,
+            ))
+            .with_state(state.clone());
+
+        let (account, user_id) = register_account(&state, "reader@example.com").await;
+        let token = state.auth_service.generate_token_pair(&account, None, None).await.unwrap().access_token;
+        let (client, _rx) = test_client(&user_id, Some(account.id));
+        let connection_id = client.connection_id;
+        state.add_client(client).await;
+
+        let owner = UserId::new();
+        let room = state
+            .room_manager
+            .create_room(room::CreateRoomRequest { name: "news".to_string(), description: None, max_members: None, persistent: true }, owner.clone())
+            .await
+            .unwrap();
+        state.room_manager.join_room(room.id, user_id.clone()).await.unwrap();
+
+        let mut messages = Vec::new();
+        for index in 0..3 {
+            let mut message = Message::new_room_text(owner.clone(), format!("news {}", index), None, room.id.to_string());
+            message.timestamp = chrono::Utc::now() - chrono::Duration::minutes(10 - index);
+            state.message_db.save_message(&message).await.unwrap();
+            messages.push(message);
+        }
+
+        let unread_count = |app: Router| {
+            let token = token.clone();
+            async move {
+                let response = app.oneshot(authorized_request("GET", "/api/user/rooms", &token)).await.unwrap();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                body["data"][0]["unread_count"].clone()
+            }
+        };
+
+        // 从未读过时全部未读
+        assert_eq!(unread_count(app.clone()).await, 3);
+
+        let text = serde_json::json!({
+            "type": "MarkRead",
+            "data": { "room_id": room.id.to_string(), "message_id": messages[1].id }
+        })
+        .to_string();
+        handle_client_message(&text, &connection_id, &user_id, &state)
+            .await
+            .unwrap();
+        assert_eq!(unread_count(app.clone()).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_friend_routes_require_authentication() {
+        use axum::body::Body;
+        use axum::http::{header, Request, StatusCode};
+        use tower::ServiceExt;
+
+        let state = test_state().await;
+        let (alice, alice_id) = register_account(&state, "alice@example.com").await;
+        let (bob, bob_id) = register_account(&state, "bob@example.com").await;
+        let (mallory, _) = register_account(&state, "mallory@example.com").await;
+        let token = |account: &auth::Account| {
+            let state = state.clone();
+            let account = account.clone();
+            async move { state.auth_service.generate_token_pair(&account, None, None).await.unwrap().access_token }
+        };
+        let alice_token = token(&alice).await;
+        let bob_token = token(&bob).await;
+        let mallory_token = token(&mallory).await;
+
+        let app = Router::new()
+            .nest("/api/friends", create_friend_routes()
+                .layer(axum::middleware::from_fn_with_state(
+                    state.clone(),
+                    auth::middleware::auth_middleware,
+                )))
+            .with_state(state.clone());
+        let json_request = |method: &str, uri: &str, access_token: Option<&str>, body: serde_json::Value| {
+            let mut request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(header::CONTENT_TYPE, "application/json");
+            if let Some(access_token) = access_token {
+                request = request.header(header::AUTHORIZATION, format!("Bearer {}", access_token));
+            }
+            request.body(Body::from(body.to_string())).unwrap()
+        };
+
+        // 没有令牌时，即使在查询参数中指定用户也会被拒绝
+        let response = app
+            .clone()
+            .oneshot(Request::get(format!("/api/friends/list?user_id={}", alice_id)).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = app
+            .clone()
+            .oneshot(json_request("POST", "/api/friends/request", None, serde_json::json!({ "to_user_id": bob_id })))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        // 发送者取自令牌，伪造的 user_id 查询参数不起作用
+        let response = app
+            .clone()
+            .oneshot(json_request(
+                "POST",
+                &format!("/api/friends/request?user_id={}", bob_id),
+                Some(&alice_token),
+                serde_json::json!({ "to_user_id": bob_id }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let request: rustchat_types::FriendRequest = serde_json::from_slice(&body).unwrap();
+        assert_eq!(request.from_user_id, alice_id);
+
+        // 只有接收者可以接受请求
+        let respond = |access_token: &str| {
+            json_request(
+                "POST",
+                "/api/friends/request/respond",
+                Some(access_token),
+                serde_json::json!({ "request_id": request.id, "accept": true }),
+            )
+        };
+        let response = app.clone().oneshot(respond(&mallory_token)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = app.clone().oneshot(respond(&bob_token)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app
+            .oneshot(authorized_request("GET", "/api/friends/list", &bob_token))
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let friends: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(friends, serde_json::json!([{ "user_id": alice_id, "alias": null }]));
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_uses_configured_intervals() {
+        let invalid = ServerConfig {
+            heartbeat_interval: Duration::from_secs(30),
+            heartbeat_timeout: Duration::from_secs(10),
+            ..ServerConfig::default()
+        };
+        let message_db = MessageDatabase::new_in_memory().await.unwrap();
+        assert!(AppState::with_database(invalid, message_db).await.is_err());
+
+        let state = test_state_with_config(ServerConfig {
+            heartbeat_interval: Duration::from_millis(20),
+            heartbeat_timeout: Duration::from_millis(60),
+            ..ServerConfig::default()
+        })
+        .await;
+        let user_id = UserId::new();
+        let (client, mut rx) = test_client(&user_id, None);
+        let connection_id = client.connection_id;
+        state.add_client(client).await;
+
+        // 客户端从不回复Pong，超时后被断开
+        time::timeout(Duration::from_secs(2), heartbeat_task(connection_id, user_id, state.clone()))
+            .await
+            .expect("心跳任务应在超时后结束");
+        assert!(matches!(rx.try_recv(), Ok(WsEvent::Ping)));
+        assert!(!state.clients.read().await.contains_key(&connection_id));
+    }
+
+    #[tokio::test]
+    async fn test_activity_resets_idle_clock() {
+        let state = test_state_with_config(ServerConfig {
+            heartbeat_interval: Duration::from_millis(20),
+            heartbeat_timeout: Duration::from_secs(10),
+            idle_timeout: Some(Duration::from_millis(300)),
+            ..ServerConfig::default()
+        })
+        .await;
+        let user_id = UserId::new();
+        let (client, mut rx) = test_client(&user_id, None);
+        let connection_id = client.connection_id;
+        let last_activity = client.last_activity.clone();
+        state.add_client(client).await;
+
+        // 心跳Pong不算活动，其他客户端消息重置空闲时间
+        *last_activity.lock().await = Instant::now() - Duration::from_millis(250);
+        let mut limiter = MessageRateLimiter::new(0, 1, Instant::now());
+        handle_incoming_text(r#"{"type":"Pong"}"#, &mut limiter, Instant::now(), &connection_id, &user_id, &state).await;
+        assert!(last_activity.lock().await.elapsed() >= Duration::from_millis(250));
+        handle_incoming_text(r#"{"type":"ListMyRooms"}"#, &mut limiter, Instant::now(), &connection_id, &user_id, &state).await;
+        assert!(last_activity.lock().await.elapsed() < Duration::from_millis(100));
+        while rx.try_recv().is_ok() {}
+
+        // 之后一直没有活动：先收到警告，超时后被断开
+        time::timeout(Duration::from_secs(2), heartbeat_task(connection_id, user_id, state.clone()))
+            .await
+            .expect("心跳任务应在空闲超时后结束");
+        let codes: Vec<ErrorCode> = std::iter::from_fn(|| rx.try_recv().ok())
+            .filter_map(|event| match event {
+                WsEvent::Error { code, .. } => Some(code),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(codes, vec![ErrorCode::IdleWarning, ErrorCode::IdleTimeout]);
+        assert!(!state.clients.read().await.contains_key(&connection_id));
+    }
+
+    #[tokio::test]
+    async fn test_lagging_client_is_notified_and_keeps_receiving() {
+        let (broadcast_tx, broadcast_rx) = broadcast::channel(2);
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+
+        // 订阅者尚未读取时发送超过容量的事件，最早的3条被覆盖
+        for _ in 0..5 {
+            broadcast_tx.send(WsEvent::Ping).unwrap();
+        }
+        drop(broadcast_tx);
+
+        broadcast_message_task(UserId::new(), broadcast_rx, tx).await;
+
+        assert!(matches!(rx.try_recv(), Ok(WsEvent::Error { code: ErrorCode::SlowConsumer, message }) if message.contains("3")));
+        assert!(matches!(rx.try_recv(), Ok(WsEvent::Ping)));
+        assert!(matches!(rx.try_recv(), Ok(WsEvent::Ping)));
+        assert!(rx.try_recv().is_err());
+    }
+
+    /// 需要PostgreSQL服务器：
+    /// `RUSTCHAT_TEST_POSTGRES_URL=postgres://postgres@localhost/postgres cargo test -p rustchat-server --features postgres -- --ignored`
+    #[cfg(feature = "postgres")]
+    #[tokio::test]
+    #[ignore]
+    async fn test_postgres_backend() {
+        let admin_url = std::env::var("RUSTCHAT_TEST_POSTGRES_URL").expect("RUSTCHAT_TEST_POSTGRES_URL is not set");
+        let admin = rustchat_core::connect_pool(&admin_url, Some(1)).await.expect("Failed to connect to PostgreSQL");
+        let name = format!("rustchat_test_{}", uuid::Uuid::new_v4().simple());
+        sqlx::query(&format!("CREATE DATABASE {}", name)).execute(&admin).await.unwrap();
+        let (server, _) = admin_url.rsplit_once('/').expect("Invalid PostgreSQL URL");
+
+        let upload_dir = std::env::temp_dir().join(format!("rustchat-uploads-{}", uuid::Uuid::new_v4()));
+        let message_db = MessageDatabase::connect(&format!("{}/{}", server, name)).await.unwrap();
+        let state = AppState::with_database(ServerConfig { upload_dir: upload_dir.clone(), ..ServerConfig::default() }, message_db)
+            .await
+            .unwrap();
+        let auth = &state.auth_service;
+
+        // 邮箱验证码
+        let (account, user_id) = register_account(&state, "alice@example.com").await;
+        auth.send_verification_code(account.email.clone(), auth::VerificationPurpose::EmailVerification).await.unwrap();
+        let code: String = sqlx::query_scalar("SELECT code FROM email_verifications WHERE email = $1")
+            .bind(&account.email)
+            .fetch_one(auth.get_pool())
+            .await
+            .unwrap();
+        auth.verify_email_code(account.email.clone(), code.clone(), auth::VerificationPurpose::EmailVerification).await.unwrap();
+        assert!(auth.verify_email_code(account.email.clone(), code, auth::VerificationPurpose::EmailVerification).await.is_err());
+        assert!(auth.get_account_by_id(&account.id).await.unwrap().email_verified);
+
+        // 会话的刷新和撤销
+        let account = auth.login(account.email.clone(), "secret123".to_string()).await.unwrap();
+        let tokens = auth.generate_token_pair(&account, Some("test".to_string()), None).await.unwrap();
+        auth.refresh_access_token(&tokens.refresh_token).await.unwrap();
+        let session = auth.list_active_sessions(&account.id).await.unwrap().remove(0);
+        assert!(session.is_active);
+        auth.revoke_session(&account.id, &session.id).await.unwrap();
+        assert!(auth.list_sessions(&account.id).await.unwrap().iter().any(|s| s.id == session.id && !s.is_active));
+        auth.record_audit(&account.id, "test", None, None).await.unwrap();
+        auth.cleanup_expired().await.unwrap();
+
+        // 上传配额和清除用户数据
+        state.uploads.save(&user_id, b"notes", "text/plain").await.unwrap();
+        state.uploads.save(&user_id, b"notes", "text/plain").await.unwrap();
+        assert_eq!(state.uploads.used_bytes(&user_id).await.unwrap(), 5);
+        state.message_db.save_message(&Message::new_text(user_id.clone(), "hi".to_string(), None)).await.unwrap();
+        let summary = state.purge_user_data(&user_id).await.unwrap();
+        assert_eq!((summary.messages, summary.uploads), (1, 1));
+        assert_eq!(state.uploads.used_bytes(&user_id).await.unwrap(), 0);
+
+        state.message_db.get_pool().close().await;
+        sqlx::query(&format!("DROP DATABASE {} WITH (FORCE)", name)).execute(&admin).await.unwrap();
+        let _ = std::fs::remove_dir_all(&upload_dir);
+    }
+}
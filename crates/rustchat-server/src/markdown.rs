@@ -0,0 +1,215 @@
+use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+use std::ops::Range;
+
+/// Markdown消息的服务器端清理
+///
+/// 在保存和广播前把Markdown源文本转换为安全子集：
+/// - 解析器识别为原始HTML（HTML块和行内HTML）的部分中的 `<` 转义为 `&lt;`，代码块和行内代码保持原样
+/// - 链接地址只允许 http、https、mailto 协议和相对地址，其他地址替换为 `#`
+///
+/// 是否为HTML、代码或链接都以CommonMark解析器的结果为准，与客户端渲染时的判断一致。
+/// 转义HTML后原本在HTML块中的文本可能被解析成链接，因此重复处理直到结果不再变化；
+/// 每一轮都会减少 `<` 或不安全链接的数量，处理一定会结束。清理结果仍然是Markdown源文本，客户端照常渲染即可
+pub fn sanitize_markdown(source: &str) -> String {
+    let mut current = source.to_string();
+    loop {
+        let next = rewrite_unsafe_links(&escape_raw_html(&current));
+        if next == current {
+            return next;
+        }
+        current = next;
+    }
+}
+
+/// 转义解析器识别出的原始HTML中的 `<`
+fn escape_raw_html(source: &str) -> String {
+    let mut output = String::with_capacity(source.len());
+    let mut position = 0;
+    for (event, range) in Parser::new(source).into_offset_iter() {
+        if !matches!(event, Event::Html(_) | Event::InlineHtml(_)) || range.start < position {
+            continue;
+        }
+        output.push_str(&source[position..range.start]);
+        output.push_str(&source[range.clone()].replace('<', "&lt;"));
+        position = range.end;
+    }
+    output.push_str(&source[position..]);
+    output
+}
+
+/// 按CommonMark解析文本，把地址仍不安全的链接、图片和引用定义改写为 `#`
+///
+/// 解析器给出的地址已经去掉换行并解码了实体，以它为准可以覆盖所有能被渲染成链接的写法（包括跨行的地址）。
+/// 不安全的链接只保留文字部分，引用定义保留标题
+fn rewrite_unsafe_links(source: &str) -> String {
+    let mut replacements: Vec<(Range<usize>, String)> = Vec::new();
+    let parser = Parser::new(source).into_offset_iter();
+
+    for (label, definition) in parser.reference_definitions().iter() {
+        if !is_safe_url(&definition.dest) {
+            let span = &source[definition.span.clone()];
+            let end = definition.span.start + span.trim_end().len();
+            let mut replacement = format!("[{}]: #", escape_link_text(label));
+            if let Some(title) = &definition.title {
+                replacement.push_str(&format!(" \"{}\"", escape_title(title)));
+            }
+            replacements.push((definition.span.start..end, replacement));
+        }
+    }
+
+    // 图片可以嵌套在链接中，用栈记录每层的 (范围, 是否图片, 是否不安全, 文字)
+    let mut open: Vec<(Range<usize>, bool, bool, String)> = Vec::new();
+    for (event, range) in parser {
+        match event {
+            Event::Start(Tag::Link { dest_url, .. }) => open.push((range, false, !is_safe_url(&dest_url), String::new())),
+            Event::Start(Tag::Image { dest_url, .. }) => open.push((range, true, !is_safe_url(&dest_url), String::new())),
+            Event::Text(text) | Event::Code(text) => {
+                for (_, _, _, content) in &mut open {
+                    content.push_str(&text);
+                }
+            }
+            Event::SoftBreak | Event::HardBreak => {
+                for (_, _, _, content) in &mut open {
+                    content.push(' ');
+                }
+            }
+            Event::End(TagEnd::Link | TagEnd::Image) => {
+                if let Some((range, is_image, true, content)) = open.pop() {
+                    let prefix = if is_image { "!" } else { "" };
+                    replacements.push((range, format!("{}[{}](#)", prefix, escape_link_text(&content))));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // 外层的替换已经覆盖了内层，按起点排序后跳过与前一个替换重叠的部分
+    replacements.sort_by_key(|(range, _)| (range.start, std::cmp::Reverse(range.end)));
+    let mut output = String::with_capacity(source.len());
+    let mut position = 0;
+    for (range, replacement) in replacements {
+        if range.start < position {
+            continue;
+        }
+        output.push_str(&source[position..range.start]);
+        output.push_str(&replacement);
+        position = range.end;
+    }
+    output.push_str(&source[position..]);
+    output
+}
+
+/// 改写后的链接文字不能再构成链接或HTML
+fn escape_link_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '<' => escaped.push_str("&lt;"),
+            '\\' | '[' | ']' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// 引用定义的标题写回双引号中
+fn escape_title(title: &str) -> String {
+    title.replace('\\', "\\\\").replace('"', "\\\"").replace('<', "&lt;")
+}
+
+/// 判断链接地址是否安全：允许的协议或不含协议的相对地址
+///
+/// 含有 `&` 或 `%` 的相对地址也会被拒绝，避免通过实体或编码拼出协议（如 `javascript&#58;`）
+fn is_safe_url(url: &str) -> bool {
+    let lower = url.to_ascii_lowercase();
+    if ["http://", "https://", "mailto:"].iter().any(|scheme| lower.starts_with(scheme)) {
+        return true;
+    }
+    !lower.chars().any(|c| matches!(c, ':' | '&' | '%') || c.is_control() || c.is_whitespace())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_markdown() {
+        // 普通Markdown不受影响
+        let safe = "# 标题\n**粗体** [链接](https://example.com \"说明\") [相对](/rooms)\n> 引用";
+        assert_eq!(sanitize_markdown(safe), safe);
+
+        // 原始HTML被转义
+        assert_eq!(
+            sanitize_markdown("<script>alert(1)</script> <img src=x onerror=alert(1)>"),
+            "&lt;script>alert(1)&lt;/script> &lt;img src=x onerror=alert(1)>"
+        );
+
+        // 危险的链接协议被替换
+        assert_eq!(sanitize_markdown("[点我](javascript:alert(1))"), "[点我](#)");
+        assert_eq!(sanitize_markdown("[点我](JavaScript:alert(1))"), "[点我](#)");
+        assert_eq!(sanitize_markdown("[点我](javascript&#58;alert(1))"), "[点我](#)");
+        assert_eq!(sanitize_markdown("![图](data:image/svg+xml,abc)"), "![图](#)");
+        assert_eq!(sanitize_markdown("[x]: vbscript:msgbox \"t\""), "[x]: # \"t\"");
+
+        // 代码中的内容原样保留
+        let code = "`<b>` 和\n```\n<div>[x](javascript:1)</div>\n```";
+        assert_eq!(sanitize_markdown(code), code);
+        let code = "    <script>alert(1)</script>\n~~~\n<b>\n~~~";
+        assert_eq!(sanitize_markdown(code), code);
+    }
+
+    #[test]
+    fn test_sanitize_html_after_lines_that_only_look_like_fences() {
+        // 缩进4个空格是缩进代码块而不是围栏，下一行的HTML块会被渲染
+        assert_eq!(
+            sanitize_markdown("    ```\n<script>alert(1)</script>"),
+            "    ```\n&lt;script>alert(1)&lt;/script>"
+        );
+        // 反引号围栏的信息字符串中不能有反引号，这一行只是普通段落
+        assert_eq!(
+            sanitize_markdown("```x`\n<script>alert(2)</script>"),
+            "```x`\n&lt;script>alert(2)&lt;/script>"
+        );
+
+        // HTML块中的文本转义后变成段落，其中的链接同样要检查
+        let sanitized = sanitize_markdown("<div>\n[x](javascript:alert(1))\n</div>");
+        assert!(!sanitized.contains("javascript"), "{}", sanitized);
+
+        // 清理结果再交给解析器时不应再出现任何原始HTML
+        for source in [
+            "    ```\n<script>alert(1)</script>",
+            "```x`\n<script>alert(2)</script>",
+            "> ```\n<img src=x onerror=alert(3)>",
+            "- a\n\n      ```\n  <iframe src=x>",
+            "<!-- x -->\n<style>*{}</style> <a href=x>y</a>",
+        ] {
+            let sanitized = sanitize_markdown(source);
+            let html: Vec<_> = Parser::new(&sanitized)
+                .filter(|event| matches!(event, Event::Html(_) | Event::InlineHtml(_)))
+                .collect();
+            assert!(html.is_empty(), "{:?} -> {:?}: {:?}", source, sanitized, html);
+        }
+    }
+
+    #[test]
+    fn test_sanitize_links_spanning_lines() {
+        assert_eq!(sanitize_markdown("[a](\njavascript:alert(1))"), "[a](#)");
+        assert_eq!(sanitize_markdown("![图](\n  data:image/svg+xml,abc)"), "![图](#)");
+        assert_eq!(sanitize_markdown("[跨\n行](\njavascript:alert(1) \"t\")"), "[跨 行](#)");
+
+        // 引用定义的地址写在下一行
+        let sanitized = sanitize_markdown("[a]:\njavascript:alert(1)\n\n点[这里][a]");
+        assert!(!sanitized.contains("javascript"), "{}", sanitized);
+        assert!(sanitized.starts_with("[a]: #"), "{}", sanitized);
+        let sanitized = sanitize_markdown("[点我][x]\n\n[x]:\n  JavaScript:alert(1)\n  \"标题\"");
+        assert!(!sanitized.to_lowercase().contains("javascript"), "{}", sanitized);
+
+        // 链接中的图片和链接本身分别处理，安全的跨行链接保持不变
+        assert_eq!(sanitize_markdown("[![i](\njavascript:1)](https://example.com)"), "[![i](#)](https://example.com)");
+        let safe = "[a](\nhttps://example.com)";
+        assert_eq!(sanitize_markdown(safe), safe);
+    }
+}
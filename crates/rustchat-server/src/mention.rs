@@ -0,0 +1,71 @@
+use rustchat_types::UserId;
+
+/// 根据在线用户的昵称解析消息中提及的用户
+///
+/// 同一昵称可能被多个在线用户使用，为保证结果确定：
+/// - 优先区分大小写精确匹配，没有精确匹配时才忽略大小写匹配
+/// - 仍有多个不同用户匹配时全部视为被提及
+/// - 发送者自己不计入，结果按用户ID排序并去重
+pub fn resolve_mentions(nicknames: &[String], online: &[(UserId, String)], sender: &UserId) -> Vec<UserId> {
+    let mut mentioned: Vec<UserId> = Vec::new();
+
+    for nickname in nicknames {
+        let exact: Vec<&UserId> = online
+            .iter()
+            .filter(|(_, online_nick)| online_nick == nickname)
+            .map(|(user_id, _)| user_id)
+            .collect();
+        let matches = if exact.is_empty() {
+            online
+                .iter()
+                .filter(|(_, online_nick)| online_nick.to_lowercase() == nickname.to_lowercase())
+                .map(|(user_id, _)| user_id)
+                .collect()
+        } else {
+            exact
+        };
+
+        mentioned.extend(matches.into_iter().filter(|user_id| *user_id != sender).cloned());
+    }
+
+    mentioned.sort_by_key(|user_id| user_id.to_string());
+    mentioned.dedup();
+    mentioned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ambiguous_nicknames_resolve_deterministically() {
+        let sender = UserId::new();
+        let alice = UserId::new();
+        let alice_upper = UserId::new();
+        let bob = UserId::new();
+        let bob_other_device = UserId::new();
+        let online = vec![
+            (sender.clone(), "me".to_string()),
+            (alice.clone(), "alice".to_string()),
+            (alice_upper.clone(), "Alice".to_string()),
+            (bob.clone(), "bob".to_string()),
+            (bob_other_device.clone(), "bob".to_string()),
+        ];
+        let names = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+
+        // 精确匹配优先于忽略大小写的匹配
+        assert_eq!(resolve_mentions(&names(&["Alice"]), &online, &sender), vec![alice_upper.clone()]);
+        assert_eq!(resolve_mentions(&names(&["ALICE"]), &online, &sender).len(), 2);
+
+        // 同名用户全部被提及，结果与在线列表顺序无关
+        let mut expected = vec![bob.clone(), bob_other_device.clone()];
+        expected.sort_by_key(|user_id| user_id.to_string());
+        let mut reversed = online.clone();
+        reversed.reverse();
+        assert_eq!(resolve_mentions(&names(&["bob", "bob"]), &online, &sender), expected);
+        assert_eq!(resolve_mentions(&names(&["bob"]), &reversed, &sender), expected);
+
+        // 不提及自己，也不匹配离线昵称
+        assert!(resolve_mentions(&names(&["me", "carol"]), &online, &sender).is_empty());
+    }
+}
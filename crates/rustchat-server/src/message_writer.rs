@@ -0,0 +1,157 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use rustchat_core::MessageDatabase;
+use rustchat_types::Message;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{self, Instant};
+use tracing::{debug, error, warn};
+
+/// 每个事务最多写入的消息数
+pub const MAX_WRITE_BATCH: usize = 100;
+
+/// 等待写入的一条消息，写入完成后通过 `done` 返回结果
+struct PendingWrite {
+    message: Message,
+    done: oneshot::Sender<anyhow::Result<()>>,
+}
+
+/// 合并写入消息的缓冲写入器
+///
+/// 所有连接的消息交给同一个后台任务写入：收到第一条消息后最多再等待 `window`，
+/// 或攒够 [`MAX_WRITE_BATCH`] 条后在一个事务中提交。`window` 为零时不额外等待，
+/// 只合并上一批写入期间积压的消息。[`MessageWriter::save`] 在消息真正保存后才返回
+#[derive(Clone)]
+pub struct MessageWriter {
+    sender: mpsc::UnboundedSender<PendingWrite>,
+}
+
+impl MessageWriter {
+    /// 启动后台写入任务
+    pub fn start(message_db: Arc<MessageDatabase>, window: Duration) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(write_task(message_db, receiver, window));
+        Self { sender }
+    }
+
+    /// 保存一条消息，返回时消息已写入数据库（或写入失败）
+    pub async fn save(&self, message: &Message) -> anyhow::Result<()> {
+        let (done, result) = oneshot::channel();
+        self.sender
+            .send(PendingWrite { message: message.clone(), done })
+            .map_err(|_| anyhow::anyhow!("消息写入任务已停止"))?;
+        result.await.map_err(|_| anyhow::anyhow!("消息写入任务已停止"))?
+    }
+}
+
+/// 后台写入任务，所有写入器句柄都被释放后退出
+async fn write_task(message_db: Arc<MessageDatabase>, mut receiver: mpsc::UnboundedReceiver<PendingWrite>, window: Duration) {
+    while let Some(first) = receiver.recv().await {
+        let mut batch = vec![first];
+        let deadline = Instant::now() + window;
+
+        while batch.len() < MAX_WRITE_BATCH {
+            let next = match receiver.try_recv() {
+                Ok(pending) => Some(pending),
+                Err(_) if window.is_zero() => None,
+                Err(_) => time::timeout_at(deadline, receiver.recv()).await.ok().flatten(),
+            };
+            match next {
+                Some(pending) => batch.push(pending),
+                None => break,
+            }
+        }
+
+        write_batch(&message_db, batch).await;
+    }
+}
+
+/// 在一个事务中写入一批消息；整批失败时逐条重试，单条失败的消息照常进入死信日志
+async fn write_batch(message_db: &MessageDatabase, batch: Vec<PendingWrite>) {
+    let messages: Vec<&Message> = batch.iter().map(|pending| &pending.message).collect();
+    match message_db.save_messages_batch(&messages).await {
+        Ok(result) => {
+            debug!("批量保存 {} 条消息", result.inserted);
+            for pending in batch {
+                let _ = pending.done.send(Ok(()));
+            }
+        }
+        Err(e) => {
+            warn!("批量保存 {} 条消息失败，改为逐条保存: {:#}", batch.len(), e);
+            for pending in batch {
+                let result = message_db.save_message(&pending.message).await;
+                if let Err(e) = &result {
+                    error!("保存消息 {} 失败: {:#}", pending.message.id, e);
+                }
+                let _ = pending.done.send(result);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustchat_types::UserId;
+
+    #[tokio::test]
+    async fn test_concurrent_writes_are_all_saved() {
+        let message_db = Arc::new(MessageDatabase::new_in_memory().await.unwrap());
+        let writer = MessageWriter::start(message_db.clone(), Duration::from_millis(20));
+
+        let messages: Vec<Message> = (0..250)
+            .map(|i| Message::new_text(UserId::new(), format!("burst {}", i), None))
+            .collect();
+        let saves = messages.iter().map(|message| writer.save(message));
+        for result in futures_util::future::join_all(saves).await {
+            result.unwrap();
+        }
+
+        // 超过单批上限的突发写入分多批提交，全部可以读回
+        let saved = message_db.get_recent_messages(1000).await.unwrap();
+        assert_eq!(saved.len(), messages.len());
+        for message in &messages {
+            assert!(saved.iter().any(|saved| saved.id == message.id));
+        }
+
+        // 不等待的模式下单条消息同样立即保存
+        let writer = MessageWriter::start(message_db.clone(), Duration::ZERO);
+        let single = Message::new_text(UserId::new(), "single".to_string(), None);
+        writer.save(&single).await.unwrap();
+        assert_eq!(message_db.get_message_count().await.unwrap(), messages.len() as i64 + 1);
+    }
+
+    /// 文件数据库上逐条写入与每批100条写入的吞吐对比
+    ///
+    /// 运行方式：`cargo test -p rustchat-server --release -- --ignored bench_batched_writes --nocapture`
+    #[tokio::test]
+    #[ignore]
+    async fn bench_batched_writes_on_file_database() {
+        const MESSAGES: usize = 2000;
+
+        let dir = std::env::temp_dir().join(format!("rustchat-write-bench-{}", UserId::new()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let message_db = MessageDatabase::connect(&format!("sqlite://{}", dir.join("bench.db").display())).await.unwrap();
+        let messages: Vec<Message> = (0..MESSAGES)
+            .map(|i| Message::new_text(UserId::new(), format!("bench {}", i), None))
+            .collect();
+
+        let (single, batched) = messages.split_at(MESSAGES / 2);
+        let started = Instant::now();
+        for message in single {
+            message_db.save_message(message).await.unwrap();
+        }
+        let single_rate = single.len() as f64 / started.elapsed().as_secs_f64();
+
+        let started = Instant::now();
+        for chunk in batched.chunks(MAX_WRITE_BATCH) {
+            message_db.save_messages_batch(chunk).await.unwrap();
+        }
+        let batched_rate = batched.len() as f64 / started.elapsed().as_secs_f64();
+
+        println!("逐条写入: {:.0} 条/秒", single_rate);
+        println!("每批{}条: {:.0} 条/秒", MAX_WRITE_BATCH, batched_rate);
+        message_db.close().await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
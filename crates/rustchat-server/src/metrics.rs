@@ -0,0 +1,158 @@
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use std::fmt::Write;
+
+use crate::{AppState, SERVER_VERSION};
+
+/// Prometheus 文本格式的内容类型
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// 创建监控指标路由
+pub fn create_metrics_routes() -> Router<AppState> {
+    Router::new().route("/metrics", get(get_metrics))
+}
+
+/// 一次抓取时的指标快照
+///
+/// 每次请求时从各管理器现有的统计方法读取，不单独维护全局计数器
+#[derive(Debug, Default)]
+pub struct MetricsSnapshot {
+    pub connected_clients: usize,
+    pub rooms_total: usize,
+    pub messages_total: i64,
+    pub broadcast_subscribers: usize,
+    pub global_queued: usize,
+    pub global_capacity: usize,
+    pub room_max_queued: usize,
+    pub room_capacity: usize,
+    pub uptime_secs: u64,
+}
+
+impl MetricsSnapshot {
+    /// 从应用状态收集当前指标
+    pub async fn collect(state: &AppState) -> anyhow::Result<Self> {
+        let connected_clients = state.clients.read().await.len();
+        let rooms_total = state.room_manager.get_stats().await.total_rooms;
+        let messages_total = state.message_db.get_message_count().await?;
+        let broadcast = state.room_broadcast_manager.get_broadcast_stats().await;
+
+        Ok(Self {
+            connected_clients,
+            rooms_total,
+            messages_total,
+            broadcast_subscribers: broadcast.total_subscribers,
+            global_queued: state.tx.len(),
+            global_capacity: state.config.broadcast_capacity,
+            room_max_queued: broadcast.max_queued,
+            room_capacity: broadcast.channel_capacity,
+            uptime_secs: state.uptime().as_secs(),
+        })
+    }
+
+    /// 按 Prometheus 文本格式输出
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        write_gauge(&mut output, "rustchat_connected_clients", "当前WebSocket连接数", &[("", self.connected_clients as i64)]);
+        write_gauge(&mut output, "rustchat_rooms_total", "房间总数", &[("", self.rooms_total as i64)]);
+        write_gauge(&mut output, "rustchat_messages_total", "数据库中保存的消息数", &[("", self.messages_total)]);
+        write_gauge(
+            &mut output,
+            "rustchat_broadcast_subscribers",
+            "房间广播通道的订阅者总数",
+            &[("", self.broadcast_subscribers as i64)],
+        );
+        write_gauge(
+            &mut output,
+            "rustchat_broadcast_queued",
+            "广播通道中尚未被所有订阅者读取的事件数（房间通道取积压最多的一个）",
+            &[
+                ("channel=\"global\"", self.global_queued as i64),
+                ("channel=\"room\"", self.room_max_queued as i64),
+            ],
+        );
+        write_gauge(
+            &mut output,
+            "rustchat_broadcast_capacity",
+            "广播通道容量",
+            &[
+                ("channel=\"global\"", self.global_capacity as i64),
+                ("channel=\"room\"", self.room_capacity as i64),
+            ],
+        );
+        write_gauge(&mut output, "rustchat_uptime_seconds", "服务器运行时长（秒）", &[("", self.uptime_secs as i64)]);
+        write_gauge(
+            &mut output,
+            "rustchat_build_info",
+            "服务器版本，值恒为1",
+            &[(&format!("version=\"{}\"", SERVER_VERSION), 1)],
+        );
+        output
+    }
+}
+
+/// 输出一个 gauge 指标，`samples` 为（标签, 值），标签为空时不输出花括号
+fn write_gauge(output: &mut String, name: &str, help: &str, samples: &[(&str, i64)]) {
+    let _ = writeln!(output, "# HELP {} {}", name, help);
+    let _ = writeln!(output, "# TYPE {} gauge", name);
+    for (labels, value) in samples {
+        if labels.is_empty() {
+            let _ = writeln!(output, "{} {}", name, value);
+        } else {
+            let _ = writeln!(output, "{}{{{}}} {}", name, labels, value);
+        }
+    }
+}
+
+/// 返回 Prometheus 文本格式的监控指标
+async fn get_metrics(State(state): State<AppState>) -> Response {
+    match MetricsSnapshot::collect(&state).await {
+        Ok(snapshot) => ([(header::CONTENT_TYPE, CONTENT_TYPE)], snapshot.render()).into_response(),
+        Err(e) => {
+            tracing::error!("收集监控指标失败: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_metrics() {
+        let snapshot = MetricsSnapshot {
+            connected_clients: 3,
+            rooms_total: 2,
+            messages_total: 42,
+            broadcast_subscribers: 1,
+            global_queued: 5,
+            global_capacity: 1000,
+            room_max_queued: 0,
+            room_capacity: 500,
+            uptime_secs: 90,
+        };
+        let output = snapshot.render();
+
+        assert!(output.contains("# TYPE rustchat_connected_clients gauge\nrustchat_connected_clients 3\n"));
+        assert!(output.contains("\nrustchat_rooms_total 2\n"));
+        assert!(output.contains("\nrustchat_messages_total 42\n"));
+        assert!(output.contains("\nrustchat_broadcast_subscribers 1\n"));
+        assert!(output.contains("\nrustchat_broadcast_queued{channel=\"global\"} 5\n"));
+        assert!(output.contains("\nrustchat_broadcast_capacity{channel=\"room\"} 500\n"));
+        assert!(output.contains("\nrustchat_uptime_seconds 90\n"));
+        assert!(output.contains(&format!("\nrustchat_build_info{{version=\"{}\"}} 1\n", SERVER_VERSION)));
+
+        // 每个指标都有 HELP 和 TYPE 行，样本行格式为 `名称[{标签}] 值`
+        for line in output.lines().filter(|line| !line.starts_with('#')) {
+            let (name, value) = line.rsplit_once(' ').unwrap();
+            assert!(name.starts_with("rustchat_"));
+            assert!(value.parse::<i64>().is_ok());
+        }
+        assert_eq!(output.matches("# HELP ").count(), 8);
+    }
+}
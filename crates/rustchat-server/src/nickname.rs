@@ -0,0 +1,84 @@
+use rustchat_types::UserId;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// 昵称修改频率限制（按用户计算，同一账户的多个设备共享）
+#[derive(Debug)]
+pub struct NickChangeLimiter {
+    cooldown: Duration,
+    last_changes: HashMap<UserId, Instant>,
+}
+
+impl NickChangeLimiter {
+    pub fn new(cooldown: Duration) -> Self {
+        Self {
+            cooldown,
+            last_changes: HashMap::new(),
+        }
+    }
+
+    /// 尝试在指定时间记录一次昵称修改，冷却中返回剩余等待时间
+    ///
+    /// 同时清除已经过了冷却时间的记录，记录数不会随用户数无限增长
+    pub fn try_change(&mut self, user_id: &UserId, now: Instant) -> Result<(), Duration> {
+        let cooldown = self.cooldown;
+        self.last_changes
+            .retain(|_, last_change| now.saturating_duration_since(*last_change) < cooldown);
+
+        if let Some(last_change) = self.last_changes.get(user_id) {
+            let elapsed = now.saturating_duration_since(*last_change);
+            if elapsed < self.cooldown {
+                return Err(self.cooldown - elapsed);
+            }
+        }
+
+        if !cooldown.is_zero() {
+            self.last_changes.insert(user_id.clone(), now);
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.last_changes.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cooldown_boundary() {
+        let mut limiter = NickChangeLimiter::new(Duration::from_secs(30));
+        let user_id = UserId::new();
+        let start = Instant::now();
+
+        assert!(limiter.try_change(&user_id, start).is_ok());
+        assert_eq!(
+            limiter.try_change(&user_id, start + Duration::from_secs(29)),
+            Err(Duration::from_secs(1))
+        );
+        // 被拒绝的修改不会重置冷却时间
+        assert!(limiter.try_change(&user_id, start + Duration::from_secs(30)).is_ok());
+
+        // 其他用户不受影响
+        assert!(limiter.try_change(&UserId::new(), start).is_ok());
+        assert_eq!(limiter.len(), 2);
+
+        // 过了冷却时间的记录在下一次修改时被清除
+        assert!(limiter.try_change(&UserId::new(), start + Duration::from_secs(60)).is_ok());
+        assert_eq!(limiter.len(), 1);
+    }
+
+    #[test]
+    fn test_zero_cooldown_never_limits() {
+        let mut limiter = NickChangeLimiter::new(Duration::ZERO);
+        let user_id = UserId::new();
+        let now = Instant::now();
+
+        assert!(limiter.try_change(&user_id, now).is_ok());
+        assert!(limiter.try_change(&user_id, now).is_ok());
+        assert_eq!(limiter.len(), 0);
+    }
+}
@@ -0,0 +1,73 @@
+use axum::Router;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+use utoipa_swagger_ui::SwaggerUi;
+
+use crate::auth::api::AuthApi;
+use crate::friend::FriendApi;
+use crate::room::RoomApi;
+use crate::AppState;
+
+/// OpenAPI文档的地址
+const OPENAPI_PATH: &str = "/api/openapi.json";
+/// Swagger UI的地址
+const SWAGGER_UI_PATH: &str = "/api/docs";
+
+/// REST API 的OpenAPI描述，各模块的接口描述在 `openapi()` 中合并进来
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "RustChat API", description = "RustChat 服务器的 REST 接口，实时消息通过 /ws 的 WebSocket 收发"),
+    modifiers(&BearerAuth),
+    tags(
+        (name = "auth", description = "注册、登录、令牌和会话管理"),
+        (name = "rooms", description = "房间和房间消息"),
+        (name = "friends", description = "好友请求和好友列表")
+    )
+)]
+struct ApiDoc;
+
+/// 注册 `Authorization: Bearer <访问令牌>` 认证方式，需要认证的接口通过 `bearer_auth` 引用
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer_auth",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).bearer_format("JWT").build()),
+        );
+    }
+}
+
+/// 生成完整的OpenAPI文档
+pub fn openapi() -> utoipa::openapi::OpenApi {
+    let mut doc = ApiDoc::openapi();
+    doc.merge(AuthApi::openapi());
+    doc.merge(RoomApi::openapi());
+    doc.merge(FriendApi::openapi());
+    doc
+}
+
+/// 创建文档路由：`/api/openapi.json` 返回OpenAPI文档，`/api/docs` 提供Swagger UI
+pub fn create_openapi_routes() -> Router<AppState> {
+    SwaggerUi::new(SWAGGER_UI_PATH).url(OPENAPI_PATH, openapi()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_openapi_covers_rest_modules() {
+        let doc = openapi();
+
+        for path in ["/api/auth/register", "/api/rooms/{room_id}/messages", "/api/friends/request"] {
+            assert!(doc.paths.paths.contains_key(path), "缺少接口 {}", path);
+        }
+
+        let schemas = &doc.components.as_ref().expect("缺少组件").schemas;
+        for schema in ["RegisterRequest", "AuthResponse", "RoomResponse", "Message", "FriendRequest"] {
+            assert!(schemas.contains_key(schema), "缺少结构 {}", schema);
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+    routing::get,
+    Extension, Router,
+};
+use chrono::{DateTime, Utc};
+use rustchat_types::UserId;
+use serde::Serialize;
+
+use crate::auth::{AccountId, AccountStatus, AuthError, AuthenticatedUser};
+use crate::room::ApiResponse;
+use crate::AppState;
+
+/// 创建用户公开资料路由（需配合可选认证中间件使用）
+pub fn create_profile_routes() -> Router<AppState> {
+    Router::new().route("/api/users/{user_id}", get(get_user_profile))
+}
+
+/// 用户的公开资料，供提及提示和好友列表显示使用
+#[derive(Debug, Serialize)]
+pub struct UserProfile {
+    pub user_id: UserId,
+    /// 当前连接使用的昵称，离线或未设置昵称时为None
+    pub nickname: Option<String>,
+    /// 账户的显示名称，匿名用户为None
+    pub display_name: Option<String>,
+    pub is_online: bool,
+    /// 首次出现的时间：账户注册时间和最早一条消息的时间中较早的一个
+    pub first_seen_at: Option<DateTime<Utc>>,
+}
+
+/// 获取用户的公开资料，没有账户、不在线也没有发过消息的用户返回404
+async fn get_user_profile(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+    auth_user: Option<Extension<AuthenticatedUser>>,
+) -> Result<Json<ApiResponse<UserProfile>>, StatusCode> {
+    if !state.config.permits_connection(auth_user.is_some()) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    let user_id = UserId::parse(&user_id).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    // 认证用户的UserId与账户ID相同，已注销的账户视为不存在
+    let account_id = AccountId::parse(&user_id.to_string()).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let account = match state.auth_service.get_account_by_id(&account_id).await {
+        Ok(account) if account.status == AccountStatus::Deleted => None,
+        Ok(account) => Some(account),
+        Err(AuthError::AccountNotFound) => None,
+        Err(e) => {
+            tracing::error!("查询用户账户失败: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let online = state.client_snapshot(&user_id).await;
+    let is_online = online.is_some();
+    let nickname = online.and_then(|snapshot| snapshot.nickname);
+
+    let first_message_at = match state.message_db.get_user_stats(&user_id).await {
+        Ok(stats) => stats.first_message_at,
+        Err(e) => {
+            tracing::error!("查询用户消息统计失败: {}", e);
+            None
+        }
+    };
+
+    if account.is_none() && !is_online && first_message_at.is_none() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let registered_at = account.as_ref().map(|account| account.created_at);
+    let first_seen_at = match (registered_at, first_message_at) {
+        (Some(registered_at), Some(first_message_at)) => Some(registered_at.min(first_message_at)),
+        (registered_at, first_message_at) => registered_at.or(first_message_at),
+    };
+
+    Ok(Json(ApiResponse::success(UserProfile {
+        user_id,
+        nickname,
+        display_name: account.and_then(|account| account.display_name),
+        is_online,
+        first_seen_at,
+    })))
+}
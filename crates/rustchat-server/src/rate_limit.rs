@@ -0,0 +1,69 @@
+use std::time::Instant;
+
+/// 单个连接的消息频率限制（令牌桶）
+///
+/// 桶中最多积攒 `burst` 个令牌，每秒补充 `rate_per_sec` 个，每条客户端消息消耗一个。
+/// `rate_per_sec` 为零时不限制
+#[derive(Debug, Clone)]
+pub struct MessageRateLimiter {
+    rate_per_sec: f64,
+    burst: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl MessageRateLimiter {
+    pub fn new(rate_per_sec: u32, burst: u32, now: Instant) -> Self {
+        let burst = f64::from(burst.max(1));
+        Self {
+            rate_per_sec: f64::from(rate_per_sec),
+            burst,
+            tokens: burst,
+            last_refill: now,
+        }
+    }
+
+    /// 尝试在指定时间消耗一个令牌，超出频率时返回false
+    pub fn try_acquire(&mut self, now: Instant) -> bool {
+        if self.rate_per_sec == 0.0 {
+            return true;
+        }
+
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate_per_sec).min(self.burst);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_burst_then_refill() {
+        let start = Instant::now();
+        let mut limiter = MessageRateLimiter::new(10, 20, start);
+
+        let accepted = (0..25).filter(|_| limiter.try_acquire(start)).count();
+        assert_eq!(accepted, 20);
+
+        // 100毫秒补充一个令牌
+        assert!(limiter.try_acquire(start + Duration::from_millis(100)));
+        assert!(!limiter.try_acquire(start + Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn test_zero_rate_never_limits() {
+        let now = Instant::now();
+        let mut limiter = MessageRateLimiter::new(0, 1, now);
+        assert!((0..100).all(|_| limiter.try_acquire(now)));
+    }
+}
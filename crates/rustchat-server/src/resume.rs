@@ -0,0 +1,112 @@
+use chrono::{DateTime, Utc};
+use rustchat_types::UserId;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// 匿名连接的恢复令牌
+///
+/// 匿名连接建立时签发，连接断开后在有效期内携带 `?resume=<token>` 重连即可沿用原来的用户ID。
+/// 每个令牌只能使用一次，恢复后的连接会得到新的令牌
+#[derive(Debug)]
+pub struct ResumeTokens {
+    ttl: chrono::Duration,
+    tokens: HashMap<String, ResumeEntry>,
+}
+
+#[derive(Debug)]
+struct ResumeEntry {
+    user_id: UserId,
+    /// 连接断开的时间，连接仍在线时为None
+    disconnected_at: Option<DateTime<Utc>>,
+}
+
+/// 通过令牌恢复的会话
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResumedSession {
+    pub user_id: UserId,
+    /// 原连接断开的时间，之后收到的消息需要补发；原连接尚未断开时为None
+    pub disconnected_at: Option<DateTime<Utc>>,
+}
+
+impl ResumeTokens {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl: chrono::Duration::from_std(ttl).unwrap_or(chrono::Duration::MAX),
+            tokens: HashMap::new(),
+        }
+    }
+
+    /// 有效期为零时不签发令牌
+    pub fn enabled(&self) -> bool {
+        !self.ttl.is_zero()
+    }
+
+    /// 为匿名连接签发新令牌
+    pub fn issue(&mut self, user_id: &UserId) -> String {
+        let token = uuid::Uuid::new_v4().simple().to_string();
+        self.tokens.insert(
+            token.clone(),
+            ResumeEntry { user_id: user_id.clone(), disconnected_at: None },
+        );
+        token
+    }
+
+    /// 标记令牌对应的连接已断开，有效期从此刻开始计算
+    pub fn mark_disconnected(&mut self, token: &str, now: DateTime<Utc>) {
+        if let Some(entry) = self.tokens.get_mut(token) {
+            entry.disconnected_at = Some(now);
+        }
+        self.remove_expired(now);
+    }
+
+    /// 使用令牌恢复会话，令牌不存在、已使用或已过期时返回None
+    pub fn take(&mut self, token: &str, now: DateTime<Utc>) -> Option<ResumedSession> {
+        self.remove_expired(now);
+        self.tokens.remove(token).map(|entry| ResumedSession {
+            user_id: entry.user_id,
+            disconnected_at: entry.disconnected_at,
+        })
+    }
+
+    fn remove_expired(&mut self, now: DateTime<Utc>) {
+        let ttl = self.ttl;
+        self.tokens
+            .retain(|_, entry| entry.disconnected_at.is_none_or(|at| now.signed_duration_since(at) < ttl));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_is_single_use_and_expires() {
+        let mut tokens = ResumeTokens::new(Duration::from_secs(60));
+        let user_id = UserId::new();
+        let start = Utc::now();
+
+        // 连接仍在线时也可以恢复（服务器尚未察觉旧连接断开）
+        let token = tokens.issue(&user_id);
+        assert_eq!(
+            tokens.take(&token, start),
+            Some(ResumedSession { user_id: user_id.clone(), disconnected_at: None })
+        );
+        assert_eq!(tokens.take(&token, start), None);
+
+        let token = tokens.issue(&user_id);
+        tokens.mark_disconnected(&token, start);
+        let resumed = tokens.take(&token, start + chrono::Duration::seconds(59)).unwrap();
+        assert_eq!(resumed.disconnected_at, Some(start));
+
+        let token = tokens.issue(&user_id);
+        tokens.mark_disconnected(&token, start);
+        assert_eq!(tokens.take(&token, start + chrono::Duration::seconds(60)), None);
+        assert_eq!(tokens.take("unknown", start), None);
+    }
+
+    #[test]
+    fn test_zero_ttl_disables_tokens() {
+        assert!(!ResumeTokens::new(Duration::ZERO).enabled());
+        assert!(ResumeTokens::new(Duration::from_secs(1)).enabled());
+    }
+}
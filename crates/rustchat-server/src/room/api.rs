@@ -4,14 +4,14 @@ use axum::{
     response::Json,
     routing::{get, post, delete},
     Router,
-    middleware,
 };
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, OpenApi, ToSchema};
 
-use crate::room::{CreateRoomRequest, RoomId, RoomResponse, RoomError};
+use crate::room::{CreateRoomRequest, RoomId, RoomResponse, RoomError, RoomStats};
 use crate::AppState;
-use crate::auth::{AuthenticatedUser, middleware::auth_middleware, middleware::optional_auth_middleware};
-use rustchat_types::{UserId, Message};
+use crate::auth::AuthenticatedUser;
+use rustchat_types::{Message, MessageId, UserId};
 
 /// 创建需要认证的房间路由
 pub fn create_protected_room_routes() -> Router<AppState> {
@@ -23,6 +23,8 @@ pub fn create_protected_room_routes() -> Router<AppState> {
         .route("/api/rooms/{room_id}/members", get(get_room_members))
         .route("/api/rooms/{room_id}/messages", get(get_room_messages))
         .route("/api/rooms/{room_id}/messages", post(send_room_message))
+        .route("/api/rooms/{room_id}/messages/{id}/read", post(mark_room_message_read))
+        .route("/api/rooms/{room_id}/messages/{id}/seen-count", get(get_message_seen_count))
         .route("/api/user/rooms", get(get_user_rooms))
 }
 
@@ -34,58 +36,115 @@ pub fn create_public_room_routes() -> Router<AppState> {
         .route("/api/rooms/stats", get(get_room_stats))
 }
 
-/// API 路由（兼容性保持）
-pub fn create_room_routes() -> Router<AppState> {
-    Router::new()
-        .merge(create_protected_room_routes())
-        .merge(create_public_room_routes())
-}
+/// 房间接口的OpenAPI描述
+#[derive(OpenApi)]
+#[openapi(paths(
+    create_room, get_room, delete_room, join_room, leave_room, get_room_members, get_user_rooms, list_rooms,
+    get_room_stats, get_room_messages, send_room_message, mark_room_message_read, get_message_seen_count
+))]
+pub(crate) struct RoomApi;
 
 /// 查询参数
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 struct ListRoomsQuery {
+    /// 跳过的房间数，默认0
     offset: Option<usize>,
+    /// 每页房间数，默认50，最多100
     limit: Option<usize>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 struct MessagesQuery {
+    /// 每页消息数，默认50，范围1~100
     limit: Option<usize>,
+    /// 已弃用：按偏移量从最早的消息开始分页，新消息到达时翻页结果会错位，请改用 `order=newest`
     offset: Option<usize>,
+    /// 分页方式，默认 `oldest` 按偏移量从最早的消息开始；`newest` 从最新的消息开始按游标向前翻页
+    #[serde(default)]
+    #[param(inline)]
+    order: MessageOrder,
+    /// 游标（消息ID），只返回排在该消息之前的消息；取上一页响应中的 `next_cursor`，隐含 `order=newest`
+    before: Option<MessageId>,
 }
 
-#[derive(Debug, Deserialize)]
+/// 房间消息的分页方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum MessageOrder {
+    /// 从最早的消息开始按偏移量分页（兼容旧客户端）
+    #[default]
+    Oldest,
+    /// 从最新的消息开始按游标向前翻页
+    Newest,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
 struct SendMessageRequest {
     content: String,
+    /// 内容是否为Markdown格式
+    #[serde(default)]
+    markdown: bool,
+}
+
+/// 消息已读人数统计
+#[derive(Debug, Serialize, ToSchema)]
+struct SeenCountResponse {
+    message_id: String,
+    /// 已读到该消息（或更新消息）的房间成员数
+    seen_count: usize,
+    member_count: usize,
 }
 
 /// API 响应类型
-#[derive(Serialize)]
-struct ApiResponse<T> {
+#[derive(Serialize, ToSchema)]
+pub(crate) struct ApiResponse<T> {
     success: bool,
     data: Option<T>,
     error: Option<String>,
+    /// 下一页的分页游标，没有更多数据或接口不分页时省略
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_cursor: Option<String>,
 }
 
 impl<T> ApiResponse<T> {
-    fn success(data: T) -> Self {
+    pub(crate) fn success(data: T) -> Self {
         Self {
             success: true,
             data: Some(data),
             error: None,
+            next_cursor: None,
         }
     }
+
+    /// 附加下一页的分页游标
+    pub(crate) fn with_next_cursor(mut self, cursor: Option<String>) -> Self {
+        self.next_cursor = cursor;
+        self
+    }
     
-    fn error(message: String) -> Self {
+    pub(crate) fn error(message: String) -> Self {
         Self {
             success: false,
             data: None,
             error: Some(message),
+            next_cursor: None,
         }
     }
 }
 
 /// 创建房间
+#[utoipa::path(
+    post,
+    path = "/api/rooms",
+    tag = "rooms",
+    security(("bearer_auth" = [])),
+    request_body = CreateRoomRequest,
+    responses(
+        (status = 200, description = "房间创建成功", body = ApiResponse<RoomResponse>)
+    )
+)]
 async fn create_room(
     State(state): State<AppState>,
     Extension(auth_user): Extension<AuthenticatedUser>,
@@ -111,6 +170,17 @@ async fn create_room(
 }
 
 /// 获取房间信息
+#[utoipa::path(
+    get,
+    path = "/api/rooms/{room_id}",
+    tag = "rooms",
+    params(("room_id" = String, Path, description = "房间ID")),
+    responses(
+        (status = 200, description = "成功", body = ApiResponse<RoomResponse>),
+        (status = 400, description = "房间ID无效"),
+        (status = 404, description = "房间不存在")
+    )
+)]
 async fn get_room(
     State(state): State<AppState>,
     Path(room_id): Path<String>,
@@ -123,7 +193,7 @@ async fn get_room(
     // 如果有认证用户，使用其ID，否则生成临时ID用于显示
     let user_id = auth_user
         .map(|ext| ext.user_id.clone())
-        .unwrap_or_else(|| UserId::new());
+        .unwrap_or_default();
     
     match state.room_manager.get_room(room_id).await {
         Ok(room) => {
@@ -139,6 +209,19 @@ async fn get_room(
 }
 
 /// 删除房间
+#[utoipa::path(
+    delete,
+    path = "/api/rooms/{room_id}",
+    tag = "rooms",
+    security(("bearer_auth" = [])),
+    params(("room_id" = String, Path, description = "房间ID")),
+    responses(
+        (status = 200, description = "房间已删除", body = ApiResponse<RoomResponse>),
+        (status = 400, description = "房间ID无效"),
+        (status = 403, description = "只有房主可以删除房间"),
+        (status = 404, description = "房间不存在")
+    )
+)]
 async fn delete_room(
     State(state): State<AppState>,
     Path(room_id): Path<String>,
@@ -164,6 +247,19 @@ async fn delete_room(
 }
 
 /// 加入房间
+#[utoipa::path(
+    post,
+    path = "/api/rooms/{room_id}/join",
+    tag = "rooms",
+    security(("bearer_auth" = [])),
+    params(("room_id" = String, Path, description = "房间ID")),
+    responses(
+        (status = 200, description = "已加入房间（已是成员时同样返回成功）", body = ApiResponse<RoomResponse>),
+        (status = 400, description = "房间ID无效"),
+        (status = 404, description = "房间不存在"),
+        (status = 409, description = "房间已满")
+    )
+)]
 async fn join_room(
     State(state): State<AppState>,
     Path(room_id): Path<String>,
@@ -205,6 +301,19 @@ async fn join_room(
 }
 
 /// 离开房间
+#[utoipa::path(
+    post,
+    path = "/api/rooms/{room_id}/leave",
+    tag = "rooms",
+    security(("bearer_auth" = [])),
+    params(("room_id" = String, Path, description = "房间ID")),
+    responses(
+        (status = 200, description = "已离开房间", body = ApiResponse<RoomResponse>),
+        (status = 400, description = "房间ID无效"),
+        (status = 404, description = "房间不存在"),
+        (status = 409, description = "不在房间中")
+    )
+)]
 async fn leave_room(
     State(state): State<AppState>,
     Path(room_id): Path<String>,
@@ -233,6 +342,19 @@ async fn leave_room(
 }
 
 /// 获取房间成员列表
+#[utoipa::path(
+    get,
+    path = "/api/rooms/{room_id}/members",
+    tag = "rooms",
+    security(("bearer_auth" = [])),
+    params(("room_id" = String, Path, description = "房间ID")),
+    responses(
+        (status = 200, description = "成员的用户ID列表", body = ApiResponse<Vec<String>>),
+        (status = 400, description = "房间ID无效"),
+        (status = 403, description = "不是房间成员"),
+        (status = 404, description = "房间不存在")
+    )
+)]
 async fn get_room_members(
     State(state): State<AppState>,
     Path(room_id): Path<String>,
@@ -261,21 +383,50 @@ async fn get_room_members(
 }
 
 /// 获取用户房间列表
+#[utoipa::path(
+    get,
+    path = "/api/user/rooms",
+    tag = "rooms",
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "当前用户所在的房间，附带未读消息数", body = ApiResponse<Vec<RoomResponse>>)
+    )
+)]
 async fn get_user_rooms(
     State(state): State<AppState>,
     Extension(auth_user): Extension<AuthenticatedUser>,
 ) -> Result<Json<ApiResponse<Vec<RoomResponse>>>, StatusCode> {
-    let user_id = auth_user.user_id;
-    
-    let rooms = state.room_manager.get_user_rooms(&user_id).await;
-    let responses: Vec<RoomResponse> = rooms.iter()
-        .map(|room| RoomResponse::from_room(room, &user_id))
-        .collect();
-    
+    let responses = user_room_responses(&state, &auth_user.user_id).await;
     Ok(Json(ApiResponse::success(responses)))
 }
 
+/// 用户所在的房间列表（附带未读消息数，统计失败时省略）
+pub(crate) async fn user_room_responses(state: &AppState, user_id: &UserId) -> Vec<RoomResponse> {
+    let rooms = state.room_manager.get_user_rooms(user_id).await;
+    let mut responses = Vec::with_capacity(rooms.len());
+    for room in &rooms {
+        let response = RoomResponse::from_room(room, user_id);
+        match state.message_db.get_unread_count(user_id, &room.id.to_string()).await {
+            Ok(unread_count) => responses.push(response.with_unread_count(unread_count)),
+            Err(e) => {
+                tracing::warn!("统计房间 {} 未读消息失败: {}", room.id, e);
+                responses.push(response);
+            }
+        }
+    }
+    responses
+}
+
 /// 获取房间列表
+#[utoipa::path(
+    get,
+    path = "/api/rooms",
+    tag = "rooms",
+    params(ListRoomsQuery),
+    responses(
+        (status = 200, description = "成功", body = ApiResponse<Vec<RoomResponse>>)
+    )
+)]
 async fn list_rooms(
     State(state): State<AppState>,
     Query(query): Query<ListRoomsQuery>,
@@ -284,7 +435,7 @@ async fn list_rooms(
     // 如果有认证用户，使用其ID；否则使用虚拟ID
     let user_id = auth_user
         .map(|ext| ext.user_id.clone())
-        .unwrap_or_else(|| UserId::new());
+        .unwrap_or_default();
     
     let offset = query.offset.unwrap_or(0);
     let limit = query.limit.unwrap_or(50).min(100); // 最大限制100
@@ -298,14 +449,35 @@ async fn list_rooms(
 }
 
 /// 获取房间统计信息
+#[utoipa::path(
+    get,
+    path = "/api/rooms/stats",
+    tag = "rooms",
+    responses(
+        (status = 200, description = "成功", body = ApiResponse<RoomStats>)
+    )
+)]
 async fn get_room_stats(
     State(state): State<AppState>,
-) -> Json<ApiResponse<crate::room::RoomStats>> {
+) -> Json<ApiResponse<RoomStats>> {
     let stats = state.room_manager.get_stats().await;
     Json(ApiResponse::success(stats))
 }
 
 /// 获取房间消息
+#[utoipa::path(
+    get,
+    path = "/api/rooms/{room_id}/messages",
+    tag = "rooms",
+    security(("bearer_auth" = [])),
+    params(("room_id" = String, Path, description = "房间ID"), MessagesQuery),
+    responses(
+        (status = 200, description = "按时间正序排列的一页消息，按游标分页且取满一页时附带 next_cursor；不保存历史的房间始终为空", body = ApiResponse<Vec<Message>>),
+        (status = 400, description = "房间ID无效，或同时使用了 offset 和游标分页"),
+        (status = 403, description = "不是房间成员"),
+        (status = 404, description = "游标消息不存在、已过期或不属于该房间")
+    )
+)]
 async fn get_room_messages(
     State(state): State<AppState>,
     Path(room_id): Path<String>,
@@ -321,12 +493,53 @@ async fn get_room_messages(
         return Err(StatusCode::FORBIDDEN);
     }
     
-    let limit = query.limit.unwrap_or(50).min(100);
-    let offset = query.offset.unwrap_or(0);
-    
-    // 从消息数据库获取房间消息
-    match state.message_db.get_room_messages(&room_id.to_string(), limit, offset).await {
-        Ok(messages) => Ok(Json(ApiResponse::success(messages))),
+    // 不保存历史的房间没有可查询的消息
+    if !state.room_manager.is_room_persistent(room_id).await {
+        return Ok(Json(ApiResponse::success(Vec::new())));
+    }
+
+    let limit = query.limit.unwrap_or(50).clamp(1, 100);
+    let cursor_paging = query.order == MessageOrder::Newest || query.before.is_some();
+    if cursor_paging && query.offset.is_some() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    // 默认保持旧的行为：按偏移量从最早的消息开始分页
+    if !cursor_paging {
+        let offset = query.offset.unwrap_or(0);
+        return match state.message_db.get_room_messages(&room_id.to_string(), limit, offset).await {
+            Ok(messages) => Ok(Json(ApiResponse::success(messages))),
+            Err(e) => {
+                tracing::error!("获取房间消息失败: {}", e);
+                Ok(Json(ApiResponse::error(e.to_string())))
+            }
+        };
+    }
+
+    // 游标必须是本房间中仍然存在的消息，否则无法确定翻页位置
+    if let Some(before) = &query.before {
+        match state.message_db.get_message(before).await {
+            Ok(Some(message)) if message.room_id.as_deref() == Some(room_id.to_string().as_str()) => {}
+            Ok(_) => return Err(StatusCode::NOT_FOUND),
+            Err(e) => {
+                tracing::error!("查询游标消息失败: {}", e);
+                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            }
+        }
+    }
+
+    // 从最新的消息开始按游标向前翻页，取满一页时返回本页最早的消息作为下一页游标
+    match state
+        .message_db
+        .get_room_messages_before(&room_id.to_string(), query.before.as_ref(), limit as i64)
+        .await
+    {
+        Ok(messages) => {
+            let next_cursor = (messages.len() == limit)
+                .then(|| messages.first().map(|message| message.id.to_string()))
+                .flatten();
+            Ok(Json(ApiResponse::success(messages).with_next_cursor(next_cursor)))
+        }
         Err(e) => {
             tracing::error!("获取房间消息失败: {}", e);
             Ok(Json(ApiResponse::error(e.to_string())))
@@ -335,6 +548,19 @@ async fn get_room_messages(
 }
 
 /// 发送房间消息
+#[utoipa::path(
+    post,
+    path = "/api/rooms/{room_id}/messages",
+    tag = "rooms",
+    security(("bearer_auth" = [])),
+    params(("room_id" = String, Path, description = "房间ID")),
+    request_body = SendMessageRequest,
+    responses(
+        (status = 200, description = "消息已保存并广播给房间成员（不保存历史的房间只广播）", body = ApiResponse<Message>),
+        (status = 400, description = "房间ID无效"),
+        (status = 403, description = "不是房间成员")
+    )
+)]
 async fn send_room_message(
     State(state): State<AppState>,
     Path(room_id): Path<String>,
@@ -351,20 +577,28 @@ async fn send_room_message(
     }
     
     // 创建消息
-    let message = Message::new_text(
-        user_id.clone(), 
-        request.content.clone(), 
-        None
-    );
+    let message = if request.markdown {
+        Message::new_markdown(user_id.clone(), crate::markdown::sanitize_markdown(&request.content), None)
+    } else {
+        Message::new_text(user_id.clone(), request.content.clone(), None)
+    };
     
     // 设置消息的房间ID
     let mut room_message = message;
-    room_message.additional_data = Some(serde_json::json!({
-        "room_id": room_id.to_string()
-    }));    // 保存消息到数据库
-    if let Err(e) = state.message_db.save_message(&room_message).await {
-        tracing::error!("保存房间消息失败: {}", e);
-        return Ok(Json(ApiResponse::error(e.to_string())));
+    room_message.set_room_id(room_id.to_string());
+    room_message.mark_received();
+
+    // 保存消息到数据库，不保存历史的房间只转发
+    if state.room_manager.is_room_persistent(room_id).await {
+        if let Err(e) = state.message_writer.save(&room_message).await {
+            tracing::error!("保存房间消息失败: {}", e);
+            return Ok(Json(ApiResponse::error(e.to_string())));
+        }
+
+        // 发送者视为已读自己的消息
+        if let Err(e) = state.message_db.mark_room_read(&room_id.to_string(), &user_id, &room_message).await {
+            tracing::warn!("更新发送者已读位置失败: {}", e);
+        }
     }
     
     // 广播消息给房间成员（完整方案）
@@ -376,3 +610,125 @@ async fn send_room_message(
     
     Ok(Json(ApiResponse::success(room_message)))
 }
+
+/// 将自己在房间中的已读位置推进到指定消息
+#[utoipa::path(
+    post,
+    path = "/api/rooms/{room_id}/messages/{id}/read",
+    tag = "rooms",
+    security(("bearer_auth" = [])),
+    params(
+        ("room_id" = String, Path, description = "房间ID"),
+        ("id" = String, Path, description = "消息ID")
+    ),
+    responses(
+        (status = 200, description = "已读位置已更新", body = ApiResponse<SeenCountResponse>),
+        (status = 400, description = "房间ID无效"),
+        (status = 403, description = "不是房间成员"),
+        (status = 404, description = "消息不存在或不属于该房间")
+    )
+)]
+async fn mark_room_message_read(
+    State(state): State<AppState>,
+    Path((room_id, message_id)): Path<(String, String)>,
+    Extension(auth_user): Extension<AuthenticatedUser>,
+) -> Result<Json<ApiResponse<SeenCountResponse>>, StatusCode> {
+    let (room_id, message) = load_room_message(&state, &room_id, &message_id, &auth_user).await?;
+
+    if let Err(e) = state.message_db.mark_room_read(&room_id.to_string(), &auth_user.user_id, &message).await {
+        tracing::error!("更新已读位置失败: {}", e);
+        return Ok(Json(ApiResponse::error(e.to_string())));
+    }
+
+    seen_count_response(&state, room_id, &message).await
+}
+
+/// 获取房间中已读到指定消息的成员数量（根据各成员的已读位置计算）
+#[utoipa::path(
+    get,
+    path = "/api/rooms/{room_id}/messages/{id}/seen-count",
+    tag = "rooms",
+    security(("bearer_auth" = [])),
+    params(
+        ("room_id" = String, Path, description = "房间ID"),
+        ("id" = String, Path, description = "消息ID")
+    ),
+    responses(
+        (status = 200, description = "成功", body = ApiResponse<SeenCountResponse>),
+        (status = 400, description = "房间ID无效"),
+        (status = 403, description = "不是房间成员"),
+        (status = 404, description = "消息不存在或不属于该房间")
+    )
+)]
+async fn get_message_seen_count(
+    State(state): State<AppState>,
+    Path((room_id, message_id)): Path<(String, String)>,
+    Extension(auth_user): Extension<AuthenticatedUser>,
+) -> Result<Json<ApiResponse<SeenCountResponse>>, StatusCode> {
+    let (room_id, message) = load_room_message(&state, &room_id, &message_id, &auth_user).await?;
+    seen_count_response(&state, room_id, &message).await
+}
+
+/// 校验成员身份并加载属于该房间的消息
+async fn load_room_message(
+    state: &AppState,
+    room_id: &str,
+    message_id: &str,
+    auth_user: &AuthenticatedUser,
+) -> Result<(RoomId, Message), StatusCode> {
+    let room_id = RoomId::parse(room_id)
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let message_id = MessageId::parse(message_id)
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    // 只有房间成员可以查看已读情况
+    if !state.room_manager.is_user_in_room(room_id, &auth_user.user_id).await {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let message = match state.message_db.get_message(&message_id).await {
+        Ok(Some(message)) => message,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            tracing::error!("获取消息失败: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    if message.get_room_id() != Some(room_id.to_string().as_str()) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok((room_id, message))
+}
+
+async fn seen_count_response(
+    state: &AppState,
+    room_id: RoomId,
+    message: &Message,
+) -> Result<Json<ApiResponse<SeenCountResponse>>, StatusCode> {
+    let members = match state.room_manager.get_room_members(room_id).await {
+        Ok(members) => members,
+        Err(RoomError::RoomNotFound) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => return Ok(Json(ApiResponse::error(e.to_string()))),
+    };
+
+    let markers = match state.message_db.get_room_read_markers(&room_id.to_string()).await {
+        Ok(markers) => markers,
+        Err(e) => {
+            tracing::error!("获取已读位置失败: {}", e);
+            return Ok(Json(ApiResponse::error(e.to_string())));
+        }
+    };
+
+    // 已离开房间的用户不计入
+    let seen_count = members
+        .iter()
+        .filter(|member| markers.get(member).is_some_and(|last_read_at| *last_read_at >= message.timestamp))
+        .count();
+
+    Ok(Json(ApiResponse::success(SeenCountResponse {
+        message_id: message.id.to_string(),
+        seen_count,
+        member_count: members.len(),
+    })))
+}
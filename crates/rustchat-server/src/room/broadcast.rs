@@ -3,6 +3,8 @@ use std::sync::Arc;
 use tokio::sync::{broadcast, RwLock};
 use tracing::{debug, warn};
 
+use crate::cluster::ClusterRelay;
+use crate::config::DEFAULT_BROADCAST_CAPACITY;
 use crate::room::RoomId;
 use crate::WsEvent;
 use rustchat_types::{Message, UserId};
@@ -14,16 +16,33 @@ pub struct RoomBroadcastManager {
     room_channels: Arc<RwLock<HashMap<RoomId, broadcast::Sender<WsEvent>>>>,
     /// 用户当前所在房间映射
     user_current_room: Arc<RwLock<HashMap<UserId, RoomId>>>,
+    /// 每个房间广播通道的容量
+    channel_capacity: usize,
+    /// 跨实例广播中继
+    relay: ClusterRelay,
 }
 
 impl RoomBroadcastManager {
-    /// 创建新的房间广播管理器
+    /// 创建新的房间广播管理器，使用默认通道容量
     pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_BROADCAST_CAPACITY)
+    }
+
+    /// 创建指定房间通道容量的房间广播管理器
+    pub fn with_capacity(channel_capacity: usize) -> Self {
         Self {
             room_channels: Arc::new(RwLock::new(HashMap::new())),
             user_current_room: Arc::new(RwLock::new(HashMap::new())),
+            channel_capacity,
+            relay: ClusterRelay::new(),
         }
     }
+
+    /// 房间广播同时通过中继发布给其他实例
+    pub fn with_relay(mut self, relay: ClusterRelay) -> Self {
+        self.relay = relay;
+        self
+    }
     
     /// 为房间创建广播通道
     pub async fn create_room_channel(&self, room_id: RoomId) -> broadcast::Sender<WsEvent> {
@@ -32,7 +51,7 @@ impl RoomBroadcastManager {
         if let Some(sender) = channels.get(&room_id) {
             sender.clone()
         } else {
-            let (sender, _) = broadcast::channel(1000);
+            let (sender, _) = broadcast::channel(self.channel_capacity);
             channels.insert(room_id, sender.clone());
             debug!("为房间 {} 创建广播通道", room_id);
             sender
@@ -73,6 +92,9 @@ impl RoomBroadcastManager {
     
     /// 向指定房间广播消息
     pub async fn broadcast_to_room(&self, room_id: RoomId, event: WsEvent) -> Result<usize, broadcast::error::SendError<WsEvent>> {
+        // 其他实例上可能有该房间的成员
+        self.relay.publish_room(room_id, &event);
+
         let channels = self.room_channels.read().await;
         
         if let Some(sender) = channels.get(&room_id) {
@@ -91,7 +113,16 @@ impl RoomBroadcastManager {
             Ok(0)
         }
     }
-      /// 向用户当前所在房间广播消息
+    /// 只向本实例中的房间成员投递事件（其他实例转发来的事件），不再通过中继发布
+    pub async fn deliver_to_room(&self, room_id: RoomId, event: WsEvent) {
+        let channels = self.room_channels.read().await;
+        if let Some(sender) = channels.get(&room_id) {
+            // 本实例中没有该房间的成员时发送失败，可以忽略
+            let _ = sender.send(event);
+        }
+    }
+
+    /// 向用户当前所在房间广播消息
     pub async fn broadcast_to_user_room(&self, user_id: UserId, event: WsEvent) -> Result<Option<usize>, broadcast::error::SendError<WsEvent>> {
         if let Some(room_id) = self.get_user_current_room(&user_id).await {
             let count = self.broadcast_to_room(room_id, event).await?;
@@ -129,11 +160,17 @@ impl RoomBroadcastManager {
         let total_subscribers: usize = channels.values()
             .map(|sender| sender.receiver_count())
             .sum();
+        let max_queued = channels.values()
+            .map(|sender| sender.len())
+            .max()
+            .unwrap_or(0);
         
         BroadcastStats {
             total_rooms,
             total_users_in_rooms,
             total_subscribers,
+            channel_capacity: self.channel_capacity,
+            max_queued,
         }
     }
     
@@ -155,6 +192,10 @@ pub struct BroadcastStats {
     pub total_rooms: usize,
     pub total_users_in_rooms: usize,
     pub total_subscribers: usize,
+    /// 每个房间通道的容量
+    pub channel_capacity: usize,
+    /// 积压最多的房间通道中尚未被所有订阅者读取的事件数，接近容量说明有订阅者即将丢消息
+    pub max_queued: usize,
 }
 
 /// 房间消息路由器
@@ -3,6 +3,7 @@ use rustchat_types::UserId;
 use std::collections::HashMap;
 use tokio::sync::RwLock;
 use tracing::{debug, info, warn};
+use utoipa::ToSchema;
 
 /// 房间管理器
 #[derive(Debug)]
@@ -32,6 +33,7 @@ impl RoomManager {
         let mut room = Room::new(request.name, owner.clone());
         room.set_description(request.description);
         room.set_max_members(request.max_members);
+        room.set_persistent(request.persistent);
         
         let room_id = room.id;
         
@@ -51,19 +53,19 @@ impl RoomManager {
         Ok(room)
     }
       /// 加入房间
+    ///
+    /// 所有加入路径（WebSocket和HTTP）都经过这里，成员检查、人数上限检查和写入在同一个写锁内完成，
+    /// 并发加入时不会超过上限
     pub async fn join_room(&self, room_id: RoomId, user_id: UserId) -> Result<Room, RoomError> {
         // 获取并修改房间
         let room = {
             let mut rooms = self.rooms.write().await;
             let room = rooms.get_mut(&room_id).ok_or(RoomError::RoomNotFound)?;
             
-            // 检查用户是否已在房间中
-            if room.is_member(&user_id) {
+            // 已在房间中的用户不重复添加
+            if !room.add_member(&user_id)? {
                 return Err(RoomError::UserAlreadyInRoom);
             }
-            
-            // 添加成员
-            room.add_member(&user_id)?;
             room.clone()
         };
         
@@ -90,15 +92,15 @@ impl RoomManager {
             // 移除成员
             room.remove_member(&user_id);
             
-            // 如果房间为空且不是所有者，删除房间
+            let room = room.clone();
+
+            // 如果房间为空，删除房间
             if room.members.is_empty() {
-                let room_to_remove = room.clone();
                 rooms.remove(&room_id);
-                debug!("删除空房间: {} ({})", room_to_remove.name, room_id);
-                return Ok(room_to_remove);
+                debug!("删除空房间: {} ({})", room.name, room_id);
             }
-            
-            room.clone()
+
+            room
         };
         
         // 更新用户房间映射
@@ -152,6 +154,11 @@ impl RoomManager {
         } else {
             false
         }
+    }
+    /// 房间是否保存消息历史，房间不存在时返回true
+    pub async fn is_room_persistent(&self, room_id: RoomId) -> bool {
+        let rooms = self.rooms.read().await;
+        rooms.get(&room_id).is_none_or(|room| room.persistent)
     }
       /// 获取房间成员列表
     pub async fn get_room_members(&self, room_id: RoomId) -> Result<Vec<UserId>, RoomError> {
@@ -194,16 +201,24 @@ impl RoomManager {
     }
       /// 处理用户断线，清理相关数据
     pub async fn handle_user_disconnect(&self, user_id: UserId) {
+        self.leave_all_rooms(&user_id).await;
+    }
+
+    /// 让用户离开所有已加入的房间，返回成功离开的房间ID
+    pub async fn leave_all_rooms(&self, user_id: &UserId) -> Vec<RoomId> {
         let user_room_ids = {
             let user_rooms = self.user_rooms.read().await;
-            user_rooms.get(&user_id).cloned().unwrap_or_default()
+            user_rooms.get(user_id).cloned().unwrap_or_default()
         };
-        
+
+        let mut left = Vec::with_capacity(user_room_ids.len());
         for room_id in user_room_ids {
-            if let Err(e) = self.leave_room(room_id, user_id.clone()).await {
-                warn!("用户 {} 断线时离开房间 {} 失败: {}", user_id, room_id, e);
+            match self.leave_room(room_id, user_id.clone()).await {
+                Ok(_) => left.push(room_id),
+                Err(e) => warn!("用户 {} 离开房间 {} 失败: {}", user_id, room_id, e),
             }
         }
+        left
     }
     
     /// 获取房间统计信息
@@ -230,7 +245,7 @@ impl Default for RoomManager {
 }
 
 /// 房间统计信息
-#[derive(Debug, serde::Serialize)]
+#[derive(Debug, serde::Serialize, ToSchema)]
 pub struct RoomStats {
     pub total_rooms: usize,
     pub total_users: usize,
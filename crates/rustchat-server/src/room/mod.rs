@@ -3,12 +3,14 @@ mod api;
 mod broadcast;
 
 pub use manager::{RoomManager, RoomStats};
-pub use api::{create_room_routes, create_protected_room_routes, create_public_room_routes};
-pub use broadcast::{RoomBroadcastManager, RoomMessageRouter, BroadcastStats};
+pub use api::{create_protected_room_routes, create_public_room_routes};
+pub(crate) use api::{ApiResponse, RoomApi, user_room_responses};
+pub use broadcast::{RoomBroadcastManager, RoomMessageRouter};
 
 use rustchat_types::UserId;
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
+use utoipa::ToSchema;
 use uuid::Uuid;
 
 /// 房间唯一标识符
@@ -25,11 +27,6 @@ impl RoomId {
     pub fn parse(s: &str) -> Result<Self, uuid::Error> {
         Ok(Self(Uuid::parse_str(s)?))
     }
-    
-    /// 转换为字符串
-    pub fn to_string(&self) -> String {
-        self.0.to_string()
-    }
 }
 
 impl std::fmt::Display for RoomId {
@@ -61,6 +58,8 @@ pub struct Room {
     pub description: Option<String>,
     /// 最大成员数（可选，None表示无限制）
     pub max_members: Option<usize>,
+    /// 是否保存消息历史，为false时房间消息只实时转发给在线成员
+    pub persistent: bool,
 }
 
 impl Room {    /// 创建新房间
@@ -76,10 +75,17 @@ impl Room {    /// 创建新房间
             members,
             description: None,
             max_members: None,
+            persistent: true,
         }
     }
       /// 添加成员
+    ///
+    /// 已是成员时返回false，不受人数上限影响；只有新成员会因房间已满被拒绝
     pub fn add_member(&mut self, user_id: &UserId) -> Result<bool, RoomError> {
+        if self.is_member(user_id) {
+            return Ok(false);
+        }
+
         // 检查房间是否已满
         if let Some(max) = self.max_members {
             if self.members.len() >= max {
@@ -87,7 +93,6 @@ impl Room {    /// 创建新房间
             }
         }
         
-        // 添加成员（如果已存在则返回false）
         Ok(self.members.insert(user_id.clone()))
     }
       /// 移除成员
@@ -119,6 +124,11 @@ impl Room {    /// 创建新房间
     pub fn set_max_members(&mut self, max_members: Option<usize>) {
         self.max_members = max_members;
     }
+
+    /// 设置是否保存消息历史
+    pub fn set_persistent(&mut self, persistent: bool) {
+        self.persistent = persistent;
+    }
 }
 
 /// 房间相关错误
@@ -141,15 +151,22 @@ pub enum RoomError {
 }
 
 /// 房间创建请求
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct CreateRoomRequest {
     pub name: String,
     pub description: Option<String>,
     pub max_members: Option<usize>,
+    /// 是否保存消息历史（默认保存），临时会话等不需要留存记录的房间可设为false
+    #[serde(default = "default_persistent")]
+    pub persistent: bool,
+}
+
+fn default_persistent() -> bool {
+    true
 }
 
 /// 房间信息响应
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct RoomResponse {
     pub id: String,
     pub name: String,
@@ -158,8 +175,13 @@ pub struct RoomResponse {
     pub member_count: usize,
     pub description: Option<String>,
     pub max_members: Option<usize>,
+    /// 是否保存消息历史
+    pub persistent: bool,
     pub is_member: bool,
     pub is_owner: bool,
+    /// 请求者在该房间中的未读消息数（仅在用户房间列表中返回）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unread_count: Option<i64>,
 }
 
 impl RoomResponse {
@@ -172,8 +194,16 @@ impl RoomResponse {
             member_count: room.member_count(),
             description: room.description.clone(),
             max_members: room.max_members,
+            persistent: room.persistent,
             is_member: room.is_member(requester),
             is_owner: room.is_owner(requester),
+            unread_count: None,
         }
     }
+
+    /// 附带未读消息数
+    pub fn with_unread_count(mut self, unread_count: i64) -> Self {
+        self.unread_count = Some(unread_count);
+        self
+    }
 }
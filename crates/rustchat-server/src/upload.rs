@@ -0,0 +1,438 @@
+use axum::{
+    extract::{multipart::MultipartError, DefaultBodyLimit, Multipart, Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Json, Response},
+    routing::{get, post},
+    Extension, Router,
+};
+use rustchat_core::{run_migrations, Migration};
+use rustchat_types::UserId;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{AnyConnection, AnyPool, Row};
+use std::path::PathBuf;
+use tracing::{error, info, warn};
+
+use crate::auth::AuthenticatedUser;
+use crate::room::ApiResponse;
+use crate::AppState;
+
+/// 允许上传的文件类型，上传时还会检查文件内容与声明的类型是否相符
+pub const ALLOWED_UPLOAD_TYPES: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "application/pdf",
+    "text/plain",
+];
+
+/// 记录每个文件由哪些用户上传，用于计算配额和在清除用户数据时删除文件
+const UPLOAD_MIGRATIONS: &[Migration] = &[Migration {
+    version: 1,
+    description: "上传文件归属表",
+    statements: &[
+        r#"
+        CREATE TABLE IF NOT EXISTS uploads (
+            hash TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            size INTEGER NOT NULL,
+            created_at TEXT NOT NULL,
+            PRIMARY KEY (hash, user_id)
+        )
+        "#,
+        "CREATE INDEX IF NOT EXISTS idx_uploads_user_id ON uploads(user_id)",
+    ],
+}];
+
+/// multipart请求中除文件内容外的边界和字段头允许占用的字节数
+const MULTIPART_OVERHEAD: usize = 16 * 1024;
+
+/// 创建上传文件路由（需配合认证中间件使用），请求体超过 `max_size` 加少量开销时直接拒绝
+pub fn create_upload_routes(max_size: usize) -> Router<AppState> {
+    Router::new()
+        .route("/api/uploads", post(upload_file))
+        .layer(DefaultBodyLimit::max(max_size.saturating_add(MULTIPART_OVERHEAD)))
+}
+
+/// 创建读取上传文件的路由
+///
+/// 不需要认证，以便 `<img>` 等无法携带令牌的标签直接引用；文件地址由内容哈希决定，无法枚举
+pub fn create_public_upload_routes() -> Router<AppState> {
+    Router::new().route("/api/uploads/{hash}", get(get_upload))
+}
+
+/// 上传成功后返回的文件信息，`url` 可直接用在文件或图片消息中
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UploadInfo {
+    pub url: String,
+    pub size: u64,
+    pub mime: String,
+}
+
+/// 上传错误
+#[derive(Debug, thiserror::Error)]
+pub enum UploadError {
+    #[error("请求中没有文件字段 file")]
+    MissingFile,
+    #[error("文件为空")]
+    EmptyFile,
+    #[error("文件超过大小上限 {0} 字节")]
+    TooLarge(usize),
+    #[error("上传文件总大小超过配额 {0} 字节")]
+    QuotaExceeded(u64),
+    #[error("不支持的文件类型: {0}")]
+    UnsupportedType(String),
+    #[error("文件内容与声明的类型 {0} 不符")]
+    ContentMismatch(String),
+    #[error("上传请求格式错误: {0}")]
+    Multipart(#[from] MultipartError),
+    #[error("保存文件失败: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("记录上传文件失败: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+impl UploadError {
+    fn status(&self) -> StatusCode {
+        match self {
+            UploadError::MissingFile | UploadError::EmptyFile | UploadError::ContentMismatch(_) => StatusCode::BAD_REQUEST,
+            UploadError::TooLarge(_) | UploadError::QuotaExceeded(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            UploadError::UnsupportedType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            UploadError::Multipart(e) => e.status(),
+            UploadError::Io(_) | UploadError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl IntoResponse for UploadError {
+    fn into_response(self) -> Response {
+        (self.status(), Json(ApiResponse::<()>::error(self.to_string()))).into_response()
+    }
+}
+
+/// 按内容哈希保存上传文件的目录
+///
+/// 每个文件保存为 `<sha256>`，类型记录在同名的 `<sha256>.mime` 中。相同内容只保存一份，
+/// 重复上传时沿用第一次记录的类型。上传者记录在数据库的 `uploads` 表中，
+/// 同一文件的所有上传者都被清除后文件才会删除
+#[derive(Debug, Clone)]
+pub struct UploadStore {
+    dir: PathBuf,
+    max_size: usize,
+    quota: u64,
+    pool: AnyPool,
+}
+
+/// 在事务中删除上传记录的结果
+#[derive(Debug, Default)]
+pub struct RemovedUploads {
+    /// 删除的记录数
+    pub records: usize,
+    /// 已没有任何上传记录、可以删除的文件哈希
+    pub unreferenced: Vec<String>,
+}
+
+impl UploadStore {
+    pub fn new(dir: PathBuf, max_size: usize, quota: u64, pool: AnyPool) -> Self {
+        Self { dir, max_size, quota, pool }
+    }
+
+    /// 创建上传记录表
+    pub async fn initialize(&self) -> anyhow::Result<()> {
+        run_migrations(&self.pool, "uploads", UPLOAD_MIGRATIONS).await?;
+        Ok(())
+    }
+
+    /// 单个文件的大小上限（字节）
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// 用户已上传文件的总大小（字节），同一文件只计算一次
+    pub async fn used_bytes(&self, user_id: &UserId) -> Result<u64, UploadError> {
+        let used: i64 = sqlx::query_scalar("SELECT CAST(COALESCE(SUM(size), 0) AS BIGINT) FROM uploads WHERE user_id = $1")
+            .bind(user_id.to_string())
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(used as u64)
+    }
+
+    /// 检查类型、内容和上传者的配额后保存文件，返回文件的内容哈希和实际记录的类型
+    pub async fn save(&self, owner: &UserId, bytes: &[u8], mime: &str) -> Result<(String, String), UploadError> {
+        if bytes.is_empty() {
+            return Err(UploadError::EmptyFile);
+        }
+        if bytes.len() > self.max_size {
+            return Err(UploadError::TooLarge(self.max_size));
+        }
+        let mime = normalize_mime(mime);
+        if !ALLOWED_UPLOAD_TYPES.contains(&mime.as_str()) {
+            return Err(UploadError::UnsupportedType(mime));
+        }
+        if !content_matches(&mime, bytes) {
+            return Err(UploadError::ContentMismatch(mime));
+        }
+
+        let hash = hex::encode(Sha256::digest(bytes));
+        let owned = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM uploads WHERE hash = $1 AND user_id = $2")
+            .bind(&hash)
+            .bind(owner.to_string())
+            .fetch_one(&self.pool)
+            .await?
+            > 0;
+        // 重复上传自己已有的文件不占用额外配额
+        if !owned && self.used_bytes(owner).await? + bytes.len() as u64 > self.quota {
+            return Err(UploadError::QuotaExceeded(self.quota));
+        }
+
+        let mime = self.write_file(&hash, bytes, &mime).await?;
+        sqlx::query("INSERT INTO uploads (hash, user_id, size, created_at) VALUES ($1, $2, $3, $4) ON CONFLICT DO NOTHING")
+            .bind(&hash)
+            .bind(owner.to_string())
+            .bind(bytes.len() as i64)
+            .bind(chrono::Utc::now().to_rfc3339())
+            .execute(&self.pool)
+            .await?;
+        Ok((hash, mime))
+    }
+
+    /// 写入文件内容，文件已存在时返回第一次记录的类型
+    async fn write_file(&self, hash: &str, bytes: &[u8], mime: &str) -> Result<String, UploadError> {
+        let path = self.dir.join(hash);
+        if tokio::fs::try_exists(&path).await? {
+            return Ok(tokio::fs::read_to_string(path.with_extension("mime")).await?);
+        }
+
+        // 先写类型再原子地放入文件内容，读取时文件存在即说明类型已经写好
+        tokio::fs::create_dir_all(&self.dir).await?;
+        tokio::fs::write(path.with_extension("mime"), mime).await?;
+        let temp = self.dir.join(format!("{}.{}.tmp", hash, uuid::Uuid::new_v4()));
+        tokio::fs::write(&temp, bytes).await?;
+        tokio::fs::rename(&temp, &path).await?;
+        Ok(mime.to_string())
+    }
+
+    /// 删除用户的上传记录，已没有其他上传者的文件一并删除，返回删除的记录数
+    pub async fn remove_user_uploads(&self, user_id: &UserId) -> Result<usize, UploadError> {
+        let mut tx = self.pool.begin().await?;
+        let removed = Self::remove_user_upload_records(&mut tx, user_id).await?;
+        tx.commit().await?;
+        self.remove_unreferenced_files(&removed.unreferenced).await;
+        Ok(removed.records)
+    }
+
+    /// 在调用方的事务中删除用户的上传记录，文件在事务提交后再用 [`Self::remove_unreferenced_files`] 删除
+    pub async fn remove_user_upload_records(
+        conn: &mut AnyConnection,
+        user_id: &UserId,
+    ) -> Result<RemovedUploads, UploadError> {
+        let hashes: Vec<String> = sqlx::query("DELETE FROM uploads WHERE user_id = $1 RETURNING hash")
+            .bind(user_id.to_string())
+            .fetch_all(&mut *conn)
+            .await?
+            .iter()
+            .map(|row| row.get("hash"))
+            .collect();
+
+        let mut unreferenced = Vec::new();
+        for hash in &hashes {
+            let shared = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM uploads WHERE hash = $1")
+                .bind(hash)
+                .fetch_one(&mut *conn)
+                .await?
+                > 0;
+            if !shared && !unreferenced.contains(hash) {
+                unreferenced.push(hash.clone());
+            }
+        }
+        Ok(RemovedUploads { records: hashes.len(), unreferenced })
+    }
+
+    /// 删除已没有上传记录的文件，失败只记录警告
+    pub async fn remove_unreferenced_files(&self, hashes: &[String]) {
+        for hash in hashes {
+            // 先删除内容再删除类型，与写入顺序相反
+            let path = self.dir.join(hash);
+            for path in [path.clone(), path.with_extension("mime")] {
+                if let Err(e) = tokio::fs::remove_file(&path).await {
+                    if e.kind() != std::io::ErrorKind::NotFound {
+                        warn!("删除上传文件 {} 失败: {}", path.display(), e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// 读取文件内容和类型，哈希格式不对或文件不存在时返回None
+    pub async fn load(&self, hash: &str) -> Result<Option<(Vec<u8>, String)>, UploadError> {
+        if !is_valid_hash(hash) {
+            return Ok(None);
+        }
+        let path = self.dir.join(hash);
+        let bytes = match tokio::fs::read(&path).await {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let mime = tokio::fs::read_to_string(path.with_extension("mime")).await?;
+        Ok(Some((bytes, mime)))
+    }
+}
+
+/// 去掉参数（如 `; charset=utf-8`）并转为小写
+fn normalize_mime(mime: &str) -> String {
+    mime.split(';').next().unwrap_or_default().trim().to_ascii_lowercase()
+}
+
+/// 按文件头检查内容是否为声明的类型，避免把其他内容以图片等类型返回给浏览器
+fn content_matches(mime: &str, bytes: &[u8]) -> bool {
+    match mime {
+        "image/png" => bytes.starts_with(b"\x89PNG\r\n\x1a\n"),
+        "image/jpeg" => bytes.starts_with(&[0xFF, 0xD8, 0xFF]),
+        "image/gif" => bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a"),
+        "image/webp" => bytes.len() >= 12 && bytes.starts_with(b"RIFF") && &bytes[8..12] == b"WEBP",
+        "application/pdf" => bytes.starts_with(b"%PDF-"),
+        "text/plain" => std::str::from_utf8(bytes).is_ok(),
+        _ => false,
+    }
+}
+
+/// 64位小写十六进制的SHA-256，同时保证哈希不会被当作路径穿越目录
+fn is_valid_hash(hash: &str) -> bool {
+    hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+/// 上传文件，请求体为multipart，文件放在 `file` 字段中
+async fn upload_file(
+    State(state): State<AppState>,
+    Extension(auth_user): Extension<AuthenticatedUser>,
+    mut multipart: Multipart,
+) -> Result<Json<ApiResponse<UploadInfo>>, UploadError> {
+    let store = &state.uploads;
+    while let Some(mut field) = multipart.next_field().await? {
+        if field.name() != Some("file") {
+            continue;
+        }
+        let mime = field.content_type().unwrap_or("application/octet-stream").to_string();
+
+        // 边读边检查大小，超过上限立即停止读取
+        let mut bytes = Vec::new();
+        while let Some(chunk) = field.chunk().await? {
+            if bytes.len() + chunk.len() > store.max_size() {
+                return Err(UploadError::TooLarge(store.max_size()));
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+
+        let (hash, mime) = store.save(&auth_user.user_id, &bytes, &mime).await.inspect_err(|e| match e {
+            UploadError::Io(e) => error!("保存上传文件失败: {}", e),
+            UploadError::Database(e) => error!("记录上传文件失败: {}", e),
+            _ => {}
+        })?;
+        info!("用户 {} 上传文件 {}（{}，{} 字节）", auth_user.user_id, hash, mime, bytes.len());
+        return Ok(Json(ApiResponse::success(UploadInfo {
+            url: format!("/api/uploads/{}", hash),
+            size: bytes.len() as u64,
+            mime,
+        })));
+    }
+    Err(UploadError::MissingFile)
+}
+
+/// 按内容哈希读取上传的文件
+///
+/// 图片以内联方式返回以便直接嵌入，其他类型要求浏览器下载；`sandbox` 策略保证即使文件在浏览器中打开，
+/// 其中的脚本也不能以本站的身份运行
+async fn get_upload(State(state): State<AppState>, Path(hash): Path<String>) -> Response {
+    match state.uploads.load(&hash).await {
+        Ok(Some((bytes, mime))) => {
+            let disposition = if mime.starts_with("image/") { "inline" } else { "attachment" };
+            (
+                [
+                    (header::CONTENT_TYPE, mime),
+                    (header::CONTENT_DISPOSITION, disposition.to_string()),
+                    (header::CONTENT_SECURITY_POLICY, "sandbox".to_string()),
+                    (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+                    // 内容由哈希决定，永远不会改变
+                    (header::CACHE_CONTROL, "public, max-age=31536000, immutable".to_string()),
+                ],
+                bytes,
+            )
+                .into_response()
+        }
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            error!("读取上传文件 {} 失败: {}", hash, e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n fake image data";
+
+    async fn test_store(dir: PathBuf, max_size: usize, quota: u64) -> UploadStore {
+        let pool = rustchat_core::connect_pool("sqlite::memory:", Some(1)).await.unwrap();
+        let store = UploadStore::new(dir, max_size, quota, pool);
+        store.initialize().await.unwrap();
+        store
+    }
+
+    #[tokio::test]
+    async fn test_identical_uploads_are_stored_once() {
+        let dir = std::env::temp_dir().join(format!("rustchat-uploads-{}", uuid::Uuid::new_v4()));
+        let store = test_store(dir.clone(), 1024, 1 << 20).await;
+        let user = UserId::new();
+
+        let (hash, mime) = store.save(&user, PNG, "image/PNG").await.unwrap();
+        assert_eq!(mime, "image/png");
+        // 相同内容再次上传得到同一个哈希，沿用第一次的类型
+        assert_eq!(store.save(&user, PNG, "image/png; charset=binary").await.unwrap(), (hash.clone(), mime));
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+        assert_eq!(store.load(&hash).await.unwrap(), Some((PNG.to_vec(), "image/png".to_string())));
+
+        assert!(matches!(store.save(&user, b"<svg/>", "image/svg+xml").await, Err(UploadError::UnsupportedType(_))));
+        assert!(matches!(store.save(&user, b"<html>", "image/png").await, Err(UploadError::ContentMismatch(_))));
+        assert!(matches!(store.save(&user, &[0; 1025], "text/plain").await, Err(UploadError::TooLarge(1024))));
+        assert!(matches!(store.save(&user, b"", "text/plain").await, Err(UploadError::EmptyFile)));
+
+        assert_eq!(store.load(&"0".repeat(64)).await.unwrap(), None);
+        assert_eq!(store.load("../secret").await.unwrap(), None);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_quota_and_removal_follow_owners() {
+        let dir = std::env::temp_dir().join(format!("rustchat-uploads-{}", uuid::Uuid::new_v4()));
+        let store = test_store(dir.clone(), 1024, 32).await;
+        let (alice, bob) = (UserId::new(), UserId::new());
+
+        let (shared, _) = store.save(&alice, PNG, "image/png").await.unwrap();
+        assert_eq!(store.used_bytes(&alice).await.unwrap(), PNG.len() as u64);
+        // 重复上传自己的文件不再计入配额，新文件超过配额时拒绝
+        store.save(&alice, PNG, "image/png").await.unwrap();
+        assert!(matches!(store.save(&alice, b"some more text", "text/plain").await, Err(UploadError::QuotaExceeded(32))));
+        let (own, _) = store.save(&alice, b"short", "text/plain").await.unwrap();
+
+        // 其他用户上传同一内容时各自计算配额，文件只保存一份
+        store.save(&bob, PNG, "image/png").await.unwrap();
+        assert_eq!(store.used_bytes(&bob).await.unwrap(), PNG.len() as u64);
+
+        // 清除 alice 后只删除其独有的文件
+        assert_eq!(store.remove_user_uploads(&alice).await.unwrap(), 2);
+        assert_eq!(store.used_bytes(&alice).await.unwrap(), 0);
+        assert_eq!(store.load(&own).await.unwrap(), None);
+        assert!(store.load(&shared).await.unwrap().is_some());
+
+        assert_eq!(store.remove_user_uploads(&bob).await.unwrap(), 1);
+        assert_eq!(store.load(&shared).await.unwrap(), None);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,175 @@
+use anyhow::Context;
+use std::path::Path;
+
+/// 屏蔽词的替换文本
+const MASK: &str = "***";
+
+/// 命中屏蔽词时的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FilterMode {
+    /// 将命中的词替换为 `***` 后照常发送
+    #[default]
+    Mask,
+    /// 拒绝整条消息
+    Reject,
+}
+
+impl FilterMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "mask" => Some(Self::Mask),
+            "reject" => Some(Self::Reject),
+            _ => None,
+        }
+    }
+}
+
+/// 消息因包含屏蔽词被拒绝
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("消息包含被禁止的词语，未发送")]
+pub struct ContentRejected;
+
+/// 消息内容过滤器
+///
+/// 按字符逐个比较，忽略大小写（包括非ASCII字母），中文等没有大小写的文字按原样匹配。
+/// 多个词在同一位置命中时优先匹配最长的词
+#[derive(Debug, Clone)]
+pub struct MessageFilter {
+    words: Vec<String>,
+    /// 折叠大小写后的词，按长度从长到短排列
+    folded: Vec<Vec<char>>,
+    mode: FilterMode,
+}
+
+impl MessageFilter {
+    /// 从词表创建过滤器，忽略空白词
+    pub fn from_words<I, S>(words: I, mode: FilterMode) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut unique: Vec<String> = Vec::new();
+        for word in words {
+            let word = word.as_ref().trim();
+            if !word.is_empty() && !unique.iter().any(|existing| existing == word) {
+                unique.push(word.to_string());
+            }
+        }
+
+        let mut folded: Vec<Vec<char>> = unique.iter().map(|word| word.chars().map(fold_case).collect()).collect();
+        folded.sort_by_key(|word| std::cmp::Reverse(word.len()));
+        folded.dedup();
+
+        Self { words: unique, folded, mode }
+    }
+
+    /// 从文件加载词表：每行一个词，空行和以 `#` 开头的行被忽略
+    pub fn load(path: &Path, mode: FilterMode) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read word filter list {}", path.display()))?;
+        let words = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+        Ok(Self::from_words(words, mode))
+    }
+
+    /// 已加载的屏蔽词
+    pub fn words(&self) -> &[String] {
+        &self.words
+    }
+
+    pub fn mode(&self) -> FilterMode {
+        self.mode
+    }
+
+    /// 过滤消息文本：屏蔽模式下返回替换后的文本，拒绝模式下命中任一屏蔽词即返回错误
+    pub fn apply(&self, text: &str) -> Result<String, ContentRejected> {
+        let chars: Vec<char> = text.chars().collect();
+        let folded: Vec<char> = chars.iter().copied().map(fold_case).collect();
+        let mut output = String::with_capacity(text.len());
+        let mut i = 0;
+
+        while i < chars.len() {
+            match self.match_at(&folded[i..]) {
+                Some(_) if self.mode == FilterMode::Reject => return Err(ContentRejected),
+                Some(len) => {
+                    output.push_str(MASK);
+                    i += len;
+                }
+                None => {
+                    output.push(chars[i]);
+                    i += 1;
+                }
+            }
+        }
+
+        Ok(output)
+    }
+
+    /// 返回在文本开头命中的最长屏蔽词的长度（字符数）
+    fn match_at(&self, text: &[char]) -> Option<usize> {
+        self.folded
+            .iter()
+            .find(|word| text.starts_with(word))
+            .map(|word| word.len())
+    }
+}
+
+/// 单字符的大小写折叠，小写形式不止一个字符时保持原样，保证与原文逐字符对齐
+fn fold_case(c: char) -> char {
+    let mut lower = c.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(folded), None) => folded,
+        _ => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mask_mode_replaces_matches() {
+        let filter = MessageFilter::from_words(["darn", "笨蛋", "ÄRGER"], FilterMode::Mask);
+
+        assert_eq!(filter.apply("Oh DARN it").unwrap(), "Oh *** it");
+        assert_eq!(filter.apply("你这个笨蛋！笨蛋").unwrap(), "你这个***！***");
+        assert_eq!(filter.apply("so viel ärger").unwrap(), "so viel ***");
+        assert_eq!(filter.apply("nothing to see").unwrap(), "nothing to see");
+    }
+
+    #[test]
+    fn test_longest_match_wins() {
+        let filter = MessageFilter::from_words(["ab", "abc"], FilterMode::Mask);
+        assert_eq!(filter.apply("xabcx abx").unwrap(), "x***x ***x");
+    }
+
+    #[test]
+    fn test_reject_mode() {
+        let filter = MessageFilter::from_words(["坏词"], FilterMode::Reject);
+
+        assert_eq!(filter.apply("这是坏词"), Err(ContentRejected));
+        assert_eq!(filter.apply("这是好词").unwrap(), "这是好词");
+    }
+
+    #[test]
+    fn test_load_skips_comments_and_blank_lines() {
+        let path = std::env::temp_dir().join(format!("rustchat-wordfilter-{}.txt", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "# 屏蔽词\n\n darn \n笨蛋\ndarn\n").unwrap();
+
+        let filter = MessageFilter::load(&path, FilterMode::Reject).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(filter.words(), ["darn", "笨蛋"]);
+        assert_eq!(filter.mode(), FilterMode::Reject);
+        assert!(MessageFilter::load(&path, FilterMode::Mask).is_err());
+    }
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!(FilterMode::parse(" Reject "), Some(FilterMode::Reject));
+        assert_eq!(FilterMode::parse("mask"), Some(FilterMode::Mask));
+        assert_eq!(FilterMode::parse("block"), None);
+    }
+}
@@ -0,0 +1,66 @@
+use serde::{Deserialize, Serialize};
+
+/// WebSocket错误事件的错误代码，客户端据此区分错误类型，不必解析错误信息文本
+///
+/// 序列化为大写下划线形式（如 `ROOM_NOT_MEMBER`）。新版服务器增加的代码在旧客户端中解析为 `Unknown`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    /// 无法解析的客户端消息
+    InvalidRequest,
+    /// 消息内容为空
+    MsgEmpty,
+    /// 消息内容超过长度限制
+    MsgTooLong,
+    /// 消息包含屏蔽词
+    MsgRejected,
+    /// 阅后即焚消息的存活时间超出范围
+    InvalidTtl,
+    /// 发送过于频繁
+    RateLimited,
+    /// 昵称不合法
+    NickInvalid,
+    /// 修改昵称过于频繁
+    NickCooldown,
+    /// 房间ID格式错误
+    InvalidRoomId,
+    /// 房间不存在
+    RoomNotFound,
+    /// 不是房间成员
+    RoomNotMember,
+    /// 房间已满
+    RoomFull,
+    /// 没有操作权限
+    PermissionDenied,
+    /// 消息不存在
+    MessageNotFound,
+    /// 表情回应不合法
+    InvalidReaction,
+    /// 连接接收过慢，错过了部分广播消息
+    SlowConsumer,
+    /// 会话被服务器结束（账户注销或被管理员注销会话）
+    SessionTerminated,
+    /// 连接即将因长时间没有活动被断开
+    IdleWarning,
+    /// 连接因长时间没有活动被断开
+    IdleTimeout,
+    /// 服务器内部错误
+    InternalError,
+    /// 未知错误（旧版服务器未提供错误代码，或本客户端不认识的代码）
+    #[default]
+    #[serde(other)]
+    Unknown,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_code_serialization() {
+        assert_eq!(serde_json::to_string(&ErrorCode::RoomNotMember).unwrap(), r#""ROOM_NOT_MEMBER""#);
+        assert_eq!(serde_json::from_str::<ErrorCode>(r#""MSG_TOO_LONG""#).unwrap(), ErrorCode::MsgTooLong);
+        // 新版服务器增加的代码不影响旧客户端解析
+        assert_eq!(serde_json::from_str::<ErrorCode>(r#""SOMETHING_NEW""#).unwrap(), ErrorCode::Unknown);
+    }
+}
@@ -3,6 +3,7 @@ use crate::user::UserId;
 
 /// 好友请求状态
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub enum FriendRequestStatus {
     /// 待处理
     Pending,
@@ -14,6 +15,7 @@ pub enum FriendRequestStatus {
 
 /// 好友请求
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct FriendRequest {
     /// 请求ID
     pub id: String,
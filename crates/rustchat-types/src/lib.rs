@@ -1,7 +1,22 @@
 pub mod user;
 pub mod message;
 pub mod friend;
+pub mod validation;
+pub mod error_code;
 
 pub use user::{User, UserId};
-pub use message::{Message, MessageId, MessageType};
+pub use message::{markdown_to_plain, Message, MessageId, MessageType};
 pub use friend::{FriendRequest, FriendRequestStatus, Friendship};
+pub use validation::{validate_nickname, ValidationError};
+pub use error_code::ErrorCode;
+
+/// 客户端与服务器之间的WebSocket协议版本（主版本号不同即不兼容）
+///
+/// - 1.1: 新增消息确认（`SendMessage.client_msg_id` / `MessageAck`）
+/// - 1.2: 匿名连接的断线恢复令牌（`Connected.resume_token` / `?resume=<token>`）
+/// - 1.3: 错误事件附带错误代码（`Error.code`），处理失败的请求都会收到错误事件
+/// - 1.4: 服务器可将连续的多条消息合并为一个 `MessageBatch` 事件
+/// - 1.5: 加入或离开房间的连接会收到 `RoomJoined` / `RoomLeft` 确认
+/// - 1.6: 客户端连接时通过 `?protocol=<版本>` 声明协议版本，服务器只对声明了支持的连接合并消息；
+///   同一房间连续的多条消息可合并为一个 `RoomMessageBatch` 事件
+pub const PROTOCOL_VERSION: &str = "1.6";
@@ -3,9 +3,11 @@ use serde::{Deserialize, Serialize};
 use std::fmt;
 
 use crate::user::UserId;
+use crate::validation::{ValidationError, MAX_CONTENT_CHARS};
 
 /// 消息唯一标识符
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct MessageId(uuid::Uuid);
 
 impl MessageId {
@@ -13,10 +15,6 @@ impl MessageId {
         Self(uuid::Uuid::new_v4())
     }
 
-    pub fn to_string(&self) -> String {
-        self.0.to_string()
-    }
-
     /// 从字符串解析MessageId
     pub fn parse(s: &str) -> Result<Self, uuid::Error> {
         Ok(Self(uuid::Uuid::parse_str(s)?))
@@ -63,6 +61,7 @@ impl std::str::FromStr for MessageId {
 /// 消息类型枚举
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", content = "data")]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub enum MessageType {
     /// 普通文本消息
     Text(String),
@@ -70,10 +69,15 @@ pub enum MessageType {
     System(String),
     /// 昵称变更消息
     NickChange { old_nick: String, new_nick: String },
+    /// 动作消息（如 /me waves）
+    Action(String),
+    /// Markdown格式的文本消息（保存原始Markdown源文本）
+    Markdown(String),
 }
 
 /// 消息结构体
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct Message {
     /// 消息ID
     pub id: MessageId,
@@ -81,7 +85,7 @@ pub struct Message {
     pub from: UserId,
     /// 消息内容
     pub content: MessageType,
-    /// 时间戳
+    /// 时间戳，由创建消息的一方（通常是服务器）设置，排序、分页和清理都以它为准
     pub timestamp: DateTime<Utc>,
     /// 发送者昵称（可选，用于显示）
     pub from_nick: Option<String>,
@@ -89,9 +93,38 @@ pub struct Message {
     pub room_id: Option<String>,
     /// 附加数据（可选，JSON格式）
     pub additional_data: Option<serde_json::Value>,
+    /// 服务器接收时间（由服务器设置，用于分析时钟偏差和投递延迟）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub received_at: Option<DateTime<Utc>>,
+    /// 客户端声明的发送时间，只用于展示和分析时钟偏差，不影响排序
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_timestamp: Option<DateTime<Utc>>,
+    /// 过期时间（阅后即焚消息，过期后自动删除）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// 被@提及的用户（由服务器根据在线用户的昵称解析）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mentions: Vec<UserId>,
+    /// 是否由机器人发送（由服务器设置，旧消息默认为false）
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub is_bot: bool,
 }
 
-impl Message {    /// 创建新的文本消息
+impl Message {
+    /// 校验客户端提交的消息内容：去掉首尾空白后不能为空，且不超过4000个字符
+    ///
+    /// 消息内容允许换行
+    pub fn validate_content(content: &str) -> Result<(), ValidationError> {
+        if content.trim().is_empty() {
+            return Err(ValidationError::Empty);
+        }
+        if content.chars().count() > MAX_CONTENT_CHARS {
+            return Err(ValidationError::TooLong(MAX_CONTENT_CHARS));
+        }
+        Ok(())
+    }
+
+    /// 创建新的文本消息
     pub fn new_text(from: UserId, text: String, from_nick: Option<String>) -> Self {
         Self {
             id: MessageId::new(),
@@ -101,8 +134,21 @@ impl Message {    /// 创建新的文本消息
             from_nick,
             room_id: None,
             additional_data: None,
+            received_at: None,
+            client_timestamp: None,
+            expires_at: None,
+            mentions: Vec::new(),
+            is_bot: false,
+        }
+    }    /// 创建机器人发送的文本消息
+    pub fn new_bot_text(text: String, bot_name: &str) -> Self {
+        Self {
+            is_bot: true,
+            ..Self::new_text(UserId::new(), text, Some(bot_name.to_string()))
         }
-    }    /// 创建系统消息
+    }
+
+    /// 创建系统消息
     pub fn new_system(text: String) -> Self {
         Self {
             id: MessageId::new(),
@@ -112,6 +158,11 @@ impl Message {    /// 创建新的文本消息
             from_nick: Some("System".to_string()),
             room_id: None,
             additional_data: None,
+            received_at: None,
+            client_timestamp: None,
+            expires_at: None,
+            mentions: Vec::new(),
+            is_bot: false,
         }
     }    /// 创建昵称变更消息
     pub fn new_nick_change(
@@ -128,6 +179,47 @@ impl Message {    /// 创建新的文本消息
             from_nick,
             room_id: None,
             additional_data: None,
+            received_at: None,
+            client_timestamp: None,
+            expires_at: None,
+            mentions: Vec::new(),
+            is_bot: false,
+        }
+    }
+
+    /// 创建动作消息
+    pub fn new_action(from: UserId, text: String, from_nick: Option<String>) -> Self {
+        Self {
+            id: MessageId::new(),
+            from,
+            content: MessageType::Action(text),
+            timestamp: Utc::now(),
+            from_nick,
+            room_id: None,
+            additional_data: None,
+            received_at: None,
+            client_timestamp: None,
+            expires_at: None,
+            mentions: Vec::new(),
+            is_bot: false,
+        }
+    }
+
+    /// 创建Markdown消息
+    pub fn new_markdown(from: UserId, source: String, from_nick: Option<String>) -> Self {
+        Self {
+            id: MessageId::new(),
+            from,
+            content: MessageType::Markdown(source),
+            timestamp: Utc::now(),
+            from_nick,
+            room_id: None,
+            additional_data: None,
+            received_at: None,
+            client_timestamp: None,
+            expires_at: None,
+            mentions: Vec::new(),
+            is_bot: false,
         }
     }
 
@@ -148,6 +240,11 @@ impl Message {    /// 创建新的文本消息
             additional_data: Some(serde_json::json!({
                 "room_id": room_id
             })),
+            received_at: None,
+            client_timestamp: None,
+            expires_at: None,
+            mentions: Vec::new(),
+            is_bot: false,
         }
     }
 
@@ -162,6 +259,70 @@ impl Message {    /// 创建新的文本消息
         }
     }
 
+    /// 记录服务器接收时间为当前时间
+    pub fn mark_received(&mut self) {
+        self.received_at = Some(Utc::now());
+    }
+
+    /// 服务器接收时间与客户端发送时间的差值（正值表示延迟，负值表示客户端时钟超前），
+    /// 客户端未提供发送时间时与消息时间戳比较
+    pub fn receipt_delta(&self) -> Option<chrono::Duration> {
+        let sent_at = self.client_timestamp.unwrap_or(self.timestamp);
+        self.received_at.map(|received_at| received_at - sent_at)
+    }
+
+    /// 设置消息在指定时长后过期
+    pub fn expire_after(&mut self, ttl: chrono::Duration) {
+        self.expires_at = Some(Utc::now() + ttl);
+    }
+
+    /// 消息在指定时间是否已过期
+    pub fn is_expired_at(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    /// 消息当前是否已过期
+    pub fn is_expired(&self) -> bool {
+        self.is_expired_at(Utc::now())
+    }
+
+    /// 提取文本和Markdown消息中的 `@昵称`，按出现顺序去重
+    ///
+    /// `@` 前必须是开头、空白或标点（排除邮箱地址），昵称到空白为止，并去掉末尾的标点
+    pub fn extract_mentions(&self) -> Vec<String> {
+        let text = match &self.content {
+            MessageType::Text(text) | MessageType::Markdown(text) => text,
+            _ => return Vec::new(),
+        };
+
+        let mut mentions: Vec<String> = Vec::new();
+        let mut previous: Option<char> = None;
+        for (index, c) in text.char_indices() {
+            let at_boundary = previous.is_none_or(|p| !p.is_alphanumeric() && p != '@');
+            previous = Some(c);
+            if c != '@' || !at_boundary {
+                continue;
+            }
+
+            let rest = &text[index + 1..];
+            let token = rest.split(char::is_whitespace).next().unwrap_or_default();
+            let nickname = token.trim_end_matches(is_trailing_punctuation);
+            if !nickname.is_empty()
+                && nickname.chars().count() <= MAX_MENTION_LENGTH
+                && !mentions.iter().any(|existing| existing == nickname)
+            {
+                mentions.push(nickname.to_string());
+            }
+        }
+
+        mentions
+    }
+
+    /// 消息是否提及了指定用户
+    pub fn mentions_user(&self, user_id: &UserId) -> bool {
+        self.mentions.contains(user_id)
+    }
+
     /// 获取房间ID
     pub fn get_room_id(&self) -> Option<&str> {
         self.room_id.as_deref()
@@ -183,9 +344,18 @@ impl Message {    /// 创建新的文本消息
             MessageType::NickChange { old_nick, new_nick } => {
                 format!("{} 将昵称改为 {}", old_nick, new_nick)
             }
+            MessageType::Action(text) => {
+                format!("* {} {}", self.from_nick.as_deref().unwrap_or("匿名用户"), text)
+            }
+            MessageType::Markdown(source) => markdown_to_plain(source),
         }
     }
 
+    /// 获取适合纯文本终端显示的内容，Markdown消息会去除格式标记
+    pub fn render_plain(&self) -> String {
+        self.get_body()
+    }
+
     /// 检查是否为系统消息
     pub fn is_system(&self) -> bool {
         matches!(self.content, MessageType::System(_))
@@ -200,12 +370,167 @@ impl Message {    /// 创建新的文本消息
     pub fn is_nick_change(&self) -> bool {
         matches!(self.content, MessageType::NickChange { .. })
     }
+
+    /// 检查是否为动作消息
+    pub fn is_action(&self) -> bool {
+        matches!(self.content, MessageType::Action(_))
+    }
+
+    /// 检查是否为Markdown消息
+    pub fn is_markdown(&self) -> bool {
+        matches!(self.content, MessageType::Markdown(_))
+    }
+}
+
+/// 可被提及的昵称最大长度（与服务器的昵称长度限制一致）
+const MAX_MENTION_LENGTH: usize = 32;
+
+/// 提及后常见的结尾标点，不计入昵称
+fn is_trailing_punctuation(c: char) -> bool {
+    (c.is_ascii_punctuation() && !matches!(c, '_' | '-')) || "，。！？；：、）】》”’".contains(c)
+}
+
+/// 将Markdown源文本降级为纯文本：去除标题、引用、强调和代码标记，链接显示为"文本 (地址)"
+pub fn markdown_to_plain(source: &str) -> String {
+    let mut lines = Vec::new();
+    let mut in_fence = false;
+
+    for line in source.lines() {
+        let trimmed = line.trim_start();
+
+        // 代码块原样保留，去掉围栏行
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence {
+            lines.push(line.to_string());
+            continue;
+        }
+
+        let mut content = trimmed;
+        let hashes = content.chars().take_while(|c| *c == '#').count();
+        if (1..=6).contains(&hashes) && content[hashes..].starts_with(' ') {
+            content = content[hashes..].trim_start();
+        }
+        while let Some(rest) = content.strip_prefix('>') {
+            content = rest.trim_start();
+        }
+
+        // 统一列表符号，避免被当作强调标记去掉
+        let (bullet, content) = match content.strip_prefix("* ").or_else(|| content.strip_prefix("+ ")) {
+            Some(rest) => ("- ", rest),
+            None => ("", content),
+        };
+        lines.push(format!("{}{}", bullet, strip_inline_markdown(content)));
+    }
+
+    lines.join("\n")
+}
+
+/// 去除单行内的强调、代码和链接标记
+fn strip_inline_markdown(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut output = String::with_capacity(text.len());
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\\' if chars.get(i + 1).is_some_and(|next| next.is_ascii_punctuation()) => {
+                output.push(chars[i + 1]);
+                i += 2;
+            }
+            '`' => match chars[i + 1..].iter().position(|next| *next == '`') {
+                Some(len) => {
+                    output.extend(&chars[i + 1..i + 1 + len]);
+                    i += len + 2;
+                }
+                None => {
+                    output.push(c);
+                    i += 1;
+                }
+            },
+            '*' | '_' | '~' => {
+                let run = chars[i..].iter().take_while(|next| **next == c).count();
+                let before = i.checked_sub(1).map(|index| chars[index]);
+                let after = chars.get(i + run).copied();
+                let left_flanking = after.is_some_and(|ch| !ch.is_whitespace());
+                let right_flanking = before.is_some_and(|ch| !ch.is_whitespace());
+                // snake_case 之类的词内下划线不是强调标记
+                let intraword = c == '_'
+                    && before.is_some_and(char::is_alphanumeric)
+                    && after.is_some_and(char::is_alphanumeric);
+
+                if (left_flanking || right_flanking) && !intraword {
+                    i += run;
+                } else {
+                    output.extend(&chars[i..i + run]);
+                    i += run;
+                }
+            }
+            '!' if chars.get(i + 1) == Some(&'[') => match parse_link(&chars, i + 1) {
+                Some((label, _, end)) => {
+                    output.push_str(&strip_inline_markdown(&label));
+                    i = end;
+                }
+                None => {
+                    output.push(c);
+                    i += 1;
+                }
+            },
+            '[' => match parse_link(&chars, i) {
+                Some((label, url, end)) => {
+                    output.push_str(&strip_inline_markdown(&label));
+                    output.push_str(&format!(" ({})", url));
+                    i = end;
+                }
+                None => {
+                    output.push(c);
+                    i += 1;
+                }
+            },
+            _ => {
+                output.push(c);
+                i += 1;
+            }
+        }
+    }
+
+    output
+}
+
+/// 解析从 `start`（指向 '['）开始的 `[文本](地址)`，返回文本、地址和结束位置
+fn parse_link(chars: &[char], start: usize) -> Option<(String, String, usize)> {
+    let label_len = chars[start + 1..].iter().position(|c| *c == ']')?;
+    let label_end = start + 1 + label_len;
+    if chars.get(label_end + 1) != Some(&'(') {
+        return None;
+    }
+    let url_len = chars[label_end + 2..].iter().position(|c| *c == ')')?;
+    let url_end = label_end + 2 + url_len;
+
+    let label = chars[start + 1..label_end].iter().collect();
+    let url = chars[label_end + 2..url_end].iter().collect::<String>().trim().to_string();
+    Some((label, url, url_end + 1))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_validate_content() {
+        assert_eq!(Message::validate_content("hello"), Ok(()));
+        assert_eq!(Message::validate_content("第一行\n第二行"), Ok(()));
+        assert_eq!(Message::validate_content(" \n\t"), Err(ValidationError::Empty));
+        assert_eq!(Message::validate_content(&"消".repeat(MAX_CONTENT_CHARS)), Ok(()));
+        assert_eq!(
+            Message::validate_content(&"a".repeat(MAX_CONTENT_CHARS + 1)),
+            Err(ValidationError::TooLong(MAX_CONTENT_CHARS))
+        );
+    }
+
     #[test]
     fn test_message_creation() {
         let user_id = UserId::new();
@@ -224,6 +549,18 @@ mod tests {
         assert_eq!(message.from_nick, Some("Alice".to_string()));
     }
 
+    #[test]
+    fn test_message_expiry() {
+        let mut message = Message::new_text(UserId::new(), "secret".to_string(), None);
+        assert!(!message.is_expired());
+
+        message.expire_after(chrono::Duration::seconds(60));
+        let expires_at = message.expires_at.unwrap();
+        assert!(!message.is_expired());
+        assert!(!message.is_expired_at(expires_at - chrono::Duration::seconds(1)));
+        assert!(message.is_expired_at(expires_at));
+    }
+
     #[test]
     fn test_message_serialization() {
         let user_id = UserId::new();
@@ -276,6 +613,17 @@ mod tests {
         assert!(!nick_msg.is_text());
         assert!(!nick_msg.is_system());
         assert!(nick_msg.is_nick_change());
+
+        // 测试动作消息
+        let action_msg = Message::new_action(
+            UserId::new(),
+            "waves".to_string(),
+            Some("Alice".to_string()),
+        );
+        assert_eq!(action_msg.get_text(), None);
+        assert_eq!(action_msg.get_body(), "* Alice waves");
+        assert!(action_msg.is_action());
+        assert!(!action_msg.is_text());
     }
 
     #[test]
@@ -321,4 +669,38 @@ mod tests {
         let converted_uuid: uuid::Uuid = id.into();
         assert_eq!(uuid, converted_uuid);
     }
+
+    #[test]
+    fn test_markdown_render_plain() {
+        let source = "# 标题\n> **粗体** 和 _斜体_，`code` 与 snake_case\n* 查看 [文档](https://example.com) ![图](a.png)\n```\nlet x = *y;\n```\n2 * 3";
+        let message = Message::new_markdown(UserId::new(), source.to_string(), None);
+
+        assert!(message.is_markdown());
+        assert_eq!(
+            message.render_plain(),
+            "标题\n粗体 和 斜体，code 与 snake_case\n- 查看 文档 (https://example.com) 图\nlet x = *y;\n2 * 3"
+        );
+
+        // 序列化保留原始Markdown源文本
+        let json = serde_json::to_string(&message).unwrap();
+        let parsed: Message = serde_json::from_str(&json).unwrap();
+        assert!(matches!(parsed.content, MessageType::Markdown(ref text) if text == source));
+    }
+
+    #[test]
+    fn test_extract_mentions() {
+        let message = Message::new_text(
+            UserId::new(),
+            "@alice 你好，@bob_2! 请联系 carol@example.com，@alice 和 @张三：@ 结束".to_string(),
+            None,
+        );
+        assert_eq!(message.extract_mentions(), vec!["alice", "bob_2", "张三"]);
+
+        let markdown = Message::new_markdown(UserId::new(), "**@dave** 看这里".to_string(), None);
+        assert_eq!(markdown.extract_mentions(), vec!["dave"]);
+
+        // 只解析文本和Markdown消息
+        let action = Message::new_action(UserId::new(), "pings @alice".to_string(), None);
+        assert!(action.extract_mentions().is_empty());
+    }
 }
@@ -4,6 +4,7 @@ use uuid::Uuid;
 
 /// 用户唯一标识符
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct UserId(Uuid);
 
 /// 用户信息
@@ -66,9 +67,10 @@ impl UserId {
         &self.0
     }
 
-    /// 转换为字符串
-    pub fn to_string(&self) -> String {
-        self.0.to_string()
+    /// 用于显示的简短形式（UUID的前8个十六进制字符），不能用于解析
+    pub fn short(&self) -> String {
+        let mut buffer = Uuid::encode_buffer();
+        self.0.simple().encode_lower(&mut buffer)[..8].to_string()
     }
 }
 
@@ -128,6 +130,17 @@ mod tests {
         assert_eq!(user_id.to_string(), uuid_str);
     }
 
+    #[test]
+    fn test_user_id_short() {
+        let user_id = UserId::parse("550E8400-E29B-41D4-A716-446655440000").unwrap();
+        assert_eq!(user_id.short(), "550e8400");
+        assert_eq!(user_id.short(), user_id.short());
+
+        let user_id = UserId::new();
+        assert_eq!(user_id.short().len(), 8);
+        assert!(user_id.to_string().starts_with(&user_id.short()));
+    }
+
     #[test]
     fn test_user_id_serialization() {
         let user_id = UserId::new();
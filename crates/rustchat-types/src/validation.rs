@@ -0,0 +1,51 @@
+/// 昵称的最大长度（字符数）
+pub const MAX_NICKNAME_CHARS: usize = 32;
+
+/// 消息内容的最大长度（字符数）
+pub const MAX_CONTENT_CHARS: usize = 4000;
+
+/// 昵称或消息内容不合法的原因
+///
+/// 错误信息不含主语，调用方按需加上“昵称”“消息内容”等前缀
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ValidationError {
+    #[error("不能为空")]
+    Empty,
+    #[error("不能超过{0}个字符")]
+    TooLong(usize),
+    #[error("不能包含换行符或制表符")]
+    ForbiddenChar,
+}
+
+/// 校验昵称：去掉首尾空白后不能为空、不超过32个字符、不含换行符和制表符
+pub fn validate_nickname(nickname: &str) -> Result<(), ValidationError> {
+    let nickname = nickname.trim();
+    if nickname.is_empty() {
+        return Err(ValidationError::Empty);
+    }
+    if nickname.chars().count() > MAX_NICKNAME_CHARS {
+        return Err(ValidationError::TooLong(MAX_NICKNAME_CHARS));
+    }
+    if nickname.contains(['\n', '\r', '\t']) {
+        return Err(ValidationError::ForbiddenChar);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_nickname() {
+        assert_eq!(validate_nickname("Alice"), Ok(()));
+        assert_eq!(validate_nickname("  Alice  "), Ok(()));
+        assert_eq!(validate_nickname("   "), Err(ValidationError::Empty));
+        assert_eq!(validate_nickname("a\tb"), Err(ValidationError::ForbiddenChar));
+        assert_eq!(validate_nickname("a\nb"), Err(ValidationError::ForbiddenChar));
+
+        // 长度按字符计算
+        assert_eq!(validate_nickname(&"好".repeat(32)), Ok(()));
+        assert_eq!(validate_nickname(&"a".repeat(33)), Err(ValidationError::TooLong(32)));
+    }
+}